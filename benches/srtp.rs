@@ -0,0 +1,20 @@
+//! SRTP protect throughput, copying versus in place.
+//!
+//! Run with: `cargo bench --bench srtp`
+
+use str0m::_internal_test_exports::bench;
+
+const PACKETS: u64 = 200_000;
+
+fn main() {
+    for t in bench::srtp_protect(PACKETS) {
+        let mb_s = |d: std::time::Duration| t.bytes as f64 / d.as_secs_f64() / 1e6;
+
+        println!(
+            "{}: copy {:.0} MB/s, in place {:.0} MB/s",
+            t.profile,
+            mb_s(t.copy),
+            mb_s(t.in_place)
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Measurements driven from `benches/`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::crypto::{KeyingMaterial, SrtpProfile};
+use crate::rtp_::{ExtensionMap, RtpHeader, SrtpContext, SRTP_OVERHEAD};
+
+/// UNSTABLE: not public API!
+#[derive(Debug)]
+pub struct SrtpProtectTiming {
+    pub profile: String,
+    pub bytes: u64,
+    pub copy: Duration,
+    pub in_place: Duration,
+}
+
+/// UNSTABLE: not public API!
+///
+/// Protect `packets` RTP packets with both the copying and the in place
+/// variant, for every SRTP profile.
+pub fn srtp_protect(packets: u64) -> Vec<SrtpProtectTiming> {
+    let mut packet = vec![0x80, 96, 0, 0, 0, 0, 0, 1, 0, 0, 0, 42];
+    packet.extend((0..1184).map(|i| i as u8));
+    let header = RtpHeader::parse(&packet, &ExtensionMap::empty()).unwrap();
+
+    SrtpProfile::ALL
+        .iter()
+        .map(|profile| {
+            let len = profile.keying_material_len();
+            let mat = KeyingMaterial::new((0..len).map(|i| i as u8).collect());
+            let mut ctx = SrtpContext::new(*profile, &mat, true);
+
+            let start = Instant::now();
+            for index in 0..packets {
+                consume(&ctx.protect_rtp(&packet, &header, index));
+            }
+            let copy = start.elapsed();
+
+            let mut buf = packet.clone();
+            let start = Instant::now();
+            for index in 0..packets {
+                // Like the send path, the buffer is filled anew for each packet.
+                buf.clear();
+                buf.extend_from_slice(&packet);
+                buf.resize(packet.len() + SRTP_OVERHEAD, 0);
+                let n = ctx
+                    .protect_rtp_in_place(&mut buf, packet.len(), &header, index)
+                    .unwrap();
+                consume(&buf[..n]);
+            }
+            let in_place = start.elapsed();
+
+            SrtpProtectTiming {
+                profile: profile.to_string(),
+                bytes: packets * packet.len() as u64,
+                copy,
+                in_place,
+            }
+        })
+        .collect()
+}
+
+// std::hint::black_box is not in our MSRV. The last byte is the end of
+// the tag, which depends on all of the output.
+static SINK: AtomicUsize = AtomicUsize::new(0);
+
+fn consume(buf: &[u8]) {
+    SINK.fetch_add(buf[buf.len() - 1] as usize, Ordering::Relaxed);
+}
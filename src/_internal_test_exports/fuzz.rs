@@ -7,7 +7,7 @@ use crate::change::{SdpAnswer, SdpOffer};
 use crate::crypto::KeyingMaterial;
 use crate::crypto::SrtpProfile;
 use crate::format::Codec;
-use crate::packet::{DepacketizingBuffer, RtpMeta};
+use crate::packet::{CodecDepacketizer, CodecExtra, Depacketizer, DepacketizingBuffer, RtpMeta};
 use crate::rtp_::{Frequency, MediaTime, RtpHeader};
 use crate::streams::register::ReceiverRegister;
 use crate::streams::rtx_cache_buf::EvictingBuffer;
@@ -54,14 +54,15 @@ pub fn rtp_packet(data: &[u8]) -> Option<()> {
     let config = random_config(&mut rng)?;
 
     let mut session = Session::new(&config);
+    let start = Instant::now();
     session.set_keying_material(
         KeyingMaterial::new(rng.slice(16)?.to_vec()),
         SrtpProfile::PassThrough,
         rng.bool()?,
+        start,
     );
 
     // Loop rest of data as RTP input.
-    let start = Instant::now();
     loop {
         let now = start + Duration::from_micros(rng.u64(u64::MAX)?);
         let len = rng.usize(76)?;
@@ -87,12 +88,13 @@ pub fn sdp_answer(data: &[u8]) -> Option<()> {
 pub fn depack(data: &[u8]) -> Option<()> {
     let mut rng = Rng::new(data);
 
-    let codec = match rng.u8(4)? {
+    let codec = match rng.u8(5)? {
         0 => Codec::Opus,
         1 => Codec::Vp8,
         2 => Codec::Vp9,
         3 => Codec::H264,
         4 => Codec::H265,
+        5 => Codec::Av1,
         _ => unreachable!(),
     };
 
@@ -124,6 +126,30 @@ pub fn depack(data: &[u8]) -> Option<()> {
     }
 }
 
+pub fn av1_depack(data: &[u8]) -> Option<()> {
+    let mut rng = Rng::new(data);
+
+    let mut depack = CodecDepacketizer::from(Codec::Av1);
+    let mut out = Vec::new();
+    let mut extra = CodecExtra::None;
+
+    loop {
+        // Bias towards the aggregation header bits and leb128 lengths
+        // to exercise fragmentation and length parsing.
+        let agg = rng.u8(255)? & 0xf8;
+        let len = rng.usize(1200)?;
+        let mut packet = vec![agg];
+        packet.extend_from_slice(rng.slice(len)?);
+
+        let _ = depack.depacketize(&packet, &mut out, &mut extra);
+
+        if rng.bool()? {
+            out.clear();
+            extra = CodecExtra::None;
+        }
+    }
+}
+
 pub fn receive_register(data: &[u8]) -> Option<()> {
     let mut rng = Rng::new(data);
     let mut rr = ReceiverRegister::new();
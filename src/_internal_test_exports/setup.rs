@@ -49,6 +49,8 @@ pub fn random_config(rng: &mut Rng) -> Option<RtcConfig> {
         let t = Duration::from_millis(rng.u64(10_000)?);
         c = c.set_stats_interval(Some(t));
     }
+    let t = Duration::from_millis(rng.u64(10_000)?);
+    c = c.set_rtc_stats_interval(t);
     if rng.bool()? {
         rng.bool();
         c = c.enable_bwe(None);
@@ -57,6 +59,7 @@ pub fn random_config(rng: &mut Rng) -> Option<RtcConfig> {
     }
     c = c.set_reordering_size_audio(rng.usize(usize::MAX)?);
     c = c.set_reordering_size_video(rng.usize(usize::MAX)?);
+    c = c.set_h264_length_prefixed(rng.bool()?);
     c = c.set_send_buffer_audio(rng.usize(usize::MAX)?.saturating_add(1)); // panics if set to 0
     c = c.set_send_buffer_video(rng.usize(usize::MAX)?);
     c = c.set_rtp_mode(rng.bool()?);
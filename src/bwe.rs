@@ -2,17 +2,106 @@
 
 use crate::{rtp_::Mid, Rtc};
 
+pub use crate::packet::{AllocationChanged, Allocator, StreamBitrates};
+pub use crate::packet::{PacedQueueStats, PacedSender, PacerQueueOverflow, PacketPriority};
 pub use crate::rtp_::Bitrate;
 
 #[derive(Debug, PartialEq)]
 /// Bandwidth estimation kind.
 pub enum BweKind {
     /// Transport wide congestion control.
-    Twcc(Bitrate),
+    Twcc(BweEstimate),
     /// REMB (Receiver Estimated Maximum Bitrate)
     Remb(Mid, Bitrate),
 }
 
+/// Default floor for the estimate.
+pub const DEFAULT_BWE_MIN_BITRATE: Bitrate = Bitrate::kbps(40);
+
+/// Default ceiling for the estimate.
+pub const DEFAULT_BWE_MAX_BITRATE: Bitrate = Bitrate::gbps(10);
+
+/// Operating bounds for the bandwidth estimation.
+///
+/// Set with [`RtcConfig::set_bwe_config`][crate::RtcConfig::set_bwe_config], and changed at
+/// runtime with [`Bwe::set_config`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BweConfig {
+    start: Bitrate,
+    min: Bitrate,
+    max: Bitrate,
+}
+
+impl BweConfig {
+    /// Estimate starting from `start`, between 40kbit/s and 10Gbit/s.
+    pub fn new(start: Bitrate) -> Self {
+        BweConfig {
+            start,
+            min: DEFAULT_BWE_MIN_BITRATE,
+            max: DEFAULT_BWE_MAX_BITRATE,
+        }
+    }
+
+    /// Sets the floor for the estimate. Keeps audio from starving.
+    pub fn set_min_bitrate(mut self, min: Bitrate) -> Self {
+        self.min = min;
+        self
+    }
+
+    /// Sets the ceiling for the estimate.
+    pub fn set_max_bitrate(mut self, max: Bitrate) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// The estimate to start from, also after [`BweResetReason::RouteChange`].
+    ///
+    /// Always within min and max.
+    pub fn start_bitrate(&self) -> Bitrate {
+        self.start.clamp(self.min, self.max)
+    }
+
+    /// The floor for the estimate.
+    pub fn min_bitrate(&self) -> Bitrate {
+        self.min
+    }
+
+    /// The ceiling for the estimate.
+    ///
+    /// Never below the floor.
+    pub fn max_bitrate(&self) -> Bitrate {
+        self.max.max(self.min)
+    }
+}
+
+/// Why the estimate was reset, see [`Bwe::reset`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BweResetReason {
+    /// The network path changed, such as when ICE nominates another candidate pair.
+    ///
+    /// Starts over from [`BweConfig::start_bitrate`].
+    RouteChange,
+    /// Reset by the application, starting over from the given bitrate.
+    Application(Bitrate),
+}
+
+/// Estimate from the TWCC based bandwidth estimation.
+///
+/// Emitted in [`Event::EgressBitrateEstimate`][crate::Event::EgressBitrateEstimate] when
+/// `target` changes by more than 5%, when `loss_limited` changes, or at least once a second
+/// while there are estimates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BweEstimate {
+    /// The bitrate to allocate to the media, such as the sum of encoder target bitrates.
+    pub target: Bitrate,
+    /// The bitrate the remote has acknowledged receiving, if known.
+    pub acked: Option<Bitrate>,
+    /// Whether `target` is held down by packet loss rather than by delay.
+    pub loss_limited: bool,
+    /// Set on the first estimate after a reset.
+    pub reset: Option<BweResetReason>,
+}
+
 /// Access to the Bandwidth Estimate subsystem.
 pub struct Bwe<'a>(pub(crate) &'a mut Rtc);
 
@@ -83,15 +172,51 @@ impl<'a> Bwe<'a> {
         self.0.session.set_bwe_desired_bitrate(desired_bitrate);
     }
 
-    /// Reset the BWE with a new init_bitrate
+    /// Configure the bitrate bounds for a media line.
+    ///
+    /// **Note:** This only has an effect if BWE has been enabled via
+    /// [`RtcConfig::enable_bwe`][crate::RtcConfig::enable_bwe].
+    ///
+    /// * `min` the bitrate the media sends at the least, such as the lowest simulcast layer.
+    ///   The pacer always allows this much.
+    /// * `max` the bitrate the media could use at most. The sum over all media lines is probed
+    ///   for like with [`Bwe::set_desired_bitrate`], whichever is higher.
+    ///
+    /// The bounds are removed with the media.
+    pub fn set_bitrate_bounds(&mut self, mid: Mid, min: Bitrate, max: Bitrate) {
+        self.0.session.set_bwe_bitrate_bounds(mid, min, max);
+    }
+
+    /// The current estimate.
+    ///
+    /// For polling, as opposed to waiting for
+    /// [`Event::EgressBitrateEstimate`][crate::Event::EgressBitrateEstimate]. `None` until the
+    /// first estimate is made, or if BWE isn't enabled.
+    pub fn estimate(&self) -> Option<BweEstimate> {
+        self.0.session.bwe_estimate()
+    }
+
+    /// Change the operating bounds of the BWE.
+    ///
+    /// The current estimate is clamped to the new bounds right away. The start bitrate is
+    /// used for the next [`BweResetReason::RouteChange`].
+    pub fn set_config(&mut self, config: BweConfig) {
+        self.0.session.set_bwe_config(config);
+    }
+
+    /// Reset the BWE, discarding all history.
+    ///
+    /// str0m does this by itself with [`BweResetReason::RouteChange`] when ICE nominates another
+    /// candidate pair. The next [`BweEstimate`] is emitted right away, with `reset` set.
     ///
     /// # Example
     ///
     /// This method is useful when you initially start with only an audio stream. In this case, the BWE will report a very low estimated bitrate.
     /// Later, when you start a video stream, the estimated bitrate will be affected by the previous low bitrate, resulting in a very low estimated bitrate, which can cause poor video stream quality.
-    /// To avoid this, you need to warm up the video stream for a while then calling reset with a provided init_bitrate.
+    /// To avoid this, you need to warm up the video stream for a while then calling reset with
+    /// [`BweResetReason::Application`] and a provided init bitrate.
     ///
-    pub fn reset(&mut self, init_bitrate: Bitrate) {
-        self.0.session.reset_bwe(init_bitrate);
+    pub fn reset(&mut self, reason: BweResetReason) {
+        self.0.session.reset_bwe(reason);
     }
 }
@@ -1,6 +1,6 @@
 use crate::channel::ChannelId;
-use crate::crypto::Fingerprint;
-use crate::media::{Media, MediaKind};
+use crate::crypto::{Fingerprint, KeyingMaterial, SrtpProfile};
+use crate::media::{Media, MediaFeatures, MediaKind};
 use crate::rtp_::{Mid, Rid, Ssrc};
 use crate::sctp::ChannelConfig;
 use crate::streams::{StreamRx, StreamTx, DEFAULT_RTX_CACHE_DURATION};
@@ -86,15 +86,40 @@ impl<'a> DirectApi<'a> {
     }
 
     /// Sets the remote DTLS fingerprint.
+    ///
+    /// The remote certificate is verified against it when the DTLS handshake completes.
     pub fn set_remote_fingerprint(&mut self, dtls_fingerprint: Fingerprint) {
-        self.rtc.remote_fingerprint = Some(dtls_fingerprint);
+        self.rtc.set_remote_fingerprints(vec![dtls_fingerprint]);
+    }
+
+    /// Sets several remote DTLS fingerprints, of which the remote certificate must match one.
+    pub fn set_remote_fingerprints(&mut self, dtls_fingerprints: Vec<Fingerprint>) {
+        self.rtc.set_remote_fingerprints(dtls_fingerprints);
     }
 
     /// Start the DTLS subsystem.
+    ///
+    /// The role is kept for the lifetime of the DTLS transport. Called before accepting an
+    /// offer, this decides the `a=setup` of the answer.
     pub fn start_dtls(&mut self, active: bool) -> Result<(), RtcError> {
         self.rtc.init_dtls(active)
     }
 
+    /// Switch SRTP to new keys, such as from an external keying mechanism.
+    ///
+    /// `active` selects which half of the keying material is used for sending, in the
+    /// same way as the DTLS role. Outgoing packets use the new keys immediately. Incoming
+    /// packets protected with the previous keys are still accepted for a few seconds,
+    /// to cover packets in flight while the remote peer switches.
+    ///
+    /// Also see [`Event::SrtpKeyExpiring`][crate::Event::SrtpKeyExpiring].
+    pub fn rekey_srtp(&mut self, mat: KeyingMaterial, profile: SrtpProfile, active: bool) {
+        let now = self.rtc.last_now;
+        self.rtc
+            .session
+            .set_keying_material(mat, profile, active, now);
+    }
+
     /// Start the SCTP over DTLS.
     pub fn start_sctp(&mut self, client: bool) {
         self.rtc.init_sctp(client)
@@ -122,6 +147,41 @@ impl<'a> DirectApi<'a> {
         self.rtc.session.enable_twcc_feedback()
     }
 
+    /// Set whether feedback can be sent as reduced-size RTCP (RFC 5506).
+    ///
+    /// Reduced-size RTCP sends feedback, such as a PLI, on its own without a receiver
+    /// report and SDES. When disabled, feedback is always in a compound RTCP.
+    ///
+    /// Defaults to `true`. In the SDP API, this is negotiated with `a=rtcp-rsize`.
+    pub fn set_rtcp_reduced_size(&mut self, enabled: bool) {
+        self.rtc.session.set_rtcp_reduced_size(enabled)
+    }
+
+    /// Enable REMB feedback.
+    ///
+    /// The incoming bitrate is estimated and sent to the remote as REMB. This does nothing
+    /// if TWCC feedback is enabled.
+    pub fn enable_remb_feedback(&mut self) {
+        self.rtc.session.enable_remb_feedback()
+    }
+
+    /// Send a pre-serialized RTCP packet.
+    ///
+    /// The packet is placed verbatim in the next compound RTCP, after the RTCP str0m
+    /// generates itself, and is protected by SRTCP like the rest. This is for RTCP str0m
+    /// doesn't model, such as APP or experimental feedback.
+    ///
+    /// `data` must be exactly one RTCP packet, header included, with a length field matching
+    /// the data, a multiple of 4 bytes and small enough to fit a datagram. The contents are
+    /// not checked.
+    ///
+    /// Incoming RTCP that str0m doesn't handle is emitted as
+    /// [`Event::RtcpPacket`][crate::Event::RtcpPacket].
+    pub fn write_raw_rtcp(&mut self, data: impl Into<Vec<u8>>) -> Result<(), RtcError> {
+        self.rtc.session.enqueue_raw_rtcp(data.into())?;
+        Ok(())
+    }
+
     /// Generate a ssrc that is not already used in session
     pub fn new_ssrc(&self) -> Ssrc {
         self.rtc.session.streams.new_ssrc()
@@ -157,12 +217,40 @@ impl<'a> DirectApi<'a> {
         };
 
         let exts = self.rtc.session.exts.cloned_with_type(kind.is_audio());
-        let m = Media::from_direct_api(mid, next_index, kind, exts);
+        let mut m = Media::from_direct_api(mid, next_index, kind, exts);
+        m.set_rtp_mode(self.rtc.session.rtp_mode);
+        m.set_cname(self.rtc.session.cname.clone());
+        m.set_features(self.rtc.session.media_features);
 
         self.rtc.session.medias.push(m);
         self.rtc.session.medias.last_mut().unwrap()
     }
 
+    /// Set the [`MediaFeatures`] for a media.
+    ///
+    /// The default is [`RtcConfig::set_media_features()`][crate::RtcConfig::set_media_features].
+    /// Returns [`RtcError::MediaFeaturesLocked`] if the media doesn't exist or already has
+    /// streams.
+    pub fn set_media_features(
+        &mut self,
+        mid: Mid,
+        features: MediaFeatures,
+    ) -> Result<(), RtcError> {
+        let streams = &mut self.rtc.session.streams;
+        let has_streams = streams.streams_rx().any(|s| s.mid() == mid)
+            || streams.streams_tx().any(|s| s.mid() == mid);
+
+        let media = self.rtc.session.media_by_mid_mut(mid);
+
+        match media {
+            Some(media) if !has_streams => {
+                media.set_features(features);
+                Ok(())
+            }
+            _ => Err(RtcError::MediaFeaturesLocked(mid)),
+        }
+    }
+
     /// Remove `Media`.
     ///
     /// Removes media and all streams belong to a media identified by a `mid`.
@@ -180,12 +268,12 @@ impl<'a> DirectApi<'a> {
         mid: Mid,
         rid: Option<Rid>,
     ) -> &mut StreamRx {
-        let Some(_media) = self.rtc.session.media_by_mid(mid) else {
+        let Some(media) = self.rtc.session.media_by_mid(mid) else {
             panic!("No media declared for mid: {}", mid);
         };
 
         // By default we do not suppress nacks, this has to be called explicitly by the user of direct API.
-        let suppress_nack = false;
+        let suppress_nack = !media.features().nack;
 
         self.rtc
             .session
@@ -193,6 +281,14 @@ impl<'a> DirectApi<'a> {
             .expect_stream_rx(ssrc, rtx, mid, rid, suppress_nack, None)
     }
 
+    /// Allow incoming FlexFEC packets on `fec` protecting the receive stream `ssrc`.
+    ///
+    /// This corresponds to `a=ssrc-group:FEC-FR <ssrc> <fec>` in the SDP. The stream must
+    /// already be expected. Returns `None` if it's not.
+    pub fn expect_stream_rx_fec(&mut self, ssrc: Ssrc, fec: Ssrc) -> Option<&mut StreamRx> {
+        self.rtc.session.streams.expect_stream_rx_fec(ssrc, fec)
+    }
+
     /// Remove the receive stream for the given SSRC.
     ///
     /// Returns true if stream existed and was removed.
@@ -233,6 +329,7 @@ impl<'a> DirectApi<'a> {
         };
 
         let is_audio = media.kind().is_audio();
+        let rtx_enabled = media.features().rtx;
 
         let stream = self
             .rtc
@@ -240,6 +337,11 @@ impl<'a> DirectApi<'a> {
             .streams
             .declare_stream_tx(ssrc, rtx, mid, rid);
 
+        if !rtx_enabled {
+            stream.disable_rtx_cache();
+            return stream;
+        }
+
         let size = if is_audio {
             self.rtc.session.send_buffer_audio
         } else {
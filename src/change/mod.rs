@@ -14,10 +14,10 @@
 //! some "other way" keeping the two peers in sync.
 mod sdp;
 pub(crate) use sdp::AddMedia;
-pub use sdp::{SdpAnswer, SdpApi, SdpOffer, SdpPendingOffer};
+pub use sdp::{RtcpMuxPolicy, SdpAnswer, SdpApi, SdpOffer, SdpPendingOffer};
 
 mod direct;
 pub use direct::DirectApi;
 
-pub use crate::crypto::Fingerprint;
+pub use crate::crypto::{Fingerprint, KeyingMaterial, SrtpKeys, SrtpProfile};
 pub use crate::dtls::DtlsCert;
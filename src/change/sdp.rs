@@ -5,25 +5,26 @@ use std::ops::{Deref, DerefMut};
 
 use crate::channel::ChannelId;
 use crate::crypto::Fingerprint;
-use crate::format::CodecConfig;
 use crate::format::PayloadParams;
+use crate::format::{Codec, CodecConfig, OpusParams};
 use crate::io::Id;
-use crate::media::Media;
+use crate::media::{FeedbackCaps, Media, MediaFeatures, Simulcast};
 use crate::packet::MediaKind;
 use crate::rtp_::Rid;
 use crate::rtp_::{Direction, Extension, ExtensionMap, Mid, Pt, Ssrc};
-use crate::sctp::ChannelConfig;
+use crate::sctp::{ChannelConfig, MAX_MESSAGE_SIZE};
+use crate::sdp::Simulcast as SdpSimulcast;
 use crate::sdp::SimulcastGroups;
 use crate::sdp::{self, MediaAttribute, MediaLine, MediaType, Msid, Sdp};
-use crate::sdp::{Proto, SessionAttribute, Setup};
+use crate::sdp::{Proto, SdpError, SessionAttribute, Setup};
 use crate::session::Session;
 use crate::Rtc;
 use crate::RtcError;
-use crate::{Candidate, IceCreds};
+use crate::{Candidate, CandidateKind, IceCreds};
 
 pub use crate::sdp::{SdpAnswer, SdpOffer};
-use crate::streams::Streams;
 use crate::streams::DEFAULT_RTX_CACHE_DURATION;
+use crate::streams::{LayerDiscovered, Streams};
 
 /// Changes to the Rtc via SDP Offer/Answer dance.
 pub struct SdpApi<'a> {
@@ -73,31 +74,40 @@ impl<'a> SdpApi<'a> {
             return Err(RtcError::RemoteSdp("No m-lines in offer".into()));
         }
 
+        // Before anything is changed, i.e. a=rtcp-mux missing fails the negotiation.
+        offer.assert_consistency()?;
+        let rtcp_mux = negotiate_rtcp_mux(self.rtc, &offer)?;
+
         if self.rtc.ice.ice_lite() && offer.session.ice_lite() {
             return Err(RtcError::RemoteSdp(
                 "Both peers being ICE-Lite not supported".into(),
             ));
         }
 
+        self.rtc.session.set_rtcp_mux(rtcp_mux);
+
+        if self.rtc.ice.remote_credentials().is_none() {
+            // The side that makes the first offer is the controlling side, unless they
+            // are ICE Lite, in which case the roles are reversed (see RFC 5245). This has
+            // no bearing on the DTLS role, which is decided by a=setup.
+            self.rtc.ice.set_controlling(offer.session.ice_lite());
+        }
+
         add_ice_details(self.rtc, &offer, None)?;
 
-        if self.rtc.remote_fingerprint.is_none() {
-            if let Some(f) = offer.fingerprint() {
-                self.rtc.remote_fingerprint = Some(f);
+        if self.rtc.remote_fingerprints.is_empty() {
+            let f = offer.fingerprints();
+            if !f.is_empty() {
+                self.rtc.set_remote_fingerprints(f);
             } else {
                 self.rtc.disconnect();
                 return Err(RtcError::RemoteSdp("missing a=fingerprint".into()));
             }
         }
 
-        if !self.rtc.dtls.is_inited() {
-            // The side that makes the first offer is the controlling side, unless they
-            // are ICE Lite, in which case the roles are reversed (see RFC 5245).
-            self.rtc.ice.set_controlling(offer.session.ice_lite());
-        }
-
         // Ensure setup=active/passive is corresponding remote and init dtls.
-        init_dtls(self.rtc, &offer)?;
+        init_dtls(self.rtc, &offer, true)?;
+        update_max_message_size(self.rtc, &offer);
 
         // Modify session with offer
         apply_offer(&mut self.rtc.session, offer)?;
@@ -150,20 +160,27 @@ impl<'a> SdpApi<'a> {
             return Err(RtcError::ChangesOutOfOrder);
         }
 
+        answer.assert_consistency()?;
+        let rtcp_mux = negotiate_rtcp_mux(self.rtc, &answer)?;
+
         if self.rtc.ice.ice_lite() && answer.session.ice_lite() {
             return Err(RtcError::RemoteSdp(
                 "Both peers being ICE-Lite not supported".into(),
             ));
         }
 
+        self.rtc.session.set_rtcp_mux(rtcp_mux);
+
         add_ice_details(self.rtc, &answer, Some(&pending))?;
 
         // Ensure setup=active/passive is corresponding remote and init dtls.
-        init_dtls(self.rtc, &answer)?;
+        init_dtls(self.rtc, &answer, false)?;
+        update_max_message_size(self.rtc, &answer);
 
-        if self.rtc.remote_fingerprint.is_none() {
-            if let Some(f) = answer.fingerprint() {
-                self.rtc.remote_fingerprint = Some(f);
+        if self.rtc.remote_fingerprints.is_empty() {
+            let f = answer.fingerprints();
+            if !f.is_empty() {
+                self.rtc.set_remote_fingerprints(f);
             } else {
                 self.rtc.disconnect();
                 return Err(RtcError::RemoteSdp("missing a=fingerprint".into()));
@@ -215,8 +232,8 @@ impl<'a> SdpApi<'a> {
     /// the mid been advertised via [`Event::MediaAdded`][crate::Event::MediaAdded].
     ///
     /// * `stream_id` is used to synchronize media. It is `a=msid-semantic: WMS <streamId>` line in SDP.
-    /// * `track_id` is becomes both the track id in `a=msid <streamId> <trackId>` as well as the
-    ///   CNAME in the RTP SDES.
+    /// * `track_id` is the track id in `a=msid <streamId> <trackId>`. The CNAME is the same
+    ///   for all media, see [`RtcConfig::set_cname()`][crate::RtcConfig::set_cname].
     ///
     /// ```
     /// # use str0m::{Rtc, media::MediaKind, media::Direction};
@@ -233,7 +250,7 @@ impl<'a> SdpApi<'a> {
         stream_id: Option<String>,
         track_id: Option<String>,
     ) -> Mid {
-        let mid = self.rtc.new_mid();
+        let mid = self.new_mid();
 
         // https://www.rfc-editor.org/rfc/rfc8830
         // msid-id = 1*64token-char
@@ -262,7 +279,9 @@ impl<'a> SdpApi<'a> {
             Id::<20>::random().to_string()
         };
 
-        let rtx = kind.is_video().then(|| self.rtc.session.streams.new_ssrc());
+        let features = self.rtc.session.media_features;
+
+        let rtx = (kind.is_video() && features.rtx).then(|| self.rtc.session.streams.new_ssrc());
         let ssrcs = vec![(self.rtc.session.streams.new_ssrc(), rtx)];
 
         // TODO: let user configure stream/track name.
@@ -273,11 +292,15 @@ impl<'a> SdpApi<'a> {
 
         let add = AddMedia {
             mid,
-            cname: track_id,
+            cname: self.rtc.session.cname.clone(),
             msid,
             kind,
             dir,
             ssrcs,
+            rtp_mode: self.rtc.session.rtp_mode,
+            features,
+
+            simulcast: None,
 
             // Added later
             pts: vec![],
@@ -301,6 +324,11 @@ impl<'a> SdpApi<'a> {
     /// If the direction is set for media that doesn't exist, or if the direction is
     /// the same that's already set [`SdpApi::apply()`] not require a negotiation.
     pub fn set_direction(&mut self, mid: Mid, dir: Direction) {
+        let disabled = self.rtc.media(mid).map_or(false, |m| m.is_disabled());
+        if disabled {
+            return;
+        }
+
         let changed = self.rtc.session.set_direction(mid, dir);
 
         if changed {
@@ -308,6 +336,164 @@ impl<'a> SdpApi<'a> {
         }
     }
 
+    /// Remove an already existing media.
+    ///
+    /// The media is torn down when the negotiation completes: the streams are removed, and
+    /// an RTCP BYE is sent for our SSRCs. The m-line stays in the SDP with port 0 and
+    /// [`Direction::Inactive`], since m-lines are never removed, and the mid is never used
+    /// again. See [`Media::is_disabled()`].
+    ///
+    /// Removing media that doesn't exist, or is removed already, does nothing.
+    ///
+    /// ```
+    /// # use str0m::{Rtc, media::MediaKind, media::Direction};
+    /// let mut rtc = Rtc::new();
+    ///
+    /// let mut changes = rtc.sdp_api();
+    /// let mid = changes.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+    /// // ... negotiate, and later
+    ///
+    /// let mut changes = rtc.sdp_api();
+    /// changes.remove_media(mid);
+    /// ```
+    pub fn remove_media(&mut self, mid: Mid) {
+        let exists = self.rtc.media(mid).map_or(false, |m| !m.is_disabled());
+        if exists && !self.changes.removes(mid) {
+            self.changes.0.push(Change::RemoveMedia(mid));
+        }
+    }
+
+    /// Set RTP mode for a single media.
+    ///
+    /// A media in RTP mode bypasses str0m's payloaders and depayloaders. Incoming packets are
+    /// emitted as [`Event::RtpPacket`][crate::Event::RtpPacket] with the payload untouched, and
+    /// outgoing packets are written with [`StreamTx::write_rtp()`][crate::rtp::StreamTx::write_rtp].
+    /// RTCP, NACK/RTX and TWCC are still handled by str0m. Other media in the same session keep
+    /// using the sample level API.
+    ///
+    /// The default is [`RtcConfig::set_rtp_mode()`][crate::RtcConfig::set_rtp_mode]. For media
+    /// added via [`SdpApi::add_media()`] in this change, the mode is set when the negotiation
+    /// completes. For media added by the remote peer, set the mode when the
+    /// [`Event::MediaAdded`][crate::Event::MediaAdded] is emitted, before any packets arrive.
+    ///
+    /// RTP mode is not signalled in the SDP and never requires a negotiation.
+    ///
+    /// ```
+    /// # use str0m::{Rtc, media::MediaKind, media::Direction};
+    /// let mut rtc = Rtc::new();
+    ///
+    /// let mut changes = rtc.sdp_api();
+    ///
+    /// let mid = changes.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+    /// changes.set_rtp_mode(mid, true);
+    /// ```
+    pub fn set_rtp_mode(&mut self, mid: Mid, enabled: bool) {
+        for change in &mut self.changes.0 {
+            if let Change::AddMedia(add) = change {
+                if add.mid == mid {
+                    add.rtp_mode = enabled;
+                    return;
+                }
+            }
+        }
+
+        if let Some(media) = self.rtc.session.media_by_mid_mut(mid) {
+            media.set_rtp_mode(enabled);
+        }
+    }
+
+    /// Set the [`MediaFeatures`] for a single media.
+    ///
+    /// The default is [`RtcConfig::set_media_features()`][crate::RtcConfig::set_media_features].
+    /// The features are part of the SDP, and can only be set for media added via
+    /// [`SdpApi::add_media()`] in this change. For media added by the remote peer, the
+    /// default is used.
+    ///
+    /// Returns [`RtcError::MediaFeaturesLocked`] for any other media.
+    ///
+    /// ```
+    /// # use str0m::{Rtc, media::MediaKind, media::Direction, media::MediaFeatures};
+    /// let mut rtc = Rtc::new();
+    ///
+    /// let mut changes = rtc.sdp_api();
+    ///
+    /// let mid = changes.add_media(MediaKind::Video, Direction::RecvOnly, None, None);
+    /// changes.set_media_features(mid, MediaFeatures::none()).unwrap();
+    /// ```
+    pub fn set_media_features(
+        &mut self,
+        mid: Mid,
+        features: MediaFeatures,
+    ) -> Result<(), RtcError> {
+        for change in &mut self.changes.0 {
+            if let Change::AddMedia(add) = change {
+                if add.mid == mid {
+                    add.features = features;
+                    if !features.rtx {
+                        for (_, rtx) in &mut add.ssrcs {
+                            *rtx = None;
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(RtcError::MediaFeaturesLocked(mid))
+    }
+
+    /// Set simulcast for a media, `a=simulcast` with an `a=rid` line per layer.
+    ///
+    /// The `send` rids are the layers (encodings) we produce, and `recv` those we want to
+    /// receive. The answer can remove layers, but not add any. When the negotiation completes,
+    /// there is a send stream per negotiated send rid, see
+    /// [`DirectApi::stream_tx_by_mid()`][crate::change::DirectApi::stream_tx_by_mid].
+    ///
+    /// Works both for media added in this change, and media already negotiated, where
+    /// layers can be added or removed. Setting the same simulcast again does nothing.
+    ///
+    /// ```
+    /// # use str0m::{Rtc, media::MediaKind, media::Direction, media::Simulcast};
+    /// let mut rtc = Rtc::new();
+    ///
+    /// let mut changes = rtc.sdp_api();
+    ///
+    /// let mid = changes.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+    /// changes.set_simulcast(mid, Simulcast {
+    ///     send: vec!["h".into(), "m".into(), "l".into()],
+    ///     recv: vec![],
+    /// });
+    /// ```
+    pub fn set_simulcast(&mut self, mid: Mid, simulcast: Simulcast) {
+        let simulcast: SdpSimulcast = simulcast.into();
+
+        for change in &mut self.changes.0 {
+            if let Change::AddMedia(add) = change {
+                if add.mid == mid {
+                    // The send streams are made per rid when the negotiation completes.
+                    if !simulcast.send.is_empty() {
+                        add.ssrcs.clear();
+                    }
+                    add.simulcast = Some(simulcast);
+                    return;
+                }
+            }
+        }
+
+        let Some(media) = self.rtc.session.media_by_mid(mid) else {
+            return;
+        };
+
+        if media.simulcast() == Some(&simulcast) {
+            return;
+        }
+
+        self.changes
+            .0
+            .retain(|c| !matches!(c, Change::Simulcast(m, _) if *m == mid));
+        self.changes.0.push(Change::Simulcast(mid, simulcast));
+    }
+
     /// Add a new data channel and get the `id` that will be used.
     ///
     /// The first ever data channel added to a WebRTC session results in a media
@@ -329,19 +515,42 @@ impl<'a> SdpApi<'a> {
     /// let cid = changes.add_channel("my special channel".to_string());
     /// ```
     pub fn add_channel(&mut self, label: String) -> ChannelId {
+        let config = ChannelConfig {
+            label,
+            ..Default::default()
+        };
+
+        self.add_channel_with_config(config)
+    }
+
+    /// Add a new data channel with a specific configuration.
+    ///
+    /// Like [`SdpApi::add_channel()`], but for channels that are unordered, partially
+    /// reliable or negotiated out-of-band.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use str0m::channel::{ChannelConfig, Reliability};
+    /// let mut rtc = Rtc::new();
+    ///
+    /// let mut changes = rtc.sdp_api();
+    ///
+    /// let cid = changes.add_channel_with_config(ChannelConfig {
+    ///     label: "telemetry".into(),
+    ///     ordered: false,
+    ///     reliability: Reliability::MaxRetransmits { retransmits: 0 },
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn add_channel_with_config(&mut self, config: ChannelConfig) -> ChannelId {
         let has_media = self.rtc.session.app().is_some();
         let changes_contains_add_app = self.changes.contains_add_app();
 
         if !has_media && !changes_contains_add_app {
-            let mid = self.rtc.new_mid();
+            let mid = self.new_mid();
             self.changes.0.push(Change::AddApp(mid));
         }
 
-        let config = ChannelConfig {
-            label,
-            ..Default::default()
-        };
-
         let id = self.rtc.chan.new_channel(&config);
 
         self.changes.0.push(Change::AddChannel((id, config)));
@@ -358,6 +567,10 @@ impl<'a> SdpApi<'a> {
     /// candidates must be added via [`Rtc::add_local_candidate`] before connectivity can be
     /// re-established.
     ///
+    /// The restart takes effect when the answer is accepted. Media continues on the current
+    /// candidate pair until a pair is nominated with the new credentials, for as long as
+    /// consent for it lasts. DTLS and SRTP are not affected.
+    ///
     /// Returns the new ICE credentials that will be used going forward.
     pub fn ice_restart(&mut self, keep_local_candidates: bool) -> IceCreds {
         self.changes
@@ -443,6 +656,21 @@ impl<'a> SdpApi<'a> {
         pending_offer.retain_relevant(self.rtc);
         self.changes.extend(pending_offer.changes.drain(..));
     }
+
+    /// A new mid, that is neither in the session nor in the changes.
+    fn new_mid(&self) -> Mid {
+        loop {
+            let mid = self.rtc.new_mid();
+            let pending = self.changes.iter().any(|c| match c {
+                Change::AddMedia(v) => v.mid == mid,
+                Change::AddApp(v) => *v == mid,
+                _ => false,
+            });
+            if !pending {
+                break mid;
+            }
+        }
+    }
 }
 
 /// Pending offer from a previous [`Rtc::sdp_api()`] call.
@@ -486,7 +714,12 @@ impl SdpPendingOffer {
                     // If mid is missing, this is not relevant.
                     rtc.media(*m).map(|m| m.direction() != *d).unwrap_or(false)
                 }
+                Change::RemoveMedia(m) => rtc.media(*m).map_or(false, |m| !m.is_disabled()),
                 Change::IceRestart(v, _) => rtc.ice.local_credentials() != v,
+                Change::Simulcast(m, s) => rtc
+                    .media(*m)
+                    .map(|m| m.simulcast() != Some(s))
+                    .unwrap_or(false),
             }
         }
 
@@ -494,6 +727,25 @@ impl SdpPendingOffer {
     }
 }
 
+/// Whether to accept peers that don't multiplex RTP and RTCP on the same port.
+///
+/// WebRTC always does `a=rtcp-mux` (RFC 8834), but some legacy gateways refuse it and
+/// expect RTCP on a separate port, which with ICE is component 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtcpMuxPolicy {
+    /// Offer `a=rtcp-mux` without `a=rtcp-mux-only`, and accept a peer without mux.
+    ///
+    /// Without mux, RTCP goes over a second candidate pair, for which the local candidates
+    /// are made with [`Candidate::with_rtcp_component()`]. The negotiation fails if
+    /// there are no such candidates.
+    Negotiate,
+
+    /// Offer `a=rtcp-mux-only` (RFC 8858), and fail the negotiation if the peer
+    /// doesn't do mux.
+    #[default]
+    Require,
+}
+
 #[derive(Default)]
 pub(crate) struct Changes(pub Vec<Change>);
 
@@ -519,7 +771,9 @@ pub(crate) enum Change {
     AddApp(Mid),
     AddChannel((ChannelId, ChannelConfig)),
     Direction(Mid, Direction),
+    RemoveMedia(Mid),
     IceRestart(IceCreds, bool),
+    Simulcast(Mid, SdpSimulcast),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -530,6 +784,9 @@ pub(crate) struct AddMedia {
     pub kind: MediaKind,
     pub dir: Direction,
     pub ssrcs: Vec<(Ssrc, Option<Ssrc>)>,
+    pub rtp_mode: bool,
+    pub features: MediaFeatures,
+    pub simulcast: Option<SdpSimulcast>,
 
     // pts and index are filled in when creating the SDP OFFER.
     // The default PT order is set by the Session (BUNDLE).
@@ -560,6 +817,8 @@ fn requires_negotiation(c: &Change) -> bool {
         Change::AddApp(_) => true,
         Change::AddChannel(_) => false,
         Change::Direction(_, _) => true,
+        Change::RemoveMedia(_) => true,
+        Change::Simulcast(_, _) => true,
     }
 }
 
@@ -572,8 +831,63 @@ fn apply_direct_changes(rtc: &mut Rtc, mut changes: Changes) {
     }
 }
 
+/// Whether RTCP is muxed with RTP, from the a=rtcp-mux of the remote offer or answer.
+///
+/// The remote only goes without mux if our offer allowed it, or if we can answer without it,
+/// which both depend on the same things. Once muxed, it can't be turned off (RFC 5761 5.1.3).
+fn negotiate_rtcp_mux(rtc: &Rtc, remote: &Sdp) -> Result<bool, RtcError> {
+    let mut lines = remote
+        .media_lines
+        .iter()
+        .filter(|m| m.proto == Proto::Srtp && !m.disabled);
+
+    let Some(first) = lines.next() else {
+        // No RTP, nothing to negotiate.
+        return Ok(rtc.session.rtcp_mux());
+    };
+
+    let rtcp_mux = first.rtcp_mux();
+
+    // We have one ICE transport for the BUNDLE, with or without a separate RTCP component.
+    if let Some(m) = lines.find(|m| m.rtcp_mux() != rtcp_mux) {
+        return Err(SdpError::Inconsistent(format!(
+            "a=rtcp-mux differs between mid: {} and mid: {}",
+            first.mid(),
+            m.mid()
+        ))
+        .into());
+    }
+
+    if rtcp_mux {
+        return Ok(true);
+    }
+
+    let has_rtcp_candidates = rtc
+        .ice
+        .local_candidates()
+        .iter()
+        .any(|c| c.is_rtcp_component() && !c.discarded());
+
+    let reason = if rtc.session.rtcp_mux_policy() == RtcpMuxPolicy::Require {
+        "a=rtcp-mux is required"
+    } else if rtc.session.rtcp_mux_negotiated() == Some(true) {
+        "a=rtcp-mux can't be turned off"
+    } else if !has_rtcp_candidates {
+        "no local candidates for RTCP"
+    } else {
+        return Ok(false);
+    };
+
+    Err(SdpError::Inconsistent(format!(
+        "Expected a=rtcp-mux or a=rtcp-mux-only for mid: {} ({})",
+        first.mid(),
+        reason
+    ))
+    .into())
+}
+
 fn create_offer(rtc: &mut Rtc, changes: &Changes) -> SdpOffer {
-    if !rtc.dtls.is_inited() {
+    if rtc.ice.remote_credentials().is_none() {
         // The side that makes the first offer is the controlling side, unless they
         // are ICE Lite, in which case the roles are reversed (see RFC 5245).
         rtc.ice.set_controlling(!rtc.ice.ice_lite());
@@ -603,43 +917,77 @@ fn add_ice_details(
         Some(v) => *v != creds,
         None => false,
     };
-    if ice_restart {
-        let (new_local_creds, keep_local_candidates) = if let Some(pending) = pending {
-            // Since we have a pending, this is an answer to our offer.
-            pending.changes.ice_restart().ok_or_else(||
-                // Answer contained changed remote creds, indicating an ice restart
-                // but since we have no pending ice-creds, we didn't initiate it
-                // Ice restart in an ANSWER breaks spec.
-                    RtcError::RemoteSdp(
-                    "Ice restart in answer without one in the preceeding offer".into(),
-                ))?
-        } else {
-            // The remote OFFER had an ice restart, and we need to respond with
-            // new credentials in the ANSWER.
-            (IceCreds::new(), true)
-        };
 
-        rtc.ice
-            .ice_restart(new_local_creds.clone(), keep_local_candidates);
+    // Our offer had new credentials. They are used from here on, even if the remote
+    // didn't change theirs in the answer.
+    let local_restart = pending.and_then(|p| p.changes.ice_restart());
+
+    if let Some((new_local_creds, keep_local_candidates)) = local_restart {
+        rtc.ice.ice_restart(new_local_creds, keep_local_candidates);
+    } else if ice_restart {
+        if pending.is_some() {
+            // Answer contained changed remote creds, indicating an ice restart
+            // but since we have no pending ice-creds, we didn't initiate it
+            // Ice restart in an ANSWER breaks spec.
+            return Err(RtcError::RemoteSdp(
+                "Ice restart in answer without one in the preceeding offer".into(),
+            ));
+        }
+
+        // The remote OFFER had an ice restart, and we need to respond with
+        // new credentials in the ANSWER.
+        rtc.ice.ice_restart(IceCreds::new(), true);
     }
 
     rtc.ice.set_remote_credentials(creds);
 
     for r in sdp.ice_candidates() {
-        rtc.ice.add_remote_candidate(r.clone());
+        rtc.add_remote_candidate(r.clone());
+    }
+
+    let end_of_candidates =
+        sdp.session.end_of_candidates() || sdp.media_lines.iter().any(|m| m.end_of_candidates());
+
+    if end_of_candidates {
+        rtc.ice.set_remote_end_of_candidates();
     }
 
     Ok(())
 }
 
-fn init_dtls(rtc: &mut Rtc, remote_sdp: &Sdp) -> Result<(), RtcError> {
-    let setup = match remote_sdp.setup() {
-        Some(v) => match v {
-            // Remote being ActPass, we take Passive role.
-            Setup::ActPass => Setup::Passive,
-            _ => v.invert(),
-        },
+/// Decide our DTLS role from the `a=setup` of the remote OFFER or ANSWER (RFC 8842 5).
+///
+/// The role is independent of the ICE controlling/controlled roles. Once the DTLS
+/// transport is established, the role is kept for as long as it lives.
+fn init_dtls(rtc: &mut Rtc, remote_sdp: &Sdp, is_offer: bool) -> Result<(), RtcError> {
+    let remote = remote_sdp.setup();
+
+    if let Some(active) = rtc.dtls.is_active() {
+        let ours = if active {
+            Setup::Active
+        } else {
+            Setup::Passive
+        };
+        if remote == Some(ours) {
+            warn!(
+                "Remote a=setup:{} would flip the DTLS role, keeping {}",
+                ours, ours
+            );
+        }
+        return Ok(());
+    }
+
+    let setup = match remote {
+        // The answerer should take the active role, to save a round trip.
+        Some(Setup::ActPass) if is_offer => Setup::Active,
+        Some(Setup::ActPass) => {
+            return Err(RtcError::RemoteSdp(
+                "a=setup:actpass is not allowed in an answer".into(),
+            ));
+        }
+        Some(v) => v.invert(),
 
+        // The default is the remote being active (RFC 4145 4).
         None => {
             warn!("Missing a=setup line");
             Setup::Passive
@@ -652,7 +1000,22 @@ fn init_dtls(rtc: &mut Rtc, remote_sdp: &Sdp) -> Result<(), RtcError> {
     Ok(())
 }
 
+/// Limit what we send on data channels to the `a=max-message-size` of the remote.
+fn update_max_message_size(rtc: &mut Rtc, remote_sdp: &Sdp) {
+    let Some(app) = remote_sdp
+        .media_lines
+        .iter()
+        .find(|m| m.typ.is_channel() && !m.disabled)
+    else {
+        return;
+    };
+
+    rtc.sctp.set_remote_max_message_size(app.max_message_size());
+}
+
 fn as_sdp(session: &Session, params: AsSdpParams) -> Sdp {
+    let is_offer = params.pending.is_some();
+
     let (media_lines, mids, stream_ids) = {
         let mut v = as_media_lines(session);
 
@@ -671,12 +1034,19 @@ fn as_sdp(session: &Session, params: AsSdpParams) -> Sdp {
         // Add potentially new m-lines to the existing ones.
         v.extend(new_lines.iter().map(|n| n as &dyn AsSdpMediaLine));
 
+        // Removed, or being removed in this offer.
+        let is_disabled = |m: &dyn AsSdpMediaLine| {
+            m.is_disabled() || params.pending.map_or(false, |p| p.removes(m.mid()))
+        };
+
+        // Candidates should only be in the first BUNDLE mid, which is not a removed m-line.
+        let first_mid = v.iter().find(|m| !is_disabled(**m)).map(|m| m.mid());
+
         // Turn into sdp::MediaLine (m-line).
         let mut lines = v
             .iter()
             .map(|m| {
-                // Candidates should only be in the first BUNDLE mid
-                let include_candidates = m.index() == 0;
+                let include_candidates = Some(m.mid()) == first_mid;
 
                 let attrs = params.media_attributes(include_candidates);
 
@@ -694,7 +1064,47 @@ fn as_sdp(session: &Session, params: AsSdpParams) -> Sdp {
                     .cloned()
                     .collect();
 
-                m.as_media_line(attrs, &ssrcs, &session.exts, &params)
+                let mut line = m.as_media_line(attrs, &ssrcs, &session.exts, &params);
+
+                if is_disabled(*m) {
+                    disable_line(&mut line, &params);
+                    return line;
+                }
+
+                let is_media = line.typ != sdp::MediaType::Application;
+
+                let after_mux = |line: &MediaLine| {
+                    line.attrs
+                        .iter()
+                        .rposition(|a| {
+                            matches!(a, MediaAttribute::RtcpMux | MediaAttribute::RtcpMuxOnly)
+                        })
+                        .map(|i| i + 1)
+                        .unwrap_or(line.attrs.len())
+                };
+
+                if !session.rtcp_mux() {
+                    // RTCP on its own ICE component.
+                    line.attrs.retain(|a| *a != MediaAttribute::RtcpMux);
+                } else if is_media
+                    && is_offer
+                    && session.rtcp_mux_policy() == RtcpMuxPolicy::Require
+                {
+                    // We don't do RTCP on a separate port, which we tell in the offer (RFC 8858).
+                    let pos = after_mux(&line);
+                    line.attrs.insert(pos, MediaAttribute::RtcpMuxOnly);
+                }
+
+                // Reduced-size RTCP is always offered, and answered if the offer had it.
+                if is_media && (is_offer || session.rtcp_reduced_size()) {
+                    let pos = after_mux(&line);
+                    line.attrs.insert(pos, MediaAttribute::RtcpRsize);
+                }
+
+                let fecs = session.streams.fecs_tx(m.mid());
+                add_fec_ssrcs(&mut line, &fecs);
+
+                line
             })
             .collect::<Vec<_>>();
 
@@ -702,11 +1112,19 @@ fn as_sdp(session: &Session, params: AsSdpParams) -> Sdp {
             pending.apply_to(&mut lines);
         }
 
-        // Mids go into the session part of the SDP.
-        let mids = v.iter().map(|m| m.mid()).collect();
+        // Mids go into the session part of the SDP. Removed m-lines are not in the BUNDLE.
+        let mids = lines
+            .iter()
+            .filter(|l| !l.disabled)
+            .map(|l| l.mid())
+            .collect();
 
         let mut stream_ids = vec![];
-        for msid in v.iter().filter_map(|v| v.msid()) {
+        for msid in v
+            .iter()
+            .filter(|m| !is_disabled(**m))
+            .filter_map(|v| v.msid())
+        {
             if !stream_ids.contains(&msid.stream_id) {
                 stream_ids.push(msid.stream_id.clone());
             }
@@ -726,6 +1144,19 @@ fn as_sdp(session: &Session, params: AsSdpParams) -> Sdp {
         },
     ];
 
+    // We offer mixing one and two-byte header extensions if configured. In an answer,
+    // we can only allow it if the offer did (RFC 8285).
+    let allow_mixed = session.exts.allow_mixed()
+        && (params.pending.is_some()
+            || session
+                .medias
+                .iter()
+                .all(|m| m.remote_extmap().allow_mixed()));
+
+    if allow_mixed {
+        attrs.push(SessionAttribute::ExtmapAllowMixed);
+    }
+
     if session.ice_lite {
         attrs.push(SessionAttribute::IceLite);
     }
@@ -740,14 +1171,43 @@ fn as_sdp(session: &Session, params: AsSdpParams) -> Sdp {
     }
 }
 
-fn apply_offer(session: &mut Session, offer: SdpOffer) -> Result<(), RtcError> {
-    offer.assert_consistency()?;
+/// Make a removed or rejected m-line: port 0, inactive, and a single PT to be valid.
+fn disable_line(line: &mut MediaLine, params: &[PayloadParams]) {
+    use MediaAttribute::*;
+
+    let pt = line.pts.first().copied();
+
+    line.disabled = true;
+    line.bw = None;
+    line.attrs.retain(|a| match a {
+        RtpMap { pt: p, .. } => Some(*p) == pt,
+        IceUfrag(_) | IcePwd(_) | IceOptions(_) | Fingerprint(_) | Setup(_) => true,
+        Mid(_) | RtcpMux => true,
+        _ => false,
+    });
+
+    // A rejected m-line has none of the PTs, and any PT will do since they are ignored.
+    line.pts = match (pt, params.first()) {
+        (Some(pt), _) => vec![pt],
+        (None, Some(p)) => {
+            line.attrs.push(RtpMap {
+                pt: p.pt(),
+                value: p.spec().into(),
+            });
+            vec![p.pt()]
+        }
+        (None, None) => vec![0.into()],
+    };
 
+    line.attrs.push(Inactive);
+}
+
+fn apply_offer(session: &mut Session, offer: SdpOffer) -> Result<(), RtcError> {
     update_session(session, &offer);
 
-    let new_lines = sync_medias(session, &offer).map_err(RtcError::RemoteSdp)?;
+    let new_lines = sync_medias(session, &offer, None).map_err(RtcError::RemoteSdp)?;
 
-    add_new_lines(session, &new_lines, true).map_err(RtcError::RemoteSdp)?;
+    add_new_lines(session, &offer, &new_lines, None).map_err(RtcError::RemoteSdp)?;
 
     ensure_stream_tx(session);
 
@@ -759,18 +1219,16 @@ fn apply_answer(
     pending: Changes,
     answer: SdpAnswer,
 ) -> Result<(), RtcError> {
-    answer.assert_consistency()?;
-
     update_session(session, &answer);
 
-    let new_lines = sync_medias(session, &answer).map_err(RtcError::RemoteSdp)?;
+    let new_lines = sync_medias(session, &answer, Some(&pending)).map_err(RtcError::RemoteSdp)?;
 
     // The new_lines from the answer must correspond to what we sent in the offer.
     if let Some(err) = pending.ensure_correct_answer(&new_lines) {
         return Err(RtcError::RemoteSdp(err));
     }
 
-    add_new_lines(session, &new_lines, false).map_err(RtcError::RemoteSdp)?;
+    add_new_lines(session, &answer, &new_lines, Some(&pending)).map_err(RtcError::RemoteSdp)?;
 
     // Add all pending changes (since we pre-allocated SSRC communicated in the Offer).
     add_pending_changes(session, pending);
@@ -789,22 +1247,37 @@ fn ensure_stream_tx(session: &mut Session) {
 
         let mut rids: Vec<Option<Rid>> = vec![];
 
-        if let Some(sim) = media.simulcast() {
-            for rid in &*sim.send {
-                let rid: Rid = rid.0.as_str().into();
+        if let Some(sim) = media.simulcast().filter(|s| !s.send.is_empty()) {
+            // We send the first alternative of each layer.
+            for alts in &*sim.send {
+                let rid: Rid = alts.first().0.as_str().into();
                 rids.push(Some(rid));
             }
         } else {
             rids.push(None);
         }
 
+        // Layers removed by a renegotiation, or the single stream replaced by layers.
+        let removed: Vec<Ssrc> = session
+            .streams
+            .streams_tx_by_mid(media.mid())
+            .filter(|s| !rids.contains(&s.rid()))
+            .map(|s| s.ssrc())
+            .collect();
+
+        for ssrc in removed {
+            info!("Remove send stream no longer negotiated: {}", ssrc);
+            session.streams.remove_stream_tx(ssrc);
+        }
+
         // If any payload param has RTX, we need to prepare for RTX. This is because we always
         // communicate a=ssrc lines, which need to be complete with main and RTX SSRC.
-        let has_rtx = session
-            .codec_config
-            .iter()
-            .filter(|p| media.remote_pts().contains(&p.pt))
-            .any(|p| p.resend().is_some());
+        let has_rtx = media.features().rtx
+            && session
+                .codec_config
+                .iter()
+                .filter(|p| media.remote_pts().contains(&p.pt))
+                .any(|p| p.resend().is_some());
 
         for rid in rids {
             // If we already have the stream, we don't make any new one.
@@ -829,6 +1302,11 @@ fn ensure_stream_tx(session: &mut Session) {
                 .streams
                 .declare_stream_tx(ssrc, rtx, media.mid(), rid);
 
+            if !media.features().rtx {
+                stream.disable_rtx_cache();
+                continue;
+            }
+
             // Configure cache size
             let size = if media.kind().is_audio() {
                 session.send_buffer_audio
@@ -846,6 +1324,10 @@ fn add_pending_changes(session: &mut Session, pending: Changes) {
     for change in pending.0 {
         let add_media = match change {
             Change::AddMedia(v) => v,
+            Change::RemoveMedia(mid) => {
+                session.disable_media(mid);
+                continue;
+            }
             _ => continue,
         };
 
@@ -855,10 +1337,17 @@ fn add_pending_changes(session: &mut Session, pending: Changes) {
             .find(|m| m.mid() == add_media.mid)
             .expect("Media to be added for pending mid");
 
+        // The answer rejected the m-line.
+        if media.is_disabled() {
+            continue;
+        }
+
         // the cname/msid has already been communicated in the offer, we need to kep
         // it the same once the m-line is created.
         media.set_cname(add_media.cname);
         media.set_msid(add_media.msid);
+        media.set_rtp_mode(add_media.rtp_mode);
+        media.set_features(add_media.features);
 
         for (ssrc, rtx) in add_media.ssrcs {
             // TODO: When we allow sending RID, we need to add that here.
@@ -866,6 +1355,11 @@ fn add_pending_changes(session: &mut Session, pending: Changes) {
                 .streams
                 .declare_stream_tx(ssrc, rtx, add_media.mid, None);
 
+            if !add_media.features.rtx {
+                stream.disable_rtx_cache();
+                continue;
+            }
+
             let size = if media.kind().is_audio() {
                 session.send_buffer_audio
             } else {
@@ -880,8 +1374,13 @@ fn add_pending_changes(session: &mut Session, pending: Changes) {
 /// Compares m-lines in Sdp with that already in the session.
 ///
 /// * Existing m-lines can apply changes (such as direction change).
-/// * New m-lines are returned to the caller.
-fn sync_medias<'a>(session: &mut Session, sdp: &'a Sdp) -> Result<Vec<&'a MediaLine>, String> {
+/// * Existing m-lines with port 0 disable the media.
+/// * New m-lines are returned to the caller with their index.
+fn sync_medias<'a>(
+    session: &mut Session,
+    sdp: &'a Sdp,
+    pending: Option<&Changes>,
+) -> Result<Vec<(usize, &'a MediaLine)>, String> {
     let mut new_lines = Vec::with_capacity(sdp.media_lines.len());
 
     for (idx, m) in sdp.media_lines.iter().enumerate() {
@@ -901,12 +1400,34 @@ fn sync_medias<'a>(session: &mut Session, sdp: &'a Sdp) -> Result<Vec<&'a MediaL
                         return index_err(m.mid());
                     }
 
+                    if m.disabled {
+                        session.disable_media(m.mid());
+                        continue;
+                    }
+
+                    if media.is_disabled() {
+                        warn!("Ignore port for removed m-line with mid: {}", m.mid());
+                        continue;
+                    }
+
+                    // For an existing m-line, we offered what was previously negotiated,
+                    // unless changed in the offer.
+                    let offered = pending.map(|p| Offered {
+                        exts: media.remote_extmap().clone(),
+                        simulcast: p
+                            .simulcast_for(media.mid())
+                            .unwrap_or(media.simulcast())
+                            .cloned(),
+                    });
+
                     update_media(
                         media,
                         m,
                         &mut session.codec_config,
                         &session.exts,
                         &mut session.streams,
+                        offered.as_ref(),
+                        sdp.session.extmap_allow_mixed(),
                     );
 
                     continue;
@@ -917,8 +1438,19 @@ fn sync_medias<'a>(session: &mut Session, sdp: &'a Sdp) -> Result<Vec<&'a MediaL
             }
         }
 
+        // A removed m-line can be reused for a new mid.
+        let recycled = session
+            .medias
+            .iter()
+            .position(|l| l.index() == idx && l.is_disabled());
+
+        if let Some(pos) = recycled {
+            let media = session.medias.remove(pos);
+            info!("Reuse m-line of mid {} for: {}", media.mid(), m.mid());
+        }
+
         // Second, discover new m-lines.
-        new_lines.push(m);
+        new_lines.push((idx, m));
     }
 
     fn index_err<T>(mid: Mid) -> Result<T, String> {
@@ -931,23 +1463,59 @@ fn sync_medias<'a>(session: &mut Session, sdp: &'a Sdp) -> Result<Vec<&'a MediaL
 /// Adds new m-lines as found in an offer or answer.
 fn add_new_lines(
     session: &mut Session,
-    new_lines: &[&MediaLine],
-    is_offer: bool,
+    sdp: &Sdp,
+    new_lines: &[(usize, &MediaLine)],
+    pending: Option<&Changes>,
 ) -> Result<(), String> {
-    for m in new_lines {
-        let idx = session.line_count();
+    let is_offer = pending.is_none();
+
+    for (idx, m) in new_lines {
+        let idx = *idx;
 
         if m.typ.is_media() {
             let mut media = Media::from_remote_media_line(m, idx, is_offer);
             media.need_open_event = is_offer;
+            media.set_rtp_mode(session.rtp_mode);
+            media.set_cname(session.cname.clone());
+            media.set_features(session.media_features);
+
+            // We can only reject an offered m-line without any codec we have.
+            let no_codec = is_offer
+                && !m
+                    .rtp_params()
+                    .into_iter()
+                    .any(|p| session.codec_config.match_params(p).is_some());
+
+            if no_codec {
+                info!("Reject m-line without matching codec: {}", m.mid());
+            }
+
+            // Removed before we knew it, rejected by us, or rejected in the answer.
+            if m.disabled || no_codec {
+                media.set_disabled();
+                media.need_open_event = false;
+                media.need_changed_event = false;
+                session.add_media(media);
+                continue;
+            }
 
             // Match/remap remote params.
             session
                 .codec_config
                 .update_params(&m.rtp_params(), m.direction());
 
-            // Remap the extension to that of the answer.
-            session.exts.remap(&m.extmaps());
+            // For a new m-line in an answer, we offered the session extensions.
+            let offered = pending.map(|p| Offered {
+                exts: session.exts.cloned_with_type(media.kind().is_audio()),
+                simulcast: p.simulcast_for(m.mid()).flatten().cloned(),
+            });
+
+            // Remap the extension to that of the offer, or lock down those in the answer.
+            let extmaps = match &offered {
+                Some(offered) => answered_extmaps(m, &offered.exts),
+                None => m.extmaps(),
+            };
+            session.exts.remap(&extmaps);
 
             update_media(
                 &mut media,
@@ -955,6 +1523,8 @@ fn add_new_lines(
                 &mut session.codec_config,
                 &session.exts,
                 &mut session.streams,
+                offered.as_ref(),
+                sdp.session.extmap_allow_mixed(),
             );
 
             session.add_media(media);
@@ -974,11 +1544,35 @@ fn add_new_lines(
 /// Update session level properties like
 /// Extensions from offer or answer.
 fn update_session(session: &mut Session, sdp: &Sdp) {
-    // Does any m-line contain a a=rtcp-fb:xx transport-cc?
-    let has_transport_cc = sdp
+    // With BUNDLE, all m-lines share the RTCP, so reduced-size needs every one of them
+    // to agree.
+    let rtcp_rsize = sdp
         .media_lines
         .iter()
-        .any(|m| m.rtp_params().iter().any(|p| p.fb_transport_cc));
+        .filter(|m| m.typ != sdp::MediaType::Application && !m.disabled)
+        .all(|m| m.rtcp_rsize());
+    session.set_rtcp_reduced_size(rtcp_rsize);
+
+    let caps: Vec<_> = sdp
+        .media_lines
+        .iter()
+        .map(|m| {
+            let features = session
+                .media_by_mid(m.mid())
+                .map(|m| m.features())
+                .unwrap_or(session.media_features);
+
+            let mut caps = negotiated_feedback(&session.codec_config, m);
+            caps.twcc &= features.twcc;
+            caps
+        })
+        .collect();
+
+    // Does any m-line contain a a=rtcp-fb:xx transport-cc we also have?
+    let has_transport_cc = caps.iter().any(|c| c.twcc);
+
+    // Does any m-line contain a a=rtcp-fb:xx goog-remb we also have?
+    let has_remb = caps.iter().any(|c| c.remb);
 
     // Is the session level sequence number enabled?
     let has_twcc_header = session
@@ -991,9 +1585,37 @@ fn update_session(session: &mut Session, sdp: &Sdp) {
     // number header is enabled.
     if has_transport_cc && has_twcc_header {
         session.enable_twcc_feedback();
+    } else if has_remb {
+        // The remote can't estimate bandwidth itself, but takes our estimate as REMB.
+        session.enable_remb_feedback();
     }
 }
 
+/// The feedback both we and the remote have for any of the payload types of the m-line.
+fn negotiated_feedback(config: &CodecConfig, m: &MediaLine) -> FeedbackCaps {
+    let mut caps = FeedbackCaps::none();
+
+    for remote in m.rtp_params() {
+        if let Some(local) = config.match_params(remote) {
+            caps.add_negotiated(local, &remote);
+        }
+    }
+
+    caps
+}
+
+/// The Opus parameters of the first Opus PT of the m-line that we have too.
+fn negotiated_opus(config: &CodecConfig, m: &MediaLine) -> Option<OpusParams> {
+    m.rtp_params()
+        .into_iter()
+        .filter(|p| p.spec().codec == Codec::Opus)
+        .find_map(|remote| {
+            let local = config.match_params(remote)?;
+            let remote = OpusParams::parse(m.fmtp(remote.pt()));
+            Some(OpusParams::from(local.spec().format).negotiate(&remote))
+        })
+}
+
 /// Returns all media/channels as `AsMediaLine` trait.
 fn as_media_lines(session: &Session) -> Vec<&dyn AsSdpMediaLine> {
     let mut v = vec![];
@@ -1006,12 +1628,44 @@ fn as_media_lines(session: &Session) -> Vec<&dyn AsSdpMediaLine> {
     v
 }
 
+/// What we offered for an m-line, to check the answer against.
+struct Offered {
+    exts: ExtensionMap,
+    simulcast: Option<SdpSimulcast>,
+}
+
+/// The extmaps of an answer that are in the offer.
+///
+/// RFC 8285: The answerer must use the ids of the offer, so a mapping with a different id
+/// is rejected. An extension we never offered is ignored.
+fn answered_extmaps<'a>(m: &'a MediaLine, offered: &ExtensionMap) -> Vec<(u8, &'a Extension)> {
+    m.extmaps()
+        .into_iter()
+        .filter(|(id, ext)| match offered.id_of((*ext).clone()) {
+            Some(offered_id) if offered_id == *id => true,
+            Some(offered_id) => {
+                warn!(
+                    "Reject extmap with different id in answer ({} != {}): {}",
+                    id, offered_id, ext
+                );
+                false
+            }
+            None => {
+                debug!("Ignore extmap in answer that wasn't offered: {}", ext);
+                false
+            }
+        })
+        .collect()
+}
+
 fn update_media(
     media: &mut Media,
     m: &MediaLine,
     config: &mut CodecConfig,
     exts: &ExtensionMap,
     streams: &mut Streams,
+    offered: Option<&Offered>,
+    sdp_allow_mixed: bool,
 ) {
     // Direction changes
     //
@@ -1033,8 +1687,28 @@ fn update_media(
         media.set_direction(new_dir);
     }
 
-    for rid in m.rids().iter() {
-        media.expect_rid(*rid);
+    // Simulcast configuration
+    let simulcast = m.simulcast().and_then(|s| {
+        if s.is_munged {
+            warn!("Not supporting simulcast via munging SDP");
+            return None;
+        }
+
+        // Invert before setting, since it has a recv and send config.
+        let s = s.invert();
+
+        match offered {
+            // An answer can only remove from what we offered.
+            Some(o) => s.narrow_to(o.simulcast.as_ref()?),
+            None => Some(s),
+        }
+    });
+    media.set_simulcast(simulcast);
+
+    if media.simulcast().is_none() {
+        for rid in m.rids().iter() {
+            media.expect_rid(*rid);
+        }
     }
 
     // Narrowing/ordering of of PT
@@ -1045,8 +1719,33 @@ fn update_media(
         .collect();
     media.set_remote_pts(pts);
 
+    for pt in m.dangling_rtx_pts() {
+        warn!(
+            "No RTX for PT {} in mid {}, apt missing or not a known PT",
+            pt,
+            media.mid()
+        );
+    }
+
+    let mut caps = negotiated_feedback(config, m);
+    caps.nack &= media.features().nack;
+    caps.twcc &= media.features().twcc;
+    media.set_feedback_caps(caps);
+
+    media.set_opus_params(negotiated_opus(config, m));
+
     let mut remote_extmap = ExtensionMap::empty();
-    for (id, ext) in m.extmaps().into_iter() {
+
+    // Mixing is allowed if both sides say so, at session or media level.
+    remote_extmap
+        .set_allow_mixed(exts.allow_mixed() && (sdp_allow_mixed || m.extmap_allow_mixed()));
+
+    let extmaps = match offered {
+        Some(offered) => answered_extmaps(m, &offered.exts),
+        None => m.extmaps(),
+    };
+
+    for (id, ext) in extmaps {
         // The remapping of extensions should already have happened, which
         // means the ID are matching in the session to the remote.
 
@@ -1061,9 +1760,18 @@ fn update_media(
             continue;
         }
 
+        // Without transport-cc for any of the payload types, the remote doesn't
+        // report the sequence numbers.
+        if *in_session == Extension::TransportSequenceNumber && !caps.twcc {
+            continue;
+        }
+
         // Use the Extension from session, since there might be a special
         // serializer for cases like VLA.
         remote_extmap.set(id, in_session.clone());
+
+        // The remote direction is reverse to ours.
+        remote_extmap.set_direction(id, m.extmap_direction(id).invert());
     }
     media.set_remote_extmap(remote_extmap);
 
@@ -1072,7 +1780,35 @@ fn update_media(
         // This will always be for ReceiverSource since any incoming a=ssrc line will be
         // about the remote side's SSRC.
         let infos = m.ssrc_info();
-        let main = infos.iter().filter(|i| i.repairs.is_none());
+        let main = infos
+            .iter()
+            .filter(|i| i.repairs.is_none() && i.fec_for.is_none());
+
+        // RTX SSRCs are only of use with a RTX PT.
+        let has_rtx_pt = m.rtp_params().iter().any(|p| p.resend.is_some());
+
+        for i in &infos {
+            let Some(repairs) = i.repairs else {
+                continue;
+            };
+            if !infos.iter().any(|m| m.ssrc == repairs) {
+                warn!(
+                    "Ignore a=ssrc-group:FID {} {} without a=ssrc for {}",
+                    repairs, i.ssrc, repairs
+                );
+            } else if !has_rtx_pt {
+                warn!(
+                    "Ignore a=ssrc-group:FID {} {} without RTX PT in mid {}",
+                    repairs,
+                    i.ssrc,
+                    media.mid()
+                );
+            }
+        }
+
+        // Older senders signal simulcast with a=ssrc-group:SIM and never send rid.
+        let sim_group = m.simulcast().is_none() && infos.iter().any(|i| i.sim_layer.is_some());
+        media.set_sim_group(sim_group);
 
         if m.simulcast().is_none() {
             // Only use pre-communicated SSRC if we are running without simulcast.
@@ -1086,38 +1822,100 @@ fn update_media(
                 let repair_ssrc = infos
                     .iter()
                     .find(|r| r.repairs == Some(i.ssrc))
-                    .map(|r| r.ssrc);
+                    .map(|r| r.ssrc)
+                    .filter(|_| has_rtx_pt);
+
+                // The SIM layers get a rid after their position in the group, to be the
+                // same layers as for rid based simulcast.
+                let rid = i.sim_layer.map(|l| Rid::from(l.to_string().as_str()));
+                if let Some(rid) = rid {
+                    media.expect_rid(rid);
+                }
+
+                let is_new = !streams.has_stream_rx(i.ssrc);
 
                 // If remote communicated a main a=ssrc, but no RTX, we will not send nacks.
-                let suppress_nack = repair_ssrc.is_none();
-                streams.expect_stream_rx(
-                    i.ssrc,
-                    repair_ssrc,
-                    media.mid(),
-                    None,
-                    suppress_nack,
-                    None,
-                );
-            }
-        }
+                let suppress_nack = repair_ssrc.is_none() || !media.features().nack;
+                // A renegotiation can add the RTX to an existing stream.
+                streams
+                    .expect_stream_rx(i.ssrc, repair_ssrc, media.mid(), rid, suppress_nack, None)
+                    .suppress_nack(suppress_nack);
+
+                if let Some(rid) = rid.filter(|_| is_new) {
+                    streams.add_layer_discovered(LayerDiscovered {
+                        mid: media.mid(),
+                        rid,
+                        ssrc: i.ssrc,
+                    });
+                }
 
-        // Simulcast configuration
-        if let Some(s) = m.simulcast() {
-            if s.is_munged {
-                warn!("Not supporting simulcast via munging SDP");
-            } else if media.simulcast().is_none() {
-                // Invert before setting, since it has a recv and send config.
-                media.set_simulcast(s.invert());
+                let fec_ssrc = infos.iter().find(|r| r.fec_for == Some(i.ssrc));
+                if let Some(fec_ssrc) = fec_ssrc {
+                    streams.expect_stream_rx_fec(i.ssrc, fec_ssrc.ssrc);
+                }
             }
         }
     }
 }
 
+/// The a=rid lines and a=simulcast for our side of the simulcast.
+fn simulcast_attrs(s: &SdpSimulcast) -> Vec<MediaAttribute> {
+    let to_rids = |gs: &SimulcastGroups, direction: &'static str| -> Vec<MediaAttribute> {
+        gs.rids()
+            .map(|rid| MediaAttribute::Rid {
+                id: rid.clone(),
+                direction,
+                pt: vec![],
+                // The restrictions we understand are the ones we agree to.
+                restriction: s
+                    .restrictions(&rid.0)
+                    .map(|r| r.known())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    };
+
+    let mut attrs = to_rids(&s.recv, "recv");
+    attrs.extend(to_rids(&s.send, "send"));
+    attrs.push(MediaAttribute::Simulcast(s.clone()));
+    attrs
+}
+
+/// Announce the FlexFEC SSRCs of the send streams, `a=ssrc-group:FEC-FR <ssrc> <fec>`.
+fn add_fec_ssrcs(line: &mut MediaLine, fecs: &[(Ssrc, Ssrc)]) {
+    for (ssrc, fec) in fecs {
+        // Same cname and msid as the protected SSRC.
+        let fec_attrs: Vec<_> = line
+            .attrs
+            .iter()
+            .filter_map(|a| match a {
+                MediaAttribute::Ssrc {
+                    ssrc: s,
+                    attr,
+                    value,
+                } if s == ssrc => Some(MediaAttribute::Ssrc {
+                    ssrc: *fec,
+                    attr: attr.clone(),
+                    value: value.clone(),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        line.attrs.extend(fec_attrs);
+        line.attrs.push(MediaAttribute::SsrcGroup {
+            semantics: "FEC-FR".to_string(),
+            ssrcs: vec![*ssrc, *fec],
+        });
+    }
+}
+
 trait AsSdpMediaLine {
     fn mid(&self) -> Mid;
     fn msid(&self) -> Option<&Msid>;
     fn index(&self) -> usize;
     fn kind(&self) -> MediaKind;
+    fn is_disabled(&self) -> bool;
     fn as_media_line(
         &self,
         attrs: Vec<MediaAttribute>,
@@ -1137,6 +1935,9 @@ impl AsSdpMediaLine for (Mid, usize) {
     fn index(&self) -> usize {
         self.1
     }
+    fn is_disabled(&self) -> bool {
+        false
+    }
     fn kind(&self) -> MediaKind {
         MediaKind::Audio // doesn't matter for App
     }
@@ -1149,7 +1950,7 @@ impl AsSdpMediaLine for (Mid, usize) {
     ) -> MediaLine {
         attrs.push(MediaAttribute::Mid(self.0));
         attrs.push(MediaAttribute::SctpPort(5000));
-        attrs.push(MediaAttribute::MaxMessageSize(262144));
+        attrs.push(MediaAttribute::MaxMessageSize(MAX_MESSAGE_SIZE));
 
         MediaLine {
             typ: sdp::MediaType::Application,
@@ -1175,6 +1976,9 @@ impl AsSdpMediaLine for Media {
     fn kind(&self) -> MediaKind {
         Media::kind(self)
     }
+    fn is_disabled(&self) -> bool {
+        Media::is_disabled(self)
+    }
     fn as_media_line(
         &self,
         mut attrs: Vec<MediaAttribute>,
@@ -1191,9 +1995,17 @@ impl AsSdpMediaLine for Media {
 
         let audio = self.kind() == MediaKind::Audio;
         for (id, ext) in self.remote_extmap().iter_by_media_type(audio) {
+            if *ext == Extension::TransportSequenceNumber && !self.features().twcc {
+                continue;
+            }
             attrs.push(MediaAttribute::ExtMap {
                 id,
                 ext: ext.clone(),
+                // Our side of the direction, as negotiated. sendrecv is not written out.
+                direction: self
+                    .remote_extmap()
+                    .direction(id)
+                    .filter(|d| *d != Direction::SendRecv),
             });
         }
 
@@ -1208,8 +2020,24 @@ impl AsSdpMediaLine for Media {
 
         let mut pts = vec![];
 
+        let features = self.features();
+
         for p in effective_params {
-            p.as_media_attrs(&mut attrs);
+            // Don't offer feedback for what is turned off.
+            let mut p = *p;
+            p.fb_nack &= features.nack;
+            p.fb_transport_cc &= features.twcc;
+            if !features.rtx {
+                p.resend = None;
+            }
+
+            match self.opus_params() {
+                // What we receive, constrained by what the remote said.
+                Some(opus) if p.spec().codec == Codec::Opus => {
+                    p.as_media_attrs_with_fmtp(&mut attrs, opus.to_format_param());
+                }
+                _ => p.as_media_attrs(&mut attrs),
+            }
 
             // The pts that will be advertised in the SDP
             pts.push(p.pt());
@@ -1219,20 +2047,7 @@ impl AsSdpMediaLine for Media {
         }
 
         if let Some(s) = self.simulcast() {
-            fn to_rids<'a>(
-                gs: &'a SimulcastGroups,
-                direction: &'static str,
-            ) -> impl Iterator<Item = MediaAttribute> + 'a {
-                gs.iter().map(move |rid| MediaAttribute::Rid {
-                    id: rid.clone(),
-                    direction,
-                    pt: vec![],
-                    restriction: vec![],
-                })
-            }
-            attrs.extend(to_rids(&s.recv, "recv"));
-            attrs.extend(to_rids(&s.send, "send"));
-            attrs.push(MediaAttribute::Simulcast(s.clone()));
+            attrs.extend(simulcast_attrs(s));
         }
 
         // Outgoing SSRCs
@@ -1262,18 +2077,14 @@ impl AsSdpMediaLine for Media {
             }
         }
 
-        let count = ssrcs_tx.len();
-        #[allow(clippy::comparison_chain)]
-        if count == 1 {
-            let (ssrc, ssrc_rtx) = &ssrcs_tx[0];
+        // One per simulcast layer, if we send simulcast.
+        for (ssrc, ssrc_rtx) in ssrcs_tx {
             if let Some(ssrc_rtx) = ssrc_rtx {
                 attrs.push(MediaAttribute::SsrcGroup {
                     semantics: "FID".to_string(),
                     ssrcs: vec![*ssrc, *ssrc_rtx],
                 });
             }
-        } else {
-            // TODO: handle simulcast
         }
 
         MediaLine {
@@ -1296,6 +2107,25 @@ impl From<MediaKind> for MediaType {
     }
 }
 
+/// Local candidates to put in the SDP.
+///
+/// Peer reflexive candidates are discovered by the checks and are never signalled.
+fn signalled_candidates(rtc: &Rtc, is_offer: bool) -> Vec<Candidate> {
+    // Candidates for RTCP are offered until a=rtcp-mux is agreed, and answered without it.
+    let rtcp = match rtc.session.rtcp_mux_negotiated() {
+        Some(rtcp_mux) => !rtcp_mux,
+        None => is_offer && rtc.session.rtcp_mux_policy() == RtcpMuxPolicy::Negotiate,
+    };
+
+    rtc.ice
+        .local_candidates()
+        .iter()
+        .filter(|c| c.kind() != CandidateKind::PeerReflexive)
+        .filter(|c| rtcp || !c.is_rtcp_component())
+        .cloned()
+        .collect()
+}
+
 struct AsSdpParams<'a, 'b> {
     pub candidates: Vec<Candidate>,
     pub creds: IceCreds,
@@ -1313,7 +2143,7 @@ impl<'a, 'b> AsSdpParams<'a, 'b> {
                 // If we are performing an ICE restart and we are keeping the same
                 // candidates we need to use ufrag from the new ICE credentials
                 // in our offer.
-                let mut new_candidates = rtc.ice.local_candidates().to_vec();
+                let mut new_candidates = signalled_candidates(rtc, true);
                 for c in &mut new_candidates {
                     c.set_ufrag(&new_creds.ufrag);
                 }
@@ -1325,7 +2155,7 @@ impl<'a, 'b> AsSdpParams<'a, 'b> {
         } else {
             (
                 rtc.ice.local_credentials().clone(),
-                rtc.ice.local_candidates().to_vec(),
+                signalled_candidates(rtc, pending.is_some()),
             )
         };
 
@@ -1399,7 +2229,7 @@ impl Changes {
     }
 
     /// Tests the given lines (from answer) corresponds to changes.
-    fn ensure_correct_answer(&self, lines: &[&MediaLine]) -> Option<String> {
+    fn ensure_correct_answer(&self, lines: &[(usize, &MediaLine)]) -> Option<String> {
         if self.count_new_medias() != lines.len() {
             return Some(format!(
                 "Differing m-line count in offer vs answer: {} != {}",
@@ -1408,7 +2238,7 @@ impl Changes {
             ));
         }
 
-        'next: for l in lines {
+        'next: for (_, l) in lines {
             let mid = l.mid();
 
             for m in &self.0 {
@@ -1457,22 +2287,50 @@ impl Changes {
     ) -> impl Iterator<Item = Media> + '_ {
         self.0
             .iter()
+            .filter(|c| matches!(c, Change::AddMedia(_) | Change::AddApp(_)))
             .enumerate()
             .filter_map(move |(idx, c)| c.as_new_media(index_start + idx, config, exts))
     }
 
+    /// Whether the changes remove the media.
+    fn removes(&self, mid: Mid) -> bool {
+        self.0
+            .iter()
+            .any(|c| matches!(c, Change::RemoveMedia(m) if *m == mid))
+    }
+
     pub(crate) fn apply_to(&self, lines: &mut [MediaLine]) {
         for change in &self.0 {
-            if let Change::Direction(mid, dir) = change {
-                if let Some(line) = lines.iter_mut().find(|l| l.mid() == *mid) {
-                    if let Some(dir_pos) = line.attrs.iter().position(|a| a.is_direction()) {
-                        line.attrs[dir_pos] = (*dir).into();
+            match change {
+                Change::Direction(mid, dir) => {
+                    if let Some(line) = lines.iter_mut().find(|l| l.mid() == *mid && !l.disabled) {
+                        if let Some(dir_pos) = line.attrs.iter().position(|a| a.is_direction()) {
+                            line.attrs[dir_pos] = (*dir).into();
+                        }
+                    }
+                }
+                Change::Simulcast(mid, s) => {
+                    if let Some(line) = lines.iter_mut().find(|l| l.mid() == *mid && !l.disabled) {
+                        line.attrs.retain(|a| {
+                            !matches!(a, MediaAttribute::Rid { .. } | MediaAttribute::Simulcast(_))
+                        });
+                        line.attrs.extend(simulcast_attrs(s));
                     }
                 }
+                _ => {}
             }
         }
     }
 
+    /// The simulcast we offer for a mid, if changed in this offer.
+    fn simulcast_for(&self, mid: Mid) -> Option<Option<&SdpSimulcast>> {
+        self.0.iter().find_map(|c| match c {
+            Change::AddMedia(v) if v.mid == mid => Some(v.simulcast.as_ref()),
+            Change::Simulcast(m, s) if *m == mid => Some(Some(s)),
+            _ => None,
+        })
+    }
+
     fn ssrcs_for_mid(&self, mid: Mid) -> &[(Ssrc, Option<Ssrc>)] {
         let maybe_add_media = self
             .0
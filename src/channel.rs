@@ -51,6 +51,9 @@ impl<'a> Channel<'a> {
     }
 
     /// Write data to the remote peer and indicate whether it's text or binary.
+    ///
+    /// Each write is one message, up to the `a=max-message-size` of the remote, and at most
+    /// 256 kB. Larger messages are split into SCTP chunks and reassembled by the remote.
     pub fn write(&mut self, binary: bool, buf: &[u8]) -> Result<usize, RtcError> {
         Ok(self.rtc.sctp.write(self.sctp_stream_id, binary, buf)?)
     }
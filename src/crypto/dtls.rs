@@ -5,6 +5,9 @@ use std::fmt;
 
 use crate::net::DatagramSend;
 
+#[cfg(feature = "openssl")]
+use crate::dtls::DtlsError;
+
 use super::{CryptoError, Fingerprint, KeyingMaterial, SrtpProfile};
 
 // libWebRTC says "WebRTC" here when doing OpenSSL, for BoringSSL they seem
@@ -25,11 +28,15 @@ pub enum DtlsEvent {
 
     /// The fingerprint of the remote peer.
     ///
-    /// This should be checked against the fingerprint communicated in the SDP.
+    /// When verification is enabled, this is the fingerprint communicated in the SDP
+    /// that matched the remote certificate.
     RemoteFingerprint(Fingerprint),
 
     /// Decrypted data from incoming DTLS traffic.
     Data(Vec<u8>),
+
+    /// The remote sent a close_notify alert, which has been answered with ours.
+    CloseNotify,
 }
 
 /// Certificate used for DTLS.
@@ -50,6 +57,44 @@ impl DtlsCert {
         DtlsCert(DtlsCertInner::OpenSsl(cert))
     }
 
+    /// Create an OpenSSL variant from a DER encoded certificate and private key.
+    ///
+    /// Use this to keep the same fingerprint across restarts. The key is either PKCS#8
+    /// or the traditional format for its type. Fails with [`DtlsError::KeyMismatch`] if
+    /// the key doesn't belong to the certificate.
+    ///
+    /// ```no_run
+    /// # use str0m::change::DtlsCert;
+    /// # use str0m::RtcConfig;
+    /// let cert = std::fs::read("cert.der").unwrap();
+    /// let key = std::fs::read("key.der").unwrap();
+    /// let dtls_cert = DtlsCert::from_openssl_der(&cert, &key).unwrap();
+    ///
+    /// // The fingerprint for SDP is known before any handshake.
+    /// println!("a=fingerprint:{}", dtls_cert.fingerprint());
+    ///
+    /// let rtc = RtcConfig::new().set_dtls_cert(dtls_cert).build();
+    /// ```
+    #[cfg(feature = "openssl")]
+    pub fn from_openssl_der(cert: &[u8], key: &[u8]) -> Result<Self, DtlsError> {
+        let cert = super::ossl::OsslDtlsCert::from_der(cert, key)?;
+        Ok(DtlsCert(DtlsCertInner::OpenSsl(cert)))
+    }
+
+    /// Create an OpenSSL variant from an existing certificate and private key.
+    ///
+    /// The key can be a handle, i.e. one loaded from an OpenSSL engine or provider
+    /// that keeps the private material in a hardware module. Fails with
+    /// [`DtlsError::KeyMismatch`] if the key doesn't belong to the certificate.
+    #[cfg(feature = "openssl")]
+    pub fn from_openssl(
+        x509: openssl::x509::X509,
+        pkey: openssl::pkey::PKey<openssl::pkey::Private>,
+    ) -> Result<Self, DtlsError> {
+        let cert = super::ossl::OsslDtlsCert::from_parts(x509, pkey)?;
+        Ok(DtlsCert(DtlsCertInner::OpenSsl(cert)))
+    }
+
     /// Creates a fingerprint for this certificate.
     ///
     /// Fingerprints are used to verify a remote peer's certificate.
@@ -95,6 +140,20 @@ pub trait DtlsInner: Sized {
     /// If set_active, returns what was set.
     fn is_active(&self) -> Option<bool>;
 
+    /// Max size of outgoing datagrams. Handshake messages are fragmented to fit.
+    ///
+    /// This must be set before starting to handshake.
+    fn set_mtu(&mut self, mtu: usize) -> Result<(), CryptoError>;
+
+    /// Whether to verify the remote certificate against the expected fingerprints.
+    fn set_fingerprint_verification(&mut self, enabled: bool);
+
+    /// The fingerprints, one of which the remote certificate must match.
+    ///
+    /// The check is done when the handshake completes, before any keying material
+    /// is exported.
+    fn set_remote_fingerprints(&mut self, fingerprints: Vec<Fingerprint>);
+
     /// Handles an incoming DTLS datagrams.
     fn handle_receive(&mut self, m: &[u8], o: &mut VecDeque<DtlsEvent>) -> Result<(), CryptoError>;
 
@@ -106,6 +165,12 @@ pub trait DtlsInner: Sized {
 
     /// Whether the DTLS connection is established.
     fn is_connected(&self) -> bool;
+
+    /// Name of the negotiated cipher, once connected.
+    fn cipher(&self) -> Option<String>;
+
+    /// Send a close_notify alert to the remote.
+    fn close(&mut self) -> Result<(), CryptoError>;
 }
 
 pub enum DtlsImpl {
@@ -138,6 +203,30 @@ impl DtlsImpl {
         }
     }
 
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<(), CryptoError> {
+        match self {
+            #[cfg(feature = "openssl")]
+            DtlsImpl::OpenSsl(i) => i.set_mtu(mtu),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn set_fingerprint_verification(&mut self, enabled: bool) {
+        match self {
+            #[cfg(feature = "openssl")]
+            DtlsImpl::OpenSsl(i) => i.set_fingerprint_verification(enabled),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn set_remote_fingerprints(&mut self, fingerprints: Vec<Fingerprint>) {
+        match self {
+            #[cfg(feature = "openssl")]
+            DtlsImpl::OpenSsl(i) => i.set_remote_fingerprints(fingerprints),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn handle_receive(
         &mut self,
         m: &[u8],
@@ -173,4 +262,20 @@ impl DtlsImpl {
             _ => unreachable!(),
         }
     }
+
+    pub fn cipher(&self) -> Option<String> {
+        match self {
+            #[cfg(feature = "openssl")]
+            DtlsImpl::OpenSsl(i) => i.cipher(),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn close(&mut self) -> Result<(), CryptoError> {
+        match self {
+            #[cfg(feature = "openssl")]
+            DtlsImpl::OpenSsl(i) => i.close(),
+            _ => unreachable!(),
+        }
+    }
 }
@@ -15,6 +15,49 @@ pub struct Fingerprint {
     pub bytes: Vec<u8>,
 }
 
+#[cfg(feature = "openssl")]
+impl Fingerprint {
+    /// The hash function, if it's one of the registered names.
+    pub(crate) fn hash(&self) -> Option<HashFunc> {
+        HashFunc::from_name(&self.hash_func)
+    }
+}
+
+/// Hash functions for certificate fingerprints.
+///
+/// These are the names in the IANA "Hash Function Textual Names" registry,
+/// as referenced by RFC 8122.
+#[cfg(feature = "openssl")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HashFunc {
+    Md2,
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+#[cfg(feature = "openssl")]
+impl HashFunc {
+    /// Parse the name, which is case insensitive in SDP.
+    pub fn from_name(name: &str) -> Option<Self> {
+        use HashFunc::*;
+        let v = match name.to_ascii_lowercase().as_str() {
+            "md2" => Md2,
+            "md5" => Md5,
+            "sha-1" => Sha1,
+            "sha-224" => Sha224,
+            "sha-256" => Sha256,
+            "sha-384" => Sha384,
+            "sha-512" => Sha512,
+            _ => return None,
+        };
+        Some(v)
+    }
+}
+
 // DO NOT CHANGE!
 // This format is exactly what's needed in n SDP.
 impl fmt::Display for Fingerprint {
@@ -68,4 +111,19 @@ mod test {
             "foo 00:01:02:03:04:05:06:07:08:09:0A:0B:0C:0D:0E:0F:10:11"
         );
     }
+
+    #[test]
+    #[cfg(feature = "openssl")]
+    fn fingerprint_hash_func() {
+        let f = |s: &str| s.parse::<Fingerprint>().unwrap().hash();
+
+        assert_eq!(f("sha-256 00:01"), Some(HashFunc::Sha256));
+        assert_eq!(f("SHA-256 00:01"), Some(HashFunc::Sha256));
+        assert_eq!(f("sha-1 00:01"), Some(HashFunc::Sha1));
+        assert_eq!(f("sha-224 00:01"), Some(HashFunc::Sha224));
+        assert_eq!(f("sha-384 00:01"), Some(HashFunc::Sha384));
+        assert_eq!(f("sha-512 00:01"), Some(HashFunc::Sha512));
+        assert_eq!(f("md5 00:01"), Some(HashFunc::Md5));
+        assert_eq!(f("sha256 00:01"), None);
+    }
 }
@@ -1,12 +1,60 @@
 use std::ops::Deref;
 
+use super::SrtpProfile;
+
 /// Keying material used as master key for SRTP.
 pub struct KeyingMaterial(Vec<u8>);
 
 impl KeyingMaterial {
+    /// Wrap the master key followed by the master salt, one set per direction.
+    ///
+    /// This is the layout exported from DTLS, see
+    /// [RFC 5764](https://www.rfc-editor.org/rfc/rfc5764#section-4.2).
     pub fn new(m: Vec<u8>) -> Self {
         KeyingMaterial(m)
     }
+
+    /// Split the material exported with the "EXTRACTOR-dtls_srtp" label into keys for
+    /// sending and receiving.
+    ///
+    /// The exported material is laid out as:
+    ///
+    /// ```text
+    /// client_write_SRTP_master_key[master_key_len]
+    /// server_write_SRTP_master_key[master_key_len]
+    /// client_write_SRTP_master_salt[master_salt_len]
+    /// server_write_SRTP_master_salt[master_salt_len]
+    /// ```
+    ///
+    /// `is_client` is the DTLS role (the active side is the client). Returns `(tx, rx)`.
+    ///
+    /// # Panics
+    ///
+    /// If the length of the material doesn't match the profile.
+    pub fn derive(&self, profile: SrtpProfile, is_client: bool) -> (SrtpKeys, SrtpKeys) {
+        let key_len = profile.master_key_len();
+        let salt_len = profile.master_salt_len();
+
+        assert_eq!(
+            self.0.len(),
+            profile.keying_material_len(),
+            "KeyingMaterial length for {}",
+            profile
+        );
+
+        let (client_key, rest) = self.0.split_at(key_len);
+        let (server_key, rest) = rest.split_at(key_len);
+        let (client_salt, server_salt) = rest.split_at(salt_len);
+
+        let client = SrtpKeys::new(client_key.to_vec(), client_salt.to_vec());
+        let server = SrtpKeys::new(server_key.to_vec(), server_salt.to_vec());
+
+        if is_client {
+            (client, server)
+        } else {
+            (server, client)
+        }
+    }
 }
 
 impl Deref for KeyingMaterial {
@@ -22,3 +70,78 @@ impl std::fmt::Debug for KeyingMaterial {
         write!(f, "KeyingMaterial")
     }
 }
+
+/// SRTP master key and salt for one direction.
+#[derive(Clone)]
+pub struct SrtpKeys {
+    master: Vec<u8>,
+    salt: Vec<u8>,
+}
+
+impl SrtpKeys {
+    /// Create keys from a master key and salt.
+    pub fn new(master: Vec<u8>, salt: Vec<u8>) -> Self {
+        SrtpKeys { master, salt }
+    }
+
+    /// The master key.
+    pub fn master(&self) -> &[u8] {
+        &self.master
+    }
+
+    /// The master salt.
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+}
+
+impl std::fmt::Debug for SrtpKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SrtpKeys")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn derive_slicing_order() {
+        for profile in SrtpProfile::ALL {
+            let k = profile.master_key_len();
+            let s = profile.master_salt_len();
+
+            // Tag each byte with its part: 1 client key, 2 server key, 3 client salt,
+            // 4 server salt. The low nibble is the position within the part.
+            let mut v = vec![];
+            v.extend((0..k).map(|i| 0x10 | (i as u8 & 0xf)));
+            v.extend((0..k).map(|i| 0x20 | (i as u8 & 0xf)));
+            v.extend((0..s).map(|i| 0x30 | (i as u8 & 0xf)));
+            v.extend((0..s).map(|i| 0x40 | (i as u8 & 0xf)));
+            let mat = KeyingMaterial::new(v);
+
+            let part = |b: &[u8]| b.iter().map(|x| x >> 4).collect::<Vec<_>>();
+            let pos = |b: &[u8]| b.iter().map(|x| x & 0xf).collect::<Vec<_>>();
+
+            let (tx, rx) = mat.derive(*profile, true);
+            assert_eq!(part(tx.master()), vec![1; k], "{}", profile);
+            assert_eq!(part(rx.master()), vec![2; k], "{}", profile);
+            assert_eq!(part(tx.salt()), vec![3; s], "{}", profile);
+            assert_eq!(part(rx.salt()), vec![4; s], "{}", profile);
+            assert_eq!(pos(tx.salt()), (0..s as u8).collect::<Vec<_>>());
+
+            let (tx, rx) = mat.derive(*profile, false);
+            assert_eq!(part(tx.master()), vec![2; k], "{}", profile);
+            assert_eq!(part(rx.master()), vec![1; k], "{}", profile);
+            assert_eq!(part(tx.salt()), vec![4; s], "{}", profile);
+            assert_eq!(part(rx.salt()), vec![3; s], "{}", profile);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn derive_wrong_length() {
+        let mat = KeyingMaterial::new(vec![0; 59]);
+        mat.derive(SrtpProfile::Aes128CmSha1_80, true);
+    }
+}
@@ -11,13 +11,16 @@ pub use dtls::{DtlsCert, DtlsEvent, DtlsImpl};
 
 mod finger;
 pub use finger::Fingerprint;
+#[cfg(feature = "openssl")]
+pub(crate) use finger::HashFunc;
 
 mod keying;
-pub use keying::KeyingMaterial;
+pub use keying::{KeyingMaterial, SrtpKeys};
 
 mod srtp;
-pub use srtp::{aead_aes_128_gcm, aes_128_cm_sha1_80, new_aead_aes_128_gcm};
-pub use srtp::{new_aes_128_cm_sha1_80, srtp_aes_128_ecb_round, SrtpProfile};
+pub use srtp::{aead_aes_128_gcm, aead_aes_256_gcm, aes_128_cm_sha1_80};
+pub use srtp::{new_aead_aes_128_gcm, new_aead_aes_256_gcm, new_aes_128_cm_sha1_80};
+pub use srtp::{srtp_aes_128_ecb_round, srtp_aes_256_ecb_round, SrtpProfile};
 
 /// SHA1 HMAC as used for STUN and older SRTP.
 pub fn sha1_hmac(key: &[u8], payloads: &[&[u8]]) -> [u8; 20] {
@@ -34,6 +37,22 @@ pub fn sha1_hmac(key: &[u8], payloads: &[&[u8]]) -> [u8; 20] {
     hmac.finalize().into_bytes().into()
 }
 
+/// Fill the buffer with cryptographically secure random bytes.
+///
+/// Without the openssl feature, this falls back to the non-cryptographic generator.
+pub fn random_bytes(buf: &mut [u8]) {
+    #[cfg(feature = "openssl")]
+    {
+        openssl::rand::rand_bytes(buf).expect("openssl to produce random bytes");
+    }
+    #[cfg(not(feature = "openssl"))]
+    {
+        for b in buf {
+            *b = crate::util::NonCryptographicRng::u8();
+        }
+    }
+}
+
 /// Errors that can arise in DTLS.
 #[derive(Debug, Error)]
 pub enum CryptoError {
@@ -42,6 +61,14 @@ pub enum CryptoError {
     #[cfg(feature = "openssl")]
     OpenSsl(#[from] openssl::error::ErrorStack),
 
+    /// The private key doesn't belong to the certificate.
+    #[error("private key does not match certificate")]
+    KeyMismatch,
+
+    /// The remote certificate doesn't match any expected fingerprint.
+    #[error("remote certificate fingerprint mismatch")]
+    FingerprintMismatch,
+
     /// Other IO errors.
     #[error("{0}")]
     Io(#[from] io::Error),
@@ -28,6 +28,23 @@ impl OsslDtlsCert {
         Self::self_signed().expect("create dtls cert")
     }
 
+    /// Use an existing certificate and private key.
+    ///
+    /// Fails if the key doesn't belong to the certificate.
+    pub fn from_parts(x509: X509, pkey: PKey<Private>) -> Result<Self, CryptoError> {
+        if !x509.public_key()?.public_eq(&pkey) {
+            return Err(CryptoError::KeyMismatch);
+        }
+        Ok(OsslDtlsCert { pkey, x509 })
+    }
+
+    /// Parse a DER encoded certificate and (PKCS#8 or traditional) private key.
+    pub fn from_der(cert: &[u8], key: &[u8]) -> Result<Self, CryptoError> {
+        let x509 = X509::from_der(cert)?;
+        let pkey = PKey::private_key_from_der(key)?;
+        Self::from_parts(x509, pkey)
+    }
+
     // The libWebRTC code we try to match is at:
     // https://webrtc.googlesource.com/src/+/1568f1b1330f94494197696fe235094e6293b258/rtc_base/openssl_certificate.cc#58
     fn self_signed() -> Result<Self, CryptoError> {
@@ -6,7 +6,7 @@ use openssl::nid::Nid;
 use openssl::ssl::{Ssl, SslContext, SslContextBuilder, SslMethod, SslOptions, SslVerifyMode};
 
 use crate::crypto::dtls::DtlsInner;
-use crate::crypto::{DtlsEvent, SrtpProfile};
+use crate::crypto::{DtlsEvent, Fingerprint, SrtpProfile};
 use crate::io::{DATAGRAM_MTU, DATAGRAM_MTU_WARN};
 
 use super::cert::OsslDtlsCert;
@@ -52,6 +52,18 @@ impl DtlsInner for OsslDtlsImpl {
         self.tls.is_active()
     }
 
+    fn set_mtu(&mut self, mtu: usize) -> Result<(), CryptoError> {
+        self.tls.set_mtu(mtu)
+    }
+
+    fn set_fingerprint_verification(&mut self, enabled: bool) {
+        self.tls.set_verify_fingerprint(enabled);
+    }
+
+    fn set_remote_fingerprints(&mut self, fingerprints: Vec<Fingerprint>) {
+        self.tls.set_remote_fingerprints(fingerprints);
+    }
+
     fn handle_receive(&mut self, m: &[u8], o: &mut VecDeque<DtlsEvent>) -> Result<(), CryptoError> {
         self.tls.inner_mut().set_incoming(m);
 
@@ -68,6 +80,15 @@ impl DtlsInner for OsslDtlsImpl {
             }
             Err(e) => return Err(e.into()),
         };
+
+        if n == 0 && self.tls.is_remote_closed() {
+            debug!("Received close_notify");
+            // Answer with our own close_notify.
+            self.tls.shutdown()?;
+            o.push_back(DtlsEvent::CloseNotify);
+            return Ok(());
+        }
+
         buf.truncate(n);
 
         o.push_back(DtlsEvent::Data(buf));
@@ -94,6 +115,14 @@ impl DtlsInner for OsslDtlsImpl {
         self.tls.is_connected()
     }
 
+    fn cipher(&self) -> Option<String> {
+        self.tls.cipher()
+    }
+
+    fn close(&mut self) -> Result<(), CryptoError> {
+        Ok(self.tls.shutdown()?)
+    }
+
     fn handle_handshake(&mut self, output: &mut VecDeque<DtlsEvent>) -> Result<bool, CryptoError> {
         if self.tls.is_handshaken() {
             // Nice. Nothing to do.
@@ -3,7 +3,7 @@ use openssl::cipher_ctx::CipherCtx;
 use openssl::symm::{Cipher, Crypter, Mode};
 
 use crate::crypto::srtp::SrtpCryptoImpl;
-use crate::crypto::srtp::{aead_aes_128_gcm, aes_128_cm_sha1_80};
+use crate::crypto::srtp::{aead_aes_128_gcm, aead_aes_256_gcm, aes_128_cm_sha1_80};
 use crate::crypto::CryptoError;
 
 pub struct OsslSrtpCryptoImpl;
@@ -11,19 +11,27 @@ pub struct OsslSrtpCryptoImpl;
 impl SrtpCryptoImpl for OsslSrtpCryptoImpl {
     type Aes128CmSha1_80 = OsslAes128CmSha1_80;
     type AeadAes128Gcm = OsslAeadAes128Gcm;
+    type AeadAes256Gcm = OsslAeadAes256Gcm;
 
     fn srtp_aes_128_ecb_round(key: &[u8], input: &[u8], output: &mut [u8]) {
-        let mut aes =
-            Crypter::new(Cipher::aes_128_ecb(), Mode::Encrypt, key, None).expect("AES deriver");
-
-        // Run AES
-        let count = aes.update(input, output).expect("AES update");
-        let rest = aes.finalize(&mut output[count..]).expect("AES finalize");
+        aes_ecb_round(Cipher::aes_128_ecb(), key, input, output)
+    }
 
-        assert_eq!(count + rest, 16 + 16); // input len + block size
+    fn srtp_aes_256_ecb_round(key: &[u8], input: &[u8], output: &mut [u8]) {
+        aes_ecb_round(Cipher::aes_256_ecb(), key, input, output)
     }
 }
 
+fn aes_ecb_round(cipher: Cipher, key: &[u8], input: &[u8], output: &mut [u8]) {
+    let mut aes = Crypter::new(cipher, Mode::Encrypt, key, None).expect("AES deriver");
+
+    // Run AES
+    let count = aes.update(input, output).expect("AES update");
+    let rest = aes.finalize(&mut output[count..]).expect("AES finalize");
+
+    assert_eq!(count + rest, 16 + 16); // input len + block size
+}
+
 pub struct OsslAes128CmSha1_80(CipherCtx);
 
 impl aes_128_cm_sha1_80::CipherCtx for OsslAes128CmSha1_80 {
@@ -68,6 +76,17 @@ impl aes_128_cm_sha1_80::CipherCtx for OsslAes128CmSha1_80 {
         self.0.cipher_final(&mut output[count..])?;
         Ok(())
     }
+
+    fn encrypt_in_place(
+        &mut self,
+        iv: &aes_128_cm_sha1_80::RtpIv,
+        buf: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        self.0.encrypt_init(None, None, Some(iv))?;
+        let count = self.0.cipher_update_inplace(buf, buf.len())?;
+        self.0.cipher_final(&mut buf[count..])?;
+        Ok(())
+    }
 }
 
 pub struct OsslAeadAes128Gcm(CipherCtx);
@@ -78,20 +97,7 @@ impl aead_aes_128_gcm::CipherCtx for OsslAeadAes128Gcm {
         Self: Sized,
     {
         let t = cipher::Cipher::aes_128_gcm();
-        let mut ctx = CipherCtx::new().expect("a reusable cipher context");
-
-        if encrypt {
-            ctx.encrypt_init(Some(t), Some(&key), None)
-                .expect("enc init");
-            ctx.set_iv_length(aead_aes_128_gcm::IV_LEN)
-                .expect("IV length");
-            ctx.set_padding(false);
-        } else {
-            ctx.decrypt_init(Some(t), Some(&key), None)
-                .expect("dec init");
-        }
-
-        OsslAeadAes128Gcm(ctx)
+        OsslAeadAes128Gcm(new_gcm_ctx(t, &key, encrypt))
     }
 
     fn encrypt(
@@ -101,58 +107,170 @@ impl aead_aes_128_gcm::CipherCtx for OsslAeadAes128Gcm {
         input: &[u8],
         output: &mut [u8],
     ) -> Result<(), CryptoError> {
-        assert!(
-            aad.len() >= 12,
-            "Associated data length MUST be at least 12 octets"
-        );
+        gcm_encrypt(&mut self.0, iv, aad, input, output)
+    }
 
-        // Set the IV
-        self.0.encrypt_init(None, None, Some(iv))?;
+    fn decrypt(
+        &mut self,
+        iv: &[u8; aead_aes_128_gcm::IV_LEN],
+        aads: &[&[u8]],
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, CryptoError> {
+        gcm_decrypt(&mut self.0, iv, aads, input, output)
+    }
 
-        // Add the additional authenticated data, omitting the output argument informs
-        // OpenSSL that we are providing AAD.
-        let aad_c = self.0.cipher_update(aad, None)?;
-        // TODO: This should maybe be an error
-        assert!(aad_c == aad.len());
+    fn encrypt_in_place(
+        &mut self,
+        iv: &[u8; aead_aes_128_gcm::IV_LEN],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        gcm_encrypt_in_place(&mut self.0, iv, aad, buf)
+    }
+}
 
-        let count = self.0.cipher_update(input, Some(output))?;
-        let final_count = self.0.cipher_final(&mut output[count..])?;
+pub struct OsslAeadAes256Gcm(CipherCtx);
 
-        // Get the authentication tag and append it to the output
-        let tag_offset = count + final_count;
-        self.0
-            .tag(&mut output[tag_offset..tag_offset + aead_aes_128_gcm::TAG_LEN])?;
+impl aead_aes_256_gcm::CipherCtx for OsslAeadAes256Gcm {
+    fn new(key: aead_aes_256_gcm::AeadKey, encrypt: bool) -> Self
+    where
+        Self: Sized,
+    {
+        let t = cipher::Cipher::aes_256_gcm();
+        OsslAeadAes256Gcm(new_gcm_ctx(t, &key, encrypt))
+    }
 
-        Ok(())
+    fn encrypt(
+        &mut self,
+        iv: &[u8; aead_aes_256_gcm::IV_LEN],
+        aad: &[u8],
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        gcm_encrypt(&mut self.0, iv, aad, input, output)
     }
 
     fn decrypt(
         &mut self,
-        iv: &[u8; aead_aes_128_gcm::IV_LEN],
+        iv: &[u8; aead_aes_256_gcm::IV_LEN],
         aads: &[&[u8]],
         input: &[u8],
         output: &mut [u8],
     ) -> Result<usize, CryptoError> {
-        // This needs to be converted to an error maybe
-        assert!(input.len() >= aead_aes_128_gcm::TAG_LEN);
+        gcm_decrypt(&mut self.0, iv, aads, input, output)
+    }
 
-        let (cipher_text, tag) = input.split_at(input.len() - aead_aes_128_gcm::TAG_LEN);
+    fn encrypt_in_place(
+        &mut self,
+        iv: &[u8; aead_aes_256_gcm::IV_LEN],
+        aad: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        gcm_encrypt_in_place(&mut self.0, iv, aad, buf)
+    }
+}
 
-        self.0.decrypt_init(None, None, Some(iv))?;
+fn new_gcm_ctx(t: &cipher::CipherRef, key: &[u8], encrypt: bool) -> CipherCtx {
+    let mut ctx = CipherCtx::new().expect("a reusable cipher context");
 
-        // Add the additional authenticated data, omitting the output argument informs
-        // OpenSSL that we are providing AAD.
-        // With this the authentication tag will be verified.
-        for aad in aads {
-            self.0.cipher_update(aad, None)?;
-        }
+    if encrypt {
+        ctx.encrypt_init(Some(t), Some(key), None)
+            .expect("enc init");
+        ctx.set_iv_length(aead_aes_128_gcm::IV_LEN)
+            .expect("IV length");
+        ctx.set_padding(false);
+    } else {
+        ctx.decrypt_init(Some(t), Some(key), None)
+            .expect("dec init");
+    }
 
-        self.0.set_tag(tag)?;
+    ctx
+}
 
-        let count = self.0.cipher_update(cipher_text, Some(output))?;
+fn gcm_encrypt(
+    ctx: &mut CipherCtx,
+    iv: &[u8; aead_aes_128_gcm::IV_LEN],
+    aad: &[u8],
+    input: &[u8],
+    output: &mut [u8],
+) -> Result<(), CryptoError> {
+    assert!(
+        aad.len() >= 12,
+        "Associated data length MUST be at least 12 octets"
+    );
 
-        let final_count = self.0.cipher_final(&mut output[count..])?;
+    // Set the IV
+    ctx.encrypt_init(None, None, Some(iv))?;
 
-        Ok(count + final_count)
+    // Add the additional authenticated data, omitting the output argument informs
+    // OpenSSL that we are providing AAD.
+    let aad_c = ctx.cipher_update(aad, None)?;
+    // TODO: This should maybe be an error
+    assert!(aad_c == aad.len());
+
+    let count = ctx.cipher_update(input, Some(output))?;
+    let final_count = ctx.cipher_final(&mut output[count..])?;
+
+    // Get the authentication tag and append it to the output
+    let tag_offset = count + final_count;
+    ctx.tag(&mut output[tag_offset..tag_offset + aead_aes_128_gcm::TAG_LEN])?;
+
+    Ok(())
+}
+
+fn gcm_decrypt(
+    ctx: &mut CipherCtx,
+    iv: &[u8; aead_aes_128_gcm::IV_LEN],
+    aads: &[&[u8]],
+    input: &[u8],
+    output: &mut [u8],
+) -> Result<usize, CryptoError> {
+    // This needs to be converted to an error maybe
+    assert!(input.len() >= aead_aes_128_gcm::TAG_LEN);
+
+    let (cipher_text, tag) = input.split_at(input.len() - aead_aes_128_gcm::TAG_LEN);
+
+    ctx.decrypt_init(None, None, Some(iv))?;
+
+    // Add the additional authenticated data, omitting the output argument informs
+    // OpenSSL that we are providing AAD.
+    // With this the authentication tag will be verified.
+    for aad in aads {
+        ctx.cipher_update(aad, None)?;
     }
+
+    ctx.set_tag(tag)?;
+
+    let count = ctx.cipher_update(cipher_text, Some(output))?;
+
+    let final_count = ctx.cipher_final(&mut output[count..])?;
+
+    Ok(count + final_count)
+}
+
+fn gcm_encrypt_in_place(
+    ctx: &mut CipherCtx,
+    iv: &[u8; aead_aes_128_gcm::IV_LEN],
+    aad: &[u8],
+    buf: &mut [u8],
+) -> Result<(), CryptoError> {
+    assert!(
+        aad.len() >= 12,
+        "Associated data length MUST be at least 12 octets"
+    );
+
+    let len = buf.len() - aead_aes_128_gcm::TAG_LEN;
+
+    ctx.encrypt_init(None, None, Some(iv))?;
+    ctx.cipher_update(aad, None)?;
+
+    // GCM is a stream cipher, no room is needed beyond the input.
+    let count = ctx.cipher_update_inplace(&mut buf[..len], len)?;
+    let final_count = ctx.cipher_final(&mut buf[count..len])?;
+
+    let tag_offset = count + final_count;
+    ctx.tag(&mut buf[tag_offset..tag_offset + aead_aes_128_gcm::TAG_LEN])?;
+
+    Ok(())
 }
@@ -3,10 +3,10 @@ use std::{io, mem};
 
 use openssl::hash::MessageDigest;
 use openssl::srtp::SrtpProfileId;
-use openssl::ssl::{HandshakeError, MidHandshakeSslStream, Ssl, SslStream};
+use openssl::ssl::{HandshakeError, MidHandshakeSslStream, ShutdownState, Ssl, SslStream};
 
 use crate::change::Fingerprint;
-use crate::crypto::{KeyingMaterial, SrtpProfile};
+use crate::crypto::{HashFunc, KeyingMaterial, SrtpProfile};
 
 use super::CryptoError;
 
@@ -17,6 +17,9 @@ pub struct TlsStream<S> {
     state: State<S>,
     keying_mat: Option<(KeyingMaterial, SrtpProfile, Fingerprint)>,
     exported: bool,
+    verify_fingerprint: bool,
+    remote_fingerprints: Vec<Fingerprint>,
+    fingerprint_failed: bool,
 }
 
 pub enum State<S> {
@@ -40,6 +43,9 @@ where
             state: State::Init(ssl, stream),
             keying_mat: None,
             exported: false,
+            verify_fingerprint: true,
+            remote_fingerprints: vec![],
+            fingerprint_failed: false,
         }
     }
 
@@ -48,7 +54,7 @@ where
     }
 
     pub fn is_connected(&self) -> bool {
-        matches!(self.state, State::Established(_))
+        self.is_handshaken()
     }
 
     pub fn set_active(&mut self, active: bool) {
@@ -59,12 +65,32 @@ where
         self.active = Some(active);
     }
 
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<(), CryptoError> {
+        let State::Init(ssl, _) = &mut self.state else {
+            return Err(
+                io::Error::new(io::ErrorKind::Other, "MTU must be set before handshake").into(),
+            );
+        };
+        ssl.set_mtu(mtu as u32)?;
+        Ok(())
+    }
+
+    pub fn set_verify_fingerprint(&mut self, enabled: bool) {
+        self.verify_fingerprint = enabled;
+    }
+
+    pub fn set_remote_fingerprints(&mut self, fingerprints: Vec<Fingerprint>) {
+        self.remote_fingerprints = fingerprints;
+    }
+
     pub fn complete_handshake_until_block(&mut self) -> Result<bool, CryptoError> {
         if let Err(e) = self.handshaken() {
-            if e.kind() == io::ErrorKind::WouldBlock {
-                Ok(false)
-            } else {
-                Err(e.into())
+            if self.fingerprint_failed {
+                return Err(CryptoError::FingerprintMismatch);
+            }
+            match e.kind() {
+                io::ErrorKind::WouldBlock => Ok(false),
+                _ => Err(e.into()),
             }
         } else {
             Ok(true)
@@ -72,23 +98,69 @@ where
     }
 
     pub fn is_handshaken(&self) -> bool {
-        matches!(self.state, State::Established(_))
+        // A handshake with an unverified peer doesn't count.
+        self.exported && matches!(self.state, State::Established(_))
     }
 
     pub fn handshaken(&mut self) -> Result<&mut SslStream<S>, io::Error> {
         let active = self.is_active().expect("set_active must be called");
         let v = self.state.handshaken(active)?;
 
-        // first time we complete the handshake, we extract the keying material for SRTP.
+        // first time we complete the handshake, we verify the remote certificate
+        // and then extract the keying material for SRTP.
         if !self.exported {
-            let keying_mat = export_srtp_keying_material(v)?;
+            let fp = if self.verify_fingerprint {
+                let Some(fp) = verify_remote_fingerprint(v, &self.remote_fingerprints)? else {
+                    self.fingerprint_failed = true;
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "Remote fingerprint mismatch",
+                    ));
+                };
+                fp
+            } else {
+                remote_fingerprint(v, MessageDigest::sha256(), "sha-256")?
+            };
+            let (mat, profile) = export_srtp_keying_material(v)?;
             self.exported = true;
-            self.keying_mat = Some(keying_mat);
+            self.keying_mat = Some((mat, profile, fp));
         }
 
         Ok(v)
     }
 
+    /// Name of the negotiated cipher, once handshaken.
+    pub fn cipher(&self) -> Option<String> {
+        let State::Established(v) = &self.state else {
+            return None;
+        };
+        v.ssl().current_cipher().map(|c| c.name().to_string())
+    }
+
+    /// Whether the remote sent a close_notify alert.
+    pub fn is_remote_closed(&mut self) -> bool {
+        let State::Established(v) = &mut self.state else {
+            return false;
+        };
+        v.get_shutdown().contains(ShutdownState::RECEIVED)
+    }
+
+    /// Send a close_notify alert, unless we already did.
+    pub fn shutdown(&mut self) -> Result<(), io::Error> {
+        let State::Established(v) = &mut self.state else {
+            return Ok(());
+        };
+        if v.get_shutdown().contains(ShutdownState::SENT) {
+            return Ok(());
+        }
+        match v.shutdown() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e
+                .into_io_error()
+                .unwrap_or_else(|e| io::Error::new(io::ErrorKind::Other, e))),
+        }
+    }
+
     pub fn take_srtp_keying_material(
         &mut self,
     ) -> Option<(KeyingMaterial, SrtpProfile, Fingerprint)> {
@@ -158,21 +230,58 @@ where
     }
 }
 
-fn export_srtp_keying_material<S>(
-    stream: &mut SslStream<S>,
-) -> Result<(KeyingMaterial, SrtpProfile, Fingerprint), io::Error> {
-    let ssl = stream.ssl();
+/// Check the remote certificate against the fingerprints from SDP.
+///
+/// Any match is enough, and the matching fingerprint is returned. `None` if nothing matches.
+fn verify_remote_fingerprint<S>(
+    stream: &SslStream<S>,
+    expected: &[Fingerprint],
+) -> Result<Option<Fingerprint>, io::Error> {
+    for fp in expected {
+        let md = match fp.hash() {
+            Some(HashFunc::Sha1) => MessageDigest::sha1(),
+            Some(HashFunc::Sha224) => MessageDigest::sha224(),
+            Some(HashFunc::Sha256) => MessageDigest::sha256(),
+            Some(HashFunc::Sha384) => MessageDigest::sha384(),
+            Some(HashFunc::Sha512) => MessageDigest::sha512(),
+            // RFC 8122 forbids MD2 and MD5.
+            Some(HashFunc::Md2) | Some(HashFunc::Md5) | None => {
+                debug!("Ignore fingerprint with hash function: {}", fp.hash_func);
+                continue;
+            }
+        };
+
+        let actual = remote_fingerprint(stream, md, &fp.hash_func)?;
+        if actual.bytes == fp.bytes {
+            return Ok(Some(actual));
+        }
+    }
+
+    warn!("Remote certificate fingerprint mismatch");
+    Ok(None)
+}
 
-    // remote peer certificate fingerprint
-    let x509 = ssl
+fn remote_fingerprint<S>(
+    stream: &SslStream<S>,
+    md: MessageDigest,
+    hash_func: &str,
+) -> Result<Fingerprint, io::Error> {
+    let x509 = stream
+        .ssl()
         .peer_certificate()
         .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "No remote X509 cert"))?;
-    let digest: &[u8] = &x509.digest(MessageDigest::sha256())?;
+    let digest: &[u8] = &x509.digest(md)?;
 
-    let fp = Fingerprint {
-        hash_func: "sha-256".into(),
+    Ok(Fingerprint {
+        hash_func: hash_func.into(),
         bytes: digest.to_vec(),
-    };
+    })
+}
+
+fn export_srtp_keying_material<S>(
+    stream: &mut SslStream<S>,
+) -> Result<(KeyingMaterial, SrtpProfile), io::Error> {
+    let ssl = stream.ssl();
 
     let srtp_profile_id = ssl
         .selected_srtp_profile()
@@ -186,7 +295,7 @@ fn export_srtp_keying_material<S>(
 
     let mat = KeyingMaterial::new(buf);
 
-    Ok((mat, srtp_profile, fp))
+    Ok((mat, srtp_profile))
 }
 
 impl<S> io::Read for TlsStream<S>
@@ -215,13 +324,14 @@ impl TryFrom<SrtpProfileId> for SrtpProfile {
     type Error = io::Error;
 
     fn try_from(value: SrtpProfileId) -> Result<Self, Self::Error> {
-        match value {
-            SrtpProfileId::SRTP_AES128_CM_SHA1_80 => Ok(SrtpProfile::Aes128CmSha1_80),
-            SrtpProfileId::SRTP_AEAD_AES_128_GCM => Ok(SrtpProfile::AeadAes128Gcm),
-            x => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Unsupported SRTP profile {:x}", x.as_raw()),
-            )),
-        }
+        u16::try_from(value.as_raw())
+            .ok()
+            .and_then(SrtpProfile::from_id)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Unsupported SRTP profile {:x}", value.as_raw()),
+                )
+            })
     }
 }
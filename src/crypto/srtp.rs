@@ -1,33 +1,96 @@
 use std::fmt;
 
 use self::aead_aes_128_gcm::AeadKey;
+use self::aead_aes_256_gcm::AeadKey as AeadKey256;
 use self::aes_128_cm_sha1_80::AesKey;
 
+/// SRTP protection profile.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SrtpProfile {
+    /// No protection, only for tests.
     #[cfg(feature = "_internal_test_exports")]
     PassThrough,
+    /// SRTP_AES128_CM_SHA1_80, RFC 3711.
     Aes128CmSha1_80,
+    /// SRTP_AEAD_AES_128_GCM, RFC 7714.
     AeadAes128Gcm,
+    /// SRTP_AEAD_AES_256_GCM, RFC 7714.
+    AeadAes256Gcm,
+    /// No encryption or authentication, for reading captures while debugging.
+    ///
+    /// Packets keep the SRTP/SRTCP layout (SRTCP index, a zeroed 80 bit tag) and go
+    /// through the same ROC and replay handling as the real profiles. This can never
+    /// be negotiated with a peer, it is only set with
+    /// [`DirectApi::rekey_srtp()`][crate::change::DirectApi::rekey_srtp].
+    /// The keying material is empty.
+    ///
+    /// **Never use this outside of debugging.**
+    NullDebug,
 }
 
 #[allow(dead_code)]
 impl SrtpProfile {
     // All the profiles we support, ordered from most preferred to least.
-    pub(crate) const ALL: &'static [SrtpProfile] =
-        &[SrtpProfile::AeadAes128Gcm, SrtpProfile::Aes128CmSha1_80];
+    pub(crate) const ALL: &'static [SrtpProfile] = &[
+        SrtpProfile::AeadAes128Gcm,
+        SrtpProfile::AeadAes256Gcm,
+        SrtpProfile::Aes128CmSha1_80,
+    ];
 
     /// The length of keying material to extract from the DTLS session in bytes.
-    #[rustfmt::skip]
     pub(crate) fn keying_material_len(&self) -> usize {
+        // MASTER_KEY_LEN * 2 + MASTER_SALT * 2
+        self.master_key_len() * 2 + self.master_salt_len() * 2
+    }
+
+    /// Length of the SRTP master key in bytes.
+    #[rustfmt::skip]
+    pub fn master_key_len(&self) -> usize {
+        match self {
+            #[cfg(feature = "_internal_test_exports")]
+            SrtpProfile::PassThrough => 0,
+            SrtpProfile::Aes128CmSha1_80 => aes_128_cm_sha1_80::KEY_LEN,
+            SrtpProfile::AeadAes128Gcm   => aead_aes_128_gcm::KEY_LEN,
+            SrtpProfile::AeadAes256Gcm   => aead_aes_256_gcm::KEY_LEN,
+            SrtpProfile::NullDebug       => 0,
+        }
+    }
+
+    /// Length of the SRTP master salt in bytes.
+    #[rustfmt::skip]
+    pub fn master_salt_len(&self) -> usize {
         match self {
             #[cfg(feature = "_internal_test_exports")]
             SrtpProfile::PassThrough => 0,
-             // MASTER_KEY_LEN * 2 + MASTER_SALT * 2
-             // TODO: This is a duplication of info that is held in srtp.rs, because we
-             // don't want a dependency in that direction.
-            SrtpProfile::Aes128CmSha1_80 => 16 * 2 + 14 * 2,
-            SrtpProfile::AeadAes128Gcm   => 16 * 2 + 12 * 2,
+            SrtpProfile::Aes128CmSha1_80 => aes_128_cm_sha1_80::SALT_LEN,
+            SrtpProfile::AeadAes128Gcm   => aead_aes_128_gcm::SALT_LEN,
+            SrtpProfile::AeadAes256Gcm   => aead_aes_256_gcm::SALT_LEN,
+            SrtpProfile::NullDebug       => 0,
+        }
+    }
+
+    /// The profile for an SRTPProtectionProfile value in the use_srtp DTLS extension.
+    ///
+    /// See the [IANA registry](https://www.iana.org/assignments/srtp-protection/srtp-protection.xhtml).
+    pub fn from_id(id: u16) -> Option<Self> {
+        match id {
+            0x0001 => Some(SrtpProfile::Aes128CmSha1_80),
+            0x0007 => Some(SrtpProfile::AeadAes128Gcm),
+            0x0008 => Some(SrtpProfile::AeadAes256Gcm),
+            _ => None,
+        }
+    }
+
+    /// The SRTPProtectionProfile value used in the use_srtp DTLS extension.
+    pub fn id(&self) -> u16 {
+        match self {
+            #[cfg(feature = "_internal_test_exports")]
+            SrtpProfile::PassThrough => 0,
+            SrtpProfile::Aes128CmSha1_80 => 0x0001,
+            SrtpProfile::AeadAes128Gcm => 0x0007,
+            SrtpProfile::AeadAes256Gcm => 0x0008,
+            // Not a registered value, and never negotiated.
+            SrtpProfile::NullDebug => 0,
         }
     }
 }
@@ -72,6 +135,26 @@ pub fn new_aead_aes_128_gcm(key: AeadKey, encrypt: bool) -> Box<dyn aead_aes_128
     }
 }
 
+// TODO: Can we avoice dynamic dispatch in this signature? The parameters are:
+//       1. As few "touch points" beteen rtp/srtp.rs and here as possible.
+//       2. Clear contract towards the actual impl.
+//       3. Choice of impl passed all the way from RtcConfig.
+#[allow(unused)]
+pub fn new_aead_aes_256_gcm(
+    key: AeadKey256,
+    encrypt: bool,
+) -> Box<dyn aead_aes_256_gcm::CipherCtx> {
+    #[cfg(feature = "openssl")]
+    {
+        let ctx = super::ossl::OsslSrtpCryptoImpl::new_aead_aes_256_gcm(key, encrypt);
+        Box::new(ctx)
+    }
+    #[cfg(not(feature = "openssl"))]
+    {
+        panic!("No SRTP implementation. Enable openssl feature");
+    }
+}
+
 #[allow(unused)]
 
 pub fn srtp_aes_128_ecb_round(key: &[u8], input: &[u8], output: &mut [u8]) {
@@ -88,9 +171,22 @@ pub fn srtp_aes_128_ecb_round(key: &[u8], input: &[u8], output: &mut [u8]) {
     }
 }
 
+#[allow(unused)]
+pub fn srtp_aes_256_ecb_round(key: &[u8], input: &[u8], output: &mut [u8]) {
+    #[cfg(feature = "openssl")]
+    {
+        super::ossl::OsslSrtpCryptoImpl::srtp_aes_256_ecb_round(key, input, output)
+    }
+    #[cfg(not(feature = "openssl"))]
+    {
+        panic!("No SRTP implementation. Enable openssl feature");
+    }
+}
+
 pub trait SrtpCryptoImpl {
     type Aes128CmSha1_80: aes_128_cm_sha1_80::CipherCtx;
     type AeadAes128Gcm: aead_aes_128_gcm::CipherCtx;
+    type AeadAes256Gcm: aead_aes_256_gcm::CipherCtx;
 
     fn new_aes_128_cm_sha1_80(key: AesKey, encrypt: bool) -> Self::Aes128CmSha1_80 {
         <Self::Aes128CmSha1_80 as aes_128_cm_sha1_80::CipherCtx>::new(key, encrypt)
@@ -100,7 +196,13 @@ pub trait SrtpCryptoImpl {
         <Self::AeadAes128Gcm as aead_aes_128_gcm::CipherCtx>::new(key, encrypt)
     }
 
+    fn new_aead_aes_256_gcm(key: AeadKey256, encrypt: bool) -> Self::AeadAes256Gcm {
+        <Self::AeadAes256Gcm as aead_aes_256_gcm::CipherCtx>::new(key, encrypt)
+    }
+
     fn srtp_aes_128_ecb_round(key: &[u8], input: &[u8], output: &mut [u8]);
+
+    fn srtp_aes_256_ecb_round(key: &[u8], input: &[u8], output: &mut [u8]);
 }
 
 pub mod aes_128_cm_sha1_80 {
@@ -134,6 +236,9 @@ pub mod aes_128_cm_sha1_80 {
             input: &[u8],
             output: &mut [u8],
         ) -> Result<(), CryptoError>;
+
+        /// Encrypt `buf` in place.
+        fn encrypt_in_place(&mut self, iv: &RtpIv, buf: &mut [u8]) -> Result<(), CryptoError>;
     }
 
     pub fn rtp_hmac(key: &[u8], buf: &mut [u8], srtp_index: u64, hmac_start: usize) {
@@ -209,6 +314,15 @@ pub mod aead_aes_128_gcm {
             input: &[u8],
             output: &mut [u8],
         ) -> Result<usize, CryptoError>;
+
+        /// Encrypt `buf` in place. The plain text is followed by room for the tag,
+        /// i.e. the last `TAG_LEN` bytes of `buf` are overwritten.
+        fn encrypt_in_place(
+            &mut self,
+            iv: &[u8; IV_LEN],
+            aad: &[u8],
+            buf: &mut [u8],
+        ) -> Result<(), CryptoError>;
     }
 
     pub fn rtp_iv(salt: RtpSalt, ssrc: u32, roc: u32, seq: u16) -> RtpIv {
@@ -252,6 +366,51 @@ pub mod aead_aes_128_gcm {
         iv
     }
 }
+
+pub mod aead_aes_256_gcm {
+    use std::panic::UnwindSafe;
+
+    use crate::crypto::CryptoError;
+
+    // Apart from the key length, AEAD_AES_256_GCM is identical to AEAD_AES_128_GCM.
+    // See: https://www.rfc-editor.org/rfc/rfc7714#section-14.2
+    pub use super::aead_aes_128_gcm::{RtpSalt, IV_LEN, SALT_LEN};
+
+    pub const KEY_LEN: usize = 32;
+    pub type AeadKey = [u8; KEY_LEN];
+
+    pub trait CipherCtx: UnwindSafe + Send + Sync {
+        fn new(key: AeadKey, encrypt: bool) -> Self
+        where
+            Self: Sized;
+
+        fn encrypt(
+            &mut self,
+            iv: &[u8; IV_LEN],
+            aad: &[u8],
+            input: &[u8],
+            output: &mut [u8],
+        ) -> Result<(), CryptoError>;
+
+        fn decrypt(
+            &mut self,
+            iv: &[u8; IV_LEN],
+            aads: &[&[u8]],
+            input: &[u8],
+            output: &mut [u8],
+        ) -> Result<usize, CryptoError>;
+
+        /// Encrypt `buf` in place. The plain text is followed by room for the tag,
+        /// i.e. the last `TAG_LEN` bytes of `buf` are overwritten.
+        fn encrypt_in_place(
+            &mut self,
+            iv: &[u8; IV_LEN],
+            aad: &[u8],
+            buf: &mut [u8],
+        ) -> Result<(), CryptoError>;
+    }
+}
+
 impl fmt::Display for SrtpProfile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -259,6 +418,8 @@ impl fmt::Display for SrtpProfile {
             SrtpProfile::PassThrough => write!(f, "PassThrough"),
             SrtpProfile::Aes128CmSha1_80 => write!(f, "SRTP_AES128_CM_SHA1_80"),
             SrtpProfile::AeadAes128Gcm => write!(f, "SRTP_AEAD_AES_128_GCM"),
+            SrtpProfile::AeadAes256Gcm => write!(f, "SRTP_AEAD_AES_256_GCM"),
+            SrtpProfile::NullDebug => write!(f, "NULL_DEBUG"),
         }
     }
 }
@@ -1,8 +1,9 @@
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 use std::{fmt, io};
 use thiserror::Error;
 
-use crate::crypto::{CryptoError, DtlsImpl, Fingerprint};
+use crate::crypto::{CryptoError, DtlsImpl, Fingerprint, SrtpProfile};
 
 pub use crate::crypto::{DtlsCert, DtlsEvent};
 use crate::net::DatagramSend;
@@ -15,6 +16,21 @@ pub enum DtlsError {
     #[cfg(feature = "openssl")]
     OpenSsl(#[from] openssl::error::ErrorStack),
 
+    /// A user provided private key doesn't belong to the certificate.
+    #[error("DTLS private key does not match certificate")]
+    KeyMismatch,
+
+    /// The handshake flight was retransmitted the max number of times without
+    /// getting an answer.
+    #[error("DTLS handshake timed out")]
+    HandshakeTimeout,
+
+    /// The remote certificate doesn't match any fingerprint from the SDP.
+    ///
+    /// This is fatal, no SRTP keys are derived.
+    #[error("remote certificate fingerprint mismatch")]
+    FingerprintMismatch,
+
     /// Other IO errors.
     #[error("{0}")]
     Io(#[from] io::Error),
@@ -36,11 +52,55 @@ impl From<CryptoError> for DtlsError {
         match value {
             #[cfg(feature = "openssl")]
             CryptoError::OpenSsl(e) => DtlsError::OpenSsl(e),
+            CryptoError::KeyMismatch => DtlsError::KeyMismatch,
+            CryptoError::FingerprintMismatch => DtlsError::FingerprintMismatch,
             CryptoError::Io(e) => DtlsError::Io(e),
         }
     }
 }
 
+/// Progress of the DTLS handshake and connection.
+///
+/// Emitted as [`Event::DtlsStateChange`][crate::Event::DtlsStateChange].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DtlsState {
+    /// The handshake started.
+    ///
+    /// When `active`, we are the DTLS client and send the first flight, otherwise we
+    /// wait for the remote.
+    Started {
+        /// Whether we are the DTLS client.
+        active: bool,
+    },
+
+    /// We sent a new flight of handshake messages.
+    FlightSent,
+
+    /// The last flight was sent again since the remote didn't answer.
+    FlightRetransmitted,
+
+    /// We received handshake messages from the remote.
+    FlightReceived,
+
+    /// The handshake completed.
+    Connected {
+        /// The negotiated cipher suite, by the name used in the DTLS implementation.
+        cipher: String,
+        /// The negotiated SRTP protection profile.
+        srtp_profile: SrtpProfile,
+    },
+
+    /// The handshake failed. This is fatal.
+    Failed(String),
+
+    /// The remote closed the DTLS connection with a close_notify alert.
+    ///
+    /// No more SRTP is accepted, and the [`Rtc`][crate::Rtc] disconnects once our
+    /// close_notify is sent in return.
+    Closed,
+}
+
 /// Encapsulation of DTLS.
 pub struct Dtls {
     dtls_impl: DtlsImpl,
@@ -53,6 +113,51 @@ pub struct Dtls {
 
     /// Events ready to be polled.
     events: VecDeque<DtlsEvent>,
+
+    /// State changes ready to be polled.
+    states: VecDeque<DtlsState>,
+
+    /// Retransmission of handshake flights.
+    retransmit: Retransmit,
+
+    /// Whether the final flight of the passive side is yet to be sent.
+    final_flight: bool,
+
+    /// Whether close_notify was sent or received.
+    closed: bool,
+}
+
+/// Upper bound for the retransmission timeout (RFC 6347 4.2.4.1).
+const MAX_RTO: Duration = Duration::from_secs(60);
+
+/// Retransmission of handshake flights (RFC 6347 4.2.4).
+///
+/// The DTLS implementation only sees buffers and has no clock, so we keep the last
+/// flight it sent and resend it with exponential backoff until the remote answers.
+#[derive(Debug)]
+struct Retransmit {
+    initial_rto: Duration,
+    max_retransmits: usize,
+
+    /// Current timeout, doubled on each retransmission.
+    rto: Duration,
+
+    /// Datagrams of the last flight sent.
+    flight: Vec<Vec<u8>>,
+
+    /// Set when receiving from the remote, the next datagram sent starts a new flight.
+    flight_done: bool,
+
+    /// Retransmitted datagrams waiting to be sent.
+    queue: VecDeque<DatagramSend>,
+
+    /// Number of retransmissions of the current flight.
+    count: usize,
+
+    /// When to retransmit next.
+    at: Option<Instant>,
+
+    last_now: Option<Instant>,
 }
 
 impl Dtls {
@@ -69,9 +174,23 @@ impl Dtls {
             fingerprint,
             remote_fingerprint: None,
             events: VecDeque::new(),
+            states: VecDeque::new(),
+            retransmit: Retransmit::new(),
+            final_flight: false,
+            closed: false,
         })
     }
 
+    /// Configure the retransmission of handshake flights.
+    ///
+    /// The timeout starts at `initial_rto` and doubles up to 60 seconds. The handshake
+    /// fails after `max_retransmits` of the same flight.
+    pub fn set_retransmit(&mut self, initial_rto: Duration, max_retransmits: usize) {
+        self.retransmit.initial_rto = initial_rto;
+        self.retransmit.rto = initial_rto;
+        self.retransmit.max_retransmits = max_retransmits;
+    }
+
     /// Tells if this instance has been inited.
     ///
     /// Once true, we cannot do `set_active` anymore.
@@ -84,7 +203,8 @@ impl Dtls {
     /// i.e. initiating the client hello or not. This must be called
     /// exactly once before starting to handshake (I/O).
     pub fn set_active(&mut self, active: bool) {
-        self.dtls_impl.set_active(active)
+        self.dtls_impl.set_active(active);
+        self.states.push_back(DtlsState::Started { active });
     }
 
     /// If set_active, returns what was set.
@@ -104,9 +224,72 @@ impl Dtls {
         &self.remote_fingerprint
     }
 
+    /// Max size of outgoing datagrams.
+    ///
+    /// Handshake messages bigger than this, like a large certificate, are fragmented.
+    /// Incoming fragments are reassembled regardless of this setting.
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<(), DtlsError> {
+        Ok(self.dtls_impl.set_mtu(mtu)?)
+    }
+
+    /// Toggle verification of the remote certificate. Defaults to true.
+    pub fn set_fingerprint_verification(&mut self, enabled: bool) {
+        self.dtls_impl.set_fingerprint_verification(enabled)
+    }
+
+    /// The fingerprints from SDP, one of which the remote certificate must match.
+    pub fn set_remote_fingerprints(&mut self, fingerprints: Vec<Fingerprint>) {
+        self.dtls_impl.set_remote_fingerprints(fingerprints)
+    }
+
     /// Poll for the next datagram to send.
     pub fn poll_datagram(&mut self) -> Option<DatagramSend> {
-        self.dtls_impl.poll_datagram()
+        if let Some(d) = self.retransmit.queue.pop_front() {
+            return Some(d);
+        }
+
+        let d = self.dtls_impl.poll_datagram()?;
+
+        if self.dtls_impl.is_connected() {
+            if self.final_flight {
+                self.final_flight = false;
+                self.states.push_back(DtlsState::FlightSent);
+            }
+            // The last flight is only resent when the remote resends its flight.
+            self.retransmit.stop();
+        } else if self.retransmit.on_send(&d) {
+            self.states.push_back(DtlsState::FlightSent);
+        }
+
+        Some(d)
+    }
+
+    /// Drive the retransmission of handshake flights.
+    ///
+    /// Fails with [`DtlsError::HandshakeTimeout`] when the max retransmits are used up.
+    pub fn handle_timeout(&mut self, now: Instant) -> Result<(), DtlsError> {
+        let count = self.retransmit.count;
+
+        if let Err(e) = self.retransmit.handle_timeout(now) {
+            self.states.push_back(DtlsState::Failed(e.to_string()));
+            return Err(e);
+        }
+
+        if self.retransmit.count > count {
+            self.states.push_back(DtlsState::FlightRetransmitted);
+        }
+
+        Ok(())
+    }
+
+    /// When the next retransmission is due.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        self.retransmit.at
+    }
+
+    /// Poll for the next state change.
+    pub fn poll_state(&mut self) -> Option<DtlsState> {
+        self.states.pop_front()
     }
 
     /// Poll for an event.
@@ -115,6 +298,9 @@ impl Dtls {
         if x.is_some() {
             trace!("Poll event: {:?}", x);
         }
+        if let Some(DtlsEvent::RemoteFingerprint(fingerprint)) = &x {
+            self.remote_fingerprint = Some(fingerprint.clone());
+        }
         x
     }
 
@@ -130,25 +316,97 @@ impl Dtls {
             return Ok(());
         }
 
-        Ok(self.dtls_impl.handle_receive(message, &mut self.events)?)
+        if self.closed {
+            debug!("Ignoring DTLS datagram after close");
+            return Ok(());
+        }
+
+        let handshaking = !self.dtls_impl.is_connected();
+        if handshaking && !self.retransmit.flight_done {
+            self.states.push_back(DtlsState::FlightReceived);
+        }
+
+        let len_before = self.events.len();
+        let result = self.dtls_impl.handle_receive(message, &mut self.events);
+        self.handle_result(result, handshaking, len_before)?;
+
+        if self.dtls_impl.is_connected() {
+            // The passive side answers the last flight of the remote with its own.
+            if handshaking && self.dtls_impl.is_active() == Some(false) {
+                self.final_flight = true;
+            }
+            self.retransmit.stop();
+        } else {
+            self.retransmit.flight_done = true;
+        }
+
+        Ok(())
     }
 
     /// Handle handshaking.
     ///
     /// Once handshaken, this becomes a noop.
     pub fn handle_handshake(&mut self) -> Result<bool, DtlsError> {
+        let handshaking = !self.dtls_impl.is_connected();
         let len_before = self.events.len();
-        let result = self.dtls_impl.handle_handshake(&mut self.events)?;
+        let result = self.dtls_impl.handle_handshake(&mut self.events);
+        self.handle_result(result, handshaking, len_before)
+    }
 
-        if self.remote_fingerprint.is_none() && self.events.len() > len_before {
-            for ev in &self.events {
-                if let DtlsEvent::RemoteFingerprint(fingerprint) = ev {
-                    self.remote_fingerprint = Some(fingerprint.clone());
+    /// Derive state changes from the outcome of handshaking or receiving.
+    fn handle_result<T>(
+        &mut self,
+        result: Result<T, CryptoError>,
+        handshaking: bool,
+        len_before: usize,
+    ) -> Result<T, DtlsError> {
+        let value = match result {
+            Ok(v) => v,
+            Err(e) => {
+                let e = DtlsError::from(e);
+                if handshaking && !e.is_would_block() {
+                    self.states.push_back(DtlsState::Failed(e.to_string()));
                 }
+                return Err(e);
+            }
+        };
+
+        for e in self.events.iter().skip(len_before) {
+            match e {
+                DtlsEvent::SrtpKeyingMaterial(_, srtp_profile) => {
+                    let cipher = self.dtls_impl.cipher().unwrap_or_default();
+                    self.states.push_back(DtlsState::Connected {
+                        cipher,
+                        srtp_profile: *srtp_profile,
+                    });
+                }
+                DtlsEvent::CloseNotify => {
+                    self.closed = true;
+                    self.states.push_back(DtlsState::Closed);
+                }
+                _ => {}
             }
         }
 
-        Ok(result)
+        Ok(value)
+    }
+
+    /// Send a close_notify alert to the remote.
+    ///
+    /// Does nothing unless connected.
+    pub fn close(&mut self) -> Result<(), DtlsError> {
+        if self.closed || !self.dtls_impl.is_connected() {
+            return Ok(());
+        }
+        debug!("Send close_notify");
+        self.dtls_impl.close()?;
+        self.closed = true;
+        Ok(())
+    }
+
+    /// Whether close_notify was sent or received.
+    pub fn is_closed(&self) -> bool {
+        self.closed
     }
 
     pub(crate) fn is_connected(&self) -> bool {
@@ -156,6 +414,88 @@ impl Dtls {
     }
 }
 
+impl Retransmit {
+    fn new() -> Self {
+        // RFC 6347 4.2.4.1 recommends an initial timer value of 1 second.
+        let initial_rto = Duration::from_secs(1);
+
+        Retransmit {
+            initial_rto,
+            max_retransmits: 6,
+            rto: initial_rto,
+            flight: vec![],
+            flight_done: false,
+            queue: VecDeque::new(),
+            count: 0,
+            at: None,
+            last_now: None,
+        }
+    }
+
+    /// Returns whether the datagram starts a new flight.
+    fn on_send(&mut self, d: &[u8]) -> bool {
+        let new_flight = self.flight_done || self.flight.is_empty();
+
+        if self.flight_done {
+            self.flight.clear();
+            self.flight_done = false;
+            self.count = 0;
+            self.rto = self.initial_rto;
+        }
+
+        self.flight.push(d.to_vec());
+
+        if let Some(now) = self.last_now {
+            self.at = Some(now + self.rto);
+        }
+
+        new_flight
+    }
+
+    fn stop(&mut self) {
+        self.flight.clear();
+        self.flight_done = false;
+        self.queue.clear();
+        self.at = None;
+    }
+
+    fn handle_timeout(&mut self, now: Instant) -> Result<(), DtlsError> {
+        self.last_now = Some(now);
+
+        let Some(at) = self.at else {
+            // Sent before we had a time.
+            if !self.flight.is_empty() {
+                self.at = Some(now + self.rto);
+            }
+            return Ok(());
+        };
+
+        if now < at {
+            return Ok(());
+        }
+
+        if self.count >= self.max_retransmits {
+            warn!("DTLS handshake timed out after {} retransmits", self.count);
+            self.stop();
+            return Err(DtlsError::HandshakeTimeout);
+        }
+
+        self.count += 1;
+        self.rto = (self.rto * 2).min(MAX_RTO);
+        self.at = Some(now + self.rto);
+
+        debug!(
+            "DTLS retransmit flight ({} datagrams), next in {:?}",
+            self.flight.len(),
+            self.rto
+        );
+        self.queue
+            .extend(self.flight.iter().map(|d| DatagramSend::from(d.clone())));
+
+        Ok(())
+    }
+}
+
 impl fmt::Debug for DtlsEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -169,6 +509,197 @@ impl fmt::Debug for DtlsEvent {
                 f.debug_tuple("RemoteFingerprint").field(arg0).finish()
             }
             Self::Data(arg0) => f.debug_tuple("Data").field(&arg0.len()).finish(),
+            Self::CloseNotify => write!(f, "CloseNotify"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "openssl"))]
+mod test {
+    use super::*;
+    use crate::crypto::KeyingMaterial;
+    use crate::crypto::SrtpProfile;
+    use crate::rtp_::{ExtensionMap, RtpHeader, SrtpContext};
+
+    fn pair() -> (Dtls, Dtls) {
+        let mut client = Dtls::new(DtlsCert::new_openssl()).unwrap();
+        let mut server = Dtls::new(DtlsCert::new_openssl()).unwrap();
+
+        client.set_remote_fingerprints(vec![server.local_fingerprint().clone()]);
+        server.set_remote_fingerprints(vec![client.local_fingerprint().clone()]);
+
+        (client, server)
+    }
+
+    fn handshake() -> ((KeyingMaterial, SrtpProfile), (KeyingMaterial, SrtpProfile)) {
+        let (mut client, mut server) = pair();
+
+        client.set_active(true);
+        server.set_active(false);
+        client.handle_handshake().unwrap();
+
+        let mut client_mat = None;
+        let mut server_mat = None;
+
+        for _ in 0..20 {
+            while let Some(d) = client.poll_datagram() {
+                server.handle_receive(&d).unwrap();
+            }
+            while let Some(d) = server.poll_datagram() {
+                client.handle_receive(&d).unwrap();
+            }
+
+            for (dtls, mat) in [
+                (&mut client, &mut client_mat),
+                (&mut server, &mut server_mat),
+            ] {
+                while let Some(e) = dtls.poll_event() {
+                    if let DtlsEvent::SrtpKeyingMaterial(m, p) = e {
+                        *mat = Some((m, p));
+                    }
+                }
+            }
+
+            if client_mat.is_some() && server_mat.is_some() {
+                break;
+            }
+        }
+
+        (client_mat.unwrap(), server_mat.unwrap())
+    }
+
+    #[test]
+    fn handshake_keys_match_roles() {
+        let ((client_mat, client_profile), (server_mat, server_profile)) = handshake();
+
+        assert_eq!(client_profile, server_profile);
+        assert_eq!(*client_mat, *server_mat);
+
+        let profile = client_profile;
+        assert_eq!(client_mat.len(), profile.keying_material_len());
+
+        let (client_tx, client_rx) = client_mat.derive(profile, true);
+        let (server_tx, server_rx) = server_mat.derive(profile, false);
+
+        // What the client writes, the server reads, and vice versa.
+        assert_eq!(client_tx.master(), server_rx.master());
+        assert_eq!(client_tx.salt(), server_rx.salt());
+        assert_eq!(server_tx.master(), client_rx.master());
+        assert_eq!(server_tx.salt(), client_rx.salt());
+        assert_ne!(client_tx.master(), server_tx.master());
+
+        // RFC 5764 4.2: client key, server key, client salt, server salt.
+        let k = profile.master_key_len();
+        assert_eq!(client_tx.master(), &client_mat[..k]);
+        assert_eq!(server_tx.master(), &client_mat[k..2 * k]);
+        assert_eq!(
+            client_tx.salt(),
+            &client_mat[2 * k..2 * k + profile.master_salt_len()]
+        );
+
+        // Contexts made directly from the keys interoperate.
+        let mut tx = SrtpContext::from_keys(profile, &client_tx);
+        let mut rx = SrtpContext::from_keys(profile, &server_rx);
+
+        let buf = [&[0x80, 96, 0, 1, 0, 0, 0, 1, 0, 0, 0, 42][..], &[7; 16]].concat();
+        let header = RtpHeader::parse(&buf, &ExtensionMap::empty()).unwrap();
+        let len = buf.len();
+
+        let mut out = buf.clone();
+        out.resize(len + crate::rtp_::SRTP_OVERHEAD, 0);
+        let n = tx.protect_rtp_in_place(&mut out, len, &header, 1).unwrap();
+
+        assert_eq!(rx.unprotect_rtp(&out[..n], &header).unwrap(), [7; 16]);
+    }
+
+    #[test]
+    fn handshake_fingerprint_mismatch() {
+        let (mut client, mut server) = pair();
+
+        let mut wrong = server.local_fingerprint().clone();
+        wrong.bytes[0] ^= 1;
+        client.set_remote_fingerprints(vec![wrong]);
+
+        client.set_active(true);
+        server.set_active(false);
+        client.handle_handshake().unwrap();
+
+        let mut error = None;
+
+        for _ in 0..20 {
+            while let Some(d) = client.poll_datagram() {
+                server.handle_receive(&d).unwrap();
+            }
+            while let Some(d) = server.poll_datagram() {
+                if let Err(e) = client.handle_receive(&d) {
+                    error = Some(e);
+                }
+            }
+            if error.is_some() {
+                break;
+            }
+        }
+
+        assert!(matches!(error, Some(DtlsError::FingerprintMismatch)));
+        assert!(!client.is_connected());
+        assert_eq!(client.remote_fingerprint(), &None);
+
+        // No keys, not even from a retry.
+        assert!(client.handle_handshake().is_err());
+        while let Some(e) = client.poll_event() {
+            assert!(!matches!(
+                e,
+                DtlsEvent::SrtpKeyingMaterial(..) | DtlsEvent::Connected
+            ));
         }
     }
+
+    #[test]
+    fn handshake_fingerprint_any_matches() {
+        let (mut client, mut server) = pair();
+
+        let right = server.local_fingerprint().clone();
+        let mut wrong = right.clone();
+        wrong.bytes[0] ^= 1;
+        let unknown = Fingerprint {
+            hash_func: "md5".into(),
+            bytes: vec![0; 16],
+        };
+        let mut upper = right.clone();
+        upper.hash_func = "SHA-256".into();
+        client.set_remote_fingerprints(vec![unknown, wrong, upper]);
+
+        client.set_active(true);
+        server.set_active(false);
+        client.handle_handshake().unwrap();
+
+        for _ in 0..20 {
+            while let Some(d) = client.poll_datagram() {
+                server.handle_receive(&d).unwrap();
+            }
+            while let Some(d) = server.poll_datagram() {
+                client.handle_receive(&d).unwrap();
+            }
+            while client.poll_event().is_some() {}
+        }
+
+        assert!(client.is_connected());
+        assert_eq!(
+            client.remote_fingerprint().as_ref().map(|f| &f.bytes),
+            Some(&right.bytes)
+        );
+    }
+
+    #[test]
+    fn profile_ids() {
+        use openssl::srtp::SrtpProfileId;
+
+        for profile in SrtpProfile::ALL {
+            let id = SrtpProfileId::from_raw(profile.id() as _);
+            assert_eq!(SrtpProfile::try_from(id).unwrap(), *profile);
+            assert_eq!(SrtpProfile::from_id(profile.id()), Some(*profile));
+        }
+
+        assert_eq!(SrtpProfile::from_id(0x0002), None);
+    }
 }
@@ -2,16 +2,27 @@
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::num::NonZeroU16;
 use std::ops::RangeInclusive;
+use std::time::Duration;
 
-use crate::packet::{H264ProfileLevel, MediaKind};
+use crate::packet::{answer_profile_level_id, H264ProfileLevel, MediaKind};
 use crate::rtp_::Pt;
 use crate::rtp_::{Direction, Frequency};
 use crate::sdp::FormatParam;
 
 // These really don't belong anywhere, but I guess they're kind of related
 // to codecs etc.
-pub use crate::packet::{CodecExtra, H264CodecExtra, Vp8CodecExtra, Vp9CodecExtra};
+pub use crate::packet::is_keyframe_start;
+pub use crate::packet::{Av1CodecExtra, CodecExtra, H264CodecExtra, H265CodecExtra};
+pub use crate::packet::{Av1Decision, Av1Selector};
+pub use crate::packet::{CodecFrameInfo, CodecKey, CodecRegistry, Depacketizer, Packetizer};
+pub use crate::packet::{DtmfDecoder, DtmfEncoder, DtmfEvent, DtmfPacket};
+pub use crate::packet::{OpusFecPolicy, OpusGap, OpusMeta, OpusMode};
+pub use crate::packet::{RedDecoder, RedEncoder, RedFrame};
+pub use crate::packet::{Vp8CodecExtra, Vp8Packetizer, Vp9CodecExtra};
+pub use crate::packet::{Vp8Meta, Vp9Meta, Vp9PictureGroupEntry, Vp9ScalabilityStructure};
+pub use crate::packet::{Vp9FrameInfo, Vp9LayerFrame, Vp9LayerSelector, Vp9Selection};
 
 /// Session config for all codecs.
 #[derive(Debug, Clone, Default)]
@@ -59,6 +70,12 @@ pub struct PayloadParams {
     /// Whether the payload uses the REMB (Receiver Estimated Maximum Bitrate) mechanic.
     pub(crate) fb_remb: bool,
 
+    /// How long, in milliseconds, the sender keeps packets for RTX (resend).
+    ///
+    /// `a=fmtp:<resend> apt=<pt>;rtx-time=<ms>`
+    #[serde(default)]
+    pub(crate) rtx_time: Option<NonZeroU16>,
+
     /// Whether the payload is locked by negotiation or can still be debated.
     ///
     /// If we make an OFFER or ANSWER and the direction is sendrecv/recvonly, the parameters are locked
@@ -100,7 +117,7 @@ pub struct CodecSpec {
 }
 
 /// Known codecs.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[non_exhaustive]
 #[allow(missing_docs)]
 pub enum Codec {
@@ -114,6 +131,11 @@ pub enum Codec {
     // TODO show this when we support Av1.
     #[doc(hidden)]
     Av1,
+    /// Arbitrary payload without a known codec, such as application data.
+    ///
+    /// Each buffer is split over packets to fit the MTU, and each received RTP
+    /// payload is passed through as is.
+    Generic,
     /// Technically not a codec, but used in places where codecs go
     /// in `a=rtpmap` lines.
     #[doc(hidden)]
@@ -164,6 +186,12 @@ pub struct FormatParams {
 
     /// VP9 profile id.
     pub profile_id: Option<u32>,
+
+    /// H265 specific parameter.
+    ///
+    /// The maximum difference in decoding order number between NAL units. When greater
+    /// than 0, the RTP payloads carry DONL/DOND fields.
+    pub sprop_max_don_diff: Option<u32>,
 }
 
 impl PayloadParams {
@@ -190,6 +218,8 @@ impl PayloadParams {
             fb_pli: is_video,
             fb_remb: is_video,
 
+            rtx_time: None,
+
             locked: false,
         }
     }
@@ -260,6 +290,20 @@ impl PayloadParams {
         self.fb_remb
     }
 
+    /// Set how long the sender keeps packets for resends, `rtx-time` of the RTX payload type.
+    ///
+    /// Only written in the SDP if there is a [`resend`][Self::resend] payload type.
+    pub fn set_rtx_time(&mut self, rtx_time: Option<Duration>) {
+        self.rtx_time =
+            rtx_time.and_then(|d| NonZeroU16::new(d.as_millis().min(u16::MAX as u128) as u16));
+    }
+
+    /// How long the sender keeps packets for resends, `rtx-time` of the RTX payload type.
+    pub fn rtx_time(&self) -> Option<Duration> {
+        self.rtx_time
+            .map(|ms| Duration::from_millis(ms.get() as u64))
+    }
+
     pub(crate) fn match_score(&self, o: &PayloadParams) -> Option<usize> {
         // we don't want to compare PT
         let c0 = self.spec;
@@ -365,13 +409,43 @@ impl PayloadParams {
             .map(|l| l.try_into().ok())
             .unwrap_or(Some(H264ProfileLevel::FALLBACK))?;
 
-        if c0_profile_level != c1_profile_level {
+        // The level can differ, it's adjusted in the answer.
+        if !c0_profile_level.is_same_profile(&c1_profile_level) {
             return None;
         }
 
+        // Prefer the local config with the same level.
+        if !c0_profile_level.is_same_level(&c1_profile_level) {
+            return Some(99);
+        }
+
         Some(100)
     }
 
+    /// Adjust our H264 level to the remote, for the answer.
+    ///
+    /// Our level goes down to the remote level, unless both sides allow level asymmetry.
+    fn update_h264_level(&mut self, remote: &PayloadParams) {
+        let local = &mut self.spec.format;
+        let (Some(l), Some(r)) = (local.profile_level_id, remote.spec.format.profile_level_id)
+        else {
+            return;
+        };
+
+        let asymmetry = local.level_asymmetry_allowed == Some(true)
+            && remote.spec.format.level_asymmetry_allowed == Some(true);
+
+        if let Some(v) = answer_profile_level_id(l, r, asymmetry) {
+            if v != l {
+                debug!(
+                    "H264 PT {} profile-level-id {:06x} => {:06x}",
+                    self.pt, l, v
+                );
+            }
+            local.profile_level_id = Some(v);
+        }
+    }
+
     fn update_param(
         &mut self,
         remote_pts: &[PayloadParams],
@@ -380,6 +454,9 @@ impl PayloadParams {
     ) {
         let Some((first, _)) = remote_pts
             .iter()
+            // Another of our configs may already have the remote PT, such as for H264 with
+            // the same profile but another level.
+            .filter(|p| self.locked || !claimed.is_claimed(p.pt))
             .filter_map(|p| self.match_score(p).map(|s| (p, s)))
             .max_by_key(|(_, s)| *s)
         else {
@@ -413,6 +490,10 @@ impl PayloadParams {
             self.resend = remote_rtx;
             self.locked = true;
 
+            if self.spec.codec == Codec::H264 {
+                self.update_h264_level(first);
+            }
+
             claimed.assert_claim_once(remote_pt);
             if let Some(rtx) = remote_rtx {
                 claimed.assert_claim_once(rtx);
@@ -487,6 +568,7 @@ impl CodecConfig {
             fb_nack,
             fb_pli,
             fb_remb,
+            rtx_time: None,
             locked: false,
         };
 
@@ -812,7 +894,12 @@ impl FormatParams {
             PacketizationMode(v) => self.packetization_mode = Some(*v),
             ProfileLevelId(v) => self.profile_level_id = Some(*v),
             ProfileId(v) => self.profile_id = Some(*v),
+            SpropMaxDonDiff(v) => self.sprop_max_don_diff = Some(*v),
+            // Only in OpusParams.
+            MaxPTime(_) | MaxPlaybackRate(_) | MaxAverageBitrate(_) | Stereo(_) => {}
             Apt(_) => {}
+            RtxTime(_) => {}
+            Other(..) => {}
             Unknown => {}
         }
     }
@@ -842,6 +929,9 @@ impl FormatParams {
         if let Some(v) = self.profile_id {
             r.push(ProfileId(v));
         }
+        if let Some(v) = self.sprop_max_don_diff {
+            r.push(SpropMaxDonDiff(v));
+        }
 
         r
     }
@@ -859,6 +949,146 @@ impl fmt::Display for FormatParams {
     }
 }
 
+/// Opus parameters of a media, as negotiated in the SDP.
+///
+/// The `a=fmtp` of an Opus payload type states what the receiver wants, see
+/// [RFC 7587][rfc]. The negotiated parameters combine our format parameters with the
+/// remote's:
+///
+/// * The rate caps and `maxptime` are the lower of the two, `minptime` the higher.
+/// * A boolean is true if both sides want it, and when only one side states it, that side
+///   decides.
+///
+/// Unset means neither side said, which is the default of the RFC.
+///
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc7587#section-6.1
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OpusParams {
+    /// `maxplaybackrate`, the highest sample rate rendered, in Hz.
+    pub max_playback_rate: Option<u32>,
+
+    /// `maxaveragebitrate`, the highest average bitrate, in bits per second.
+    pub max_average_bitrate: Option<u32>,
+
+    /// `stereo`, whether stereo is preferred over mono.
+    pub stereo: Option<bool>,
+
+    /// `useinbandfec`, whether in-band FEC is used.
+    pub use_inband_fec: Option<bool>,
+
+    /// `usedtx`, whether DTX (Discontinuous Transmission) is used.
+    pub use_dtx: Option<bool>,
+
+    /// `minptime`, the minimum duration of media in a packet, in milliseconds.
+    pub min_p_time: Option<u8>,
+
+    /// `maxptime`, the maximum duration of media in a packet, in milliseconds.
+    pub max_p_time: Option<u8>,
+
+    /// The remote's keys we don't interpret, which go back as is in the answer.
+    pub(crate) other: Vec<FormatParam>,
+}
+
+impl OpusParams {
+    /// Parse the `a=fmtp` values of an Opus payload type.
+    pub(crate) fn parse(values: &[FormatParam]) -> Self {
+        use FormatParam::*;
+        let mut p = OpusParams::default();
+
+        for v in values {
+            match v {
+                MaxPlaybackRate(v) => p.max_playback_rate = Some(*v),
+                MaxAverageBitrate(v) => p.max_average_bitrate = Some(*v),
+                Stereo(v) => p.stereo = Some(*v),
+                UseInbandFec(v) => p.use_inband_fec = Some(*v),
+                UseDtx(v) => p.use_dtx = Some(*v),
+                MinPTime(v) => p.min_p_time = Some(*v),
+                MaxPTime(v) => p.max_p_time = Some(*v),
+                Other(..) => p.other.push(v.clone()),
+                _ => {}
+            }
+        }
+
+        p
+    }
+
+    /// Combine our (local) parameters with the remote's.
+    pub(crate) fn negotiate(&self, remote: &OpusParams) -> Self {
+        fn lower<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        fn higher<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        fn both(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a && b),
+                (a, b) => a.or(b),
+            }
+        }
+
+        OpusParams {
+            max_playback_rate: lower(self.max_playback_rate, remote.max_playback_rate),
+            max_average_bitrate: lower(self.max_average_bitrate, remote.max_average_bitrate),
+            stereo: both(self.stereo, remote.stereo),
+            use_inband_fec: both(self.use_inband_fec, remote.use_inband_fec),
+            use_dtx: both(self.use_dtx, remote.use_dtx),
+            min_p_time: higher(self.min_p_time, remote.min_p_time),
+            max_p_time: lower(self.max_p_time, remote.max_p_time),
+            other: remote.other.clone(),
+        }
+    }
+
+    pub(crate) fn to_format_param(&self) -> Vec<FormatParam> {
+        use FormatParam::*;
+        let mut r = Vec::with_capacity(7 + self.other.len());
+
+        if let Some(v) = self.min_p_time {
+            r.push(MinPTime(v));
+        }
+        if let Some(v) = self.max_p_time {
+            r.push(MaxPTime(v));
+        }
+        if let Some(v) = self.use_inband_fec {
+            r.push(UseInbandFec(v));
+        }
+        if let Some(v) = self.use_dtx {
+            r.push(UseDtx(v));
+        }
+        if let Some(v) = self.stereo {
+            r.push(Stereo(v));
+        }
+        if let Some(v) = self.max_playback_rate {
+            r.push(MaxPlaybackRate(v));
+        }
+        if let Some(v) = self.max_average_bitrate {
+            r.push(MaxAverageBitrate(v));
+        }
+        r.extend(self.other.iter().cloned());
+
+        r
+    }
+}
+
+impl From<FormatParams> for OpusParams {
+    fn from(v: FormatParams) -> Self {
+        OpusParams {
+            use_inband_fec: v.use_inband_fec,
+            use_dtx: v.use_dtx,
+            min_p_time: v.min_p_time,
+            ..Default::default()
+        }
+    }
+}
+
 impl Codec {
     /// Tells if codec is audio.
     pub fn is_audio(&self) -> bool {
@@ -892,6 +1122,7 @@ impl<'a> From<&'a str> for Codec {
             "vp8" => Codec::Vp8,
             "vp9" => Codec::Vp9,
             "av1" => Codec::Av1,
+            "generic" => Codec::Generic,
             "rtx" => Codec::Rtx, // resends
             _ => Codec::Unknown,
         }
@@ -907,6 +1138,7 @@ impl fmt::Display for Codec {
             Codec::Vp8 => write!(f, "VP8"),
             Codec::Vp9 => write!(f, "VP9"),
             Codec::Av1 => write!(f, "AV1"),
+            Codec::Generic => write!(f, "generic"),
             Codec::Rtx => write!(f, "rtx"),
             Codec::Null => write!(f, "null"),
             Codec::Unknown => write!(f, "unknown"),
@@ -951,6 +1183,7 @@ mod test {
                 packetization_mode,
                 profile_level_id,
                 profile_id: None, // VP8
+                sprop_max_don_diff: None,
             },
         }
     }
@@ -981,7 +1214,7 @@ mod test {
             c1: h264_codec_spec(None, None, Some(0x42B00A)),
             must_match: true,
             msg:
-                "0x424000 and 0x42B00A should match because they are both the baseline subprofile and the level idc of 0x42F01F will be adjusted to Level1B because the constraint set 3 flag is set"
+                "0x422000 and 0x42B00A should match because they are both the baseline subprofile, the level doesn't matter"
         }];
 
         for Case {
@@ -995,4 +1228,85 @@ mod test {
             assert_eq!(matched, must_match, "{msg}\nc0: {c0:#?}\nc1: {c1:#?}");
         }
     }
+
+    #[test]
+    fn h264_profile_level_id_matching() {
+        // (local, remote, match), all with packetization-mode=1.
+        #[rustfmt::skip]
+        const CASES: &[(u32, u32, bool)] = &[
+            // Chrome, same configs.
+            (0x42e01f, 0x42e01f, true),
+            (0x42001f, 0x42001f, true),
+            (0x4d001f, 0x4d001f, true),
+            (0x64001f, 0x64001f, true),
+            // Constrained baseline is not baseline, nor main.
+            (0x42e01f, 0x42001f, false),
+            (0x42001f, 0x42e01f, false),
+            (0x42e01f, 0x4d001f, false),
+            // Constrained baseline with other profile-iop.
+            (0x42e01f, 0x42c01f, true),
+            (0x42e01f, 0x4d401f, false),
+            (0x42e01f, 0x4dc01f, true),
+            // Other levels, Safari level 5.2 and level 1b.
+            (0x42e01f, 0x42e034, true),
+            (0x42e01f, 0x42f00b, true),
+            (0x4d001f, 0x4d0032, true),
+            (0x64001f, 0x640032, true),
+            // Hardware endpoints, baseline with constraint_set0_flag.
+            (0x42001f, 0x42801f, true),
+            (0x42001f, 0x42801e, true),
+            (0x42e01f, 0x42801f, false),
+            // Constrained high is not high.
+            (0x64001f, 0x640c1f, false),
+            (0x64001f, 0xf4001f, false),
+            // Not a valid profile-level-id.
+            (0x42e01f, 0x42e0ff, false),
+            (0x42e01f, 0x12e01f, false),
+        ];
+
+        for (local, remote, must_match) in CASES {
+            let c0 = h264_codec_spec(None, Some(1), Some(*local));
+            let c1 = h264_codec_spec(None, Some(1), Some(*remote));
+            let matched = PayloadParams::match_h264_score(c0, c1).is_some();
+            assert_eq!(matched, *must_match, "{local:06x} {remote:06x}");
+        }
+    }
+
+    #[test]
+    fn opus_params_negotiate() {
+        let values = |line: &str| -> Vec<FormatParam> {
+            line.split(';')
+                .map(|kv| kv.split_once('=').unwrap())
+                .map(|(k, v)| FormatParam::parse(k, v))
+                .collect()
+        };
+
+        let local = OpusParams::parse(&values("minptime=10;useinbandfec=1;maxplaybackrate=24000"));
+        let remote = OpusParams::parse(&values(
+            "minptime=20;maxptime=60;useinbandfec=0;usedtx=1;stereo=1;\
+            maxplaybackrate=48000;maxaveragebitrate=32000;x-foo=bar",
+        ));
+
+        let n = local.negotiate(&remote);
+
+        assert_eq!(n.max_playback_rate, Some(24000));
+        assert_eq!(n.max_average_bitrate, Some(32000));
+        assert_eq!(n.stereo, Some(true));
+        assert_eq!(n.use_inband_fec, Some(false));
+        assert_eq!(n.use_dtx, Some(true));
+        assert_eq!(n.min_p_time, Some(20));
+        assert_eq!(n.max_p_time, Some(60));
+
+        let line = n
+            .to_format_param()
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+        assert_eq!(
+            line,
+            "minptime=20;maxptime=60;useinbandfec=0;usedtx=1;stereo=1;\
+            maxplaybackrate=24000;maxaveragebitrate=32000;x-foo=bar"
+        );
+    }
 }
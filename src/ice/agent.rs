@@ -1,5 +1,5 @@
 use std::collections::{HashSet, VecDeque};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
@@ -10,7 +10,8 @@ use crate::io::{StunMessage, TransId, STUN_TIMEOUT};
 use crate::io::{Transmit, DATAGRAM_MTU};
 use crate::util::NonCryptographicRng;
 
-use super::candidate::{Candidate, CandidateKind};
+use super::candidate::{Candidate, CandidateKind, TypePreferences};
+use super::mdns::{MdnsHooks, MDNS_RESOLVE_TIMEOUT};
 use super::pair::{CandidatePair, CheckState, PairId};
 
 /// Handles the ICE protocol for a given peer.
@@ -67,6 +68,18 @@ pub struct IceAgent {
     /// All remote candidates, in the order we get to know them.
     remote_candidates: Vec<Candidate>,
 
+    /// Whether the remote signalled end-of-candidates (RFC 8838).
+    ///
+    /// Until then, more remote candidates might trickle in.
+    remote_end_of_candidates: bool,
+
+    /// Remote candidates with an mDNS name waiting to be resolved, and when we
+    /// started waiting.
+    remote_unresolved: Vec<(Candidate, Option<Instant>)>,
+
+    /// Hooks to resolve remote and register local mDNS names.
+    mdns: MdnsHooks,
+
     /// The candidate pairs.
     candidate_pairs: Vec<CandidatePair>,
 
@@ -88,6 +101,26 @@ pub struct IceAgent {
     /// if we get a better candidate for [`IceAgentEvent::NominatedSend`].
     nominated_send: Option<PairId>,
 
+    /// Currently nominated pair for sending RTCP, when RTCP is on component 2.
+    /// See [`IceAgentEvent::NominatedSendRtcp`].
+    nominated_send_rtcp: Option<PairId>,
+
+    /// How the controlling side picks the pair to nominate.
+    nomination: NominationStrategy,
+
+    /// Whether the controlling side nominates a better pair after the fact.
+    renomination: bool,
+
+    /// For [`NominationStrategy::Delayed`], until when we wait for better pairs.
+    nomination_deadline: Option<Instant>,
+
+    /// Type preferences for local candidate priorities.
+    type_preferences: TypePreferences,
+
+    /// After an ICE restart, until when we may keep sending on the pair nominated
+    /// before the restart. Consent can't be refreshed with the old credentials.
+    previous_consent: Option<Instant>,
+
     /// Statistics counter for the agent.
     stats: IceAgentStats,
 }
@@ -101,6 +134,8 @@ struct StunRequest {
     trans_id: TransId,
     prio: u32,
     use_candidate: bool,
+    ice_controlling: Option<u64>,
+    ice_controlled: Option<u64>,
     remote_ufrag: String,
 }
 
@@ -111,7 +146,7 @@ const REMOTE_PEER_REFLEXIVE_TEMP_FOUNDATION: &str = "tmp_prflx";
 /// More details on connection states can be found in the [ICE RFC][1].
 ///
 /// [1]: https://www.rfc-editor.org/rfc/rfc8445
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum IceConnectionState {
     /// The ICE agent is gathering addresses.
     New,
@@ -130,6 +165,8 @@ pub enum IceConnectionState {
 
     /// The ICE agent has finished gathering candidates, has checked all pairs
     /// against one another, and has found a working connection.
+    ///
+    /// Finished gathering means the remote has signalled end-of-candidates.
     Completed,
 
     /// Connection failed. This is a less stringent test than `failed` and may trigger
@@ -139,7 +176,7 @@ pub enum IceConnectionState {
     Disconnected,
     //
     // NB: The failed and closed state doesn't really have a mapping in this implementation.
-    //     We never end trickle ice and it's always possible to "come back" if more remote
+    //     Even after end-of-candidates, it's always possible to "come back" if more remote
     //     candidates are added.
     //
     // The ICE candidate has checked all candidates pairs against one another and has
@@ -162,6 +199,23 @@ impl IceConnectionState {
     }
 }
 
+/// How the controlling agent nominates a candidate pair.
+///
+/// The controlled agent uses whatever the controlling agent nominates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NominationStrategy {
+    /// Nominate the first pair that succeeds.
+    #[default]
+    Eager,
+
+    /// Wait for up to this long after the first pair succeeds, for pairs with higher
+    /// priority to succeed, before nominating the best one.
+    ///
+    /// There is no wait if the pair that succeeded has the highest priority of the pairs
+    /// still being checked.
+    Delayed(Duration),
+}
+
 /// Credentials for STUN packages.
 ///
 /// By matching IceCreds in STUN to SDP, we know which STUN belongs to which Peer.
@@ -224,6 +278,33 @@ pub enum IceAgentEvent {
         /// The remote address to send datagrams to.
         destination: SocketAddr,
     },
+
+    /// A nominated local and remote socket for sending RTCP.
+    ///
+    /// Only happens when there are candidates for component 2, which is the case when
+    /// RTCP is not multiplexed with RTP (no `a=rtcp-mux`). Like [`IceAgentEvent::NominatedSend`]
+    /// this can be sent multiple times, and the last one is the one to use.
+    NominatedSendRtcp {
+        /// The protocol to use for the socket.
+        proto: Protocol,
+        /// The local socket address to send datagrams from.
+        source: SocketAddr,
+        /// The remote address to send datagrams to.
+        destination: SocketAddr,
+    },
+
+    /// A remote candidate has an mDNS hostname (`<uuid>.local`) to resolve.
+    ///
+    /// Resolve it with [`IceAgent::resolve_mdns`]. The candidate is dropped if that
+    /// doesn't happen within 10 seconds.
+    ResolveMdns(String),
+
+    /// Consent (RFC 7675) on the pair nominated for sending has expired.
+    ///
+    /// There was no response to the consent checks for 30 seconds, and we must stop
+    /// sending on the pair. Another valid pair might be nominated, otherwise the
+    /// connection goes to disconnected and the application should ICE restart.
+    ConsentExpired,
 }
 
 impl IceCreds {
@@ -261,12 +342,21 @@ impl IceAgent {
             state: IceConnectionState::New,
             local_candidates: vec![],
             remote_candidates: vec![],
+            remote_end_of_candidates: false,
+            remote_unresolved: vec![],
+            mdns: MdnsHooks::default(),
             candidate_pairs: vec![],
             transmit: VecDeque::new(),
             events: VecDeque::new(),
             stun_server_queue: VecDeque::new(),
             discovered_recv: HashSet::new(),
             nominated_send: None,
+            nominated_send_rtcp: None,
+            nomination: NominationStrategy::default(),
+            renomination: true,
+            nomination_deadline: None,
+            type_preferences: TypePreferences::default(),
+            previous_consent: None,
             stats: IceAgentStats::default(),
             timing_advance: Duration::from_millis(50),
         }
@@ -288,9 +378,37 @@ impl IceAgent {
 
     /// Enable or disable ice_lite.
     ///
+    /// An ice-lite agent only has host candidates, never sends any binding requests
+    /// and is always controlled, i.e. the full agent on the other side must nominate.
+    ///
     /// Default is disabled.
     pub fn set_ice_lite(&mut self, enabled: bool) {
         self.ice_lite = enabled;
+        if enabled {
+            self.controlling = false;
+        }
+    }
+
+    /// Set how the controlling side nominates a pair.
+    ///
+    /// Default is [`NominationStrategy::Eager`].
+    pub fn set_nomination_strategy(&mut self, strategy: NominationStrategy) {
+        self.nomination = strategy;
+    }
+
+    /// Whether the controlling side nominates a pair with higher priority, when
+    /// it succeeds after the nomination.
+    ///
+    /// Default is enabled.
+    pub fn set_renomination(&mut self, enabled: bool) {
+        self.renomination = enabled;
+    }
+
+    /// Set the type preferences used for the priority of local candidates.
+    ///
+    /// This must be done before adding local candidates.
+    pub fn set_type_preferences(&mut self, prefs: TypePreferences) {
+        self.type_preferences = prefs;
     }
 
     /// Set a new timing advance (Ta) value.
@@ -337,6 +455,51 @@ impl IceAgent {
             .any(|pair| self.remote_candidates[pair.remote_idx()].addr() == addr)
     }
 
+    /// Whether the local address belongs to a candidate for the RTCP component (component 2).
+    pub(crate) fn is_rtcp_local(&self, proto: Protocol, addr: SocketAddr) -> bool {
+        self.local_candidates
+            .iter()
+            .any(|c| c.component_id() == 2 && c.proto() == proto && c.base() == addr)
+    }
+
+    /// The remote will not send any more candidates (until an ICE restart).
+    ///
+    /// Without this, the agent doesn't reach [`IceConnectionState::Completed`], since
+    /// a trickled candidate might still give a better pair.
+    pub fn set_remote_end_of_candidates(&mut self) {
+        if !self.remote_end_of_candidates {
+            info!("Remote end-of-candidates");
+            self.remote_end_of_candidates = true;
+        }
+    }
+
+    pub(crate) fn set_mdns_hooks(&mut self, mdns: MdnsHooks) {
+        self.mdns = mdns;
+    }
+
+    /// Provide the address of a remote mDNS hostname.
+    ///
+    /// The remote candidates with this name are paired as usual, in response to
+    /// [`IceAgentEvent::ResolveMdns`].
+    pub fn resolve_mdns(&mut self, name: &str, ip: IpAddr) {
+        let (resolved, pending): (Vec<_>, Vec<_>) =
+            self.remote_unresolved.drain(..).partition(|(c, _)| {
+                c.mdns_name()
+                    .map_or(false, |n| n.eq_ignore_ascii_case(name))
+            });
+        self.remote_unresolved = pending;
+
+        if resolved.is_empty() {
+            debug!("No candidate for resolved mDNS name: {}", name);
+        }
+
+        for (mut c, _) in resolved {
+            info!("Resolved mDNS name {} to {}", name, ip);
+            c.resolve(ip);
+            self.add_remote_candidate(c);
+        }
+    }
+
     /// Sets the remote ice credentials.
     pub fn set_remote_credentials(&mut self, r: IceCreds) {
         if self.remote_credentials.as_ref() != Some(&r) {
@@ -390,12 +553,48 @@ impl IceAgent {
 
     /// Set whether we are the controlling side.
     ///
+    /// An ice-lite agent is always controlled, and ignores `true`.
+    ///
     /// You should not call this function after ICE candidate pair formation
     /// has started, as the controlling state influences candidate prio!
     pub fn set_controlling(&mut self, v: bool) {
+        if v && self.ice_lite {
+            debug!("Ignore set_controlling, ice-lite is always controlled");
+            return;
+        }
         self.controlling = v;
     }
 
+    #[cfg(test)]
+    pub(crate) fn set_control_tie_breaker(&mut self, v: u64) {
+        self.control_tie_breaker = v;
+    }
+
+    /// Change role to resolve a role conflict.
+    ///
+    /// Unlike [`IceAgent::set_controlling`], this recalculates the pair priorities.
+    fn switch_role(&mut self, controlling: bool) {
+        info!(
+            "Switch role to {} due to role conflict",
+            if controlling {
+                "controlling"
+            } else {
+                "controlled"
+            }
+        );
+
+        self.controlling = controlling;
+
+        for pair in &mut self.candidate_pairs {
+            let local = pair.local_candidate(&self.local_candidates);
+            let remote = pair.remote_candidate(&self.remote_candidates);
+            let prio = CandidatePair::calculate_prio(controlling, remote.prio(), local.prio());
+            pair.set_prio(prio);
+        }
+
+        self.candidate_pairs.sort();
+    }
+
     /// Current ice agent state.
     pub fn state(&self) -> IceConnectionState {
         self.state
@@ -428,6 +627,18 @@ impl IceAgent {
         // "Adopt" any incoming candidate by setting our current ufrag.
         c.set_ufrag(&self.local_credentials.ufrag);
 
+        if self.type_preferences != TypePreferences::default() {
+            c.set_type_preferences(self.type_preferences);
+        }
+
+        // Hide the IP of host candidates behind an mDNS name.
+        if c.kind() == CandidateKind::Host && c.mdns_name().is_none() {
+            if let Some(name) = self.mdns.register(ip) {
+                debug!("Register mDNS name {} for local candidate: {:?}", name, c);
+                c.set_mdns_name(name);
+            }
+        }
+
         // https://datatracker.ietf.org/doc/html/rfc8445#section-5.1.2.1
         // The local preference MUST be an integer from 0 (lowest preference) to
         // 65535 (highest preference) inclusive.  When there is only a single IP
@@ -474,15 +685,21 @@ impl IceAgent {
             x - if ip.is_ipv6() { 0 } else { 1 }
         };
 
-        // Count the number of existing candidates of the same kind.
+        // Count the number of existing candidates of the same kind (and component).
         let same_kind = self
             .local_candidates
             .iter()
             .filter(|v| v.kind() == c.kind())
+            .filter(|v| v.component_id() == c.component_id())
             .filter(|v| v.addr().is_ipv6() == ip.is_ipv6())
             .count() as u32;
 
-        let pref = counter_start - same_kind * 2;
+        let mut pref = counter_start - same_kind * 2;
+
+        // TCP candidates have the direction in the top bits, and the rest scaled down.
+        if let Some(tcptype) = c.tcptype() {
+            pref = tcptype.local_preference(pref >> 3);
+        }
         trace!("Calculated local preference: {}", pref);
 
         c.set_local_preference(pref);
@@ -563,10 +780,9 @@ impl IceAgent {
     /// Adding loopback addresses or multicast/broadcast addresses causes
     /// an error.
     pub fn add_remote_candidate(&mut self, mut c: Candidate) {
-        // This is a a:rtcp-mux-only implementation. The only component
-        // we accept is 1 for RTP.
-        if c.component_id() != 1 {
-            debug!("Reject candidate for component other than 1: {:?}", c);
+        // Component 1 is RTP, and 2 is RTCP when not doing a=rtcp-mux.
+        if c.component_id() != 1 && c.component_id() != 2 {
+            debug!("Reject candidate for component other than 1 or 2: {:?}", c);
             return;
         }
 
@@ -586,6 +802,18 @@ impl IceAgent {
         // confusing inspecting the state.
         c.clear_ufrag();
 
+        if c.is_unresolved() {
+            let name = c.mdns_name().unwrap_or_default().to_string();
+
+            if let Some(ip) = self.mdns.resolve(&name) {
+                debug!("Resolved mDNS name {} to {}", name, ip);
+                c.resolve(ip);
+            } else {
+                self.add_remote_unresolved(c, name);
+                return;
+            }
+        }
+
         let existing_prflx = self
             .remote_candidates
             .iter_mut()
@@ -642,6 +870,33 @@ impl IceAgent {
         self.form_pairs(&local_idxs, &remote_idxs);
     }
 
+    /// Hold on to a remote candidate until the mDNS name is resolved.
+    fn add_remote_unresolved(&mut self, c: Candidate, name: String) {
+        let mut same_name = self
+            .remote_unresolved
+            .iter()
+            .map(|(o, _)| o)
+            .filter(|o| o.mdns_name() == Some(&name));
+
+        if same_name
+            .clone()
+            .any(|o| o.addr() == c.addr() && o.proto() == c.proto())
+        {
+            trace!("Ignore already unresolved remote candidate: {:?}", c);
+            return;
+        }
+
+        // Only ask once per name.
+        let first = same_name.next().is_none();
+
+        info!("Add unresolved remote candidate: {:?}", c);
+        self.remote_unresolved.push((c, self.last_now));
+
+        if first {
+            self.emit_event(IceAgentEvent::ResolveMdns(name));
+        }
+    }
+
     /// Form pairs given two slices of indexes into the local_candidates and remote_candidates.
     fn form_pairs(&mut self, local_idxs: &[usize], remote_idxs: &[usize]) {
         for local_idx in local_idxs {
@@ -654,6 +909,18 @@ impl IceAgent {
                     continue 'outer;
                 }
 
+                // And be for the same component.
+                if local.component_id() != remote.component_id() {
+                    continue 'outer;
+                }
+
+                // TCP candidates pair active with passive, and so with so (RFC 6544).
+                match (local.tcptype(), remote.tcptype()) {
+                    (None, None) => {}
+                    (Some(l), Some(r)) if l.can_pair(r) => {}
+                    _ => continue 'outer,
+                }
+
                 let prio =
                     CandidatePair::calculate_prio(self.controlling, remote.prio(), local.prio());
                 let mut pair = CandidatePair::new(*local_idx, *remote_idx, prio);
@@ -796,12 +1063,23 @@ impl IceAgent {
         // data can continue to be sent using existing data sessions, and a new
         // data session always requires the roles to be determined.
 
+        // Data continues on the nominated pair until we nominate a new one.
+        self.previous_consent = self
+            .candidate_pairs
+            .iter()
+            .find(|p| p.is_selected())
+            .and_then(|p| p.consent_expires())
+            .or(self.previous_consent);
+
         self.remote_credentials = None;
         self.remote_candidates.clear();
+        self.remote_end_of_candidates = false;
+        self.remote_unresolved.clear();
         self.candidate_pairs.clear();
         self.transmit.clear();
         self.events.clear();
         self.discovered_recv.clear();
+        self.nomination_deadline = None;
 
         if keep_local_candidates {
             // If we're keeping the candidates, we must update the ufrag to the new credentials.
@@ -929,6 +1207,8 @@ impl IceAgent {
             self.stun_server_handle_message(now, &packet);
         } else if packet.message.is_successful_binding_response() {
             self.stun_client_handle_response(now, packet.message);
+        } else if packet.message.is_failed_binding_response() {
+            self.stun_client_handle_error(packet.message);
         }
 
         self.emit_event(IceAgentEvent::DiscoveredRecv {
@@ -936,8 +1216,6 @@ impl IceAgent {
             source: packet.source,
         });
 
-        // TODO handle unsuccessful responses.
-
         true
     }
 
@@ -952,6 +1230,16 @@ impl IceAgent {
             self.emit_event(IceAgentEvent::IceRestart(self.local_credentials.clone()));
         }
 
+        // Give up on mDNS names not resolved in time, without holding up other pairs.
+        self.remote_unresolved.retain_mut(|(c, since)| {
+            let since = *since.get_or_insert(now);
+            let keep = now - since < MDNS_RESOLVE_TIMEOUT;
+            if !keep {
+                debug!("Drop remote candidate with unresolved mDNS name: {:?}", c);
+            }
+            keep
+        });
+
         self.evaluate_state(now);
 
         // First we try to empty the queue of saved STUN requests.
@@ -977,7 +1265,22 @@ impl IceAgent {
 
         self.last_now = Some(now);
 
-        self.evaluate_nomination();
+        self.evaluate_nomination(now);
+
+        if self
+            .candidate_pairs
+            .iter()
+            .any(|p| p.is_consent_expired(now))
+        {
+            info!("Consent expired for nominated pair");
+            self.emit_event(IceAgentEvent::ConsentExpired);
+        }
+
+        if self.previous_consent.map_or(false, |t| now >= t) {
+            info!("Consent expired for pair nominated before ICE restart");
+            self.previous_consent = None;
+            self.emit_event(IceAgentEvent::ConsentExpired);
+        }
 
         // prune failed candidates.
         let mut any_pruned = false;
@@ -994,7 +1297,7 @@ impl IceAgent {
             keep
         });
         if any_pruned {
-            self.evaluate_nomination();
+            self.evaluate_nomination(now);
             self.evaluate_state(now);
         }
 
@@ -1078,16 +1381,37 @@ impl IceAgent {
                 .min()
         };
 
+        // or drop an unresolved mDNS candidate.
+        let unresolved = self
+            .remote_unresolved
+            .iter()
+            .map(|(_, since)| since.unwrap_or(last_now) + MDNS_RESOLVE_TIMEOUT)
+            .min();
+
+        // Keepalives and consent checks are far apart, but we wake up at least as
+        // often as when idle.
+        let idle = last_now + Duration::from_secs(3);
+
+        // or nominate after waiting for better pairs.
+        let nomination = self.nomination_deadline.filter(|_| self.controlling);
+
+        let maybe_next = maybe_next
+            .into_iter()
+            .chain(unresolved)
+            .chain(nomination)
+            .chain(self.previous_consent)
+            .min();
+
         // Time must advance with at least Ta.
         let next = if let Some(next) = maybe_next {
             if next < last_now + self.timing_advance {
                 last_now + self.timing_advance
             } else {
-                next
+                next.min(idle)
             }
         } else {
             // IDLE for a while.
-            last_now + Duration::from_secs(3)
+            idle
         };
 
         Some(next)
@@ -1144,6 +1468,8 @@ impl IceAgent {
             trans_id,
             prio,
             use_candidate,
+            ice_controlling: message.ice_controlling(),
+            ice_controlled: message.ice_controlled(),
             remote_ufrag: remote_ufrag.into(),
         };
 
@@ -1191,6 +1517,14 @@ impl IceAgent {
             return;
         }
 
+        if self.has_role_conflict(&req) {
+            debug!("STUN request rejected, role conflict");
+
+            let reply = StunMessage::error_reply(req.trans_id, 487, "Role Conflict");
+            self.stun_server_send(req.proto, req.destination, req.source, reply);
+            return;
+        }
+
         if req.use_candidate && self.controlling {
             // the other side is not controlling, and it sent USE-CANDIDATE. that's wrong.
             debug!("STUN request rejected, USE-CANDIDATE when local is controlling");
@@ -1199,6 +1533,31 @@ impl IceAgent {
 
         self.stats.bind_request_recv += 1;
 
+        let local_idx = match self.local_candidates.iter().enumerate().find(|(_, v)| {
+            // The local candidate will be
+            // either a host candidate (for cases where the request was not received
+            // through a relay) or a relayed candidate (for cases where it is
+            // received through a relay).  The local candidate can never be a
+            // server-reflexive candidate.
+            matches!(v.kind(), CandidateKind::Host | CandidateKind::Relayed)
+                && v.addr() == req.destination
+                && v.proto() == req.proto
+        }) {
+            Some((i, _)) => i,
+            None => {
+                // Receiving traffic for an IP address that neither is a HOST nor RELAY
+                // is most likely a configuration fault where the user forgot to add a
+                // candidate for the local interface. We are network-connected application
+                // so we need to handle this gracefully: Log a message and discard the packet.
+
+                debug!(
+                    "Discarding STUN request on unknown interface: {}",
+                    req.destination
+                );
+                return;
+            }
+        };
+
         // If the source transport address of the request does not match any
         // existing remote candidates, it represents a new peer-reflexive remote
         // candidate.
@@ -1231,7 +1590,7 @@ impl IceAgent {
             //     foundations of all other remote candidates.  If any subsequent
             //     candidate exchanges contain this peer-reflexive candidate, it will
             //     signal the actual foundation for the candidate.
-            let c = Candidate::peer_reflexive(
+            let mut c = Candidate::peer_reflexive(
                 req.proto,
                 req.source,
                 req.source,
@@ -1245,6 +1604,9 @@ impl IceAgent {
                 self.local_credentials.ufrag.clone(),
             );
 
+            // The remote is for the same component as the local the request came in on.
+            c.set_component_id(self.local_candidates[local_idx].component_id());
+
             info!(
                 "Created peer reflexive remote candidate from STUN request: {:?}",
                 c
@@ -1257,31 +1619,6 @@ impl IceAgent {
             self.remote_candidates.len() - 1
         };
 
-        let local_idx = match self.local_candidates.iter().enumerate().find(|(_, v)| {
-            // The local candidate will be
-            // either a host candidate (for cases where the request was not received
-            // through a relay) or a relayed candidate (for cases where it is
-            // received through a relay).  The local candidate can never be a
-            // server-reflexive candidate.
-            matches!(v.kind(), CandidateKind::Host | CandidateKind::Relayed)
-                && v.addr() == req.destination
-                && v.proto() == req.proto
-        }) {
-            Some((i, _)) => i,
-            None => {
-                // Receiving traffic for an IP address that neither is a HOST nor RELAY
-                // is most likely a configuration fault where the user forgot to add a
-                // candidate for the local interface. We are network-connected application
-                // so we need to handle this gracefully: Log a message and discard the packet.
-
-                debug!(
-                    "Discarding STUN request on unknown interface: {}",
-                    req.destination
-                );
-                return;
-            }
-        };
-
         let maybe_pair = self
             .candidate_pairs
             .iter_mut()
@@ -1343,19 +1680,55 @@ impl IceAgent {
 
         if self.controlling && pair.state() == CheckState::Succeeded {
             // See if we can nominate something now.
-            self.evaluate_nomination();
+            self.evaluate_nomination(req.now);
         }
 
-        let (_, password) = self.stun_credentials(true);
-
         let reply = StunMessage::reply(req.trans_id, req.source);
 
-        trace!(
-            "Send STUN reply: {} -> {} {:?}",
-            local_addr,
-            remote_addr,
-            reply
-        );
+        self.stun_server_send(proto, local_addr, remote_addr, reply);
+    }
+
+    /// Detects and repairs role conflicts (RFC 8445 7.3.1.1).
+    ///
+    /// Returns true if the request must be answered with a 487 (Role Conflict).
+    fn has_role_conflict(&mut self, req: &StunRequest) -> bool {
+        if self.controlling {
+            let Some(remote) = req.ice_controlling else {
+                return false;
+            };
+
+            if self.control_tie_breaker >= remote {
+                true
+            } else {
+                self.switch_role(false);
+                false
+            }
+        } else {
+            let Some(remote) = req.ice_controlled else {
+                return false;
+            };
+
+            // The ice-lite agent never takes the controlling role, which
+            // makes the full agent switch.
+            if !self.ice_lite && self.control_tie_breaker >= remote {
+                self.switch_role(true);
+                false
+            } else {
+                true
+            }
+        }
+    }
+
+    fn stun_server_send(
+        &mut self,
+        proto: Protocol,
+        source: SocketAddr,
+        destination: SocketAddr,
+        reply: StunMessage<'_>,
+    ) {
+        let (_, password) = self.stun_credentials(true);
+
+        trace!("Send STUN reply: {} -> {} {:?}", source, destination, reply);
 
         let mut buf = vec![0_u8; DATAGRAM_MTU];
 
@@ -1366,8 +1739,8 @@ impl IceAgent {
 
         let trans = Transmit {
             proto,
-            source: local_addr,
-            destination: remote_addr,
+            source,
+            destination,
             contents: buf.into(),
         };
 
@@ -1384,7 +1757,7 @@ impl IceAgent {
         // Only the controlling side sends USE-CANDIDATE.
         let use_candidate = self.controlling && pair.is_nominated();
 
-        let trans_id = pair.new_attempt(now);
+        let trans_id = pair.new_attempt(now, self.controlling);
 
         self.stats.bind_request_sent += 1;
 
@@ -1451,11 +1824,12 @@ impl IceAgent {
             // This should be caught in the parsing.
             .expect("Mapped address in STUN response");
 
+        let proto = pair.local_candidate(&self.local_candidates).proto();
         let found_in_local = self
             .local_candidates
             .iter()
             .enumerate()
-            .find(|(_, c)| c.addr() == mapped_address);
+            .find(|(_, c)| c.proto() == proto && c.addr() == mapped_address);
 
         let (pair, valid_idx) = if let Some((valid_idx, _)) = found_in_local {
             // Note, the valid_idx might not be the same as the local_idx that we
@@ -1487,7 +1861,7 @@ impl IceAgent {
             let base = local_sent_from.base();
 
             // o  The type is peer reflexive.
-            let candidate = Candidate::peer_reflexive(
+            let mut candidate = Candidate::peer_reflexive(
                 local_sent_from.proto(),
                 mapped_address,
                 base,
@@ -1495,6 +1869,8 @@ impl IceAgent {
                 None,
                 self.local_credentials.ufrag.clone(),
             );
+            candidate.set_tcptype(local_sent_from.tcptype());
+            candidate.set_component_id(local_sent_from.component_id());
 
             debug!(
                 "Created local peer reflexive candidate for mapped address: {}",
@@ -1509,8 +1885,9 @@ impl IceAgent {
             // the peer-reflexive candidate.  This will cause the peer-reflexive candidate
             // to be paired with all other remote candidates.
 
-            // For now we do not tell the other side about discovered peer-reflexive candidates.
-            // We just include it in our list of local candidates and use it for the "valid pair".
+            // We never tell the other side about discovered peer-reflexive candidates, they
+            // are left out of the SDP. We just include it in our list of local candidates
+            // and use it for the "valid pair".
             self.local_candidates.push(candidate);
 
             let idx = self.local_candidates.len() - 1;
@@ -1521,21 +1898,63 @@ impl IceAgent {
         pair.record_binding_response(now, trans_id, valid_idx);
 
         if self.controlling {
-            self.evaluate_nomination();
+            self.evaluate_nomination(now);
         }
 
         // State might change when we get a response.
         self.evaluate_state(now);
     }
 
-    fn evaluate_nomination(&mut self) {
-        let nominated_pair_priority = self.nominated_pair_priority();
+    fn stun_client_handle_error(&mut self, message: StunMessage<'_>) {
+        let Some((code, reason)) = message.error_code() else {
+            debug!("STUN error response without ERROR-CODE");
+            return;
+        };
+
+        if code != 487 {
+            debug!("Ignore STUN error response: {} {}", code, reason);
+            return;
+        }
+
+        let trans_id = message.trans_id();
+        let Some(sent_controlling) = self
+            .candidate_pairs
+            .iter_mut()
+            .find_map(|p| p.remove_role_conflict_attempt(trans_id))
+        else {
+            return;
+        };
+
+        // If we already switched role, due to an earlier response or an incoming
+        // request, the check will just be retried.
+        if sent_controlling == self.controlling && !self.ice_lite {
+            self.switch_role(!sent_controlling);
+        }
+    }
+
+    fn evaluate_nomination(&mut self, now: Instant) {
+        // Component 1 is RTP, and 2 is RTCP when it isn't muxed.
+        self.evaluate_nomination_for(now, 1);
+        self.evaluate_nomination_for(now, 2);
+    }
+
+    fn evaluate_nomination_for(&mut self, now: Instant, component: u16) {
+        let nominated_pair_priority = self.nominated_pair_priority(component);
+
+        if self.controlling && !self.should_nominate(now, component, nominated_pair_priority) {
+            return;
+        }
+
+        let local_candidates = &self.local_candidates;
+        let in_component =
+            |p: &CandidatePair| p.local_candidate(local_candidates).component_id() == component;
 
         let best_prio = if self.controlling {
             // For controlling agents, we pick the best candidate pair using
             // this strategy.
             self.candidate_pairs
                 .iter_mut()
+                .filter(|p| in_component(p))
                 .filter(|p| p.state() == CheckState::Succeeded)
                 .max_by_key(|p| p.prio())
         } else {
@@ -1543,6 +1962,7 @@ impl IceAgent {
             // agent has indicated with USE-CANDIDATE stun attribute.
             self.candidate_pairs
                 .iter_mut()
+                .filter(|p| in_component(p))
                 .filter(|p| p.is_nominated())
                 .max_by_key(|p| p.prio())
         };
@@ -1566,17 +1986,162 @@ impl IceAgent {
             let local = best_prio.local_candidate(&self.local_candidates);
             let remote = best_prio.remote_candidate(&self.remote_candidates);
 
-            self.nominated_send = Some(best_prio.id());
-            self.emit_event(IceAgentEvent::NominatedSend {
-                proto: local.proto(),
-                source: local.base(),
-                destination: remote.addr(),
-            })
+            let id = best_prio.id();
+            let (proto, source, destination) = (local.proto(), local.base(), remote.addr());
+
+            let event = if component == 1 {
+                self.nominated_send = Some(id);
+                self.previous_consent = None;
+                IceAgentEvent::NominatedSend {
+                    proto,
+                    source,
+                    destination,
+                }
+            } else {
+                self.nominated_send_rtcp = Some(id);
+                IceAgentEvent::NominatedSendRtcp {
+                    proto,
+                    source,
+                    destination,
+                }
+            };
+
+            self.nomination_deadline = None;
+
+            let local_candidates = &self.local_candidates;
+            for p in &mut self.candidate_pairs {
+                if p.local_candidate(local_candidates).component_id() == component {
+                    p.set_selected(now, p.id() == id);
+                }
+            }
+
+            self.emit_event(event);
+        }
+    }
+
+    /// Whether the controlling agent nominates the best succeeded pair now.
+    fn should_nominate(&mut self, now: Instant, component: u16, nominated: Option<u64>) -> bool {
+        if nominated.is_some() {
+            // The nominated pair is kept as long as it's alive.
+            return self.renomination;
+        }
+
+        let NominationStrategy::Delayed(window) = self.nomination else {
+            return true;
+        };
+
+        let in_component = |p: &&CandidatePair| {
+            p.local_candidate(&self.local_candidates).component_id() == component
+        };
+
+        let Some(best) = self
+            .candidate_pairs
+            .iter()
+            .filter(in_component)
+            .filter(|p| p.state() == CheckState::Succeeded)
+            .map(|p| p.prio())
+            .max()
+        else {
+            return true;
+        };
+
+        // The window starts with the first successful pair.
+        let deadline = *self.nomination_deadline.get_or_insert(now + window);
+
+        let highest_possible = self
+            .candidate_pairs
+            .iter()
+            .filter(in_component)
+            .filter(|p| p.is_still_possible(now))
+            .map(|p| p.prio())
+            .max()
+            .unwrap_or(best);
+
+        if best < highest_possible && now < deadline {
+            trace!("Delay nomination for a pair with higher priority");
+            return false;
+        }
+
+        true
+    }
+
+    /// The route used for sending changed.
+    ///
+    /// Restarts the keepalive on the nominated pair, to get consent on the new path right away.
+    pub(crate) fn handle_route_change(&mut self, now: Instant) {
+        let Some(id) = self.nominated_send else {
+            return;
+        };
+
+        if let Some(pair) = self.candidate_pairs.iter_mut().find(|p| p.id() == id) {
+            pair.restart_keepalive(now);
+        }
+    }
+
+    /// All candidate pairs, with their local and remote candidate.
+    pub(crate) fn candidate_pairs(
+        &self,
+    ) -> impl Iterator<Item = (&CandidatePair, &Candidate, &Candidate)> + '_ {
+        self.candidate_pairs.iter().map(|p| {
+            (
+                p,
+                p.local_candidate(&self.local_candidates),
+                p.remote_candidate(&self.remote_candidates),
+            )
+        })
+    }
+
+    /// Count bytes sent, if it is on the pair nominated for sending.
+    pub(crate) fn record_bytes_sent(
+        &mut self,
+        proto: Protocol,
+        source: SocketAddr,
+        destination: SocketAddr,
+        n: usize,
+    ) {
+        if let Some(pair) = self.selected_pair_mut(proto, source, destination) {
+            pair.add_bytes_sent(n as u64);
         }
     }
 
-    fn nominated_pair_priority(&self) -> Option<u64> {
-        let id = self.nominated_send?;
+    /// Count bytes received, if it is on the pair nominated for sending.
+    pub(crate) fn record_bytes_received(
+        &mut self,
+        proto: Protocol,
+        source: SocketAddr,
+        destination: SocketAddr,
+        n: usize,
+    ) {
+        if let Some(pair) = self.selected_pair_mut(proto, destination, source) {
+            pair.add_bytes_received(n as u64);
+        }
+    }
+
+    fn selected_pair_mut(
+        &mut self,
+        proto: Protocol,
+        local: SocketAddr,
+        remote: SocketAddr,
+    ) -> Option<&mut CandidatePair> {
+        // Either the pair for RTP, or the one for RTCP when that's on component 2.
+        let ids = [self.nominated_send, self.nominated_send_rtcp];
+
+        self.candidate_pairs.iter_mut().find(|p| {
+            let l = p.local_candidate(&self.local_candidates);
+            let r = p.remote_candidate(&self.remote_candidates);
+            ids.contains(&Some(p.id()))
+                && l.proto() == proto
+                && l.base() == local
+                && r.addr() == remote
+        })
+    }
+
+    fn nominated_pair_priority(&self, component: u16) -> Option<u64> {
+        let id = if component == 1 {
+            self.nominated_send?
+        } else {
+            self.nominated_send_rtcp?
+        };
 
         self.candidate_pairs
             .iter()
@@ -1598,7 +2163,10 @@ impl IceAgent {
         let mut any_still_possible = false;
 
         for p in &self.candidate_pairs {
-            if p.is_nominated() {
+            // A nomination for RTCP (component 2) alone doesn't make us connected.
+            let is_rtp = p.local_candidate(&self.local_candidates).component_id() == 1;
+
+            if p.is_nominated() && is_rtp {
                 any_nomination = true;
             } else if p.is_still_possible(now) {
                 any_still_possible = true;
@@ -1607,11 +2175,25 @@ impl IceAgent {
 
         // As a special case, before the ice agent has received any add_remote_candidate() or
         // discovered a peer reflexive via a STUN message, the agent is still viable. This is
-        // also the case for ice_restart.
-        if self.remote_candidates.is_empty() {
+        // also the case for ice_restart. Unless the remote said there will be none.
+        if self.remote_candidates.is_empty() && !self.remote_end_of_candidates {
             any_still_possible = true;
         }
 
+        // Likewise, local candidates may still be gathered, such as after an ICE restart
+        // that didn't keep them.
+        if self.local_candidates.is_empty() {
+            any_still_possible = true;
+        }
+
+        // A remote candidate waiting for its mDNS name might still give a pair.
+        if !self.remote_unresolved.is_empty() {
+            any_still_possible = true;
+        }
+
+        // Nothing more to try, and nothing more to come.
+        let done = !any_still_possible && self.remote_end_of_candidates;
+
         match self.state {
             New => {
                 self.set_connection_state(Checking, "new connection");
@@ -1622,10 +2204,10 @@ impl IceAgent {
                         self.set_connection_state(Completed, "got nomination in ice lite");
                         return;
                     }
-                    if any_still_possible {
-                        self.set_connection_state(Connected, "got nomination, still trying others");
-                    } else {
+                    if done {
                         self.set_connection_state(Completed, "got nomination, no others to try");
+                    } else {
+                        self.set_connection_state(Connected, "got nomination, still trying others");
                     }
                 } else if !any_still_possible {
                     self.set_connection_state(Disconnected, "no possible pairs");
@@ -1633,7 +2215,7 @@ impl IceAgent {
             }
             Connected => {
                 if any_nomination {
-                    if !any_still_possible {
+                    if done {
                         self.set_connection_state(Completed, "no more possible to try");
                     }
                 } else {
@@ -1660,6 +2242,7 @@ impl IceAgent {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::TcpType;
     use std::net::SocketAddr;
 
     impl IceAgent {
@@ -1846,6 +2429,47 @@ mod test {
         assert_eq!(agent.pair_indexes(), [(0, 0), (2, 1)]);
     }
 
+    #[test]
+    fn form_pairs_component() {
+        let mut agent = IceAgent::new();
+
+        let rtcp = |addr: SocketAddr| {
+            let addr = SocketAddr::new(addr.ip(), addr.port() + 1);
+            Candidate::host(addr, "udp").unwrap().with_rtcp_component()
+        };
+
+        agent.add_remote_candidate(Candidate::host(ipv4_3(), "udp").unwrap());
+        agent.add_remote_candidate(rtcp(ipv4_3()));
+        agent.add_local_candidate(Candidate::host(ipv4_1(), "udp").unwrap());
+        agent.add_local_candidate(rtcp(ipv4_1()));
+
+        // RTP pairs with RTP, and RTCP with RTCP.
+        assert_eq!(agent.pair_indexes(), [(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn form_pairs_tcptype() {
+        let mut agent = IceAgent::new();
+
+        let tcp = |addr, t| Candidate::host(addr, "tcp").unwrap().with_tcptype(t);
+
+        // local 0 active, 1 passive, 2 so
+        agent.add_local_candidate(tcp(ipv4_1(), TcpType::Active).unwrap());
+        agent.add_local_candidate(tcp(ipv4_2(), TcpType::Passive).unwrap());
+        agent.add_local_candidate(tcp(ipv4_4(), TcpType::So).unwrap());
+
+        // remote 0 active, 1 passive, 2 so
+        agent.add_remote_candidate(tcp(ipv4_3(), TcpType::Active).unwrap());
+        agent.add_remote_candidate(tcp("5.6.7.8:5000".parse().unwrap(), TcpType::Passive).unwrap());
+        agent.add_remote_candidate(tcp("6.7.8.9:5000".parse().unwrap(), TcpType::So).unwrap());
+
+        // Active pairs with passive, so with so. The passive local is only
+        // paired by incoming checks.
+        let mut pairs = agent.pair_indexes();
+        pairs.sort();
+        assert_eq!(pairs, [(0, 1), (2, 2)]);
+    }
+
     #[test]
     fn form_pairs_replace_redundant() {
         let mut agent = IceAgent::new();
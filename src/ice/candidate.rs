@@ -37,6 +37,9 @@ pub struct Candidate {
     /// Protocol for the candidate.
     proto: Protocol,
 
+    /// For TCP candidates, whether it opens connections, accepts them, or both (RFC 6544).
+    tcptype: Option<TcpType>,
+
     /// Priority.
     ///
     /// For remote, this is communicated, and locally it's (mostly) calculated.
@@ -79,11 +82,26 @@ pub struct Candidate {
     /// If we discarded this candidate (for example due to being redundant
     /// against another candidate).
     discarded: bool,
+
+    /// mDNS hostname (`<uuid>.local`) used instead of the IP for host candidates.
+    ///
+    /// For local candidates the name is signalled instead of the IP. For remote,
+    /// the address is unspecified until the name is resolved.
+    mdns_name: Option<String>,
+
+    /// Type preferences for the priority of local candidates, if not the defaults.
+    type_preferences: Option<TypePreferences>,
 }
 
 impl fmt::Debug for Candidate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Candidate({}={}/{}", self.kind, self.addr, self.proto)?;
+        if let Some(name) = &self.mdns_name {
+            write!(f, " mdns={name}")?;
+        }
+        if let Some(tcptype) = self.tcptype {
+            write!(f, " {tcptype}")?;
+        }
         if let Some(base) = self.base {
             if base != self.addr {
                 write!(f, " base={base}")?;
@@ -117,6 +135,7 @@ impl Candidate {
             foundation,
             component_id,
             proto,
+            tcptype: None,
             prio,
             addr,
             base,
@@ -125,6 +144,8 @@ impl Candidate {
             ufrag,
             local_preference: None,
             discarded: false,
+            mdns_name: None,
+            type_preferences: None,
         }
     }
 
@@ -138,8 +159,9 @@ impl Candidate {
         kind: CandidateKind,
         raddr: Option<SocketAddr>,
         ufrag: Option<String>,
+        tcptype: Option<TcpType>,
     ) -> Self {
-        Candidate::new(
+        let mut c = Candidate::new(
             Some(foundation),
             component_id,
             proto,
@@ -149,7 +171,9 @@ impl Candidate {
             kind,
             raddr,
             ufrag,
-        )
+        );
+        c.tcptype = tcptype;
+        c
     }
 
     /// Creates a host ICE candidate.
@@ -222,6 +246,52 @@ impl Candidate {
         ))
     }
 
+    /// Sets the TCP candidate type (RFC 6544).
+    ///
+    /// Only valid for [`Protocol::Tcp`] candidates. An active candidate opens connections
+    /// and conventionally has port 9, since the OS assigns the actual port for each
+    /// connection. A passive candidate is a listening socket, and a simultaneous-open (so)
+    /// candidate does both.
+    ///
+    /// ```
+    /// # use str0m::{Candidate, TcpType};
+    /// let addr = "1.2.3.4:9".parse().unwrap();
+    /// let c = Candidate::host(addr, "tcp").unwrap().with_tcptype(TcpType::Active).unwrap();
+    /// assert_eq!(c.tcptype(), Some(TcpType::Active));
+    /// ```
+    pub fn with_tcptype(mut self, tcptype: TcpType) -> Result<Self, IceError> {
+        if self.proto != Protocol::Tcp {
+            return Err(IceError::BadCandidate(format!(
+                "tcptype for {} candidate",
+                self.proto
+            )));
+        }
+        self.tcptype = Some(tcptype);
+        Ok(self)
+    }
+
+    /// Makes this a candidate for the RTCP component (component 2).
+    ///
+    /// Only needed when talking to peers that don't do `a=rtcp-mux`, which expect RTCP
+    /// on a separate port, see [`RtcConfig::set_rtcp_mux_policy()`][crate::RtcConfig::set_rtcp_mux_policy].
+    /// The candidate must be a different socket than the ones used for RTP.
+    ///
+    /// ```
+    /// # use str0m::Candidate;
+    /// let addr = "1.2.3.4:5001".parse().unwrap();
+    /// let c = Candidate::host(addr, "udp").unwrap().with_rtcp_component();
+    /// assert!(c.is_rtcp_component());
+    /// ```
+    pub fn with_rtcp_component(mut self) -> Self {
+        self.component_id = 2;
+        self
+    }
+
+    /// Whether this candidate is for the RTCP component (component 2).
+    pub fn is_rtcp_component(&self) -> bool {
+        self.component_id == 2
+    }
+
     /// Creates a new ICE candidate from a string.
     pub fn from_sdp_string(s: &str) -> Result<Self, IceError> {
         parse_candidate(s).map_err(|e| IceError::BadCandidate(format!("{}: {}", s, e)))
@@ -330,21 +400,10 @@ impl Candidate {
             self.kind
         };
 
-        // Per RFC5245 Sec. 4.1.2.1, the RECOMMENDED values for type preferences are
-        // 126 for host candidates, 110 for peer-reflexive candidates, 100 for
-        // server-reflexive candidates, and 0 for relayed candidates. The variations
-        // for non-UDP protocols are taken from libwebrtc:
-        // <https://webrtc.googlesource.com/src/+/refs/heads/main/p2p/base/port.h#68>
-        let type_preference = match (kind, self.proto) {
-            (CandidateKind::Host, Protocol::Udp) => 126,
-            (CandidateKind::PeerReflexive, Protocol::Udp) => 110,
-            (CandidateKind::ServerReflexive, _) => 100,
-            (CandidateKind::Host, _) => 90,
-            (CandidateKind::PeerReflexive, _) => 80,
-            (CandidateKind::Relayed, Protocol::Udp) => 2,
-            (CandidateKind::Relayed, Protocol::Tcp) => 1,
-            (CandidateKind::Relayed, _) => 0,
-        };
+        let type_preference = self
+            .type_preferences
+            .unwrap_or_default()
+            .get(kind, self.proto);
 
         // The recommended formula combines a preference for the candidate type
         // (server reflexive, peer reflexive, relayed, and host), a preference
@@ -365,14 +424,27 @@ impl Candidate {
     }
 
     pub(crate) fn local_preference(&self) -> u32 {
-        self.local_preference
-            .unwrap_or_else(|| if self.addr.is_ipv6() { 65_535 } else { 65_534 })
+        if let Some(v) = self.local_preference {
+            return v;
+        }
+
+        let Some(tcptype) = self.tcptype else {
+            return if self.addr.is_ipv6() { 65_535 } else { 65_534 };
+        };
+
+        let other_pref = if self.addr.is_ipv6() { 8_191 } else { 8_190 };
+
+        tcptype.local_preference(other_pref)
     }
 
     pub(crate) fn component_id(&self) -> u16 {
         self.component_id
     }
 
+    pub(crate) fn set_component_id(&mut self, component_id: u16) {
+        self.component_id = component_id;
+    }
+
     /// Returns the address for the specified ICE candidate.
     pub fn addr(&self) -> SocketAddr {
         self.addr
@@ -384,6 +456,36 @@ impl Candidate {
         self.proto
     }
 
+    /// Returns the TCP candidate type, if this is a TCP candidate that has one.
+    pub fn tcptype(&self) -> Option<TcpType> {
+        self.tcptype
+    }
+
+    pub(crate) fn set_tcptype(&mut self, tcptype: Option<TcpType>) {
+        self.tcptype = tcptype;
+    }
+
+    /// Returns the mDNS hostname (`<uuid>.local`) of the candidate, if it has one.
+    ///
+    /// A remote candidate with a name has an unspecified [`Candidate::addr()`] until
+    /// the name is resolved.
+    pub fn mdns_name(&self) -> Option<&str> {
+        self.mdns_name.as_deref()
+    }
+
+    pub(crate) fn set_mdns_name(&mut self, name: String) {
+        self.mdns_name = Some(name);
+    }
+
+    /// Whether this has an mDNS name that isn't resolved to an address yet.
+    pub(crate) fn is_unresolved(&self) -> bool {
+        self.mdns_name.is_some() && self.addr.ip().is_unspecified()
+    }
+
+    pub(crate) fn resolve(&mut self, ip: IpAddr) {
+        self.addr = SocketAddr::new(ip, self.addr.port());
+    }
+
     pub(crate) fn base(&self) -> SocketAddr {
         self.base.unwrap_or(self.addr)
     }
@@ -397,6 +499,10 @@ impl Candidate {
         self.kind
     }
 
+    pub(crate) fn set_type_preferences(&mut self, v: TypePreferences) {
+        self.type_preferences = Some(v);
+    }
+
     pub(crate) fn set_local_preference(&mut self, v: u32) {
         self.local_preference = Some(v);
     }
@@ -423,16 +529,20 @@ impl Candidate {
 
     /// Generates a candidate attribute string.
     pub fn to_sdp_string(&self) -> String {
+        let ip = self.addr.ip().to_string();
         let mut s = format!(
             "candidate:{} {} {} {} {} {} typ {}",
             self.foundation(),
             self.component_id,
             self.proto,
             self.prio(),
-            self.addr.ip(),
+            self.mdns_name.as_deref().unwrap_or(&ip),
             self.addr.port(),
             self.kind
         );
+        if let Some(tcptype) = &self.tcptype {
+            s.push_str(&format!(" tcptype {}", tcptype));
+        }
         if let Some(raddr) = &self.raddr {
             s.push_str(&format!(" raddr {} rport {}", raddr.ip(), raddr.port()))
         }
@@ -480,6 +590,146 @@ impl fmt::Display for CandidateKind {
     }
 }
 
+/// Type of TCP candidate (RFC 6544).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TcpType {
+    /// Opens outgoing connections, but does not accept incoming.
+    Active,
+    /// Accepts incoming connections, but does not open outgoing.
+    Passive,
+    /// Simultaneous-open, where both sides try to open a connection at the same time.
+    So,
+}
+
+impl TcpType {
+    /// RFC 6544 Sec 4.2, the local preference for TCP candidates is
+    ///
+    /// local pref = (2^13) * direction-pref + other-pref
+    ///
+    /// where direction-pref for host candidates is 6 for active, 4 for passive
+    /// and 2 for simultaneous-open, and other-pref is 0 - 8191.
+    pub(crate) fn local_preference(&self, other_pref: u32) -> u32 {
+        let direction_pref = match self {
+            TcpType::Active => 6,
+            TcpType::Passive => 4,
+            TcpType::So => 2,
+        };
+
+        direction_pref << 13 | other_pref.min(8_191)
+    }
+
+    /// Whether a local candidate of this type can be paired with a remote of `remote` type.
+    ///
+    /// A passive local candidate is not paired with an active remote, since we can't
+    /// open a connection from it. Such pairs are formed from incoming connectivity checks.
+    pub(crate) fn can_pair(&self, remote: TcpType) -> bool {
+        matches!(
+            (self, remote),
+            (TcpType::Active, TcpType::Passive) | (TcpType::So, TcpType::So)
+        )
+    }
+}
+
+impl fmt::Display for TcpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x = match self {
+            TcpType::Active => "active",
+            TcpType::Passive => "passive",
+            TcpType::So => "so",
+        };
+        write!(f, "{x}")
+    }
+}
+
+impl TryFrom<&str> for TcpType {
+    type Error = IceError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "active" => Ok(TcpType::Active),
+            "passive" => Ok(TcpType::Passive),
+            "so" => Ok(TcpType::So),
+            _ => Err(IceError::BadCandidate(format!("invalid tcptype: {value}"))),
+        }
+    }
+}
+
+/// Type preferences used for the priority of local candidates (RFC 8445 Sec 5.1.2.2).
+///
+/// The type preference is an integer from 0 to 126, where a higher value means the candidate
+/// type (and protocol) is preferred. It is the most significant part of the candidate
+/// priority, which in turn decides the candidate pair priority.
+///
+/// ```
+/// # use str0m::{CandidateKind, TypePreferences};
+/// # use str0m::net::Protocol;
+/// // Prefer TURN over UDP to STUN.
+/// let prefs = TypePreferences::new().set(CandidateKind::Relayed, Protocol::Udp, 105);
+/// assert_eq!(prefs.get(CandidateKind::Relayed, Protocol::Udp), 105);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypePreferences {
+    // Indexed by kind and protocol.
+    table: [[u8; 4]; 4],
+}
+
+impl TypePreferences {
+    /// Creates the default type preferences.
+    ///
+    /// Per RFC 8445, the RECOMMENDED values are 126 for host candidates, 110 for
+    /// peer reflexive, 100 for server reflexive and 0 for relayed candidates. The
+    /// variations for non-UDP protocols are taken from libwebrtc:
+    /// <https://webrtc.googlesource.com/src/+/refs/heads/main/p2p/base/port.h#68>
+    pub fn new() -> Self {
+        //        udp  tcp  ssltcp tls
+        TypePreferences {
+            table: [
+                [126, 90, 90, 90],    // host
+                [110, 80, 80, 80],    // prflx
+                [100, 100, 100, 100], // srflx
+                [2, 1, 0, 0],         // relay
+            ],
+        }
+    }
+
+    /// Set the type preference for a kind of candidate and protocol.
+    ///
+    /// Values above 126 are capped to 126.
+    pub fn set(mut self, kind: CandidateKind, proto: Protocol, pref: u32) -> Self {
+        self.table[Self::kind_idx(kind)][Self::proto_idx(proto)] = pref.min(126) as u8;
+        self
+    }
+
+    /// The type preference for a kind of candidate and protocol.
+    pub fn get(&self, kind: CandidateKind, proto: Protocol) -> u32 {
+        self.table[Self::kind_idx(kind)][Self::proto_idx(proto)] as u32
+    }
+
+    fn kind_idx(kind: CandidateKind) -> usize {
+        match kind {
+            CandidateKind::Host => 0,
+            CandidateKind::PeerReflexive => 1,
+            CandidateKind::ServerReflexive => 2,
+            CandidateKind::Relayed => 3,
+        }
+    }
+
+    fn proto_idx(proto: Protocol) -> usize {
+        match proto {
+            Protocol::Udp => 0,
+            Protocol::Tcp => 1,
+            Protocol::SslTcp => 2,
+            Protocol::Tls => 3,
+        }
+    }
+}
+
+impl Default for TypePreferences {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn is_valid_ip(ip: IpAddr) -> bool {
     match ip {
         IpAddr::V4(v) => {
@@ -631,6 +881,70 @@ mod tests {
         assert_eq!(candidate.addr().to_string(), "1.2.3.4:9876");
     }
 
+    #[test]
+    fn tcptype() {
+        let s = "candidate:1 1 tcp 1518280447 1.2.3.4 9 typ host tcptype active";
+        let candidate = Candidate::from_sdp_string(s).unwrap();
+        assert_eq!(candidate.proto(), Protocol::Tcp);
+        assert_eq!(candidate.tcptype(), Some(TcpType::Active));
+        assert_eq!(candidate.to_string(), s);
+
+        let s = "candidate:1 1 tcp 1518280447 1.2.3.4 9 typ host tcptype bogus";
+        assert!(Candidate::from_sdp_string(s).is_err());
+
+        let addr = "1.2.3.4:9876".parse().unwrap();
+        let passive = Candidate::host(addr, "tcp")
+            .unwrap()
+            .with_tcptype(TcpType::Passive)
+            .unwrap();
+        assert!(passive.to_string().ends_with(" typ host tcptype passive"));
+
+        // Active is preferred over passive, which is preferred over so.
+        let active = passive.clone().with_tcptype(TcpType::Active).unwrap();
+        let so = passive.clone().with_tcptype(TcpType::So).unwrap();
+        assert!(active.prio() > passive.prio());
+        assert!(passive.prio() > so.prio());
+
+        let udp = Candidate::host(addr, "udp").unwrap();
+        assert!(udp.with_tcptype(TcpType::Active).is_err());
+    }
+
+    #[test]
+    fn mdns_name() {
+        let s = "candidate:1 1 udp 2122260223 4a6b1c2d-9e8f.local 5000 typ host";
+        let mut candidate = Candidate::from_sdp_string(s).unwrap();
+        assert_eq!(candidate.mdns_name(), Some("4a6b1c2d-9e8f.local"));
+        assert!(candidate.is_unresolved());
+        assert_eq!(candidate.to_string(), s);
+
+        candidate.resolve("1.2.3.4".parse().unwrap());
+        assert!(!candidate.is_unresolved());
+        assert_eq!(candidate.addr().to_string(), "1.2.3.4:5000");
+        assert_eq!(candidate.to_string(), s);
+
+        // Only .local names.
+        let s = "candidate:1 1 udp 2122260223 example.com 5000 typ host";
+        assert!(Candidate::from_sdp_string(s).is_err());
+    }
+
+    #[test]
+    fn type_preferences() {
+        let addr = "1.2.3.4:5000".parse().unwrap();
+        let mut host = Candidate::host(addr, "udp").unwrap();
+        let mut relay = Candidate::relayed(addr, "udp").unwrap();
+        assert!(host.prio() > relay.prio());
+
+        // Capped at 126.
+        let prefs = TypePreferences::new().set(CandidateKind::Relayed, Protocol::Udp, 200);
+        assert_eq!(prefs.get(CandidateKind::Relayed, Protocol::Udp), 126);
+        assert_eq!(prefs.get(CandidateKind::Host, Protocol::Udp), 126);
+
+        let prefs = prefs.set(CandidateKind::Host, Protocol::Udp, 100);
+        host.set_type_preferences(prefs);
+        relay.set_type_preferences(prefs);
+        assert!(relay.prio() > host.prio());
+    }
+
     #[test]
     fn bad_candidate() {
         let s = "candidate:12344 bad value";
@@ -0,0 +1,40 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait for a remote mDNS candidate to be resolved.
+pub(crate) const MDNS_RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type ResolveFn = Arc<dyn Fn(&str) -> Option<IpAddr> + Send + Sync + RefUnwindSafe>;
+
+type RegisterFn = Arc<dyn Fn(IpAddr) -> Option<String> + Send + Sync + RefUnwindSafe>;
+
+/// Hooks for mDNS (`.local`) host candidates.
+#[derive(Clone, Default)]
+pub(crate) struct MdnsHooks {
+    /// Resolves remote names when they are added.
+    pub resolve: Option<ResolveFn>,
+    /// Registers a name for a local host candidate IP.
+    pub register: Option<RegisterFn>,
+}
+
+impl MdnsHooks {
+    pub fn resolve(&self, name: &str) -> Option<IpAddr> {
+        self.resolve.as_ref().and_then(|f| f(name))
+    }
+
+    pub fn register(&self, ip: IpAddr) -> Option<String> {
+        self.register.as_ref().and_then(|f| f(ip))
+    }
+}
+
+impl fmt::Debug for MdnsHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MdnsHooks")
+            .field("resolve", &self.resolve.is_some())
+            .field("register", &self.register.is_some())
+            .finish()
+    }
+}
@@ -4,12 +4,16 @@
 use thiserror::Error;
 
 mod agent;
-pub use agent::{IceAgent, IceAgentEvent, IceConnectionState, IceCreds};
+pub use agent::{IceAgent, IceAgentEvent, IceConnectionState, IceCreds, NominationStrategy};
 
 mod candidate;
-pub use candidate::{Candidate, CandidateKind};
+pub use candidate::{Candidate, CandidateKind, TcpType, TypePreferences};
+
+mod mdns;
+pub(crate) use mdns::MdnsHooks;
 
 mod pair;
+pub(crate) use pair::CheckState;
 
 /// Errors from the ICE agent.
 #[allow(missing_docs)]
@@ -98,6 +102,11 @@ mod test {
         Candidate::relayed(sock(s), proto).unwrap()
     }
 
+    pub fn mdns(name: &str, port: u16) -> Candidate {
+        let s = format!("candidate:1 1 udp 2130706431 {name} {port} typ host");
+        Candidate::from_sdp_string(&s).unwrap()
+    }
+
     /// Transform the socket to rig different test scenarios.
     ///
     /// * either port 9999 -> closed (packets dropped)
@@ -194,29 +203,124 @@ mod test {
         let (d, e) = a2.events.last().unwrap();
         assert_last_event(d, e);
 
-        assert_eq!(
-            a1.stats(),
-            IceAgentStats {
-                bind_request_sent: 11,
-                bind_success_recv: 2,
-                bind_request_recv: 11,
-                discovered_recv_count: 1,
-                nomination_send_count: 1,
+        // Consent expired on both sides.
+        assert!(a1.has_event(|e| *e == IceAgentEvent::ConsentExpired));
+        assert!(a2.has_event(|e| *e == IceAgentEvent::ConsentExpired));
+
+        // The consent checks are randomized, every 4-6 seconds for 30 seconds.
+        let s1 = a1.stats();
+        let s2 = a2.stats();
+        assert!((7..=10).contains(&s1.bind_request_sent), "{s1:?}");
+        assert!((5..=8).contains(&s2.bind_request_sent), "{s2:?}");
+        assert_eq!(s1.bind_request_recv, s2.bind_request_sent);
+        assert_eq!(s2.bind_request_recv, 2);
+        assert_eq!(s1.bind_success_recv, 2);
+        assert_eq!(s2.bind_success_recv, 1);
+        assert_eq!(s1.nomination_send_count, 1);
+        assert_eq!(s2.nomination_send_count, 1);
+    }
+
+    #[test]
+    pub fn consent_expired() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
             }
-        );
+            progress(&mut a1, &mut a2);
+        }
+
+        // Consent checks every 4-6 seconds.
+        let sent = a1.stats().bind_request_sent;
+        let until = a1.time + Duration::from_secs(60);
+        while a1.time < until {
+            a1.progress_count %= 2;
+            a2.progress_count %= 2;
+            progress(&mut a1, &mut a2);
+        }
+        let checks = a1.stats().bind_request_sent - sent;
+        assert!((10..=16).contains(&checks), "{checks} consent checks");
+        assert!(a1.state().is_connected());
+
+        // The remote stops answering.
+        a2.drop_sent_packets = true;
+        let dropped = a1.time - a1.start_time;
 
+        while !a1.has_event(|e| *e == IceAgentEvent::ConsentExpired) {
+            a1.progress_count %= 2;
+            a2.progress_count %= 2;
+            progress(&mut a1, &mut a2);
+        }
+
+        let (expired, _) = a1
+            .events
+            .iter()
+            .find(|(_, e)| *e == IceAgentEvent::ConsentExpired)
+            .unwrap();
+        assert!(*expired - dropped >= Duration::from_secs(30) - Duration::from_secs(6));
+        assert!(*expired - dropped <= Duration::from_secs(30) + Duration::from_secs(3));
+
+        // No other pair to fail over to.
         assert_eq!(
-            a2.stats(),
-            IceAgentStats {
-                bind_request_sent: 11,
-                bind_success_recv: 2,
-                bind_request_recv: 2,
-                discovered_recv_count: 1,
-                nomination_send_count: 1,
-            }
+            a1.events.last().unwrap().1,
+            IceAgentEvent::IceConnectionStateChange(IceConnectionState::Disconnected)
         );
     }
 
+    #[test]
+    pub fn ice_restart_previous_consent() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        // A restart that never gets new candidates.
+        a1.ice_restart(IceCreds::new(), false);
+        let restarted = a1.time - a1.start_time;
+
+        while !a1.has_event(|e| *e == IceAgentEvent::ConsentExpired) {
+            a1.progress_count %= 2;
+            a2.progress_count %= 2;
+            progress(&mut a1, &mut a2);
+        }
+
+        // The pair from before the restart could be used until its consent ran out.
+        let (expired, _) = a1
+            .events
+            .iter()
+            .find(|(_, e)| *e == IceAgentEvent::ConsentExpired)
+            .unwrap();
+        assert!(*expired - restarted >= Duration::from_secs(30) - Duration::from_secs(6));
+        assert!(*expired - restarted <= Duration::from_secs(30) + Duration::from_secs(3));
+    }
+
     #[test]
     pub fn host_host() {
         let mut a1 = TestAgent::new(info_span!("L"));
@@ -243,7 +347,7 @@ mod test {
             IceAgentStats {
                 bind_request_sent: 2,
                 bind_success_recv: 2,
-                bind_request_recv: 2,
+                bind_request_recv: 1,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
             }
@@ -252,8 +356,8 @@ mod test {
         assert_eq!(
             a2.stats(),
             IceAgentStats {
-                bind_request_sent: 3,
-                bind_success_recv: 2,
+                bind_request_sent: 2,
+                bind_success_recv: 1,
                 bind_request_recv: 2,
                 discovered_recv_count: 1,
                 nomination_send_count: 1,
@@ -614,6 +718,251 @@ mod test {
         }
     }
 
+    #[test]
+    pub fn ice_lite_full_nominates() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        a2.set_ice_lite(true);
+
+        // ice-lite only has host candidates.
+        assert!(!a2.add_local_candidate(srflx("3.3.3.3:1000", "2.2.2.2:1000", "udp")));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+
+        a1.set_controlling(true);
+        a2.set_controlling(true);
+        assert!(!a2.controlling());
+
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        assert!(a1.has_event(|e| matches!(e, IceAgentEvent::NominatedSend { .. })));
+        assert!(a2.has_event(|e| matches!(e, IceAgentEvent::NominatedSend { .. })));
+        assert_eq!(a2.stats().bind_request_sent, 0);
+    }
+
+    #[test]
+    pub fn ice_lite_role_conflict() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        a2.set_ice_lite(true);
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+
+        // Both sides think they are controlled. The ice-lite side
+        // answers with 487 and the full agent takes over.
+        a1.set_controlling(false);
+        a2.set_controlling(true);
+        assert!(!a2.controlling());
+
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        assert!(a1.controlling());
+        assert!(!a2.controlling());
+        assert!(a1.has_event(|e| matches!(e, IceAgentEvent::NominatedSend { .. })));
+        assert!(a2.has_event(|e| matches!(e, IceAgentEvent::NominatedSend { .. })));
+    }
+
+    #[test]
+    pub fn role_conflict_both_controlling() {
+        role_conflict(true);
+    }
+
+    #[test]
+    pub fn role_conflict_both_controlled() {
+        role_conflict(false);
+    }
+
+    fn role_conflict(controlling: bool) {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+
+        a1.set_controlling(controlling);
+        a2.set_controlling(controlling);
+
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        // The tie breakers decided who is controlling.
+        assert_ne!(a1.controlling(), a2.controlling());
+        assert!(a1.has_event(|e| matches!(e, IceAgentEvent::NominatedSend { .. })));
+        assert!(a2.has_event(|e| matches!(e, IceAgentEvent::NominatedSend { .. })));
+    }
+
+    #[test]
+    pub fn role_conflict_known_tie_breakers() {
+        for controlling in [true, false] {
+            for (t1, t2) in [(2, 1), (1, 2)] {
+                let mut a1 = TestAgent::new(info_span!("L"));
+                let mut a2 = TestAgent::new(info_span!("R"));
+
+                let c1 = host("1.1.1.1:1000", "udp");
+                a1.add_local_candidate(c1.clone());
+                a2.add_remote_candidate(c1);
+                let c2 = host("2.2.2.2:1000", "udp");
+                a2.add_local_candidate(c2.clone());
+                a1.add_remote_candidate(c2);
+
+                a1.set_controlling(controlling);
+                a2.set_controlling(controlling);
+                a1.set_control_tie_breaker(t1);
+                a2.set_control_tie_breaker(t2);
+
+                loop {
+                    if a1.state().is_connected() && a2.state().is_connected() {
+                        break;
+                    }
+                    progress(&mut a1, &mut a2);
+                }
+
+                // The larger tie breaker is controlling, regardless of where we started.
+                assert_eq!(a1.controlling(), t1 > t2);
+                assert_eq!(a2.controlling(), t2 > t1);
+
+                // Converged straight away by retrying after switching.
+                assert!(a1.stats().bind_request_sent + a2.stats().bind_request_sent <= 6);
+            }
+        }
+    }
+
+    /// L has a host and a relay candidate, but the host pair is lossy until told otherwise.
+    fn lossy_host_pair(strategy: NominationStrategy, renomination: bool) -> (TestAgent, TestAgent) {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        a1.set_nomination_strategy(strategy);
+        a1.set_renomination(renomination);
+
+        for c in [host("1.1.1.1:1000", "udp"), relay("5.5.5.5:1000", "udp")] {
+            a1.add_local_candidate(c.clone());
+            a2.add_remote_candidate(c);
+        }
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        a1.drop_sent_from = Some(sock("1.1.1.1:1000"));
+
+        // Until the relay pair works.
+        while a1.stats().bind_success_recv == 0 {
+            progress(&mut a1, &mut a2);
+        }
+
+        (a1, a2)
+    }
+
+    fn nominated_sources(a: &TestAgent) -> Vec<SocketAddr> {
+        a.events
+            .iter()
+            .filter_map(|(_, e)| match e {
+                IceAgentEvent::NominatedSend { source, .. } => Some(*source),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn progress_for(a1: &mut TestAgent, a2: &mut TestAgent, dur: Duration) {
+        let until = a1.time + dur;
+        while a1.time < until {
+            a1.progress_count %= 2;
+            a2.progress_count %= 2;
+            progress(a1, a2);
+        }
+    }
+
+    #[test]
+    pub fn delayed_nomination_waits_for_host() {
+        let strategy = NominationStrategy::Delayed(Duration::from_secs(3));
+        let (mut a1, mut a2) = lossy_host_pair(strategy, true);
+
+        // Waiting for the host pair.
+        progress_for(&mut a1, &mut a2, Duration::from_millis(500));
+        assert!(nominated_sources(&a1).is_empty());
+
+        a1.drop_sent_from = None;
+
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        // The relay was never nominated.
+        assert_eq!(nominated_sources(&a1), vec![sock("1.1.1.1:1000")]);
+    }
+
+    #[test]
+    pub fn delayed_nomination_deadline() {
+        let strategy = NominationStrategy::Delayed(Duration::from_secs(1));
+        let (mut a1, mut a2) = lossy_host_pair(strategy, true);
+
+        progress_for(&mut a1, &mut a2, Duration::from_millis(1500));
+
+        // The host pair didn't make it in time.
+        assert_eq!(nominated_sources(&a1), vec![sock("5.5.5.5:1000")]);
+    }
+
+    #[test]
+    pub fn eager_nomination_renominates() {
+        let (mut a1, mut a2) = lossy_host_pair(NominationStrategy::Eager, true);
+        progress(&mut a1, &mut a2);
+        assert_eq!(nominated_sources(&a1), vec![sock("5.5.5.5:1000")]);
+
+        a1.drop_sent_from = None;
+        progress_for(&mut a1, &mut a2, Duration::from_secs(5));
+
+        let nominated = vec![sock("5.5.5.5:1000"), sock("1.1.1.1:1000")];
+        assert_eq!(nominated_sources(&a1), nominated);
+    }
+
+    #[test]
+    pub fn eager_nomination_without_renomination() {
+        let (mut a1, mut a2) = lossy_host_pair(NominationStrategy::Eager, false);
+
+        a1.drop_sent_from = None;
+        progress_for(&mut a1, &mut a2, Duration::from_secs(5));
+
+        // The host pair works, but we stay on the relay.
+        assert_eq!(nominated_sources(&a1), vec![sock("5.5.5.5:1000")]);
+        assert!(a1.state().is_connected());
+    }
+
     #[test]
     pub fn trickle_host_host() {
         let mut a1 = TestAgent::new(info_span!("L"));
@@ -646,6 +995,162 @@ mod test {
         }
     }
 
+    #[test]
+    pub fn trickle_remote_relay_selected() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+
+        // The host candidate of R arrives first, but no traffic is possible.
+        let c2 = host("2.2.2.2:9999", "udp");
+        a2.add_local_candidate(c2.clone());
+        a1.add_remote_candidate(c2);
+
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        while a1.time - a1.start_time < Duration::from_secs(2) {
+            a1.progress_count = 0;
+            a2.progress_count = 0;
+            progress(&mut a1, &mut a2);
+        }
+        assert!(!a1.state().is_connected());
+
+        // The relay of R trickles in 2s later.
+        let c3 = relay("5.5.5.5:1000", "udp");
+        a2.add_local_candidate(c3.clone());
+        a1.add_remote_candidate(c3);
+
+        a1.progress_count = 0;
+        a2.progress_count = 0;
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+
+        assert!(a1.has_event(|e| {
+            matches!(e, IceAgentEvent::NominatedSend { destination, .. } if destination == &sock("5.5.5.5:1000"))
+        }));
+
+        // Without end-of-candidates, more might come.
+        assert_eq!(a1.state(), IceConnectionState::Connected);
+
+        a1.agent.set_remote_end_of_candidates();
+
+        loop {
+            if a1.state() == IceConnectionState::Completed {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+    }
+
+    #[test]
+    pub fn end_of_candidates_without_candidates() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        a1.add_local_candidate(host("1.1.1.1:1000", "udp"));
+        a2.add_local_candidate(host("2.2.2.2:1000", "udp"));
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        // Waiting for remote candidates to trickle in.
+        for _ in 0..10 {
+            progress(&mut a1, &mut a2);
+        }
+        assert_eq!(a1.state(), IceConnectionState::Checking);
+
+        // There will be none.
+        a1.agent.set_remote_end_of_candidates();
+        progress(&mut a1, &mut a2);
+        progress(&mut a1, &mut a2);
+
+        assert_eq!(a1.state(), IceConnectionState::Disconnected);
+    }
+
+    #[test]
+    pub fn mdns_resolved_later() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        a1.add_local_candidate(host("1.1.1.1:1000", "udp"));
+        a2.add_local_candidate(host("2.2.2.2:1000", "udp"));
+        a1.add_remote_candidate(mdns("r.local", 1000));
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        // Nothing to pair until resolved.
+        loop {
+            if a1.has_event(|e| *e == IceAgentEvent::ResolveMdns("r.local".into())) {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+        assert!(a1.agent.remote_candidates().is_empty());
+        assert!(!a1.state().is_connected());
+
+        a1.agent.resolve_mdns("r.local", "2.2.2.2".parse().unwrap());
+
+        let c = &a1.agent.remote_candidates()[0];
+        assert_eq!(c.addr(), sock("2.2.2.2:1000"));
+        assert_eq!(c.mdns_name(), Some("r.local"));
+
+        a1.progress_count = 0;
+        a2.progress_count = 0;
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+    }
+
+    #[test]
+    pub fn mdns_unresolved_times_out() {
+        let mut a1 = TestAgent::new(info_span!("L"));
+        let mut a2 = TestAgent::new(info_span!("R"));
+
+        let c1 = host("1.1.1.1:1000", "udp");
+        a1.add_local_candidate(c1.clone());
+        a2.add_remote_candidate(c1);
+        let c2 = host("2.2.2.2:1000", "udp");
+        a2.add_local_candidate(c2.clone());
+
+        // The name is never resolved.
+        a1.add_remote_candidate(mdns("x.local", 2000));
+        a1.add_remote_candidate(c2);
+        a1.agent.set_remote_end_of_candidates();
+        a1.set_controlling(true);
+        a2.set_controlling(false);
+
+        // The other pair isn't held up.
+        loop {
+            if a1.state().is_connected() && a2.state().is_connected() {
+                break;
+            }
+            progress(&mut a1, &mut a2);
+        }
+        assert_eq!(a1.state(), IceConnectionState::Connected);
+
+        loop {
+            if a1.state() == IceConnectionState::Completed {
+                break;
+            }
+            a1.progress_count = 0;
+            a2.progress_count = 0;
+            progress(&mut a1, &mut a2);
+        }
+
+        assert!(a1.time - a1.start_time >= Duration::from_secs(10));
+        assert_eq!(a1.agent.remote_candidates().len(), 1);
+    }
+
     #[test]
     pub fn candidate_pair_of_same_kind_does_not_get_nominated() {
         let mut a1 = TestAgent::new(info_span!("L"));
@@ -701,6 +1206,7 @@ mod test {
         pub progress_count: u64,
         pub time: Instant,
         pub drop_sent_packets: bool,
+        pub drop_sent_from: Option<SocketAddr>,
     }
 
     impl TestAgent {
@@ -714,6 +1220,7 @@ mod test {
                 progress_count: 0,
                 time: now,
                 drop_sent_packets: false,
+                drop_sent_from: None,
             }
         }
 
@@ -740,7 +1247,7 @@ mod test {
 
             // rewrite receive with test transforms, and potentially drop the packet.
             if let Some((source, destination)) = transform(trans.source, trans.destination) {
-                if f.drop_sent_packets {
+                if f.drop_sent_packets || f.drop_sent_from == Some(trans.source) {
                     // drop packet
                     t.span.in_scope(|| t.agent.handle_timeout(t.time));
                 } else {
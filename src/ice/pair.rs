@@ -4,10 +4,23 @@ use std::time::{Duration, Instant};
 
 use crate::io::{stun_resend_delay, STUN_MAX_RETRANS};
 use crate::io::{Id, TransId, STUN_MAX_RTO_MILLIS};
+use crate::util::NonCryptographicRng;
 use crate::Candidate;
 
 const MIN_TIMEOUT: Duration = Duration::from_millis(STUN_MAX_RTO_MILLIS);
 
+/// Consent (RFC 7675) on the selected pair expires after this long without a response.
+pub(crate) const CONSENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keepalive for valid pairs that are not selected. Slower than the consent checks, but
+/// often enough to keep NAT bindings open in case we need to fail over.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Consent checks are sent at a randomized interval of 4-6 seconds.
+fn consent_interval() -> Duration {
+    Duration::from_millis(4000 + (NonCryptographicRng::f32() * 2000.0) as u64)
+}
+
 // When running ice-lite we need a cutoff when we consider the remote definitely gone.
 const RECENT_BINDING_REQUEST: Duration = Duration::from_secs(15);
 
@@ -50,6 +63,34 @@ pub struct CandidatePair {
 
     /// State of nomination for this candidate pair.
     nomination_state: NominationState,
+
+    /// Whether this is the pair nominated for sending, which makes the binding
+    /// requests consent checks.
+    selected: bool,
+
+    /// Last time we got a binding response. This is when consent was last granted.
+    last_response: Option<Instant>,
+
+    /// Number of binding requests sent on this pair.
+    requests_sent: u64,
+
+    /// Number of binding responses received on this pair.
+    responses_received: u64,
+
+    /// Round trip time of the last binding request that got a response.
+    rtt: Option<Duration>,
+
+    /// Smoothed round trip time, like SRTT in RFC 6298.
+    rtt_ewma: Option<Duration>,
+
+    /// Bytes sent while the pair is selected.
+    bytes_sent: u64,
+
+    /// Bytes received while the pair is selected.
+    bytes_received: u64,
+
+    /// When the pair was first selected after a nomination.
+    nominated_at: Option<Instant>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -104,6 +145,9 @@ pub struct BindingAttempt {
 
     /// Whether the binding attempt is nominated.
     nominated: bool,
+
+    /// Whether we were controlling when sending the binding request.
+    controlling: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -167,6 +211,46 @@ impl CandidatePair {
         self.prio
     }
 
+    pub fn set_prio(&mut self, prio: u64) {
+        self.prio = prio;
+    }
+
+    pub fn set_selected(&mut self, now: Instant, selected: bool) {
+        if self.selected != selected {
+            self.selected = selected;
+            self.cached_next_attempt_time = None;
+        }
+        if selected && self.nominated_at.is_none() {
+            self.nominated_at = Some(now);
+        }
+    }
+
+    pub fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    /// Whether we do consent checks on this pair. This starts with the first response
+    /// after it was selected.
+    fn has_consent(&self) -> bool {
+        self.selected && self.last_response.is_some()
+    }
+
+    /// When consent for the selected pair expires, unless refreshed.
+    pub fn consent_expires(&self) -> Option<Instant> {
+        self.last_response
+            .filter(|_| self.has_consent())
+            .map(|t| t + CONSENT_TIMEOUT)
+    }
+
+    /// Whether consent for the selected pair has expired.
+    pub fn is_consent_expired(&self, now: Instant) -> bool {
+        if !self.has_consent() {
+            return false;
+        }
+        let last = self.last_response.unwrap();
+        now >= last + CONSENT_TIMEOUT
+    }
+
     pub fn state(&self) -> CheckState {
         self.state
     }
@@ -206,7 +290,7 @@ impl CandidatePair {
     /// Records a new binding request attempt.
     ///
     /// Returns the transaction id to use in the STUN message.
-    pub fn new_attempt(&mut self, now: Instant) -> TransId {
+    pub fn new_attempt(&mut self, now: Instant, controlling: bool) -> TransId {
         // calculate a new time
         self.cached_next_attempt_time = None;
 
@@ -220,9 +304,11 @@ impl CandidatePair {
             request_sent: now,
             respone_recv: None,
             nominated: self.is_nominated(),
+            controlling,
         };
 
         self.binding_attempts.push_back(attempt);
+        self.requests_sent += 1;
 
         // Never keep more than STUN_MAX_RETRANS attempts.
         while self.binding_attempts.len() > STUN_MAX_RETRANS {
@@ -265,6 +351,15 @@ impl CandidatePair {
             .expect("Binding request attempt");
 
         attempt.respone_recv = Some(now);
+        self.last_response = Some(now);
+        self.responses_received += 1;
+
+        let rtt = now - attempt.request_sent;
+        self.rtt = Some(rtt);
+        self.rtt_ewma = Some(match self.rtt_ewma {
+            Some(v) => (v * 7 + rtt) / 8,
+            None => rtt,
+        });
 
         if attempt.nominated && self.nomination_state == NominationState::Attempt {
             self.nomination_state = NominationState::Success;
@@ -283,6 +378,22 @@ impl CandidatePair {
         trace!("Recorded binding response: {:?}", self);
     }
 
+    /// Removes a binding request attempt that failed with a role conflict, so it is
+    /// retried without backoff.
+    ///
+    /// Returns whether we were controlling when sending it.
+    pub fn remove_role_conflict_attempt(&mut self, trans_id: TransId) -> Option<bool> {
+        let idx = self
+            .binding_attempts
+            .iter()
+            .position(|b| b.trans_id == trans_id)?;
+        let attempt = self.binding_attempts.remove(idx)?;
+
+        self.cached_next_attempt_time = None;
+
+        Some(attempt.controlling)
+    }
+
     /// The time of the last binding request attempt.
     ///
     /// `None` means there has been no attempts.
@@ -315,6 +426,21 @@ impl CandidatePair {
         let next = if matches!(self.nomination_state, NominationState::Nominated) {
             // Cheating a bit to make the nomination "skip the queue".
             now.checked_sub(Duration::from_secs(60)).unwrap()
+        } else if let Some(last) = self.last_attempt_time().filter(|_| self.has_consent()) {
+            // A consent check every 4-6 seconds, regardless of whether the previous ones
+            // were answered. Any other check on the pair, such as one triggered by a route
+            // change, postpones it.
+            let next = last + consent_interval();
+            self.cached_next_attempt_time = Some(next);
+            return next;
+        } else if let Some(last) = self
+            .last_attempt_time()
+            .filter(|_| self.state == CheckState::Succeeded && self.unanswered().is_none())
+        {
+            // Keepalive on valid pairs that are not selected.
+            let next = last + KEEPALIVE_INTERVAL;
+            self.cached_next_attempt_time = Some(next);
+            return next;
         } else if let Some(last) = self.last_attempt_time() {
             // When we have unanswered for longer than STUN_MAX_RTO_MILLIS / 2, start
             // checking more often.
@@ -342,10 +468,19 @@ impl CandidatePair {
         at_least
     }
 
+    /// Make the next binding request go out at `now`, rather than on the regular schedule.
+    pub fn restart_keepalive(&mut self, now: Instant) {
+        self.cached_next_attempt_time = Some(now);
+    }
+
     /// Tells if this candidate pair is still possible to use for connectivity.
     ///
     /// Returns `false` if the candidate has failed.
     pub fn is_still_possible(&self, now: Instant) -> bool {
+        if self.has_consent() {
+            return !self.is_consent_expired(now);
+        }
+
         let attempts = self.binding_attempts.len();
         let unanswered = self.unanswered().map(|b| b.0).unwrap_or(0);
 
@@ -360,6 +495,42 @@ impl CandidatePair {
         }
     }
 
+    pub fn requests_sent(&self) -> u64 {
+        self.requests_sent
+    }
+
+    pub fn responses_received(&self) -> u64 {
+        self.responses_received
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    pub fn rtt_ewma(&self) -> Option<Duration> {
+        self.rtt_ewma
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    pub fn nominated_at(&self) -> Option<Instant> {
+        self.nominated_at
+    }
+
+    pub fn add_bytes_sent(&mut self, n: u64) {
+        self.bytes_sent += n;
+    }
+
+    pub fn add_bytes_received(&mut self, n: u64) {
+        self.bytes_received += n;
+    }
+
     pub(crate) fn copy_remote_binding_requests(&mut self, other: &CandidatePair) {
         self.remote_binding_requests = other.remote_binding_requests;
         self.remote_binding_request_time = other.remote_binding_request_time;
@@ -415,3 +586,61 @@ impl fmt::Debug for CandidatePair {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn valid_pair(now: Instant) -> CandidatePair {
+        let mut pair = CandidatePair::new(0, 0, 1);
+        let trans_id = pair.new_attempt(now, true);
+        pair.record_binding_response(now, trans_id, 0);
+        assert_eq!(pair.state(), CheckState::Succeeded);
+        pair
+    }
+
+    #[test]
+    fn consent_checks_on_selected_pair() {
+        let now = Instant::now();
+        let mut pair = valid_pair(now);
+        pair.set_selected(now, true);
+
+        let next = pair.next_binding_attempt(now);
+        assert!(next >= now + Duration::from_secs(4));
+        assert!(next <= now + Duration::from_secs(6));
+
+        // Unanswered checks don't make the pair fail before consent expires.
+        for i in 1..=6 {
+            pair.new_attempt(now + Duration::from_secs(i * 5), true);
+        }
+        assert!(!pair.is_consent_expired(now + Duration::from_secs(29)));
+        assert!(pair.is_still_possible(now + Duration::from_secs(29)));
+        assert!(pair.is_consent_expired(now + CONSENT_TIMEOUT));
+        assert!(!pair.is_still_possible(now + CONSENT_TIMEOUT));
+    }
+
+    #[test]
+    fn round_trip_time() {
+        let now = Instant::now();
+        let mut pair = valid_pair(now);
+        assert_eq!(pair.rtt(), Some(Duration::ZERO));
+
+        let t = now + Duration::from_secs(1);
+        let trans_id = pair.new_attempt(t, true);
+        pair.record_binding_response(t + Duration::from_millis(80), trans_id, 0);
+
+        assert_eq!(pair.rtt(), Some(Duration::from_millis(80)));
+        assert_eq!(pair.rtt_ewma(), Some(Duration::from_millis(10)));
+        assert_eq!(pair.requests_sent(), 2);
+        assert_eq!(pair.responses_received(), 2);
+    }
+
+    #[test]
+    fn keepalive_on_valid_pair() {
+        let now = Instant::now();
+        let mut pair = valid_pair(now);
+
+        assert_eq!(pair.next_binding_attempt(now), now + KEEPALIVE_INTERVAL);
+        assert!(!pair.is_consent_expired(now + CONSENT_TIMEOUT));
+    }
+}
@@ -0,0 +1,103 @@
+use std::io;
+
+/// Framing of packets over a TCP stream (RFC 4571).
+///
+/// Each packet on the stream is prefixed with its length as a 16 bit big endian
+/// integer. Use [`Rfc4571::frame`] for the contents of a [`Transmit`][crate::net::Transmit]
+/// before writing it to the TCP connection, and one `Rfc4571` per connection to split
+/// the incoming bytes into packets for [`Receive`][crate::net::Receive].
+///
+/// ```
+/// # use str0m::net::Rfc4571;
+/// let mut stream = Rfc4571::frame(b"hello").unwrap();
+/// stream.extend(Rfc4571::frame(b"world").unwrap());
+///
+/// let mut framing = Rfc4571::new();
+/// framing.push(&stream[..4]);
+/// assert_eq!(framing.pop(), None);
+///
+/// framing.push(&stream[4..]);
+/// assert_eq!(framing.pop().as_deref(), Some(&b"hello"[..]));
+/// assert_eq!(framing.pop().as_deref(), Some(&b"world"[..]));
+/// assert_eq!(framing.pop(), None);
+/// ```
+#[derive(Debug, Default)]
+pub struct Rfc4571 {
+    buf: Vec<u8>,
+}
+
+impl Rfc4571 {
+    /// Creates a new instance for one TCP connection.
+    pub fn new() -> Self {
+        Rfc4571::default()
+    }
+
+    /// Prefix a packet with its length.
+    ///
+    /// Errors if the packet is larger than 65535 bytes.
+    pub fn frame(packet: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let len = u16::try_from(packet.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Packet too large to frame")
+        })?;
+
+        let mut v = Vec::with_capacity(2 + packet.len());
+        v.extend_from_slice(&len.to_be_bytes());
+        v.extend_from_slice(packet);
+
+        Ok(v)
+    }
+
+    /// Add bytes read from the TCP stream.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Take the next complete packet, if there is one.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        if self.buf.len() < 2 {
+            return None;
+        }
+
+        let len = u16::from_be_bytes([self.buf[0], self.buf[1]]) as usize;
+        if self.buf.len() < 2 + len {
+            return None;
+        }
+
+        let packet = self.buf[2..2 + len].to_vec();
+        self.buf.drain(..2 + len);
+
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_and_split() {
+        let mut stream = vec![];
+        for packet in [&[1_u8; 100][..], &[], &[2; 1200]] {
+            stream.extend(Rfc4571::frame(packet).unwrap());
+        }
+        assert_eq!(&stream[..2], &[0, 100]);
+
+        // One byte at a time.
+        let mut framing = Rfc4571::new();
+        let mut packets = vec![];
+        for b in stream {
+            framing.push(&[b]);
+            while let Some(p) = framing.pop() {
+                packets.push(p);
+            }
+        }
+
+        assert_eq!(packets, vec![vec![1; 100], vec![], vec![2; 1200]]);
+    }
+
+    #[test]
+    fn frame_too_large() {
+        assert!(Rfc4571::frame(&[0; 65_535]).is_ok());
+        assert!(Rfc4571::frame(&[0; 65_536]).is_err());
+    }
+}
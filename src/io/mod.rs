@@ -16,6 +16,9 @@ pub use stun::StunMessage;
 pub(crate) use stun::{Class as StunClass, Method as StunMethod, StunError};
 pub(crate) use stun::{TransId, STUN_MAX_RETRANS, STUN_MAX_RTO_MILLIS, STUN_TIMEOUT};
 
+mod framing;
+pub use framing::Rfc4571;
+
 mod id;
 // this is only exported from this crate to avoid needing
 // a "util" crate or similar.
@@ -60,6 +63,33 @@ pub enum Protocol {
     Tls,
 }
 
+/// The network path used for sending changed.
+///
+/// Emitted in [`Event::NetworkRouteChange`][crate::Event::NetworkRouteChange]. Before the
+/// event, str0m has already reacted internally: the bandwidth estimate starts over, packets
+/// in flight on the old path don't count as loss, the pacer holds back from draining its
+/// queue faster than the pacing rate, and the ICE keepalive restarts on the new path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkRouteChange {
+    /// The protocol of the new route.
+    pub proto: Protocol,
+    /// The local address now sent from.
+    pub new_local: SocketAddr,
+    /// The remote address now sent to.
+    pub new_remote: SocketAddr,
+    /// Why the route changed.
+    pub reason: RouteChangeReason,
+}
+
+/// Why the route changed in a [`NetworkRouteChange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteChangeReason {
+    /// ICE nominated another candidate pair with a different remote address or protocol.
+    CandidatePair,
+    /// Only the local address changed, such as when switching network interface.
+    LocalInterface,
+}
+
 /// An instruction to send an outgoing packet.
 #[derive(Serialize, Deserialize)]
 pub struct Transmit {
@@ -103,6 +133,12 @@ pub struct Transmit {
     ///     used for [`Transmit::source`].
     /// * `Peer reflexive` is another, internal, type of candidate that str0m infers by using the other
     ///     types of candidates.
+    ///
+    /// For [`Protocol::Tcp`], send the contents framed with [`Rfc4571`] on the connection between
+    /// `source` and `destination`. If there is no such connection and `source` is an active (or so)
+    /// [`TcpType`][crate::TcpType] candidate, open one to `destination`. The port of an active
+    /// candidate is not the one the OS assigns the connection, so data read from it must
+    /// be given to str0m with the candidate address as [`Receive::destination`].
     pub source: SocketAddr,
 
     /// The destination address this datagram should be sent to.
@@ -141,9 +177,14 @@ pub struct Receive<'a> {
     pub source: SocketAddr,
 
     /// The destination ip of the datagram.
+    ///
+    /// For [`Protocol::Tcp`], this is the address of the local candidate the connection
+    /// belongs to, see [`Transmit::source`].
     pub destination: SocketAddr,
 
     /// Parsed contents of the datagram.
+    ///
+    /// For [`Protocol::Tcp`], one packet split from the stream with [`Rfc4571`].
     #[serde(borrow)]
     pub contents: DatagramRecv<'a>,
 }
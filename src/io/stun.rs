@@ -160,11 +160,17 @@ impl<'a> StunMessage<'a> {
     /// Whether this STUN message is a _successful_ BINDING response.
     ///
     /// STUN binding requests are very simple, they just return the observed address.
-    /// As such, they cannot actually fail which is why we don't have `is_failed_binding_response`.
     pub(crate) fn is_successful_binding_response(&self) -> bool {
         self.method == Method::Binding && self.class == Class::Success
     }
 
+    /// Whether this STUN message is a _failed_ BINDING response.
+    ///
+    /// For ICE, the only failure we act on is 487 (Role Conflict).
+    pub(crate) fn is_failed_binding_response(&self) -> bool {
+        self.method == Method::Binding && self.class == Class::Failure
+    }
+
     /// The transaction ID of this STUN message.
     pub(crate) fn trans_id(&self) -> TransId {
         self.trans_id
@@ -211,6 +217,21 @@ impl<'a> StunMessage<'a> {
         }
     }
 
+    /// Constructs a new STUN BINDING error reply.
+    pub(crate) fn error_reply(trans_id: TransId, code: u16, reason: &'a str) -> StunMessage<'a> {
+        StunMessage {
+            class: Class::Failure,
+            method: Method::Binding,
+            trans_id,
+            attrs: Attributes {
+                error_code: Some((code, reason)),
+                ..Default::default()
+            },
+            integrity: &[],
+            integrity_len: 0,
+        }
+    }
+
     /// If present, splits the value of the USERNAME attribute into local and remote (separated by `:`).
     pub fn split_username(&self) -> Option<(&str, &str)> {
         self.attrs.split_username()
@@ -231,6 +252,21 @@ impl<'a> StunMessage<'a> {
         self.attrs.use_candidate
     }
 
+    /// If present, returns the tie breaker of the ICE-CONTROLLING attribute.
+    pub(crate) fn ice_controlling(&self) -> Option<u64> {
+        self.attrs.ice_controlling
+    }
+
+    /// If present, returns the tie breaker of the ICE-CONTROLLED attribute.
+    pub(crate) fn ice_controlled(&self) -> Option<u64> {
+        self.attrs.ice_controlled
+    }
+
+    /// If present, returns the value of the ERROR-CODE attribute.
+    pub(crate) fn error_code(&self) -> Option<(u16, &str)> {
+        self.attrs.error_code
+    }
+
     /// Verify the integrity of this message against the provided password.
     #[must_use]
     pub(crate) fn check_integrity(&self, password: &str) -> bool {
@@ -515,8 +551,21 @@ impl<'a> Attributes<'a> {
         } else {
             0
         };
+        let error_code = self
+            .error_code
+            .map(|(_, reason)| {
+                let len = 4 + reason.len();
+                ATTR_TLV_LENGTH + len + (4 - len % 4) % 4
+            })
+            .unwrap_or_default();
 
-        username + ice_controlled + ice_controlling + priority + address + use_candidate
+        username
+            + ice_controlled
+            + ice_controlling
+            + priority
+            + address
+            + use_candidate
+            + error_code
     }
 
     fn to_bytes(self, vec: &mut dyn Write, trans_id: &[u8]) -> io::Result<()> {
@@ -555,6 +604,16 @@ impl<'a> Attributes<'a> {
             vec.write_all(&Self::USE_CANDIDATE.to_be_bytes())?;
             vec.write_all(&0_u16.to_be_bytes())?;
         }
+        if let Some((code, reason)) = self.error_code {
+            let len = 4 + reason.len();
+            vec.write_all(&Self::ERROR_CODE.to_be_bytes())?;
+            vec.write_all(&(len as u16).to_be_bytes())?;
+            vec.write_all(&[0, 0, (code / 100) as u8, (code % 100) as u8])?;
+            vec.write_all(reason.as_bytes())?;
+            for _ in 0..(4 - len % 4) % 4 {
+                vec.write_all(&[0])?;
+            }
+        }
 
         Ok(())
     }
@@ -853,6 +912,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn error_reply_roundtrip() {
+        let trans_id = TransId::new();
+        let reply = StunMessage::error_reply(trans_id, 487, "Role Conflict");
+
+        let mut buf = vec![0; 1500];
+        let n = reply.to_bytes("pass", &mut buf).unwrap();
+        buf.truncate(n);
+
+        let message = StunMessage::parse(&buf).unwrap();
+        assert!(message.check_integrity("pass"));
+        assert!(message.is_failed_binding_response());
+        assert_eq!(message.trans_id(), trans_id);
+        assert_eq!(message.error_code(), Some((487, "Role Conflict")));
+    }
+
     #[test]
     fn parse_zero_length_buffer() {
         let result = StunMessage::parse(&[]);
@@ -370,6 +370,10 @@
 //!     .build();
 //! ```
 //!
+//! RTP mode can also be enabled for a single media line with
+//! [`SdpApi::set_rtp_mode()`][crate::change::SdpApi::set_rtp_mode], leaving other
+//! media on the sample level API.
+//!
 //! RTP mode gives us some new API points.
 //!
 //! 1. [`Event::RtpPacket`][rtppak] emitted for every incoming RTP packet. Empty packets for bandwidth
@@ -579,14 +583,16 @@
 #[macro_use]
 extern crate tracing;
 
-use bwe::{Bwe, BweKind};
-use change::{DirectApi, SdpApi};
+use bwe::{Bwe, BweConfig, BweKind};
+use change::{DirectApi, RtcpMuxPolicy, SdpApi};
 use rtp::RawPacket;
 use std::fmt;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use streams::RtpPacket;
-use streams::StreamPaused;
+use streams::{LayerDiscovered, StreamEnded, StreamPaused, StreamRestarted, StreamSilence};
 use thiserror::Error;
 use util::InstantExt;
 
@@ -595,13 +601,16 @@ use crypto::Fingerprint;
 
 mod dtls;
 use dtls::DtlsCert;
+pub use dtls::DtlsState;
 use dtls::{Dtls, DtlsEvent};
 
 #[path = "ice/mod.rs"]
 mod ice_;
 use ice_::IceAgent;
 use ice_::IceAgentEvent;
-pub use ice_::{Candidate, CandidateKind, IceConnectionState, IceCreds};
+pub use ice_::{Candidate, CandidateKind, IceConnectionState, IceCreds, TcpType};
+use ice_::{CheckState, MdnsHooks};
+pub use ice_::{NominationStrategy, TypePreferences};
 
 /// Low level ICE access.
 // The ICE API is not necessary to interact with directly for "regular"
@@ -616,14 +625,16 @@ pub mod ice {
 }
 
 mod io;
-use io::DatagramRecvInner;
+use io::{DatagramRecvInner, MultiplexKind, RouteChangeReason, DATAGRAM_MTU};
 
 mod packet;
 
 #[path = "rtp/mod.rs"]
 mod rtp_;
 use rtp_::Bitrate;
-use rtp_::{Extension, ExtensionMap};
+use rtp_::{Extension, ExtensionMap, RtcpConfig};
+use rtp_::{DEFAULT_MAX_SSRCS, DEFAULT_SSRC_IDLE};
+use rtp_::{DEFAULT_REPLAY_WINDOW, MAX_SRTP_KEY_LIFETIME};
 
 /// Low level RTP access.
 pub mod rtp {
@@ -632,17 +643,29 @@ pub mod rtp {
         pub use crate::rtp_::{Descriptions, ExtendedReport, Fir, Goodbye, Nack, Pli};
         pub use crate::rtp_::{Dlrr, NackEntry, ReceptionReport, ReportBlock};
         pub use crate::rtp_::{FirEntry, ReceiverReport, SenderInfo, SenderReport, Twcc};
-        pub use crate::rtp_::{ReportList, Rrtr, Rtcp, Sdes, SdesType};
+        pub use crate::rtp_::{RawRtcp, ReportList, Rrtr, Rtcp, RtcpConfig, Sdes, SdesType};
     }
     use self::rtcp::Rtcp;
 
     /// Video Layers Allocation RTP Header Extension
     pub mod vla;
+    pub use crate::rtp_::{AbsCaptureTime, AbsSendTimeUnwrapper};
     pub use crate::rtp_::{Extension, ExtensionMap, ExtensionSerializer};
     pub use crate::rtp_::{ExtensionValues, UserExtensionValues};
 
-    pub use crate::rtp_::{RtpHeader, SeqNo, Ssrc, VideoOrientation};
-    pub use crate::streams::{RtpPacket, StreamPaused, StreamRx, StreamTx};
+    pub use crate::rtp_::{FrameMarking, FrameMarkingLayers};
+    pub use crate::rtp_::{RtpHeader, SeqNo, Ssrc, SsrcAllocator, VideoOrientation, VideoTiming};
+
+    pub use crate::rtp_::{ChromaSiting, Chromaticity, ColorRange, ColorSpace, HdrMetadata};
+    pub use crate::rtp_::{ColorPrimaries, MatrixCoefficients, TransferCharacteristics};
+
+    pub use crate::packet::{AssemblerOutput, Frame, FrameAssembler};
+    pub use crate::rtp_::{DependencyDescriptor, Dti, FrameDependency};
+    pub use crate::rtp_::{FrameDependencyStructure, RenderResolution};
+    pub use crate::streams::StreamSilence;
+    pub use crate::streams::{rtx_unwrap, rtx_wrap};
+    pub use crate::streams::{FlexfecConfig, RtpPacket, StreamPaused};
+    pub use crate::streams::{LayerDiscovered, StreamEnded, StreamRestarted, StreamRx, StreamTx};
 
     /// Debug output of the unencrypted RTP and RTCP packets.
     ///
@@ -670,13 +693,13 @@ use sctp::{RtcSctp, SctpEvent};
 mod sdp;
 
 pub mod format;
-use format::CodecConfig;
+use format::{CodecConfig, CodecRegistry};
 
 pub mod channel;
 use channel::{Channel, ChannelData, ChannelHandler, ChannelId};
 
 pub mod media;
-use media::{Direction, Media, Mid, Pt, Rid, Writer};
+use media::{Direction, Media, MediaFeatures, Mid, Pt, Rid, Writer};
 use media::{KeyframeRequest, KeyframeRequestKind};
 use media::{MediaAdded, MediaChanged, MediaData};
 
@@ -689,13 +712,18 @@ mod session;
 use session::Session;
 
 pub mod stats;
-use stats::{MediaEgressStats, MediaIngressStats, PeerStats, Stats, StatsEvent, StatsSnapshot};
+use stats::{
+    CandidatePairState, CandidatePairStats, RtcStats, RtcStatsSchedule, Stats, StatsEvent,
+    StatsSnapshot,
+};
+use stats::{IngressStats, MediaEgressStats, MediaIngressStats, PeerStats};
 
 mod streams;
 
 /// Network related types to get socket data in/out of [`Rtc`].
 pub mod net {
-    pub use crate::io::{DatagramRecv, DatagramSend, Protocol, Receive, Transmit};
+    pub use crate::io::{DatagramRecv, DatagramSend, Protocol, Receive, Rfc4571, Transmit};
+    pub use crate::io::{NetworkRouteChange, RouteChangeReason};
 }
 
 /// Various error types.
@@ -796,6 +824,10 @@ pub enum RtcError {
     #[error("Changes made out of order")]
     ChangesOutOfOrder,
 
+    /// The media features can't change once the media has streams.
+    #[error("Media features can't change for mid: {0}")]
+    MediaFeaturesLocked(Mid),
+
     /// The [`Writer`] was used twice without doing `Rtc::poll_output` in between. This
     /// is an incorrect usage pattern of the str0m API.
     #[error("Consecutive calls to write() without poll_output() in between")]
@@ -835,19 +867,41 @@ pub struct Rtc {
     alive: bool,
     ice: IceAgent,
     dtls: Dtls,
+    /// DTLS on ICE component 2, for when RTCP is not muxed with RTP.
+    dtls_rtcp: Option<Dtls>,
     sctp: RtcSctp,
     chan: ChannelHandler,
     stats: Option<Stats>,
+    rtc_stats: Option<RtcStatsSchedule>,
     session: Session,
-    remote_fingerprint: Option<Fingerprint>,
+    remote_fingerprints: Vec<Fingerprint>,
     remote_addrs: Vec<SocketAddr>,
     send_addr: Option<SendAddr>,
+    /// Where to send RTCP when it's not muxed with RTP.
+    send_addr_rtcp: Option<SendAddr>,
     need_init_time: bool,
     last_now: Instant,
     peer_bytes_rx: u64,
     peer_bytes_tx: u64,
     change_counter: usize,
     last_timeout_reason: Reason,
+    /// Output held back by [`Rtc::poll_batch()`] since it didn't fit the batch.
+    pending_output: Option<Output>,
+}
+
+/// Whether `t` can be appended to the `batch` for [`Rtc::poll_batch()`].
+fn fits_batch(batch: &[net::Transmit], t: &net::Transmit) -> bool {
+    let (Some(first), Some(last)) = (batch.first(), batch.last()) else {
+        return true;
+    };
+
+    if first.proto != t.proto || first.source != t.source || first.destination != t.destination {
+        return false;
+    }
+
+    // Segments must be of equal size for GSO, where only the last may be shorter.
+    t.proto != net::Protocol::Udp
+        || (last.contents.len() == first.contents.len() && t.contents.len() <= first.contents.len())
 }
 
 struct SendAddr {
@@ -866,10 +920,39 @@ pub enum Event {
     /// Emitted when we got ICE connection and established DTLS.
     Connected,
 
+    /// Progress of the DTLS handshake, and the remote closing DTLS.
+    ///
+    /// A [`DtlsState::Failed`] or [`DtlsState::Closed`] is delivered by
+    /// [`Rtc::poll_output()`] even though the instance is disconnected.
+    DtlsStateChange(DtlsState),
+
     /// ICE connection state changes tells us whether the [`Rtc`] instance is
     /// connected to the peer or not.
     IceConnectionStateChange(IceConnectionState),
 
+    /// The remote stopped answering the consent checks (RFC 7675) on the path in use.
+    ///
+    /// After 30 seconds without a response, we stop sending on the path. Unless there
+    /// is another path to fail over to, this is followed by
+    /// [`IceConnectionState::Disconnected`], and the application should reconnect or
+    /// do an ICE restart.
+    IceConsentExpired,
+
+    /// A remote ICE candidate has an mDNS hostname (`<uuid>.local`) that needs resolving.
+    ///
+    /// Browsers hide the IP of host candidates this way. Resolve the name with mDNS and
+    /// provide the address using [`Rtc::resolve_mdns()`], or set a resolver with
+    /// [`RtcConfig::set_mdns_resolver()`]. Candidates not resolved within 10 seconds
+    /// are dropped.
+    ResolveMdns(String),
+
+    /// The SRTP keys in either direction are close to their lifetime.
+    ///
+    /// Emitted once per key when 90% of [`RtcConfig::srtp_key_lifetime()`] SRTP packets, or
+    /// 90% of the 2^31 SRTCP packets, have been protected/unprotected. New keys are set
+    /// using [`DirectApi::rekey_srtp()`][crate::change::DirectApi::rekey_srtp].
+    SrtpKeyExpiring,
+
     // =================== Media related events ==================
 
     /// Upon adding new media to the session. The lines are emitted.
@@ -920,9 +1003,23 @@ pub enum Event {
     /// Aggregated statistics for each media (mid, rid) in the egress direction
     MediaEgressStats(MediaEgressStats),
 
+    /// Statistics for each incoming SSRC, with loss and jitter as sent in receiver reports.
+    IngressStats(IngressStats),
+
+    /// Snapshot of all statistics, the same as returned by [`Rtc::stats()`].
+    ///
+    /// Emitted every [`RtcConfig::set_rtc_stats_interval()`], and straight away when
+    /// streams are added or removed, or the candidate pair in use changes.
+    RtcStats(RtcStats),
+
     /// A new estimate from the bandwidth estimation subsystem.
     EgressBitrateEstimate(BweKind),
 
+    /// The network path used for sending changed.
+    ///
+    /// Not emitted for the first nominated path.
+    NetworkRouteChange(net::NetworkRouteChange),
+
     // =================== RTP related events ===================
 
     /// Incoming keyframe request for media that we are sending to the remote peer.
@@ -935,9 +1032,32 @@ pub enum Event {
     /// This means the stream has not received any data for some time (default 1.5 seconds).
     StreamPaused(StreamPaused),
 
+    /// An incoming encoded stream restarted.
+    ///
+    /// The remote jumped to new sequence numbers or RTP timestamps, and the stream state
+    /// has been re-initialized.
+    StreamRestarted(StreamRestarted),
+
+    /// The remote ended an incoming encoded stream with an RTCP BYE.
+    StreamEnded(StreamEnded),
+
+    /// An incoming Opus stream was silent using DTX. Emitted as the silence ends.
+    StreamSilence(StreamSilence),
+
+    /// An incoming simulcast layer was bound to an SSRC using the rid header extension,
+    /// or `a=ssrc-group:SIM` in the SDP.
+    LayerDiscovered(LayerDiscovered),
+
     /// Incoming RTP data.
     RtpPacket(RtpPacket),
 
+    /// Incoming RTCP packet that str0m doesn't handle.
+    ///
+    /// These are packets str0m can't parse, such as APP, unknown packet types or feedback
+    /// formats, delivered verbatim. Outgoing raw RTCP is written with
+    /// [`DirectApi::write_raw_rtcp()`][crate::change::DirectApi::write_raw_rtcp].
+    RtcpPacket(rtp::rtcp::RawRtcp),
+
     /// Debug output of incoming and outgoing RTCP/RTP packets.
     ///
     /// Enable using [`RtcConfig::enable_raw_packets()`].
@@ -993,6 +1113,11 @@ pub enum Reason {
     /// Includes checking candidate pairs and various cleanups.
     Ice,
 
+    /// The DTLS handshake.
+    ///
+    /// Retransmission of handshake flights.
+    Dtls,
+
     /// The SCTP subsystem.
     ///
     /// Things like handling retransmissions and keep-alive checks.
@@ -1024,6 +1149,12 @@ pub enum Reason {
     /// sides support it.
     Twcc,
 
+    /// Reporting of REMB (if enabled).
+    ///
+    /// The incoming bitrate is estimated locally and sent as REMB. Enabled via SDP if the
+    /// remote supports REMB, but not TWCC.
+    Remb,
+
     /// RTP streams not receiving data goes into a paused state.
     ///
     /// Whenever an RTP receive stream receives data, a new timeout is scheduled.
@@ -1092,6 +1223,10 @@ impl Rtc {
         if config.ice_lite {
             ice.set_ice_lite(config.ice_lite);
         }
+        ice.set_mdns_hooks(config.mdns.clone());
+        ice.set_nomination_strategy(config.ice_nomination);
+        ice.set_renomination(config.ice_renomination);
+        ice.set_type_preferences(config.ice_type_preferences);
 
         let dtls_cert = if let Some(c) = config.dtls_cert {
             c
@@ -1106,21 +1241,42 @@ impl Rtc {
             }
         };
 
+        let new_dtls = |cert: DtlsCert| {
+            let mut dtls = Dtls::new(cert).expect("DTLS to init without problem");
+            dtls.set_fingerprint_verification(config.fingerprint_verification);
+            dtls.set_retransmit(config.dtls_initial_rto, config.dtls_max_retransmits);
+            if let Err(e) = dtls.set_mtu(config.dtls_mtu) {
+                warn!("Ignoring DTLS MTU {}: {}", config.dtls_mtu, e);
+            }
+            dtls
+        };
+
+        // Without a=rtcp-mux, the RTCP component does its own DTLS handshake with
+        // the same certificate (RFC 5764 4.1).
+        let dtls_rtcp = (config.rtcp_mux_policy == RtcpMuxPolicy::Negotiate)
+            .then(|| new_dtls(dtls_cert.clone()));
+        let dtls = new_dtls(dtls_cert);
+
         Rtc {
             alive: true,
             ice,
-            dtls: Dtls::new(dtls_cert).expect("DTLS to init without problem"),
+            dtls,
+            dtls_rtcp,
             session,
             sctp: RtcSctp::new(),
             chan: ChannelHandler::default(),
             stats: config.stats_interval.map(Stats::new),
-            remote_fingerprint: None,
+            rtc_stats: (!config.rtc_stats_interval.is_zero())
+                .then(|| RtcStatsSchedule::new(config.rtc_stats_interval)),
+            remote_fingerprints: vec![],
             remote_addrs: vec![],
             send_addr: None,
+            send_addr_rtcp: None,
             need_init_time: true,
             last_now: already_happened(),
             peer_bytes_rx: 0,
             peer_bytes_tx: 0,
+            pending_output: None,
             change_counter: 0,
             last_timeout_reason: Reason::NotHappening,
         }
@@ -1166,6 +1322,33 @@ impl Rtc {
         }
     }
 
+    /// Gracefully closes the instance.
+    ///
+    /// Any pending RTCP is sent, followed by a final RTCP with reports for all streams and
+    /// a BYE for all our SSRCs, with the optional `reason`. No more RTP is sent. Once the
+    /// BYE is sent, a DTLS close_notify follows, and the instance disconnects as with
+    /// [`Rtc::disconnect()`].
+    ///
+    /// If not connected, this disconnects straight away.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let mut rtc = Rtc::new();
+    ///
+    /// rtc.close(Some("bye"));
+    ///
+    /// // Nothing to send when not connected.
+    /// rtc.poll_output().unwrap();
+    /// assert!(!rtc.is_alive());
+    /// ```
+    pub fn close(&mut self, reason: Option<&str>) {
+        if !self.alive {
+            return;
+        }
+        info!("Close with reason: {:?}", reason);
+        self.session.close(self.last_now, reason);
+    }
+
     /// Add a local ICE candidate. Local candidates are socket addresses the `Rtc` instance
     /// use for communicating with the peer.
     ///
@@ -1211,9 +1394,43 @@ impl Rtc {
     ///
     /// [1]: https://www.rfc-editor.org/rfc/rfc8838.txt
     pub fn add_remote_candidate(&mut self, c: Candidate) {
+        if c.is_rtcp_component() && self.session.rtcp_mux_negotiated() == Some(true) {
+            debug!("Ignore RTCP candidate with a=rtcp-mux: {:?}", c);
+            return;
+        }
         self.ice.add_remote_candidate(c);
     }
 
+    /// The remote has no more ICE candidates to trickle.
+    ///
+    /// This is the `a=end-of-candidates` of [Trickle Ice][1], which is also picked up
+    /// from a remote [`SdpOffer`][change::SdpOffer] or [`SdpAnswer`][change::SdpAnswer].
+    /// Only then can the ICE state reach [`IceConnectionState::Completed`], and with no
+    /// remote candidates at all, the ICE state goes to `Disconnected`.
+    ///
+    /// An ICE restart expects new remote candidates again.
+    ///
+    /// [1]: https://www.rfc-editor.org/rfc/rfc8838.txt
+    pub fn set_remote_end_of_candidates(&mut self) {
+        self.ice.set_remote_end_of_candidates();
+    }
+
+    /// Provide the address of a remote mDNS hostname from [`Event::ResolveMdns`].
+    ///
+    /// ```
+    /// # use str0m::{Rtc, Candidate};
+    /// let mut rtc = Rtc::new();
+    ///
+    /// let s = "candidate:1 1 udp 2122260223 4a6b1c2d-9e8f.local 5000 typ host";
+    /// rtc.add_remote_candidate(Candidate::from_sdp_string(s).unwrap());
+    ///
+    /// // Once resolved.
+    /// rtc.resolve_mdns("4a6b1c2d-9e8f.local", "1.2.3.4".parse().unwrap());
+    /// ```
+    pub fn resolve_mdns(&mut self, name: &str, ip: IpAddr) {
+        self.ice.resolve_mdns(name, ip);
+    }
+
     /// Checks if we are connected.
     ///
     /// This tests both if we have ICE connection and DTLS is ready.
@@ -1281,12 +1498,12 @@ impl Rtc {
     /// This is a sample level API: For RTP level see [`DirectApi::stream_tx()`] and [`DirectApi::stream_rx()`].
     ///
     pub fn writer(&mut self, mid: Mid) -> Option<Writer> {
-        if self.session.rtp_mode {
+        let media = self.session.media_by_mid_mut(mid)?;
+
+        if media.rtp_mode() {
             panic!("In rtp_mode use direct_api().stream_tx().write_rtp()");
         }
 
-        self.session.media_by_mid_mut(mid)?;
-
         Some(Writer::new(&mut self.session, mid))
     }
 
@@ -1297,21 +1514,110 @@ impl Rtc {
         self.session.media_by_mid(mid)
     }
 
+    /// Snapshot of the statistics of the session.
+    ///
+    /// Covers every outgoing and incoming stream, the candidate pair in use and the bandwidth
+    /// estimate, all taken at the time of the last input. The only allocation is for the
+    /// returned snapshot. For periodic snapshots, see [`RtcConfig::set_rtc_stats_interval()`].
+    ///
+    /// The snapshot serializes with serde, for instance to feed a dashboard.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let mut rtc = Rtc::new();
+    ///
+    /// let stats = rtc.stats();
+    /// let json = serde_json::to_string(&stats).unwrap();
+    ///
+    /// assert!(json.starts_with(r#"{"bytes_sent":0,"bytes_received":0,"candidate_pair":null"#));
+    /// ```
+    pub fn stats(&mut self) -> RtcStats {
+        let state = self.ice.state();
+
+        let candidate_pairs: Vec<_> = self
+            .ice
+            .candidate_pairs()
+            .map(|(pair, local, remote)| CandidatePairStats {
+                state,
+                check_state: match pair.state() {
+                    CheckState::Waiting => CandidatePairState::Waiting,
+                    CheckState::InProgress => CandidatePairState::InProgress,
+                    CheckState::Succeeded => CandidatePairState::Succeeded,
+                },
+                nominated: pair.is_nominated(),
+                selected: pair.is_selected(),
+                priority: pair.prio(),
+                protocol: local.proto(),
+                local_address: local.base(),
+                local_candidate_type: local.kind(),
+                remote_address: remote.addr(),
+                remote_candidate_type: remote.kind(),
+                requests_sent: pair.requests_sent(),
+                requests_received: pair.remote_binding_requests,
+                responses_received: pair.responses_received(),
+                current_round_trip_time: pair.rtt().map(|v| v.as_secs_f64()),
+                round_trip_time_ewma: pair.rtt_ewma().map(|v| v.as_secs_f64()),
+                bytes_sent: pair.bytes_sent(),
+                bytes_received: pair.bytes_received(),
+                nominated_at: pair.nominated_at(),
+            })
+            .collect();
+
+        let candidate_pair = candidate_pairs.iter().find(|p| p.selected).cloned();
+
+        let mut stats = RtcStats {
+            timestamp: self.last_now,
+            bytes_sent: self.peer_bytes_tx,
+            bytes_received: self.peer_bytes_rx,
+            candidate_pair,
+            candidate_pairs,
+            bwe: None,
+            outbound: Vec::new(),
+            inbound: Vec::new(),
+            rtp_packets_unmapped: 0,
+            ssrc_group_fallbacks: 0,
+        };
+
+        self.session.fill_stats(self.last_now, &mut stats);
+
+        stats
+    }
+
     fn init_dtls(&mut self, active: bool) -> Result<(), RtcError> {
-        if self.dtls.is_inited() {
-            return Ok(());
+        if !self.dtls.is_inited() {
+            info!("DTLS setup is: {:?}", active);
+            self.dtls.set_active(active);
+
+            if active {
+                self.dtls.handle_handshake()?;
+            }
+        }
+
+        // With a=rtcp-mux, the RTCP component is not used.
+        if self.session.rtcp_mux() {
+            self.dtls_rtcp = None;
         }
 
-        info!("DTLS setup is: {:?}", active);
-        self.dtls.set_active(active);
+        if let Some(dtls) = self.dtls_rtcp.as_mut().filter(|d| !d.is_inited()) {
+            info!("DTLS setup for RTCP is: {:?}", active);
+            dtls.set_active(active);
 
-        if active {
-            self.dtls.handle_handshake()?;
+            if active {
+                dtls.handle_handshake()?;
+            }
         }
 
         Ok(())
     }
 
+    fn set_remote_fingerprints(&mut self, fingerprints: Vec<Fingerprint>) {
+        self.remote_fingerprints = fingerprints.clone();
+        if let Some(dtls) = &mut self.dtls_rtcp {
+            dtls.set_remote_fingerprints(fingerprints.clone());
+        }
+        self.dtls.set_remote_fingerprints(fingerprints);
+    }
+
     fn init_sctp(&mut self, client: bool) {
         // If we got an m=application line, ensure we have negotiated the
         // SCTP association with the other side.
@@ -1348,6 +1654,10 @@ impl Rtc {
     ///
     /// See [`Rtc`] instance documentation for how this is expected to be used in a loop.
     pub fn poll_output(&mut self) -> Result<Output, RtcError> {
+        if let Some(o) = self.pending_output.take() {
+            return Ok(o);
+        }
+
         let o = self.do_poll_output()?;
 
         match &o {
@@ -1359,6 +1669,12 @@ impl Rtc {
             },
             Output::Transmit(t) => {
                 self.peer_bytes_tx += t.contents.len() as u64;
+                let kind = MultiplexKind::try_from(&t.contents[..]);
+                if !matches!(kind, Ok(MultiplexKind::Stun)) {
+                    let len = t.contents.len();
+                    self.ice
+                        .record_bytes_sent(t.proto, t.source, t.destination, len);
+                }
                 trace!("OUT {:?}", t)
             }
             Output::Timeout(_t) => {}
@@ -1367,7 +1683,78 @@ impl Rtc {
         Ok(o)
     }
 
+    /// Poll the `Rtc` instance for a batch of datagrams.
+    ///
+    /// This is an alternative to [`Rtc::poll_output()`] for socket layers using `sendmmsg`
+    /// or UDP GSO. When the next output is a transmit, it is appended to `out` together with
+    /// up to `max - 1` directly following transmits in the same batch, and `None` is returned.
+    /// Otherwise the event or timeout is returned as it would be by `poll_output()`.
+    ///
+    /// A batch is datagrams in the order they were produced, with the same protocol, source
+    /// and destination. For UDP, the datagrams also have a layout that GSO can send in one
+    /// call: all are the size of the first, except the last which may be shorter. Such a batch
+    /// typically comes from the pacer releasing many packets at once, like for a keyframe.
+    ///
+    /// Both functions can be used interchangeably, any output that didn't fit the batch is
+    /// returned on the next poll.
+    ///
+    /// ```no_run
+    /// # use str0m::{Rtc, Output};
+    /// # let mut rtc = Rtc::new();
+    /// let mut batch = Vec::new();
+    ///
+    /// loop {
+    ///     match rtc.poll_batch(64, &mut batch).unwrap() {
+    ///         None => {
+    ///             // send the batch with sendmmsg or GSO
+    ///             batch.clear();
+    ///         }
+    ///         Some(Output::Timeout(_)) => break,
+    ///         Some(_) => {
+    ///             // handle the event
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn poll_batch(
+        &mut self,
+        max: usize,
+        out: &mut Vec<net::Transmit>,
+    ) -> Result<Option<Output>, RtcError> {
+        let start = out.len();
+        let max = max.max(1);
+
+        while out.len() - start < max {
+            let o = self.poll_output()?;
+
+            let Output::Transmit(t) = o else {
+                if out.len() == start {
+                    return Ok(Some(o));
+                }
+                // Timeouts are calculated again on the next poll.
+                if matches!(o, Output::Event(_)) {
+                    self.pending_output = Some(o);
+                }
+                break;
+            };
+
+            if !fits_batch(&out[start..], &t) {
+                self.pending_output = Some(Output::Transmit(t));
+                break;
+            }
+
+            out.push(t);
+        }
+
+        Ok(None)
+    }
+
     fn do_poll_output(&mut self) -> Result<Output, RtcError> {
+        // Delivered also after disconnecting, to tell why.
+        if let Some(v) = self.dtls.poll_state() {
+            return Ok(Output::Event(Event::DtlsStateChange(v)));
+        }
+
         if !self.alive {
             self.last_timeout_reason = Reason::NotHappening;
             return Ok(Output::Timeout(not_happening()));
@@ -1381,6 +1768,15 @@ impl Rtc {
                 IceAgentEvent::IceConnectionStateChange(v) => {
                     return Ok(Output::Event(Event::IceConnectionStateChange(v)))
                 }
+                IceAgentEvent::ResolveMdns(name) => {
+                    return Ok(Output::Event(Event::ResolveMdns(name)))
+                }
+                IceAgentEvent::ConsentExpired => {
+                    // Without consent, we must stop sending to the remote.
+                    self.send_addr = None;
+                    self.send_addr_rtcp = None;
+                    return Ok(Output::Event(Event::IceConsentExpired));
+                }
                 IceAgentEvent::DiscoveredRecv { proto, source } => {
                     info!("ICE remote address: {:?}/{:?}", source, proto);
                     self.remote_addrs.push(source);
@@ -1397,11 +1793,55 @@ impl Rtc {
                         "ICE nominated send from: {:?} to: {:?} with protocol {:?}",
                         source, destination, proto,
                     );
+
+                    let route_change = self.send_addr.as_ref().and_then(|s| {
+                        let reason = if s.proto != proto || s.destination != destination {
+                            RouteChangeReason::CandidatePair
+                        } else if s.source != source {
+                            RouteChangeReason::LocalInterface
+                        } else {
+                            return None;
+                        };
+                        Some(net::NetworkRouteChange {
+                            proto,
+                            new_local: source,
+                            new_remote: destination,
+                            reason,
+                        })
+                    });
+
                     self.send_addr = Some(SendAddr {
                         proto,
                         source,
                         destination,
                     });
+
+                    if let Some(schedule) = &mut self.rtc_stats {
+                        schedule.trigger();
+                    }
+
+                    if let Some(change) = route_change {
+                        info!("Network route change: {:?}", change);
+                        self.session.handle_route_change(self.last_now);
+                        self.ice.handle_route_change(self.last_now);
+                        return Ok(Output::Event(Event::NetworkRouteChange(change)));
+                    }
+                }
+                IceAgentEvent::NominatedSendRtcp {
+                    proto,
+                    source,
+                    destination,
+                } => {
+                    info!(
+                        "ICE nominated RTCP send from: {:?} to: {:?} with protocol {:?}",
+                        source, destination, proto,
+                    );
+
+                    self.send_addr_rtcp = Some(SendAddr {
+                        proto,
+                        source,
+                        destination,
+                    });
                 }
             }
         }
@@ -1420,23 +1860,19 @@ impl Rtc {
                         srtp_profile
                     );
                     let active = self.dtls.is_active().expect("DTLS must be inited by now");
-                    self.session.set_keying_material(mat, srtp_profile, active);
+                    self.session
+                        .set_keying_material(mat, srtp_profile, active, self.last_now);
                 }
-                DtlsEvent::RemoteFingerprint(v1) => {
-                    debug!("DTLS verify remote fingerprint");
-                    if let Some(v2) = &self.remote_fingerprint {
-                        if v1 != *v2 {
-                            self.disconnect();
-                            return Err(RtcError::RemoteSdp("remote fingerprint no match".into()));
-                        }
-                    } else {
-                        self.disconnect();
-                        return Err(RtcError::RemoteSdp("no a=fingerprint before dtls".into()));
-                    }
+                DtlsEvent::RemoteFingerprint(v) => {
+                    // Verified by the DTLS layer before any keying material.
+                    debug!("DTLS remote fingerprint: {}", v);
                 }
                 DtlsEvent::Data(v) => {
                     self.sctp.handle_input(self.last_now, &v);
                 }
+                DtlsEvent::CloseNotify => {
+                    info!("DTLS closed by remote");
+                }
             }
         }
 
@@ -1444,6 +1880,29 @@ impl Rtc {
             return Ok(Output::Event(Event::Connected));
         }
 
+        if let Some(dtls) = &mut self.dtls_rtcp {
+            while let Some(v) = dtls.poll_state() {
+                debug!("DTLS for RTCP state: {:?}", v);
+            }
+
+            while let Some(e) = dtls.poll_event() {
+                match e {
+                    DtlsEvent::SrtpKeyingMaterial(mat, srtp_profile) => {
+                        info!("DTLS set SRTCP keying material for RTCP component");
+                        let active = dtls.is_active().expect("DTLS must be inited by now");
+                        self.session
+                            .set_rtcp_keying_material(mat, srtp_profile, active);
+                    }
+                    DtlsEvent::Data(_) => {
+                        trace!("Ignoring application data on RTCP component");
+                    }
+                    e => {
+                        debug!("DTLS for RTCP event: {:?}", e);
+                    }
+                }
+            }
+        }
+
         while let Some(e) = self.sctp.poll() {
             match e {
                 SctpEvent::Transmit { mut packets } => {
@@ -1498,11 +1957,21 @@ impl Rtc {
             return Ok(Output::Event(ev));
         }
 
+        let stream_count = self.session.streams.stream_count();
+        let rtc_stats_due = self.rtc_stats.as_mut().map_or(false, |s| {
+            s.check_streams(stream_count);
+            s.poll_due()
+        });
+        if rtc_stats_due {
+            return Ok(Output::Event(Event::RtcStats(self.stats())));
+        }
+
         if let Some(e) = self.stats.as_mut().and_then(|s| s.poll_output()) {
             return Ok(match e {
                 StatsEvent::Peer(s) => Output::Event(Event::PeerStats(s)),
                 StatsEvent::MediaIngress(s) => Output::Event(Event::MediaIngressStats(s)),
                 StatsEvent::MediaEgress(s) => Output::Event(Event::MediaEgressStats(s)),
+                StatsEvent::Ingress(s) => Output::Event(Event::IngressStats(s)),
             });
         }
 
@@ -1512,9 +1981,33 @@ impl Rtc {
 
         if let Some(send) = &self.send_addr {
             // These can only be sent after we got an ICE connection.
-            let datagram = None
-                .or_else(|| self.dtls.poll_datagram())
-                .or_else(|| self.session.poll_datagram(self.last_now));
+            let dtls_closed = self.dtls.is_closed();
+            let datagram = None.or_else(|| self.dtls.poll_datagram()).or_else(|| {
+                // No more SRTP after close_notify.
+                (!dtls_closed)
+                    .then(|| self.session.poll_datagram(self.last_now))
+                    .flatten()
+            });
+
+            if let Some(contents) = datagram {
+                let t = net::Transmit {
+                    proto: send.proto,
+                    source: send.source,
+                    destination: send.destination,
+                    contents,
+                };
+                return Ok(Output::Transmit(t));
+            }
+        }
+
+        // Without a=rtcp-mux, DTLS and RTCP for the RTCP component.
+        if let (Some(send), Some(dtls)) = (&self.send_addr_rtcp, &mut self.dtls_rtcp) {
+            let dtls_closed = self.dtls.is_closed();
+            let datagram = None.or_else(|| dtls.poll_datagram()).or_else(|| {
+                (!dtls_closed)
+                    .then(|| self.session.poll_rtcp_datagram(self.last_now))
+                    .flatten()
+            });
 
             if let Some(contents) = datagram {
                 let t = net::Transmit {
@@ -1527,14 +2020,49 @@ impl Rtc {
             }
         }
 
+        // Where the final RTCP goes.
+        let rtcp_send_addr = if self.session.rtcp_mux() {
+            &self.send_addr
+        } else {
+            &self.send_addr_rtcp
+        };
+
+        // Closing is done when the BYE is sent, or there is no way to send it.
+        let session_closed =
+            self.session.is_closed() || (self.session.is_closing() && rtcp_send_addr.is_none());
+
+        if session_closed && self.send_addr.is_some() && !self.dtls.is_closed() {
+            // Tell the remote with a close_notify, which is sent on the next poll.
+            self.dtls.close()?;
+            if self.dtls.is_closed() {
+                return self.do_poll_output();
+            }
+        }
+
+        // A closed DTLS is done when our close_notify is sent.
+        if session_closed || self.dtls.is_closed() {
+            self.disconnect();
+            self.last_timeout_reason = Reason::NotHappening;
+            return Ok(Output::Timeout(not_happening()));
+        }
+
         let stats = self.stats.as_mut();
 
         let time_and_reason = (None, Reason::NotHappening)
             .soonest((self.ice.poll_timeout(), Reason::Ice))
+            .soonest((self.dtls.poll_timeout(), Reason::Dtls))
+            .soonest((
+                self.dtls_rtcp.as_ref().and_then(|d| d.poll_timeout()),
+                Reason::Dtls,
+            ))
             .soonest(self.session.poll_timeout())
             .soonest((self.sctp.poll_timeout(), Reason::Sctp))
             .soonest((self.chan.poll_timeout(&self.sctp), Reason::Channel))
-            .soonest((stats.and_then(|s| s.poll_timeout()), Reason::Stats));
+            .soonest((stats.and_then(|s| s.poll_timeout()), Reason::Stats))
+            .soonest((
+                self.rtc_stats.as_ref().and_then(|s| s.poll_timeout()),
+                Reason::Stats,
+            ));
 
         // trace!("poll_output timeout reason: {}", time_and_reason.1);
 
@@ -1613,7 +2141,10 @@ impl Rtc {
 
         // Fast path: DTLS, RTP, and RTCP traffic coming in from the same socket address
         // we've nominated for sending via the ICE agent. This is the typical case
-        if let Some(send_addr) = &self.send_addr {
+        for send_addr in [&self.send_addr, &self.send_addr_rtcp]
+            .into_iter()
+            .flatten()
+        {
             if r.source == send_addr.destination {
                 return true;
             }
@@ -1692,6 +2223,16 @@ impl Rtc {
 
         self.last_now = now;
         self.ice.handle_timeout(now);
+        if let Err(e) = self.dtls.handle_timeout(now) {
+            self.disconnect();
+            return Err(e.into());
+        }
+        if let Some(dtls) = &mut self.dtls_rtcp {
+            if let Err(e) = dtls.handle_timeout(now) {
+                self.disconnect();
+                return Err(e.into());
+            }
+        }
         self.sctp.handle_timeout(now);
         self.chan.handle_timeout(now, &mut self.sctp);
         self.session.handle_timeout(now)?;
@@ -1706,6 +2247,10 @@ impl Rtc {
             }
         }
 
+        if let Some(schedule) = &mut self.rtc_stats {
+            schedule.handle_timeout(now);
+        }
+
         Ok(())
     }
 
@@ -1723,6 +2268,12 @@ impl Rtc {
         };
 
         self.peer_bytes_rx += bytes_rx as u64;
+        self.ice
+            .record_bytes_received(r.proto, r.source, r.destination, bytes_rx);
+
+        // Without a=rtcp-mux, everything but STUN on the RTCP component is DTLS or SRTCP.
+        let rtcp_component =
+            !self.session.rtcp_mux() && self.ice.is_rtcp_local(r.proto, r.destination);
 
         match r.contents.inner {
             Stun(stun) => {
@@ -1734,7 +2285,26 @@ impl Rtc {
                 };
                 self.ice.handle_packet(now, packet);
             }
-            Dtls(dtls) => self.dtls.handle_receive(dtls)?,
+            Dtls(dtls) => {
+                let d = match &mut self.dtls_rtcp {
+                    Some(d) if rtcp_component => d,
+                    _ => &mut self.dtls,
+                };
+                if let Err(e) = d.handle_receive(dtls) {
+                    if matches!(e, error::DtlsError::FingerprintMismatch) {
+                        // Not the peer negotiated in SDP.
+                        self.disconnect();
+                    }
+                    return Err(e.into());
+                }
+            }
+            Rtp(_) | Rtcp(_) if self.dtls.is_closed() => {
+                trace!("Ignoring SRTP after DTLS close");
+            }
+            Rtp(v) | Rtcp(v) if rtcp_component => self.session.handle_rtcp_receive(now, v),
+            // Not muxed, so there is no RTCP on the RTP component. The payload types the
+            // demux classifies as RTCP (RFC 5761 4) are fine for RTP then.
+            Rtp(v) | Rtcp(v) if !self.session.rtcp_mux() => self.session.handle_rtp_receive(now, v),
             Rtp(rtp) => self.session.handle_rtp_receive(now, rtp),
             Rtcp(rtcp) => self.session.handle_rtcp_receive(now, rtcp),
         }
@@ -1813,18 +2383,39 @@ pub struct RtcConfig {
     local_ice_credentials: Option<IceCreds>,
     dtls_cert: Option<DtlsCert>,
     fingerprint_verification: bool,
+    dtls_initial_rto: Duration,
+    dtls_max_retransmits: usize,
+    dtls_mtu: usize,
     ice_lite: bool,
+    ice_nomination: NominationStrategy,
+    ice_renomination: bool,
+    ice_type_preferences: TypePreferences,
     codec_config: CodecConfig,
     exts: ExtensionMap,
     stats_interval: Option<Duration>,
+    rtc_stats_interval: Duration,
     /// Whether to use Bandwidth Estimation to discover the egress bandwidth.
-    bwe_initial_bitrate: Option<Bitrate>,
+    bwe_config: Option<BweConfig>,
     reordering_size_audio: usize,
     reordering_size_video: usize,
+    h264_length_prefixed: bool,
+    codec_registry: CodecRegistry,
+    ulpfec_receive: Option<(Pt, Pt)>,
     send_buffer_audio: usize,
     send_buffer_video: usize,
+    srtp_replay_window: usize,
+    srtp_key_lifetime: u64,
+    srtp_max_ssrcs: usize,
+    srtp_ssrc_idle_timeout: Duration,
     rtp_mode: bool,
+    media_features: MediaFeatures,
     enable_raw_packets: bool,
+    cname: Option<String>,
+    sdes_name: Option<String>,
+    sdes_tool: Option<String>,
+    rtcp_config: Option<RtcpConfig>,
+    rtcp_mux_policy: RtcpMuxPolicy,
+    mdns: MdnsHooks,
 }
 
 impl RtcConfig {
@@ -1868,7 +2459,9 @@ impl RtcConfig {
     /// Set the DTLS certificate for secure communication.
     ///
     /// Generating a certificate can be a time-consuming process.
-    /// Use this API to reuse a previously created [`DtlsCert`] if available.
+    /// Use this API to reuse a previously created [`DtlsCert`] if available,
+    /// or one loaded with [`DtlsCert::from_openssl_der`] to keep the fingerprint
+    /// across restarts. Without it, a self signed certificate is generated.
     ///
     /// ```
     /// # use str0m::RtcConfig;
@@ -1887,6 +2480,10 @@ impl RtcConfig {
     /// An [`Rtc`] instance in ice lite mode will not make STUN binding requests, but only
     /// answer to requests from the remote peer.
     ///
+    /// Ice lite only accepts host candidates, signals `a=ice-lite` and always takes
+    /// the controlled role, which means the remote (full) peer must nominate.
+    /// Both peers being ice lite is not supported.
+    ///
     /// See [ICE RFC][1]
     ///
     /// [1]: https://www.rfc-editor.org/rfc/rfc8445#page-13
@@ -1910,12 +2507,86 @@ impl RtcConfig {
 
     /// Toggle certificate fingerprint verification.
     ///
-    /// By default the certificate fingerprint is verified.
+    /// By default the certificate fingerprint is verified. The remote DTLS certificate
+    /// must match one of the `a=fingerprint` in the SDP, or the handshake fails with
+    /// [`DtlsError::FingerprintMismatch`][crate::error::DtlsError::FingerprintMismatch]
+    /// before any SRTP keys are derived.
     pub fn set_fingerprint_verification(mut self, enabled: bool) -> Self {
         self.fingerprint_verification = enabled;
         self
     }
 
+    /// Initial timeout for retransmitting a DTLS handshake flight.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use std::time::Duration;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to 1 second.
+    /// assert_eq!(config.dtls_initial_rto(), Duration::from_secs(1));
+    /// ```
+    pub fn dtls_initial_rto(&self) -> Duration {
+        self.dtls_initial_rto
+    }
+
+    /// Set the initial timeout for retransmitting a DTLS handshake flight.
+    ///
+    /// A flight that isn't answered is sent again, with the timeout doubling each time
+    /// up to 60 seconds (RFC 6347). The RFC recommends 1 second, but since ICE has already
+    /// verified the path, a lower value gets through a lost packet quicker.
+    pub fn set_dtls_initial_rto(mut self, rto: Duration) -> Self {
+        self.dtls_initial_rto = rto;
+        self
+    }
+
+    /// Max number of retransmits of a DTLS handshake flight.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to 6.
+    /// assert_eq!(config.dtls_max_retransmits(), 6);
+    /// ```
+    pub fn dtls_max_retransmits(&self) -> usize {
+        self.dtls_max_retransmits
+    }
+
+    /// Set the max number of retransmits of a DTLS handshake flight.
+    ///
+    /// When the timeout passes after the last retransmit, the [`Rtc`] is disconnected and
+    /// [`Rtc::handle_input`] fails with
+    /// [`DtlsError::HandshakeTimeout`][crate::error::DtlsError::HandshakeTimeout].
+    pub fn set_dtls_max_retransmits(mut self, max: usize) -> Self {
+        self.dtls_max_retransmits = max;
+        self
+    }
+
+    /// Max size of DTLS datagrams.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to 1150.
+    /// assert_eq!(config.dtls_mtu(), 1150);
+    /// ```
+    pub fn dtls_mtu(&self) -> usize {
+        self.dtls_mtu
+    }
+
+    /// Set the max size of DTLS datagrams.
+    ///
+    /// Handshake messages that don't fit, typically a large certificate chain, are
+    /// fragmented. The default leaves room for tunnel overhead on a 1280 byte path.
+    /// Values below the minimum of the DTLS implementation (around 256 bytes) are
+    /// ignored.
+    pub fn set_dtls_mtu(mut self, mtu: usize) -> Self {
+        self.dtls_mtu = mtu;
+        self
+    }
+
     /// Tells whether ice lite is enabled.
     ///
     /// ```
@@ -1929,6 +2600,67 @@ impl RtcConfig {
         self.ice_lite
     }
 
+    /// Set how the candidate pair to use is nominated, when we are the controlling agent.
+    ///
+    /// [`NominationStrategy::Eager`] nominates the first pair that works, which gives the
+    /// fastest connection. [`NominationStrategy::Delayed`] waits a bit for pairs with
+    /// higher priority, such as a host pair that is slower to check than a relay pair.
+    ///
+    /// Defaults to [`NominationStrategy::Eager`].
+    pub fn set_ice_nomination(mut self, strategy: NominationStrategy) -> Self {
+        self.ice_nomination = strategy;
+        self
+    }
+
+    /// Get the nomination strategy.
+    ///
+    /// ```
+    /// # use str0m::{Rtc, NominationStrategy};
+    /// let config = Rtc::builder();
+    ///
+    /// assert_eq!(config.ice_nomination(), NominationStrategy::Eager);
+    /// ```
+    pub fn ice_nomination(&self) -> NominationStrategy {
+        self.ice_nomination
+    }
+
+    /// Toggle renomination. When we are the controlling agent, a pair with higher priority
+    /// that succeeds after the nomination is nominated instead.
+    ///
+    /// When disabled, the nominated pair is kept for as long as it works.
+    ///
+    /// Defaults to true.
+    pub fn set_ice_renomination(mut self, enabled: bool) -> Self {
+        self.ice_renomination = enabled;
+        self
+    }
+
+    /// Tells whether renomination is enabled.
+    pub fn ice_renomination(&self) -> bool {
+        self.ice_renomination
+    }
+
+    /// Set the type preferences used for the priority of local candidates.
+    ///
+    /// The priorities decide the order of the connectivity checks, and which
+    /// candidate pair is nominated.
+    ///
+    /// ```
+    /// # use str0m::{Rtc, CandidateKind, TypePreferences};
+    /// # use str0m::net::Protocol;
+    /// let prefs = TypePreferences::new().set(CandidateKind::Relayed, Protocol::Udp, 105);
+    /// let config = Rtc::builder().set_ice_type_preferences(prefs);
+    /// ```
+    pub fn set_ice_type_preferences(mut self, prefs: TypePreferences) -> Self {
+        self.ice_type_preferences = prefs;
+        self
+    }
+
+    /// Get the type preferences for local candidates.
+    pub fn ice_type_preferences(&self) -> TypePreferences {
+        self.ice_type_preferences
+    }
+
     /// Lower level access to precise configuration of codecs (payload types).
     pub fn codec_config(&mut self) -> &mut CodecConfig {
         &mut self.codec_config
@@ -2035,11 +2767,77 @@ impl RtcConfig {
         self
     }
 
+    /// Set the CNAME identifying this endpoint.
+    ///
+    /// The CNAME is shared by all SSRCs we send, in the SDP and in the SDES of every
+    /// compound RTCP packet. By default it is a random string of 16 characters, new for
+    /// each [`Rtc`], as recommended by [RFC 7022][1]. Avoid anything identifying, such as a
+    /// hostname or user name.
+    ///
+    /// The CNAME is fixed for the lifetime of the [`Rtc`].
+    ///
+    /// Panics if the CNAME is empty or longer than 255 bytes.
+    ///
+    /// [1]: https://www.rfc-editor.org/rfc/rfc7022
+    pub fn set_cname(mut self, cname: impl Into<String>) -> Self {
+        let cname = cname.into();
+        assert!(
+            !cname.is_empty() && cname.len() <= 255,
+            "CNAME must be 1-255 bytes"
+        );
+        self.cname = Some(cname);
+        self
+    }
+
+    /// The configured CNAME.
+    ///
+    /// None means a random CNAME is made for each [`Rtc`].
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to None.
+    /// assert_eq!(config.cname(), None);
+    /// ```
+    pub fn cname(&self) -> Option<&str> {
+        self.cname.as_deref()
+    }
+
+    /// Set the SDES NAME and TOOL items sent with the CNAME.
+    ///
+    /// Both are optional and off by default. They are sent in the first SDES chunk of
+    /// every compound RTCP packet.
+    ///
+    /// Panics if either is longer than 255 bytes.
+    pub fn set_sdes_items(mut self, name: Option<String>, tool: Option<String>) -> Self {
+        for v in name.iter().chain(tool.iter()) {
+            assert!(v.len() <= 255, "SDES item must be at most 255 bytes");
+        }
+        self.sdes_name = name;
+        self.sdes_tool = tool;
+        self
+    }
+
+    /// The configured SDES NAME and TOOL items.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to None.
+    /// assert_eq!(config.sdes_items(), (None, None));
+    /// ```
+    pub fn sdes_items(&self) -> (Option<&str>, Option<&str>) {
+        (self.sdes_name.as_deref(), self.sdes_tool.as_deref())
+    }
+
     /// Set the interval between statistics events.
     ///
     /// None turns off the stats events.
     ///
-    /// This includes [`MediaEgressStats`], [`MediaIngressStats`], [`MediaEgressStats`]
+    /// This includes [`PeerStats`], [`MediaIngressStats`], [`MediaEgressStats`] and
+    /// [`IngressStats`]. An interval of 1 second is typical.
     pub fn set_stats_interval(mut self, interval: Option<Duration>) -> Self {
         self.stats_interval = interval;
         self
@@ -2061,13 +2859,40 @@ impl RtcConfig {
         self.stats_interval
     }
 
+    /// Set the interval between [`Event::RtcStats`] snapshots.
+    ///
+    /// Zero turns off the snapshot events. Besides the interval, a snapshot is emitted
+    /// straight away when streams are added or removed, or the candidate pair changes.
+    ///
+    /// The snapshot is the same as [`Rtc::stats()`] and is independent of
+    /// [`RtcConfig::set_stats_interval()`].
+    pub fn set_rtc_stats_interval(mut self, interval: Duration) -> Self {
+        self.rtc_stats_interval = interval;
+        self
+    }
+
+    /// The configured interval between [`Event::RtcStats`] snapshots.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use std::time::Duration;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to zero, disabled.
+    /// assert_eq!(config.rtc_stats_interval(), Duration::ZERO);
+    /// ```
+    pub fn rtc_stats_interval(&self) -> Duration {
+        self.rtc_stats_interval
+    }
+
     /// Enables estimation of available bandwidth (BWE).
     ///
     /// None disables the BWE. This is an estimation of the send bandwidth, not receive.
     ///
-    /// This includes setting the initial estimate to start with.
+    /// This includes setting the initial estimate to start with. See
+    /// [`Self::set_bwe_config()`] to also set bounds for the estimate.
     pub fn enable_bwe(mut self, initial_estimate: Option<Bitrate>) -> Self {
-        self.bwe_initial_bitrate = initial_estimate;
+        self.bwe_config = initial_estimate.map(BweConfig::new);
 
         self
     }
@@ -2082,7 +2907,88 @@ impl RtcConfig {
     /// assert_eq!(config.bwe_initial_bitrate(), None);
     /// ```
     pub fn bwe_initial_bitrate(&self) -> Option<Bitrate> {
-        self.bwe_initial_bitrate
+        self.bwe_config.map(|c| c.start_bitrate())
+    }
+
+    /// Enables estimation of available bandwidth (BWE) with a start bitrate and bounds.
+    ///
+    /// None disables the BWE, like [`Self::enable_bwe()`].
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use str0m::bwe::{Bitrate, BweConfig};
+    /// let bwe = BweConfig::new(Bitrate::mbps(2))
+    ///     .set_min_bitrate(Bitrate::kbps(64))
+    ///     .set_max_bitrate(Bitrate::mbps(5));
+    ///
+    /// let config = Rtc::builder().set_bwe_config(Some(bwe));
+    ///
+    /// assert_eq!(config.bwe_initial_bitrate(), Some(Bitrate::mbps(2)));
+    /// ```
+    pub fn set_bwe_config(mut self, config: Option<BweConfig>) -> Self {
+        self.bwe_config = config;
+        self
+    }
+
+    /// The BWE config as set by [`Self::set_bwe_config()`] or [`Self::enable_bwe()`].
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to None - BWE off.
+    /// assert_eq!(config.bwe_config(), None);
+    /// ```
+    pub fn bwe_config(&self) -> Option<BweConfig> {
+        self.bwe_config
+    }
+
+    /// Sets the bandwidth and interval bounds for the regular RTCP reports.
+    ///
+    /// None, the default, sends reports every second for video and every 5 seconds for
+    /// audio, which is what libWebRTC expects. With a config, the interval follows from
+    /// the RTCP bandwidth and the number of SSRCs in the session, see [`RtcpConfig`].
+    pub fn set_rtcp_config(mut self, config: Option<RtcpConfig>) -> Self {
+        self.rtcp_config = config;
+        self
+    }
+
+    /// The RTCP config as set by [`Self::set_rtcp_config()`].
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to None - fixed intervals.
+    /// assert_eq!(config.rtcp_config(), None);
+    /// ```
+    pub fn rtcp_config(&self) -> Option<RtcpConfig> {
+        self.rtcp_config
+    }
+
+    /// Sets whether RTCP must be multiplexed with RTP on the same port (`a=rtcp-mux`).
+    ///
+    /// Defaults to [`RtcpMuxPolicy::Require`], which offers `a=rtcp-mux-only` and fails the
+    /// negotiation with a peer that doesn't do mux. [`RtcpMuxPolicy::Negotiate`] is for peers
+    /// that want RTCP on a separate ICE component, which needs local candidates made with
+    /// [`Candidate::with_rtcp_component()`].
+    pub fn set_rtcp_mux_policy(mut self, policy: RtcpMuxPolicy) -> Self {
+        self.rtcp_mux_policy = policy;
+        self
+    }
+
+    /// The RTCP mux policy as set by [`Self::set_rtcp_mux_policy()`].
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use str0m::change::RtcpMuxPolicy;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to requiring a=rtcp-mux.
+    /// assert_eq!(config.rtcp_mux_policy(), RtcpMuxPolicy::Require);
+    /// ```
+    pub fn rtcp_mux_policy(&self) -> RtcpMuxPolicy {
+        self.rtcp_mux_policy
     }
 
     /// Sets the number of packets held back for reordering audio packets.
@@ -2151,6 +3057,128 @@ impl RtcConfig {
         self.reordering_size_video
     }
 
+    /// Output depacketized H264 with a length prefix instead of Annex B start codes.
+    ///
+    /// By default every NAL unit in [`MediaData::data`][crate::media::MediaData::data] is
+    /// preceded by a start code `00 00 00 01` (Annex B). When enabled, each NAL unit is
+    /// instead preceded by its length as a 4 byte big endian integer (AVC format).
+    ///
+    /// The same format is expected for H264 written with [`Writer::write`][crate::media::Writer::write].
+    ///
+    /// This setting is ignored in [RTP mode][`RtcConfig::set_rtp_mode()`].
+    pub fn set_h264_length_prefixed(mut self, enabled: bool) -> Self {
+        self.h264_length_prefixed = enabled;
+
+        self
+    }
+
+    /// Checks if depacketized H264 is length prefixed.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to false.
+    /// assert_eq!(config.h264_length_prefixed(), false);
+    /// ```
+    pub fn h264_length_prefixed(&self) -> bool {
+        self.h264_length_prefixed
+    }
+
+    /// Set a function to resolve remote mDNS hostnames (`<uuid>.local`).
+    ///
+    /// It is called when a remote candidate with a hostname is added. If it returns
+    /// `None`, the name is instead emitted as [`Event::ResolveMdns`].
+    ///
+    /// Defaults to not set.
+    pub fn set_mdns_resolver(
+        mut self,
+        resolve: impl Fn(&str) -> Option<IpAddr> + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Self {
+        self.mdns.resolve = Some(Arc::new(resolve));
+        self
+    }
+
+    /// Set a function to register mDNS hostnames for local host candidates.
+    ///
+    /// It is called with the IP of each host candidate added with
+    /// [`Rtc::add_local_candidate()`], and should register a random `<uuid>.local` name for
+    /// it with the mDNS responder. The name is then signalled instead of the IP. If it returns
+    /// `None`, the IP is used.
+    ///
+    /// ```
+    /// # use str0m::{Rtc, Candidate};
+    /// let mut rtc = Rtc::builder()
+    ///     .set_mdns_register(|_ip| Some("4a6b1c2d-9e8f.local".into()))
+    ///     .build();
+    ///
+    /// let a = "192.168.1.2:5000".parse().unwrap();
+    /// rtc.add_local_candidate(Candidate::host(a, "udp").unwrap());
+    /// ```
+    ///
+    /// Defaults to not set.
+    pub fn set_mdns_register(
+        mut self,
+        register: impl Fn(IpAddr) -> Option<String> + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Self {
+        self.mdns.register = Some(Arc::new(register));
+        self
+    }
+
+    /// Set custom packetizers and depacketizers, used instead of the built in ones.
+    ///
+    /// See [`CodecRegistry`] for an example. The registry is ignored for receiving in
+    /// [RTP mode][`RtcConfig::set_rtp_mode()`].
+    pub fn set_codec_registry(mut self, registry: CodecRegistry) -> Self {
+        self.codec_registry = registry;
+
+        self
+    }
+
+    /// The custom packetizers and depacketizers.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use str0m::format::Codec;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to empty.
+    /// assert!(!config.codec_registry().contains(Codec::Vp8));
+    /// ```
+    pub fn codec_registry(&self) -> &CodecRegistry {
+        &self.codec_registry
+    }
+
+    /// Receive video wrapped in RED and recover lost packets with ULPFEC.
+    ///
+    /// Given as the payload types `(red, ulpfec)`. RED and ULPFEC are not negotiated
+    /// in the SDP, the payload types must be agreed with the remote peer some other way.
+    /// Packets with the RED payload type are unwrapped to the media payload type inside,
+    /// and recovered packets are passed on as if they were received, with
+    /// [`RtpPacket::recovered`][crate::rtp::RtpPacket::recovered] set.
+    ///
+    /// A lost packet is recovered when it is the only one missing of those protected
+    /// by an ULPFEC packet. The receive stream must already be known, RED packets are
+    /// not used to map streams dynamically.
+    pub fn set_ulpfec_receive(mut self, pts: Option<(Pt, Pt)>) -> Self {
+        self.ulpfec_receive = pts;
+
+        self
+    }
+
+    /// The payload types for RED and ULPFEC, if set.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to None.
+    /// assert_eq!(config.ulpfec_receive(), None);
+    /// ```
+    pub fn ulpfec_receive(&self) -> Option<(Pt, Pt)> {
+        self.ulpfec_receive
+    }
+
     /// Sets the buffer size for outgoing audio packets.
     ///
     /// This must be larger than 0. The value configures an internal ring buffer used as a temporary
@@ -2212,12 +3240,110 @@ impl RtcConfig {
         self.send_buffer_video
     }
 
+    /// Sets the size of the SRTP/SRTCP replay window.
+    ///
+    /// Incoming packets that were already received, or are older than this many packets
+    /// behind the newest one, are dropped. There is one window per SSRC.
+    /// See [RFC 3711](https://www.rfc-editor.org/rfc/rfc3711#section-3.3.2).
+    ///
+    /// The size is rounded up to a multiple of 64, and is at least 64.
+    pub fn set_srtp_replay_window(mut self, size: usize) -> Self {
+        self.srtp_replay_window = size;
+        self
+    }
+
+    /// Returns the setting for the SRTP/SRTCP replay window.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to 1024.
+    /// assert_eq!(config.srtp_replay_window(), 1024);
+    /// ```
+    pub fn srtp_replay_window(&self) -> usize {
+        self.srtp_replay_window
+    }
+
+    /// Sets the number of SRTP packets that can be protected/unprotected with one key.
+    ///
+    /// Counted per direction. [`Event::SrtpKeyExpiring`] is emitted when 90% of this is
+    /// used. The max, and default, is 2^48 as per
+    /// [RFC 3711](https://www.rfc-editor.org/rfc/rfc3711#section-9.2).
+    pub fn set_srtp_key_lifetime(mut self, packets: u64) -> Self {
+        self.srtp_key_lifetime = packets.min(MAX_SRTP_KEY_LIFETIME);
+        self
+    }
+
+    /// Returns the setting for the SRTP key lifetime.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to 2^48.
+    /// assert_eq!(config.srtp_key_lifetime(), 1 << 48);
+    /// ```
+    pub fn srtp_key_lifetime(&self) -> u64 {
+        self.srtp_key_lifetime
+    }
+
+    /// Sets the max number of remote SSRCs to keep SRTP/SRTCP receive state for.
+    ///
+    /// The state is the rollover counter and replay window per SSRC. When more SSRCs
+    /// are in use, the least recently used one is dropped, counted in
+    /// [`PeerStats::srtp_ssrcs_evicted`]. Should a dropped SSRC come back, only packets
+    /// newer than the last one seen are accepted. The min is 1.
+    pub fn set_srtp_max_ssrcs(mut self, max: usize) -> Self {
+        self.srtp_max_ssrcs = max.max(1);
+        self
+    }
+
+    /// Returns the setting for the max number of remote SSRCs with SRTP state.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to 1000.
+    /// assert_eq!(config.srtp_max_ssrcs(), 1000);
+    /// ```
+    pub fn srtp_max_ssrcs(&self) -> usize {
+        self.srtp_max_ssrcs
+    }
+
+    /// Sets how long a remote SSRC can be silent before its SRTP/SRTCP receive state
+    /// is dropped.
+    ///
+    /// The state is also dropped when the remote sends an RTCP BYE for the SSRC.
+    pub fn set_srtp_ssrc_idle_timeout(mut self, idle: Duration) -> Self {
+        self.srtp_ssrc_idle_timeout = idle;
+        self
+    }
+
+    /// Returns the setting for the SRTP SSRC idle timeout.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use std::time::Duration;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to 5 minutes.
+    /// assert_eq!(config.srtp_ssrc_idle_timeout(), Duration::from_secs(300));
+    /// ```
+    pub fn srtp_ssrc_idle_timeout(&self) -> Duration {
+        self.srtp_ssrc_idle_timeout
+    }
+
     /// Make the entire Rtc be in RTP mode.
     ///
     /// This means all media, read from [`RtpPacket`] and written to
     /// [`StreamTx::write_rtp`][crate::rtp::StreamTx::write_rtp] are RTP packetized.
     /// It bypasses all internal packetization/depacketization inside str0m.
     ///
+    /// This is the default for every media. It can be changed per media line with
+    /// [`SdpApi::set_rtp_mode()`][crate::change::SdpApi::set_rtp_mode].
+    ///
     /// WARNING: This is a low level API and is not str0m's primary use case.
     pub fn set_rtp_mode(mut self, enabled: bool) -> Self {
         self.rtp_mode = enabled;
@@ -2238,6 +3364,39 @@ impl RtcConfig {
         self.rtp_mode
     }
 
+    /// Set the NACK, RTX and TWCC processing for all media.
+    ///
+    /// This is the default for every media. It can be changed per media with
+    /// [`SdpApi::set_media_features()`][crate::change::SdpApi::set_media_features] or
+    /// [`DirectApi::set_media_features()`][crate::change::DirectApi::set_media_features].
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use str0m::media::MediaFeatures;
+    /// // Recording only, decrypt and depacketize.
+    /// let rtc = Rtc::builder()
+    ///     .set_media_features(MediaFeatures::none())
+    ///     .build();
+    /// ```
+    pub fn set_media_features(mut self, features: MediaFeatures) -> Self {
+        self.media_features = features;
+        self
+    }
+
+    /// The NACK, RTX and TWCC processing for all media.
+    ///
+    /// ```
+    /// # use str0m::Rtc;
+    /// # use str0m::media::MediaFeatures;
+    /// let config = Rtc::builder();
+    ///
+    /// // Defaults to everything on.
+    /// assert_eq!(config.media_features(), MediaFeatures::all());
+    /// ```
+    pub fn media_features(&self) -> MediaFeatures {
+        self.media_features
+    }
+
     /// Enable the [`Event::RawPacket`] event.
     ///
     /// This clones data, and is therefore expensive.
@@ -2259,17 +3418,38 @@ impl Default for RtcConfig {
             local_ice_credentials: None,
             dtls_cert: None,
             fingerprint_verification: true,
+            dtls_initial_rto: Duration::from_secs(1),
+            dtls_max_retransmits: 6,
+            dtls_mtu: DATAGRAM_MTU,
             ice_lite: false,
+            ice_nomination: NominationStrategy::Eager,
+            ice_renomination: true,
+            ice_type_preferences: TypePreferences::new(),
             codec_config: CodecConfig::new_with_defaults(),
             exts: ExtensionMap::standard(),
             stats_interval: None,
-            bwe_initial_bitrate: None,
+            rtc_stats_interval: Duration::ZERO,
+            bwe_config: None,
             reordering_size_audio: 15,
             reordering_size_video: 30,
+            h264_length_prefixed: false,
+            codec_registry: CodecRegistry::default(),
+            ulpfec_receive: None,
             send_buffer_audio: 50,
             send_buffer_video: 1000,
+            srtp_replay_window: DEFAULT_REPLAY_WINDOW,
+            srtp_key_lifetime: MAX_SRTP_KEY_LIFETIME,
+            srtp_max_ssrcs: DEFAULT_MAX_SSRCS,
+            srtp_ssrc_idle_timeout: DEFAULT_SSRC_IDLE,
             rtp_mode: false,
+            media_features: MediaFeatures::all(),
             enable_raw_packets: false,
+            cname: None,
+            sdes_name: None,
+            sdes_tool: None,
+            rtcp_config: None,
+            rtcp_mux_policy: RtcpMuxPolicy::Require,
+            mdns: MdnsHooks::default(),
         }
     }
 }
@@ -2278,11 +3458,15 @@ impl PartialEq for Event {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::IceConnectionStateChange(l0), Self::IceConnectionStateChange(r0)) => l0 == r0,
+            (Self::DtlsStateChange(l0), Self::DtlsStateChange(r0)) => l0 == r0,
+            (Self::ResolveMdns(l0), Self::ResolveMdns(r0)) => l0 == r0,
             (Self::MediaAdded(m0), Self::MediaAdded(m1)) => m0 == m1,
             (Self::MediaData(m1), Self::MediaData(m2)) => m1 == m2,
             (Self::ChannelOpen(l0, l1), Self::ChannelOpen(r0, r1)) => l0 == r0 && l1 == r1,
             (Self::ChannelData(l0), Self::ChannelData(r0)) => l0 == r0,
             (Self::ChannelClose(l0), Self::ChannelClose(r0)) => l0 == r0,
+            (Self::NetworkRouteChange(l0), Self::NetworkRouteChange(r0)) => l0 == r0,
+            (Self::RtcpPacket(l0), Self::RtcpPacket(r0)) => l0 == r0,
             _ => false,
         }
     }
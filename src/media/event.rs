@@ -5,19 +5,45 @@ use std::time::Instant;
 use crate::packet::MediaKind;
 use crate::rtp_::{Direction, ExtensionValues, MediaTime, Mid, Pt, Rid, SenderInfo, SeqNo};
 use crate::sdp::Simulcast as SdpSimulcast;
+use crate::sdp::{RestrictionId, SimulcastAlternatives, SimulcastGroups};
 
 use super::PayloadParams;
 use crate::format::CodecExtra;
 
 impl From<SdpSimulcast> for Simulcast {
     fn from(s: SdpSimulcast) -> Self {
-        let send = s.send.iter().map(|r| r.0.as_ref()).map(Rid::from).collect();
-        let recv = s.recv.iter().map(|r| r.0.as_ref()).map(Rid::from).collect();
+        // We send the first alternative, but can receive any of them.
+        let send = s
+            .send
+            .iter()
+            .map(|a| a.first().0.as_ref())
+            .map(Rid::from)
+            .collect();
+        let recv = s.recv.rids().map(|r| r.0.as_ref()).map(Rid::from).collect();
 
         Simulcast { send, recv }
     }
 }
 
+impl From<Simulcast> for SdpSimulcast {
+    fn from(s: Simulcast) -> Self {
+        fn to_groups(rids: Vec<Rid>) -> SimulcastGroups {
+            let alts = rids
+                .iter()
+                .map(|r| SimulcastAlternatives(vec![RestrictionId::new_active(r.to_string())]))
+                .collect();
+            SimulcastGroups(alts)
+        }
+
+        SdpSimulcast {
+            send: to_groups(s.send),
+            recv: to_groups(s.recv),
+            restrictions: vec![],
+            is_munged: false,
+        }
+    }
+}
+
 /// A new media appeared in an Rtc session.
 ///
 /// This event fires both for negotiations triggered by a remote or local offer.
@@ -59,7 +85,7 @@ pub struct MediaChanged {
 /// The [full spec][1] covers many cases that are not used by simple simulcast.
 ///
 /// [1]: https://datatracker.ietf.org/doc/html/draft-ietf-mmusic-sdp-simulcast-14
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Simulcast {
     /// The RID used for sending simulcast.
     pub send: Vec<Rid>,
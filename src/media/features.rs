@@ -0,0 +1,56 @@
+/// Processing done per media, which can be turned off to save CPU and memory.
+///
+/// Turning something off means the state for it is never created, not only that its output
+/// is suppressed. This is for deployments such as recording, where incoming media only needs
+/// SRTP decryption and depacketizing. Stats that depend on a disabled feature are reported
+/// as `None` in [`RtcStats`][crate::stats::RtcStats].
+///
+/// The features are set before the media has any streams, with
+/// [`RtcConfig::set_media_features()`][crate::RtcConfig::set_media_features] as the default
+/// for all media, or per media with
+/// [`SdpApi::set_media_features()`][crate::change::SdpApi::set_media_features] and
+/// [`DirectApi::set_media_features()`][crate::change::DirectApi::set_media_features].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaFeatures {
+    /// Track losses on incoming streams and send NACK for them.
+    ///
+    /// When off, `nack` is not offered in the SDP.
+    pub nack: bool,
+
+    /// Keep a cache of sent packets for resends, and answer NACK with RTX.
+    ///
+    /// When off, no RTX SSRC or payload type is used for the media.
+    pub rtx: bool,
+
+    /// Record incoming packets for the transport wide congestion control feedback.
+    ///
+    /// When off, `transport-cc` and the transport wide sequence number header extension are
+    /// not offered in the SDP for the media.
+    pub twcc: bool,
+}
+
+impl MediaFeatures {
+    /// Everything on. This is the default.
+    pub const fn all() -> Self {
+        MediaFeatures {
+            nack: true,
+            rtx: true,
+            twcc: true,
+        }
+    }
+
+    /// Everything off.
+    pub const fn none() -> Self {
+        MediaFeatures {
+            nack: false,
+            rtx: false,
+            twcc: false,
+        }
+    }
+}
+
+impl Default for MediaFeatures {
+    fn default() -> Self {
+        Self::all()
+    }
+}
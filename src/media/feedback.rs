@@ -0,0 +1,146 @@
+use crate::format::PayloadParams;
+
+use super::KeyframeRequestKind;
+
+/// RTCP feedback negotiated for a media.
+///
+/// In the SDP API, a feedback mechanic is enabled when both sides have a matching
+/// `a=rtcp-fb` line for any of the payload types of the media. Feedback that isn't
+/// negotiated is never sent, since some endpoints treat unexpected feedback as a protocol
+/// error.
+///
+/// In the direct API, all feedback is enabled unless set otherwise with
+/// [`Media::set_feedback_caps()`][crate::media::Media::set_feedback_caps].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeedbackCaps {
+    /// NACK, `a=rtcp-fb:<pt> nack`.
+    pub nack: bool,
+
+    /// PLI, `a=rtcp-fb:<pt> nack pli`.
+    pub pli: bool,
+
+    /// FIR, `a=rtcp-fb:<pt> ccm fir`.
+    pub fir: bool,
+
+    /// Transport wide congestion control, `a=rtcp-fb:<pt> transport-cc`.
+    ///
+    /// TWCC feedback is session wide, and is sent if any media has this together with the
+    /// transport sequence number header extension. The sequence number is only written on
+    /// packets of media that have this.
+    pub twcc: bool,
+
+    /// REMB, `a=rtcp-fb:<pt> goog-remb`.
+    pub remb: bool,
+}
+
+impl FeedbackCaps {
+    /// All feedback enabled.
+    pub const fn all() -> Self {
+        FeedbackCaps {
+            nack: true,
+            pli: true,
+            fir: true,
+            twcc: true,
+            remb: true,
+        }
+    }
+
+    /// No feedback enabled.
+    pub const fn none() -> Self {
+        FeedbackCaps {
+            nack: false,
+            pli: false,
+            fir: false,
+            twcc: false,
+            remb: false,
+        }
+    }
+
+    /// Add the feedback both the local and remote params have.
+    pub(crate) fn add_negotiated(&mut self, local: &PayloadParams, remote: &PayloadParams) {
+        self.nack |= local.fb_nack && remote.fb_nack;
+        self.pli |= local.fb_pli && remote.fb_pli;
+        self.fir |= local.fb_fir && remote.fb_fir;
+        self.twcc |= local.fb_transport_cc && remote.fb_transport_cc;
+        self.remb |= local.fb_remb && remote.fb_remb;
+    }
+
+    /// The keyframe request to send for the one asked for.
+    ///
+    /// FIR falls back on PLI and the other way around. None if neither is negotiated.
+    pub(crate) fn keyframe_request(
+        &self,
+        kind: KeyframeRequestKind,
+    ) -> Option<KeyframeRequestKind> {
+        use KeyframeRequestKind::*;
+
+        match kind {
+            Pli if self.pli => Some(Pli),
+            Fir if self.fir => Some(Fir),
+            _ if self.pli => Some(Pli),
+            _ if self.fir => Some(Fir),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::{Codec, CodecSpec};
+    use crate::media::Frequency;
+
+    #[test]
+    fn keyframe_request_fallback() {
+        use KeyframeRequestKind::*;
+
+        let mut caps = FeedbackCaps::none();
+        assert_eq!(caps.keyframe_request(Pli), None);
+        assert_eq!(caps.keyframe_request(Fir), None);
+
+        caps.pli = true;
+        assert_eq!(caps.keyframe_request(Pli), Some(Pli));
+        assert_eq!(caps.keyframe_request(Fir), Some(Pli));
+
+        caps.pli = false;
+        caps.fir = true;
+        assert_eq!(caps.keyframe_request(Pli), Some(Fir));
+        assert_eq!(caps.keyframe_request(Fir), Some(Fir));
+
+        let caps = FeedbackCaps::all();
+        assert_eq!(caps.keyframe_request(Pli), Some(Pli));
+        assert_eq!(caps.keyframe_request(Fir), Some(Fir));
+    }
+
+    #[test]
+    fn negotiated_is_both_sides() {
+        let local = PayloadParams::new(
+            96.into(),
+            None,
+            CodecSpec {
+                codec: Codec::Vp8,
+                clock_rate: Frequency::NINETY_KHZ,
+                channels: None,
+                format: Default::default(),
+            },
+        );
+
+        let mut remote = local;
+        remote.set_fb_nack(false);
+        remote.set_fb_remb(false);
+
+        let mut caps = FeedbackCaps::none();
+        caps.add_negotiated(&local, &remote);
+
+        assert_eq!(
+            caps,
+            FeedbackCaps {
+                nack: false,
+                pli: true,
+                fir: true,
+                twcc: true,
+                remb: false,
+            }
+        );
+    }
+}
@@ -4,9 +4,9 @@ use std::collections::{HashMap, VecDeque};
 use std::time::Instant;
 
 use crate::change::AddMedia;
-use crate::format::CodecConfig;
+use crate::format::{CodecConfig, CodecFrameInfo, CodecRegistry, OpusParams};
 use crate::io::{Id, DATAGRAM_MTU};
-use crate::packet::{DepacketizingBuffer, Payloader, RtpMeta};
+use crate::packet::{CodecDepacketizer, CodecPacketizer, DepacketizingBuffer, Payloader, RtpMeta};
 use crate::rtp_::ExtensionMap;
 use crate::rtp_::SRTP_BLOCK_SIZE;
 use crate::rtp_::SRTP_OVERHEAD;
@@ -24,7 +24,16 @@ pub use event::*;
 mod writer;
 pub use writer::Writer;
 
+mod feedback;
+pub use feedback::FeedbackCaps;
+
+mod features;
+pub use features::MediaFeatures;
+
 pub use crate::packet::MediaKind;
+pub use crate::packet::{AudioLevelAggregator, SpeakerRanking};
+pub use crate::packet::{SourceSwitcher, SwitchOutput};
+pub use crate::packet::{SyncGroups, SyncOffset};
 pub use crate::rtp_::{Direction, ExtensionValues, Frequency, MediaTime, Mid, Pt, Rid};
 
 #[derive(Debug)]
@@ -97,6 +106,39 @@ pub struct Media {
     /// SDP property.
     simulcast: Option<SdpSimulcast>,
 
+    /// [`true`] if this media is in RTP mode, bypassing payloaders and depayloaders.
+    ///
+    /// Defaults to [`RtcConfig::set_rtp_mode()`][crate::RtcConfig::set_rtp_mode] and can be
+    /// set per m-line via [`SdpApi::set_rtp_mode()`][crate::change::SdpApi::set_rtp_mode].
+    rtp_mode: bool,
+
+    /// RTCP feedback we are allowed to send for this media.
+    ///
+    /// SDP property.
+    feedback_caps: FeedbackCaps,
+
+    /// Opus parameters negotiated for this media.
+    ///
+    /// SDP property.
+    opus_params: Option<OpusParams>,
+
+    /// NACK, RTX and TWCC processing for this media.
+    ///
+    /// RTP level.
+    features: MediaFeatures,
+
+    /// Whether the incoming simulcast layers are from `a=ssrc-group:SIM` rather than rid.
+    ///
+    /// SDP property.
+    sim_group: bool,
+
+    /// Whether the m-line is removed or rejected, with port 0.
+    ///
+    /// The m-line stays to keep the order of the m-lines, and the mid is not reused.
+    ///
+    /// SDP property.
+    disabled: bool,
+
     // ========================================= Payloaders, etc =========================================
     //
     /// Buffers of incoming RTP packets. These do reordering/jitter buffer and also
@@ -152,6 +194,7 @@ pub(crate) struct ToPayload {
     pub rtp_time: MediaTime,
     pub data: Vec<u8>,
     pub ext_vals: ExtensionValues,
+    pub frame_info: Option<CodecFrameInfo>,
 }
 
 impl Media {
@@ -166,8 +209,9 @@ impl Media {
     ///
     /// Persistent transport-level identifier for an RTP source.
     ///
-    /// RTP level property. The value is sent in RTCP reports for `StreamTx`. Incoming
-    /// cnames can be found in [`StreamRx::cname`][crate::rtp::StreamRx::cname].
+    /// RTP level property. The same for all media, see
+    /// [`RtcConfig::set_cname()`][crate::RtcConfig::set_cname]. The value is sent in the
+    /// SDES of every compound RTCP packet. Incoming cnames can be found in [`StreamRx::cname`][crate::rtp::StreamRx::cname].
     pub fn cname(&self) -> &str {
         &self.cname
     }
@@ -232,6 +276,82 @@ impl Media {
         self.dir
     }
 
+    /// Whether this media is removed by an SDP negotiation.
+    ///
+    /// This is also the case for a rejected m-line, either by us since we have none of the
+    /// offered codecs, or by the remote answering with port 0.
+    ///
+    /// A removed media is [`Direction::Inactive`] and has no streams. It stays around since
+    /// m-lines are never removed from the SDP, but is written with port 0.
+    ///
+    /// See [`SdpApi::remove_media()`][crate::change::SdpApi::remove_media].
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub(crate) fn set_disabled(&mut self) {
+        self.disabled = true;
+        self.set_direction(Direction::Inactive);
+    }
+
+    /// Whether this media is in RTP mode.
+    ///
+    /// In RTP mode, incoming packets are emitted as [`Event::RtpPacket`][crate::Event::RtpPacket]
+    /// and outgoing packets are written with
+    /// [`StreamTx::write_rtp()`][crate::rtp::StreamTx::write_rtp]. The sample level
+    /// [`Writer`][crate::media::Writer] is not available.
+    pub fn rtp_mode(&self) -> bool {
+        self.rtp_mode
+    }
+
+    pub(crate) fn set_rtp_mode(&mut self, enabled: bool) {
+        self.rtp_mode = enabled;
+    }
+
+    /// The RTCP feedback negotiated for this media.
+    ///
+    /// Requests for feedback that isn't negotiated are dropped, apart from keyframe
+    /// requests that use PLI instead of FIR, or the other way around, when only one of
+    /// them is negotiated.
+    pub fn feedback_caps(&self) -> FeedbackCaps {
+        self.feedback_caps
+    }
+
+    /// The Opus parameters negotiated for this media.
+    ///
+    /// None if no Opus is negotiated, or for the direct API.
+    pub fn opus_params(&self) -> Option<&OpusParams> {
+        self.opus_params.as_ref()
+    }
+
+    pub(crate) fn set_opus_params(&mut self, params: Option<OpusParams>) {
+        self.opus_params = params;
+    }
+
+    /// The NACK, RTX and TWCC processing for this media.
+    pub fn features(&self) -> MediaFeatures {
+        self.features
+    }
+
+    pub(crate) fn set_features(&mut self, features: MediaFeatures) {
+        self.features = features;
+    }
+
+    pub(crate) fn has_sim_group(&self) -> bool {
+        self.sim_group
+    }
+
+    pub(crate) fn set_sim_group(&mut self, sim_group: bool) {
+        self.sim_group = sim_group;
+    }
+
+    /// Set the RTCP feedback allowed for this media.
+    ///
+    /// This is for the direct API. In the SDP API, the feedback is set by the negotiation.
+    pub fn set_feedback_caps(&mut self, caps: FeedbackCaps) {
+        self.feedback_caps = caps;
+    }
+
     pub(crate) fn simulcast(&self) -> Option<&SdpSimulcast> {
         self.simulcast.as_ref()
     }
@@ -255,7 +375,7 @@ impl Media {
                     network_time: dep.first_network_time(),
                     seq_range: dep.seq_range(),
                     contiguous: dep.contiguous,
-                    ext_vals: dep.ext_vals().clone(),
+                    ext_vals: dep.ext_vals(),
                     codec_extra: dep.codec_extra,
                     last_sender_info: dep.first_sender_info(),
                     data: dep.data,
@@ -265,12 +385,15 @@ impl Media {
         Ok(None)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn depayload(
         &mut self,
         rid: Option<Rid>,
         packet: RtpPacket,
         reordering_size_audio: usize,
         reordering_size_video: usize,
+        h264_length_prefixed: bool,
+        registry: &CodecRegistry,
         params: &[PayloadParams],
     ) {
         if !self.dir.is_receiving() {
@@ -297,7 +420,16 @@ impl Media {
                 reordering_size_video
             };
 
-            let buffer = DepacketizingBuffer::new(codec.into(), hold_back);
+            let mut depack = registry.depacketizer(pt, codec);
+            match &mut depack {
+                CodecDepacketizer::H264(h264) => h264.is_avc = h264_length_prefixed,
+                CodecDepacketizer::H265(h265) => {
+                    h265.with_donl(params.spec.format.sprop_max_don_diff.unwrap_or(0) > 0)
+                }
+                _ => {}
+            }
+
+            let buffer = DepacketizingBuffer::new(depack, hold_back);
 
             self.depayloaders.insert((pt, rid), buffer);
         }
@@ -316,6 +448,15 @@ impl Media {
         buffer.push(meta, packet.payload);
     }
 
+    /// Reset the depacketizing after the incoming stream restarted.
+    pub(crate) fn reset_depayloaders(&mut self, rid: Option<Rid>) {
+        for ((_, r), buffer) in &mut self.depayloaders {
+            if *r == rid {
+                buffer.reset();
+            }
+        }
+    }
+
     pub(crate) fn set_cname(&mut self, cname: String) {
         self.cname = cname;
     }
@@ -329,21 +470,44 @@ impl Media {
         self.dir = new_dir;
     }
 
-    pub(crate) fn set_simulcast(&mut self, s: SdpSimulcast) {
+    pub(crate) fn set_simulcast(&mut self, s: Option<SdpSimulcast>) {
+        if self.simulcast == s {
+            return;
+        }
         info!("Set simulcast: {:?}", s);
-        self.simulcast = Some(s);
+
+        // The rids we expect follow the negotiation, also when layers are removed.
+        let recv = s.as_ref().map(|s| &s.recv).filter(|r| !r.is_empty());
+        if let Some(recv) = recv {
+            self.rids_rx = Rids::Specific(recv.rids().map(|r| r.0.as_str().into()).collect());
+        } else if self
+            .simulcast
+            .as_ref()
+            .map_or(false, |s| !s.recv.is_empty())
+        {
+            self.rids_rx = Rids::Any;
+        }
+
+        self.simulcast = s;
     }
 
     fn payloader_for(
         &mut self,
         pt: Pt,
         rid: Option<Rid>,
+        registry: &CodecRegistry,
         params: &[PayloadParams],
+        h264_length_prefixed: bool,
     ) -> &mut Payloader {
         self.payloaders.entry((pt, rid)).or_insert_with(|| {
             // Unwrap is OK, the pt should be checked already when calling this function.
             let params = params.iter().find(|p| p.pt == pt).unwrap();
-            Payloader::new(params.spec)
+            let mut pack = registry.packetizer(pt, params.spec.codec);
+            if let CodecPacketizer::H264(h264) = &mut pack {
+                h264.is_avc = h264_length_prefixed;
+                h264.single_nal = params.spec.format.packetization_mode == Some(0);
+            }
+            Payloader::new(params.spec, pack)
         })
     }
 
@@ -369,7 +533,9 @@ impl Media {
         &mut self,
         now: Instant,
         streams: &mut Streams,
+        registry: &CodecRegistry,
         params: &[PayloadParams],
+        h264_length_prefixed: bool,
     ) -> Result<(), RtcError> {
         let Some(to_payload) = self.to_payload.pop_front() else {
             return Ok(());
@@ -387,14 +553,18 @@ impl Media {
 
         let pt = *pt;
 
-        let payloader = self.payloader_for(pt, *rid, params);
-
         const RTP_SIZE: usize = DATAGRAM_MTU - SRTP_OVERHEAD;
+
+        // The payload gets what remains after the header and negotiated extensions.
+        let header_size = stream.rtp_header_size(now, pt, &to_payload.ext_vals, &self.remote_exts);
+        let mtu = RTP_SIZE.saturating_sub(header_size);
         // align to SRTP block size to minimize padding needs
-        const MTU: usize = RTP_SIZE - RTP_SIZE % SRTP_BLOCK_SIZE;
+        let mtu = mtu - mtu % SRTP_BLOCK_SIZE;
+
+        let payloader = self.payloader_for(pt, *rid, registry, params, h264_length_prefixed);
 
         payloader
-            .push_sample(now, to_payload, MTU, is_audio, stream)
+            .push_sample(now, to_payload, mtu, is_audio, stream)
             .map_err(|e| RtcError::Packet(self.mid, pt, e))?;
 
         Ok(())
@@ -472,6 +642,12 @@ impl Default for Media {
             remote_created: false,
             dir: Direction::SendRecv,
             simulcast: None,
+            rtp_mode: false,
+            feedback_caps: FeedbackCaps::all(),
+            opus_params: None,
+            features: MediaFeatures::all(),
+            sim_group: false,
+            disabled: false,
             rids_rx: Rids::Any,
             payloaders: HashMap::new(),
             depayloaders: HashMap::new(),
@@ -517,6 +693,9 @@ impl Media {
             remote_pts: a.pts,
             remote_exts: a.exts,
             remote_created: false,
+            simulcast: a.simulcast,
+            rtp_mode: a.rtp_mode,
+            features: a.features,
             ..Default::default()
         }
     }
@@ -1,7 +1,9 @@
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::format::PayloadParams;
-use crate::rtp_::VideoOrientation;
+use crate::format::{CodecFrameInfo, PayloadParams};
+use crate::rtp_::{AbsCaptureTime, DependencyDescriptor, FrameMarking};
+use crate::rtp_::{VideoOrientation, VideoTiming};
 use crate::session::Session;
 use crate::RtcError;
 
@@ -18,6 +20,7 @@ pub struct Writer<'a> {
     mid: Mid,
     rid: Option<Rid>,
     ext_vals: ExtensionValues,
+    frame_info: Option<CodecFrameInfo>,
 }
 
 impl<'a> Writer<'a> {
@@ -30,6 +33,7 @@ impl<'a> Writer<'a> {
             mid,
             rid: None,
             ext_vals: ExtensionValues::default(),
+            frame_info: None,
         }
     }
 
@@ -70,7 +74,8 @@ impl<'a> Writer<'a> {
     /// Add on audio level and voice activity. These values are communicated in the same
     /// RTP header extension, hence it makes sense setting both at the same time.
     ///
-    /// Audio level is measured in negative decibel. 0 is max and a "normal" value might be -30.
+    /// Audio level is measured in negative decibel (dBov). 0 is max and a "normal" value
+    /// might be -30. -127 means silence and lower values are clamped to it.
     pub fn audio_level(mut self, audio_level: i8, voice_activity: bool) -> Self {
         self.ext_vals.audio_level = Some(audio_level);
         self.ext_vals.voice_activity = Some(voice_activity);
@@ -84,6 +89,63 @@ impl<'a> Writer<'a> {
         self
     }
 
+    /// Add horizontal flip (mirroring) of the video. This is sent together with the
+    /// video orientation and has no effect unless that is also set.
+    pub fn video_flip(mut self, flip: bool) -> Self {
+        self.ext_vals.video_flip = Some(flip);
+        self
+    }
+
+    /// Add a playout delay hint for the receiver's jitter buffer.
+    ///
+    /// Setting both to zero asks the receiver to render as soon as possible.
+    pub fn playout_delay(mut self, min: Duration, max: Duration) -> Self {
+        self.ext_vals.playout_delay = Some(Box::new((min, max)));
+        self
+    }
+
+    /// Add the time the media was captured. The receiver can use this to measure
+    /// end-to-end latency, also across forwarding SFUs.
+    pub fn abs_capture_time(mut self, v: AbsCaptureTime) -> Self {
+        self.ext_vals.abs_capture_time = Some(Box::new(v));
+        self
+    }
+
+    /// Add video timing for debugging the send path latency.
+    ///
+    /// The encode deltas are set by the caller, the packetization and pacer exit
+    /// deltas are filled in by str0m relative to the `wallclock` given to [`Writer::write`].
+    pub fn video_timing(mut self, v: VideoTiming) -> Self {
+        self.ext_vals.video_timing = Some(v);
+        self
+    }
+
+    /// Add frame marking. The start and end of frame flags are set by str0m
+    /// for each packet of the frame.
+    pub fn frame_marking(mut self, v: FrameMarking) -> Self {
+        self.ext_vals.frame_marking = Some(v);
+        self
+    }
+
+    /// Add an AV1 dependency descriptor. The start and end of frame flags are set by str0m
+    /// for each packet of the frame.
+    ///
+    /// The template structure is written when `structure_attached` is set, which is
+    /// typically done for keyframes.
+    pub fn dependency_descriptor(mut self, v: DependencyDescriptor) -> Self {
+        self.ext_vals.dependency_descriptor = Some(Arc::new(v));
+        self
+    }
+
+    /// Add codec specific information about the frame from the encoder.
+    ///
+    /// This is used by the packetizer, such as the spatial and temporal layers of
+    /// VP9 SVC. Codecs without use for the information ignore it.
+    pub fn codec_frame_info(mut self, info: CodecFrameInfo) -> Self {
+        self.frame_info = Some(info);
+        self
+    }
+
     /// Set a user extension value.
     pub fn user_extension_value<T: Send + Sync + 'static>(mut self, val: T) -> Self {
         self.ext_vals.user_values.set(val);
@@ -104,7 +166,7 @@ impl<'a> Writer<'a> {
     ///
     /// If you write media before `IceConnectionState` is `Connected` it will be dropped.
     ///
-    /// Panics if [`RtcConfig::set_rtp_mode()`][crate::RtcConfig::set_rtp_mode] is `true`.
+    /// Panics if the media is in [RTP mode][crate::media::Media::rtp_mode].
     pub fn write(
         self,
         pt: Pt,
@@ -143,6 +205,7 @@ impl<'a> Writer<'a> {
             rtp_time,
             data,
             ext_vals: self.ext_vals,
+            frame_info: self.frame_info,
         };
 
         media.set_to_payload(to_payload)?;
@@ -0,0 +1,498 @@
+use std::collections::VecDeque;
+
+use crate::media::MediaKind;
+use crate::rtp_::{Bitrate, Mid, Rid};
+
+/// How far above a boundary the allocation must go to turn on a layer, or resume a stream.
+///
+/// Turning off happens right at the boundary. The gap in between keeps an estimate that
+/// hovers around a boundary from switching back and forth.
+const HYSTERESIS: f64 = 0.15;
+
+/// Bitrate needs of one outgoing stream, for the [`Allocator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamBitrates {
+    kind: MediaKind,
+    priority: u8,
+    min: Bitrate,
+    target: Bitrate,
+    max: Bitrate,
+    layers: Vec<(Rid, Bitrate)>,
+}
+
+impl StreamBitrates {
+    /// Create the needs of a stream.
+    ///
+    /// * `min` the least the stream can go with. A video stream that can't get this much is
+    ///   paused. Audio always gets its min.
+    /// * `target` the bitrate for a good quality.
+    /// * `max` the most the stream can make use of.
+    pub fn new(kind: MediaKind, min: Bitrate, target: Bitrate, max: Bitrate) -> Self {
+        StreamBitrates {
+            kind,
+            priority: 0,
+            min,
+            target: target.max(min),
+            max: max.max(target).max(min),
+            layers: vec![],
+        }
+    }
+
+    /// Set the priority. Higher goes first, within audio and video respectively.
+    ///
+    /// Defaults to 0. Streams of the same priority go in the order they were added.
+    pub fn set_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Add a simulcast layer, enabled when the stream is allocated at least `bitrate`.
+    ///
+    /// The layer with the lowest bitrate is always enabled while the stream isn't paused.
+    pub fn add_layer(mut self, rid: Rid, bitrate: Bitrate) -> Self {
+        self.layers.push((rid, bitrate));
+        self.layers
+            .sort_by(|a, b| a.1.as_f64().total_cmp(&b.1.as_f64()));
+        self
+    }
+
+    /// The kind of stream.
+    pub fn kind(&self) -> MediaKind {
+        self.kind
+    }
+
+    /// The priority.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+}
+
+/// A new allocation for a stream, from [`Allocator::poll_output`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllocationChanged {
+    /// The stream.
+    pub mid: Mid,
+    /// The bitrate to encode at. Zero when the stream is paused.
+    pub bitrate: Bitrate,
+    /// The simulcast layers to send, lowest first. Empty without layers or when paused.
+    pub layers: Vec<Rid>,
+}
+
+/// Sans-IO allocation of an egress estimate over outgoing streams.
+///
+/// The estimate is handed out in passes: first every stream's min, then up to every
+/// stream's target, then up to max. Within each pass audio goes first, then video, each in
+/// priority order. Video streams with simulcast layers get the layers their allocation
+/// covers.
+///
+/// Feed it the target of each [`BweEstimate`][crate::bwe::BweEstimate] with
+/// [`Allocator::set_estimate`], then poll [`Allocator::poll_output`] until it returns
+/// `None`. Changes are only output for streams whose allocation actually changed.
+///
+/// ```
+/// # use str0m::bwe::{Allocator, Bitrate, StreamBitrates};
+/// # use str0m::media::{MediaKind, Mid, Rid};
+/// let mut allocator = Allocator::new();
+///
+/// let video = StreamBitrates::new(
+///     MediaKind::Video,
+///     Bitrate::kbps(150),
+///     Bitrate::kbps(1_000),
+///     Bitrate::kbps(2_500),
+/// )
+/// .add_layer(Rid::from("q"), Bitrate::kbps(150))
+/// .add_layer(Rid::from("h"), Bitrate::kbps(500))
+/// .add_layer(Rid::from("f"), Bitrate::kbps(1_500));
+///
+/// allocator.set_stream(Mid::from("v"), video);
+/// allocator.set_estimate(Bitrate::kbps(800));
+///
+/// let change = allocator.poll_output().unwrap();
+/// assert_eq!(change.bitrate, Bitrate::kbps(800));
+/// assert_eq!(change.layers, vec![Rid::from("q"), Rid::from("h")]);
+/// ```
+#[derive(Debug)]
+pub struct Allocator {
+    estimate: Bitrate,
+    streams: Vec<Stream>,
+    output: VecDeque<AllocationChanged>,
+}
+
+#[derive(Debug)]
+struct Stream {
+    mid: Mid,
+    bitrates: StreamBitrates,
+    /// Whether the stream got its min the last time.
+    active: bool,
+    /// Number of layers enabled the last time.
+    layers: usize,
+    /// The last output.
+    last: Option<AllocationChanged>,
+}
+
+impl Allocator {
+    /// Create a new allocator, without streams and a zero estimate.
+    pub fn new() -> Self {
+        Allocator {
+            estimate: Bitrate::ZERO,
+            streams: vec![],
+            output: VecDeque::new(),
+        }
+    }
+
+    /// Add a stream, or change the needs of an existing one.
+    pub fn set_stream(&mut self, mid: Mid, bitrates: StreamBitrates) {
+        if let Some(s) = self.streams.iter_mut().find(|s| s.mid == mid) {
+            s.bitrates = bitrates;
+        } else {
+            self.streams.push(Stream {
+                mid,
+                bitrates,
+                active: false,
+                layers: 0,
+                last: None,
+            });
+        }
+
+        self.allocate();
+    }
+
+    /// Remove a stream, giving its share to the others.
+    pub fn remove_stream(&mut self, mid: Mid) {
+        self.streams.retain(|s| s.mid != mid);
+        self.output.retain(|o| o.mid != mid);
+
+        self.allocate();
+    }
+
+    /// Set the estimate to allocate.
+    pub fn set_estimate(&mut self, estimate: Bitrate) {
+        self.estimate = estimate;
+
+        self.allocate();
+    }
+
+    /// Poll for changed allocations.
+    pub fn poll_output(&mut self) -> Option<AllocationChanged> {
+        self.output.pop_front()
+    }
+
+    fn allocate(&mut self) {
+        // Stable sort, keeping the order of addition within the same priority.
+        let mut order: Vec<usize> = (0..self.streams.len()).collect();
+        order.sort_by_key(|i| {
+            let b = &self.streams[*i].bitrates;
+            (b.kind.is_video(), u8::MAX - b.priority)
+        });
+
+        let mut remaining = self.estimate.as_f64();
+        let mut allocated = vec![0.0; self.streams.len()];
+
+        // Everyone's min. Audio isn't paused, even if that goes over the estimate.
+        for i in &order {
+            let s = &mut self.streams[*i];
+            let min = s.bitrates.min.as_f64();
+
+            let needed = if s.active {
+                min
+            } else {
+                min * (1.0 + HYSTERESIS)
+            };
+            s.active = s.bitrates.kind.is_audio() || remaining >= needed;
+
+            if s.active {
+                allocated[*i] = min;
+                remaining -= min;
+            }
+        }
+
+        // Then up to target, then up to max.
+        for to_max in [false, true] {
+            for i in &order {
+                let s = &self.streams[*i];
+                if !s.active || remaining <= 0.0 {
+                    continue;
+                }
+
+                let level = if to_max {
+                    s.bitrates.max
+                } else {
+                    s.bitrates.target
+                };
+                let room = level.as_f64() - allocated[*i];
+                let add = room.clamp(0.0, remaining);
+                allocated[*i] += add;
+                remaining -= add;
+            }
+        }
+
+        for (s, bitrate) in self.streams.iter_mut().zip(allocated) {
+            s.layers = if s.active {
+                enabled_layers(&s.bitrates.layers, s.layers, bitrate)
+            } else {
+                0
+            };
+
+            let change = AllocationChanged {
+                mid: s.mid,
+                bitrate: Bitrate::from(bitrate.round()),
+                layers: s.bitrates.layers[..s.layers]
+                    .iter()
+                    .map(|(rid, _)| *rid)
+                    .collect(),
+            };
+
+            if s.last.as_ref() == Some(&change) {
+                continue;
+            }
+
+            // Only the latest change for a stream is of interest.
+            self.output.retain(|o| o.mid != s.mid);
+            self.output.push_back(change.clone());
+            s.last = Some(change);
+        }
+    }
+}
+
+/// The number of layers, lowest first, to enable for `bitrate`.
+fn enabled_layers(layers: &[(Rid, Bitrate)], previous: usize, bitrate: f64) -> usize {
+    if layers.is_empty() {
+        return 0;
+    }
+
+    let mut count = 1;
+
+    for (i, (_, threshold)) in layers.iter().enumerate().skip(1) {
+        let threshold = threshold.as_f64();
+        let needed = if i < previous {
+            threshold
+        } else {
+            threshold * (1.0 + HYSTERESIS)
+        };
+
+        if bitrate < needed {
+            break;
+        }
+
+        count = i + 1;
+    }
+
+    count
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kbps(v: u64) -> Bitrate {
+        Bitrate::kbps(v)
+    }
+
+    fn audio() -> StreamBitrates {
+        StreamBitrates::new(MediaKind::Audio, kbps(32), kbps(64), kbps(64))
+    }
+
+    fn simulcast() -> StreamBitrates {
+        StreamBitrates::new(MediaKind::Video, kbps(150), kbps(1_200), kbps(2_500))
+            .add_layer("q".into(), kbps(150))
+            .add_layer("h".into(), kbps(500))
+            .add_layer("f".into(), kbps(1_500))
+    }
+
+    fn camera() -> StreamBitrates {
+        StreamBitrates::new(MediaKind::Video, kbps(100), kbps(500), kbps(1_000))
+    }
+
+    /// Drain the outputs into the latest allocation per stream.
+    fn drain(a: &mut Allocator, latest: &mut Vec<AllocationChanged>) -> usize {
+        let mut n = 0;
+        while let Some(o) = a.poll_output() {
+            latest.retain(|l| l.mid != o.mid);
+            latest.push(o);
+            n += 1;
+        }
+        n
+    }
+
+    fn allocation(latest: &[AllocationChanged], mid: &str) -> (u64, Vec<String>) {
+        let l = latest.iter().find(|l| l.mid == mid.into()).unwrap();
+        (
+            l.bitrate.as_u64() / 1000,
+            l.layers.iter().map(|r| r.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn allocation_table() {
+        // (estimate kbps, [(mid, kbps, layers)])
+        #[allow(clippy::type_complexity)]
+        let table: &[(u64, &[(&str, u64, &[&str])])] = &[
+            // Audio is never paused.
+            (0, &[("a", 32, &[]), ("v", 0, &[]), ("w", 0, &[])]),
+            // Video v is first in line for its min, w can't get one.
+            (250, &[("a", 64, &[]), ("v", 186, &["q"]), ("w", 0, &[])]),
+            // Everyone's min, then audio target, then video up to target in priority order.
+            (700, &[("a", 64, &[]), ("v", 536, &["q"]), ("w", 100, &[])]),
+            // v reaches target before w gets above min, then goes on to max.
+            (
+                2_000,
+                &[("a", 64, &[]), ("v", 1436, &["q", "h"]), ("w", 500, &[])],
+            ),
+            // Then up to max.
+            (
+                3_500,
+                &[
+                    ("a", 64, &[]),
+                    ("v", 2500, &["q", "h", "f"]),
+                    ("w", 936, &[]),
+                ],
+            ),
+            (
+                10_000,
+                &[
+                    ("a", 64, &[]),
+                    ("v", 2500, &["q", "h", "f"]),
+                    ("w", 1000, &[]),
+                ],
+            ),
+        ];
+
+        for (estimate, expected) in table {
+            let mut a = Allocator::new();
+            a.set_stream("w".into(), camera());
+            a.set_stream("v".into(), simulcast().set_priority(1));
+            a.set_stream("a".into(), audio());
+            a.set_estimate(kbps(*estimate));
+
+            let mut latest = vec![];
+            drain(&mut a, &mut latest);
+
+            for (mid, kbps, layers) in *expected {
+                let (bitrate, rids) = allocation(&latest, mid);
+                assert_eq!(bitrate, *kbps, "{} at {}", mid, estimate);
+                assert_eq!(rids, layers.to_vec(), "{} at {}", mid, estimate);
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_is_monotone() {
+        let mut a = Allocator::new();
+        a.set_stream("a".into(), audio());
+        a.set_stream("v".into(), simulcast());
+        let mut latest = vec![];
+
+        let mut previous = (0, vec![]);
+        for estimate in (0..4_000).step_by(10) {
+            a.set_estimate(kbps(estimate));
+            drain(&mut a, &mut latest);
+
+            let (bitrate, layers) = allocation(&latest, "v");
+            assert!(bitrate >= previous.0, "{} at {}", bitrate, estimate);
+            assert!(
+                layers.len() >= previous.1.len(),
+                "{:?} at {}",
+                layers,
+                estimate
+            );
+            previous = (bitrate, layers);
+        }
+        assert_eq!(previous.1, vec!["q", "h", "f"]);
+
+        for estimate in (0..4_000).rev().step_by(10) {
+            a.set_estimate(kbps(estimate));
+            drain(&mut a, &mut latest);
+
+            let (bitrate, layers) = allocation(&latest, "v");
+            assert!(bitrate <= previous.0, "{} at {}", bitrate, estimate);
+            assert!(
+                layers.len() <= previous.1.len(),
+                "{:?} at {}",
+                layers,
+                estimate
+            );
+            previous = (bitrate, layers);
+        }
+        assert_eq!(previous, (0, vec![]));
+    }
+
+    #[test]
+    fn hysteresis_at_layer_boundary() {
+        let mut a = Allocator::new();
+        a.set_stream("v".into(), simulcast());
+        let mut latest = vec![];
+
+        // h is enabled 15% above 500.
+        a.set_estimate(kbps(560));
+        drain(&mut a, &mut latest);
+        assert_eq!(allocation(&latest, "v").1, vec!["q"]);
+
+        a.set_estimate(kbps(580));
+        drain(&mut a, &mut latest);
+        assert_eq!(allocation(&latest, "v").1, vec!["q", "h"]);
+
+        // Hovering around the boundary doesn't switch layers.
+        for estimate in [560, 520, 575, 510, 590, 505] {
+            a.set_estimate(kbps(estimate));
+            drain(&mut a, &mut latest);
+            assert_eq!(allocation(&latest, "v").1, vec!["q", "h"], "{}", estimate);
+        }
+
+        a.set_estimate(kbps(490));
+        drain(&mut a, &mut latest);
+        assert_eq!(allocation(&latest, "v").1, vec!["q"]);
+
+        // Same for pausing the stream.
+        a.set_estimate(kbps(140));
+        drain(&mut a, &mut latest);
+        assert_eq!(allocation(&latest, "v"), (0, vec![]));
+
+        a.set_estimate(kbps(160));
+        drain(&mut a, &mut latest);
+        assert_eq!(allocation(&latest, "v"), (0, vec![]));
+
+        a.set_estimate(kbps(175));
+        drain(&mut a, &mut latest);
+        assert_eq!(allocation(&latest, "v"), (175, vec!["q".to_string()]));
+    }
+
+    #[test]
+    fn output_only_on_change() {
+        let mut a = Allocator::new();
+        a.set_stream("a".into(), audio());
+        a.set_stream("v".into(), camera());
+        let mut latest = vec![];
+
+        a.set_estimate(kbps(300));
+        assert_eq!(drain(&mut a, &mut latest), 2);
+
+        // Same estimate again.
+        a.set_estimate(kbps(300));
+        assert_eq!(drain(&mut a, &mut latest), 0);
+
+        // Only video changes, audio is at max already.
+        a.set_estimate(kbps(400));
+        assert_eq!(drain(&mut a, &mut latest), 1);
+        assert_eq!(allocation(&latest, "v").0, 336);
+
+        // Several changes before polling are one output.
+        a.set_estimate(kbps(500));
+        a.set_estimate(kbps(600));
+        assert_eq!(drain(&mut a, &mut latest), 1);
+        assert_eq!(allocation(&latest, "v").0, 536);
+
+        // Removing a stream gives its share to the others.
+        a.set_stream("w".into(), camera().set_priority(1));
+        drain(&mut a, &mut latest);
+        assert_eq!(allocation(&latest, "w").0, 436);
+        a.remove_stream("w".into());
+        assert_eq!(drain(&mut a, &mut latest), 1);
+        assert_eq!(allocation(&latest, "v").0, 536);
+    }
+}
@@ -0,0 +1,670 @@
+use super::{CodecExtra, Depacketizer, PacketError, Packetizer};
+
+/// AV1 information describing the depacketized data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Av1CodecExtra {
+    /// Flag which indicates that within [`MediaData`], a new coded video sequence starts.
+    /// The decoder can start decoding from here.
+    ///
+    /// [`MediaData`]: crate::media::MediaData
+    pub is_keyframe: bool,
+}
+
+const OBU_TYPE_SEQUENCE_HEADER: u8 = 1;
+const OBU_TYPE_TEMPORAL_DELIMITER: u8 = 2;
+const OBU_TYPE_TILE_LIST: u8 = 8;
+const OBU_TYPE_PADDING: u8 = 15;
+
+const OBU_EXTENSION_BITMASK: u8 = 0x04;
+const OBU_HAS_SIZE_BITMASK: u8 = 0x02;
+
+/// Temporal delimiter OBU with a zero size field.
+const TEMPORAL_DELIMITER: &[u8] = &[OBU_TYPE_TEMPORAL_DELIMITER << 3 | OBU_HAS_SIZE_BITMASK, 0];
+
+/// Packetizes AV1 RTP packets.
+///
+/// See the [AV1 RTP payload format](https://aomediacodec.github.io/av1-rtp-spec/#4-payload-format).
+/// The input is the OBUs of a temporal unit in the low overhead bitstream format. The OBUs
+/// are sent without size fields, aggregated or fragmented to fill the packets. Layer
+/// information is taken from the OBU extension headers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Av1Packetizer;
+
+/// Packet being filled with OBU elements, each an OBU or a fragment of one.
+#[derive(Default)]
+struct Av1Packet<'a> {
+    /// Z. The first element continues an OBU of the previous packet.
+    z: bool,
+    /// Y. The last element continues in the next packet.
+    y: bool,
+    elements: Vec<&'a [u8]>,
+}
+
+impl Av1Packet<'_> {
+    /// Size of the packet with all element lengths written.
+    fn size(&self) -> usize {
+        let elements: usize = self
+            .elements
+            .iter()
+            .map(|e| leb128_size(e.len() as u64) + e.len())
+            .sum();
+        1 + elements
+    }
+
+    fn to_bytes(&self, n: bool) -> Vec<u8> {
+        // With at most 3 elements, W is the count and the last element has no length.
+        let count = self.elements.len();
+        let w = if count <= 3 { count as u8 } else { 0 };
+
+        //  0 1 2 3 4 5 6 7
+        // +-+-+-+-+-+-+-+-+
+        // |Z|Y| W |N|-|-|-|
+        // +-+-+-+-+-+-+-+-+
+        let mut out = Vec::with_capacity(self.size());
+        out.push((self.z as u8) << 7 | (self.y as u8) << 6 | w << 4 | (n as u8) << 3);
+
+        for (i, e) in self.elements.iter().enumerate() {
+            if w == 0 || i + 1 < count {
+                write_leb128(&mut out, e.len() as u64);
+            }
+            out.extend_from_slice(e);
+        }
+
+        out
+    }
+}
+
+impl Packetizer for Av1Packetizer {
+    fn packetize(&mut self, mtu: usize, payload: &[u8]) -> Result<Vec<Vec<u8>>, PacketError> {
+        let mut new_sequence = false;
+        let mut obus = vec![];
+        let mut rest = payload;
+
+        while !rest.is_empty() {
+            let (obu, len) = read_obu(rest)?;
+            rest = &rest[len..];
+
+            let obu_type = (obu[0] >> 3) & 0x0f;
+
+            // Temporal delimiters are implied by the RTP timestamp, tile lists are not
+            // allowed in RTP and padding is pointless.
+            if matches!(
+                obu_type,
+                OBU_TYPE_TEMPORAL_DELIMITER | OBU_TYPE_TILE_LIST | OBU_TYPE_PADDING
+            ) {
+                continue;
+            }
+
+            if obu_type == OBU_TYPE_SEQUENCE_HEADER {
+                new_sequence = true;
+            }
+
+            obus.push(obu);
+        }
+
+        // Aggregation header and at least one byte of OBU.
+        if mtu < 2 {
+            return Ok(vec![]);
+        }
+
+        let mut packets = vec![Av1Packet::default()];
+
+        for obu in &obus {
+            let mut element = obu.as_slice();
+
+            loop {
+                let packet = packets.last_mut().unwrap();
+
+                // Room for the element, which has no length if it's one of the first 3.
+                let avail = mtu.saturating_sub(packet.size());
+                let max = if packet.elements.len() < 3 {
+                    avail
+                } else {
+                    avail.saturating_sub(leb128_size(avail as u64))
+                };
+
+                if element.len() <= max {
+                    packet.elements.push(element);
+                    break;
+                }
+
+                if max > 0 {
+                    // Fragment the OBU, continuing in the next packet.
+                    packet.elements.push(&element[..max]);
+                    packet.y = true;
+                    element = &element[max..];
+                }
+
+                packets.push(Av1Packet {
+                    z: max > 0,
+                    ..Default::default()
+                });
+            }
+        }
+
+        let payloads = packets
+            .iter()
+            .filter(|p| !p.elements.is_empty())
+            .enumerate()
+            // N is set on the first packet of a coded video sequence.
+            .map(|(i, p)| p.to_bytes(i == 0 && new_sequence))
+            .collect();
+
+        Ok(payloads)
+    }
+
+    fn is_marker(&mut self, _data: &[u8], _previous: Option<&[u8]>, last: bool) -> bool {
+        last
+    }
+}
+
+/// Read one OBU of the low overhead bitstream format.
+///
+/// Returns the OBU without size field and the number of bytes read. An OBU without size
+/// field extends to the end of the data.
+fn read_obu(buf: &[u8]) -> Result<(Vec<u8>, usize), PacketError> {
+    let header = buf[0];
+    let header_len = if header & OBU_EXTENSION_BITMASK > 0 {
+        2
+    } else {
+        1
+    };
+
+    if buf.len() < header_len {
+        return Err(PacketError::ErrAv1CorruptedPacket);
+    }
+
+    let (payload, len) = if header & OBU_HAS_SIZE_BITMASK > 0 {
+        let (size, n) =
+            read_leb128(&buf[header_len..]).ok_or(PacketError::ErrAv1CorruptedPacket)?;
+        let start = header_len + n;
+        if size as usize > buf.len() - start {
+            return Err(PacketError::ErrAv1CorruptedPacket);
+        }
+        let end = start + size as usize;
+        (&buf[start..end], end)
+    } else {
+        (&buf[header_len..], buf.len())
+    };
+
+    let mut obu = Vec::with_capacity(header_len + payload.len());
+    obu.push(header & !OBU_HAS_SIZE_BITMASK);
+    obu.extend_from_slice(&buf[1..header_len]);
+    obu.extend_from_slice(payload);
+
+    Ok((obu, len))
+}
+
+/// Depacketizes AV1 RTP packets.
+///
+/// See the [AV1 RTP payload format](https://aomediacodec.github.io/av1-rtp-spec/#4-payload-format).
+/// The output is the OBUs of a temporal unit in the low overhead bitstream format, i.e.
+/// starting with a temporal delimiter and with the size field set in every OBU.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct Av1Depacketizer {
+    /// OBU fragments being reassembled across packets.
+    fragment: Option<Vec<u8>>,
+}
+
+impl Depacketizer for Av1Depacketizer {
+    fn depacketize(
+        &mut self,
+        packet: &[u8],
+        out: &mut Vec<u8>,
+        extra: &mut CodecExtra,
+    ) -> Result<(), PacketError> {
+        //  0 1 2 3 4 5 6 7
+        // +-+-+-+-+-+-+-+-+
+        // |Z|Y| W |N|-|-|-|
+        // +-+-+-+-+-+-+-+-+
+        let Some(&agg) = packet.first() else {
+            return Err(PacketError::ErrShortPacket);
+        };
+
+        let z = agg & 0x80 > 0;
+        let y = agg & 0x40 > 0;
+        let w = (agg >> 4) & 0x03;
+        let n = agg & 0x08 > 0;
+
+        if n {
+            set_keyframe(extra);
+        }
+
+        if !z && self.fragment.take().is_some() {
+            trace!("Discard AV1 OBU fragment without continuation");
+        }
+
+        let mut rest = &packet[1..];
+        let mut count = 0;
+
+        while !rest.is_empty() {
+            count += 1;
+
+            // With W set, the last OBU element has no length field.
+            let len = if count == w {
+                rest.len()
+            } else {
+                let Some((len, n)) = read_leb128(rest) else {
+                    self.fragment = None;
+                    return Err(PacketError::ErrAv1CorruptedPacket);
+                };
+                rest = &rest[n..];
+                len as usize
+            };
+
+            if len > rest.len() {
+                self.fragment = None;
+                return Err(PacketError::ErrShortPacket);
+            }
+
+            let element = &rest[..len];
+            rest = &rest[len..];
+
+            let is_first = count == 1;
+            let is_last = rest.is_empty() || count == w;
+
+            let obu = if is_first && z {
+                match self.fragment.take() {
+                    Some(mut fragment) => {
+                        fragment.extend_from_slice(element);
+                        Some(fragment)
+                    }
+                    None => {
+                        // Without the start of the OBU, it can't be reconstructed.
+                        trace!("Discard AV1 OBU fragment without start");
+                        None
+                    }
+                }
+            } else {
+                Some(element.to_vec())
+            };
+
+            let Some(obu) = obu else {
+                if is_last {
+                    break;
+                }
+                continue;
+            };
+
+            if is_last && y {
+                self.fragment = Some(obu);
+                break;
+            }
+
+            if let Err(e) = write_obu(&obu, out, extra) {
+                self.fragment = None;
+                return Err(e);
+            }
+
+            if is_last {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_partition_head(&self, packet: &[u8]) -> bool {
+        // Z bit not set, the packet starts with a new OBU.
+        packet.first().map(|b| b & 0x80 == 0).unwrap_or(false)
+    }
+
+    fn is_partition_tail(&self, marker: bool, _packet: &[u8]) -> bool {
+        marker
+    }
+
+    fn reset(&mut self) {
+        self.fragment = None;
+    }
+}
+
+/// Write one complete OBU with its size field set.
+fn write_obu(obu: &[u8], out: &mut Vec<u8>, extra: &mut CodecExtra) -> Result<(), PacketError> {
+    //  0 1 2 3 4 5 6 7
+    // +-+-+-+-+-+-+-+-+
+    // |0| type  |X|S|-| (REQUIRED)
+    // +-+-+-+-+-+-+-+-+
+    // X: | TID |SID|-|-|-| (OPTIONAL)
+    // +-+-+-+-+-+-+-+-+
+    // S: |   obu_size    | (OPTIONAL, leb128)
+    // +-+-+-+-+-+-+-+-+
+    let Some(&header) = obu.first() else {
+        // Empty OBU elements carry nothing.
+        return Ok(());
+    };
+
+    let obu_type = (header >> 3) & 0x0f;
+    let header_len = if header & OBU_EXTENSION_BITMASK > 0 {
+        2
+    } else {
+        1
+    };
+
+    if obu.len() < header_len {
+        return Err(PacketError::ErrAv1CorruptedPacket);
+    }
+
+    let mut payload = &obu[header_len..];
+
+    if header & OBU_HAS_SIZE_BITMASK > 0 {
+        let (size, n) = read_leb128(payload).ok_or(PacketError::ErrAv1CorruptedPacket)?;
+        payload = &payload[n..];
+        if size as usize > payload.len() {
+            return Err(PacketError::ErrAv1CorruptedPacket);
+        }
+        payload = &payload[..size as usize];
+    }
+
+    // Temporal delimiters are implied by the RTP timestamp and tile lists are not
+    // allowed in RTP. Both are ignored.
+    if obu_type == OBU_TYPE_TEMPORAL_DELIMITER || obu_type == OBU_TYPE_TILE_LIST {
+        return Ok(());
+    }
+
+    if obu_type == OBU_TYPE_SEQUENCE_HEADER {
+        set_keyframe(extra);
+    }
+
+    if out.is_empty() {
+        out.extend_from_slice(TEMPORAL_DELIMITER);
+    }
+
+    out.push(header | OBU_HAS_SIZE_BITMASK);
+    out.extend_from_slice(&obu[1..header_len]);
+    write_leb128(out, payload.len() as u64);
+    out.extend_from_slice(payload);
+
+    Ok(())
+}
+
+fn set_keyframe(extra: &mut CodecExtra) {
+    *extra = CodecExtra::Av1(Av1CodecExtra { is_keyframe: true });
+}
+
+/// Read an unsigned LEB128 value of at most 8 bytes, as limited by the AV1 spec.
+///
+/// Returns the value and the number of bytes read.
+fn read_leb128(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, b) in buf.iter().take(8).enumerate() {
+        value |= ((b & 0x7f) as u64) << (i * 7);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn leb128_size(value: u64) -> usize {
+    ((64 - value.leading_zeros() as usize + 6) / 7).max(1)
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Sequence header and frame OBUs without size fields, as sent in RTP.
+    const SEQUENCE_HEADER: &[u8] = &[0x08, 0x00, 0x00, 0x00, 0x42, 0xa7, 0xbf, 0xe4];
+    const FRAME: &[u8] = &[0x30, 0x10, 0x01, 0x02, 0x03, 0x04, 0x05];
+
+    fn depack(packets: &[&[u8]]) -> (Vec<u8>, CodecExtra) {
+        let mut d = Av1Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+        for p in packets {
+            d.depacketize(p, &mut out, &mut extra).unwrap();
+        }
+        (out, extra)
+    }
+
+    fn with_size(obu: &[u8]) -> Vec<u8> {
+        let mut v = vec![obu[0] | OBU_HAS_SIZE_BITMASK];
+        write_leb128(&mut v, obu.len() as u64 - 1);
+        v.extend_from_slice(&obu[1..]);
+        v
+    }
+
+    #[test]
+    fn leb128() {
+        assert_eq!(read_leb128(&[0x00]), Some((0, 1)));
+        assert_eq!(read_leb128(&[0x7f, 0xff]), Some((127, 1)));
+        assert_eq!(read_leb128(&[0xe5, 0x8e, 0x26]), Some((624485, 3)));
+        assert_eq!(read_leb128(&[0x80, 0x80]), None);
+        assert_eq!(read_leb128(&[0xff; 9]), None);
+        assert_eq!(read_leb128(&[]), None);
+
+        for v in [0, 1, 127, 128, 16383, 16384, 624485, u32::MAX as u64] {
+            let mut buf = vec![];
+            write_leb128(&mut buf, v);
+            assert_eq!(read_leb128(&buf), Some((v, buf.len())));
+        }
+    }
+
+    #[test]
+    fn keyframe_aggregated() {
+        // N:1, W:2 where the last element has no length.
+        let mut packet = vec![0x28, SEQUENCE_HEADER.len() as u8];
+        packet.extend_from_slice(SEQUENCE_HEADER);
+        packet.extend_from_slice(FRAME);
+
+        let (out, extra) = depack(&[&packet]);
+
+        let mut expected = TEMPORAL_DELIMITER.to_vec();
+        expected.extend_from_slice(&with_size(SEQUENCE_HEADER));
+        expected.extend_from_slice(&with_size(FRAME));
+        assert_eq!(out, expected);
+        assert_eq!(extra, CodecExtra::Av1(Av1CodecExtra { is_keyframe: true }));
+    }
+
+    #[test]
+    fn delta_frame_with_lengths() {
+        // W:0, every element has a length. The temporal delimiter is dropped.
+        let packet = &[
+            0x00, 0x02, 0x12, 0x00, 0x07, 0x30, 0x10, 0x01, 0x02, 0x03, 0x04, 0x05,
+        ];
+
+        let (out, extra) = depack(&[packet]);
+
+        let mut expected = TEMPORAL_DELIMITER.to_vec();
+        expected.extend_from_slice(&with_size(FRAME));
+        assert_eq!(out, expected);
+        assert_eq!(extra, CodecExtra::None);
+    }
+
+    #[test]
+    fn obu_with_size_field() {
+        // OBU already has a size field, with trailing bytes beyond it.
+        let packet = &[0x10, 0x32, 0x02, 0xaa, 0xbb, 0xcc];
+
+        let (out, _) = depack(&[packet]);
+
+        assert_eq!(&out[2..], &[0x32, 0x02, 0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn fragmented_obu() {
+        // Frame OBU split over three packets: Y, then Z+Y, then Z.
+        let (out, _) = depack(&[
+            &[0x50, 0x30, 0x10, 0x01],
+            &[0xd0, 0x02, 0x03],
+            &[0x90, 0x04, 0x05],
+        ]);
+
+        let mut expected = TEMPORAL_DELIMITER.to_vec();
+        expected.extend_from_slice(&with_size(FRAME));
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn orphan_fragment() {
+        let mut d = Av1Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+
+        // Continuation fragments whose start was lost.
+        d.depacketize(&[0xd0, 0x02, 0x03], &mut out, &mut extra)
+            .unwrap();
+        d.depacketize(&[0x90, 0x04, 0x05], &mut out, &mut extra)
+            .unwrap();
+        assert!(out.is_empty());
+
+        // Start of an OBU, whose continuation is lost.
+        d.depacketize(&[0x50, 0x30, 0x10, 0x01], &mut out, &mut extra)
+            .unwrap();
+
+        // The next temporal unit is unaffected.
+        d.depacketize(
+            &[0x10, 0x30, 0x10, 0x01, 0x02, 0x03, 0x04, 0x05],
+            &mut out,
+            &mut extra,
+        )
+        .unwrap();
+
+        let mut expected = TEMPORAL_DELIMITER.to_vec();
+        expected.extend_from_slice(&with_size(FRAME));
+        assert_eq!(out, expected);
+    }
+
+    fn temporal_unit(obus: &[&[u8]]) -> Vec<u8> {
+        let mut tu = TEMPORAL_DELIMITER.to_vec();
+        for obu in obus {
+            tu.extend_from_slice(&with_size(obu));
+        }
+        tu
+    }
+
+    fn packetize(mtu: usize, tu: &[u8]) -> Vec<Vec<u8>> {
+        let packets = Av1Packetizer.packetize(mtu, tu).unwrap();
+        assert!(packets.iter().all(|p| p.len() <= mtu));
+        packets
+    }
+
+    #[test]
+    fn packetize_aggregated() {
+        let tu = temporal_unit(&[SEQUENCE_HEADER, FRAME]);
+
+        let packets = packetize(1200, &tu);
+
+        // N:1, W:2 where the last element has no length. No temporal delimiter.
+        let mut expected = vec![0x28, SEQUENCE_HEADER.len() as u8];
+        expected.extend_from_slice(SEQUENCE_HEADER);
+        expected.extend_from_slice(FRAME);
+        assert_eq!(packets, [expected]);
+
+        let packets: Vec<&[u8]> = packets.iter().map(|p| p.as_slice()).collect();
+        let (out, extra) = depack(&packets);
+        assert_eq!(out, tu);
+        assert_eq!(extra, CodecExtra::Av1(Av1CodecExtra { is_keyframe: true }));
+    }
+
+    #[test]
+    fn packetize_more_than_three_obus() {
+        let tu = temporal_unit(&[FRAME, FRAME, FRAME, FRAME]);
+
+        let packets = packetize(1200, &tu);
+
+        // W:0 with every element having a length.
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0][0], 0x00);
+        assert_eq!(packets[0].len(), 1 + 4 * (1 + FRAME.len()));
+
+        let (out, extra) = depack(&[&packets[0]]);
+        assert_eq!(out, tu);
+        assert_eq!(extra, CodecExtra::None);
+    }
+
+    #[test]
+    fn packetize_fragmented() {
+        let mut large = vec![0x32];
+        large.extend((0..3000).map(|i| i as u8));
+        let tu = temporal_unit(&[SEQUENCE_HEADER, &large, FRAME]);
+
+        for mtu in [3, 10, 100, 1000, 1200] {
+            let packets = packetize(mtu, &tu);
+
+            let d = Av1Depacketizer::default();
+            assert!(d.is_partition_head(&packets[0]));
+            assert_eq!(packets[0][0] & 0x08, 0x08);
+            assert!(packets[1..].iter().all(|p| p[0] & 0x08 == 0));
+
+            // Every fragment continues in the next packet.
+            for w in packets.windows(2) {
+                assert_eq!(w[0][0] & 0x40 > 0, w[1][0] & 0x80 > 0);
+            }
+
+            let packets: Vec<&[u8]> = packets.iter().map(|p| p.as_slice()).collect();
+            let (out, _) = depack(&packets);
+            assert_eq!(out, tu, "mtu {mtu}");
+        }
+    }
+
+    #[test]
+    fn packetize_without_size_fields() {
+        // The last OBU may lack a size field and extends to the end.
+        let mut tu = with_size(SEQUENCE_HEADER);
+        tu.extend_from_slice(FRAME);
+
+        let packets = packetize(1200, &tu);
+
+        let (out, _) = depack(&[&packets[0]]);
+        assert_eq!(out, temporal_unit(&[SEQUENCE_HEADER, FRAME]));
+    }
+
+    #[test]
+    fn packetize_malformed() {
+        // OBU size beyond the end.
+        assert_eq!(
+            Av1Packetizer.packetize(1200, &[0x32, 0x05, 0x01]),
+            Err(PacketError::ErrAv1CorruptedPacket)
+        );
+        // OBU extension header missing.
+        assert_eq!(
+            Av1Packetizer.packetize(1200, &[0x34]),
+            Err(PacketError::ErrAv1CorruptedPacket)
+        );
+        assert_eq!(Av1Packetizer.packetize(1200, &[]), Ok(vec![]));
+        assert_eq!(Av1Packetizer.packetize(1, FRAME), Ok(vec![]));
+    }
+
+    #[test]
+    fn malformed() {
+        let mut d = Av1Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+
+        // Empty packet.
+        assert_eq!(
+            d.depacketize(&[], &mut out, &mut extra),
+            Err(PacketError::ErrShortPacket)
+        );
+        // Element length longer than the packet.
+        assert_eq!(
+            d.depacketize(&[0x00, 0x05, 0x30, 0x10], &mut out, &mut extra),
+            Err(PacketError::ErrShortPacket)
+        );
+        // Unterminated leb128 element length.
+        assert_eq!(
+            d.depacketize(&[0x00, 0x80, 0x80], &mut out, &mut extra),
+            Err(PacketError::ErrAv1CorruptedPacket)
+        );
+        // OBU size field longer than the element.
+        assert_eq!(
+            d.depacketize(&[0x10, 0x32, 0x05, 0xaa], &mut out, &mut extra),
+            Err(PacketError::ErrAv1CorruptedPacket)
+        );
+        // OBU extension header missing.
+        assert_eq!(
+            d.depacketize(&[0x10, 0x34], &mut out, &mut extra),
+            Err(PacketError::ErrAv1CorruptedPacket)
+        );
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::VecDeque;
+
+use crate::rtp_::{DependencyDescriptor, Dti};
+
+/// How many frames back the selector remembers, for frame and chain diffs.
+const MAX_HISTORY: usize = 512;
+
+/// Selects the frames of an AV1 stream to forward for one decode target, such as in an SFU.
+///
+/// The selection is driven by the [`DependencyDescriptor`] of each packet, which must have
+/// been resolved against the stream's structure, as str0m does for incoming RTP. Give every
+/// packet to [`Av1Selector::forward()`], in order.
+///
+/// A frame is forwarded when it's part of the decode target, the frames it depends on were
+/// forwarded, and the chain protecting the decode target is intact. The chain is what tells
+/// a lost frame the decode target needs from one it doesn't. When the chain breaks, or the
+/// switch to a new target can't be done, [`Av1Decision::needs_keyframe`] is set.
+///
+/// Packet loss is seen as frame numbers that never show up, or frames that don't start
+/// with their first packet. Loss of packets in the middle of a frame is not detected.
+#[derive(Debug)]
+pub struct Av1Selector {
+    target: u8,
+    /// Frames seen, latest last.
+    history: VecDeque<FrameState>,
+    /// Whether the decode target was decodable at the last frame the chain cares about.
+    decodable: bool,
+    /// Whether a keyframe has been asked for since the target stopped being decodable.
+    keyframe_asked: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameState {
+    frame_number: u16,
+    forwarded: bool,
+    /// Whether the end of frame packet was seen.
+    ended: bool,
+    /// Bit per chain, whether it's intact up to and including this frame.
+    chains: u32,
+}
+
+/// What to do with one packet, from [`Av1Selector::forward()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Av1Decision {
+    /// Whether to forward the packet.
+    pub forward: bool,
+    /// The marker bit to forward the packet with.
+    ///
+    /// Set at the end of the frame of the highest spatial layer in the decode target.
+    pub marker: bool,
+    /// The decode target can't be decoded without a keyframe.
+    ///
+    /// Set once each time the target becomes undecodable, the caller is expected to send
+    /// a PLI or FIR.
+    pub needs_keyframe: bool,
+}
+
+impl Av1Selector {
+    /// Create a selector for the decode target index `target`.
+    pub fn new(target: u8) -> Self {
+        Av1Selector {
+            target,
+            history: VecDeque::new(),
+            decodable: false,
+            keyframe_asked: false,
+        }
+    }
+
+    /// Select the decode target index to forward.
+    ///
+    /// Switching is immediate when the frames the new target depends on were forwarded,
+    /// like going down a temporal layer. Otherwise it waits for a frame to switch at, which
+    /// with k-SVC means a keyframe, also for going down a spatial layer.
+    pub fn select(&mut self, target: u8) {
+        if target != self.target {
+            self.keyframe_asked = false;
+        }
+        self.target = target;
+    }
+
+    /// The selected decode target.
+    pub fn target(&self) -> u8 {
+        self.target
+    }
+
+    /// Whether the selected decode target is decodable by the receiver.
+    ///
+    /// This is as of the last frame that is required by the decode target.
+    pub fn is_decodable(&self) -> bool {
+        self.decodable
+    }
+
+    /// Decide on one packet.
+    pub fn forward(&mut self, dd: &DependencyDescriptor) -> Av1Decision {
+        let n = dd.frame_number;
+
+        if let Some(state) = self.state_mut(n) {
+            // Continuing a frame we have decided on.
+            let forward = state.forwarded;
+            state.ended |= dd.end_of_frame;
+            return Av1Decision {
+                forward,
+                marker: forward && dd.end_of_frame && self.is_top(dd),
+                needs_keyframe: false,
+            };
+        }
+
+        // A frame started but never ended is incomplete, and must not be depended on.
+        if let Some(last) = self.history.back_mut() {
+            if !last.ended {
+                last.forwarded = false;
+            }
+        }
+
+        let chains = self.chains(dd);
+
+        let mut state = FrameState {
+            frame_number: n,
+            forwarded: false,
+            ended: dd.end_of_frame,
+            chains,
+        };
+
+        let (forward, decodable) = self.decide(dd, chains);
+        state.forwarded = forward;
+
+        self.history.push_back(state);
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+
+        let mut needs_keyframe = false;
+
+        if let Some(decodable) = decodable {
+            if decodable && !self.decodable {
+                self.keyframe_asked = false;
+            }
+            self.decodable = decodable;
+
+            if !decodable && !self.keyframe_asked {
+                self.keyframe_asked = true;
+                needs_keyframe = true;
+            }
+        }
+
+        Av1Decision {
+            forward,
+            marker: forward && dd.end_of_frame && self.is_top(dd),
+            needs_keyframe,
+        }
+    }
+
+    /// Whether to forward a new frame, and whether the decode target is decodable as of
+    /// the frame. The latter is None for frames that don't tell.
+    fn decide(&self, dd: &DependencyDescriptor, chains: u32) -> (bool, Option<bool>) {
+        let (Some(s), Some(frame)) = (&dd.structure, &dd.frame) else {
+            // Without the structure, we don't know anything about the frame.
+            return (false, Some(false));
+        };
+
+        let target = self.target as usize;
+        let dti = frame.dtis.get(target).copied().unwrap_or(Dti::NotPresent);
+
+        if dti == Dti::NotPresent {
+            return (false, None);
+        }
+
+        // The chain protecting the target tells whether it's decodable. Without chains,
+        // we go by whether the frames the target requires can be forwarded.
+        let chain_intact = s
+            .decode_target_protected_by_chain
+            .get(target)
+            .map(|c| chains & (1 << c) > 0);
+
+        let refs_forwarded = frame.fdiffs.iter().all(|d| {
+            self.state(dd.frame_number.wrapping_sub(*d))
+                .map(|s| s.forwarded)
+                .unwrap_or(false)
+        });
+
+        // The first packet missing means the frame is incomplete.
+        let forward = dd.start_of_frame && chain_intact.unwrap_or(true) && refs_forwarded;
+
+        let decodable = match chain_intact {
+            Some(v) => Some(v),
+            None if dti != Dti::Discardable => Some(forward),
+            None => None,
+        };
+
+        (forward, decodable)
+    }
+
+    /// The intact chains at a new frame.
+    fn chains(&self, dd: &DependencyDescriptor) -> u32 {
+        let Some(frame) = &dd.frame else {
+            return 0;
+        };
+
+        let mut chains = 0;
+        for (c, d) in frame.chain_fdiffs.iter().enumerate() {
+            // 0 means there is no earlier frame in the chain, i.e. it starts here.
+            let intact = *d == 0
+                || self
+                    .state(dd.frame_number.wrapping_sub(*d as u16))
+                    .map(|s| s.forwarded && s.chains & (1 << c) > 0)
+                    .unwrap_or(false);
+            if intact {
+                chains |= 1 << c;
+            }
+        }
+
+        chains
+    }
+
+    /// Whether the frame is of the highest spatial layer of the decode target.
+    fn is_top(&self, dd: &DependencyDescriptor) -> bool {
+        let (Some(s), Some(frame)) = (&dd.structure, &dd.frame) else {
+            return false;
+        };
+
+        let target = self.target as usize;
+        let top = s
+            .templates
+            .iter()
+            .filter(|t| t.dtis.get(target).map_or(false, |d| *d != Dti::NotPresent))
+            .map(|t| t.spatial_id)
+            .max();
+
+        top == Some(frame.spatial_id)
+    }
+
+    fn state(&self, frame_number: u16) -> Option<&FrameState> {
+        self.history
+            .iter()
+            .rev()
+            .find(|s| s.frame_number == frame_number)
+    }
+
+    fn state_mut(&mut self, frame_number: u16) -> Option<&mut FrameState> {
+        self.history
+            .iter_mut()
+            .rev()
+            .find(|s| s.frame_number == frame_number)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::rtp_::{FrameDependency, FrameDependencyStructure};
+
+    use Dti::*;
+
+    fn template(
+        spatial_id: u8,
+        temporal_id: u8,
+        dtis: &[Dti],
+        fdiffs: &[u16],
+        chain_fdiffs: &[u8],
+    ) -> FrameDependency {
+        FrameDependency {
+            spatial_id,
+            temporal_id,
+            dtis: dtis.to_vec(),
+            fdiffs: fdiffs.to_vec(),
+            chain_fdiffs: chain_fdiffs.to_vec(),
+        }
+    }
+
+    /// L1T3 as libwebrtc sends it. Decode targets T0, T1 and T2, protected by one chain
+    /// of the T0 frames.
+    fn l1t3() -> FrameDependencyStructure {
+        FrameDependencyStructure {
+            template_id_offset: 0,
+            decode_target_count: 3,
+            chain_count: 1,
+            decode_target_protected_by_chain: vec![0, 0, 0],
+            templates: vec![
+                template(0, 0, &[Switch, Switch, Switch], &[], &[0]),
+                template(0, 0, &[Switch, Switch, Switch], &[4], &[4]),
+                template(0, 1, &[NotPresent, Switch, Switch], &[2], &[2]),
+                template(0, 2, &[NotPresent, NotPresent, Discardable], &[1], &[1]),
+                template(0, 2, &[NotPresent, NotPresent, Discardable], &[1], &[3]),
+            ],
+            resolutions: vec![],
+        }
+    }
+
+    /// L2T1 k-SVC as libwebrtc sends it. Decode targets S0 and S1, each protected by its
+    /// own chain. S1 only depends on S0 in keyframes.
+    fn l2t1_key() -> FrameDependencyStructure {
+        FrameDependencyStructure {
+            template_id_offset: 0,
+            decode_target_count: 2,
+            chain_count: 2,
+            decode_target_protected_by_chain: vec![0, 1],
+            templates: vec![
+                template(0, 0, &[Switch, Switch], &[], &[0, 0]),
+                template(0, 0, &[Switch, NotPresent], &[2], &[2, 1]),
+                template(1, 0, &[NotPresent, Switch], &[1], &[1, 1]),
+                template(1, 0, &[NotPresent, Required], &[2], &[1, 2]),
+            ],
+            resolutions: vec![],
+        }
+    }
+
+    /// A trace of frames of 2 packets each, by template index.
+    ///
+    /// Returns the packets with the frame number and whether they're the end of a
+    /// temporal unit, which is where the sender sets the marker.
+    fn trace(
+        s: FrameDependencyStructure,
+        templates: &[usize],
+        temporal_unit: usize,
+    ) -> Vec<DependencyDescriptor> {
+        let s = Arc::new(s);
+        let mut packets = vec![];
+
+        for (n, t) in templates.iter().enumerate() {
+            for p in 0..2 {
+                let mut dd = DependencyDescriptor::new(p == 0, p == 1, *t as u8, n as u16);
+                dd.structure = Some(s.clone());
+                dd.structure_attached = *t == 0 && p == 0;
+                dd.frame = Some(s.templates[*t].clone());
+                packets.push(dd);
+            }
+        }
+
+        // Sanity check the trace itself, every frame's references exist.
+        for (n, t) in templates.iter().enumerate() {
+            for d in &s.templates[*t].fdiffs {
+                assert!(n >= *d as usize, "Bad trace at frame {}", n);
+            }
+        }
+        assert_eq!(templates.len() % temporal_unit, 0);
+
+        packets
+    }
+
+    /// L1T3 frames: keyframe, then T2 T1 T2 T0 repeating.
+    fn l1t3_templates(frames: usize, keyframes: &[usize]) -> Vec<usize> {
+        let mut templates = vec![];
+        let mut since_key = 0;
+        for n in 0..frames {
+            if keyframes.contains(&n) {
+                since_key = 0;
+            }
+            templates.push(match since_key % 4 {
+                0 if since_key == 0 => 0,
+                0 => 1,
+                1 => 3,
+                2 => 2,
+                _ => 4,
+            });
+            since_key += 1;
+        }
+        templates
+    }
+
+    /// L2T1 k-SVC frames: S0 S1 per temporal unit.
+    fn l2t1_templates(units: usize, keyframes: &[usize]) -> Vec<usize> {
+        let mut templates = vec![];
+        for u in 0..units {
+            if keyframes.contains(&u) {
+                templates.extend([0, 2]);
+            } else {
+                templates.extend([1, 3]);
+            }
+        }
+        templates
+    }
+
+    struct Outcome {
+        frames: Vec<u16>,
+        markers: Vec<u16>,
+        keyframe_needed: Vec<u16>,
+    }
+
+    /// Run the packets, skipping the lost frames and selecting a new target at a frame.
+    fn run(
+        selector: &mut Av1Selector,
+        packets: &[DependencyDescriptor],
+        lost: &[u16],
+        changes: &[(u16, u8)],
+    ) -> Outcome {
+        let mut out = Outcome {
+            frames: vec![],
+            markers: vec![],
+            keyframe_needed: vec![],
+        };
+
+        for dd in packets {
+            let n = dd.frame_number;
+            if lost.contains(&n) {
+                continue;
+            }
+            if dd.start_of_frame {
+                for (at, target) in changes {
+                    if *at == n {
+                        selector.select(*target);
+                    }
+                }
+            }
+
+            let d = selector.forward(dd);
+            if d.forward && dd.start_of_frame {
+                out.frames.push(n);
+            }
+            if d.marker {
+                out.markers.push(n);
+            }
+            if d.needs_keyframe {
+                out.keyframe_needed.push(n);
+            }
+        }
+
+        out
+    }
+
+    /// Frames of the decode target, which is what a conforming selector must forward
+    /// without loss.
+    fn target_frames(packets: &[DependencyDescriptor], target: usize) -> Vec<u16> {
+        packets
+            .iter()
+            .filter(|dd| dd.start_of_frame)
+            .filter(|dd| dd.frame.as_ref().unwrap().dtis[target] != NotPresent)
+            .map(|dd| dd.frame_number)
+            .collect()
+    }
+
+    #[test]
+    fn l1t3_per_target() {
+        let packets = trace(l1t3(), &l1t3_templates(16, &[0]), 1);
+
+        let expected = [
+            vec![0, 4, 8, 12],
+            vec![0, 2, 4, 6, 8, 10, 12, 14],
+            (0..16).collect(),
+        ];
+
+        for (target, expected) in expected.iter().enumerate() {
+            let mut selector = Av1Selector::new(target as u8);
+            let out = run(&mut selector, &packets, &[], &[]);
+
+            assert_eq!(&out.frames, expected, "Target {}", target);
+            assert_eq!(target_frames(&packets, target), *expected);
+            assert_eq!(out.markers, *expected);
+            assert!(out.keyframe_needed.is_empty());
+            assert!(selector.is_decodable());
+        }
+    }
+
+    #[test]
+    fn l1t3_loss_of_discardable() {
+        let packets = trace(l1t3(), &l1t3_templates(12, &[0]), 1);
+        let mut selector = Av1Selector::new(2);
+
+        // Frame 2 is T1, which frame 3 (T2) depends on. The chain is only T0 frames.
+        let out = run(&mut selector, &packets, &[2], &[]);
+
+        assert_eq!(out.frames, vec![0, 1, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert!(out.keyframe_needed.is_empty());
+        assert!(selector.is_decodable());
+    }
+
+    #[test]
+    fn l1t3_loss_breaks_chain() {
+        let packets = trace(l1t3(), &l1t3_templates(16, &[0, 12]), 1);
+        let mut selector = Av1Selector::new(1);
+
+        // Frame 4 is T0, the chain is broken until the keyframe at 12.
+        let out = run(&mut selector, &packets, &[4], &[]);
+
+        assert_eq!(out.frames, vec![0, 2, 12, 14]);
+        assert_eq!(out.keyframe_needed, vec![6]);
+        assert!(selector.is_decodable());
+    }
+
+    #[test]
+    fn l1t3_partial_frame() {
+        let mut packets = trace(l1t3(), &l1t3_templates(8, &[0]), 1);
+        // The first packet of frame 2 (T1) is lost.
+        packets.remove(4);
+
+        let mut selector = Av1Selector::new(2);
+        let out = run(&mut selector, &packets, &[], &[]);
+
+        // Frame 3 depends on 2, but the chain of T0 frames is intact.
+        assert_eq!(out.frames, vec![0, 1, 4, 5, 6, 7]);
+        assert!(out.keyframe_needed.is_empty());
+        assert!(selector.is_decodable());
+    }
+
+    #[test]
+    fn l1t3_switch_up_and_down() {
+        let packets = trace(l1t3(), &l1t3_templates(16, &[0]), 1);
+        let mut selector = Av1Selector::new(0);
+
+        // Up to T2 at frame 3, which depends on T1 frame 2 not forwarded. Down to T0 at 9.
+        let out = run(&mut selector, &packets, &[], &[(3, 2), (9, 0)]);
+
+        assert_eq!(out.frames, vec![0, 4, 5, 6, 7, 8, 12]);
+        assert!(out.keyframe_needed.is_empty());
+    }
+
+    #[test]
+    fn l2t1_key_per_target() {
+        let packets = trace(l2t1_key(), &l2t1_templates(6, &[0]), 2);
+
+        let mut selector = Av1Selector::new(0);
+        let out = run(&mut selector, &packets, &[], &[]);
+        assert_eq!(out.frames, vec![0, 2, 4, 6, 8, 10]);
+        assert_eq!(out.frames, target_frames(&packets, 0));
+        // The end of S0 is the end of the temporal unit for the receiver.
+        assert_eq!(out.markers, out.frames);
+
+        let mut selector = Av1Selector::new(1);
+        let out = run(&mut selector, &packets, &[], &[]);
+        assert_eq!(out.frames, vec![0, 1, 3, 5, 7, 9, 11]);
+        assert_eq!(out.frames, target_frames(&packets, 1));
+        assert_eq!(out.markers, vec![1, 3, 5, 7, 9, 11]);
+    }
+
+    #[test]
+    fn l2t1_key_switch_up_needs_keyframe() {
+        let packets = trace(l2t1_key(), &l2t1_templates(8, &[0, 5]), 2);
+        let mut selector = Av1Selector::new(0);
+
+        // Up to S1 at frame 4. S1 frames depend on earlier S1 frames, not forwarded.
+        let out = run(&mut selector, &packets, &[], &[(4, 1)]);
+
+        assert_eq!(out.keyframe_needed, vec![5]);
+        assert_eq!(out.frames, vec![0, 2, 10, 11, 13, 15]);
+        assert!(selector.is_decodable());
+
+        // Down to S0 at frame 6 also waits, since the S0 delta frames aren't in S1.
+        let mut selector = Av1Selector::new(1);
+        let out = run(&mut selector, &packets, &[], &[(6, 0)]);
+        assert_eq!(out.frames, vec![0, 1, 3, 5, 10, 12, 14]);
+        assert_eq!(out.keyframe_needed, vec![6]);
+    }
+
+    #[test]
+    fn no_structure() {
+        // Joining mid-stream, the structure comes with the keyframe.
+        let s = Arc::new(l1t3());
+        let mut selector = Av1Selector::new(0);
+
+        let mut dd = DependencyDescriptor::new(true, true, 1, 100);
+        let d = selector.forward(&dd);
+        assert!(!d.forward);
+        assert!(d.needs_keyframe);
+
+        dd = DependencyDescriptor::new(true, true, 0, 101);
+        dd.structure = Some(s.clone());
+        dd.structure_attached = true;
+        dd.frame = Some(s.templates[0].clone());
+        let d = selector.forward(&dd);
+        assert!(d.forward);
+        assert!(d.marker);
+        assert!(selector.is_decodable());
+    }
+}
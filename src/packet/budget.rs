@@ -0,0 +1,227 @@
+use std::time::{Duration, Instant};
+
+use crate::rtp_::{Bitrate, DataSize};
+
+/// How much unused rate can be saved up for padding.
+const MAX_SAVED: Duration = Duration::from_millis(40);
+
+/// How much overshoot is carried over, before it's forgiven.
+const MAX_OVERSHOOT: Duration = Duration::from_millis(500);
+
+/// What a sent packet was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendClass {
+    /// Regular media, including FEC.
+    Media,
+    /// Resends in response to NACK.
+    Rtx,
+    /// Padding, blank or resent history.
+    Padding,
+}
+
+/// Shared accounting of what is sent on the link.
+///
+/// Media, RTX and padding all draw from the same target rate. Padding is only allowed to
+/// use whatever the other two leave over, so probing with padding can't push media over
+/// the estimate.
+#[derive(Debug)]
+pub struct SendBudget {
+    /// The rate everything together should stay under.
+    target: Bitrate,
+    /// Bits that can be sent without exceeding the target. Negative after overshooting.
+    available: f64,
+    last_refill: Option<Instant>,
+    /// Padding isn't limited until this time. For probe clusters.
+    exempt_until: Option<Instant>,
+    /// Total payload bytes sent per class.
+    totals: SendTotals,
+}
+
+/// Total payload bytes sent per [`SendClass`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SendTotals {
+    pub media: u64,
+    pub rtx: u64,
+    pub padding: u64,
+}
+
+impl SendBudget {
+    pub fn new() -> Self {
+        SendBudget {
+            target: Bitrate::ZERO,
+            available: 0.0,
+            last_refill: None,
+            exempt_until: None,
+            totals: SendTotals::default(),
+        }
+    }
+
+    /// Set the rate media, RTX and padding together should stay under.
+    ///
+    /// This is the padding rate, never above the estimate.
+    pub fn set_target(&mut self, target: Bitrate) {
+        self.target = target;
+    }
+
+    /// Let padding go beyond the budget until `until`.
+    ///
+    /// A probe cluster deliberately sends above the current rate to find out if the link
+    /// takes it. What is sent still counts.
+    #[allow(unused)]
+    pub fn exempt_until(&mut self, until: Instant) {
+        self.exempt_until = Some(until);
+    }
+
+    /// Move time forward, which makes room for more.
+    pub fn handle_timeout(&mut self, now: Instant) {
+        let Some(last) = self.last_refill else {
+            self.last_refill = Some(now);
+            return;
+        };
+
+        let elapsed = now.saturating_duration_since(last);
+        self.last_refill = Some(now);
+
+        let bits_per_sec = self.target.as_f64();
+        self.available = (self.available + bits_per_sec * elapsed.as_secs_f64()).clamp(
+            -bits_per_sec * MAX_OVERSHOOT.as_secs_f64(),
+            bits_per_sec * MAX_SAVED.as_secs_f64(),
+        );
+    }
+
+    /// Forget any unused rate saved up for padding. Overshoot is still carried over.
+    pub fn clear_saved(&mut self) {
+        self.available = self.available.min(0.0);
+    }
+
+    /// Record a sent packet.
+    pub fn register(&mut self, class: SendClass, size: DataSize) {
+        let bytes = size.as_bytes_usize() as u64;
+
+        match class {
+            SendClass::Media => self.totals.media += bytes,
+            SendClass::Rtx => self.totals.rtx += bytes,
+            SendClass::Padding => self.totals.padding += bytes,
+        }
+
+        self.available -= bytes as f64 * 8.0;
+    }
+
+    /// The number of padding bytes that can be sent right now.
+    pub fn padding_allowed(&self, now: Instant) -> usize {
+        if self.is_exempt(now) {
+            return usize::MAX;
+        }
+
+        (self.available.max(0.0) / 8.0) as usize
+    }
+
+    /// How long until there's room for padding again.
+    pub fn padding_wait(&self, now: Instant) -> Duration {
+        if self.is_exempt(now) || self.available > 0.0 || self.target == Bitrate::ZERO {
+            return Duration::ZERO;
+        }
+
+        // Wait until at least one byte is available.
+        let bits = -self.available + 8.0;
+        Duration::from_secs_f64(bits / self.target.as_f64())
+    }
+
+    /// Total payload bytes sent per class.
+    pub fn totals(&self) -> SendTotals {
+        self.totals
+    }
+
+    fn is_exempt(&self, now: Instant) -> bool {
+        self.exempt_until.map_or(false, |t| now < t)
+    }
+}
+
+impl Default for SendBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn padding_fills_the_gap() {
+        let mut budget = SendBudget::new();
+        budget.set_target(Bitrate::kbps(1000));
+
+        let now = Instant::now();
+        budget.handle_timeout(now);
+
+        // 10ms at 1Mbit/s is 1250 bytes, media takes 1000 of them.
+        let now = now + Duration::from_millis(10);
+        budget.handle_timeout(now);
+        budget.register(SendClass::Media, DataSize::bytes(800));
+        budget.register(SendClass::Rtx, DataSize::bytes(200));
+
+        assert_eq!(budget.padding_allowed(now), 250);
+
+        budget.register(SendClass::Padding, DataSize::bytes(250));
+        assert_eq!(budget.padding_allowed(now), 0);
+
+        assert_eq!(
+            budget.totals(),
+            SendTotals {
+                media: 800,
+                rtx: 200,
+                padding: 250
+            }
+        );
+    }
+
+    #[test]
+    fn overshoot_delays_padding() {
+        let mut budget = SendBudget::new();
+        budget.set_target(Bitrate::kbps(1000));
+
+        let now = Instant::now();
+        budget.handle_timeout(now);
+
+        // 20ms worth of media in one go.
+        budget.register(SendClass::Media, DataSize::bytes(2500));
+        assert_eq!(budget.padding_allowed(now), 0);
+
+        let wait = budget.padding_wait(now);
+        assert!(wait > Duration::from_millis(19) && wait < Duration::from_millis(21));
+
+        budget.handle_timeout(now + wait);
+        assert!(budget.padding_allowed(now + wait) > 0);
+    }
+
+    #[test]
+    fn saved_up_is_limited() {
+        let mut budget = SendBudget::new();
+        budget.set_target(Bitrate::kbps(1000));
+
+        let now = Instant::now();
+        budget.handle_timeout(now);
+        budget.handle_timeout(now + Duration::from_secs(10));
+
+        // 40ms at 1Mbit/s
+        assert_eq!(budget.padding_allowed(now), 5000);
+    }
+
+    #[test]
+    fn exempt() {
+        let mut budget = SendBudget::new();
+        budget.set_target(Bitrate::kbps(1000));
+
+        let now = Instant::now();
+        budget.handle_timeout(now);
+        budget.register(SendClass::Media, DataSize::bytes(2500));
+
+        budget.exempt_until(now + Duration::from_millis(5));
+        assert_eq!(budget.padding_allowed(now), usize::MAX);
+        assert_eq!(budget.padding_wait(now), Duration::ZERO);
+
+        let later = now + Duration::from_millis(5);
+        assert_eq!(budget.padding_allowed(later), 0);
+    }
+}
@@ -7,8 +7,6 @@ use std::time::Instant;
 use crate::rtp_::{ExtensionValues, MediaTime, RtpHeader, SenderInfo, SeqNo};
 
 use super::contiguity::{self, Contiguity};
-use super::contiguity_vp8::Vp8Contiguity;
-use super::contiguity_vp9::Vp9Contiguity;
 use super::{CodecDepacketizer, CodecExtra, Depacketizer, PacketError, Vp8CodecExtra};
 
 #[derive(Clone, PartialEq, Eq)]
@@ -60,10 +58,21 @@ impl Depacketized {
         first..=last
     }
 
-    pub fn ext_vals(&self) -> &ExtensionValues {
+    pub fn ext_vals(&self) -> ExtensionValues {
         // We use the extensions from the last packet because certain extensions, such as video
         // orientation, are only added on the last packet to save bytes.
-        &self.meta[self.meta.len() - 1].header.ext_vals
+        let mut ext_vals = self.meta[self.meta.len() - 1].header.ext_vals.clone();
+
+        // The color space is only sent for keyframes, and senders differ in which
+        // packet of the frame it goes on.
+        if ext_vals.color_space.is_none() {
+            ext_vals.color_space = self
+                .meta
+                .iter()
+                .find_map(|m| m.header.ext_vals.color_space.clone());
+        }
+
+        ext_vals
     }
 }
 
@@ -89,15 +98,7 @@ pub struct DepacketizingBuffer {
 
 impl DepacketizingBuffer {
     pub(crate) fn new(depack: CodecDepacketizer, hold_back: usize) -> Self {
-        let contiguity = match depack {
-            CodecDepacketizer::Vp8(_) => Contiguity::Vp8(Vp8Contiguity::new()),
-            CodecDepacketizer::Vp9(_) => Contiguity::Vp9(Vp9Contiguity::new()),
-            CodecDepacketizer::H264(_)
-            | CodecDepacketizer::H265(_)
-            | CodecDepacketizer::Boxed(_)
-            | CodecDepacketizer::Opus(_)
-            | CodecDepacketizer::Null(_) => Contiguity::None,
-        };
+        let contiguity = (&depack).into();
 
         DepacketizingBuffer {
             hold_back,
@@ -111,6 +112,17 @@ impl DepacketizingBuffer {
         }
     }
 
+    /// Drop all buffered packets and start over, such as after a stream restart.
+    pub fn reset(&mut self) {
+        self.depack.reset();
+        self.queue.clear();
+        self.segments.clear();
+        self.last_emitted = None;
+        self.max_time = None;
+        self.depack_cache = None;
+        self.contiguity = (&self.depack).into();
+    }
+
     pub fn push(&mut self, meta: RtpMeta, data: Vec<u8>) {
         // We're not emitting samples in the wrong order. If we receive
         // packets that are before the last emitted, we drop.
@@ -475,6 +487,56 @@ mod test {
         ])
     }
 
+    #[test]
+    fn color_space_from_any_packet() {
+        use crate::rtp_::{ChromaSiting, ColorPrimaries, ColorRange, ColorSpace};
+        use crate::rtp_::{MatrixCoefficients, TransferCharacteristics, VideoOrientation};
+
+        let color_space = ColorSpace {
+            primaries: ColorPrimaries::Bt709,
+            transfer: TransferCharacteristics::Bt709,
+            matrix: MatrixCoefficients::Bt709,
+            range: ColorRange::Limited,
+            chroma_siting_horizontal: ChromaSiting::Unspecified,
+            chroma_siting_vertical: ChromaSiting::Unspecified,
+            hdr_metadata: None,
+        };
+
+        let depack = CodecDepacketizer::Boxed(Box::new(TestDepack));
+        let mut buf = DepacketizingBuffer::new(depack, 0);
+
+        let meta = |seq: u64, ext_vals: ExtensionValues| RtpMeta {
+            received: Instant::now(),
+            seq_no: seq.into(),
+            time: MediaTime::from_90khz(1),
+            last_sender_info: None,
+            header: RtpHeader {
+                sequence_number: seq as u16,
+                timestamp: 1,
+                ext_vals,
+                ..Default::default()
+            },
+        };
+
+        let first = ExtensionValues {
+            color_space: Some(Box::new(color_space)),
+            ..Default::default()
+        };
+        let last = ExtensionValues {
+            video_orientation: Some(VideoOrientation::Deg90),
+            ..Default::default()
+        };
+
+        buf.push(meta(1, first), vec![1]);
+        buf.push(meta(2, last), vec![9]);
+
+        let dep = buf.pop().unwrap().unwrap();
+        let ext_vals = dep.ext_vals();
+
+        assert_eq!(ext_vals.color_space.as_deref(), Some(&color_space));
+        assert_eq!(ext_vals.video_orientation, Some(VideoOrientation::Deg90));
+    }
+
     fn test(
         v: &[(
             u64,   // seq
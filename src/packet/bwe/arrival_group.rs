@@ -1,7 +1,7 @@
 use std::mem;
 use std::time::{Duration, Instant};
 
-use crate::rtp_::SeqNo;
+use crate::rtp_::{DataSize, SeqNo};
 
 use super::AckedPacket;
 
@@ -14,6 +14,8 @@ pub struct ArrivalGroup {
     last_local_send_time: Option<Instant>,
     last_remote_recv_time: Option<Instant>,
     size: usize,
+    /// Total size of the packets in the group.
+    bytes: DataSize,
 }
 
 impl ArrivalGroup {
@@ -40,6 +42,7 @@ impl ArrivalGroup {
             .max(Some(packet.remote_recv_time));
         self.last_local_send_time = self.last_local_send_time.max(Some(packet.local_send_time));
         self.size += 1;
+        self.bytes += packet.size;
         self.last_seq_no = self.last_seq_no.max(Some(packet.seq_no));
 
         false
@@ -171,6 +174,7 @@ impl ArrivalGroupAccumulator {
         // Variation between previous group and current.
         let delay_delta = self.inter_group_delay_delta();
         let send_delta = self.send_delta();
+        let size_delta = self.size_delta();
         let last_remote_recv_time = self.current_group.remote_recv_time();
 
         let current_group = mem::take(&mut self.current_group);
@@ -181,6 +185,7 @@ impl ArrivalGroupAccumulator {
         Some(InterGroupDelayDelta {
             send_delta: send_delta?,
             delay_delta: delay_delta?,
+            size_delta: size_delta?,
             last_remote_recv_time,
         })
     }
@@ -196,6 +201,12 @@ impl ArrivalGroupAccumulator {
             .as_ref()
             .and_then(|prev| prev.departure_delta(&self.current_group))
     }
+
+    fn size_delta(&self) -> Option<f64> {
+        self.previous_group
+            .as_ref()
+            .map(|prev| self.current_group.bytes.as_bytes_f64() - prev.bytes.as_bytes_f64())
+    }
 }
 
 /// The calculate delay delta between two groups of packets.
@@ -206,6 +217,8 @@ pub(super) struct InterGroupDelayDelta {
     pub(super) send_delta: Duration,
     /// The delay delta between the two groups.
     pub(super) delay_delta: f64,
+    /// The difference in size between the two groups in bytes.
+    pub(super) size_delta: f64,
     /// The reported receive time for the last packet in the first arrival group.
     pub(super) last_remote_recv_time: Instant,
 }
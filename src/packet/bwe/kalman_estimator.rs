@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::{BandwithUsage, InterGroupDelayDelta};
+
+// Ported from libWebRTC's src/modules/remote_bitrate_estimator/overuse_estimator.cc and
+// overuse_detector.cc. This is the detector used by the receive side estimator, before the
+// trendline estimator replaced it for TWCC.
+
+const MIN_FRAME_PERIOD_HISTORY: usize = 60;
+const DELTA_COUNTER_MAX: usize = 1000;
+const DELAY_COUNT_MAX: usize = 60;
+
+const PROCESS_NOISE: [f64; 2] = [1e-13, 1e-3];
+const INITIAL_SLOPE: f64 = 8.0 / 512.0;
+const INITIAL_VAR_NOISE: f64 = 50.0;
+
+const OVER_USE_THRESHOLD_DEFAULT_MS: f64 = 12.5;
+const OVER_USE_TIME_THRESHOLD: Duration = Duration::from_millis(10);
+const MAX_ADOPT_OFFSET_MS: f64 = 15.0;
+const MAX_TIME_DELTA_MS: f64 = 100.0;
+const THRESHOLD_RANGE_MS: (f64, f64) = (6.0, 600.0);
+
+const K_UP: f64 = 0.0087;
+const K_DOWN: f64 = 0.039;
+
+/// Kalman filter estimating the queuing delay from inter group delay variations.
+///
+/// The state is the inverse capacity (slope against the size difference between groups)
+/// and the offset, which is the queuing delay. The offset is compared to an adaptive
+/// threshold to form the hypothesis.
+pub(super) struct KalmanEstimator {
+    /// Estimated inverse capacity, ms per byte.
+    slope: f64,
+    /// Estimated queuing delay in ms.
+    offset: f64,
+    /// Offset before the last update.
+    previous_offset: f64,
+    /// Error covariance.
+    e: [[f64; 2]; 2],
+    avg_noise: f64,
+    var_noise: f64,
+    /// Number of delay variations seen, capped.
+    num_deltas: usize,
+    /// Recent send deltas in ms, to find the frame period.
+    send_deltas: VecDeque<f64>,
+
+    /// The adaptive offset threshold in ms.
+    threshold: f64,
+    /// The last time we updated the adaptive threshold.
+    last_threshold_update: Option<Instant>,
+    /// How long the offset has been above the threshold.
+    time_over_using: Option<Duration>,
+    /// Number of consecutive delay variations above the threshold.
+    overuse_counter: usize,
+    /// Offset at the last detection.
+    detected_offset: f64,
+
+    /// Our current hypothesis about the bandwidth usage.
+    hypothesis: BandwithUsage,
+}
+
+impl KalmanEstimator {
+    pub(super) fn new() -> Self {
+        Self {
+            slope: INITIAL_SLOPE,
+            offset: 0.0,
+            previous_offset: 0.0,
+            e: [[100.0, 0.0], [0.0, 1e-1]],
+            avg_noise: 0.0,
+            var_noise: INITIAL_VAR_NOISE,
+            num_deltas: 0,
+            send_deltas: VecDeque::default(),
+            threshold: OVER_USE_THRESHOLD_DEFAULT_MS,
+            last_threshold_update: None,
+            time_over_using: None,
+            overuse_counter: 0,
+            detected_offset: 0.0,
+            hypothesis: BandwithUsage::Normal,
+        }
+    }
+
+    pub(super) fn add_delay_observation(
+        &mut self,
+        delay_variation: InterGroupDelayDelta,
+        now: Instant,
+    ) {
+        let send_delta = delay_variation.send_delta.as_secs_f64() * 1000.0;
+
+        self.update_filter(delay_variation, send_delta);
+        self.detect(send_delta, now);
+    }
+
+    pub(super) fn hypothesis(&self) -> BandwithUsage {
+        self.hypothesis
+    }
+
+    /// The estimated queuing delay in ms.
+    #[cfg(test)]
+    pub(super) fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    fn update_filter(&mut self, delay_variation: InterGroupDelayDelta, send_delta: f64) {
+        let min_frame_period = self.update_min_frame_period(send_delta);
+
+        self.num_deltas = (self.num_deltas + 1).min(DELTA_COUNTER_MAX);
+
+        let e = &mut self.e;
+        e[0][0] += PROCESS_NOISE[0];
+        e[1][1] += PROCESS_NOISE[1];
+
+        let offset_moving_away = match self.hypothesis {
+            BandwithUsage::Overuse => self.offset < self.previous_offset,
+            BandwithUsage::Underuse => self.offset > self.previous_offset,
+            BandwithUsage::Normal => false,
+        };
+        if offset_moving_away {
+            e[1][1] += 10.0 * PROCESS_NOISE[1];
+        }
+
+        let h = [delay_variation.size_delta, 1.0];
+        let eh = [
+            e[0][0] * h[0] + e[0][1] * h[1],
+            e[1][0] * h[0] + e[1][1] * h[1],
+        ];
+
+        let residual = delay_variation.delay_delta - self.slope * h[0] - self.offset;
+
+        // Outliers are capped to not throw off the noise estimate.
+        let max_residual = 3.0 * self.var_noise.sqrt();
+        let stable = self.hypothesis == BandwithUsage::Normal;
+        self.update_noise_estimate(
+            residual.clamp(-max_residual, max_residual),
+            min_frame_period,
+            stable,
+        );
+
+        let e = &mut self.e;
+        let denom = self.var_noise + h[0] * eh[0] + h[1] * eh[1];
+        let k = [eh[0] / denom, eh[1] / denom];
+
+        let ikh = [
+            [1.0 - k[0] * h[0], -k[0] * h[1]],
+            [-k[1] * h[0], 1.0 - k[1] * h[1]],
+        ];
+        let e00 = e[0][0];
+        let e01 = e[0][1];
+
+        e[0][0] = e00 * ikh[0][0] + e[1][0] * ikh[0][1];
+        e[0][1] = e01 * ikh[0][0] + e[1][1] * ikh[0][1];
+        e[1][0] = e00 * ikh[1][0] + e[1][0] * ikh[1][1];
+        e[1][1] = e01 * ikh[1][0] + e[1][1] * ikh[1][1];
+
+        self.previous_offset = self.offset;
+        self.slope += k[0] * residual;
+        self.offset += k[1] * residual;
+
+        trace!(
+            "Kalman update slope: {:.5} offset: {:.3} var_noise: {:.3}",
+            self.slope,
+            self.offset,
+            self.var_noise
+        );
+    }
+
+    fn update_min_frame_period(&mut self, send_delta: f64) -> f64 {
+        if self.send_deltas.len() >= MIN_FRAME_PERIOD_HISTORY {
+            self.send_deltas.pop_front();
+        }
+
+        let min = self.send_deltas.iter().copied().fold(send_delta, f64::min);
+
+        self.send_deltas.push_back(send_delta);
+
+        min
+    }
+
+    fn update_noise_estimate(&mut self, residual: f64, send_delta: f64, stable: bool) {
+        if !stable {
+            return;
+        }
+
+        // Faster filter during startup to faster adapt to the jitter level of the network.
+        let alpha: f64 = if self.num_deltas > 10 * 30 {
+            0.002
+        } else {
+            0.01
+        };
+
+        // Only update the noise estimate if we're not over-using. Beta is a function of
+        // alpha and the time delta since the previous update.
+        let beta = (1.0 - alpha).powf(send_delta * 30.0 / 1000.0);
+
+        self.avg_noise = beta * self.avg_noise + (1.0 - beta) * residual;
+        self.var_noise = beta * self.var_noise + (1.0 - beta) * (self.avg_noise - residual).powi(2);
+
+        if self.var_noise < 1.0 {
+            self.var_noise = 1.0;
+        }
+    }
+
+    fn detect(&mut self, send_delta: f64, now: Instant) {
+        if self.num_deltas < 2 {
+            self.hypothesis = BandwithUsage::Normal;
+            return;
+        }
+
+        let modified_offset = self.num_deltas.min(DELAY_COUNT_MAX) as f64 * self.offset;
+
+        if modified_offset > self.threshold {
+            let time_over_using = match self.time_over_using {
+                // Initialize the timer. Assume that we've been over-using half of the time
+                // since the previous sample.
+                None => Duration::from_secs_f64(send_delta / 2.0 / 1000.0),
+                Some(t) => t + Duration::from_secs_f64(send_delta / 1000.0),
+            };
+            self.time_over_using = Some(time_over_using);
+            self.overuse_counter += 1;
+
+            if time_over_using > OVER_USE_TIME_THRESHOLD
+                && self.overuse_counter > 1
+                && self.offset >= self.detected_offset
+            {
+                self.time_over_using = Some(Duration::ZERO);
+                self.overuse_counter = 0;
+                self.set_hypothesis(BandwithUsage::Overuse);
+            }
+        } else if modified_offset < -self.threshold {
+            self.time_over_using = None;
+            self.overuse_counter = 0;
+            self.set_hypothesis(BandwithUsage::Underuse);
+        } else {
+            self.time_over_using = None;
+            self.overuse_counter = 0;
+            self.set_hypothesis(BandwithUsage::Normal);
+        }
+
+        self.detected_offset = self.offset;
+        self.update_threshold(modified_offset, now);
+    }
+
+    fn set_hypothesis(&mut self, hypothesis: BandwithUsage) {
+        if self.hypothesis != hypothesis {
+            debug!(
+                "Kalman estimator: Moving from {} to {}",
+                self.hypothesis, hypothesis
+            );
+        }
+        self.hypothesis = hypothesis;
+    }
+
+    fn update_threshold(&mut self, modified_offset: f64, now: Instant) {
+        let last_update = *self.last_threshold_update.get_or_insert(now);
+
+        let abs_offset = modified_offset.abs();
+        if abs_offset > self.threshold + MAX_ADOPT_OFFSET_MS {
+            // Avoid adapting the threshold to big latency spikes, caused e.g., by a sudden
+            // capacity drop.
+            self.last_threshold_update = Some(now);
+            return;
+        }
+
+        let k = if abs_offset < self.threshold {
+            K_DOWN
+        } else {
+            K_UP
+        };
+        let time_delta_ms = ((now - last_update).as_secs_f64() * 1000.0).min(MAX_TIME_DELTA_MS);
+
+        self.threshold += k * (abs_offset - self.threshold) * time_delta_ms;
+        self.threshold = self
+            .threshold
+            .clamp(THRESHOLD_RANGE_MS.0, THRESHOLD_RANGE_MS.1);
+        self.last_threshold_update = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn variation(delay_delta: f64, now: Instant) -> InterGroupDelayDelta {
+        InterGroupDelayDelta {
+            send_delta: Duration::from_millis(33),
+            delay_delta,
+            size_delta: 0.0,
+            last_remote_recv_time: now,
+        }
+    }
+
+    #[test]
+    fn stable_delay_is_normal() {
+        let mut estimator = KalmanEstimator::new();
+        let mut now = Instant::now();
+
+        for i in 0..200 {
+            // Some jitter around zero.
+            let jitter = if i % 2 == 0 { 1.0 } else { -1.0 };
+            estimator.add_delay_observation(variation(jitter, now), now);
+            now += Duration::from_millis(33);
+        }
+
+        assert_eq!(estimator.hypothesis(), BandwithUsage::Normal);
+        assert!(estimator.offset().abs() < 1.0);
+    }
+
+    #[test]
+    fn growing_delay_is_overuse() {
+        let mut estimator = KalmanEstimator::new();
+        let mut now = Instant::now();
+
+        for _ in 0..100 {
+            estimator.add_delay_observation(variation(0.0, now), now);
+            now += Duration::from_millis(33);
+        }
+
+        // Queue building up by 5ms per frame.
+        let mut overuse = false;
+        for _ in 0..20 {
+            estimator.add_delay_observation(variation(5.0, now), now);
+            now += Duration::from_millis(33);
+            overuse |= estimator.hypothesis() == BandwithUsage::Overuse;
+        }
+        assert!(overuse);
+
+        // Queue draining.
+        let mut underuse = false;
+        for _ in 0..20 {
+            estimator.add_delay_observation(variation(-5.0, now), now);
+            now += Duration::from_millis(33);
+            underuse |= estimator.hypothesis() == BandwithUsage::Underuse;
+        }
+        assert!(underuse);
+    }
+}
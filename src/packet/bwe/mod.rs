@@ -6,8 +6,10 @@
 
 mod acked_bitrate_estimator;
 mod arrival_group;
+mod kalman_estimator;
 pub(crate) mod macros;
 mod rate_control;
+mod remote;
 mod trendline_estimator;
 
 use std::cmp::Ordering;
@@ -15,6 +17,7 @@ use std::collections::VecDeque;
 use std::fmt;
 use std::time::{Duration, Instant};
 
+use crate::bwe::BweConfig;
 use crate::rtp_::{Bitrate, DataSize, SeqNo, TwccSendRecord};
 use crate::util::already_happened;
 
@@ -23,6 +26,8 @@ use arrival_group::{ArrivalGroupAccumulator, InterGroupDelayDelta};
 use rate_control::RateControl;
 use trendline_estimator::TrendlineEstimator;
 
+pub(crate) use remote::ReceiveSideBandwithEstimator;
+
 const MAX_RTT_HISTORY_WINDOW: usize = 32;
 const INITIAL_BITRATE_WINDOW: Duration = Duration::from_millis(500);
 const BITRATE_WINDOW: Duration = Duration::from_millis(150);
@@ -54,7 +59,7 @@ pub struct SendSideBandwithEstimator {
 }
 
 impl SendSideBandwithEstimator {
-    pub fn new(initial_bitrate: Bitrate) -> Self {
+    pub fn new(config: &BweConfig) -> Self {
         Self {
             arrival_group_accumulator: ArrivalGroupAccumulator::default(),
             trendline_estimator: TrendlineEstimator::new(20),
@@ -62,7 +67,11 @@ impl SendSideBandwithEstimator {
                 INITIAL_BITRATE_WINDOW,
                 BITRATE_WINDOW,
             ),
-            rate_control: RateControl::new(initial_bitrate, Bitrate::kbps(40), Bitrate::gbps(10)),
+            rate_control: RateControl::new(
+                config.start_bitrate(),
+                config.min_bitrate(),
+                config.max_bitrate(),
+            ),
             last_estimate: None,
             max_rtt_history: VecDeque::default(),
             mean_max_rtt: None,
@@ -153,6 +162,31 @@ impl SendSideBandwithEstimator {
         self.last_estimate
     }
 
+    /// Start over from `start_bitrate`, discarding all history.
+    ///
+    /// Unlike a new estimator, there is an estimate right away.
+    pub(crate) fn reset(&mut self, config: &BweConfig, start_bitrate: Bitrate) {
+        let start_bitrate = start_bitrate.clamp(config.min_bitrate(), config.max_bitrate());
+        *self = Self::new(config);
+        self.rate_control =
+            RateControl::new(start_bitrate, config.min_bitrate(), config.max_bitrate());
+        self.last_estimate = Some(start_bitrate);
+    }
+
+    /// Change the bounds of the estimate, clamping the current one.
+    pub(crate) fn set_bounds(&mut self, min: Bitrate, max: Bitrate) {
+        self.rate_control.set_bounds(min, max);
+
+        if let Some(estimate) = &mut self.last_estimate {
+            *estimate = self.rate_control.estimated_bitrate();
+        }
+    }
+
+    /// Get the bitrate acknowledged by the remote.
+    pub(crate) fn acked_bitrate(&self) -> Option<Bitrate> {
+        self.acked_bitrate_estimator.current_estimate()
+    }
+
     fn add_max_rtt(&mut self, max_rtt: Duration) {
         while self.max_rtt_history.len() > MAX_RTT_HISTORY_WINDOW {
             self.max_rtt_history.pop_front();
@@ -259,3 +293,61 @@ impl fmt::Display for BandwithUsage {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reset_discards_history() {
+        let config = BweConfig::new(Bitrate::kbps(1000));
+        let mut bwe = SendSideBandwithEstimator::new(&config);
+        let now = Instant::now();
+        let ms = Duration::from_millis;
+
+        bwe.add_max_rtt(ms(100));
+
+        // Overuse makes AIMD decrease, after which it holds.
+        let observed = Some(Bitrate::kbps(500));
+        bwe.update_estimate(BandwithUsage::Overuse, observed, bwe.mean_max_rtt, now);
+        bwe.update_estimate(
+            BandwithUsage::Normal,
+            observed,
+            bwe.mean_max_rtt,
+            now + ms(100),
+        );
+        assert!(bwe.last_estimate().unwrap() < Bitrate::kbps(500));
+
+        bwe.reset(&config, Bitrate::kbps(800));
+        assert_eq!(bwe.last_estimate(), Some(Bitrate::kbps(800)));
+        assert!(bwe.max_rtt_history.is_empty());
+        assert_eq!(bwe.mean_max_rtt, None);
+        assert_eq!(bwe.trendline_estimator.hypothesis(), BandwithUsage::Normal);
+        assert_eq!(bwe.acked_bitrate(), None);
+
+        // Increasing again, as from the start.
+        let observed = Some(Bitrate::kbps(800));
+        bwe.update_estimate(BandwithUsage::Normal, observed, None, now + ms(200));
+        assert!(bwe.last_estimate().unwrap() > Bitrate::kbps(800));
+    }
+
+    #[test]
+    fn reset_and_bounds_clamp() {
+        let config = BweConfig::new(Bitrate::kbps(300))
+            .set_min_bitrate(Bitrate::kbps(100))
+            .set_max_bitrate(Bitrate::kbps(2000));
+        let mut bwe = SendSideBandwithEstimator::new(&config);
+
+        bwe.reset(&config, Bitrate::kbps(5000));
+        assert_eq!(bwe.last_estimate(), Some(Bitrate::kbps(2000)));
+
+        bwe.reset(&config, Bitrate::kbps(10));
+        assert_eq!(bwe.last_estimate(), Some(Bitrate::kbps(100)));
+
+        bwe.set_bounds(Bitrate::kbps(500), Bitrate::kbps(1000));
+        assert_eq!(bwe.last_estimate(), Some(Bitrate::kbps(500)));
+
+        bwe.set_bounds(Bitrate::kbps(100), Bitrate::kbps(200));
+        assert_eq!(bwe.last_estimate(), Some(Bitrate::kbps(200)));
+    }
+}
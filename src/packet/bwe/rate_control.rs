@@ -97,6 +97,13 @@ impl RateControl {
         self.estimated_bitrate
     }
 
+    /// Change the bounds, clamping the current estimate.
+    pub(super) fn set_bounds(&mut self, min_bitrate: Bitrate, max_bitrate: Bitrate) {
+        self.min_bitrate = min_bitrate;
+        self.max_bitrate = max_bitrate;
+        self.estimated_bitrate = self.estimated_bitrate.clamp(min_bitrate, max_bitrate);
+    }
+
     fn increase(&mut self, observed_bitrate: Bitrate, now: Instant) {
         let last_estimate_update = *self.last_estimate_update.get_or_insert(now);
 
@@ -398,6 +405,29 @@ mod test {
             assert_eq!(rate_controller.estimated_bitrate().as_u64(), 100_000);
         }
 
+        #[test]
+        fn test_set_bounds_clamps_estimate() {
+            let now = Instant::now();
+            let mut rate_controller = make_control(100_000);
+
+            rate_controller.set_bounds(200_000.into(), 1_000_000.into());
+            assert_eq!(rate_controller.estimated_bitrate().as_u64(), 200_000);
+
+            rate_controller.set_bounds(10_000.into(), 150_000.into());
+            assert_eq!(rate_controller.estimated_bitrate().as_u64(), 150_000);
+
+            // Increases stop at the max.
+            for i in 0..20 {
+                rate_controller.update(
+                    Signal::Normal,
+                    140_000.into(),
+                    None,
+                    now + duration_ms(i * 500),
+                );
+            }
+            assert_eq!(rate_controller.estimated_bitrate().as_u64(), 150_000);
+        }
+
         #[test]
         fn test_normal_yields_multiplicative_increase() {
             let now = Instant::now();
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::bwe::{DEFAULT_BWE_MAX_BITRATE, DEFAULT_BWE_MIN_BITRATE};
+use crate::rtp_::{Bitrate, DataSize, Frequency, SeqNo, Ssrc};
+use crate::util::already_happened;
+
+use super::acked_bitrate_estimator::AckedBitrateEstimator;
+use super::arrival_group::ArrivalGroupAccumulator;
+use super::kalman_estimator::KalmanEstimator;
+use super::rate_control::RateControl;
+use super::{AckedPacket, INITIAL_BITRATE_WINDOW, UPDATE_INTERVAL};
+
+/// Where the estimate starts before the incoming bitrate is known.
+const START_BITRATE: Bitrate = Bitrate::kbps(300);
+/// Window for the incoming bitrate once the initial estimate is made.
+const INCOMING_BITRATE_WINDOW: Duration = Duration::from_secs(1);
+/// How often to send REMB when the estimate isn't dropping.
+const REMB_INTERVAL: Duration = Duration::from_secs(1);
+/// A drop in estimate bigger than this is sent right away.
+const REMB_DECREASE_RATIO: f64 = 0.97;
+/// SSRCs not heard from in this time are no longer included in the REMB.
+const SSRC_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Receive side estimate of the available incoming bitrate, for senders that don't do TWCC.
+///
+/// The remote doesn't get to see the arrival times, so we run the delay based estimator
+/// here and tell the remote the result using REMB.
+///
+/// Packets are grouped on their abs-send-time. If the remote doesn't send abs-send-time, the
+/// RTP timestamp of the first SSRC stands in for the send time. This is less precise, since
+/// all packets of a frame share the same timestamp regardless of when they were sent.
+pub struct ReceiveSideBandwithEstimator {
+    arrival_group_accumulator: ArrivalGroupAccumulator,
+    kalman_estimator: KalmanEstimator,
+    rate_control: RateControl,
+    incoming_bitrate_estimator: AckedBitrateEstimator,
+    /// Stands in for the TWCC sequence number when grouping packets.
+    seq_no: SeqNo,
+    /// Whether any packet had abs-send-time. Then we only use that.
+    has_abs_send_time: bool,
+    /// SSRC, RTP time and derived send time of the last packet used for the fallback.
+    rtp_time: Option<(Ssrc, u32, Instant)>,
+    /// Last time each SSRC was received.
+    ssrcs: HashMap<Ssrc, Instant>,
+    /// Last estimate produced.
+    last_estimate: Option<Bitrate>,
+    /// The last sent REMB.
+    last_remb: Option<(Bitrate, Instant)>,
+    /// The next time we should update the estimate.
+    next_timeout: Instant,
+}
+
+impl ReceiveSideBandwithEstimator {
+    pub fn new() -> Self {
+        Self {
+            arrival_group_accumulator: ArrivalGroupAccumulator::default(),
+            kalman_estimator: KalmanEstimator::new(),
+            rate_control: RateControl::new(
+                START_BITRATE,
+                DEFAULT_BWE_MIN_BITRATE,
+                DEFAULT_BWE_MAX_BITRATE,
+            ),
+            incoming_bitrate_estimator: AckedBitrateEstimator::new(
+                INITIAL_BITRATE_WINDOW,
+                INCOMING_BITRATE_WINDOW,
+            ),
+            seq_no: 0.into(),
+            has_abs_send_time: false,
+            rtp_time: None,
+            ssrcs: HashMap::new(),
+            last_estimate: None,
+            last_remb: None,
+            next_timeout: already_happened(),
+        }
+    }
+
+    /// Record an incoming RTP packet.
+    ///
+    /// * `abs_send_time` the abs-send-time header extension, unwrapped past the 64 second
+    ///   wrap-around by the session's [`AbsSendTimeUnwrapper`](crate::rtp_::AbsSendTimeUnwrapper).
+    /// * `rtp_time` the RTP timestamp and clock rate, used without abs-send-time.
+    /// * `size` the size of the entire packet.
+    pub(crate) fn update(
+        &mut self,
+        now: Instant,
+        ssrc: Ssrc,
+        abs_send_time: Option<Instant>,
+        rtp_time: (u32, Frequency),
+        size: DataSize,
+    ) {
+        self.ssrcs.insert(ssrc, now);
+        self.incoming_bitrate_estimator.update(now, size);
+
+        let send_time = if let Some(t) = abs_send_time {
+            self.has_abs_send_time = true;
+            Some(t)
+        } else if !self.has_abs_send_time {
+            self.send_time_from_rtp_time(now, ssrc, rtp_time)
+        } else {
+            None
+        };
+
+        let Some(send_time) = send_time else {
+            return;
+        };
+
+        let packet = AckedPacket {
+            seq_no: self.seq_no.inc(),
+            size,
+            local_send_time: send_time,
+            remote_recv_time: now,
+        };
+
+        if let Some(delay_variation) = self.arrival_group_accumulator.accumulate_packet(packet) {
+            crate::packet::bwe::macros::log_delay_variation!(delay_variation.delay_delta);
+
+            self.kalman_estimator
+                .add_delay_observation(delay_variation, now);
+        }
+    }
+
+    fn send_time_from_rtp_time(
+        &mut self,
+        now: Instant,
+        ssrc: Ssrc,
+        (time, clock_rate): (u32, Frequency),
+    ) -> Option<Instant> {
+        let Some((prev_ssrc, prev_time, prev_send_time)) = self.rtp_time else {
+            self.rtp_time = Some((ssrc, time, now));
+            return Some(now);
+        };
+
+        if prev_ssrc != ssrc {
+            return None;
+        }
+
+        // Signed, since packets can be reordered.
+        let ticks = time.wrapping_sub(prev_time) as i32;
+        let delta = Duration::from_secs_f64(ticks.unsigned_abs() as f64 / clock_rate.get() as f64);
+
+        let send_time = if ticks >= 0 {
+            let t = prev_send_time + delta;
+            self.rtp_time = Some((ssrc, time, t));
+            t
+        } else {
+            prev_send_time.checked_sub(delta)?
+        };
+
+        Some(send_time)
+    }
+
+    pub(crate) fn poll_timeout(&self) -> Option<Instant> {
+        if self.ssrcs.is_empty() {
+            return None;
+        }
+
+        let remb_at = self
+            .last_remb
+            .map(|(_, t)| t + REMB_INTERVAL)
+            .unwrap_or(self.next_timeout);
+
+        Some(self.next_timeout.min(remb_at))
+    }
+
+    pub(crate) fn handle_timeout(&mut self, now: Instant) {
+        if now < self.next_timeout {
+            return;
+        }
+
+        // Set this even if we didn't update, otherwise we get stuck in a poll -> handle loop
+        // that starves the run loop.
+        self.next_timeout = now + UPDATE_INTERVAL;
+
+        self.ssrcs.retain(|_, t| now - *t < SSRC_TIMEOUT);
+
+        let Some(incoming_bitrate) = self.incoming_bitrate_estimator.current_estimate() else {
+            return;
+        };
+
+        self.rate_control.update(
+            self.kalman_estimator.hypothesis().into(),
+            incoming_bitrate,
+            None,
+            now,
+        );
+
+        let estimate = self.rate_control.estimated_bitrate();
+        crate::packet::bwe::macros::log_bitrate_estimate!(estimate.as_f64());
+        self.last_estimate = Some(estimate);
+    }
+
+    /// Get the latest estimate.
+    pub(crate) fn last_estimate(&self) -> Option<Bitrate> {
+        self.last_estimate
+    }
+
+    /// A REMB to send, if it's time.
+    ///
+    /// A drop in estimate is sent immediately, otherwise the estimate is repeated at a
+    /// regular interval. Returns the estimate and the SSRCs it applies to.
+    pub(crate) fn poll_remb(&mut self, now: Instant) -> Option<(Bitrate, Vec<Ssrc>)> {
+        let estimate = self.last_estimate?;
+
+        if self.ssrcs.is_empty() {
+            return None;
+        }
+
+        let send = match self.last_remb {
+            None => true,
+            Some((bitrate, at)) => {
+                estimate.as_f64() < bitrate.as_f64() * REMB_DECREASE_RATIO
+                    || now >= at + REMB_INTERVAL
+            }
+        };
+
+        if !send {
+            return None;
+        }
+
+        self.last_remb = Some((estimate, now));
+
+        let mut ssrcs: Vec<_> = self.ssrcs.keys().copied().collect();
+        ssrcs.sort();
+
+        Some((estimate, ssrcs))
+    }
+}
+
+impl Default for ReceiveSideBandwithEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rtp_::{AbsSendTimeUnwrapper, ExtensionValues};
+
+    use super::*;
+
+    /// A simulated link carrying a 30fps video stream.
+    ///
+    /// The link has 1Mbit/s capacity. Halfway through it drops to 400kbit/s for a while,
+    /// which builds a queue.
+    struct Trace {
+        start: Instant,
+        /// When the link is free to send the next packet.
+        link_free: Instant,
+    }
+
+    impl Trace {
+        fn capacity(&self, at: Instant) -> Bitrate {
+            let t = at - self.start;
+            if t >= Duration::from_secs(10) && t < Duration::from_secs(15) {
+                Bitrate::kbps(400)
+            } else {
+                Bitrate::kbps(1000)
+            }
+        }
+
+        /// The arrival time of a packet sent at `send_time`.
+        fn arrival(&mut self, send_time: Instant, size: DataSize) -> Instant {
+            let start = self.link_free.max(send_time);
+            let transmit = Duration::from_secs_f64(
+                size.as_bytes_f64() * 8.0 / self.capacity(send_time).as_f64(),
+            );
+            self.link_free = start + transmit;
+            // Constant propagation delay.
+            self.link_free + Duration::from_millis(20)
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum SendTime {
+        /// Only the RTP time.
+        Rtp,
+        /// The exact send time.
+        Abs,
+        /// The abs-send-time as received, 24 bits wrapping every 64 seconds.
+        /// The sender's clock wraps 12 seconds in, during the congestion.
+        AbsOnWire,
+    }
+
+    struct Run {
+        estimates: Vec<(Duration, Bitrate)>,
+        rembs: Vec<(Duration, Bitrate, Vec<Ssrc>)>,
+    }
+
+    /// Sends at the estimate (bounded by 800kbit/s) in 30fps frames.
+    fn run(kind: SendTime) -> Run {
+        let start = Instant::now();
+        let mut trace = Trace {
+            start,
+            link_free: start,
+        };
+        let mut bwe = ReceiveSideBandwithEstimator::new();
+        let ssrc: Ssrc = 42.into();
+        let mut unwrapper = AbsSendTimeUnwrapper::new();
+
+        let mut run = Run {
+            estimates: vec![],
+            rembs: vec![],
+        };
+        let mut send_rate = Bitrate::kbps(300);
+        let mut arrivals = vec![];
+
+        for frame in 0..(20 * 30) {
+            let send_time = start + Duration::from_millis(frame * 1000 / 30);
+            let rtp_time = (frame * 3000) as u32;
+
+            let frame_size = (send_rate.as_f64() / 30.0 / 8.0) as usize;
+            let packets = (frame_size + 999) / 1000;
+
+            for i in 0..packets {
+                let size = DataSize::from(frame_size / packets);
+                // Packets of a frame are paced out 1ms apart.
+                let send_time = send_time + Duration::from_millis(i as u64);
+                let arrival = trace.arrival(send_time, size);
+                let abs = (kind != SendTime::Rtp).then_some(send_time);
+                arrivals.push((arrival, abs, rtp_time, size));
+            }
+
+            // Deliver everything that has arrived before the next frame.
+            let next = send_time + Duration::from_millis(1000 / 30);
+            arrivals.sort_by_key(|a| a.0);
+            let split = arrivals.partition_point(|a| a.0 < next);
+
+            for (arrival, mut abs, rtp_time, size) in arrivals.drain(..split) {
+                if kind == SendTime::AbsOnWire {
+                    let sent = abs.unwrap() - start + Duration::from_secs(52);
+                    let relative_64_secs =
+                        Duration::from_micros(sent.as_micros() as u64 % 64_000_000);
+                    let mut ext_vals = ExtensionValues {
+                        abs_send_time: Some(already_happened() + relative_64_secs),
+                        ..Default::default()
+                    };
+                    ext_vals.update_absolute_send_time(arrival, &mut unwrapper);
+                    abs = ext_vals.abs_send_time;
+                }
+
+                bwe.update(arrival, ssrc, abs, (rtp_time, Frequency::NINETY_KHZ), size);
+
+                bwe.handle_timeout(arrival);
+                if let Some((bitrate, ssrcs)) = bwe.poll_remb(arrival) {
+                    run.rembs.push((arrival - start, bitrate, ssrcs));
+                }
+            }
+
+            if let Some(estimate) = bwe.last_estimate() {
+                run.estimates.push((next - start, estimate));
+                send_rate = estimate.min(Bitrate::kbps(800));
+            }
+        }
+
+        run
+    }
+
+    fn estimate_at(run: &Run, at: Duration) -> Bitrate {
+        run.estimates
+            .iter()
+            .rev()
+            .find(|(t, _)| *t <= at)
+            .map(|(_, e)| *e)
+            .unwrap()
+    }
+
+    fn lowest_during_congestion(run: &Run) -> Bitrate {
+        run.estimates
+            .iter()
+            .filter(|(t, _)| *t > Duration::from_secs(10) && *t < Duration::from_secs(15))
+            .map(|(_, e)| *e)
+            .min_by(|a, b| a.as_f64().total_cmp(&b.as_f64()))
+            .unwrap()
+    }
+
+    #[test]
+    fn congestion_episode_abs_send_time() {
+        let run = run(SendTime::Abs);
+
+        // Ramps up past the reduced capacity before the congestion.
+        let before = estimate_at(&run, Duration::from_secs(10));
+        assert!(before > Bitrate::kbps(600), "{}", before);
+
+        // Backs off below the reduced capacity.
+        let during = lowest_during_congestion(&run);
+        assert!(during < Bitrate::kbps(400), "{}", during);
+
+        // Recovers after.
+        let after = estimate_at(&run, Duration::from_secs(20));
+        assert!(after > during, "{}", after);
+
+        // The drop was sent right away, not waiting for the interval.
+        let drop = run
+            .rembs
+            .windows(2)
+            .find(|w| w[1].0 > Duration::from_secs(10) && w[1].1 < w[0].1)
+            .unwrap();
+        assert!(drop[1].0 - drop[0].0 < REMB_INTERVAL);
+        assert!(drop[1].0 < Duration::from_millis(11_000));
+
+        // Otherwise at least once per interval.
+        for w in run.rembs.windows(2) {
+            assert!(w[1].0 - w[0].0 <= REMB_INTERVAL + UPDATE_INTERVAL * 2);
+        }
+
+        assert!(run.rembs.iter().all(|r| r.2 == vec![42.into()]));
+    }
+
+    #[test]
+    fn congestion_episode_rtp_time() {
+        let run = run(SendTime::Rtp);
+
+        let before = estimate_at(&run, Duration::from_secs(10));
+        assert!(before > Bitrate::kbps(600), "{}", before);
+
+        let during = lowest_during_congestion(&run);
+        assert!(during < Bitrate::kbps(400), "{}", during);
+    }
+
+    #[test]
+    fn congestion_episode_abs_send_time_wrap() {
+        let run = run(SendTime::AbsOnWire);
+
+        let before = estimate_at(&run, Duration::from_secs(10));
+        assert!(before > Bitrate::kbps(600), "{}", before);
+
+        let during = lowest_during_congestion(&run);
+        assert!(during < Bitrate::kbps(400), "{}", during);
+
+        let after = estimate_at(&run, Duration::from_secs(20));
+        assert!(after > during, "{}", after);
+
+        // No stall at the wrap-around.
+        for w in run.rembs.windows(2) {
+            assert!(w[1].0 - w[0].0 <= REMB_INTERVAL + UPDATE_INTERVAL * 2);
+        }
+    }
+
+    #[test]
+    fn no_remb_without_packets() {
+        let mut bwe = ReceiveSideBandwithEstimator::new();
+        let now = Instant::now();
+
+        assert_eq!(bwe.poll_timeout(), None);
+        bwe.handle_timeout(now);
+        assert!(bwe.poll_remb(now).is_none());
+    }
+}
@@ -398,6 +398,7 @@ mod test {
         InterGroupDelayDelta {
             send_delta,
             delay_delta: delay,
+            size_delta: 0.0,
             last_remote_recv_time,
         }
     }
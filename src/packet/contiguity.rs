@@ -1,6 +1,6 @@
 use super::contiguity_vp8::Vp8Contiguity;
 use super::contiguity_vp9::Vp9Contiguity;
-use super::CodecExtra;
+use super::{CodecDepacketizer, CodecExtra};
 
 #[derive(Debug)]
 pub enum Contiguity {
@@ -9,6 +9,22 @@ pub enum Contiguity {
     None,
 }
 
+impl From<&CodecDepacketizer> for Contiguity {
+    fn from(depack: &CodecDepacketizer) -> Self {
+        match depack {
+            CodecDepacketizer::Vp8(_) => Contiguity::Vp8(Vp8Contiguity::new()),
+            CodecDepacketizer::Vp9(_) => Contiguity::Vp9(Vp9Contiguity::new()),
+            CodecDepacketizer::H264(_)
+            | CodecDepacketizer::H265(_)
+            | CodecDepacketizer::Av1(_)
+            | CodecDepacketizer::Generic(_)
+            | CodecDepacketizer::Boxed(_)
+            | CodecDepacketizer::Opus(_)
+            | CodecDepacketizer::Null(_) => Contiguity::None,
+        }
+    }
+}
+
 impl Contiguity {
     pub fn check(&mut self, next: &CodecExtra, contiguous_seq: bool) -> (bool, bool) {
         match (self, next) {
@@ -0,0 +1,511 @@
+use std::time::Duration;
+
+use crate::rtp_::{Frequency, MediaTime, Pt};
+
+use super::PacketError;
+
+/// Size of a telephone-event payload.
+const EVENT_SIZE: usize = 4;
+
+/// The DTMF events 0-15 as digits.
+const DIGITS: [char; 16] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '*', '#', 'A', 'B', 'C', 'D',
+];
+
+/// Times the end packet is sent, as recommended by RFC 4733 section 2.5.1.4.
+const END_REPEATS: usize = 3;
+
+/// Largest volume, -63 dBm0.
+const MAX_VOLUME: u8 = 63;
+
+/// A DTMF digit, received or to send, as an RTP telephone-event described in
+/// [RFC 4733](https://datatracker.ietf.org/doc/html/rfc4733).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DtmfEvent {
+    /// The digit, `0-9`, `*`, `#` or `A-D`.
+    pub digit: char,
+    /// RTP time of the start of the event.
+    pub time: MediaTime,
+    /// Duration of the event.
+    pub duration: Duration,
+    /// Power level of the tone, 0 to 63 as -dBm0. Higher is quieter.
+    pub volume: u8,
+}
+
+/// An RTP packet of a telephone-event, made by [`DtmfEncoder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtmfPacket {
+    /// When to send the packet, relative the start of the event.
+    pub offset: Duration,
+    /// The RTP time, the same for all packets of an event.
+    pub time: MediaTime,
+    /// The RTP marker bit, set on the first packet.
+    pub marker: bool,
+    /// The telephone-event payload.
+    pub payload: Vec<u8>,
+}
+
+/// Makes the RTP packets of DTMF telephone-events,
+/// [RFC 4733](https://datatracker.ietf.org/doc/html/rfc4733).
+///
+/// The packets are written on the audio stream with the payload type of the negotiated
+/// `telephone-event`, using [`StreamTx::write_rtp`][crate::rtp::StreamTx::write_rtp].
+///
+/// An event is a train of packets with the same RTP time and increasing duration. The
+/// first packet has the marker bit set and the final duration is sent in three end
+/// packets.
+#[derive(Debug)]
+pub struct DtmfEncoder {
+    pt: Pt,
+    frequency: Frequency,
+    interval: Duration,
+}
+
+impl DtmfEncoder {
+    /// Create an encoder of the `telephone-event` with `pt` and clock rate `frequency`,
+    /// as mapped in the SDP, i.e. `a=rtpmap:101 telephone-event/8000`.
+    pub fn new(pt: Pt, frequency: Frequency) -> Self {
+        DtmfEncoder {
+            pt,
+            frequency,
+            interval: Duration::from_millis(50),
+        }
+    }
+
+    /// The payload type of the telephone-event.
+    pub fn pt(&self) -> Pt {
+        self.pt
+    }
+
+    /// Set the time between packets of an event.
+    ///
+    /// Defaults to 50ms.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval.max(Duration::from_millis(1));
+    }
+
+    /// Make the packets of an event.
+    ///
+    /// Events longer than the 16 bit duration field are split into segments with a
+    /// new RTP time each, but only the first packet has the marker bit.
+    pub fn encode(&self, event: DtmfEvent) -> Result<Vec<DtmfPacket>, PacketError> {
+        let Some(code) = DIGITS.iter().position(|d| *d == event.digit) else {
+            return Err(PacketError::ErrDtmfInvalidDigit(event.digit));
+        };
+        let volume = event.volume.min(MAX_VOLUME);
+
+        let hz = self.frequency.get() as u64;
+        let to_units = |d: Duration| (d.as_micros() as u64 * hz / 1_000_000).max(1);
+        let to_duration = |units: u64| Duration::from_micros(units * 1_000_000 / hz);
+
+        let total = to_units(event.duration);
+        let step = to_units(self.interval);
+        let start = event.time.rebase(self.frequency).numer();
+
+        let mut packets = vec![];
+        let mut segment_start = 0;
+
+        loop {
+            let segment_len = (total - segment_start).min(u16::MAX as u64);
+            let is_last = segment_start + segment_len == total;
+            let time = MediaTime::new(start + segment_start, self.frequency);
+
+            let mut duration = 0;
+            loop {
+                duration = (duration + step).min(segment_len);
+                let end = is_last && duration == segment_len;
+
+                let payload = payload(code as u8, end, volume, duration as u16);
+                let offset = to_duration(segment_start + duration);
+                let repeats = if end { END_REPEATS } else { 1 };
+
+                for _ in 0..repeats {
+                    packets.push(DtmfPacket {
+                        offset,
+                        time,
+                        marker: packets.is_empty(),
+                        payload: payload.clone(),
+                    });
+                }
+
+                if duration == segment_len {
+                    break;
+                }
+            }
+
+            if is_last {
+                break;
+            }
+            segment_start += segment_len;
+        }
+
+        Ok(packets)
+    }
+}
+
+fn payload(event: u8, end: bool, volume: u8, duration: u16) -> Vec<u8> {
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |     event     |E|R| volume    |          duration             |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    let [d0, d1] = duration.to_be_bytes();
+    vec![event, if end { 0x80 } else { 0 } | volume, d0, d1]
+}
+
+/// Collates the packets of DTMF telephone-events,
+/// [RFC 4733](https://datatracker.ietf.org/doc/html/rfc4733), into [`DtmfEvent`].
+///
+/// An event is output once, on the first end packet, ignoring the repeated end packets.
+/// Lost packets in the middle of an event don't matter, since every packet has the
+/// duration so far. If all end packets are lost, the event is output when the next
+/// event starts.
+#[derive(Debug)]
+pub struct DtmfDecoder {
+    pt: Pt,
+    /// The event being received.
+    current: Option<Current>,
+    /// Start of the last ended event, to ignore the repeated end packets.
+    ended: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Current {
+    event: u8,
+    volume: u8,
+    frequency: Frequency,
+    /// Start of the event.
+    start: u64,
+    /// Start of the current segment of a long event.
+    segment: u64,
+    /// Duration of the current segment.
+    duration: u64,
+}
+
+impl Current {
+    fn to_event(&self) -> DtmfEvent {
+        let units = self.segment - self.start + self.duration;
+        let hz = self.frequency.get() as u64;
+
+        DtmfEvent {
+            digit: DIGITS[self.event as usize],
+            time: MediaTime::new(self.start, self.frequency),
+            duration: Duration::from_micros(units * 1_000_000 / hz),
+            volume: self.volume,
+        }
+    }
+}
+
+impl DtmfDecoder {
+    /// Create a decoder of the `telephone-event` with payload type `pt`, as mapped in
+    /// the SDP, i.e. `a=rtpmap:101 telephone-event/8000`.
+    pub fn new(pt: Pt) -> Self {
+        DtmfDecoder {
+            pt,
+            current: None,
+            ended: None,
+        }
+    }
+
+    /// The payload type of the telephone-event.
+    pub fn pt(&self) -> Pt {
+        self.pt
+    }
+
+    /// Decode a received packet, returning the events that ended.
+    ///
+    /// Packets of other payload types are ignored, and so are the events that are not
+    /// DTMF digits.
+    pub fn decode(
+        &mut self,
+        pt: Pt,
+        time: MediaTime,
+        payload: &[u8],
+    ) -> Result<Vec<DtmfEvent>, PacketError> {
+        if pt != self.pt {
+            return Ok(vec![]);
+        }
+
+        if payload.len() < EVENT_SIZE {
+            return Err(PacketError::ErrShortPacket);
+        }
+
+        let event = payload[0];
+        let end = payload[1] & 0x80 > 0;
+        let volume = payload[1] & 0x3f;
+        let duration = u16::from_be_bytes([payload[2], payload[3]]) as u64;
+        let numer = time.numer();
+
+        if event as usize >= DIGITS.len() {
+            return Ok(vec![]);
+        }
+
+        // Repeated end packets, or a late packet of an ended event.
+        if self.ended == Some(numer) {
+            return Ok(vec![]);
+        }
+
+        let mut events = vec![];
+
+        let continues = self.current.as_ref().map(|c| {
+            if c.event != event {
+                return false;
+            }
+            // The same segment, or the next segment of a long event.
+            c.segment == numer || (numer > c.segment && numer - c.segment <= u16::MAX as u64)
+        });
+
+        match continues {
+            Some(true) => {
+                let c = self.current.as_mut().expect("current event");
+                if numer != c.segment {
+                    c.segment = numer;
+                    c.duration = 0;
+                }
+                c.duration = c.duration.max(duration);
+                c.volume = volume;
+            }
+            Some(false) => {
+                // A new event while the previous never ended, all its end packets lost.
+                let previous = self.current.take().expect("current event");
+                if numer < previous.start {
+                    // Late packet of an event before the current.
+                    self.current = Some(previous);
+                    return Ok(vec![]);
+                }
+                events.push(previous.to_event());
+                self.current = Some(self.start(event, volume, time, duration));
+            }
+            None => {
+                self.current = Some(self.start(event, volume, time, duration));
+            }
+        }
+
+        if end {
+            let c = self.current.take().expect("current event");
+            self.ended = Some(c.segment);
+            events.push(c.to_event());
+        }
+
+        Ok(events)
+    }
+
+    fn start(&self, event: u8, volume: u8, time: MediaTime, duration: u64) -> Current {
+        Current {
+            event,
+            volume,
+            frequency: time.frequency(),
+            start: time.numer(),
+            segment: time.numer(),
+            duration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PT: u8 = 101;
+
+    fn time(v: u64) -> MediaTime {
+        MediaTime::new(v, Frequency::new(8000).unwrap())
+    }
+
+    fn decode_all(dec: &mut DtmfDecoder, packets: &[(u64, &[u8])]) -> Vec<DtmfEvent> {
+        packets
+            .iter()
+            .flat_map(|(t, p)| dec.decode(PT.into(), time(*t), p).unwrap())
+            .collect()
+    }
+
+    // The telephone-event payloads of digit 5 sent by a Cisco gateway, 8kHz, packets
+    // every 50ms, three start packets with the marker bit and three end packets.
+    const CISCO: &[(u64, &[u8])] = &[
+        (160_000, &[0x05, 0x0a, 0x00, 0x00]),
+        (160_000, &[0x05, 0x0a, 0x00, 0x00]),
+        (160_000, &[0x05, 0x0a, 0x00, 0x00]),
+        (160_000, &[0x05, 0x0a, 0x01, 0x90]),
+        (160_000, &[0x05, 0x0a, 0x03, 0x20]),
+        (160_000, &[0x05, 0x0a, 0x04, 0xb0]),
+        (160_000, &[0x05, 0x0a, 0x06, 0x40]),
+        (160_000, &[0x05, 0x8a, 0x06, 0xe0]),
+        (160_000, &[0x05, 0x8a, 0x06, 0xe0]),
+        (160_000, &[0x05, 0x8a, 0x06, 0xe0]),
+        // Digit 9 directly after.
+        (162_400, &[0x09, 0x0a, 0x00, 0x00]),
+        (162_400, &[0x09, 0x0a, 0x00, 0x00]),
+        (162_400, &[0x09, 0x0a, 0x00, 0x00]),
+        (162_400, &[0x09, 0x0a, 0x01, 0x90]),
+        (162_400, &[0x09, 0x8a, 0x02, 0x80]),
+        (162_400, &[0x09, 0x8a, 0x02, 0x80]),
+        (162_400, &[0x09, 0x8a, 0x02, 0x80]),
+    ];
+
+    #[test]
+    fn decode_cisco() {
+        let mut dec = DtmfDecoder::new(PT.into());
+        let events = decode_all(&mut dec, CISCO);
+
+        assert_eq!(
+            events,
+            [
+                DtmfEvent {
+                    digit: '5',
+                    time: time(160_000),
+                    duration: Duration::from_millis(220),
+                    volume: 10,
+                },
+                DtmfEvent {
+                    digit: '9',
+                    time: time(162_400),
+                    duration: Duration::from_millis(80),
+                    volume: 10,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_lost_interior_packets() {
+        let mut dec = DtmfDecoder::new(PT.into());
+        let packets: Vec<_> = CISCO[..10]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| ![0, 1, 2, 4, 5].contains(i))
+            .map(|(_, p)| *p)
+            .collect();
+
+        let events = decode_all(&mut dec, &packets);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].digit, '5');
+        assert_eq!(events[0].duration, Duration::from_millis(220));
+    }
+
+    #[test]
+    fn decode_lost_end_packets() {
+        let mut dec = DtmfDecoder::new(PT.into());
+        let packets: Vec<_> = CISCO[..7].iter().chain(&CISCO[10..]).copied().collect();
+
+        let events = decode_all(&mut dec, &packets);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].digit, '5');
+        assert_eq!(events[0].duration, Duration::from_millis(200));
+        assert_eq!(events[1].digit, '9');
+    }
+
+    #[test]
+    fn decode_late_end_packet() {
+        let mut dec = DtmfDecoder::new(PT.into());
+        decode_all(&mut dec, &CISCO[..10]);
+
+        // A reordered end packet after the event ended.
+        assert!(dec
+            .decode(PT.into(), time(160_000), CISCO[9].1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn decode_ignores_other() {
+        let mut dec = DtmfDecoder::new(PT.into());
+
+        // Other payload type.
+        assert!(dec
+            .decode(0.into(), time(0), &[0x05, 0x8a, 0x00, 0xa0])
+            .unwrap()
+            .is_empty());
+
+        // Not a DTMF event, 32 is a fax tone.
+        assert!(dec
+            .decode(PT.into(), time(0), &[32, 0x8a, 0x00, 0xa0])
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            dec.decode(PT.into(), time(0), &[0x05, 0x8a]),
+            Err(PacketError::ErrShortPacket)
+        );
+    }
+
+    #[test]
+    fn encode_packet_train() {
+        let enc = DtmfEncoder::new(PT.into(), Frequency::new(8000).unwrap());
+
+        let event = DtmfEvent {
+            digit: '#',
+            time: time(1000),
+            duration: Duration::from_millis(120),
+            volume: 10,
+        };
+        let packets = enc.encode(event).unwrap();
+
+        let expected: &[(u64, bool, [u8; 4])] = &[
+            (50, true, [11, 0x0a, 0x01, 0x90]),
+            (100, false, [11, 0x0a, 0x03, 0x20]),
+            (120, false, [11, 0x8a, 0x03, 0xc0]),
+            (120, false, [11, 0x8a, 0x03, 0xc0]),
+            (120, false, [11, 0x8a, 0x03, 0xc0]),
+        ];
+
+        assert_eq!(packets.len(), expected.len());
+        for (p, (offset, marker, payload)) in packets.iter().zip(expected) {
+            assert_eq!(p.offset, Duration::from_millis(*offset));
+            assert_eq!(p.time, time(1000));
+            assert_eq!(p.marker, *marker);
+            assert_eq!(p.payload, payload);
+        }
+
+        // Round trip.
+        let mut dec = DtmfDecoder::new(PT.into());
+        let events: Vec<_> = packets
+            .iter()
+            .flat_map(|p| dec.decode(PT.into(), p.time, &p.payload).unwrap())
+            .collect();
+        assert_eq!(events, [event]);
+    }
+
+    #[test]
+    fn encode_long_event() {
+        let enc = DtmfEncoder::new(PT.into(), Frequency::new(8000).unwrap());
+
+        // Longer than 65535 units at 8kHz.
+        let event = DtmfEvent {
+            digit: '1',
+            time: time(0),
+            duration: Duration::from_secs(10),
+            volume: 0,
+        };
+        let packets = enc.encode(event).unwrap();
+
+        assert_eq!(packets.iter().filter(|p| p.marker).count(), 1);
+        assert_eq!(packets[0].time, time(0));
+        assert_eq!(packets.last().unwrap().time, time(65_535));
+        assert_eq!(
+            packets.iter().filter(|p| p.payload[1] & 0x80 > 0).count(),
+            3
+        );
+
+        let mut dec = DtmfDecoder::new(PT.into());
+        let events: Vec<_> = packets
+            .iter()
+            .flat_map(|p| dec.decode(PT.into(), p.time, &p.payload).unwrap())
+            .collect();
+        assert_eq!(events, [event]);
+    }
+
+    #[test]
+    fn encode_invalid_digit() {
+        let enc = DtmfEncoder::new(PT.into(), Frequency::new(8000).unwrap());
+        let event = DtmfEvent {
+            digit: 'x',
+            time: time(0),
+            duration: Duration::from_millis(100),
+            volume: 10,
+        };
+        assert_eq!(
+            enc.encode(event),
+            Err(PacketError::ErrDtmfInvalidDigit('x'))
+        );
+    }
+}
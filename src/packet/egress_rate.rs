@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::rtp_::{Bitrate, DataSize, Ssrc};
+use crate::stats::EgressStats;
+
+use super::SendClass;
+
+/// Length of one bucket in the sliding window.
+const BUCKET: Duration = Duration::from_millis(100);
+
+/// Number of buckets, making up a 1 second window.
+const BUCKETS: usize = 10;
+
+/// Weight of each closed bucket in the smoothed rate. Gives a time constant of about
+/// one second with 100ms buckets.
+const EWMA_ALPHA: f64 = 0.1;
+
+/// Per SSRC rates are forgotten after this long without sending.
+const SSRC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bytes sent over a sliding window of fixed size buckets, plus a smoothed rate.
+///
+/// Adding never allocates, old buckets are reused as time moves forward.
+#[derive(Debug)]
+pub(crate) struct RateWindow {
+    buckets: [u64; BUCKETS],
+    /// Start of bucket 0. Set on first use.
+    start: Option<Instant>,
+    /// Index of the current bucket counted from `start`.
+    head: u64,
+    /// Smoothed rate in bits per second, updated as buckets close.
+    ewma: f64,
+}
+
+impl RateWindow {
+    pub fn new() -> Self {
+        RateWindow {
+            buckets: [0; BUCKETS],
+            start: None,
+            head: 0,
+            ewma: 0.0,
+        }
+    }
+
+    /// Record `bytes` sent at `now`.
+    pub fn add(&mut self, now: Instant, bytes: u64) {
+        self.advance(now);
+        self.buckets[(self.head % BUCKETS as u64) as usize] += bytes;
+    }
+
+    /// The rate over the last second.
+    pub fn rate(&mut self, now: Instant) -> Bitrate {
+        self.advance(now);
+        let bytes: u64 = self.buckets.iter().sum();
+        let window = BUCKET * BUCKETS as u32;
+        Bitrate::from(bytes as f64 * 8.0 / window.as_secs_f64())
+    }
+
+    /// The exponentially smoothed rate, over closed buckets.
+    pub fn ewma(&mut self, now: Instant) -> Bitrate {
+        self.advance(now);
+        Bitrate::from(self.ewma)
+    }
+
+    fn advance(&mut self, now: Instant) {
+        let start = *self.start.get_or_insert(now);
+        let index = (now.saturating_duration_since(start).as_nanos() / BUCKET.as_nanos()) as u64;
+
+        if index <= self.head {
+            return;
+        }
+
+        let closed = (index - self.head) as i32;
+
+        // The bucket at head is now complete.
+        let head_bytes = self.buckets[(self.head % BUCKETS as u64) as usize];
+        let head_rate = head_bytes as f64 * 8.0 / BUCKET.as_secs_f64();
+        self.ewma = EWMA_ALPHA * head_rate + (1.0 - EWMA_ALPHA) * self.ewma;
+
+        // Any buckets skipped over were empty.
+        self.ewma *= (1.0 - EWMA_ALPHA).powi(closed - 1);
+
+        for i in 1..=(closed as u64).min(BUCKETS as u64) {
+            self.buckets[((self.head + i) % BUCKETS as u64) as usize] = 0;
+        }
+
+        self.head = index;
+    }
+}
+
+impl Default for RateWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rates per kind of traffic sent.
+#[derive(Debug, Default)]
+pub(crate) struct ClassRates {
+    pub media: RateWindow,
+    pub rtx: RateWindow,
+    pub padding: RateWindow,
+    pub rtcp: RateWindow,
+    last: Option<Instant>,
+}
+
+impl ClassRates {
+    fn add(&mut self, now: Instant, class: SendClass, bytes: u64) {
+        let window = match class {
+            SendClass::Media => &mut self.media,
+            SendClass::Rtx => &mut self.rtx,
+            SendClass::Padding => &mut self.padding,
+        };
+        window.add(now, bytes);
+        self.last = Some(now);
+    }
+
+    /// Fill in the rates of `stats` as of `now`.
+    pub fn fill(&mut self, stats: &mut EgressStats, now: Instant) {
+        stats.bitrate = self.media.rate(now);
+        stats.rtx_bitrate = self.rtx.rate(now);
+        stats.padding_bitrate = self.padding.rate(now);
+        stats.rtcp_bitrate = self.rtcp.rate(now);
+        stats.bitrate_ewma = self.media.ewma(now);
+        stats.rtx_bitrate_ewma = self.rtx.ewma(now);
+        stats.padding_bitrate_ewma = self.padding.ewma(now);
+        stats.rtcp_bitrate_ewma = self.rtcp.ewma(now);
+    }
+}
+
+/// Egress rates per SSRC and in total.
+///
+/// Fed with the same sends that are registered with the pacer. RTX and padding are
+/// counted on the SSRC of the main stream they are sent for.
+#[derive(Debug, Default)]
+pub(crate) struct EgressRates {
+    totals: ClassRates,
+    per_ssrc: HashMap<Ssrc, ClassRates>,
+}
+
+impl EgressRates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a sent RTP packet.
+    pub fn register_rtp(&mut self, now: Instant, ssrc: Ssrc, class: SendClass, size: DataSize) {
+        let bytes = size.as_bytes_usize() as u64;
+        self.totals.add(now, class, bytes);
+        self.per_ssrc
+            .entry(ssrc)
+            .or_default()
+            .add(now, class, bytes);
+    }
+
+    /// Record sent RTCP.
+    ///
+    /// Compound RTCP covers many SSRCs, so this only counts towards the totals.
+    pub fn register_rtcp(&mut self, now: Instant, size: DataSize) {
+        self.totals.rtcp.add(now, size.as_bytes_usize() as u64);
+    }
+
+    pub fn totals(&mut self) -> &mut ClassRates {
+        &mut self.totals
+    }
+
+    pub fn ssrc(&mut self, ssrc: Ssrc) -> Option<&mut ClassRates> {
+        self.per_ssrc.get_mut(&ssrc)
+    }
+
+    /// Forget SSRCs that haven't sent for a while.
+    pub fn prune(&mut self, now: Instant) {
+        self.per_ssrc.retain(|_, r| {
+            r.last
+                .map_or(false, |l| now.saturating_duration_since(l) < SSRC_TIMEOUT)
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window_exact() {
+        let mut w = RateWindow::new();
+        let start = Instant::now();
+
+        // 1000 bytes every 10ms is 800kbps.
+        for i in 0..200 {
+            w.add(start + Duration::from_millis(i * 10), 1000);
+        }
+
+        // The window at 1.999s covers buckets 1.0s-1.999s, 100 packets.
+        let now = start + Duration::from_millis(1999);
+        assert_eq!(w.rate(now), Bitrate::kbps(800));
+
+        // Two buckets later, the last 200ms are empty.
+        let now = start + Duration::from_millis(2150);
+        assert_eq!(w.rate(now), Bitrate::kbps(640));
+
+        // Nothing after a full window of silence.
+        let now = start + Duration::from_millis(3000);
+        assert_eq!(w.rate(now), Bitrate::ZERO);
+    }
+
+    #[test]
+    fn ewma_converges_and_decays() {
+        let mut w = RateWindow::new();
+        let start = Instant::now();
+
+        for i in 0..1000 {
+            w.add(start + Duration::from_millis(i * 10), 1000);
+        }
+
+        let now = start + Duration::from_millis(9999);
+        let ewma = w.ewma(now).as_f64();
+        assert!((ewma - 800_000.0).abs() < 1000.0, "{}", ewma);
+
+        // 10 empty buckets decays by 0.9^10.
+        let now = start + Duration::from_millis(11_000);
+        let decayed = w.ewma(now).as_f64();
+        let expected = (0.1 * 800_000.0 + 0.9 * ewma) * 0.9_f64.powi(10);
+        assert!((decayed - expected).abs() < 1.0, "{} {}", decayed, expected);
+    }
+
+    #[test]
+    fn per_class_and_ssrc() {
+        let mut rates = EgressRates::new();
+        let start = Instant::now();
+        let a = Ssrc::from(1);
+        let b = Ssrc::from(2);
+
+        // Every 20ms: 1000 media on a, 500 media on b, 100 padding on b. Every 100ms 200
+        // rtx on a and 50 RTCP.
+        for i in 0..100 {
+            let now = start + Duration::from_millis(i * 20);
+            rates.register_rtp(now, a, SendClass::Media, DataSize::bytes(1000));
+            rates.register_rtp(now, b, SendClass::Media, DataSize::bytes(500));
+            rates.register_rtp(now, b, SendClass::Padding, DataSize::bytes(100));
+            if i % 5 == 0 {
+                rates.register_rtp(now, a, SendClass::Rtx, DataSize::bytes(200));
+                rates.register_rtcp(now, DataSize::bytes(50));
+            }
+        }
+
+        let now = start + Duration::from_millis(1999);
+
+        let ra = rates.ssrc(a).unwrap();
+        assert_eq!(ra.media.rate(now), Bitrate::kbps(400));
+        assert_eq!(ra.rtx.rate(now), Bitrate::kbps(16));
+        assert_eq!(ra.padding.rate(now), Bitrate::ZERO);
+
+        let rb = rates.ssrc(b).unwrap();
+        assert_eq!(rb.media.rate(now), Bitrate::kbps(200));
+        assert_eq!(rb.padding.rate(now), Bitrate::kbps(40));
+
+        let t = rates.totals();
+        assert_eq!(t.media.rate(now), Bitrate::kbps(600));
+        assert_eq!(t.rtx.rate(now), Bitrate::kbps(16));
+        assert_eq!(t.padding.rate(now), Bitrate::kbps(40));
+        assert_eq!(t.rtcp.rate(now), Bitrate::kbps(4));
+
+        rates.prune(start + Duration::from_secs(10));
+        assert!(rates.ssrc(a).is_none());
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use crate::format::Codec;
+use crate::rtp_::{MediaTime, SeqNo};
+use crate::streams::RtpPacket;
+
+use super::contiguity::Contiguity;
+use super::{CodecDepacketizer, CodecExtra, Depacketizer};
+
+const DEFAULT_MAX_PACKETS: usize = 500;
+const DEFAULT_MAX_DELAY: Duration = Duration::from_millis(200);
+
+/// Assembles received RTP packets into complete frames.
+///
+/// Packets are reordered by sequence number and grouped into frames using the RTP
+/// timestamp, the codec specific start of frame and the marker bit. Frames are emitted
+/// in decode order. When packets are missing, the assembler waits for them until the
+/// buffered packets exceed [`FrameAssembler::set_max_packets()`] or the oldest packet
+/// is older than [`FrameAssembler::set_max_delay()`]. After that, incomplete frames are
+/// dropped.
+///
+/// Made for rtp mode, where the application gets [`RtpPacket`] directly. In frame mode
+/// this is done internally.
+///
+/// ```
+/// # use std::time::Instant;
+/// # use str0m::format::Codec;
+/// use str0m::rtp::{AssemblerOutput, FrameAssembler};
+///
+/// let mut assembler = FrameAssembler::new(Codec::Vp8);
+///
+/// // assembler.push(packet);
+///
+/// while let Some(output) = assembler.poll_output(Instant::now()) {
+///     match output {
+///         AssemblerOutput::Frame(frame) => {
+///             // hand frame.data to the decoder
+///         }
+///         AssemblerOutput::Dropped { keyframe_needed, .. } => {
+///             // request a keyframe from the sender if needed
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FrameAssembler {
+    codec: Codec,
+    depack: CodecDepacketizer,
+    contiguity: Contiguity,
+    max_packets: usize,
+    max_delay: Duration,
+    queue: VecDeque<Entry>,
+    /// The last sequence number emitted or dropped.
+    last: Option<SeqNo>,
+    /// Whether something was dropped since the last emitted frame.
+    dropped: bool,
+}
+
+#[derive(Debug)]
+struct Entry {
+    seq_no: SeqNo,
+    time: MediaTime,
+    received: Instant,
+    data: Vec<u8>,
+    head: bool,
+    tail: bool,
+}
+
+/// A complete frame from the [`FrameAssembler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// RTP timestamp of the frame.
+    pub ts: MediaTime,
+    /// Whether the frame can be decoded without any previous frame.
+    ///
+    /// Always false for codecs without keyframes, such as audio.
+    pub keyframe: bool,
+    /// The depacketized frame.
+    pub data: Vec<u8>,
+    /// Whether nothing was lost since the previous emitted frame.
+    ///
+    /// When false, the decoder should not continue until the next keyframe. For VP8 and
+    /// VP9 with temporal layers, losing a frame that nothing depends on keeps this true.
+    pub contiguous: bool,
+    /// Sequence numbers of the packets in the frame.
+    pub seq_range: RangeInclusive<SeqNo>,
+    /// Codec specific information about the frame.
+    pub codec_extra: CodecExtra,
+}
+
+/// Output from [`FrameAssembler::poll_output()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblerOutput {
+    /// A complete frame.
+    Frame(Frame),
+    /// Packets of an incomplete or broken frame were dropped.
+    Dropped {
+        /// Sequence numbers of the dropped packets.
+        seq_range: RangeInclusive<SeqNo>,
+        /// Whether a keyframe request to the sender may be needed. Only for video.
+        keyframe_needed: bool,
+    },
+}
+
+impl FrameAssembler {
+    /// Create a new assembler for a codec.
+    ///
+    /// Waits for at most 500 packets or 200ms by default.
+    ///
+    /// Panics if the codec can't be depacketized.
+    pub fn new(codec: Codec) -> Self {
+        let depack: CodecDepacketizer = codec.into();
+        let contiguity = (&depack).into();
+
+        FrameAssembler {
+            codec,
+            depack,
+            contiguity,
+            max_packets: DEFAULT_MAX_PACKETS,
+            max_delay: DEFAULT_MAX_DELAY,
+            queue: VecDeque::new(),
+            last: None,
+            dropped: false,
+        }
+    }
+
+    /// Max number of packets to buffer while waiting for missing packets.
+    pub fn set_max_packets(mut self, max_packets: usize) -> Self {
+        self.max_packets = max_packets;
+        self
+    }
+
+    /// Max time to wait for missing packets, counted from when the oldest buffered
+    /// packet was received.
+    pub fn set_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Drop all buffered packets and start over.
+    ///
+    /// Use this when the stream restarts, as told by
+    /// [`Event::StreamRestarted`][crate::Event::StreamRestarted].
+    pub fn reset(&mut self) {
+        self.depack.reset();
+        self.contiguity = (&self.depack).into();
+        self.queue.clear();
+        self.last = None;
+        self.dropped = false;
+    }
+
+    /// Add a received packet.
+    ///
+    /// Duplicates and packets older than the last emitted frame are ignored.
+    pub fn push(&mut self, packet: RtpPacket) {
+        if let Some(last) = self.last {
+            if packet.seq_no <= last {
+                trace!("Drop before emitted: {} <= {}", packet.seq_no, last);
+                return;
+            }
+        }
+
+        let Err(i) = self
+            .queue
+            .binary_search_by_key(&packet.seq_no, |e| e.seq_no)
+        else {
+            trace!("Drop exactly same packet: {}", packet.seq_no);
+            return;
+        };
+
+        let head = self.depack.is_partition_head(&packet.payload);
+        let tail = self
+            .depack
+            .is_partition_tail(packet.header.marker, &packet.payload);
+
+        let entry = Entry {
+            seq_no: packet.seq_no,
+            time: packet.time,
+            received: packet.timestamp,
+            data: packet.payload,
+            head,
+            tail,
+        };
+
+        self.queue.insert(i, entry);
+    }
+
+    /// Poll for the next frame or dropped packets.
+    ///
+    /// Call repeatedly until it returns `None`.
+    pub fn poll_output(&mut self, now: Instant) -> Option<AssemblerOutput> {
+        loop {
+            let front = self.queue.front()?;
+
+            let follows_last = self.last.map(|l| l.is_next(front.seq_no)).unwrap_or(true);
+            let expired = self.queue.len() > self.max_packets
+                || now.saturating_duration_since(front.received) >= self.max_delay;
+
+            match self.find_frame() {
+                // A complete frame. Emit it if nothing is missing before it, or
+                // we've stopped waiting.
+                Some(stop) if follows_last || expired => {
+                    let contiguous_seq = follows_last && !self.dropped;
+                    if let Some(output) = self.emit(stop, contiguous_seq) {
+                        return Some(output);
+                    }
+                }
+                // Missing packets, either before or in the front frame.
+                _ if expired => return Some(self.drop_front()),
+                _ => return None,
+            }
+        }
+    }
+
+    /// When [`FrameAssembler::poll_output()`] should be called, even if no packets
+    /// are pushed, for incomplete frames to be dropped.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        self.queue.front().map(|e| e.received + self.max_delay)
+    }
+
+    /// Find a complete frame at the front of the queue, returning the index of its
+    /// last packet.
+    fn find_frame(&self) -> Option<usize> {
+        let first = self.queue.front()?;
+
+        if !first.head {
+            return None;
+        }
+
+        let mut prev = first;
+
+        for (index, entry) in self.queue.iter().enumerate() {
+            if index > 0 {
+                if !prev.seq_no.is_next(entry.seq_no) {
+                    return None;
+                }
+
+                if entry.time != first.time {
+                    // The timestamp changed without a gap in sequence numbers. The marker
+                    // bit is just indicative, this is the robust fallback.
+                    return Some(index - 1);
+                }
+            }
+
+            if entry.tail {
+                return Some(index);
+            }
+
+            prev = entry;
+        }
+
+        None
+    }
+
+    fn emit(&mut self, stop: usize, contiguous_seq: bool) -> Option<AssemblerOutput> {
+        let entries: Vec<Entry> = self.queue.drain(0..=stop).collect();
+        let first = &entries[0];
+        let last = &entries[entries.len() - 1];
+        let seq_range = first.seq_no..=last.seq_no;
+        let ts = first.time;
+
+        self.last = Some(last.seq_no);
+
+        let mut data = Vec::new();
+        let mut codec_extra = CodecExtra::None;
+
+        for entry in &entries {
+            if let Err(e) = self
+                .depack
+                .depacketize(&entry.data, &mut data, &mut codec_extra)
+            {
+                debug!("Drop frame that failed to depacketize: {:?}", e);
+                self.dropped = true;
+                return Some(AssemblerOutput::Dropped {
+                    seq_range,
+                    keyframe_needed: self.codec.is_video(),
+                });
+            }
+        }
+
+        let (can_emit, contiguous) = self.contiguity.check(&codec_extra, contiguous_seq);
+
+        if !can_emit {
+            trace!("Skip frame not needed by codec: {:?}", seq_range);
+            return None;
+        }
+
+        self.dropped = false;
+
+        Some(AssemblerOutput::Frame(Frame {
+            ts,
+            keyframe: is_keyframe(&codec_extra),
+            data,
+            contiguous,
+            seq_range,
+            codec_extra,
+        }))
+    }
+
+    /// Drop all packets of the first timestamp in the queue.
+    fn drop_front(&mut self) -> AssemblerOutput {
+        let time = self.queue[0].time;
+        let count = self.queue.iter().take_while(|e| e.time == time).count();
+
+        let first = self.queue[0].seq_no;
+        let last = self.queue[count - 1].seq_no;
+        self.queue.drain(0..count);
+
+        debug!("Drop incomplete frame: {}..={}", first, last);
+
+        self.last = Some(last);
+        self.dropped = true;
+
+        AssemblerOutput::Dropped {
+            seq_range: first..=last,
+            keyframe_needed: self.codec.is_video(),
+        }
+    }
+}
+
+fn is_keyframe(codec_extra: &CodecExtra) -> bool {
+    match codec_extra {
+        CodecExtra::Vp8(e) => e.is_keyframe,
+        CodecExtra::Vp9(e) => e.is_keyframe,
+        CodecExtra::H264(e) => e.is_keyframe,
+        CodecExtra::H265(e) => e.is_keyframe,
+        CodecExtra::Av1(e) => e.is_keyframe,
+        CodecExtra::None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rtp_::RtpHeader;
+
+    use super::*;
+
+    fn packet(start: Instant, seq: u64, time: u64, marker: bool, payload: &[u8]) -> RtpPacket {
+        RtpPacket {
+            seq_no: seq.into(),
+            time: MediaTime::from_90khz(time),
+            header: RtpHeader {
+                marker,
+                ..Default::default()
+            },
+            payload: payload.to_vec(),
+            timestamp: start + Duration::from_millis(seq),
+            last_sender_info: None,
+            recovered: false,
+            nackable: true,
+            wallclock: None,
+        }
+    }
+
+    fn frame(output: Option<AssemblerOutput>) -> Frame {
+        match output {
+            Some(AssemblerOutput::Frame(f)) => f,
+            o => panic!("Expected frame, got {:?}", o),
+        }
+    }
+
+    fn seqs(r: RangeInclusive<u64>) -> RangeInclusive<SeqNo> {
+        (*r.start()).into()..=(*r.end()).into()
+    }
+
+    // VP8 keyframe in two packets, S bit on the first, P bit cleared.
+    const VP8_KEY_1: &[u8] = &[0x10, 0x00, 0x9d, 0x01];
+    const VP8_KEY_2: &[u8] = &[0x00, 0x2a, 0x2b, 0x2c];
+    // VP8 delta frame in two packets.
+    const VP8_DELTA_1: &[u8] = &[0x10, 0x01, 0x02, 0x03];
+    const VP8_DELTA_2: &[u8] = &[0x00, 0x04, 0x05, 0x06];
+
+    #[test]
+    fn vp8_reordered() {
+        let now = Instant::now();
+        let mut a = FrameAssembler::new(Codec::Vp8);
+
+        a.push(packet(now, 2, 0, true, VP8_KEY_2));
+        assert_eq!(a.poll_output(now), None);
+
+        a.push(packet(now, 1, 0, false, VP8_KEY_1));
+        let f = frame(a.poll_output(now));
+        assert!(f.keyframe);
+        assert!(f.contiguous);
+        assert_eq!(f.seq_range, seqs(1..=2));
+        assert_eq!(f.data, &[0x00, 0x9d, 0x01, 0x2a, 0x2b, 0x2c]);
+
+        a.push(packet(now, 4, 3000, true, VP8_DELTA_2));
+        a.push(packet(now, 3, 3000, false, VP8_DELTA_1));
+        let f = frame(a.poll_output(now));
+        assert!(!f.keyframe);
+        assert!(f.contiguous);
+        assert_eq!(f.ts, MediaTime::from_90khz(3000));
+
+        assert_eq!(a.poll_output(now), None);
+    }
+
+    #[test]
+    fn vp8_lost_packet() {
+        let now = Instant::now();
+        let mut a = FrameAssembler::new(Codec::Vp8).set_max_delay(Duration::from_millis(100));
+
+        a.push(packet(now, 1, 0, false, VP8_KEY_1));
+        a.push(packet(now, 2, 0, true, VP8_KEY_2));
+        frame(a.poll_output(now));
+
+        // Seq 4 of the delta frame is lost.
+        a.push(packet(now, 3, 3000, false, VP8_DELTA_1));
+        a.push(packet(now, 5, 6000, false, VP8_DELTA_1));
+        a.push(packet(now, 6, 6000, true, VP8_DELTA_2));
+
+        // Wait for the missing packet.
+        assert_eq!(a.poll_output(now), None);
+        assert_eq!(a.poll_timeout(), Some(now + Duration::from_millis(103)));
+
+        let later = now + Duration::from_millis(103);
+        assert_eq!(
+            a.poll_output(later),
+            Some(AssemblerOutput::Dropped {
+                seq_range: seqs(3..=3),
+                keyframe_needed: true,
+            })
+        );
+
+        // Seq 4 could still be a frame of its own.
+        assert_eq!(a.poll_output(later), None);
+
+        let later = now + Duration::from_millis(105);
+        let f = frame(a.poll_output(later));
+        assert_eq!(f.seq_range, seqs(5..=6));
+        assert!(!f.contiguous);
+
+        // The lost packet arrives too late.
+        a.push(packet(now, 4, 3000, true, VP8_DELTA_2));
+        assert_eq!(a.poll_output(later), None);
+    }
+
+    #[test]
+    fn vp8_missing_whole_frame() {
+        let now = Instant::now();
+        let mut a = FrameAssembler::new(Codec::Vp8).set_max_packets(3);
+
+        a.push(packet(now, 1, 0, false, VP8_KEY_1));
+        a.push(packet(now, 2, 0, true, VP8_KEY_2));
+        frame(a.poll_output(now));
+
+        // Seq 3-4 are lost entirely.
+        a.push(packet(now, 5, 6000, false, VP8_DELTA_1));
+        a.push(packet(now, 6, 6000, true, VP8_DELTA_2));
+        a.push(packet(now, 7, 9000, false, VP8_DELTA_1));
+        assert_eq!(a.poll_output(now), None);
+
+        // Exceeding max packets stops the waiting.
+        a.push(packet(now, 8, 9000, true, VP8_DELTA_2));
+
+        let f = frame(a.poll_output(now));
+        assert_eq!(f.seq_range, seqs(5..=6));
+        assert!(!f.contiguous);
+
+        let f = frame(a.poll_output(now));
+        assert_eq!(f.seq_range, seqs(7..=8));
+        assert!(f.contiguous);
+    }
+
+    #[test]
+    fn h264_fua_lost_start() {
+        let now = Instant::now();
+        let mut a = FrameAssembler::new(Codec::H264).set_max_delay(Duration::from_millis(50));
+
+        // IDR over FU-A: start, middle, end.
+        a.push(packet(now, 1, 0, false, &[0x7c, 0x85, 0x01]));
+        a.push(packet(now, 3, 0, true, &[0x7c, 0x45, 0x03]));
+        a.push(packet(now, 2, 0, false, &[0x7c, 0x05, 0x02]));
+
+        let f = frame(a.poll_output(now));
+        assert!(f.keyframe);
+        assert_eq!(f.data, &[0, 0, 0, 1, 0x65, 0x01, 0x02, 0x03]);
+
+        // Non-IDR over FU-A with the start lost.
+        a.push(packet(now, 5, 3000, false, &[0x5c, 0x01, 0x05]));
+        a.push(packet(now, 6, 3000, true, &[0x5c, 0x41, 0x06]));
+        // Next frame is a single NAL.
+        a.push(packet(now, 7, 6000, true, &[0x41, 0x07]));
+
+        assert_eq!(a.poll_output(now), None);
+
+        let later = now + Duration::from_millis(55);
+        assert_eq!(
+            a.poll_output(later),
+            Some(AssemblerOutput::Dropped {
+                seq_range: seqs(5..=6),
+                keyframe_needed: true,
+            })
+        );
+
+        let f = frame(a.poll_output(later));
+        assert_eq!(f.seq_range, seqs(7..=7));
+        assert!(!f.keyframe);
+        assert!(!f.contiguous);
+    }
+
+    #[test]
+    fn opus_loss_and_reorder() {
+        let now = Instant::now();
+        let mut a = FrameAssembler::new(Codec::Opus).set_max_delay(Duration::from_millis(40));
+
+        a.push(packet(now, 2, 960, false, &[0x78, 0x02]));
+        a.push(packet(now, 1, 0, false, &[0x78, 0x01]));
+
+        let f = frame(a.poll_output(now));
+        assert_eq!(f.data, &[0x78, 0x01]);
+        assert!(!f.keyframe);
+        let f = frame(a.poll_output(now));
+        assert_eq!(f.data, &[0x78, 0x02]);
+        assert!(f.contiguous);
+
+        // Seq 3 lost.
+        a.push(packet(now, 4, 2880, false, &[0x78, 0x04]));
+        assert_eq!(a.poll_output(now), None);
+
+        let f = frame(a.poll_output(now + Duration::from_millis(44)));
+        assert_eq!(f.data, &[0x78, 0x04]);
+        assert!(!f.contiguous);
+
+        // Duplicates are ignored.
+        a.push(packet(now, 4, 2880, false, &[0x78, 0x04]));
+        a.push(packet(now, 5, 3840, false, &[0x78, 0x05]));
+        a.push(packet(now, 5, 3840, false, &[0x78, 0x05]));
+        let f = frame(a.poll_output(now));
+        assert_eq!(f.seq_range, seqs(5..=5));
+        assert!(f.contiguous);
+        assert_eq!(a.poll_output(now), None);
+    }
+
+    #[test]
+    fn vp9_defacto_tail() {
+        let now = Instant::now();
+        let mut a = FrameAssembler::new(Codec::Vp9);
+
+        // Keyframe with the marker bit missing: B and E bits in a single packet.
+        a.push(packet(now, 1, 0, false, &[0x0c, 0x00, 0x01]));
+        assert_eq!(a.poll_output(now), None);
+
+        // Next frame's timestamp ends the previous frame.
+        a.push(packet(now, 2, 3000, true, &[0x4c, 0x00, 0x02]));
+
+        let f = frame(a.poll_output(now));
+        assert_eq!(f.seq_range, seqs(1..=1));
+        assert!(f.keyframe);
+
+        let f = frame(a.poll_output(now));
+        assert_eq!(f.seq_range, seqs(2..=2));
+        assert!(!f.keyframe);
+        assert!(f.contiguous);
+    }
+}
@@ -0,0 +1,106 @@
+use super::{CodecExtra, Depacketizer, MediaKind, PacketError, Packetizer};
+
+/// Packetizes arbitrary payloads.
+///
+/// The buffer is split over as many packets as needed to fit the MTU. The last
+/// packet gets the marker bit.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct GenericPacketizer;
+
+impl Packetizer for GenericPacketizer {
+    fn packetize(&mut self, mtu: usize, payload: &[u8]) -> Result<Vec<Vec<u8>>, PacketError> {
+        if payload.is_empty() || mtu == 0 {
+            return Ok(vec![]);
+        }
+
+        Ok(payload.chunks(mtu).map(|c| c.to_vec()).collect())
+    }
+
+    fn is_marker(&mut self, _data: &[u8], _previous: Option<&[u8]>, last: bool) -> bool {
+        last
+    }
+}
+
+/// Depacketizes arbitrary payloads.
+///
+/// Each RTP payload is passed through as a complete frame.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct GenericDepacketizer;
+
+impl Depacketizer for GenericDepacketizer {
+    fn depacketize(
+        &mut self,
+        packet: &[u8],
+        out: &mut Vec<u8>,
+        _: &mut CodecExtra,
+    ) -> Result<(), PacketError> {
+        out.extend_from_slice(packet);
+        Ok(())
+    }
+
+    fn is_partition_head(&self, _packet: &[u8]) -> bool {
+        true
+    }
+
+    fn is_partition_tail(&self, _marker: bool, _packet: &[u8]) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generic_packetize_large() -> Result<(), PacketError> {
+        let mut pck = GenericPacketizer;
+        let payload: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+
+        let packets = pck.packetize(1200, &payload)?;
+
+        // 83 full packets and 400 bytes left over.
+        assert_eq!(packets.len(), 84);
+        assert!(packets[..83].iter().all(|p| p.len() == 1200));
+        assert_eq!(packets[83].len(), 400);
+        assert_eq!(packets.concat(), payload);
+
+        let markers: Vec<bool> = packets
+            .iter()
+            .enumerate()
+            .map(|(i, p)| pck.is_marker(p, None, i == packets.len() - 1))
+            .collect();
+        assert!(markers[..83].iter().all(|m| !m));
+        assert!(markers[83]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generic_packetize_empty() -> Result<(), PacketError> {
+        let mut pck = GenericPacketizer;
+
+        let packets = pck.packetize(1200, &[])?;
+        assert!(packets.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generic_depacketize() -> Result<(), PacketError> {
+        let mut pck = GenericDepacketizer;
+        let mut extra = CodecExtra::None;
+
+        let mut out = Vec::new();
+        pck.depacketize(&[0x01, 0x02, 0x03], &mut out, &mut extra)?;
+        assert_eq!(out, &[0x01, 0x02, 0x03]);
+
+        let mut out = Vec::new();
+        pck.depacketize(&[], &mut out, &mut extra)?;
+        assert!(out.is_empty());
+
+        assert!(pck.is_partition_head(&[0x00]));
+        assert!(pck.is_partition_tail(false, &[0x00]));
+
+        Ok(())
+    }
+}
@@ -14,8 +14,15 @@ pub struct H264CodecExtra {
 }
 
 /// Packetizes H264 RTP packets.
+///
+/// The NAL units are given with Annex B start codes, or with a 4 byte length prefix (AVC)
+/// if `is_avc` is set. Small NAL units are aggregated into STAP-A and large ones fragmented
+/// into FU-A, unless `single_nal` is set for packetization-mode=0.
 #[derive(Default, Debug, Clone)]
 pub struct H264Packetizer {
+    pub is_avc: bool,
+    /// Only send single NAL unit packets (packetization-mode=0).
+    pub single_nal: bool,
     sps_nalu: Option<Vec<u8>>,
     pps_nalu: Option<Vec<u8>>,
 }
@@ -38,8 +45,6 @@ pub const NALU_REF_IDC_BITMASK: u8 = 0x60;
 pub const FU_START_BITMASK: u8 = 0x80;
 pub const FU_END_BITMASK: u8 = 0x40;
 
-pub const OUTPUT_STAP_AHEADER: u8 = 0x78;
-
 pub static ANNEXB_NALUSTART_CODE: &[u8] = &[0x00, 0x00, 0x00, 0x01];
 
 impl H264Packetizer {
@@ -58,52 +63,109 @@ impl H264Packetizer {
         (-1, -1)
     }
 
-    fn emit(&mut self, nalu: &[u8], mtu: usize, payloads: &mut Vec<Vec<u8>>) {
-        if nalu.is_empty() {
-            return;
+    /// Split an access unit into NAL units.
+    fn split<'a>(&self, payload: &'a [u8]) -> Result<Vec<&'a [u8]>, PacketError> {
+        let mut nalus = vec![];
+
+        if self.is_avc {
+            let mut rest = payload;
+            while !rest.is_empty() {
+                if rest.len() < 4 {
+                    return Err(PacketError::ErrShortPacket);
+                }
+                let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+                if len > rest.len() - 4 {
+                    return Err(PacketError::ErrShortPacket);
+                }
+                nalus.push(&rest[4..4 + len]);
+                rest = &rest[4 + len..];
+            }
+            return Ok(nalus);
         }
 
-        let nalu_type = nalu[0] & NALU_TYPE_BITMASK;
-        let nalu_ref_idc = nalu[0] & NALU_REF_IDC_BITMASK;
+        let (mut next_ind_start, mut next_ind_len) = H264Packetizer::next_ind(payload, 0);
+        if next_ind_start == -1 {
+            nalus.push(payload);
+        } else {
+            while next_ind_start != -1 {
+                let prev_start = (next_ind_start + next_ind_len) as usize;
+                let (next_ind_start2, next_ind_len2) =
+                    H264Packetizer::next_ind(payload, prev_start);
+                next_ind_start = next_ind_start2;
+                next_ind_len = next_ind_len2;
+                if next_ind_start != -1 {
+                    nalus.push(&payload[prev_start..next_ind_start as usize]);
+                } else {
+                    // Until end of stream, no end indicator found
+                    nalus.push(&payload[prev_start..]);
+                }
+            }
+        }
 
-        if nalu_type == AUD_NALU_TYPE || nalu_type == FILLER_NALU_TYPE {
-            return;
-        } else if nalu_type == SPS_NALU_TYPE {
-            self.sps_nalu = Some(nalu.to_vec());
-            return;
-        } else if nalu_type == PPS_NALU_TYPE {
-            self.pps_nalu = Some(nalu.to_vec());
-            return;
-        } else if let (Some(sps_nalu), Some(pps_nalu)) = (&self.sps_nalu, &self.pps_nalu) {
-            // Pack current NALU with SPS and PPS as STAP-A
-            let sps_len = (sps_nalu.len() as u16).to_be_bytes();
-            let pps_len = (pps_nalu.len() as u16).to_be_bytes();
-
-            let mut stap_a_nalu = Vec::with_capacity(1 + 2 + sps_nalu.len() + 2 + pps_nalu.len());
-            stap_a_nalu.push(OUTPUT_STAP_AHEADER);
-            stap_a_nalu.extend(sps_len);
-            stap_a_nalu.extend_from_slice(sps_nalu);
-            stap_a_nalu.extend(pps_len);
-            stap_a_nalu.extend_from_slice(pps_nalu);
-            if stap_a_nalu.len() <= mtu {
-                payloads.push(stap_a_nalu);
+        Ok(nalus)
+    }
+}
+
+/// Payloads of one access unit being packetized.
+struct H264Payloads {
+    mtu: usize,
+    single_nal: bool,
+    payloads: Vec<Vec<u8>>,
+    /// STAP-A being aggregated and the number of NAL units in it.
+    stap_a: Vec<u8>,
+    stap_a_count: usize,
+}
+
+impl H264Payloads {
+    fn push(&mut self, nalu: &[u8]) -> Result<(), PacketError> {
+        if nalu.is_empty() {
+            return Ok(());
+        }
+
+        if self.single_nal {
+            if nalu.len() > self.mtu {
+                return Err(PacketError::NaluLargerThanMtu(nalu.len(), self.mtu));
             }
+            self.payloads.push(nalu.to_vec());
+            return Ok(());
         }
 
-        if self.sps_nalu.is_some() && self.pps_nalu.is_some() {
-            self.sps_nalu = None;
-            self.pps_nalu = None;
+        // Aggregate into a STAP-A if the NAL unit fits alongside the previous ones.
+        let stap_a_len = self.stap_a.len().max(STAPA_HEADER_SIZE);
+        if stap_a_len + STAPA_NALU_LENGTH_SIZE + nalu.len() <= self.mtu {
+            if self.stap_a.is_empty() {
+                self.stap_a.push(STAPA_NALU_TYPE);
+            }
+            // +---------------+
+            // |0|1|2|3|4|5|6|7|
+            // +-+-+-+-+-+-+-+-+
+            // |F|NRI|  Type   |
+            // +---------------+
+            // F is set if any aggregated F is set, NRI is the max of the aggregated NRI.
+            let f = (self.stap_a[0] | nalu[0]) & 0x80;
+            let nri = (self.stap_a[0] & NALU_REF_IDC_BITMASK).max(nalu[0] & NALU_REF_IDC_BITMASK);
+            self.stap_a[0] = f | nri | STAPA_NALU_TYPE;
+
+            self.stap_a.extend((nalu.len() as u16).to_be_bytes());
+            self.stap_a.extend_from_slice(nalu);
+            self.stap_a_count += 1;
+            return Ok(());
         }
 
+        self.flush();
+
         // Single NALU
-        if nalu.len() <= mtu {
-            payloads.push(nalu.to_vec());
-            return;
+        if nalu.len() <= self.mtu {
+            self.payloads.push(nalu.to_vec());
+            return Ok(());
         }
 
-        // FU-A
-        let max_fragment_size = mtu as isize - FUA_HEADER_SIZE as isize;
+        self.push_fu_a(nalu);
+
+        Ok(())
+    }
 
+    fn push_fu_a(&mut self, nalu: &[u8]) {
         // The FU payload consists of fragments of the payload of the fragmented
         // NAL unit so that if the fragmentation unit payloads of consecutive
         // FUs are sequentially concatenated, the payload of the fragmented NAL
@@ -114,53 +176,55 @@ impl H264Packetizer {
         // indicator octet of the fragmentation unit and in the type field of
         // the FU header.  An FU payload MAY have any number of octets and MAY
         // be empty.
-
-        let nalu_data = nalu;
-        // According to the RFC, the first octet is skipped due to redundant information
-        let mut nalu_data_index = 1;
-        let nalu_data_length = nalu.len() as isize - nalu_data_index;
-        let mut nalu_data_remaining = nalu_data_length;
-
-        if std::cmp::min(max_fragment_size, nalu_data_remaining) <= 0 {
+        if self.mtu <= FUA_HEADER_SIZE {
             return;
         }
+        let max_fragment_size = self.mtu - FUA_HEADER_SIZE;
+
+        let nalu_type = nalu[0] & NALU_TYPE_BITMASK;
+        let nalu_f_nri = nalu[0] & !NALU_TYPE_BITMASK;
 
-        while nalu_data_remaining > 0 {
-            let current_fragment_size = std::cmp::min(max_fragment_size, nalu_data_remaining);
-            //out: = make([]byte, fuaHeaderSize + currentFragmentSize)
-            let mut out = Vec::with_capacity(FUA_HEADER_SIZE + current_fragment_size as usize);
+        // According to the RFC, the first octet is skipped due to redundant information
+        let fragments = nalu[1..].chunks(max_fragment_size);
+        let count = fragments.len();
+
+        for (i, fragment) in fragments.enumerate() {
+            let mut out = Vec::with_capacity(FUA_HEADER_SIZE + fragment.len());
             // +---------------+
             // |0|1|2|3|4|5|6|7|
             // +-+-+-+-+-+-+-+-+
             // |F|NRI|  Type   |
             // +---------------+
-            let b0 = FUA_NALU_TYPE | nalu_ref_idc;
-            out.push(b0);
+            out.push(FUA_NALU_TYPE | nalu_f_nri);
 
             // +---------------+
-            //|0|1|2|3|4|5|6|7|
-            //+-+-+-+-+-+-+-+-+
-            //|S|E|R|  Type   |
-            //+---------------+
-
+            // |0|1|2|3|4|5|6|7|
+            // +-+-+-+-+-+-+-+-+
+            // |S|E|R|  Type   |
+            // +---------------+
             let mut b1 = nalu_type;
-            if nalu_data_remaining == nalu_data_length {
-                // Set start bit
-                b1 |= 1 << 7;
-            } else if nalu_data_remaining - current_fragment_size == 0 {
-                // Set end bit
-                b1 |= 1 << 6;
+            if i == 0 {
+                b1 |= FU_START_BITMASK;
+            }
+            if i == count - 1 {
+                b1 |= FU_END_BITMASK;
             }
             out.push(b1);
 
-            out.extend_from_slice(
-                &nalu_data
-                    [nalu_data_index as usize..(nalu_data_index + current_fragment_size) as usize],
-            );
-            payloads.push(out);
+            out.extend_from_slice(fragment);
+            self.payloads.push(out);
+        }
+    }
 
-            nalu_data_remaining -= current_fragment_size;
-            nalu_data_index += current_fragment_size;
+    /// Send the aggregated NAL units, as a single NAL unit packet if there is only one.
+    fn flush(&mut self) {
+        let stap_a = std::mem::take(&mut self.stap_a);
+        match std::mem::take(&mut self.stap_a_count) {
+            0 => {}
+            1 => self
+                .payloads
+                .push(stap_a[STAPA_HEADER_SIZE + STAPA_NALU_LENGTH_SIZE..].to_vec()),
+            _ => self.payloads.push(stap_a),
         }
     }
 }
@@ -172,32 +236,39 @@ impl Packetizer for H264Packetizer {
             return Ok(vec![]);
         }
 
-        let mut payloads = vec![];
+        let mut out = H264Payloads {
+            mtu,
+            single_nal: self.single_nal,
+            payloads: vec![],
+            stap_a: vec![],
+            stap_a_count: 0,
+        };
 
-        let (mut next_ind_start, mut next_ind_len) = H264Packetizer::next_ind(payload, 0);
-        if next_ind_start == -1 {
-            self.emit(payload, mtu, &mut payloads);
-        } else {
-            while next_ind_start != -1 {
-                let prev_start = (next_ind_start + next_ind_len) as usize;
-                let (next_ind_start2, next_ind_len2) =
-                    H264Packetizer::next_ind(payload, prev_start);
-                next_ind_start = next_ind_start2;
-                next_ind_len = next_ind_len2;
-                if next_ind_start != -1 {
-                    self.emit(
-                        &payload[prev_start..next_ind_start as usize],
-                        mtu,
-                        &mut payloads,
-                    );
-                } else {
-                    // Emit until end of stream, no end indicator found
-                    self.emit(&payload[prev_start..], mtu, &mut payloads);
+        for nalu in self.split(payload)? {
+            let Some(b0) = nalu.first() else {
+                continue;
+            };
+
+            match b0 & NALU_TYPE_BITMASK {
+                AUD_NALU_TYPE | FILLER_NALU_TYPE => continue,
+                // SPS and PPS are sent together with the following NAL unit.
+                SPS_NALU_TYPE => self.sps_nalu = Some(nalu.to_vec()),
+                PPS_NALU_TYPE => self.pps_nalu = Some(nalu.to_vec()),
+                _ => {
+                    if self.sps_nalu.is_some() && self.pps_nalu.is_some() {
+                        let sps_nalu = self.sps_nalu.take().unwrap();
+                        let pps_nalu = self.pps_nalu.take().unwrap();
+                        out.push(&sps_nalu)?;
+                        out.push(&pps_nalu)?;
+                    }
+                    out.push(nalu)?;
                 }
             }
         }
 
-        Ok(payloads)
+        out.flush();
+
+        Ok(out.payloads)
     }
 
     fn is_marker(&mut self, data: &[u8], previous: Option<&[u8]>, last: bool) -> bool {
@@ -206,9 +277,14 @@ impl Packetizer for H264Packetizer {
 }
 
 /// Depacketizes H264 RTP packets.
+///
+/// Handles single NAL unit packets, STAP-A and FU-A as described in
+/// [RFC 6184](https://datatracker.ietf.org/doc/html/rfc6184). The NAL units are output
+/// with Annex B start codes, or with a 4 byte length prefix (AVC) if `is_avc` is set.
 #[derive(PartialEq, Eq, Debug, Default, Clone)]
 pub struct H264Depacketizer {
     pub is_avc: bool,
+    /// FU-A fragments of the NAL unit being reassembled, starting with the NAL header.
     fua_buffer: Option<Vec<u8>>,
 }
 
@@ -229,21 +305,15 @@ impl Depacketizer for H264Depacketizer {
         let b0 = packet[0];
         let nalu_type = b0 & NALU_TYPE_BITMASK;
 
-        match nalu_type {
-            t @ 1..=23 => {
-                let is_keyframe = if let CodecExtra::H264(e) = extra {
-                    (t == IDR_NALU_TYPE) | e.is_keyframe
-                } else {
-                    t == IDR_NALU_TYPE
-                };
-                *extra = CodecExtra::H264(H264CodecExtra { is_keyframe });
+        if nalu_type != FUA_NALU_TYPE && self.fua_buffer.take().is_some() {
+            // In non-interleaved mode, no other packet can come between the fragments
+            // of a NAL unit. The end fragment is lost.
+            trace!("Discard incomplete FU-A before NALU type: {}", nalu_type);
+        }
 
-                if self.is_avc {
-                    out.extend_from_slice(&(packet.len() as u32).to_be_bytes());
-                } else {
-                    out.extend_from_slice(ANNEXB_NALUSTART_CODE);
-                }
-                out.extend_from_slice(packet);
+        match nalu_type {
+            1..=23 => {
+                self.emit_nalu(packet, out, extra);
                 Ok(())
             }
             STAPA_NALU_TYPE => {
@@ -260,21 +330,7 @@ impl Depacketizer for H264Depacketizer {
                         ));
                     }
 
-                    let b0 = packet[curr_offset];
-                    let t = b0 & NALU_TYPE_BITMASK;
-                    let is_keyframe = if let CodecExtra::H264(e) = extra {
-                        (t == IDR_NALU_TYPE) | e.is_keyframe
-                    } else {
-                        t == IDR_NALU_TYPE
-                    };
-                    *extra = CodecExtra::H264(H264CodecExtra { is_keyframe });
-
-                    if self.is_avc {
-                        out.extend_from_slice(&(nalu_size as u32).to_be_bytes());
-                    } else {
-                        out.extend_from_slice(ANNEXB_NALUSTART_CODE);
-                    }
-                    out.extend_from_slice(&packet[curr_offset..curr_offset + nalu_size]);
+                    self.emit_nalu(&packet[curr_offset..curr_offset + nalu_size], out, extra);
                     curr_offset += nalu_size;
                 }
 
@@ -285,40 +341,39 @@ impl Depacketizer for H264Depacketizer {
                     return Err(PacketError::ErrShortPacket);
                 }
 
-                if self.fua_buffer.is_none() {
-                    self.fua_buffer = Some(Vec::new());
+                let b1 = packet[1];
+                let fragmented_nalu_type = b1 & NALU_TYPE_BITMASK;
+
+                if b1 & FU_START_BITMASK != 0 {
+                    if self.fua_buffer.is_some() {
+                        trace!("Discard incomplete FU-A before new start fragment");
+                    }
+                    let nalu_ref_idc = b0 & NALU_REF_IDC_BITMASK;
+                    self.fua_buffer = Some(vec![nalu_ref_idc | fragmented_nalu_type]);
                 }
 
-                if let Some(fua_buffer) = &mut self.fua_buffer {
-                    fua_buffer.extend_from_slice(&packet[FUA_HEADER_SIZE as usize..]);
+                let Some(fua_buffer) = &mut self.fua_buffer else {
+                    // Without the start fragment, the NAL unit can't be reconstructed.
+                    trace!("Discard FU-A fragment without start fragment");
+                    return Ok(());
+                };
+
+                if fua_buffer[0] & NALU_TYPE_BITMASK != fragmented_nalu_type {
+                    // Fragments of another NAL unit, the ones in between are lost.
+                    trace!("Discard FU-A fragment of mismatching NALU type");
+                    self.fua_buffer = None;
+                    return Ok(());
                 }
 
-                let b1 = packet[1];
+                fua_buffer.extend_from_slice(&packet[FUA_HEADER_SIZE as usize..]);
+
                 if b1 & FU_END_BITMASK != 0 {
-                    let nalu_ref_idc = b0 & NALU_REF_IDC_BITMASK;
-                    let fragmented_nalu_type = b1 & NALU_TYPE_BITMASK;
-
-                    let is_keyframe = if let CodecExtra::H264(e) = extra {
-                        (fragmented_nalu_type == IDR_NALU_TYPE) | e.is_keyframe
-                    } else {
-                        fragmented_nalu_type == IDR_NALU_TYPE
-                    };
-                    *extra = CodecExtra::H264(H264CodecExtra { is_keyframe });
-
-                    if let Some(fua_buffer) = self.fua_buffer.take() {
-                        if self.is_avc {
-                            out.extend_from_slice(&((fua_buffer.len() + 1) as u32).to_be_bytes());
-                        } else {
-                            out.extend_from_slice(ANNEXB_NALUSTART_CODE);
-                        }
-                        out.push(nalu_ref_idc | fragmented_nalu_type);
-                        out.extend_from_slice(&fua_buffer);
+                    if let Some(nalu) = self.fua_buffer.take() {
+                        self.emit_nalu(&nalu, out, extra);
                     }
-
-                    Ok(())
-                } else {
-                    Ok(())
                 }
+
+                Ok(())
             }
             _ => Err(PacketError::NaluTypeIsNotHandled(nalu_type)),
         }
@@ -342,6 +397,36 @@ impl Depacketizer for H264Depacketizer {
     fn is_partition_tail(&self, marker: bool, _packet: &[u8]) -> bool {
         marker
     }
+
+    fn reset(&mut self) {
+        self.fua_buffer = None;
+    }
+}
+
+impl H264Depacketizer {
+    /// Write one complete NAL unit with a start code or length prefix.
+    fn emit_nalu(&self, nalu: &[u8], out: &mut Vec<u8>, extra: &mut CodecExtra) {
+        let Some(b0) = nalu.first() else {
+            return;
+        };
+
+        // An IDR, or the SPS/PPS sent ahead of it, makes the frame a keyframe.
+        let t = b0 & NALU_TYPE_BITMASK;
+        let is_keyframe = matches!(t, IDR_NALU_TYPE | SPS_NALU_TYPE | PPS_NALU_TYPE);
+        let is_keyframe = if let CodecExtra::H264(e) = extra {
+            is_keyframe | e.is_keyframe
+        } else {
+            is_keyframe
+        };
+        *extra = CodecExtra::H264(H264CodecExtra { is_keyframe });
+
+        if self.is_avc {
+            out.extend_from_slice(&(nalu.len() as u32).to_be_bytes());
+        } else {
+            out.extend_from_slice(ANNEXB_NALUSTART_CODE);
+        }
+        out.extend_from_slice(nalu);
+    }
 }
 
 #[cfg(test)]
@@ -670,12 +755,10 @@ mod test {
     #[test]
     fn test_h264_packetizer_payload_sps_and_pps_handling() -> Result<(), PacketError> {
         let mut pck = H264Packetizer::default();
-        let expected: Vec<&[u8]> = vec![
-            &[
-                0x78, 0x00, 0x03, 0x07, 0x00, 0x01, 0x00, 0x03, 0x08, 0x02, 0x03,
-            ],
-            &[0x05, 0x04, 0x05],
-        ];
+        let expected: Vec<&[u8]> = vec![&[
+            0x18, 0x00, 0x03, 0x07, 0x00, 0x01, 0x00, 0x03, 0x08, 0x02, 0x03, 0x00, 0x03, 0x05,
+            0x04, 0x05,
+        ]];
 
         // When packetizing SPS and PPS are emitted with following NALU
         let res = pck.packetize(1500, &[0x07, 0x00, 0x01])?;
@@ -690,6 +773,126 @@ mod test {
         Ok(())
     }
 
+    fn depacketize_all(packets: &[Vec<u8>], is_avc: bool) -> (Vec<u8>, CodecExtra) {
+        let mut depack = H264Depacketizer {
+            is_avc,
+            ..Default::default()
+        };
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+        for p in packets {
+            depack.depacketize(p, &mut out, &mut extra).unwrap();
+        }
+        (out, extra)
+    }
+
+    fn access_unit(slice_len: usize) -> Vec<u8> {
+        let mut au = vec![];
+        // AUD, SPS, PPS and IDR slice
+        au.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x09, 0xf0]);
+        au.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x67, 0x42, 0xc0, 0x1f, 0x8c, 0x8d]);
+        au.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x68, 0xce, 0x3c, 0x80]);
+        au.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x65]);
+        au.extend((0..slice_len).map(|i| (i % 250) as u8 + 2));
+        au
+    }
+
+    #[test]
+    fn test_h264_packetizer_round_trip() -> Result<(), PacketError> {
+        for slice_len in [10, 1090, 5000] {
+            let au = access_unit(slice_len);
+
+            let mut pck = H264Packetizer::default();
+            let packets = pck.packetize(1100, &au)?;
+
+            assert!(packets.iter().all(|p| p.len() <= 1100));
+
+            let depack = H264Depacketizer::default();
+            assert!(depack.is_partition_head(&packets[0]));
+
+            // SPS and PPS are aggregated with the slice, or its first fragment.
+            let types: Vec<_> = packets.iter().map(|p| p[0] & NALU_TYPE_BITMASK).collect();
+            if slice_len == 10 {
+                assert_eq!(types, [STAPA_NALU_TYPE]);
+                // NRI of the STAP-A is the highest one of the aggregated.
+                assert_eq!(packets[0][0], 0x78);
+            } else if slice_len == 1090 {
+                // The slice doesn't fit in the STAP-A, but in a single NAL unit packet.
+                assert_eq!(types, [STAPA_NALU_TYPE, IDR_NALU_TYPE]);
+            } else {
+                assert_eq!(types[0], STAPA_NALU_TYPE);
+                assert!(types[1..].iter().all(|t| *t == FUA_NALU_TYPE));
+                assert_eq!(packets[1][0], 0x7c);
+                assert_eq!(packets[1][1], FU_START_BITMASK | IDR_NALU_TYPE);
+                assert_eq!(packets.last().unwrap()[1], FU_END_BITMASK | IDR_NALU_TYPE);
+                assert!(packets[2..].iter().all(|p| !depack.is_partition_head(p)));
+            }
+
+            // The AUD is not sent.
+            let (out, extra) = depacketize_all(&packets, false);
+            assert_eq!(out, &au[6..]);
+            assert_eq!(
+                extra,
+                CodecExtra::H264(H264CodecExtra { is_keyframe: true })
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h264_packetizer_length_prefixed() -> Result<(), PacketError> {
+        let au = [
+            &[0x00, 0x00, 0x00, 0x03, 0x67, 0x42, 0xc0][..],
+            &[0x00, 0x00, 0x00, 0x02, 0x68, 0xce],
+            &[0x00, 0x00, 0x00, 0x04, 0x65, 0x00, 0x00, 0x01],
+        ]
+        .concat();
+
+        let mut pck = H264Packetizer {
+            is_avc: true,
+            ..Default::default()
+        };
+        let packets = pck.packetize(1100, &au)?;
+        assert_eq!(packets.len(), 1);
+
+        let (out, _) = depacketize_all(&packets, true);
+        assert_eq!(out, au);
+
+        // Length beyond the end of the data
+        let result = pck.packetize(1100, &[0x00, 0x00, 0x00, 0x05, 0x65, 0x00]);
+        assert!(matches!(result, Err(PacketError::ErrShortPacket)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h264_packetizer_single_nal_mode() -> Result<(), PacketError> {
+        let mut pck = H264Packetizer {
+            single_nal: true,
+            ..Default::default()
+        };
+
+        let au = access_unit(500);
+        let packets = pck.packetize(1100, &au)?;
+
+        // No aggregation or fragmentation.
+        let types: Vec<_> = packets.iter().map(|p| p[0] & NALU_TYPE_BITMASK).collect();
+        assert_eq!(types, [SPS_NALU_TYPE, PPS_NALU_TYPE, IDR_NALU_TYPE]);
+
+        let (out, _) = depacketize_all(&packets, false);
+        assert_eq!(out, &au[6..]);
+
+        // A NAL unit above the MTU can't be sent.
+        let result = pck.packetize(1100, &access_unit(1100));
+        assert!(matches!(
+            result,
+            Err(PacketError::NaluLargerThanMtu(1101, 1100))
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn test_h264_depacketizer_idr_handling() -> Result<(), PacketError> {
         let mut pck = H264Depacketizer::default();
@@ -731,6 +934,83 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_h264_depacketizer_sps_pps_keyframe() -> Result<(), PacketError> {
+        let mut pck = H264Depacketizer::default();
+        let mut extra = CodecExtra::None;
+        let mut out = vec![];
+
+        // Non-IDR slice is not a keyframe.
+        pck.depacketize(&[0x41, 0x9a], &mut out, &mut extra)?;
+        assert_eq!(
+            extra,
+            CodecExtra::H264(H264CodecExtra { is_keyframe: false })
+        );
+
+        // SPS and PPS in a STAP-A.
+        let mut extra = CodecExtra::None;
+        let stapa = &[0x78, 0x00, 0x02, 0x67, 0x42, 0x00, 0x02, 0x68, 0xce];
+        pck.depacketize(stapa, &mut out, &mut extra)?;
+        assert_eq!(
+            extra,
+            CodecExtra::H264(H264CodecExtra { is_keyframe: true })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h264_depacketizer_orphan_fua() -> Result<(), PacketError> {
+        let mut pck = H264Depacketizer::default();
+        let mut extra = CodecExtra::None;
+        let mut out = vec![];
+
+        // Middle and end fragments of an IDR whose start fragment was lost.
+        pck.depacketize(&[0x7c, 0x05, 0x01, 0x02], &mut out, &mut extra)?;
+        pck.depacketize(&[0x7c, 0x45, 0x03, 0x04], &mut out, &mut extra)?;
+        assert!(out.is_empty());
+        assert_eq!(extra, CodecExtra::None);
+
+        // The next frame is unaffected.
+        pck.depacketize(&[0x5c, 0x81, 0x05, 0x06], &mut out, &mut extra)?;
+        pck.depacketize(&[0x5c, 0x41, 0x07], &mut out, &mut extra)?;
+        assert_eq!(out, [0x00, 0x00, 0x00, 0x01, 0x41, 0x05, 0x06, 0x07]);
+        assert_eq!(
+            extra,
+            CodecExtra::H264(H264CodecExtra { is_keyframe: false })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h264_depacketizer_fua_lost_end() -> Result<(), PacketError> {
+        let mut pck = H264Depacketizer::default();
+        pck.is_avc = true;
+        let mut extra = CodecExtra::None;
+        let mut out = vec![];
+
+        // Start of a fragmented NAL unit, whose end never arrives.
+        pck.depacketize(&[0x7c, 0x85, 0x01, 0x02], &mut out, &mut extra)?;
+
+        // A new start fragment replaces the incomplete one.
+        pck.depacketize(&[0x5c, 0x81, 0x03], &mut out, &mut extra)?;
+        pck.depacketize(&[0x5c, 0x41, 0x04], &mut out, &mut extra)?;
+        assert_eq!(out, [0x00, 0x00, 0x00, 0x03, 0x41, 0x03, 0x04]);
+
+        // A single NAL unit drops the incomplete fragment, and so does an end
+        // fragment of another NAL unit type.
+        let mut out = vec![];
+        pck.depacketize(&[0x7c, 0x85, 0x01, 0x02], &mut out, &mut extra)?;
+        pck.depacketize(&[0x09, 0x30], &mut out, &mut extra)?;
+        pck.depacketize(&[0x7c, 0x45, 0x03], &mut out, &mut extra)?;
+        pck.depacketize(&[0x7c, 0x85, 0x01, 0x02], &mut out, &mut extra)?;
+        pck.depacketize(&[0x5c, 0x41, 0x03], &mut out, &mut extra)?;
+        assert_eq!(out, [0x00, 0x00, 0x00, 0x02, 0x09, 0x30]);
+
+        Ok(())
+    }
+
     #[test]
     fn parse_first_packet() {
         const PACKET: &[u8] = &[
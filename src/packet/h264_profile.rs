@@ -69,6 +69,11 @@ impl H264ProfileLevel {
             H264ProfileIdc::X64,
             BitPattern::new(*b"00000000"),
         ),
+        (
+            H264Profile::ConstrainedHigh,
+            H264ProfileIdc::X64,
+            BitPattern::new(*b"00001100"),
+        ),
         (
             H264Profile::High10,
             H264ProfileIdc::X6E,
@@ -126,6 +131,81 @@ impl H264ProfileLevel {
                 Some(Self { profile, level_idc })
             })
     }
+
+    /// Whether the profiles are the same, regardless of level.
+    ///
+    /// RFC 6184 8.2.2: the profile of the answer must be the same as in the offer, while the
+    /// level can differ.
+    pub(crate) fn is_same_profile(&self, other: &Self) -> bool {
+        self.profile == other.profile
+    }
+
+    /// Whether the levels are the same.
+    pub(crate) fn is_same_level(&self, other: &Self) -> bool {
+        self.level_idc == other.level_idc
+    }
+}
+
+/// The profile-level-id to answer with, for our `local` and the `remote` profile-level-id.
+///
+/// Without level asymmetry, both sides use the lower of the two levels. With it, we keep our
+/// own level for what we receive. None if the profiles differ, or either is not valid.
+///
+/// Like libWebRTC, see `H264GenerateProfileLevelIdForAnswer`.
+pub(crate) fn answer_profile_level_id(
+    local: u32,
+    remote: u32,
+    level_asymmetry_allowed: bool,
+) -> Option<u32> {
+    let l = H264ProfileLevel::try_from(local).ok()?;
+    let r = H264ProfileLevel::try_from(remote).ok()?;
+
+    if !l.is_same_profile(&r) {
+        return None;
+    }
+
+    if level_asymmetry_allowed || !r.level_idc.is_less(l.level_idc) {
+        return Some(local);
+    }
+
+    Some(with_level(local, r.level_idc))
+}
+
+/// Replace the level of a profile-level-id, keeping profile_idc and profile-iop.
+fn with_level(profile_level_id: u32, level_idc: H264LevelIdc) -> u32 {
+    const CONSTRAINT_SET3_FLAG: u8 = 0x10;
+
+    let mut bytes = profile_level_id.to_be_bytes();
+
+    if level_idc == H264LevelIdc::Level1B {
+        if is_bme(bytes[1]) {
+            // Level 1.1 with the constraint_set3_flag.
+            bytes[2] |= CONSTRAINT_SET3_FLAG;
+            bytes[3] = H264LevelIdc::Level1_1 as u8;
+        } else {
+            bytes[3] = LEVEL_1B_HIGH;
+        }
+    } else {
+        if is_bme(bytes[1]) {
+            bytes[2] &= !CONSTRAINT_SET3_FLAG;
+        }
+        bytes[3] = level_idc as u8;
+    }
+
+    u32::from_be_bytes(bytes)
+}
+
+/// Level 1b in the High profiles is level_idc 9.
+const LEVEL_1B_HIGH: u8 = 9;
+
+/// Whether the profile_idc is Baseline, Main or Extended.
+fn is_bme(profile_idc: u8) -> bool {
+    [
+        H264ProfileIdc::X42 as u8,
+        H264ProfileIdc::X4D as u8,
+        H264ProfileIdc::X58 as u8,
+    ]
+    .contains(&profile_idc)
 }
 
 impl TryFrom<u32> for H264ProfileLevel {
@@ -138,26 +218,22 @@ impl TryFrom<u32> for H264ProfileLevel {
 
         let profile_idc = bytes[1].try_into()?;
         let profile_iop = bytes[2];
-        let mut profile_level = bytes[3].try_into()?;
 
         // When profile_idc is equal to 66, 77, or 88 (the Baseline, Main, or
         // Extended profile), level_idc is equal to 11, and bit 4
         // (constraint_set3_flag) of the profile-iop byte is equal to 1,
-        // the default level is Level 1b.
-        if [
-            H264ProfileIdc::X42,
-            H264ProfileIdc::X4D,
-            H264ProfileIdc::X58,
-        ]
-        .contains(&profile_idc)
-            && profile_level == H264LevelIdc::Level1
-        {
-            profile_level = if (profile_iop & CONSTRAINT_SET3_FLAG) != 0 {
+        // the level is Level 1b. The other profiles use level_idc 9.
+        let profile_level = if is_bme(bytes[1]) {
+            if bytes[3] == H264LevelIdc::Level1_1 as u8 && profile_iop & CONSTRAINT_SET3_FLAG != 0 {
                 H264LevelIdc::Level1B
             } else {
-                H264LevelIdc::Level1
-            };
-        }
+                bytes[3].try_into()?
+            }
+        } else if bytes[3] == LEVEL_1B_HIGH {
+            H264LevelIdc::Level1B
+        } else {
+            bytes[3].try_into()?
+        };
 
         Self::new(profile_idc, profile_iop, profile_level).ok_or(())
     }
@@ -170,6 +246,7 @@ enum H264Profile {
     Main,
     Extended,
     High,
+    ConstrainedHigh,
     High10,
     High422,
     High444Predictive,
@@ -240,6 +317,19 @@ enum H264LevelIdc {
     Level5_2 = 52_u8,
 }
 
+impl H264LevelIdc {
+    /// Level 1b is between 1 and 1.1.
+    fn is_less(self, other: Self) -> bool {
+        use H264LevelIdc::*;
+
+        match (self, other) {
+            (Level1B, _) => other != Level1 && other != Level1B,
+            (_, Level1B) => self == Level1,
+            _ => (self as u8) < (other as u8),
+        }
+    }
+}
+
 impl TryFrom<u8> for H264LevelIdc {
     type Error = ();
 
@@ -269,3 +359,42 @@ impl TryFrom<u8> for H264LevelIdc {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn answer_level() {
+        // (local, remote, level-asymmetry-allowed, answer)
+        #[rustfmt::skip]
+        const CASES: &[(u32, u32, bool, Option<u32>)] = &[
+            (0x42e01f, 0x42e01f, false, Some(0x42e01f)),
+            // The remote level is higher, ours stays.
+            (0x42e01f, 0x42e034, false, Some(0x42e01f)),
+            // Lower, which only matters without asymmetry.
+            (0x42e01f, 0x42e00d, false, Some(0x42e00d)),
+            (0x42e01f, 0x42e00d, true, Some(0x42e01f)),
+            (0x42001f, 0x42801e, false, Some(0x42001e)),
+            (0x4d001f, 0x4d0016, false, Some(0x4d0016)),
+            (0x64001f, 0x64001e, false, Some(0x64001e)),
+            // Level 1b.
+            (0x42e01f, 0x42f00b, false, Some(0x42f00b)),
+            (0x42001f, 0x42100b, false, Some(0x42100b)),
+            (0x64001f, 0x640009, false, Some(0x640009)),
+            (0x42f00b, 0x42e00b, false, Some(0x42f00b)),
+            (0x4d100b, 0x4d000a, false, Some(0x4d000a)),
+            // Different profiles.
+            (0x42e01f, 0x42001f, false, None),
+            (0x64001f, 0x640c1f, false, None),
+        ];
+
+        for (local, remote, asymmetry, answer) in CASES {
+            assert_eq!(
+                answer_profile_level_id(*local, *remote, *asymmetry),
+                *answer,
+                "{local:06x} {remote:06x} {asymmetry}"
+            );
+        }
+    }
+}
@@ -1,8 +1,19 @@
 #![allow(clippy::all)]
 #![allow(unused)]
 
+use super::h264::ANNEXB_NALUSTART_CODE;
 use super::{CodecExtra, Depacketizer, PacketError};
 
+/// H265 information describing the depacketized data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct H265CodecExtra {
+    /// Flag which indicates that within [`MediaData`], there is an IRAP picture or
+    /// the parameter sets (VPS, SPS, PPS) needed to start decoding.
+    ///
+    /// [`MediaData`]: crate::media::MediaData
+    pub is_keyframe: bool,
+}
+
 ///
 /// Network Abstraction Unit Header implementation
 ///
@@ -15,6 +26,10 @@ const H265NALU_FRAGMENTATION_UNIT_TYPE: u8 = 49;
 /// https://datatracker.ietf.org/doc/html/rfc7798#section-4.4.4
 const H265NALU_PACI_PACKET_TYPE: u8 = 50;
 
+const H265NALU_VPS_TYPE: u8 = 32;
+const H265NALU_SPS_TYPE: u8 = 33;
+const H265NALU_PPS_TYPE: u8 = 34;
+
 /// H265NALUHeader is a H265 NAL Unit Header
 /// https://datatracker.ietf.org/doc/html/rfc7798#section-1.1.4
 /// +---------------+---------------+
@@ -711,10 +726,16 @@ impl Default for H265Payload {
 }
 
 /// Depacketizes H265 RTP packets.
+///
+/// Handles single NAL unit packets, aggregation packets (AP), fragmentation units (FU)
+/// and PACI packets as described in [RFC 7798](https://datatracker.ietf.org/doc/html/rfc7798).
+/// The NAL units are output with Annex B start codes.
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct H265Depacketizer {
     payload: H265Payload,
     might_need_donl: bool,
+    /// FU fragments of the NAL unit being reassembled, starting with the NAL header.
+    fu_buffer: Option<Vec<u8>>,
 }
 
 impl H265Depacketizer {
@@ -733,6 +754,36 @@ impl H265Depacketizer {
     pub fn payload(&self) -> &H265Payload {
         &self.payload
     }
+
+    /// Write one complete NAL unit, including its header, with a start code.
+    fn emit_nalu(header: H265NALUHeader, data: &[u8], out: &mut Vec<u8>, extra: &mut CodecExtra) {
+        // An IRAP picture, or the parameter sets sent ahead of it, makes the frame a keyframe.
+        let t = header.nalu_type();
+        let is_keyframe = matches!(
+            t,
+            16..=23 | H265NALU_VPS_TYPE | H265NALU_SPS_TYPE | H265NALU_PPS_TYPE
+        );
+        let is_keyframe = if let CodecExtra::H265(e) = extra {
+            is_keyframe | e.is_keyframe
+        } else {
+            is_keyframe
+        };
+        *extra = CodecExtra::H265(H265CodecExtra { is_keyframe });
+
+        out.extend_from_slice(ANNEXB_NALUSTART_CODE);
+        out.extend_from_slice(&header.0.to_be_bytes());
+        out.extend_from_slice(data);
+    }
+
+    /// Write the NAL unit of an aggregation unit, which starts with its own header.
+    fn emit_aggregated(nal_unit: &[u8], out: &mut Vec<u8>, extra: &mut CodecExtra) {
+        if nal_unit.len() < H265NALU_HEADER_SIZE {
+            trace!("Discard aggregated NAL unit without header");
+            return;
+        }
+        let header = H265NALUHeader::new(nal_unit[0], nal_unit[1]);
+        Self::emit_nalu(header, &nal_unit[H265NALU_HEADER_SIZE..], out, extra);
+    }
 }
 
 impl Depacketizer for H265Depacketizer {
@@ -741,7 +792,7 @@ impl Depacketizer for H265Depacketizer {
         &mut self,
         packet: &[u8],
         out: &mut Vec<u8>,
-        _: &mut CodecExtra,
+        extra: &mut CodecExtra,
     ) -> Result<(), PacketError> {
         if packet.len() <= H265NALU_HEADER_SIZE {
             return Err(PacketError::ErrShortPacket);
@@ -752,10 +803,30 @@ impl Depacketizer for H265Depacketizer {
             return Err(PacketError::ErrH265CorruptedPacket);
         }
 
+        if !header.is_fragmentation_unit() && self.fu_buffer.take().is_some() {
+            // No other packet can come between the fragments of a NAL unit
+            // when DONL is absent. The end fragment is lost.
+            trace!(
+                "Discard incomplete FU before NALU type: {}",
+                header.nalu_type()
+            );
+        }
+
         if header.is_paci_packet() {
             let mut decoded = H265PACIPacket::default();
             decoded.depacketize(packet)?;
 
+            let ctype = decoded.ctype();
+            if ctype < H265NALU_AGGREGATION_PACKET_TYPE {
+                // The contained NAL unit header is the PACI header with the A bit as F
+                // and cType as the type.
+                let a = (decoded.a() as u16) << 15;
+                let inner = H265NALUHeader(a | (ctype as u16) << 9 | (header.0 & 0x01ff));
+                Self::emit_nalu(inner, &decoded.payload, out, extra);
+            } else {
+                trace!("Discard PACI packet containing type: {}", ctype);
+            }
+
             self.payload = H265Payload::H265PACIPacket(decoded);
         } else if header.is_fragmentation_unit() {
             let mut decoded = H265FragmentationUnitPacket::default();
@@ -763,6 +834,39 @@ impl Depacketizer for H265Depacketizer {
 
             decoded.depacketize(packet)?;
 
+            let fu_header = decoded.fu_header();
+            let fu_type = fu_header.fu_type();
+
+            if fu_header.s() {
+                if self.fu_buffer.is_some() {
+                    trace!("Discard incomplete FU before new start fragment");
+                }
+                // The NAL header is the payload header with the type from the FU header.
+                let nalu_header = (header.0 & 0x81ff) | (fu_type as u16) << 9;
+                self.fu_buffer = Some(nalu_header.to_be_bytes().to_vec());
+            }
+
+            if let Some(fu_buffer) = &mut self.fu_buffer {
+                let buffered = H265NALUHeader::new(fu_buffer[0], fu_buffer[1]);
+                if buffered.nalu_type() == fu_type {
+                    fu_buffer.extend_from_slice(&decoded.payload);
+                } else {
+                    // Fragments of another NAL unit, the ones in between are lost.
+                    trace!("Discard FU fragment of mismatching NALU type");
+                    self.fu_buffer = None;
+                }
+            } else {
+                // Without the start fragment, the NAL unit can't be reconstructed.
+                trace!("Discard FU fragment without start fragment");
+            }
+
+            if fu_header.e() {
+                if let Some(nalu) = self.fu_buffer.take() {
+                    let header = H265NALUHeader::new(nalu[0], nalu[1]);
+                    Self::emit_nalu(header, &nalu[H265NALU_HEADER_SIZE..], out, extra);
+                }
+            }
+
             self.payload = H265Payload::H265FragmentationUnitPacket(decoded);
         } else if header.is_aggregation_packet() {
             let mut decoded = H265AggregationPacket::default();
@@ -770,6 +874,13 @@ impl Depacketizer for H265Depacketizer {
 
             decoded.depacketize(packet)?;
 
+            if let Some(first) = &decoded.first_unit {
+                Self::emit_aggregated(&first.nal_unit, out, extra);
+            }
+            for unit in &decoded.other_units {
+                Self::emit_aggregated(&unit.nal_unit, out, extra);
+            }
+
             self.payload = H265Payload::H265AggregationPacket(decoded);
         } else {
             let mut decoded = H265SingleNALUnitPacket::default();
@@ -777,23 +888,35 @@ impl Depacketizer for H265Depacketizer {
 
             decoded.depacketize(packet)?;
 
+            Self::emit_nalu(header, &decoded.payload, out, extra);
+
             self.payload = H265Payload::H265SingleNALUnitPacket(decoded);
         }
 
-        out.extend_from_slice(packet);
-
         Ok(())
     }
 
     /// is_partition_head checks if this is the head of a packetized nalu stream.
-    fn is_partition_head(&self, _payload: &[u8]) -> bool {
-        //TODO:
-        true
+    fn is_partition_head(&self, payload: &[u8]) -> bool {
+        if payload.len() <= H265NALU_HEADER_SIZE {
+            return false;
+        }
+
+        let header = H265NALUHeader::new(payload[0], payload[1]);
+        if header.is_fragmentation_unit() {
+            H265FragmentationUnitHeader(payload[2]).s()
+        } else {
+            true
+        }
     }
 
     fn is_partition_tail(&self, marker: bool, _payload: &[u8]) -> bool {
         marker
     }
+
+    fn reset(&mut self) {
+        self.fu_buffer = None;
+    }
 }
 
 #[cfg(test)]
@@ -1674,6 +1797,149 @@ mod test {
         Ok(())
     }
 
+    const VPS: &[u8] = &[
+        0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00,
+        0x03, 0x00, 0x00, 0x03, 0x00, 0x7b, 0xac, 0x09,
+    ];
+    const SPS: &[u8] = &[
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x7b, 0xa0, 0x03, 0xc0, 0x80, 0x10, 0xe5, 0x8d, 0xae, 0x49, 0x32, 0xf4, 0xdc,
+        0x04, 0x04, 0x04, 0x02,
+    ];
+    const PPS: &[u8] = &[0x44, 0x01, 0xc0, 0xf2, 0xf0, 0x3c, 0x90];
+
+    fn annexb(nalus: &[&[u8]]) -> Vec<u8> {
+        let mut v = vec![];
+        for n in nalus {
+            v.extend_from_slice(ANNEXB_NALUSTART_CODE);
+            v.extend_from_slice(n);
+        }
+        v
+    }
+
+    #[test]
+    fn test_h265_depacketize_ap_parameter_sets() -> Result<()> {
+        let mut ap = vec![0x60, 0x01];
+        for n in [VPS, SPS, PPS] {
+            ap.extend_from_slice(&(n.len() as u16).to_be_bytes());
+            ap.extend_from_slice(n);
+        }
+
+        let mut pck = H265Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+        pck.depacketize(&ap, &mut out, &mut extra)?;
+
+        assert_eq!(out, annexb(&[VPS, SPS, PPS]));
+        assert_eq!(
+            extra,
+            CodecExtra::H265(H265CodecExtra { is_keyframe: true })
+        );
+
+        // Same AP with DONL on the first unit and DOND on the following.
+        let mut ap = vec![0x60, 0x01, 0x00, 0x10];
+        for (i, n) in [VPS, SPS, PPS].into_iter().enumerate() {
+            if i > 0 {
+                ap.push(0x00);
+            }
+            ap.extend_from_slice(&(n.len() as u16).to_be_bytes());
+            ap.extend_from_slice(n);
+        }
+
+        let mut pck = H265Depacketizer::default();
+        pck.with_donl(true);
+        let mut out = vec![];
+        pck.depacketize(&ap, &mut out, &mut extra)?;
+        assert_eq!(out, annexb(&[VPS, SPS, PPS]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h265_depacketize_fu() -> Result<()> {
+        let mut pck = H265Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+
+        // IDR_W_RADL (19) fragmented in three.
+        pck.depacketize(&[0x62, 0x01, 0x93, 0xaf, 0x0d], &mut out, &mut extra)?;
+        pck.depacketize(&[0x62, 0x01, 0x13, 0x5a], &mut out, &mut extra)?;
+        assert!(out.is_empty());
+        pck.depacketize(&[0x62, 0x01, 0x53, 0xfe, 0x67], &mut out, &mut extra)?;
+
+        assert_eq!(out, annexb(&[&[0x26, 0x01, 0xaf, 0x0d, 0x5a, 0xfe, 0x67]]));
+        assert_eq!(
+            extra,
+            CodecExtra::H265(H265CodecExtra { is_keyframe: true })
+        );
+
+        // With DONL, only present in the start fragment.
+        let mut pck = H265Depacketizer::default();
+        pck.with_donl(true);
+        let mut out = vec![];
+        pck.depacketize(&[0x62, 0x01, 0x81, 0x00, 0x07, 0xaa], &mut out, &mut extra)?;
+        pck.depacketize(&[0x62, 0x01, 0x41, 0xbb], &mut out, &mut extra)?;
+        assert_eq!(out, annexb(&[&[0x02, 0x01, 0xaa, 0xbb]]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h265_depacketize_orphan_fu() -> Result<()> {
+        let mut pck = H265Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+
+        // Middle and end fragments of an IDR whose start fragment was lost.
+        pck.depacketize(&[0x62, 0x01, 0x13, 0x5a, 0x01], &mut out, &mut extra)?;
+        pck.depacketize(&[0x62, 0x01, 0x53, 0xfe, 0x67], &mut out, &mut extra)?;
+        assert!(out.is_empty());
+        assert_eq!(extra, CodecExtra::None);
+
+        // A start fragment whose end is lost, followed by a single NAL unit.
+        pck.depacketize(&[0x62, 0x01, 0x93, 0xaf, 0x0d], &mut out, &mut extra)?;
+        pck.depacketize(&[0x02, 0x01, 0xd0, 0x22], &mut out, &mut extra)?;
+
+        // The next frame is unaffected.
+        pck.depacketize(&[0x62, 0x01, 0x81, 0x01, 0x02], &mut out, &mut extra)?;
+        pck.depacketize(&[0x62, 0x01, 0x41, 0x03], &mut out, &mut extra)?;
+
+        assert_eq!(
+            out,
+            annexb(&[&[0x02, 0x01, 0xd0, 0x22], &[0x02, 0x01, 0x01, 0x02, 0x03]])
+        );
+        assert_eq!(
+            extra,
+            CodecExtra::H265(H265CodecExtra { is_keyframe: false })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h265_depacketize_single_nalu_donl() -> Result<()> {
+        let mut pck = H265Depacketizer::default();
+        pck.with_donl(true);
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+
+        pck.depacketize(&[0x02, 0x01, 0x00, 0x05, 0xd0, 0x22], &mut out, &mut extra)?;
+        assert_eq!(out, annexb(&[&[0x02, 0x01, 0xd0, 0x22]]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_h265_partition_head() {
+        let pck = H265Depacketizer::default();
+        assert!(!pck.is_partition_head(&[0x02, 0x01]));
+        assert!(pck.is_partition_head(&[0x02, 0x01, 0xd0]));
+        assert!(pck.is_partition_head(&[0x60, 0x01, 0x00]));
+        assert!(pck.is_partition_head(&[0x62, 0x01, 0x93]));
+        assert!(!pck.is_partition_head(&[0x62, 0x01, 0x13]));
+        assert!(!pck.is_partition_head(&[0x62, 0x01, 0x53]));
+    }
+
     #[test]
     fn test_h265_packet_real() -> Result<()> {
         // Tests decoding of real H265 payloads extracted from a Wireshark dump.
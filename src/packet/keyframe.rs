@@ -0,0 +1,377 @@
+use crate::format::Codec;
+
+use super::{Vp8Meta, Vp9Meta};
+
+/// Tells whether an RTP payload is the first packet of a keyframe.
+///
+/// This is a lightweight check of a single packet without a depacketizer, meant for
+/// forwarding paths such as layer switching or keyframe request limiting.
+///
+/// * VP8: start of partition 0 with the P bit cleared and the keyframe start code.
+/// * VP9: start of a frame in spatial layer 0, not inter predicted, with a keyframe
+///   uncompressed header and sync code.
+/// * H264: an SPS or IDR NAL unit, single, aggregated (STAP-A) or starting a FU-A.
+/// * H265: a VPS, SPS or IRAP NAL unit, single, aggregated (AP) or starting a FU.
+/// * AV1: a packet starting a new OBU with the N bit set, or starting with a sequence
+///   header OBU.
+///
+/// The check is biased towards false negatives. Any malformed structure, such as lengths
+/// running past the payload or forbidden bits set, makes this return `false`. Codecs
+/// without keyframes always return `false`.
+pub fn is_keyframe_start(codec: Codec, payload: &[u8]) -> bool {
+    match codec {
+        Codec::Vp8 => is_vp8_keyframe_start(payload),
+        Codec::Vp9 => is_vp9_keyframe_start(payload),
+        Codec::H264 => is_h264_keyframe_start(payload),
+        Codec::H265 => is_h265_keyframe_start(payload),
+        Codec::Av1 => is_av1_keyframe_start(payload),
+        _ => false,
+    }
+}
+
+fn is_vp8_keyframe_start(payload: &[u8]) -> bool {
+    let Ok((meta, frame)) = Vp8Meta::parse(payload) else {
+        return false;
+    };
+
+    // The 3 byte frame tag of a keyframe is followed by the start code.
+    meta.is_keyframe && frame.len() >= 6 && frame[3..6] == [0x9d, 0x01, 0x2a]
+}
+
+fn is_vp9_keyframe_start(payload: &[u8]) -> bool {
+    let Ok((meta, frame)) = Vp9Meta::parse(payload) else {
+        return false;
+    };
+
+    if !meta.is_keyframe || frame.len() < 5 {
+        return false;
+    }
+
+    // Uncompressed header, bits are read from the most significant.
+    let bits = u64::from_be_bytes([frame[0], frame[1], frame[2], frame[3], frame[4], 0, 0, 0]);
+    let mut pos = 0;
+    let mut read = |n: u32| {
+        let v = (bits << pos) >> (64 - n);
+        pos += n;
+        v
+    };
+
+    // frame_marker
+    if read(2) != 0b10 {
+        return false;
+    }
+
+    let profile_low = read(1);
+    let profile = read(1) << 1 | profile_low;
+
+    // reserved_zero
+    if profile == 3 && read(1) != 0 {
+        return false;
+    }
+
+    // show_existing_frame, frame_type (0 is KEY_FRAME)
+    if read(1) != 0 || read(1) != 0 {
+        return false;
+    }
+
+    // show_frame, error_resilient_mode
+    read(2);
+
+    // frame_sync_code
+    read(24) == 0x49_83_42
+}
+
+const H264_IDR: u8 = 5;
+const H264_SPS: u8 = 7;
+const H264_STAP_A: u8 = 24;
+const H264_FU_A: u8 = 28;
+
+fn is_h264_keyframe_start(payload: &[u8]) -> bool {
+    let [header, rest @ ..] = payload else {
+        return false;
+    };
+
+    // forbidden_zero_bit
+    if header & 0x80 > 0 || rest.is_empty() {
+        return false;
+    }
+
+    let is_key = |t: u8| t == H264_IDR || t == H264_SPS;
+
+    match header & 0x1f {
+        H264_STAP_A => {
+            let mut rest = rest;
+            let mut found = false;
+            while !rest.is_empty() {
+                let [l0, l1, nalus @ ..] = rest else {
+                    return false;
+                };
+                let len = u16::from_be_bytes([*l0, *l1]) as usize;
+                if len == 0 || len > nalus.len() || nalus[0] & 0x80 > 0 {
+                    return false;
+                }
+                found |= is_key(nalus[0] & 0x1f);
+                rest = &nalus[len..];
+            }
+            found
+        }
+        H264_FU_A => {
+            let fu_header = rest[0];
+            // S bit and not also E bit, the start of a NAL unit.
+            fu_header & 0x80 > 0 && fu_header & 0x40 == 0 && is_key(fu_header & 0x1f)
+        }
+        t => is_key(t),
+    }
+}
+
+const H265_VPS: u8 = 32;
+const H265_SPS: u8 = 33;
+const H265_AP: u8 = 48;
+const H265_FU: u8 = 49;
+
+/// Type of a valid H265 NAL unit header.
+fn h265_nal_type(header: &[u8]) -> Option<u8> {
+    let [b0, b1, ..] = header else {
+        return None;
+    };
+
+    // forbidden_zero_bit, and nuh_temporal_id_plus1 can't be 0.
+    if b0 & 0x80 > 0 || b1 & 0x07 == 0 {
+        return None;
+    }
+
+    Some((b0 >> 1) & 0x3f)
+}
+
+fn is_h265_keyframe_start(payload: &[u8]) -> bool {
+    // IRAP pictures are types 16-23.
+    let is_key = |t: u8| (16..=23).contains(&t) || t == H265_VPS || t == H265_SPS;
+
+    let Some(nal_type) = h265_nal_type(payload) else {
+        return false;
+    };
+
+    let rest = &payload[2..];
+
+    if rest.is_empty() {
+        return false;
+    }
+
+    match nal_type {
+        H265_AP => {
+            let mut rest = rest;
+            let mut found = false;
+            while !rest.is_empty() {
+                let [l0, l1, nalus @ ..] = rest else {
+                    return false;
+                };
+                let len = u16::from_be_bytes([*l0, *l1]) as usize;
+                if len > nalus.len() {
+                    return false;
+                }
+                let Some(t) = h265_nal_type(&nalus[..len]) else {
+                    return false;
+                };
+                found |= is_key(t);
+                rest = &nalus[len..];
+            }
+            found
+        }
+        H265_FU => {
+            let fu_header = rest[0];
+            fu_header & 0x80 > 0 && fu_header & 0x40 == 0 && is_key(fu_header & 0x3f)
+        }
+        t => is_key(t),
+    }
+}
+
+const AV1_OBU_SEQUENCE_HEADER: u8 = 1;
+
+fn is_av1_keyframe_start(payload: &[u8]) -> bool {
+    //  0 1 2 3 4 5 6 7
+    // +-+-+-+-+-+-+-+-+
+    // |Z|Y| W |N|-|-|-|
+    // +-+-+-+-+-+-+-+-+
+    let [agg, rest @ ..] = payload else {
+        return false;
+    };
+
+    // A continued OBU can't start a keyframe.
+    if agg & 0x80 > 0 {
+        return false;
+    }
+
+    let n = agg & 0x08 > 0;
+    let w = (agg >> 4) & 0x03;
+
+    // The first OBU element, with a length unless it's the only one.
+    let obu = if w == 1 {
+        rest
+    } else {
+        // At most 8 bytes leb128.
+        let Some(n) = rest.iter().take(8).position(|b| b & 0x80 == 0) else {
+            return false;
+        };
+        let len = rest[..=n]
+            .iter()
+            .rev()
+            .fold(0_u64, |acc, b| acc << 7 | (b & 0x7f) as u64);
+        let rest = &rest[n + 1..];
+        if len == 0 || len > rest.len() as u64 {
+            return false;
+        }
+        &rest[..len as usize]
+    };
+
+    // OBU header with forbidden bit.
+    let Some(&header) = obu.first() else {
+        return false;
+    };
+    if header & 0x80 > 0 {
+        return false;
+    }
+
+    // A new coded video sequence starts with a sequence header.
+    let is_sequence_header = (header >> 3) & 0x0f == AV1_OBU_SEQUENCE_HEADER;
+
+    is_sequence_header || n
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VP8_KEY: &[u8] = &[
+        0x90, 0x80, 0x2a, 0x50, 0x42, 0x00, 0x9d, 0x01, 0x2a, 0x80, 0x02, 0xe0, 0x01,
+    ];
+    const VP8_DELTA: &[u8] = &[0x90, 0x80, 0x2b, 0x31, 0x42, 0x00, 0x9d, 0x01, 0x2a];
+
+    // Profile 0 keyframe, not flexible mode.
+    const VP9_KEY: &[u8] = &[0x8c, 0x80, 0x2a, 0x82, 0x49, 0x83, 0x42, 0x00, 0x13];
+    const VP9_DELTA: &[u8] = &[0xcc, 0x80, 0x2b, 0x86, 0x00, 0x40, 0x92, 0xe0];
+
+    const H264_SPS_SINGLE: &[u8] = &[0x67, 0x42, 0xc0, 0x1f];
+    const H264_STAP_A: &[u8] = &[
+        0x78, 0x00, 0x04, 0x67, 0x42, 0xc0, 0x1f, 0x00, 0x02, 0x68, 0xce,
+    ];
+    const H264_FU_A_IDR_START: &[u8] = &[0x7c, 0x85, 0x88, 0x84];
+    const H264_NON_IDR: &[u8] = &[0x41, 0x9a, 0x02];
+
+    const H265_IDR: &[u8] = &[0x26, 0x01, 0xaf, 0x06];
+    const H265_CRA_FU_START: &[u8] = &[0x62, 0x01, 0x95, 0xaf, 0x06];
+    const H265_AP: &[u8] = &[
+        0x60, 0x01, 0x00, 0x03, 0x40, 0x01, 0x0c, 0x00, 0x03, 0x42, 0x01, 0x01,
+    ];
+    const H265_TRAIL: &[u8] = &[0x02, 0x01, 0xd0, 0x08];
+
+    // W=2, sequence header then a frame.
+    const AV1_KEY: &[u8] = &[0x28, 0x03, 0x08, 0x00, 0x00, 0x32, 0x10, 0x01];
+    const AV1_DELTA: &[u8] = &[0x10, 0x32, 0x10, 0x01];
+
+    #[test]
+    fn keyframes() {
+        assert!(is_keyframe_start(Codec::Vp8, VP8_KEY));
+        assert!(is_keyframe_start(Codec::Vp9, VP9_KEY));
+        assert!(is_keyframe_start(Codec::H264, H264_SPS_SINGLE));
+        assert!(is_keyframe_start(Codec::H264, H264_STAP_A));
+        assert!(is_keyframe_start(Codec::H264, H264_FU_A_IDR_START));
+        assert!(is_keyframe_start(Codec::H265, H265_IDR));
+        assert!(is_keyframe_start(Codec::H265, H265_CRA_FU_START));
+        assert!(is_keyframe_start(Codec::H265, H265_AP));
+        assert!(is_keyframe_start(Codec::Av1, AV1_KEY));
+    }
+
+    #[test]
+    fn not_keyframes() {
+        assert!(!is_keyframe_start(Codec::Vp8, VP8_DELTA));
+        assert!(!is_keyframe_start(Codec::Vp9, VP9_DELTA));
+        assert!(!is_keyframe_start(Codec::H264, H264_NON_IDR));
+        assert!(!is_keyframe_start(Codec::H265, H265_TRAIL));
+        assert!(!is_keyframe_start(Codec::Av1, AV1_DELTA));
+
+        // Middle of a FU-A.
+        assert!(!is_keyframe_start(Codec::H264, &[0x7c, 0x05, 0x88]));
+
+        // Continued OBU.
+        assert!(!is_keyframe_start(Codec::Av1, &[0x98, 0x08, 0x00]));
+
+        // Codecs without keyframes.
+        assert!(!is_keyframe_start(Codec::Opus, VP8_KEY));
+        assert!(!is_keyframe_start(Codec::Generic, H264_SPS_SINGLE));
+    }
+
+    fn corrupt(payload: &[u8], index: usize, value: u8) -> Vec<u8> {
+        let mut v = payload.to_vec();
+        v[index] = value;
+        v
+    }
+
+    #[test]
+    fn corrupt_payloads_are_not_keyframes() {
+        // VP8 broken start code.
+        assert!(!is_keyframe_start(Codec::Vp8, &corrupt(VP8_KEY, 7, 0x00)));
+        // VP8 truncated before the start code.
+        assert!(!is_keyframe_start(Codec::Vp8, &VP8_KEY[..6]));
+        // VP8 descriptor claiming extensions past the payload.
+        assert!(!is_keyframe_start(Codec::Vp8, &[0x90, 0xf0]));
+
+        // VP9 broken frame marker.
+        assert!(!is_keyframe_start(Codec::Vp9, &corrupt(VP9_KEY, 3, 0x42)));
+        // VP9 broken sync code.
+        assert!(!is_keyframe_start(Codec::Vp9, &corrupt(VP9_KEY, 5, 0x00)));
+        // VP9 truncated.
+        assert!(!is_keyframe_start(Codec::Vp9, &VP9_KEY[..6]));
+
+        // H264 forbidden bit.
+        assert!(!is_keyframe_start(
+            Codec::H264,
+            &corrupt(H264_SPS_SINGLE, 0, 0xe7)
+        ));
+        // H264 NAL header only.
+        assert!(!is_keyframe_start(Codec::H264, &H264_SPS_SINGLE[..1]));
+        // H264 STAP-A length past the payload.
+        assert!(!is_keyframe_start(
+            Codec::H264,
+            &corrupt(H264_STAP_A, 8, 0x09)
+        ));
+        // H264 STAP-A truncated length.
+        assert!(!is_keyframe_start(Codec::H264, &H264_STAP_A[..8]));
+        // H264 FU-A with both start and end.
+        assert!(!is_keyframe_start(
+            Codec::H264,
+            &corrupt(H264_FU_A_IDR_START, 1, 0xc5)
+        ));
+
+        // H265 forbidden bit.
+        assert!(!is_keyframe_start(Codec::H265, &corrupt(H265_IDR, 0, 0xa6)));
+        // H265 temporal id 0.
+        assert!(!is_keyframe_start(Codec::H265, &corrupt(H265_IDR, 1, 0x00)));
+        // H265 AP length past the payload.
+        assert!(!is_keyframe_start(Codec::H265, &corrupt(H265_AP, 8, 0x07)));
+        // H265 AP unit with a broken header.
+        assert!(!is_keyframe_start(Codec::H265, &corrupt(H265_AP, 5, 0x00)));
+
+        // AV1 unterminated leb128.
+        assert!(!is_keyframe_start(Codec::Av1, &[0x28, 0x80, 0x80]));
+        // AV1 element length past the payload.
+        assert!(!is_keyframe_start(Codec::Av1, &corrupt(AV1_KEY, 1, 0x10)));
+        // AV1 OBU forbidden bit.
+        assert!(!is_keyframe_start(Codec::Av1, &corrupt(AV1_KEY, 2, 0x88)));
+
+        // Empty payloads.
+        for codec in [Codec::Vp8, Codec::Vp9, Codec::H264, Codec::H265, Codec::Av1] {
+            assert!(!is_keyframe_start(codec, &[]));
+        }
+    }
+
+    #[test]
+    fn truncated_keyframes_are_not_keyframes() {
+        // The frame header must be complete up to the start or sync code.
+        for len in 0..VP8_KEY.len().min(9) {
+            assert!(!is_keyframe_start(Codec::Vp8, &VP8_KEY[..len]));
+        }
+        for len in 0..VP9_KEY.len().min(7) {
+            assert!(!is_keyframe_start(Codec::Vp9, &VP9_KEY[..len]));
+        }
+    }
+}
@@ -8,6 +8,32 @@ use thiserror::Error;
 use crate::format::Codec;
 use crate::sdp::MediaType;
 
+mod av1;
+pub use av1::Av1CodecExtra;
+
+mod av1_selector;
+use av1::{Av1Depacketizer, Av1Packetizer};
+pub use av1_selector::{Av1Decision, Av1Selector};
+
+mod generic;
+use generic::{GenericDepacketizer, GenericPacketizer};
+
+mod dtmf;
+pub use dtmf::{DtmfDecoder, DtmfEncoder, DtmfEvent, DtmfPacket};
+
+mod frame_assembler;
+pub use frame_assembler::{AssemblerOutput, Frame, FrameAssembler};
+
+mod keyframe;
+pub use keyframe::is_keyframe_start;
+
+mod red;
+pub(crate) use red::parse_red;
+pub use red::{RedDecoder, RedEncoder, RedFrame};
+
+mod registry;
+pub use registry::{CodecKey, CodecRegistry};
+
 mod g7xx;
 use g7xx::{G711Packetizer, G722Packetizer};
 
@@ -16,21 +42,27 @@ pub use h264::H264CodecExtra;
 use h264::{H264Depacketizer, H264Packetizer};
 
 mod h264_profile;
-pub(crate) use h264_profile::H264ProfileLevel;
+pub(crate) use h264_profile::{answer_profile_level_id, H264ProfileLevel};
 
 mod h265;
+pub use h265::H265CodecExtra;
 use h265::H265Depacketizer;
 
 mod opus;
 use opus::{OpusDepacketizer, OpusPacketizer};
+pub use opus::{OpusFecPolicy, OpusGap, OpusMeta, OpusMode};
 
 mod vp8;
-pub use vp8::Vp8CodecExtra;
-use vp8::{Vp8Depacketizer, Vp8Packetizer};
+use vp8::Vp8Depacketizer;
+pub use vp8::{Vp8CodecExtra, Vp8Meta, Vp8Packetizer};
 
 mod vp9;
-pub use vp9::Vp9CodecExtra;
+pub use vp9::{Vp9CodecExtra, Vp9FrameInfo, Vp9LayerFrame, Vp9Meta};
 use vp9::{Vp9Depacketizer, Vp9Packetizer};
+pub use vp9::{Vp9PictureGroupEntry, Vp9ScalabilityStructure};
+
+mod vp9_selector;
+pub use vp9_selector::{Vp9LayerSelector, Vp9Selection};
 
 mod null;
 use null::{NullDepacketizer, NullPacketizer};
@@ -45,12 +77,33 @@ mod payload;
 pub(crate) use payload::Payloader;
 
 mod bwe;
-pub(crate) use bwe::SendSideBandwithEstimator;
+pub(crate) use bwe::{ReceiveSideBandwithEstimator, SendSideBandwithEstimator};
+
+mod budget;
+pub(crate) use budget::{SendBudget, SendClass, SendTotals};
+
+mod egress_rate;
+pub(crate) use egress_rate::EgressRates;
 
 mod pacer;
 pub(crate) use pacer::{LeakyBucketPacer, NullPacer, Pacer, PacerImpl};
 pub(crate) use pacer::{QueuePriority, QueueSnapshot, QueueState};
 
+mod paced_sender;
+pub use paced_sender::{PacedQueueStats, PacedSender, PacerQueueOverflow, PacketPriority};
+
+mod allocator;
+pub use allocator::{AllocationChanged, Allocator, StreamBitrates};
+
+mod speaker;
+pub use speaker::{AudioLevelAggregator, SpeakerRanking};
+
+mod source_switcher;
+pub use source_switcher::{SourceSwitcher, SwitchOutput};
+
+mod sync;
+pub use sync::{SyncGroups, SyncOffset};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 /// Types of media.
 pub enum MediaKind {
@@ -73,11 +126,35 @@ impl MediaKind {
 }
 
 /// Packetizes some bytes for use as RTP packet.
-pub(crate) trait Packetizer: fmt::Debug {
+///
+/// One instance is used per outgoing stream, which means it can keep state between
+/// frames. Custom implementations are added using [`CodecRegistry`].
+pub trait Packetizer: fmt::Debug {
     /// Chunk the data up into RTP packets.
     fn packetize(&mut self, mtu: usize, b: &[u8]) -> Result<Vec<Vec<u8>>, PacketError>;
 
+    /// Chunk the data up into RTP packets using information about the frame from the
+    /// encoder.
+    ///
+    /// Defaults to [`Packetizer::packetize`], ignoring the information.
+    fn packetize_frame(
+        &mut self,
+        mtu: usize,
+        b: &[u8],
+        info: &CodecFrameInfo,
+    ) -> Result<Vec<Vec<u8>>, PacketError> {
+        let _ = info;
+        self.packetize(mtu, b)
+    }
+
+    /// Whether the RTP marker bit is set for a packet.
+    ///
+    /// `previous` is the previous packet sent on the stream, and `last` is true for the
+    /// last packet of a frame.
     fn is_marker(&mut self, data: &[u8], previous: Option<&[u8]>, last: bool) -> bool;
+
+    /// Reset any state kept between frames.
+    fn reset(&mut self) {}
 }
 
 /// Codec specific information
@@ -94,12 +171,29 @@ pub enum CodecExtra {
     Vp9(Vp9CodecExtra),
     /// Codec extra parameters for H264.
     H264(H264CodecExtra),
+    /// Codec extra parameters for H265.
+    H265(H265CodecExtra),
+    /// Codec extra parameters for AV1.
+    Av1(Av1CodecExtra),
+}
+
+/// Codec specific information about a frame, given by the encoder when writing.
+///
+/// This is the sending side counterpart of [`CodecExtra`], set with
+/// [`Writer::codec_frame_info`][crate::media::Writer::codec_frame_info].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CodecFrameInfo {
+    /// Spatial and temporal layers of a VP9 picture.
+    Vp9(Vp9FrameInfo),
 }
 
 /// Depacketizes an RTP payload.
 ///
-/// Removes any RTP specific data from the payload.
-pub(crate) trait Depacketizer: fmt::Debug {
+/// Removes any RTP specific data from the payload. One instance is used per incoming
+/// stream, which means it can keep state between packets, such as fragments being
+/// reassembled. Custom implementations are added using [`CodecRegistry`].
+pub trait Depacketizer: fmt::Debug {
     /// Unpack the RTP packet into a provided `Vec<u8>`.
     fn depacketize(
         &mut self,
@@ -117,6 +211,11 @@ pub(crate) trait Depacketizer: fmt::Debug {
     ///
     /// Returns false if the result could not be determined.
     fn is_partition_tail(&self, marker: bool, packet: &[u8]) -> bool;
+
+    /// Reset any state kept between packets.
+    ///
+    /// Called when the incoming stream restarts.
+    fn reset(&mut self) {}
 }
 
 /// Errors arising in packet- and depacketization.
@@ -137,8 +236,20 @@ pub enum PacketError {
     StapASizeLargerThanBuffer(usize, usize),
     #[error("H264 NALU type is not handled: {0}")]
     NaluTypeIsNotHandled(u8),
+    #[error("H264 NALU larger than MTU in single NAL unit mode: {0} > {1}")]
+    NaluLargerThanMtu(usize, usize),
     #[error("VP9 corrupted packet")]
     ErrVP9CorruptedPacket,
+    #[error("AV1 corrupted packet")]
+    ErrAv1CorruptedPacket,
+    #[error("VP9 frame info does not match the frame")]
+    ErrVp9InvalidFrameInfo,
+    #[error("RED corrupted packet")]
+    ErrRedCorruptedPacket,
+    #[error("Opus corrupted packet")]
+    ErrOpusCorruptedPacket,
+    #[error("Not a DTMF digit: {0}")]
+    ErrDtmfInvalidDigit(char),
 }
 
 /// Helper to replace Bytes. Provides get_u8 and get_u16 over some buffer of bytes.
@@ -191,6 +302,8 @@ pub(crate) enum CodecPacketizer {
     Opus(OpusPacketizer),
     Vp8(Vp8Packetizer),
     Vp9(Vp9Packetizer),
+    Av1(Av1Packetizer),
+    Generic(GenericPacketizer),
     Null(NullPacketizer),
     Boxed(Box<dyn Packetizer + Send + Sync + UnwindSafe>),
 }
@@ -202,6 +315,8 @@ pub(crate) enum CodecDepacketizer {
     Opus(OpusDepacketizer),
     Vp8(Vp8Depacketizer),
     Vp9(Vp9Depacketizer),
+    Av1(Av1Depacketizer),
+    Generic(GenericDepacketizer),
     Null(NullDepacketizer),
     Boxed(Box<dyn Depacketizer + Send + Sync + UnwindSafe>),
 }
@@ -214,7 +329,8 @@ impl From<Codec> for CodecPacketizer {
             Codec::H265 => unimplemented!("Missing packetizer for H265"),
             Codec::Vp8 => CodecPacketizer::Vp8(Vp8Packetizer::default()),
             Codec::Vp9 => CodecPacketizer::Vp9(Vp9Packetizer::default()),
-            Codec::Av1 => unimplemented!("Missing packetizer for AV1"),
+            Codec::Av1 => CodecPacketizer::Av1(Av1Packetizer),
+            Codec::Generic => CodecPacketizer::Generic(GenericPacketizer),
             Codec::Null => CodecPacketizer::Null(NullPacketizer),
             Codec::Rtx => panic!("Cant instantiate packetizer for RTX codec"),
             Codec::Unknown => panic!("Cant instantiate packetizer for unknown codec"),
@@ -231,7 +347,8 @@ impl From<Codec> for CodecDepacketizer {
             Codec::H265 => CodecDepacketizer::H265(H265Depacketizer::default()),
             Codec::Vp8 => CodecDepacketizer::Vp8(Vp8Depacketizer::default()),
             Codec::Vp9 => CodecDepacketizer::Vp9(Vp9Depacketizer::default()),
-            Codec::Av1 => unimplemented!("Missing depacketizer for AV1"),
+            Codec::Av1 => CodecDepacketizer::Av1(Av1Depacketizer::default()),
+            Codec::Generic => CodecDepacketizer::Generic(GenericDepacketizer),
             Codec::Null => CodecDepacketizer::Null(NullDepacketizer),
             Codec::Rtx => panic!("Cant instantiate depacketizer for RTX codec"),
             Codec::Unknown => panic!("Cant instantiate depacketizer for unknown codec"),
@@ -250,11 +367,34 @@ impl Packetizer for CodecPacketizer {
             Opus(v) => v.packetize(mtu, b),
             Vp8(v) => v.packetize(mtu, b),
             Vp9(v) => v.packetize(mtu, b),
+            Av1(v) => v.packetize(mtu, b),
+            Generic(v) => v.packetize(mtu, b),
             Null(v) => v.packetize(mtu, b),
             Boxed(v) => v.packetize(mtu, b),
         }
     }
 
+    fn packetize_frame(
+        &mut self,
+        mtu: usize,
+        b: &[u8],
+        info: &CodecFrameInfo,
+    ) -> Result<Vec<Vec<u8>>, PacketError> {
+        use CodecPacketizer::*;
+        match self {
+            G711(v) => v.packetize_frame(mtu, b, info),
+            G722(v) => v.packetize_frame(mtu, b, info),
+            H264(v) => v.packetize_frame(mtu, b, info),
+            Opus(v) => v.packetize_frame(mtu, b, info),
+            Vp8(v) => v.packetize_frame(mtu, b, info),
+            Vp9(v) => v.packetize_frame(mtu, b, info),
+            Av1(v) => v.packetize_frame(mtu, b, info),
+            Generic(v) => v.packetize_frame(mtu, b, info),
+            Null(v) => v.packetize_frame(mtu, b, info),
+            Boxed(v) => v.packetize_frame(mtu, b, info),
+        }
+    }
+
     fn is_marker(&mut self, data: &[u8], previous: Option<&[u8]>, last: bool) -> bool {
         match self {
             CodecPacketizer::G711(v) => v.is_marker(data, previous, last),
@@ -263,10 +403,28 @@ impl Packetizer for CodecPacketizer {
             CodecPacketizer::H264(v) => v.is_marker(data, previous, last),
             CodecPacketizer::Vp8(v) => v.is_marker(data, previous, last),
             CodecPacketizer::Vp9(v) => v.is_marker(data, previous, last),
+            CodecPacketizer::Av1(v) => v.is_marker(data, previous, last),
+            CodecPacketizer::Generic(v) => v.is_marker(data, previous, last),
             CodecPacketizer::Null(v) => v.is_marker(data, previous, last),
             CodecPacketizer::Boxed(v) => v.is_marker(data, previous, last),
         }
     }
+
+    fn reset(&mut self) {
+        use CodecPacketizer::*;
+        match self {
+            G711(v) => v.reset(),
+            G722(v) => v.reset(),
+            H264(v) => v.reset(),
+            Opus(v) => v.reset(),
+            Vp8(v) => v.reset(),
+            Vp9(v) => v.reset(),
+            Av1(v) => v.reset(),
+            Generic(v) => v.reset(),
+            Null(v) => v.reset(),
+            Boxed(v) => v.reset(),
+        }
+    }
 }
 
 impl Depacketizer for CodecDepacketizer {
@@ -283,6 +441,8 @@ impl Depacketizer for CodecDepacketizer {
             Opus(v) => v.depacketize(packet, out, extra),
             Vp8(v) => v.depacketize(packet, out, extra),
             Vp9(v) => v.depacketize(packet, out, extra),
+            Av1(v) => v.depacketize(packet, out, extra),
+            Generic(v) => v.depacketize(packet, out, extra),
             Null(v) => v.depacketize(packet, out, extra),
             Boxed(v) => v.depacketize(packet, out, extra),
         }
@@ -296,6 +456,8 @@ impl Depacketizer for CodecDepacketizer {
             Opus(v) => v.is_partition_head(packet),
             Vp8(v) => v.is_partition_head(packet),
             Vp9(v) => v.is_partition_head(packet),
+            Av1(v) => v.is_partition_head(packet),
+            Generic(v) => v.is_partition_head(packet),
             Null(v) => v.is_partition_head(packet),
             Boxed(v) => v.is_partition_head(packet),
         }
@@ -309,10 +471,27 @@ impl Depacketizer for CodecDepacketizer {
             Opus(v) => v.is_partition_tail(marker, packet),
             Vp8(v) => v.is_partition_tail(marker, packet),
             Vp9(v) => v.is_partition_tail(marker, packet),
+            Av1(v) => v.is_partition_tail(marker, packet),
+            Generic(v) => v.is_partition_tail(marker, packet),
             Null(v) => v.is_partition_tail(marker, packet),
             Boxed(v) => v.is_partition_tail(marker, packet),
         }
     }
+
+    fn reset(&mut self) {
+        use CodecDepacketizer::*;
+        match self {
+            H264(v) => v.reset(),
+            H265(v) => v.reset(),
+            Opus(v) => v.reset(),
+            Vp8(v) => v.reset(),
+            Vp9(v) => v.reset(),
+            Av1(v) => v.reset(),
+            Generic(v) => v.reset(),
+            Null(v) => v.reset(),
+            Boxed(v) => v.reset(),
+        }
+    }
 }
 
 impl From<MediaType> for MediaKind {
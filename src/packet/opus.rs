@@ -1,3 +1,7 @@
+use std::time::Duration;
+
+use crate::rtp_::{Frequency, MediaTime, SeqNo};
+
 use super::{CodecExtra, Depacketizer, MediaKind, PacketError, Packetizer};
 
 /// Packetizes Opus RTP packets.
@@ -55,6 +59,282 @@ impl Depacketizer for OpusDepacketizer {
     }
 }
 
+/// Coding mode of an Opus packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpusMode {
+    /// SILK only, for speech at lower bandwidths.
+    Silk,
+    /// SILK for the lower band and CELT for the higher.
+    Hybrid,
+    /// CELT only, for music and low delay.
+    Celt,
+}
+
+/// Information about a single Opus RTP packet from its TOC byte and frame layout.
+///
+/// See [RFC 6716 section 3](https://datatracker.ietf.org/doc/html/rfc6716#section-3).
+/// Opus RTP packets carry the Opus packet as is, which makes this usable on the
+/// [`MediaData`] of an Opus stream.
+///
+/// [`MediaData`]: crate::media::MediaData
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusMeta {
+    /// Configuration number 0-31 from the TOC byte.
+    pub config: u8,
+    /// The coding mode given by the configuration.
+    pub mode: OpusMode,
+    /// s bit. Whether the frames are stereo.
+    pub stereo: bool,
+    /// Number of frames in the packet, 1-48.
+    pub frame_count: u8,
+    /// Duration of each frame in 48kHz samples, 120 (2.5ms) to 2880 (60ms).
+    pub samples_per_frame: u32,
+    /// The packet has no frame data. Sent by the encoder during discontinuous transmission.
+    pub is_dtx: bool,
+    /// The first frame carries low bitrate redundancy (in-band FEC) of the previous packet.
+    pub has_fec: bool,
+}
+
+impl OpusMeta {
+    /// Parse an Opus RTP payload.
+    ///
+    /// Errors if the packet is empty or the frame layout is malformed.
+    pub fn parse(packet: &[u8]) -> Result<OpusMeta, PacketError> {
+        //  0 1 2 3 4 5 6 7
+        // +-+-+-+-+-+-+-+-+
+        // | config  |s| c |
+        // +-+-+-+-+-+-+-+-+
+        let Some(&toc) = packet.first() else {
+            return Err(PacketError::ErrShortPacket);
+        };
+
+        let config = toc >> 3;
+        let stereo = toc & 0x04 > 0;
+        let code = toc & 0x03;
+
+        let (mode, samples_per_frame) = match config {
+            0..=11 => (OpusMode::Silk, [480, 960, 1920, 2880][config as usize % 4]),
+            12..=15 => (OpusMode::Hybrid, [480, 960][config as usize % 2]),
+            _ => (OpusMode::Celt, [120, 240, 480, 960][config as usize % 4]),
+        };
+
+        // Offset and size of the first frame, and size of all frame data.
+        let (frame_count, first_offset, first_size, data_size) = match code {
+            // One frame.
+            0 => (1, 1, packet.len() - 1, packet.len() - 1),
+            // Two frames of equal size.
+            1 => {
+                let size = packet.len() - 1;
+                if size % 2 != 0 {
+                    return Err(PacketError::ErrOpusCorruptedPacket);
+                }
+                (2, 1, size / 2, size)
+            }
+            // Two frames of different size.
+            2 => {
+                let (first, n) = read_frame_size(&packet[1..])?;
+                let size = packet.len() - 1 - n;
+                if first > size {
+                    return Err(PacketError::ErrShortPacket);
+                }
+                (2, 1 + n, first, size)
+            }
+            // Arbitrary number of frames.
+            _ => {
+                //  0 1 2 3 4 5 6 7
+                // +-+-+-+-+-+-+-+-+
+                // |v|p|     M     |
+                // +-+-+-+-+-+-+-+-+
+                let Some(&b) = packet.get(1) else {
+                    return Err(PacketError::ErrShortPacket);
+                };
+                let vbr = b & 0x80 > 0;
+                let count = (b & 0x3f) as usize;
+
+                // At most 120ms of audio in a packet.
+                if count == 0 || count * samples_per_frame > 5760 {
+                    return Err(PacketError::ErrOpusCorruptedPacket);
+                }
+
+                let mut pos = 2;
+                let mut padding = 0;
+
+                if b & 0x40 > 0 {
+                    loop {
+                        let Some(&p) = packet.get(pos) else {
+                            return Err(PacketError::ErrShortPacket);
+                        };
+                        pos += 1;
+                        if p == 255 {
+                            padding += 254;
+                        } else {
+                            padding += p as usize;
+                            break;
+                        }
+                    }
+                }
+
+                let mut sizes = 0;
+                let mut first = None;
+
+                if vbr {
+                    // Sizes of all but the last frame.
+                    for _ in 1..count {
+                        let (size, n) = read_frame_size(&packet[pos..])?;
+                        pos += n;
+                        sizes += size;
+                        first.get_or_insert(size);
+                    }
+                }
+
+                let size = packet
+                    .len()
+                    .checked_sub(pos + padding)
+                    .filter(|s| *s >= sizes)
+                    .ok_or(PacketError::ErrShortPacket)?;
+
+                let first = match first {
+                    Some(first) => first,
+                    // Only one frame, it's all the data.
+                    None if vbr => size,
+                    None if size % count == 0 => size / count,
+                    None => return Err(PacketError::ErrOpusCorruptedPacket),
+                };
+
+                (count as u8, pos, first, size)
+            }
+        };
+
+        let has_fec = mode != OpusMode::Celt
+            && first_size > 0
+            && has_lbrr(packet[first_offset], samples_per_frame, stereo);
+
+        Ok(OpusMeta {
+            config,
+            mode,
+            stereo,
+            frame_count,
+            samples_per_frame: samples_per_frame as u32,
+            is_dtx: data_size == 0,
+            has_fec,
+        })
+    }
+
+    /// Duration of the packet in 48kHz samples, which is also the RTP clock rate of Opus.
+    pub fn samples(&self) -> u32 {
+        self.samples_per_frame * self.frame_count as u32
+    }
+
+    /// Duration of the packet.
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.samples() as u64 * 1_000_000 / 48_000)
+    }
+}
+
+/// Read a frame size of one or two bytes.
+fn read_frame_size(buf: &[u8]) -> Result<(usize, usize), PacketError> {
+    match buf {
+        [b0 @ 0..=251, ..] => Ok((*b0 as usize, 1)),
+        [b0, b1, ..] => Ok((*b1 as usize * 4 + *b0 as usize, 2)),
+        _ => Err(PacketError::ErrShortPacket),
+    }
+}
+
+/// Whether the first SILK frame has the LBRR flag set.
+///
+/// The range coded VAD flags, one per 20ms SILK frame, and the LBRR flag are coded with
+/// equal probability, which makes them the leading bits of the frame. The side channel
+/// of a stereo frame follows the mid channel.
+fn has_lbrr(first: u8, samples_per_frame: usize, stereo: bool) -> bool {
+    let silk_frames = (samples_per_frame / 960).max(1);
+    let mid = (first >> (7 - silk_frames)) & 1 > 0;
+    let side = stereo && (first >> (6 - 2 * silk_frames)) & 1 > 0;
+    mid || side
+}
+
+/// What happened between two received Opus packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpusGap {
+    /// The packet directly follows the previous.
+    None,
+    /// The sender paused transmission (DTX). No packets are missing, the decoder
+    /// should generate comfort noise for the duration.
+    Dtx {
+        /// Duration of the pause in 48kHz samples.
+        samples: u64,
+    },
+    /// Packets were lost.
+    Loss {
+        /// Number of packets lost.
+        packets: u64,
+        /// Duration of the missing audio in 48kHz samples.
+        samples: u64,
+        /// The packet has in-band FEC that can recover the last lost packet.
+        fec: bool,
+    },
+    /// The packet is a duplicate or older than the previous and should be dropped.
+    Late,
+}
+
+impl OpusGap {
+    /// Whether the decoder should do FEC recovery of the packet before this one, i.e.
+    /// decode the current packet with FEC enabled before decoding it as normal.
+    pub fn recover_previous_with_fec(&self) -> bool {
+        matches!(self, OpusGap::Loss { fec: true, .. })
+    }
+}
+
+/// Tells DTX pauses apart from loss in a received Opus stream.
+///
+/// A DTX pause is a timestamp advancing more than the previous packet duration while
+/// the sequence number advances by one.
+#[derive(Debug, Default, Clone)]
+pub struct OpusFecPolicy {
+    /// Sequence number, time in 48kHz and duration of the previous packet.
+    last: Option<(SeqNo, u64, u64)>,
+}
+
+impl OpusFecPolicy {
+    /// Create a new policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update with the next received packet.
+    ///
+    /// Packets must be given in sequence number order, such as the [`MediaData`]
+    /// of an Opus stream.
+    ///
+    /// [`MediaData`]: crate::media::MediaData
+    pub fn update(&mut self, seq: SeqNo, time: MediaTime, meta: &OpusMeta) -> OpusGap {
+        let time = time.rebase(Frequency::FORTY_EIGHT_KHZ).numer();
+        let samples = meta.samples() as u64;
+
+        let Some((last_seq, last_time, last_samples)) = self.last else {
+            self.last = Some((seq, time, samples));
+            return OpusGap::None;
+        };
+
+        if seq <= last_seq {
+            return OpusGap::Late;
+        }
+
+        self.last = Some((seq, time, samples));
+
+        let gap = time.saturating_sub(last_time + last_samples);
+
+        match *seq - *last_seq {
+            1 if gap == 0 => OpusGap::None,
+            1 => OpusGap::Dtx { samples: gap },
+            n => OpusGap::Loss {
+                packets: n - 1,
+                samples: gap,
+                fec: meta.has_fec,
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -114,4 +394,173 @@ mod test {
 
         Ok(())
     }
+    #[test]
+    fn test_opus_meta_frame_sizes() {
+        // (packet, samples_per_frame, frame_count, mode)
+        let cases: &[(&[u8], u32, u8, OpusMode)] = &[
+            // CELT FB 2.5ms
+            (&[0xe0, 0x04, 0x1f, 0x0b], 120, 1, OpusMode::Celt),
+            // CELT FB 5ms
+            (&[0xe8, 0x07, 0x21, 0x9c], 240, 1, OpusMode::Celt),
+            // CELT FB 10ms
+            (&[0xf0, 0x7f, 0x3a, 0x18], 480, 1, OpusMode::Celt),
+            // CELT FB 20ms stereo
+            (&[0xfc, 0xff, 0xfe, 0x20, 0x11], 960, 1, OpusMode::Celt),
+            // SILK WB 10ms
+            (&[0x40, 0x8b, 0x77, 0x01], 480, 1, OpusMode::Silk),
+            // SILK WB 20ms
+            (&[0x48, 0x82, 0x4c, 0x11], 960, 1, OpusMode::Silk),
+            // SILK WB 40ms
+            (&[0x50, 0xc1, 0x00, 0x7e], 1920, 1, OpusMode::Silk),
+            // SILK WB 60ms
+            (&[0x58, 0xe0, 0x19, 0x55], 2880, 1, OpusMode::Silk),
+            // Hybrid FB 20ms
+            (&[0x78, 0x0b, 0xe4, 0xc1], 960, 1, OpusMode::Hybrid),
+            // Hybrid FB 2x20ms of equal size
+            (&[0x79, 0x0b, 0xe4, 0x0c, 0x11], 960, 2, OpusMode::Hybrid),
+            // Hybrid FB 2x20ms of different size
+            (
+                &[0x7a, 0x02, 0x0b, 0xe4, 0x0c, 0x11, 0x4f],
+                960,
+                2,
+                OpusMode::Hybrid,
+            ),
+            // CELT FB 6x20ms CBR
+            (
+                &[0xfb, 0x06, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06],
+                960,
+                6,
+                OpusMode::Celt,
+            ),
+        ];
+
+        for (packet, spf, count, mode) in cases {
+            let meta = OpusMeta::parse(packet).unwrap();
+            assert_eq!(meta.samples_per_frame, *spf, "{:02x?}", packet);
+            assert_eq!(meta.frame_count, *count, "{:02x?}", packet);
+            assert_eq!(meta.mode, *mode, "{:02x?}", packet);
+            assert!(!meta.is_dtx);
+        }
+
+        let meta = OpusMeta::parse(&[0xe0, 0x04]).unwrap();
+        assert_eq!(meta.duration(), Duration::from_micros(2500));
+
+        let meta = OpusMeta::parse(&[0xfb, 0x06, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]).unwrap();
+        assert_eq!(meta.samples(), 5760);
+        assert_eq!(meta.duration(), Duration::from_millis(120));
+        assert!(!meta.stereo);
+
+        let meta = OpusMeta::parse(&[0xfc, 0xff, 0xfe, 0x20, 0x11]).unwrap();
+        assert!(meta.stereo);
+    }
+
+    #[test]
+    fn test_opus_meta_fec() {
+        let fec = |p: &[u8]| OpusMeta::parse(p).unwrap().has_fec;
+
+        // SILK 20ms, VAD and LBRR flags.
+        assert!(fec(&[0x48, 0xc0, 0x11]));
+        assert!(!fec(&[0x48, 0x80, 0x11]));
+        // SILK 40ms, two VAD flags before LBRR.
+        assert!(fec(&[0x50, 0x20, 0x11]));
+        assert!(!fec(&[0x50, 0xc0, 0x11]));
+        // SILK 60ms stereo, LBRR only in the side channel.
+        assert!(fec(&[0x5c, 0x01, 0x11]));
+        assert!(!fec(&[0x5c, 0xe0, 0x11]));
+        // Hybrid 20ms.
+        assert!(fec(&[0x78, 0x40, 0x11]));
+        // CELT never has FEC.
+        assert!(!fec(&[0xf8, 0xff, 0x11]));
+        // First frame is found after padding and frame sizes.
+        assert!(fec(&[0x4b, 0xc2, 0x02, 0x01, 0x40, 0xaa, 0xbb, 0x00, 0x00]));
+        assert!(!fec(&[
+            0x4b, 0xc2, 0x02, 0x01, 0x80, 0xaa, 0xbb, 0x00, 0x00
+        ]));
+    }
+
+    #[test]
+    fn test_opus_meta_dtx() {
+        let meta = OpusMeta::parse(&[0xf8]).unwrap();
+        assert!(meta.is_dtx);
+        assert!(!meta.has_fec);
+
+        let meta = OpusMeta::parse(&[0x4b, 0x02]).unwrap();
+        assert!(meta.is_dtx);
+        assert_eq!(meta.frame_count, 2);
+    }
+
+    #[test]
+    fn test_opus_meta_malformed() {
+        let err = |p: &[u8]| OpusMeta::parse(p).unwrap_err();
+
+        assert_eq!(err(&[]), PacketError::ErrShortPacket);
+        // Code 1 with odd size.
+        assert_eq!(
+            err(&[0x79, 0x01, 0x02, 0x03]),
+            PacketError::ErrOpusCorruptedPacket
+        );
+        // Code 2 first frame larger than the packet.
+        assert_eq!(err(&[0x7a, 0x05, 0x01]), PacketError::ErrShortPacket);
+        // Code 3 without frame count.
+        assert_eq!(err(&[0x7b]), PacketError::ErrShortPacket);
+        // Code 3 zero frames.
+        assert_eq!(err(&[0x7b, 0x00]), PacketError::ErrOpusCorruptedPacket);
+        // Code 3 more than 120ms.
+        assert_eq!(
+            err(&[0x7b, 0x07, 0x00]),
+            PacketError::ErrOpusCorruptedPacket
+        );
+        // Code 3 CBR not evenly divided.
+        assert_eq!(
+            err(&[0x7b, 0x02, 0x01, 0x02, 0x03]),
+            PacketError::ErrOpusCorruptedPacket
+        );
+        // Code 3 padding longer than the packet.
+        assert_eq!(err(&[0x7b, 0x41, 0xff, 0x10]), PacketError::ErrShortPacket);
+        // Code 3 VBR sizes longer than the packet.
+        assert_eq!(err(&[0x7b, 0x82, 0x10, 0x01]), PacketError::ErrShortPacket);
+    }
+
+    #[test]
+    fn test_opus_fec_policy() {
+        let mut policy = OpusFecPolicy::new();
+        let t = |v: u64| MediaTime::new(v, Frequency::FORTY_EIGHT_KHZ);
+
+        let plain = OpusMeta::parse(&[0x48, 0x80, 0x11]).unwrap();
+        let with_fec = OpusMeta::parse(&[0x48, 0xc0, 0x11]).unwrap();
+
+        assert_eq!(policy.update(1.into(), t(0), &plain), OpusGap::None);
+        assert_eq!(policy.update(2.into(), t(960), &plain), OpusGap::None);
+
+        // Timestamp jumps 3 frames, but no packet is missing.
+        let gap = policy.update(3.into(), t(4800), &plain);
+        assert_eq!(gap, OpusGap::Dtx { samples: 2880 });
+        assert!(!gap.recover_previous_with_fec());
+
+        // Seq 4 lost, seq 5 carries FEC for it.
+        let gap = policy.update(5.into(), t(6720), &with_fec);
+        assert_eq!(
+            gap,
+            OpusGap::Loss {
+                packets: 1,
+                samples: 960,
+                fec: true
+            }
+        );
+        assert!(gap.recover_previous_with_fec());
+
+        assert_eq!(policy.update(5.into(), t(6720), &plain), OpusGap::Late);
+
+        // Loss without FEC.
+        let gap = policy.update(8.into(), t(9600), &plain);
+        assert_eq!(
+            gap,
+            OpusGap::Loss {
+                packets: 2,
+                samples: 1920,
+                fec: false
+            }
+        );
+        assert!(!gap.recover_previous_with_fec());
+    }
 }
@@ -0,0 +1,708 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::rtp_::{Bitrate, DataSize, Ssrc};
+use crate::util::already_happened;
+
+const DEFAULT_PACING_FACTOR: f64 = 1.1;
+const DEFAULT_MAX_BURST: Duration = Duration::from_millis(40);
+
+/// Priority of a packet queued in a [`PacedSender`].
+///
+/// Packets are released in priority order, audio before video before padding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PacketPriority {
+    /// Audio, released first.
+    Audio = 0,
+    /// Video, including retransmissions.
+    Video = 1,
+    /// Padding, only released when there is no media to send.
+    Padding = 2,
+}
+
+impl PacketPriority {
+    const ALL: [PacketPriority; 3] = [Self::Audio, Self::Video, Self::Padding];
+}
+
+/// Queue time statistics for one [`PacketPriority`] of a [`PacedSender`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PacedQueueStats {
+    /// Number of packets currently in the queue.
+    pub queued_packets: usize,
+    /// Number of bytes currently in the queue.
+    pub queued_bytes: usize,
+    /// Total number of packets released from the queue.
+    pub sent_packets: u64,
+    /// Sum of the time all released packets spent in the queue.
+    pub total_queue_time: Duration,
+    /// The longest time a released packet spent in the queue.
+    pub max_queue_time: Duration,
+    /// Total number of packets dropped for exceeding the max queue time.
+    pub dropped_packets: u64,
+}
+
+/// Packets dropped by a [`PacedSender`] for exceeding the max queue time.
+///
+/// Counts all drops since the last [`PacedSender::poll_overflow`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PacerQueueOverflow {
+    /// Number of packets dropped.
+    pub dropped_packets: usize,
+    /// Number of bytes dropped.
+    pub dropped_bytes: usize,
+}
+
+impl PacedQueueStats {
+    /// Average time a released packet spent in the queue.
+    pub fn average_queue_time(&self) -> Duration {
+        if self.sent_packets == 0 {
+            return Duration::ZERO;
+        }
+        self.total_queue_time.div_f64(self.sent_packets as f64)
+    }
+}
+
+/// Sans-IO packet pacer releasing queued packets at a target bitrate.
+///
+/// Sending a big keyframe as a single burst easily causes queue-induced loss on the path.
+/// The pacer spreads packets out over time using a budget that refills at the pacing rate
+/// (the target bitrate multiplied by the pacing factor). Budget that isn't used while idle
+/// accumulates up to the max burst, which allows short bursts after quiet periods.
+///
+/// Drive it by calling [`PacedSender::poll_packet`] until it returns `None`, then wait
+/// until [`PacedSender::poll_timeout`].
+///
+/// ```
+/// # use std::time::Instant;
+/// # use str0m::bwe::{Bitrate, PacedSender, PacketPriority};
+/// let mut pacer = PacedSender::new(Bitrate::mbps(1));
+///
+/// let now = Instant::now();
+/// pacer.enqueue(now, vec![0_u8; 1200], PacketPriority::Video);
+///
+/// while let Some(packet) = pacer.poll_packet(now) {
+///     // send packet on the network.
+/// }
+///
+/// // Wake up again at pacer.poll_timeout().
+/// ```
+#[derive(Debug)]
+pub struct PacedSender<T> {
+    /// The target bitrate.
+    target_bitrate: Bitrate,
+    /// Multiplier for the target bitrate to get the pacing rate.
+    pacing_factor: f64,
+    /// The longest time of unused budget that accumulates while idle.
+    max_burst: Duration,
+    /// Ongoing probe cluster, if any.
+    probe: Option<ProbeCluster>,
+    /// The last time the budget and debt were updated.
+    last_update: Option<Instant>,
+    /// Data sent ahead of the pacing rate that must be paid off before sending again.
+    debt: DataSize,
+    /// Unused budget accumulated while idle, capped by the max burst.
+    budget: DataSize,
+    /// One queue per priority, indexed by `PacketPriority`.
+    queues: [Queue<T>; 3],
+    /// Video and padding queued longer than this is dropped.
+    max_queue_time: Option<Duration>,
+    /// SSRCs that are never dropped.
+    drop_exempt: Vec<Ssrc>,
+    /// Drops not yet polled.
+    overflow: PacerQueueOverflow,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ProbeCluster {
+    bitrate: Bitrate,
+    size: DataSize,
+    sent: DataSize,
+}
+
+#[derive(Debug)]
+struct Queue<T> {
+    packets: VecDeque<QueuedPacket<T>>,
+    stats: PacedQueueStats,
+}
+
+#[derive(Debug)]
+struct QueuedPacket<T> {
+    queued_at: Instant,
+    size: usize,
+    ssrc: Option<Ssrc>,
+    packet: T,
+}
+
+impl<T: AsRef<[u8]>> PacedSender<T> {
+    /// Create a new pacer for the target bitrate.
+    pub fn new(target_bitrate: Bitrate) -> Self {
+        PacedSender {
+            target_bitrate,
+            pacing_factor: DEFAULT_PACING_FACTOR,
+            max_burst: DEFAULT_MAX_BURST,
+            probe: None,
+            last_update: None,
+            debt: DataSize::ZERO,
+            budget: DataSize::ZERO,
+            queues: Default::default(),
+            max_queue_time: None,
+            drop_exempt: Vec::new(),
+            overflow: PacerQueueOverflow::default(),
+        }
+    }
+
+    /// Set the target bitrate.
+    pub fn set_target_bitrate(&mut self, target_bitrate: Bitrate) {
+        self.target_bitrate = target_bitrate;
+    }
+
+    /// Set the pacing factor.
+    ///
+    /// Packets are released at the target bitrate multiplied by this factor. Defaults to 1.1.
+    pub fn set_pacing_factor(&mut self, pacing_factor: f64) {
+        self.pacing_factor = pacing_factor.max(0.0);
+    }
+
+    /// Set the max burst.
+    ///
+    /// Budget that isn't used while idle accumulates up to this amount of time at the
+    /// pacing rate. Defaults to 40ms.
+    pub fn set_max_burst(&mut self, max_burst: Duration) {
+        self.max_burst = max_burst;
+    }
+
+    /// Set the max queue time.
+    ///
+    /// Video and padding that has been queued longer than this is dropped, oldest first,
+    /// rather than sent uselessly late. Audio is never dropped. Defaults to `None`, which
+    /// never drops anything.
+    pub fn set_max_queue_time(&mut self, max_queue_time: Option<Duration>) {
+        self.max_queue_time = max_queue_time;
+    }
+
+    /// Exempt the packets of an SSRC from being dropped for exceeding the max queue time.
+    ///
+    /// Only packets queued with [`PacedSender::enqueue_for_ssrc`] can be exempted.
+    pub fn set_drop_exempt(&mut self, ssrc: Ssrc, exempt: bool) {
+        self.drop_exempt.retain(|s| *s != ssrc);
+        if exempt {
+            self.drop_exempt.push(ssrc);
+        }
+    }
+
+    /// Start a probe cluster.
+    ///
+    /// Until `size` bytes have been released, the pacing rate is raised to at least `bitrate`.
+    /// Replaces any ongoing probe cluster. To make the cluster complete when there is no
+    /// media, enqueue padding as indicated by [`PacedSender::probe_remaining`].
+    pub fn start_probe(&mut self, bitrate: Bitrate, size: usize) {
+        self.probe = Some(ProbeCluster {
+            bitrate,
+            size: size.into(),
+            sent: DataSize::ZERO,
+        });
+    }
+
+    /// Bytes left to release for the ongoing probe cluster, not counting queued packets.
+    ///
+    /// `None` when no probe cluster is ongoing.
+    pub fn probe_remaining(&self) -> Option<usize> {
+        let probe = self.probe.as_ref()?;
+        let remaining = probe.size.saturating_sub(probe.sent).as_bytes_usize();
+        Some(remaining.saturating_sub(self.queued_bytes()))
+    }
+
+    /// The current pacing rate, including any probe cluster.
+    pub fn pacing_rate(&self) -> Bitrate {
+        let rate = self.target_bitrate * self.pacing_factor;
+
+        match &self.probe {
+            Some(p) => rate.max(p.bitrate),
+            None => rate,
+        }
+    }
+
+    /// Queue a packet to be sent.
+    pub fn enqueue(&mut self, now: Instant, packet: T, priority: PacketPriority) {
+        self.do_enqueue(now, packet, priority, None);
+    }
+
+    /// Queue a packet to be sent, belonging to the stream `ssrc`.
+    ///
+    /// The SSRC is only used for exemptions from dropping, see
+    /// [`PacedSender::set_drop_exempt`].
+    pub fn enqueue_for_ssrc(
+        &mut self,
+        now: Instant,
+        packet: T,
+        priority: PacketPriority,
+        ssrc: Ssrc,
+    ) {
+        self.do_enqueue(now, packet, priority, Some(ssrc));
+    }
+
+    fn do_enqueue(
+        &mut self,
+        now: Instant,
+        packet: T,
+        priority: PacketPriority,
+        ssrc: Option<Ssrc>,
+    ) {
+        let size = packet.as_ref().len();
+
+        let queue = &mut self.queues[priority as usize];
+        queue.stats.queued_packets += 1;
+        queue.stats.queued_bytes += size;
+        queue.packets.push_back(QueuedPacket {
+            queued_at: now,
+            size,
+            ssrc,
+            packet,
+        });
+
+        self.drop_stale(now);
+    }
+
+    /// Poll for packets dropped for exceeding the max queue time.
+    ///
+    /// Returns `None` if nothing was dropped since the last call.
+    pub fn poll_overflow(&mut self) -> Option<PacerQueueOverflow> {
+        if self.overflow.dropped_packets == 0 {
+            return None;
+        }
+        Some(std::mem::take(&mut self.overflow))
+    }
+
+    /// Poll for the next packet to send.
+    ///
+    /// Returns `None` when the queues are empty or the budget is exhausted. In the latter
+    /// case, the next packet is released at [`PacedSender::poll_timeout`].
+    pub fn poll_packet(&mut self, now: Instant) -> Option<T> {
+        self.update_budget(now);
+        self.drop_stale(now);
+
+        if self.debt > DataSize::ZERO {
+            return None;
+        }
+
+        let queue = self.queues.iter_mut().find(|q| !q.packets.is_empty())?;
+        let queued = queue.packets.pop_front()?;
+
+        let queue_time = now.saturating_duration_since(queued.queued_at);
+        let stats = &mut queue.stats;
+        stats.queued_packets -= 1;
+        stats.queued_bytes -= queued.size;
+        stats.sent_packets += 1;
+        stats.total_queue_time += queue_time;
+        stats.max_queue_time = stats.max_queue_time.max(queue_time);
+
+        self.register_send(queued.size.into());
+
+        Some(queued.packet)
+    }
+
+    /// Time at which the next queued packet can be released.
+    ///
+    /// `None` if there is nothing queued, or nothing can be sent since the pacing rate is zero.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let Some(last_update) = self.last_update else {
+            return Some(already_happened());
+        };
+
+        if self.debt == DataSize::ZERO {
+            return Some(last_update);
+        }
+
+        let rate = self.pacing_rate();
+        if rate == Bitrate::ZERO {
+            return None;
+        }
+
+        Some(last_update + self.debt / rate)
+    }
+
+    /// Whether all queues are empty.
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.packets.is_empty())
+    }
+
+    /// Total number of bytes queued for all priorities.
+    pub fn queued_bytes(&self) -> usize {
+        self.queues.iter().map(|q| q.stats.queued_bytes).sum()
+    }
+
+    /// Queue time statistics for a priority.
+    pub fn stats(&self, priority: PacketPriority) -> PacedQueueStats {
+        self.queues[priority as usize].stats
+    }
+
+    /// Queue time statistics for all priorities.
+    pub fn all_stats(&self) -> impl Iterator<Item = (PacketPriority, PacedQueueStats)> + '_ {
+        PacketPriority::ALL.into_iter().map(|p| (p, self.stats(p)))
+    }
+
+    fn drop_stale(&mut self, now: Instant) {
+        let Some(max_queue_time) = self.max_queue_time else {
+            return;
+        };
+
+        for priority in [PacketPriority::Video, PacketPriority::Padding] {
+            let queue = &mut self.queues[priority as usize];
+
+            // Queues are in time order, so anything stale is near the front.
+            let Some(first) = queue.packets.front() else {
+                continue;
+            };
+            if now.saturating_duration_since(first.queued_at) <= max_queue_time {
+                continue;
+            }
+
+            let drop_exempt = &self.drop_exempt;
+            let overflow = &mut self.overflow;
+            let stats = &mut queue.stats;
+
+            queue.packets.retain(|p| {
+                let stale = now.saturating_duration_since(p.queued_at) > max_queue_time;
+                let exempt = p.ssrc.map_or(false, |s| drop_exempt.contains(&s));
+
+                if !stale || exempt {
+                    return true;
+                }
+
+                stats.queued_packets -= 1;
+                stats.queued_bytes -= p.size;
+                stats.dropped_packets += 1;
+                overflow.dropped_packets += 1;
+                overflow.dropped_bytes += p.size;
+
+                false
+            });
+        }
+    }
+
+    fn update_budget(&mut self, now: Instant) {
+        let Some(last_update) = self.last_update else {
+            self.last_update = Some(now);
+            return;
+        };
+
+        // Time going backwards is treated as no time passing.
+        if now <= last_update {
+            return;
+        }
+        self.last_update = Some(now);
+
+        let rate = self.pacing_rate();
+        let mut drained = rate * (now - last_update);
+
+        // Pay off debt first, anything left over accumulates as budget.
+        let paid = drained.min(self.debt);
+        self.debt -= paid;
+        drained -= paid;
+
+        self.budget += drained;
+        self.budget = self.budget.min(rate * self.max_burst);
+    }
+
+    fn register_send(&mut self, size: DataSize) {
+        let from_budget = size.min(self.budget);
+        self.budget -= from_budget;
+        self.debt += size.saturating_sub(from_budget);
+
+        if let Some(probe) = &mut self.probe {
+            probe.sent += size;
+            if probe.sent >= probe.size {
+                self.probe = None;
+            }
+        }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Queue {
+            packets: VecDeque::new(),
+            stats: PacedQueueStats::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn drain(pacer: &mut PacedSender<Vec<u8>>, start: Instant) -> Vec<(Duration, Vec<u8>)> {
+        let mut sent = vec![];
+        let mut now = start;
+
+        loop {
+            while let Some(p) = pacer.poll_packet(now) {
+                sent.push((now - start, p));
+            }
+            let Some(next) = pacer.poll_timeout() else {
+                break;
+            };
+            assert!(next > now, "poll_timeout must move forward");
+            now = next;
+        }
+
+        sent
+    }
+
+    #[test]
+    fn drains_at_target_bitrate() {
+        let mut pacer = PacedSender::new(Bitrate::mbps(1));
+        pacer.set_pacing_factor(1.0);
+
+        let start = Instant::now();
+        for _ in 0..500 {
+            pacer.enqueue(start, vec![0; 1000], PacketPriority::Video);
+        }
+        assert_eq!(pacer.queued_bytes(), 500_000);
+
+        let sent = drain(&mut pacer, start);
+        assert_eq!(sent.len(), 500);
+
+        // 500kB at 1Mbit/s is 4s. The first packet goes out immediately,
+        // so the last one is released one packet time (8ms) early.
+        let last = sent.last().unwrap().0;
+        assert!(
+            last > Duration::from_millis(3950) && last < Duration::from_millis(4010),
+            "{:?}",
+            last
+        );
+
+        // No bursts, packets are spaced evenly.
+        for w in sent.windows(2) {
+            let gap = w[1].0 - w[0].0;
+            assert!(gap >= Duration::from_micros(7900), "{:?}", gap);
+        }
+
+        let stats = pacer.stats(PacketPriority::Video);
+        assert_eq!(stats.queued_packets, 0);
+        assert_eq!(stats.sent_packets, 500);
+        assert_eq!(stats.max_queue_time, last);
+        assert!(stats.average_queue_time() > Duration::from_millis(1900));
+        assert!(stats.average_queue_time() < Duration::from_millis(2100));
+    }
+
+    #[test]
+    fn pacing_factor_speeds_up_drain() {
+        let mut pacer = PacedSender::new(Bitrate::mbps(1));
+        pacer.set_pacing_factor(2.0);
+
+        let start = Instant::now();
+        for _ in 0..500 {
+            pacer.enqueue(start, vec![0; 1000], PacketPriority::Video);
+        }
+
+        let last = drain(&mut pacer, start).last().unwrap().0;
+        assert!(last > Duration::from_millis(1950) && last < Duration::from_millis(2010));
+    }
+
+    #[test]
+    fn audio_before_video_before_padding() {
+        let mut pacer = PacedSender::new(Bitrate::kbps(100));
+
+        let start = Instant::now();
+        pacer.enqueue(start, vec![3; 100], PacketPriority::Padding);
+        pacer.enqueue(start, vec![2; 100], PacketPriority::Video);
+        pacer.enqueue(start, vec![1; 100], PacketPriority::Audio);
+        pacer.enqueue(start, vec![2; 100], PacketPriority::Video);
+        pacer.enqueue(start, vec![1; 100], PacketPriority::Audio);
+
+        let order: Vec<_> = drain(&mut pacer, start)
+            .into_iter()
+            .map(|(_, p)| p[0])
+            .collect();
+        assert_eq!(order, [1, 1, 2, 2, 3]);
+
+        assert_eq!(pacer.stats(PacketPriority::Audio).sent_packets, 2);
+        assert_eq!(pacer.stats(PacketPriority::Video).sent_packets, 2);
+        assert_eq!(pacer.stats(PacketPriority::Padding).sent_packets, 1);
+    }
+
+    #[test]
+    fn budget_accumulates_while_idle_up_to_cap() {
+        // 40ms at 1Mbit/s is 5000 bytes of burst.
+        let mut pacer = PacedSender::new(Bitrate::mbps(1));
+        pacer.set_pacing_factor(1.0);
+
+        let start = Instant::now();
+        pacer.enqueue(start, vec![0; 1000], PacketPriority::Video);
+        assert!(pacer.poll_packet(start).is_some());
+
+        // Idle for a long time, much longer than the burst cap.
+        let later = start + Duration::from_secs(10);
+        for _ in 0..20 {
+            pacer.enqueue(later, vec![0; 1000], PacketPriority::Video);
+        }
+
+        let mut burst = 0;
+        while pacer.poll_packet(later).is_some() {
+            burst += 1;
+        }
+
+        // 5 packets from the budget, and one more going into debt.
+        assert_eq!(burst, 6);
+        assert_eq!(pacer.poll_timeout(), Some(later + Duration::from_millis(8)));
+    }
+
+    #[test]
+    fn probe_cluster_raises_rate_temporarily() {
+        let mut pacer = PacedSender::new(Bitrate::mbps(1));
+        pacer.set_pacing_factor(1.0);
+        pacer.set_max_burst(Duration::ZERO);
+
+        pacer.start_probe(Bitrate::mbps(4), 10_000);
+        assert_eq!(pacer.pacing_rate(), Bitrate::mbps(4));
+        assert_eq!(pacer.probe_remaining(), Some(10_000));
+
+        let start = Instant::now();
+        for _ in 0..20 {
+            pacer.enqueue(start, vec![0; 1000], PacketPriority::Padding);
+        }
+        assert_eq!(pacer.probe_remaining(), Some(0));
+
+        let sent = drain(&mut pacer, start);
+        assert_eq!(sent.len(), 20);
+
+        let gap = |i: usize| (sent[i].0 - sent[i - 1].0).as_micros();
+
+        // The probe cluster is 10 packets 2ms apart, the rest go at the target rate.
+        assert!((1990..=2010).contains(&gap(1)));
+        assert!((1990..=2010).contains(&gap(9)));
+        assert!((7990..=8010).contains(&gap(10)));
+        assert!((7990..=8010).contains(&gap(19)));
+
+        assert_eq!(pacer.probe_remaining(), None);
+        assert_eq!(pacer.pacing_rate(), Bitrate::mbps(1));
+    }
+
+    #[test]
+    fn drops_oldest_video_first() {
+        let mut pacer = PacedSender::new(Bitrate::kbps(80));
+        pacer.set_pacing_factor(1.0);
+        pacer.set_max_burst(Duration::ZERO);
+        pacer.set_max_queue_time(Some(Duration::from_secs(2)));
+
+        let exempt = Ssrc::from(2);
+        pacer.set_drop_exempt(exempt, true);
+
+        // One 1000 byte packet is 100ms at 80kbps. First one goes out immediately.
+        let start = Instant::now();
+        pacer.enqueue(start, vec![0; 1000], PacketPriority::Video);
+        assert!(pacer.poll_packet(start).is_some());
+
+        for i in 0..10_u8 {
+            let at = start + Duration::from_millis(i as u64 * 100);
+            let ssrc = if i == 0 { exempt } else { Ssrc::from(1) };
+            pacer.enqueue_for_ssrc(at, vec![i; 1000], PacketPriority::Video, ssrc);
+        }
+        assert_eq!(pacer.poll_overflow(), None);
+
+        // At 2.25s, the packets queued at 0ms (exempt), 100ms and 200ms are too old.
+        let now = start + Duration::from_millis(2250);
+        pacer.enqueue(now, vec![10; 1000], PacketPriority::Video);
+
+        assert_eq!(
+            pacer.poll_overflow(),
+            Some(PacerQueueOverflow {
+                dropped_packets: 2,
+                dropped_bytes: 2000
+            })
+        );
+        assert_eq!(pacer.poll_overflow(), None);
+
+        let stats = pacer.stats(PacketPriority::Video);
+        assert_eq!(stats.dropped_packets, 2);
+        assert_eq!(stats.queued_packets, 9);
+
+        // The exempt one is still first in line.
+        assert_eq!(pacer.poll_packet(now).unwrap()[0], 0);
+
+        // By then, the one queued at 300ms is too old as well.
+        let now = now + Duration::from_millis(100);
+        assert_eq!(pacer.poll_packet(now).unwrap()[0], 4);
+        assert_eq!(pacer.poll_overflow().map(|o| o.dropped_packets), Some(1));
+    }
+
+    #[test]
+    fn audio_survives_video_flood() {
+        let mut pacer = PacedSender::new(Bitrate::kbps(500));
+        pacer.set_pacing_factor(1.0);
+        pacer.set_max_queue_time(Some(Duration::from_secs(2)));
+
+        // Video at 2Mbit/s into a 500kbit/s pacer, audio at 32kbit/s.
+        let start = Instant::now();
+        let mut max_audio_delay = Duration::ZERO;
+        let mut max_video_delay = Duration::ZERO;
+
+        for i in 0..1000_u64 {
+            let ms = i * 10;
+            let now = start + Duration::from_millis(ms);
+
+            let millis = (ms as u32).to_be_bytes();
+            let mut video = vec![1; 2500];
+            video[1..5].copy_from_slice(&millis);
+            pacer.enqueue(now, video, PacketPriority::Video);
+
+            if i % 2 == 0 {
+                let mut audio = vec![0; 40];
+                audio[1..5].copy_from_slice(&millis);
+                pacer.enqueue(now, audio, PacketPriority::Audio);
+            }
+
+            while let Some(p) = pacer.poll_packet(now) {
+                let queued_at = u32::from_be_bytes(p[1..5].try_into().unwrap());
+                let delay = Duration::from_millis(ms - queued_at as u64);
+
+                if p[0] == 0 {
+                    max_audio_delay = max_audio_delay.max(delay);
+                } else {
+                    max_video_delay = max_video_delay.max(delay);
+                }
+            }
+        }
+
+        // All audio goes out, without much delay.
+        let audio = pacer.stats(PacketPriority::Audio);
+        assert_eq!(audio.sent_packets + audio.queued_packets as u64, 500);
+        assert_eq!(audio.dropped_packets, 0);
+        assert!(
+            max_audio_delay <= Duration::from_millis(50),
+            "{:?}",
+            max_audio_delay
+        );
+
+        // Video is dropped instead of going out later than the limit.
+        let overflow = pacer.poll_overflow().unwrap();
+        assert!(overflow.dropped_packets > 500, "{:?}", overflow);
+        assert!(
+            max_video_delay <= Duration::from_secs(2),
+            "{:?}",
+            max_video_delay
+        );
+
+        let queued = pacer.stats(PacketPriority::Video).queued_packets;
+        assert!(queued <= 201, "{}", queued);
+    }
+
+    #[test]
+    fn zero_rate_never_sends() {
+        let mut pacer = PacedSender::new(Bitrate::ZERO);
+
+        let start = Instant::now();
+        pacer.enqueue(start, vec![0; 100], PacketPriority::Video);
+        pacer.enqueue(start, vec![0; 100], PacketPriority::Video);
+
+        // First send is always allowed.
+        assert!(pacer.poll_packet(start).is_some());
+        assert!(pacer.poll_packet(start + Duration::from_secs(1)).is_none());
+        assert_eq!(pacer.poll_timeout(), None);
+    }
+}
@@ -8,13 +8,15 @@ use crate::util::already_happened;
 use crate::util::not_happening;
 use crate::util::Soonest;
 
-use super::MediaKind;
+use super::{MediaKind, SendBudget, SendClass};
 
 const MAX_BITRATE: Bitrate = Bitrate::gbps(10);
 const MAX_DEBT_IN_TIME: Duration = Duration::from_millis(500);
 const MAX_PADDING_PACKET_SIZE: DataSize = DataSize::bytes(MAX_BLANK_PADDING_PAYLOAD_SIZE as u64);
 const PADDING_BURST_INTERVAL: Duration = Duration::from_millis(5);
 const PACING: Duration = Duration::from_millis(40);
+/// How long after a route change queues aren't drained faster than the pacing rate.
+const ROUTE_CHANGE_SETTLE: Duration = Duration::from_secs(1);
 
 pub enum PacerImpl {
     Null(NullPacer),
@@ -61,10 +63,24 @@ impl Pacer for PacerImpl {
         }
     }
 
-    fn register_send(&mut self, now: Instant, packet_size: DataSize, from: Mid) {
+    fn register_send(&mut self, now: Instant, packet_size: DataSize, from: Mid, class: SendClass) {
         match self {
-            PacerImpl::Null(v) => v.register_send(now, packet_size, from),
-            PacerImpl::LeakyBucket(v) => v.register_send(now, packet_size, from),
+            PacerImpl::Null(v) => v.register_send(now, packet_size, from, class),
+            PacerImpl::LeakyBucket(v) => v.register_send(now, packet_size, from, class),
+        }
+    }
+
+    fn budget(&self) -> &SendBudget {
+        match self {
+            PacerImpl::Null(v) => v.budget(),
+            PacerImpl::LeakyBucket(v) => v.budget(),
+        }
+    }
+
+    fn handle_route_change(&mut self, now: Instant) {
+        match self {
+            PacerImpl::Null(v) => v.handle_route_change(now),
+            PacerImpl::LeakyBucket(v) => v.handle_route_change(now),
         }
     }
 }
@@ -97,7 +113,13 @@ pub trait Pacer {
     /// Register a packet having been sent.
     ///
     /// **MUST** be called each time [`Pacer::poll_queue`] produces a mid.
-    fn register_send(&mut self, now: Instant, packet_size: DataSize, from: Mid);
+    fn register_send(&mut self, now: Instant, packet_size: DataSize, from: Mid, class: SendClass);
+
+    /// What has been sent, and what is left for padding.
+    fn budget(&self) -> &SendBudget;
+
+    /// The network route changed. Anything queued shouldn't be rushed onto the new path.
+    fn handle_route_change(&mut self, now: Instant);
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -201,6 +223,7 @@ pub struct NullPacer {
     last_sends: HashMap<Mid, Instant>,
     queue_states: Vec<QueueState>,
     need_immediate_timeout: bool,
+    budget: SendBudget,
 }
 
 impl Pacer for NullPacer {
@@ -248,9 +271,18 @@ impl Pacer for NullPacer {
         result
     }
 
-    fn register_send(&mut self, now: Instant, _packet_size: DataSize, from: Mid) {
+    fn register_send(&mut self, now: Instant, packet_size: DataSize, from: Mid, class: SendClass) {
         let e = self.last_sends.entry(from).or_insert(now);
         *e = now;
+        self.budget.register(class, packet_size);
+    }
+
+    fn budget(&self) -> &SendBudget {
+        &self.budget
+    }
+
+    fn handle_route_change(&mut self, _now: Instant) {
+        // Nothing is paced.
     }
 }
 
@@ -278,6 +310,10 @@ pub struct LeakyBucketPacer {
     queue_states: Vec<QueueState>,
     /// The next return value for `poll_queue``
     next_poll_queue: Option<Mid>,
+    /// Keeps padding within what media and RTX leave over of the padding rate.
+    budget: SendBudget,
+    /// When the network route last changed.
+    route_changed_at: Option<Instant>,
 }
 
 impl Pacer for LeakyBucketPacer {
@@ -289,6 +325,7 @@ impl Pacer for LeakyBucketPacer {
 
     fn set_padding_rate(&mut self, padding_bitrate: Bitrate) {
         self.padding_bitrate = padding_bitrate;
+        self.budget.set_target(padding_bitrate);
 
         // bitrate will be updated on next handle_timeout().
     }
@@ -314,9 +351,10 @@ impl Pacer for LeakyBucketPacer {
         let elapsed = self.update_handle_time_and_get_elapsed(now);
 
         self.clear_debt(elapsed);
+        self.budget.handle_timeout(now);
         self.maybe_update_adjusted_bitrate(now);
 
-        if let Some(request) = self.maybe_create_padding_request() {
+        if let Some(request) = self.maybe_create_padding_request(now) {
             self.next_poll_queue = Some(request.mid);
             return Some(request);
         }
@@ -358,8 +396,9 @@ impl Pacer for LeakyBucketPacer {
         Some(next)
     }
 
-    fn register_send(&mut self, now: Instant, packet_size: DataSize, _from: Mid) {
+    fn register_send(&mut self, now: Instant, packet_size: DataSize, _from: Mid, class: SendClass) {
         self.last_emitted = Some(now);
+        self.budget.register(class, packet_size);
 
         self.media_debt += packet_size;
         self.media_debt = self
@@ -368,6 +407,16 @@ impl Pacer for LeakyBucketPacer {
         crate::packet::bwe::macros::log_pacer_media_debt!(self.media_debt.as_bytes_usize());
         self.add_padding_debt(packet_size);
     }
+
+    fn budget(&self) -> &SendBudget {
+        &self.budget
+    }
+
+    fn handle_route_change(&mut self, now: Instant) {
+        self.route_changed_at = Some(now);
+        self.adjusted_bitrate = self.pacing_bitrate;
+        self.budget.clear_saved();
+    }
 }
 
 impl LeakyBucketPacer {
@@ -386,6 +435,8 @@ impl LeakyBucketPacer {
             queue_limit: DEFAULT_QUEUE_LIMIT,
             queue_states: vec![],
             next_poll_queue: None,
+            budget: SendBudget::new(),
+            route_changed_at: None,
         }
     }
 
@@ -478,8 +529,9 @@ impl LeakyBucketPacer {
 
         // If all queues are empty and we have a padding rate, wait until we have drained
         // both the media debt and padding debt to send some padding.
-        let mut drain_debt_time =
-            (self.media_debt / self.adjusted_bitrate).max(self.padding_debt / self.padding_bitrate);
+        let mut drain_debt_time = (self.media_debt / self.adjusted_bitrate)
+            .max(self.padding_debt / self.padding_bitrate)
+            .max(self.budget.padding_wait(now));
         if drain_debt_time.is_zero() {
             // Give the main loop some time to do something else e.g. queue media.
             drain_debt_time = Duration::from_micros(1);
@@ -498,6 +550,14 @@ impl LeakyBucketPacer {
     fn maybe_update_adjusted_bitrate(&mut self, now: Instant) {
         self.adjusted_bitrate = self.pacing_bitrate;
 
+        // Nothing is known about a new route, don't push a backlog onto it.
+        let settling = self.route_changed_at.map_or(false, |t| {
+            now.saturating_duration_since(t) < ROUTE_CHANGE_SETTLE
+        });
+        if settling {
+            return;
+        }
+
         let (queue_time, queued_packets, queue_size) =
             self.queue_states
                 .iter()
@@ -541,7 +601,7 @@ impl LeakyBucketPacer {
     ///
     /// Returns `Some(PaddingRequest)` if padding is enabled and the current queue state
     /// allows padding, otherwise returns `None`.
-    fn maybe_create_padding_request(&self) -> Option<PaddingRequest> {
+    fn maybe_create_padding_request(&self, now: Instant) -> Option<PaddingRequest> {
         // We must have no debt.
         if self.media_debt != DataSize::ZERO || self.padding_debt != DataSize::ZERO {
             return None;
@@ -568,8 +628,14 @@ impl LeakyBucketPacer {
             .filter(|q| q.use_for_padding)
             .max_by_key(|q| q.snapshot.last_emitted)?;
 
-        // We can generate padding
-        let padding = (self.padding_bitrate * PADDING_BURST_INTERVAL).as_bytes_usize();
+        // We can generate padding, as long as media and RTX leave room for it.
+        let padding = (self.padding_bitrate * PADDING_BURST_INTERVAL)
+            .as_bytes_usize()
+            .min(self.budget.padding_allowed(now));
+
+        if padding == 0 {
+            return None;
+        }
 
         Some(PaddingRequest {
             mid: queue.mid,
@@ -996,6 +1062,27 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_padding_within_budget() {
+        // Media at 80% of the target leaves 20% for padding.
+        let config = RealisticTestConfig {
+            media_rate: Bitrate::kbps(800),
+            padding_rate: Bitrate::kbps(1000),
+            ..Default::default()
+        };
+        let (media_rate, padding_rate, total_rate) = run_realistic_test(config);
+
+        assert!(
+            padding_rate <= Bitrate::kbps(220),
+            "Padding should be capped to the gap. media_rate={media_rate}, padding_rate={padding_rate}"
+        );
+        assert!(
+            padding_rate >= Bitrate::kbps(150),
+            "Padding should fill the gap. media_rate={media_rate}, padding_rate={padding_rate}"
+        );
+        assert!(total_rate <= Bitrate::kbps(1020), "total_rate={total_rate}");
+    }
+
     #[test]
     fn test_queue_state_merge() {
         let now = Instant::now();
@@ -1062,8 +1149,9 @@ mod test {
         let qid = pacer.poll_queue().expect(msg);
         let packet = queue.next_packet().unwrap();
         let packet_size = packet.size();
+        let class = packet.class();
         do_asserts(packet);
-        pacer.register_send(now, DataSize::from(packet_size), qid);
+        pacer.register_send(now, DataSize::from(packet_size), qid, class);
         queue.register_send(qid, now);
 
         let timeout = pacer.poll_timeout();
@@ -1198,6 +1286,7 @@ mod test {
                         base + elapsed,
                         DataSize::bytes(packet.payload_len as u64),
                         mid,
+                        packet.class(),
                     );
                     if packet.kind == PacketKind::Padding {
                         padding_sent += packet.payload_len.into();
@@ -1387,6 +1476,14 @@ mod test {
             pub(super) fn size(&self) -> usize {
                 self.payload_len
             }
+
+            pub(super) fn class(&self) -> SendClass {
+                if self.kind == PacketKind::Padding {
+                    SendClass::Padding
+                } else {
+                    SendClass::Media
+                }
+            }
         }
 
         struct Inner {
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::collections::{BTreeMap, VecDeque};
 
 use std::fmt;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crate::format::CodecSpec;
@@ -19,9 +20,9 @@ pub struct Payloader {
 }
 
 impl Payloader {
-    pub(crate) fn new(spec: CodecSpec) -> Self {
+    pub(crate) fn new(spec: CodecSpec, pack: CodecPacketizer) -> Self {
         Payloader {
-            pack: spec.codec.into(),
+            pack,
             clock_rate: spec.clock_rate,
         }
     }
@@ -40,10 +41,18 @@ impl Payloader {
             wallclock,
             rtp_time,
             data,
-            ext_vals,
+            mut ext_vals,
+            frame_info,
         } = to_payload;
 
-        let chunks = self.pack.packetize(mtu, &data)?;
+        if let Some(timing) = &mut ext_vals.video_timing {
+            timing.set_packetize_complete(wallclock, now);
+        }
+
+        let chunks = match &frame_info {
+            Some(info) => self.pack.packetize_frame(mtu, &data, info)?,
+            None => self.pack.packetize(mtu, &data)?,
+        };
         let len = chunks.len();
 
         let ssrc = stream.ssrc();
@@ -61,6 +70,17 @@ impl Payloader {
 
             let seq_no = stream.next_seq_no();
 
+            let mut ext_vals = ext_vals.clone();
+            if let Some(f) = &mut ext_vals.frame_marking {
+                f.start_of_frame = first;
+                f.end_of_frame = last;
+            }
+            if let Some(d) = &mut ext_vals.dependency_descriptor {
+                let d = Arc::make_mut(d);
+                d.start_of_frame = first;
+                d.end_of_frame = last;
+            }
+
             // TODO: delegate to self.pack to decide whether this packet is nackable.
             let nackable = !is_audio;
 
@@ -70,7 +90,7 @@ impl Payloader {
                 rtp_time.rebase(self.clock_rate).numer() as u32,
                 wallclock,
                 marker,
-                ext_vals.clone(),
+                ext_vals,
                 nackable,
                 data,
             );
@@ -0,0 +1,415 @@
+use std::collections::VecDeque;
+
+use crate::rtp_::{MediaTime, Pt, SeqNo};
+
+use super::PacketError;
+
+/// Largest timestamp offset of a redundant block, 14 bits.
+const MAX_TIMESTAMP_OFFSET: u64 = 0x3fff;
+
+/// Largest length of a redundant block, 10 bits.
+const MAX_BLOCK_LENGTH: usize = 0x3ff;
+
+/// Number of sequence numbers remembered to not output a frame twice.
+const MAX_SEEN: usize = 64;
+
+/// One frame of a RED payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedFrame {
+    /// Sequence number of the packet that originally carried the frame.
+    pub seq_no: SeqNo,
+    /// Time of the frame.
+    pub time: MediaTime,
+    /// The frame, such as an Opus packet.
+    pub data: Vec<u8>,
+    /// Whether the frame is recovered from a redundant block, i.e. the packet that
+    /// originally carried it was not received.
+    pub recovered: bool,
+}
+
+/// Decodes RED payloads, redundant audio data as described in
+/// [RFC 2198](https://datatracker.ietf.org/doc/html/rfc2198).
+///
+/// Each RED payload carries the current frame, the primary, and some previous frames
+/// as redundant blocks. The redundant blocks recover the frames of lost packets.
+///
+/// The redundant blocks are assumed to be the frames of the directly preceding packets,
+/// which is how Chrome sends RED for Opus.
+#[derive(Debug)]
+pub struct RedDecoder {
+    pt: Pt,
+    /// Sequence numbers of frames already output.
+    seen: VecDeque<SeqNo>,
+}
+
+impl RedDecoder {
+    /// Create a decoder for the frames of the payload type `pt`, as mapped in the SDP,
+    /// i.e. `a=fmtp:63 111/111` for Opus with PT 111 wrapped in RED with PT 63.
+    ///
+    /// Blocks of other payload types are ignored.
+    pub fn new(pt: Pt) -> Self {
+        RedDecoder {
+            pt,
+            seen: VecDeque::with_capacity(MAX_SEEN),
+        }
+    }
+
+    /// Decode the payload of a RED packet into its frames, oldest first.
+    ///
+    /// Frames already output by an earlier call are skipped. Packets can be given out
+    /// of order.
+    pub fn decode(
+        &mut self,
+        seq_no: SeqNo,
+        time: MediaTime,
+        payload: &[u8],
+    ) -> Result<Vec<RedFrame>, PacketError> {
+        let blocks = parse_red(payload)?;
+        let redundant = blocks.len() - 1;
+
+        let mut frames = Vec::with_capacity(blocks.len());
+
+        for (i, (pt, offset, data)) in blocks.into_iter().enumerate() {
+            if pt != self.pt || data.is_empty() {
+                continue;
+            }
+
+            // The redundant blocks are the frames of the preceding packets.
+            let Some(seq_no) = seq_no.checked_sub((redundant - i) as u64) else {
+                continue;
+            };
+            let Some(numer) = time.numer().checked_sub(offset) else {
+                continue;
+            };
+            let seq_no: SeqNo = seq_no.into();
+
+            if self.seen.contains(&seq_no) {
+                continue;
+            }
+            if self.seen.len() == MAX_SEEN {
+                self.seen.pop_front();
+            }
+            self.seen.push_back(seq_no);
+
+            frames.push(RedFrame {
+                seq_no,
+                time: MediaTime::new(numer, time.frequency()),
+                data: data.to_vec(),
+                recovered: i < redundant,
+            });
+        }
+
+        Ok(frames)
+    }
+}
+
+/// Split a RED payload into blocks of payload type, timestamp offset and data.
+///
+/// The last block is the primary, with a zero offset.
+pub(crate) fn parse_red(payload: &[u8]) -> Result<Vec<(Pt, u64, &[u8])>, PacketError> {
+    //  0                   1                    2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |F|   block PT  |  timestamp offset         |   block length    |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //
+    // The last header, of the primary block, is only the first octet with F=0.
+    let mut headers = vec![];
+    let mut rest = payload;
+
+    loop {
+        let Some(&b) = rest.first() else {
+            return Err(PacketError::ErrShortPacket);
+        };
+        let pt = Pt::from(b & 0x7f);
+
+        if b & 0x80 == 0 {
+            headers.push((pt, 0, None));
+            rest = &rest[1..];
+            break;
+        }
+
+        let [_, b1, b2, b3, ..] = *rest else {
+            return Err(PacketError::ErrShortPacket);
+        };
+        let offset = (b1 as u64) << 6 | (b2 as u64) >> 2;
+        let len = ((b2 & 0x03) as usize) << 8 | b3 as usize;
+        headers.push((pt, offset, Some(len)));
+        rest = &rest[4..];
+    }
+
+    let mut blocks = Vec::with_capacity(headers.len());
+
+    for (pt, offset, len) in headers {
+        // The primary block is the remainder of the payload.
+        let len = len.unwrap_or(rest.len());
+        if len > rest.len() {
+            return Err(PacketError::ErrRedCorruptedPacket);
+        }
+        blocks.push((pt, offset, &rest[..len]));
+        rest = &rest[len..];
+    }
+
+    Ok(blocks)
+}
+
+/// Encodes RED payloads, redundant audio data as described in
+/// [RFC 2198](https://datatracker.ietf.org/doc/html/rfc2198).
+///
+/// Each payload carries the current frame and up to `depth` previous frames. Previous
+/// frames are left out if the payload would exceed the MTU, or they are too large or
+/// too old to be described by the block header.
+#[derive(Debug)]
+pub struct RedEncoder {
+    pt: Pt,
+    depth: usize,
+    /// Previous frames, oldest first.
+    history: VecDeque<(MediaTime, Vec<u8>)>,
+}
+
+impl RedEncoder {
+    /// Create an encoder of frames with payload type `pt`, as mapped in the SDP, i.e.
+    /// `a=fmtp:63 111/111` for Opus with PT 111 wrapped in RED with PT 63.
+    ///
+    /// `depth` is the number of previous frames to send with each frame. Chrome uses 1
+    /// or 2.
+    pub fn new(pt: Pt, depth: usize) -> Self {
+        RedEncoder {
+            pt,
+            depth,
+            history: VecDeque::with_capacity(depth),
+        }
+    }
+
+    /// Encode a frame into a RED payload.
+    ///
+    /// `time` is the time of the frame and the RTP time of the RED packet.
+    pub fn encode(&mut self, mtu: usize, time: MediaTime, data: &[u8]) -> Vec<u8> {
+        let numer = time.numer();
+
+        let mut redundant: Vec<(u64, &[u8])> = self
+            .history
+            .iter()
+            .filter_map(|(t, d)| {
+                let offset = numer.checked_sub(t.rebase(time.frequency()).numer())?;
+                let usable = offset > 0
+                    && offset <= MAX_TIMESTAMP_OFFSET
+                    && !d.is_empty()
+                    && d.len() <= MAX_BLOCK_LENGTH;
+                usable.then_some((offset, d.as_slice()))
+            })
+            .collect();
+
+        // Leave out the oldest frames until it fits.
+        let size = |r: &[(u64, &[u8])]| -> usize {
+            1 + data.len() + r.iter().map(|(_, d)| 4 + d.len()).sum::<usize>()
+        };
+        while !redundant.is_empty() && size(&redundant) > mtu {
+            redundant.remove(0);
+        }
+
+        let mut out = Vec::with_capacity(size(&redundant));
+        let pt = *self.pt & 0x7f;
+
+        for (offset, d) in &redundant {
+            let len = d.len();
+            out.push(0x80 | pt);
+            out.push((offset >> 6) as u8);
+            out.push(((offset & 0x3f) as u8) << 2 | (len >> 8) as u8);
+            out.push(len as u8);
+        }
+        out.push(pt);
+
+        for (_, d) in &redundant {
+            out.extend_from_slice(d);
+        }
+        out.extend_from_slice(data);
+
+        if self.depth > 0 {
+            if self.history.len() == self.depth {
+                self.history.pop_front();
+            }
+            self.history.push_back((time, data.to_vec()));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rtp_::Frequency;
+
+    use super::*;
+
+    const OPUS: u8 = 111;
+
+    fn time(v: u64) -> MediaTime {
+        MediaTime::new(v, Frequency::FORTY_EIGHT_KHZ)
+    }
+
+    fn frame(i: u8) -> Vec<u8> {
+        vec![0xfc, i, i, i]
+    }
+
+    #[test]
+    fn encode_block_headers() {
+        let mut enc = RedEncoder::new(OPUS.into(), 2);
+
+        // Nothing redundant yet.
+        assert_eq!(
+            enc.encode(1200, time(0), &frame(0)),
+            [&[OPUS][..], &frame(0)].concat()
+        );
+
+        let payload = enc.encode(1200, time(960), &frame(1));
+        assert_eq!(
+            payload,
+            [
+                &[0x80 | OPUS, 0x0f, 0x00, 0x04, OPUS][..],
+                &frame(0),
+                &frame(1)
+            ]
+            .concat()
+        );
+
+        let payload = enc.encode(1200, time(1920), &frame(2));
+        assert_eq!(
+            payload,
+            [
+                &[0x80 | OPUS, 0x1e, 0x00, 0x04][..],
+                &[0x80 | OPUS, 0x0f, 0x00, 0x04, OPUS],
+                &frame(0),
+                &frame(1),
+                &frame(2)
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn encode_within_mtu() {
+        let mut enc = RedEncoder::new(OPUS.into(), 2);
+        enc.encode(1200, time(0), &frame(0));
+        enc.encode(1200, time(960), &frame(1));
+
+        // Room for the primary and one redundant block.
+        let payload = enc.encode(13, time(1920), &frame(2));
+        assert_eq!(payload.len(), 13);
+
+        let blocks = parse_red(&payload).unwrap();
+        assert_eq!(
+            blocks,
+            [
+                (OPUS.into(), 960, &frame(1)[..]),
+                (OPUS.into(), 0, &frame(2)[..])
+            ]
+        );
+
+        // No room for redundancy.
+        let payload = enc.encode(8, time(2880), &frame(3));
+        assert_eq!(payload, [&[OPUS][..], &frame(3)].concat());
+    }
+
+    #[test]
+    fn encode_skips_old_frames() {
+        let mut enc = RedEncoder::new(OPUS.into(), 1);
+        enc.encode(1200, time(0), &frame(0));
+
+        // Beyond the 14 bit timestamp offset, such as after a long DTX pause.
+        let payload = enc.encode(1200, time(20_000), &frame(1));
+        assert_eq!(payload, [&[OPUS][..], &frame(1)].concat());
+    }
+
+    #[test]
+    fn decode_recovers_lost_primary() {
+        let mut enc = RedEncoder::new(OPUS.into(), 2);
+        let mut dec = RedDecoder::new(OPUS.into());
+
+        let payloads: Vec<_> = (0..4)
+            .map(|i| enc.encode(1200, time(i as u64 * 960), &frame(i)))
+            .collect();
+
+        let frames = dec.decode(10.into(), time(0), &payloads[0]).unwrap();
+        assert_eq!(
+            frames,
+            [RedFrame {
+                seq_no: 10.into(),
+                time: time(0),
+                data: frame(0),
+                recovered: false,
+            }]
+        );
+
+        // Packet 11 is lost, and recovered from packet 12.
+        let frames = dec.decode(12.into(), time(1920), &payloads[2]).unwrap();
+        assert_eq!(
+            frames,
+            [
+                RedFrame {
+                    seq_no: 11.into(),
+                    time: time(960),
+                    data: frame(1),
+                    recovered: true,
+                },
+                RedFrame {
+                    seq_no: 12.into(),
+                    time: time(1920),
+                    data: frame(2),
+                    recovered: false,
+                },
+            ]
+        );
+
+        // Everything but the primary is already received.
+        let frames = dec.decode(13.into(), time(2880), &payloads[3]).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].seq_no, 13.into());
+
+        // A late duplicate gives nothing.
+        let frames = dec.decode(11.into(), time(960), &payloads[1]).unwrap();
+        assert!(frames.is_empty());
+    }
+
+    #[test]
+    fn decode_ignores_other_pt() {
+        let mut enc = RedEncoder::new(OPUS.into(), 1);
+        enc.encode(1200, time(0), &frame(0));
+        let payload = enc.encode(1200, time(960), &frame(1));
+
+        let mut dec = RedDecoder::new(100.into());
+        assert!(dec
+            .decode(1.into(), time(960), &payload)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn decode_malformed() {
+        let mut dec = RedDecoder::new(OPUS.into());
+
+        assert_eq!(
+            dec.decode(1.into(), time(0), &[]),
+            Err(PacketError::ErrShortPacket)
+        );
+        // Header cut short.
+        assert_eq!(
+            dec.decode(1.into(), time(0), &[0x80 | OPUS, 0x0f]),
+            Err(PacketError::ErrShortPacket)
+        );
+        // No primary header.
+        assert_eq!(
+            dec.decode(1.into(), time(0), &[0x80 | OPUS, 0x0f, 0x00, 0x04]),
+            Err(PacketError::ErrShortPacket)
+        );
+        // Block longer than the payload.
+        assert_eq!(
+            dec.decode(
+                1.into(),
+                time(0),
+                &[0x80 | OPUS, 0x0f, 0x00, 0x04, OPUS, 1, 2]
+            ),
+            Err(PacketError::ErrRedCorruptedPacket)
+        );
+    }
+}
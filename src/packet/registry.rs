@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::Arc;
+
+use crate::format::Codec;
+use crate::rtp_::Pt;
+
+use super::{CodecDepacketizer, CodecPacketizer, Depacketizer, Packetizer};
+
+type PacketizerFactory =
+    Arc<dyn Fn() -> Box<dyn Packetizer + Send + Sync + UnwindSafe> + Send + Sync + RefUnwindSafe>;
+
+type DepacketizerFactory =
+    Arc<dyn Fn() -> Box<dyn Depacketizer + Send + Sync + UnwindSafe> + Send + Sync + RefUnwindSafe>;
+
+/// Key to register a custom packetizer or depacketizer in a [`CodecRegistry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodecKey {
+    /// A specific payload type.
+    Pt(Pt),
+    /// All payload types of a codec.
+    Codec(Codec),
+}
+
+impl From<Pt> for CodecKey {
+    fn from(v: Pt) -> Self {
+        CodecKey::Pt(v)
+    }
+}
+
+impl From<Codec> for CodecKey {
+    fn from(v: Codec) -> Self {
+        CodecKey::Codec(v)
+    }
+}
+
+/// Custom packetizers and depacketizers used instead of the built in ones.
+///
+/// A new instance is created for each stream using the registered function. Lookup is
+/// first by payload type, then by codec. Anything not registered uses the built in
+/// implementation for the codec.
+///
+/// ```
+/// use str0m::format::{Codec, CodecExtra, CodecRegistry, Depacketizer, Packetizer};
+/// use str0m::error::PacketError;
+/// use str0m::media::{Frequency, Pt};
+/// use str0m::RtcConfig;
+///
+/// // Telemetry sent as a payload with a one byte sequence header.
+/// #[derive(Debug, Default)]
+/// struct TelemetryPacketizer {
+///     count: u8,
+/// }
+///
+/// impl Packetizer for TelemetryPacketizer {
+///     fn packetize(&mut self, mtu: usize, b: &[u8]) -> Result<Vec<Vec<u8>>, PacketError> {
+///         let mut out = vec![self.count];
+///         out.extend_from_slice(&b[..b.len().min(mtu - 1)]);
+///         self.count = self.count.wrapping_add(1);
+///         Ok(vec![out])
+///     }
+///
+///     fn is_marker(&mut self, _data: &[u8], _previous: Option<&[u8]>, last: bool) -> bool {
+///         last
+///     }
+///
+///     fn reset(&mut self) {
+///         self.count = 0;
+///     }
+/// }
+///
+/// #[derive(Debug, Default)]
+/// struct TelemetryDepacketizer;
+///
+/// impl Depacketizer for TelemetryDepacketizer {
+///     fn depacketize(
+///         &mut self,
+///         packet: &[u8],
+///         out: &mut Vec<u8>,
+///         _: &mut CodecExtra,
+///     ) -> Result<(), PacketError> {
+///         let [_, data @ ..] = packet else {
+///             return Err(PacketError::ErrShortPacket);
+///         };
+///         out.extend_from_slice(data);
+///         Ok(())
+///     }
+///
+///     fn is_partition_head(&self, _packet: &[u8]) -> bool {
+///         true
+///     }
+///
+///     fn is_partition_tail(&self, _marker: bool, _packet: &[u8]) -> bool {
+///         true
+///     }
+/// }
+///
+/// let pt = Pt::new_with_value(110);
+///
+/// let mut registry = CodecRegistry::new();
+/// registry.register_packetizer(pt, TelemetryPacketizer::default);
+/// registry.register_depacketizer(pt, TelemetryDepacketizer::default);
+///
+/// let mut config = RtcConfig::new().set_codec_registry(registry);
+///
+/// // The payload type is carried as a generic codec.
+/// config
+///     .codec_config()
+///     .add_config(pt, None, Codec::Generic, Frequency::NINETY_KHZ, None, Default::default());
+/// ```
+#[derive(Clone, Default)]
+pub struct CodecRegistry {
+    packetizers: HashMap<CodecKey, PacketizerFactory>,
+    depacketizers: HashMap<CodecKey, DepacketizerFactory>,
+}
+
+impl CodecRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a packetizer for a payload type or codec.
+    ///
+    /// Replaces any previous packetizer for the same key.
+    pub fn register_packetizer<P, F>(&mut self, key: impl Into<CodecKey>, f: F)
+    where
+        P: Packetizer + Send + Sync + UnwindSafe + 'static,
+        F: Fn() -> P + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.packetizers
+            .insert(key.into(), Arc::new(move || Box::new(f())));
+    }
+
+    /// Register a depacketizer for a payload type or codec.
+    ///
+    /// Replaces any previous depacketizer for the same key.
+    pub fn register_depacketizer<D, F>(&mut self, key: impl Into<CodecKey>, f: F)
+    where
+        D: Depacketizer + Send + Sync + UnwindSafe + 'static,
+        F: Fn() -> D + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.depacketizers
+            .insert(key.into(), Arc::new(move || Box::new(f())));
+    }
+
+    /// Whether there is a custom packetizer or depacketizer for the key.
+    pub fn contains(&self, key: impl Into<CodecKey>) -> bool {
+        let key = key.into();
+        self.packetizers.contains_key(&key) || self.depacketizers.contains_key(&key)
+    }
+
+    pub(crate) fn packetizer(&self, pt: Pt, codec: Codec) -> CodecPacketizer {
+        let f = self
+            .packetizers
+            .get(&CodecKey::Pt(pt))
+            .or_else(|| self.packetizers.get(&CodecKey::Codec(codec)));
+
+        match f {
+            Some(f) => CodecPacketizer::Boxed(f()),
+            None => codec.into(),
+        }
+    }
+
+    pub(crate) fn depacketizer(&self, pt: Pt, codec: Codec) -> CodecDepacketizer {
+        let f = self
+            .depacketizers
+            .get(&CodecKey::Pt(pt))
+            .or_else(|| self.depacketizers.get(&CodecKey::Codec(codec)));
+
+        match f {
+            Some(f) => CodecDepacketizer::Boxed(f()),
+            None => codec.into(),
+        }
+    }
+}
+
+impl fmt::Debug for CodecRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CodecRegistry")
+            .field("packetizers", &self.packetizers.keys().collect::<Vec<_>>())
+            .field(
+                "depacketizers",
+                &self.depacketizers.keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::{CodecExtra, PacketError};
+
+    #[derive(Debug, Default)]
+    struct Reverse;
+
+    impl Depacketizer for Reverse {
+        fn depacketize(
+            &mut self,
+            packet: &[u8],
+            out: &mut Vec<u8>,
+            _: &mut CodecExtra,
+        ) -> Result<(), PacketError> {
+            out.extend(packet.iter().rev());
+            Ok(())
+        }
+
+        fn is_partition_head(&self, _packet: &[u8]) -> bool {
+            true
+        }
+
+        fn is_partition_tail(&self, _marker: bool, _packet: &[u8]) -> bool {
+            true
+        }
+    }
+
+    fn depack(registry: &CodecRegistry, pt: u8, codec: Codec) -> Vec<u8> {
+        let mut d = registry.depacketizer(Pt::new_with_value(pt), codec);
+        let mut out = vec![];
+        d.depacketize(&[1, 2, 3], &mut out, &mut CodecExtra::None)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn lookup_order() {
+        let mut registry = CodecRegistry::new();
+        registry.register_depacketizer(Pt::new_with_value(100), || Reverse);
+
+        assert!(registry.contains(Pt::new_with_value(100)));
+        assert!(!registry.contains(Codec::Generic));
+
+        // By payload type, otherwise built in.
+        assert_eq!(depack(&registry, 100, Codec::Generic), &[3, 2, 1]);
+        assert_eq!(depack(&registry, 101, Codec::Generic), &[1, 2, 3]);
+
+        // By codec.
+        registry.register_depacketizer(Codec::Generic, || Reverse);
+        assert_eq!(depack(&registry, 101, Codec::Generic), &[3, 2, 1]);
+
+        let p = registry.packetizer(Pt::new_with_value(100), Codec::Generic);
+        assert!(matches!(p, CodecPacketizer::Generic(_)));
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::media::KeyframeRequestKind;
+
+const DEFAULT_REQUEST_DELAY: Duration = Duration::from_millis(100);
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sans-IO coordination of switching the forwarded source to one that needs a keyframe.
+///
+/// A receiver can only start decoding a new simulcast layer, or a new source after a
+/// failover, at a keyframe. The switcher keeps forwarding the current source until a
+/// keyframe starts on the new one, and asks for a keyframe when none arrives by itself.
+///
+/// * [`SourceSwitcher::request_switch`] starts a switch, with `T` identifying the source,
+///   such as a [`Rid`][crate::media::Rid] or an SSRC.
+/// * [`SourceSwitcher::handle_keyframe`] for every keyframe start on any source, as told
+///   by [`is_keyframe_start`][crate::format::is_keyframe_start].
+/// * [`SourceSwitcher::handle_timeout`] when [`SourceSwitcher::poll_timeout`] is reached.
+/// * [`SourceSwitcher::poll_output`] after each of the above until `None`.
+///
+/// Keyframe requests are limited, the first after the request delay, then retried at an
+/// interval doubling each time. If no keyframe arrives before the timeout, the switch
+/// fails and the current source stays.
+///
+/// ```
+/// # use std::time::Instant;
+/// # use str0m::media::{Rid, SourceSwitcher, SwitchOutput};
+/// let mut switcher = SourceSwitcher::new();
+/// let now = Instant::now();
+///
+/// switcher.request_switch(now, Rid::from("h"));
+///
+/// // No keyframe in time, ask for one.
+/// let later = switcher.poll_timeout().unwrap();
+/// switcher.handle_timeout(later);
+/// assert!(matches!(
+///     switcher.poll_output(),
+///     Some(SwitchOutput::RequestKeyframe { .. })
+/// ));
+///
+/// // The keyframe arrives, forward "h" from this packet.
+/// switcher.handle_keyframe(&Rid::from("h"));
+/// assert!(matches!(switcher.poll_output(), Some(SwitchOutput::Switch { .. })));
+/// assert_eq!(switcher.current(), Some(&Rid::from("h")));
+/// ```
+#[derive(Debug)]
+pub struct SourceSwitcher<T> {
+    request_delay: Duration,
+    retry_interval: Duration,
+    timeout: Duration,
+    current: Option<T>,
+    pending: Option<Pending<T>>,
+    output: VecDeque<SwitchOutput<T>>,
+}
+
+#[derive(Debug)]
+struct Pending<T> {
+    to: T,
+    started: Instant,
+    /// When to send the next keyframe request.
+    next_request: Instant,
+    /// Number of keyframe requests sent.
+    requests: u32,
+}
+
+/// Output from the [`SourceSwitcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitchOutput<T> {
+    /// Forward `to` instead of `from`, starting with the keyframe just handled.
+    Switch {
+        /// The source forwarded until now. None if there was none.
+        from: Option<T>,
+        /// The source to forward from now on.
+        to: T,
+    },
+    /// Send a keyframe request for the source.
+    RequestKeyframe {
+        /// The source to ask for a keyframe.
+        source: T,
+        /// The kind of request.
+        kind: KeyframeRequestKind,
+    },
+    /// No keyframe arrived in time, the current source stays.
+    Failed {
+        /// The source that was switched to.
+        to: T,
+    },
+}
+
+impl<T: Clone + PartialEq> SourceSwitcher<T> {
+    /// Create a switcher without a current source.
+    pub fn new() -> Self {
+        SourceSwitcher {
+            request_delay: DEFAULT_REQUEST_DELAY,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            current: None,
+            pending: None,
+            output: VecDeque::new(),
+        }
+    }
+
+    /// How long to wait for a keyframe before requesting one.
+    ///
+    /// Defaults to 100ms.
+    pub fn set_request_delay(mut self, delay: Duration) -> Self {
+        self.request_delay = delay;
+        self
+    }
+
+    /// How long to wait for a keyframe after the first request before asking again. This
+    /// doubles for each request.
+    ///
+    /// Defaults to 500ms.
+    pub fn set_retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// How long from the switch request until it fails.
+    ///
+    /// Defaults to 5s.
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The source currently forwarded.
+    pub fn current(&self) -> Option<&T> {
+        self.current.as_ref()
+    }
+
+    /// The source being switched to, while waiting for its keyframe.
+    pub fn pending(&self) -> Option<&T> {
+        self.pending.as_ref().map(|p| &p.to)
+    }
+
+    /// Switch to forwarding `to` at its next keyframe.
+    ///
+    /// Replaces any switch in progress. Switching to the current source cancels it.
+    pub fn request_switch(&mut self, now: Instant, to: T) {
+        if self.current.as_ref() == Some(&to) {
+            self.pending = None;
+            return;
+        }
+
+        if self.pending.as_ref().map_or(false, |p| p.to == to) {
+            return;
+        }
+
+        self.pending = Some(Pending {
+            to,
+            started: now,
+            next_request: now + self.request_delay,
+            requests: 0,
+        });
+    }
+
+    /// A keyframe starts on `source`.
+    ///
+    /// If that's the source being switched to, [`SwitchOutput::Switch`] is output, and the
+    /// packet with the keyframe start is the first to forward from it.
+    pub fn handle_keyframe(&mut self, source: &T) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+
+        if pending.to != *source {
+            return;
+        }
+
+        let to = pending.to.clone();
+        self.pending = None;
+
+        let from = self.current.replace(to.clone());
+        self.output.push_back(SwitchOutput::Switch { from, to });
+    }
+
+    /// Drive time forward, to request keyframes and time out.
+    pub fn handle_timeout(&mut self, now: Instant) {
+        let Some(pending) = &mut self.pending else {
+            return;
+        };
+
+        if now >= pending.started + self.timeout {
+            let to = pending.to.clone();
+            self.pending = None;
+            self.output.push_back(SwitchOutput::Failed { to });
+            return;
+        }
+
+        if now < pending.next_request {
+            return;
+        }
+
+        let interval = self.retry_interval * 2_u32.saturating_pow(pending.requests);
+        pending.requests += 1;
+        pending.next_request = now + interval;
+
+        self.output.push_back(SwitchOutput::RequestKeyframe {
+            source: pending.to.clone(),
+            kind: KeyframeRequestKind::Pli,
+        });
+    }
+
+    /// When to call [`SourceSwitcher::handle_timeout`] next.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        let pending = self.pending.as_ref()?;
+        Some(pending.next_request.min(pending.started + self.timeout))
+    }
+
+    /// Poll for output.
+    pub fn poll_output(&mut self) -> Option<SwitchOutput<T>> {
+        self.output.pop_front()
+    }
+}
+
+impl<T: Clone + PartialEq> Default for SourceSwitcher<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// A sender of layers that answers keyframe requests after a delay, over a network
+    /// that loses some of the requests.
+    struct Sim {
+        now: Instant,
+        start: Instant,
+        switcher: SourceSwitcher<u8>,
+        /// Keyframes due on a layer.
+        keyframes: Vec<(Instant, u8)>,
+        /// Indices of requests lost on the way.
+        lost: Vec<usize>,
+        requests: Vec<(Duration, u8)>,
+        outputs: Vec<(Duration, SwitchOutput<u8>)>,
+    }
+
+    impl Sim {
+        fn new(switcher: SourceSwitcher<u8>, lost: &[usize]) -> Self {
+            let now = Instant::now();
+            Sim {
+                now,
+                start: now,
+                switcher,
+                keyframes: vec![],
+                lost: lost.to_vec(),
+                requests: vec![],
+                outputs: vec![],
+            }
+        }
+
+        fn elapsed(&self) -> Duration {
+            self.now - self.start
+        }
+
+        /// Run for `dur` in steps of 1ms.
+        fn run(&mut self, dur: Duration) {
+            let end = self.now + dur;
+            while self.now < end {
+                self.now += MS;
+
+                let due: Vec<_> = self
+                    .keyframes
+                    .iter()
+                    .filter(|(at, _)| *at <= self.now)
+                    .map(|(_, l)| *l)
+                    .collect();
+                self.keyframes.retain(|(at, _)| *at > self.now);
+                for layer in due {
+                    self.switcher.handle_keyframe(&layer);
+                }
+
+                if self
+                    .switcher
+                    .poll_timeout()
+                    .map_or(false, |t| t <= self.now)
+                {
+                    self.switcher.handle_timeout(self.now);
+                }
+
+                while let Some(o) = self.switcher.poll_output() {
+                    if let SwitchOutput::RequestKeyframe { source, .. } = &o {
+                        let index = self.requests.len();
+                        self.requests.push((self.elapsed(), *source));
+                        if !self.lost.contains(&index) {
+                            // The sender takes 20ms to produce the keyframe.
+                            self.keyframes.push((self.now + 20 * MS, *source));
+                        }
+                    }
+                    self.outputs.push((self.elapsed(), o));
+                }
+            }
+        }
+
+        fn switches(&self) -> Vec<(Duration, Option<u8>, u8)> {
+            self.outputs
+                .iter()
+                .filter_map(|(t, o)| match o {
+                    SwitchOutput::Switch { from, to } => Some((*t, *from, *to)),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn natural_keyframe_no_request() {
+        let mut sim = Sim::new(SourceSwitcher::new(), &[]);
+
+        sim.switcher.request_switch(sim.now, 0);
+        sim.keyframes.push((sim.now + 50 * MS, 0));
+        sim.run(Duration::from_secs(1));
+
+        assert!(sim.requests.is_empty());
+        assert_eq!(sim.switches(), vec![(50 * MS, None, 0)]);
+        assert_eq!(sim.switcher.current(), Some(&0));
+        assert_eq!(sim.switcher.poll_timeout(), None);
+    }
+
+    #[test]
+    fn keyframe_on_other_layer_ignored() {
+        let mut sim = Sim::new(SourceSwitcher::new(), &[]);
+
+        sim.switcher.request_switch(sim.now, 1);
+        sim.keyframes.push((sim.now + 50 * MS, 0));
+        sim.run(Duration::from_secs(1));
+
+        // Requested after 100ms, answered 20ms later.
+        assert_eq!(sim.requests, vec![(100 * MS, 1)]);
+        assert_eq!(sim.switches(), vec![(120 * MS, None, 1)]);
+    }
+
+    #[test]
+    fn first_request_lost_converges() {
+        let mut sim = Sim::new(SourceSwitcher::new(), &[]);
+        sim.switcher.request_switch(sim.now, 0);
+        sim.keyframes.push((sim.now, 0));
+        sim.run(10 * MS);
+        assert_eq!(sim.switcher.current(), Some(&0));
+
+        // Switch up, with the first PLI lost.
+        sim.lost = vec![0];
+        let at = sim.elapsed();
+        sim.switcher.request_switch(sim.now, 2);
+        sim.run(Duration::from_secs(2));
+
+        // Still forwarding layer 0 while waiting, then the retry 500ms later works.
+        assert_eq!(sim.requests, vec![(at + 100 * MS, 2), (at + 600 * MS, 2)]);
+        assert_eq!(
+            sim.switches(),
+            vec![(MS, None, 0), (at + 620 * MS, Some(0), 2)]
+        );
+        assert_eq!(sim.switcher.current(), Some(&2));
+        assert_eq!(sim.switcher.pending(), None);
+    }
+
+    #[test]
+    fn timeout_stays_on_current() {
+        let switcher = SourceSwitcher::new().set_timeout(Duration::from_secs(3));
+        let mut sim = Sim::new(switcher, &[]);
+        sim.switcher.request_switch(sim.now, 0);
+        sim.keyframes.push((sim.now, 0));
+        sim.run(10 * MS);
+
+        // The sender of layer 1 never answers.
+        sim.lost = (0..100).collect();
+        let at = sim.elapsed();
+        sim.switcher.request_switch(sim.now, 1);
+        sim.run(Duration::from_secs(5));
+
+        // Backing off, 0.1, 0.6, 1.6 and then the timeout.
+        let times: Vec<_> = sim.requests.iter().map(|(t, _)| *t - at).collect();
+        assert_eq!(times, vec![100 * MS, 600 * MS, 1600 * MS]);
+
+        let (t, last) = sim.outputs.last().unwrap();
+        assert_eq!(*last, SwitchOutput::Failed { to: 1 });
+        assert_eq!(*t - at, 3000 * MS);
+        assert_eq!(sim.switcher.current(), Some(&0));
+        assert_eq!(sim.switcher.poll_timeout(), None);
+
+        // Retrying the switch starts over.
+        sim.lost.clear();
+        let at = sim.elapsed();
+        sim.switcher.request_switch(sim.now, 1);
+        sim.run(Duration::from_secs(1));
+        assert_eq!(sim.switches().last(), Some(&(at + 120 * MS, Some(0), 1)));
+    }
+
+    #[test]
+    fn cancel_and_replace() {
+        let now = Instant::now();
+        let mut switcher = SourceSwitcher::new();
+
+        switcher.request_switch(now, 0);
+        switcher.handle_keyframe(&0);
+        assert!(matches!(
+            switcher.poll_output(),
+            Some(SwitchOutput::Switch { to: 0, .. })
+        ));
+
+        // Back to the current cancels.
+        switcher.request_switch(now, 1);
+        switcher.request_switch(now, 0);
+        assert_eq!(switcher.pending(), None);
+        assert_eq!(switcher.poll_timeout(), None);
+
+        // A new target replaces the old.
+        switcher.request_switch(now, 1);
+        switcher.request_switch(now + 50 * MS, 2);
+        switcher.handle_keyframe(&1);
+        assert_eq!(switcher.poll_output(), None);
+        assert_eq!(switcher.poll_timeout(), Some(now + 150 * MS));
+    }
+}
@@ -0,0 +1,484 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+const DEFAULT_SMOOTHING: f32 = 0.3;
+const DEFAULT_TOP_N: usize = 3;
+const DEFAULT_MIN_HOLD: Duration = Duration::from_secs(1);
+const DEFAULT_DOMINANCE: f32 = 6.0;
+const DEFAULT_SILENCE: i8 = -70;
+
+/// Sans-IO active speaker detection from the audio level header extension.
+///
+/// Feed it the [`audio_level`][crate::rtp::ExtensionValues::audio_level] and
+/// [`voice_activity`][crate::rtp::ExtensionValues::voice_activity] of every incoming audio
+/// packet with [`AudioLevelAggregator::handle_level`], with `T` identifying the speaker,
+/// such as a [`Mid`][crate::media::Mid]. Drive it with
+/// [`AudioLevelAggregator::handle_timeout`], and it outputs a [`SpeakerRanking`] every
+/// interval.
+///
+/// The algorithm, per interval (default 100ms):
+///
+/// 1. Each packet is a loudness of `127 + level`, i.e. 0 for -127dBov up to 127 for 0dBov.
+///    A packet with the voice activity (VAD) bit explicitly off is loudness 0.
+/// 2. The loudness of a speaker in the interval is the mean of its packets. A speaker
+///    without packets, such as when the sender uses DTX, is loudness 0.
+/// 3. The level of a speaker is smoothed with `level += smoothing * (loudness - level)`.
+/// 4. A speaker is eligible when its level is above the silence level (default -70dBov).
+/// 5. The dominant speaker is replaced by the loudest eligible speaker if that is louder
+///    by the dominance threshold (default 6, i.e. 6dB), and the dominant has held the spot
+///    for the minimum hold time (default 1s). A dominant speaker that is no longer eligible
+///    is replaced by the loudest eligible, with the same minimum hold. Without anyone
+///    eligible, the last dominant speaker stays.
+/// 6. The top N (default 3) always has the dominant. Free spots go to the loudest eligible
+///    speakers. A full top N replaces its quietest member (not the dominant) by a louder
+///    speaker under the same threshold and hold time as the dominant. Members that are no
+///    longer eligible leave after the hold time.
+///
+/// Ties are resolved in the order speakers were first seen, which makes the output
+/// entirely given by the input.
+#[derive(Debug)]
+pub struct AudioLevelAggregator<T> {
+    interval: Duration,
+    smoothing: f32,
+    top_n: usize,
+    min_hold: Duration,
+    dominance: f32,
+    silence: f32,
+    /// In the order first seen.
+    speakers: Vec<Speaker<T>>,
+    dominant: Option<(T, Instant)>,
+    top: Vec<(T, Instant)>,
+    next_tick: Option<Instant>,
+    output: VecDeque<SpeakerRanking<T>>,
+}
+
+#[derive(Debug)]
+struct Speaker<T> {
+    id: T,
+    sum: f32,
+    count: u32,
+    level: f32,
+}
+
+/// Periodic output of the [`AudioLevelAggregator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerRanking<T> {
+    /// When the ranking was made.
+    pub time: Instant,
+    /// The dominant speaker, the last one to speak. None until anyone has spoken.
+    pub dominant: Option<T>,
+    /// Whether the dominant speaker is different from the last ranking.
+    pub dominant_changed: bool,
+    /// The top N speakers, the dominant first and then loudest first, with their smoothed
+    /// loudness 0..=127.
+    pub top: Vec<(T, f32)>,
+}
+
+impl<T: Clone + PartialEq> AudioLevelAggregator<T> {
+    /// Create an aggregator with the default settings.
+    pub fn new() -> Self {
+        AudioLevelAggregator {
+            interval: DEFAULT_INTERVAL,
+            smoothing: DEFAULT_SMOOTHING,
+            top_n: DEFAULT_TOP_N,
+            min_hold: DEFAULT_MIN_HOLD,
+            dominance: DEFAULT_DOMINANCE,
+            silence: loudness(DEFAULT_SILENCE),
+            speakers: vec![],
+            dominant: None,
+            top: vec![],
+            next_tick: None,
+            output: VecDeque::new(),
+        }
+    }
+
+    /// How often to rank the speakers.
+    ///
+    /// Defaults to 100ms.
+    pub fn set_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Weight of a new interval in the smoothed level, 0.0 < smoothing <= 1.0.
+    ///
+    /// Defaults to 0.3.
+    pub fn set_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(f32::EPSILON, 1.0);
+        self
+    }
+
+    /// How many speakers to rank.
+    ///
+    /// Defaults to 3.
+    pub fn set_top_n(mut self, top_n: usize) -> Self {
+        self.top_n = top_n.max(1);
+        self
+    }
+
+    /// How long a speaker keeps a spot before it can be taken.
+    ///
+    /// Defaults to 1s.
+    pub fn set_min_hold(mut self, min_hold: Duration) -> Self {
+        self.min_hold = min_hold;
+        self
+    }
+
+    /// How much louder, in dB, a speaker must be to take a spot.
+    ///
+    /// Defaults to 6.0.
+    pub fn set_dominance(mut self, dominance: f32) -> Self {
+        self.dominance = dominance;
+        self
+    }
+
+    /// The level in dBov, -127..=0, at or below which a speaker is silent.
+    ///
+    /// Defaults to -70.
+    pub fn set_silence_level(mut self, level: i8) -> Self {
+        self.silence = loudness(level);
+        self
+    }
+
+    /// The audio level of a packet from `speaker`.
+    ///
+    /// `level` is in dBov, -127..=0, as in the extension.
+    pub fn handle_level(
+        &mut self,
+        now: Instant,
+        speaker: T,
+        level: i8,
+        voice_activity: Option<bool>,
+    ) {
+        self.next_tick.get_or_insert(now + self.interval);
+
+        let v = if voice_activity == Some(false) {
+            0.0
+        } else {
+            loudness(level)
+        };
+
+        let s = match self.speakers.iter_mut().position(|s| s.id == speaker) {
+            Some(i) => &mut self.speakers[i],
+            None => {
+                self.speakers.push(Speaker {
+                    id: speaker,
+                    sum: 0.0,
+                    count: 0,
+                    level: 0.0,
+                });
+                self.speakers.last_mut().unwrap()
+            }
+        };
+
+        s.sum += v;
+        s.count += 1;
+    }
+
+    /// Stop tracking a speaker.
+    pub fn remove_speaker(&mut self, speaker: &T) {
+        self.speakers.retain(|s| s.id != *speaker);
+        self.top.retain(|(id, _)| id != speaker);
+        if self
+            .dominant
+            .as_ref()
+            .map_or(false, |(id, _)| id == speaker)
+        {
+            self.dominant = None;
+        }
+    }
+
+    /// Drive time forward, making a ranking for every interval passed.
+    pub fn handle_timeout(&mut self, now: Instant) {
+        let Some(mut tick) = self.next_tick else {
+            return;
+        };
+
+        while tick <= now {
+            self.rank(tick);
+            tick += self.interval;
+        }
+
+        self.next_tick = Some(tick);
+    }
+
+    /// When to call [`AudioLevelAggregator::handle_timeout`] next.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        self.next_tick
+    }
+
+    /// Poll for rankings.
+    pub fn poll_output(&mut self) -> Option<SpeakerRanking<T>> {
+        self.output.pop_front()
+    }
+
+    fn rank(&mut self, now: Instant) {
+        for s in &mut self.speakers {
+            let v = if s.count > 0 {
+                s.sum / s.count as f32
+            } else {
+                0.0
+            };
+            s.level += self.smoothing * (v - s.level);
+            s.sum = 0.0;
+            s.count = 0;
+        }
+
+        // Loudest first, ties in the order first seen thanks to the stable sort.
+        let mut order: Vec<usize> = (0..self.speakers.len()).collect();
+        order.sort_by(|a, b| {
+            let (a, b) = (&self.speakers[*a], &self.speakers[*b]);
+            b.level.total_cmp(&a.level)
+        });
+        let eligible: Vec<&Speaker<T>> = order
+            .iter()
+            .map(|i| &self.speakers[*i])
+            .filter(|s| s.level > self.silence)
+            .collect();
+
+        let before = self.dominant.as_ref().map(|(id, _)| id.clone());
+
+        // The dominant speaker.
+        let loudest = eligible.first();
+        match (&self.dominant, loudest) {
+            (None, Some(l)) => self.dominant = Some((l.id.clone(), now)),
+            (Some((id, since)), Some(l)) if l.id != *id => {
+                let held = now.saturating_duration_since(*since) >= self.min_hold;
+                let level = self.level(id);
+                let louder = l.level > level + self.dominance || level <= self.silence;
+                if held && louder {
+                    self.dominant = Some((l.id.clone(), now));
+                }
+            }
+            _ => {}
+        }
+
+        // The top N, which always has the dominant.
+        if let Some((id, _)) = &self.dominant {
+            if !self.top.iter().any(|(t, _)| t == id) {
+                self.top.push((id.clone(), now));
+            }
+        }
+
+        let held = |since: &Instant| now.saturating_duration_since(*since) >= self.min_hold;
+        let dominant = self.dominant.as_ref().map(|(id, _)| id.clone());
+
+        // Silent members leave after the hold time.
+        let levels: Vec<f32> = self.top.iter().map(|(id, _)| self.level(id)).collect();
+        let mut i = 0;
+        self.top.retain(|(id, since)| {
+            let keep = levels[i] > self.silence || !held(since) || Some(id) == dominant.as_ref();
+            i += 1;
+            keep
+        });
+
+        for s in &eligible {
+            if self.top.iter().any(|(t, _)| *t == s.id) {
+                continue;
+            }
+
+            if self.top.len() < self.top_n {
+                self.top.push((s.id.clone(), now));
+                continue;
+            }
+
+            // The quietest member that isn't the dominant.
+            let weakest = self
+                .top
+                .iter()
+                .enumerate()
+                .filter(|(_, (id, _))| Some(id) != dominant.as_ref())
+                .min_by(|a, b| self.level(&a.1 .0).total_cmp(&self.level(&b.1 .0)))
+                .map(|(i, (id, since))| (i, self.level(id), *since));
+
+            if let Some((i, level, since)) = weakest {
+                if held(&since) && s.level > level + self.dominance {
+                    self.top[i] = (s.id.clone(), now);
+                }
+            }
+        }
+
+        // Too many, such as after lowering top N.
+        self.top.truncate(self.top_n);
+
+        let mut top: Vec<(T, f32)> = self
+            .top
+            .iter()
+            .map(|(id, _)| (id.clone(), self.level(id)))
+            .collect();
+        top.sort_by(|a, b| {
+            let a_dom = Some(&a.0) == dominant.as_ref();
+            let b_dom = Some(&b.0) == dominant.as_ref();
+            b_dom.cmp(&a_dom).then(b.1.total_cmp(&a.1))
+        });
+
+        self.output.push_back(SpeakerRanking {
+            time: now,
+            dominant_changed: dominant != before,
+            dominant,
+            top,
+        });
+    }
+
+    fn level(&self, id: &T) -> f32 {
+        self.speakers
+            .iter()
+            .find(|s| s.id == *id)
+            .map(|s| s.level)
+            .unwrap_or(0.0)
+    }
+}
+
+impl<T: Clone + PartialEq> Default for AudioLevelAggregator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn loudness(level: i8) -> f32 {
+    (127 + level.clamp(-127, 0) as i16) as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// Run for `until` ms, with `packets` sending the packets of each 20ms.
+    fn run(
+        agg: &mut AudioLevelAggregator<char>,
+        until: u64,
+        mut packets: impl FnMut(&mut AudioLevelAggregator<char>, Instant, u64),
+    ) -> (Instant, Vec<SpeakerRanking<char>>) {
+        let start = Instant::now();
+        let mut out = vec![];
+
+        for t in (0..until).step_by(20) {
+            let now = start + Duration::from_millis(t);
+            agg.handle_timeout(now);
+            while let Some(r) = agg.poll_output() {
+                out.push(r);
+            }
+            packets(agg, now, t);
+        }
+
+        (start, out)
+    }
+
+    /// The dominant speaker changes as (ms since start, new dominant).
+    fn switches(start: Instant, out: &[SpeakerRanking<char>]) -> Vec<(u64, Option<char>)> {
+        out.iter()
+            .filter(|r| r.dominant_changed)
+            .map(|r| (ms(start, r), r.dominant))
+            .collect()
+    }
+
+    fn ms(start: Instant, r: &SpeakerRanking<char>) -> u64 {
+        (r.time - start).as_millis() as u64
+    }
+
+    fn top_at(start: Instant, out: &[SpeakerRanking<char>], at: u64) -> Vec<char> {
+        let r = out.iter().find(|r| ms(start, r) == at).unwrap();
+        r.top.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn three_speakers_trading_off() {
+        let mut agg = AudioLevelAggregator::new();
+
+        // A talks first, then B, with C coughing briefly while B talks, then C talks.
+        // When not talking, they send -100dBov without VAD.
+        let script: &[(char, &[(u64, u64)])] = &[
+            ('a', &[(0, 3000)]),
+            ('b', &[(3000, 6000)]),
+            ('c', &[(4500, 5000), (6000, 9000)]),
+        ];
+
+        let (start, out) = run(&mut agg, 9000, |agg, now, t| {
+            for (id, talks) in script {
+                if talks.iter().any(|(a, b)| (*a..*b).contains(&t)) {
+                    agg.handle_level(now, *id, -20, Some(true));
+                } else {
+                    agg.handle_level(now, *id, -100, Some(false));
+                }
+            }
+        });
+
+        // A once its smoothed level is above silence, then B and C the same way, since the
+        // previous speaker decays at the same time. The cough doesn't make C dominant.
+        assert_eq!(
+            switches(start, &out),
+            vec![(300, Some('a')), (3300, Some('b')), (6300, Some('c'))]
+        );
+
+        // The cough gets C into the top 3 though, with A gone silent.
+        assert_eq!(top_at(start, &out, 4900), vec!['b', 'c']);
+        assert_eq!(top_at(start, &out, 8900), vec!['c']);
+
+        // The ranking is output every interval.
+        assert_eq!(out.len(), 89);
+    }
+
+    #[test]
+    fn min_hold() {
+        let mut agg = AudioLevelAggregator::new();
+
+        // B starts talking shortly after A, much louder.
+        let (start, out) = run(&mut agg, 5000, |agg, now, t| {
+            agg.handle_level(now, 'a', -50, Some(true));
+            if t >= 300 {
+                agg.handle_level(now, 'b', -10, Some(true));
+            }
+        });
+
+        // A got it at 400ms, B is louder soon after but has to wait for the hold.
+        assert_eq!(
+            switches(start, &out),
+            vec![(400, Some('a')), (1400, Some('b'))]
+        );
+        assert_eq!(top_at(start, &out, 4900), vec!['b', 'a']);
+    }
+
+    #[test]
+    fn top_n_hysteresis() {
+        let mut agg = AudioLevelAggregator::new().set_top_n(2);
+
+        // A dominant throughout. C from 0.5s slightly louder than B, which isn't enough to
+        // take B's spot. D from 2s clearly louder than B.
+        let (start, out) = run(&mut agg, 4000, |agg, now, t| {
+            agg.handle_level(now, 'a', -10, Some(true));
+            agg.handle_level(now, 'b', -40, Some(true));
+            if t >= 500 {
+                agg.handle_level(now, 'c', -37, Some(true));
+            }
+            if t >= 2000 {
+                agg.handle_level(now, 'd', -25, Some(true));
+            }
+        });
+
+        assert_eq!(top_at(start, &out, 1900), vec!['a', 'b']);
+        assert_eq!(top_at(start, &out, 3900), vec!['a', 'd']);
+        assert_eq!(switches(start, &out), vec![(200, Some('a'))]);
+    }
+
+    #[test]
+    fn silence_and_dtx() {
+        let mut agg = AudioLevelAggregator::new();
+
+        // A talks for 1s, then stops sending (DTX).
+        let (start, out) = run(&mut agg, 3000, |agg, now, t| {
+            if t < 1000 {
+                agg.handle_level(now, 'a', -20, None);
+            }
+        });
+
+        // Without anyone else eligible, A stays dominant also when silent.
+        assert_eq!(switches(start, &out), vec![(300, Some('a'))]);
+        let last = out.last().unwrap();
+        assert_eq!(last.dominant, Some('a'));
+        assert_eq!(last.top.len(), 1);
+        assert!(last.top[0].1 < 1.0);
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::rtp_::{MediaTime, SenderInfo, Ssrc};
+
+/// Number of sender reports used for the RTP to NTP mapping.
+const MAX_REPORTS: usize = 8;
+
+/// Sender reports must span this for the RTP clock rate to be estimated from them.
+const MIN_RATE_SPAN: f64 = 2.0;
+
+/// Largest difference of an estimated RTP clock rate from the nominal, before it's
+/// considered broken.
+const MAX_RATE_DEVIATION: f64 = 0.05;
+
+/// Largest difference between the NTP time of a sender report and what the current
+/// mapping says, before the report is rejected as an outlier.
+const MAX_REPORT_DEVIATION: f64 = 0.02;
+
+/// Consecutive outliers after which the sender clock is considered to have stepped.
+const MAX_REJECTED: usize = 3;
+
+/// Weight of a new packet in the smoothed transit time.
+const TRANSIT_SMOOTHING: f64 = 0.05;
+
+/// Packets in the smoothed transit time before it's used.
+const MIN_TRANSIT_PACKETS: u32 = 50;
+
+/// Sans-IO lip sync of the audio and video streams of a remote participant.
+///
+/// Sender reports (SR) pair an RTP time of an SSRC with the NTP time of the sender's
+/// clock. The streams from one sender share that clock, which is what the CNAME tells,
+/// and that makes it possible to see how much later one stream arrives than the other
+/// for media captured at the same time.
+///
+/// * [`SyncGroups::set_cname`] for every SSRC, such as from
+///   [`StreamRx::cname`][crate::rtp::StreamRx::cname].
+/// * [`SyncGroups::handle_sender_info`] for every SR, such as
+///   [`RtpPacket::last_sender_info`][crate::rtp::RtpPacket::last_sender_info] when it
+///   changes.
+/// * [`SyncGroups::handle_packet`] for every received packet, with its arrival time.
+/// * [`SyncGroups::offset`] for how much to delay the audio or video.
+///
+/// Per SSRC, the last 8 SRs make the mapping from RTP to NTP time. The RTP clock rate is
+/// estimated from them, which makes the mapping follow a sender whose RTP clock is
+/// skewed to its NTP clock. An SR more than 20ms off the mapping is rejected as an
+/// outlier. After 3 such in a row, the sender clock is considered to have stepped, and
+/// the mapping starts over.
+///
+/// The transit time of a packet is the arrival minus the capture time, where the capture
+/// time is the NTP time of the packet's RTP time. It's smoothed per SSRC, and the offset
+/// is the difference in transit time between the video and the audio, once there are 50
+/// packets of each after the last SR restarting the mapping. The offset is for
+/// playing out packets as they arrive; any difference in the buffering of the player
+/// comes on top.
+#[derive(Debug, Default)]
+pub struct SyncGroups {
+    streams: HashMap<Ssrc, SyncStream>,
+}
+
+#[derive(Debug, Default)]
+struct SyncStream {
+    cname: Option<String>,
+    /// RTP clock rate from the packets, or the SRs before any packet.
+    clock_rate: Option<u32>,
+    /// RTP and NTP time of the SRs in use for the mapping, oldest first.
+    reports: VecDeque<(u32, Instant)>,
+    /// Consecutive SRs rejected as outliers.
+    rejected: usize,
+    /// Smoothed transit time in seconds, and the number of packets in it.
+    transit: Option<(f64, u32)>,
+}
+
+/// Which side to delay for audio and video to play in sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOffset {
+    /// The video arrives later, delay the audio by this much.
+    DelayAudio(Duration),
+    /// The audio arrives later, delay the video by this much.
+    DelayVideo(Duration),
+}
+
+impl SyncGroups {
+    /// Create an empty set of groups.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the CNAME of an SSRC, which groups it with other SSRCs of the same sender.
+    pub fn set_cname(&mut self, ssrc: Ssrc, cname: &str) {
+        let stream = self.streams.entry(ssrc).or_default();
+        if stream.cname.as_deref() != Some(cname) {
+            stream.cname = Some(cname.to_string());
+        }
+    }
+
+    /// The CNAME of an SSRC, if known.
+    pub fn cname(&self, ssrc: Ssrc) -> Option<&str> {
+        self.streams.get(&ssrc)?.cname.as_deref()
+    }
+
+    /// The SSRCs with the given CNAME.
+    pub fn ssrcs<'a>(&'a self, cname: &'a str) -> impl Iterator<Item = Ssrc> + 'a {
+        self.streams
+            .iter()
+            .filter(move |(_, s)| s.cname.as_deref() == Some(cname))
+            .map(|(ssrc, _)| *ssrc)
+    }
+
+    /// Stop tracking an SSRC.
+    pub fn remove_ssrc(&mut self, ssrc: Ssrc) {
+        self.streams.remove(&ssrc);
+    }
+
+    /// Handle a sender report for an SSRC.
+    ///
+    /// Giving the same SR again does nothing.
+    pub fn handle_sender_info(&mut self, info: &SenderInfo) {
+        let stream = self.streams.entry(info.ssrc).or_default();
+        stream
+            .clock_rate
+            .get_or_insert(info.rtp_time.frequency().get());
+        stream.handle_report(info.rtp_time.numer() as u32, info.ntp_time);
+    }
+
+    /// Handle a received packet of an SSRC, with the RTP time of the packet.
+    pub fn handle_packet(&mut self, ssrc: Ssrc, time: MediaTime, arrival: Instant) {
+        let stream = self.streams.entry(ssrc).or_default();
+        stream.clock_rate = Some(time.frequency().get());
+
+        let Some(capture) = stream.ntp(time.numer() as u32) else {
+            return;
+        };
+        let transit = signed_secs(arrival, capture);
+
+        let (smoothed, count) = stream.transit.get_or_insert((transit, 0));
+        *smoothed += TRANSIT_SMOOTHING * (transit - *smoothed);
+        *count += 1;
+    }
+
+    /// How much to delay the audio or video to play them in sync.
+    ///
+    /// None if the streams don't have the same CNAME, are missing SRs or packets, or
+    /// while the sender clock seems to be stepping.
+    pub fn offset(&self, audio: Ssrc, video: Ssrc) -> Option<SyncOffset> {
+        let a = self.streams.get(&audio)?;
+        let v = self.streams.get(&video)?;
+
+        if a.cname.is_none() || a.cname != v.cname {
+            return None;
+        }
+
+        // Mid clock step, the streams might be on different sides of it.
+        if a.rejected > 0 || v.rejected > 0 {
+            return None;
+        }
+
+        let diff = v.transit()? - a.transit()?;
+
+        let offset = if diff >= 0.0 {
+            SyncOffset::DelayAudio(Duration::from_secs_f64(diff))
+        } else {
+            SyncOffset::DelayVideo(Duration::from_secs_f64(-diff))
+        };
+
+        Some(offset)
+    }
+}
+
+impl SyncStream {
+    fn handle_report(&mut self, rtp: u32, ntp: Instant) {
+        let Some(&(last_rtp, last_ntp)) = self.reports.back() else {
+            self.reports.push_back((rtp, ntp));
+            return;
+        };
+
+        if rtp == last_rtp && ntp == last_ntp {
+            return;
+        }
+
+        let in_order = ntp > last_ntp && rtp.wrapping_sub(last_rtp) as i32 > 0;
+        let deviation = self
+            .ntp(rtp)
+            .map(|expected| signed_secs(ntp, expected).abs())
+            .unwrap_or(0.0);
+
+        if in_order && deviation <= MAX_REPORT_DEVIATION {
+            self.rejected = 0;
+            self.reports.push_back((rtp, ntp));
+            if self.reports.len() > MAX_REPORTS {
+                self.reports.pop_front();
+            }
+            return;
+        }
+
+        self.rejected += 1;
+        debug!(
+            "Rejected SR {} of {}s deviation, in order: {}",
+            self.rejected, deviation, in_order
+        );
+
+        if self.rejected >= MAX_REJECTED {
+            debug!("Sender clock stepped, restart RTP to NTP mapping");
+            self.rejected = 0;
+            self.reports.clear();
+            self.reports.push_back((rtp, ntp));
+            self.transit = None;
+        }
+    }
+
+    fn transit(&self) -> Option<f64> {
+        self.transit
+            .filter(|(_, count)| *count >= MIN_TRANSIT_PACKETS)
+            .map(|(t, _)| t)
+    }
+
+    /// RTP ticks per second.
+    fn rate(&self) -> Option<f64> {
+        let nominal = self.clock_rate? as f64;
+
+        let (Some((rtp0, ntp0)), Some((rtp1, ntp1))) = (self.reports.front(), self.reports.back())
+        else {
+            return Some(nominal);
+        };
+
+        let span = signed_secs(*ntp1, *ntp0);
+        if span < MIN_RATE_SPAN {
+            return Some(nominal);
+        }
+
+        let rate = rtp1.wrapping_sub(*rtp0) as f64 / span;
+        if (rate / nominal - 1.0).abs() > MAX_RATE_DEVIATION {
+            return Some(nominal);
+        }
+
+        Some(rate)
+    }
+
+    /// NTP time of an RTP time.
+    fn ntp(&self, rtp: u32) -> Option<Instant> {
+        let (last_rtp, last_ntp) = *self.reports.back()?;
+        let secs = rtp.wrapping_sub(last_rtp) as i32 as f64 / self.rate()?;
+
+        if secs >= 0.0 {
+            last_ntp.checked_add(Duration::from_secs_f64(secs))
+        } else {
+            last_ntp.checked_sub(Duration::from_secs_f64(-secs))
+        }
+    }
+}
+
+/// `a - b` in seconds, negative if `b` is later.
+fn signed_secs(a: Instant, b: Instant) -> f64 {
+    if a >= b {
+        (a - b).as_secs_f64()
+    } else {
+        -(b - a).as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rtp_::Frequency;
+
+    /// A sender whose RTP clocks run off its NTP clock, which in turn runs off ours.
+    struct Sender {
+        start: Instant,
+        /// NTP clock skew to ours, and where it's at when we start.
+        ntp_skew: f64,
+        ntp_start: Instant,
+        /// Audio and video RTP clock skew to the sender NTP clock.
+        audio_skew: f64,
+        video_skew: f64,
+        /// Network and pipeline delay from capture to arrival.
+        audio_delay: f64,
+        video_delay: f64,
+    }
+
+    fn audio() -> Ssrc {
+        1.into()
+    }
+
+    fn video() -> Ssrc {
+        2.into()
+    }
+
+    impl Sender {
+        fn new(audio_skew: f64, video_skew: f64) -> Self {
+            let start = Instant::now();
+            Sender {
+                start,
+                ntp_skew: 0.0005,
+                ntp_start: start + Duration::from_secs(1000),
+                audio_skew,
+                video_skew,
+                audio_delay: 0.040,
+                video_delay: 0.120,
+            }
+        }
+
+        /// Our time at `t` seconds.
+        fn local(&self, t: f64) -> Instant {
+            self.start + Duration::from_secs_f64(t)
+        }
+
+        /// Sender NTP time at our `t` seconds.
+        fn ntp(&self, t: f64) -> Instant {
+            self.ntp_start + Duration::from_secs_f64(t * (1.0 + self.ntp_skew))
+        }
+
+        /// RTP time of media captured at our `t` seconds.
+        fn rtp(&self, ssrc: Ssrc, t: f64) -> MediaTime {
+            let t = t * (1.0 + self.ntp_skew);
+            if ssrc == audio() {
+                let rtp = 1_000_000.0 + t * 48_000.0 * (1.0 + self.audio_skew);
+                MediaTime::new(rtp as u64, Frequency::FORTY_EIGHT_KHZ)
+            } else {
+                let rtp = 3_000_000_000.0 + t * 90_000.0 * (1.0 + self.video_skew);
+                MediaTime::new(rtp as u64 % (1 << 32), Frequency::NINETY_KHZ)
+            }
+        }
+
+        fn sender_info(&self, ssrc: Ssrc, t: f64) -> SenderInfo {
+            SenderInfo {
+                ssrc,
+                ntp_time: self.ntp(t),
+                rtp_time: self.rtp(ssrc, t),
+                sender_packet_count: 0,
+                sender_octet_count: 0,
+            }
+        }
+
+        fn delay(&self, ssrc: Ssrc) -> f64 {
+            if ssrc == audio() {
+                self.audio_delay
+            } else {
+                self.video_delay
+            }
+        }
+
+        /// Run for `secs`, with SRs every `sr_interval` and jitter on arrival. The
+        /// closure can change the SRs, and checks the offset every packet.
+        fn run(
+            &self,
+            groups: &mut SyncGroups,
+            secs: f64,
+            sr_interval: f64,
+            mut sr: impl FnMut(f64, SenderInfo) -> SenderInfo,
+            mut check: impl FnMut(f64, Option<SyncOffset>),
+        ) {
+            groups.set_cname(audio(), "sender");
+            groups.set_cname(video(), "sender");
+
+            // A packet every 20ms, alternating audio and video.
+            let mut next_sr = [0.0, sr_interval / 2.0];
+            for n in 0..(secs * 50.0) as u64 {
+                let t = n as f64 * 0.020;
+                for (i, ssrc) in [audio(), video()].into_iter().enumerate() {
+                    if t >= next_sr[i] {
+                        groups.handle_sender_info(&sr(t, self.sender_info(ssrc, t)));
+                        next_sr[i] += sr_interval;
+                    }
+
+                    // Up to 10ms of jitter.
+                    let jitter = ((n * 7 + i as u64 * 3) % 11) as f64 / 1000.0;
+                    let arrival = self.local(t + self.delay(ssrc) + jitter);
+                    groups.handle_packet(ssrc, self.rtp(ssrc, t), arrival);
+                }
+
+                check(t, groups.offset(audio(), video()));
+            }
+        }
+    }
+
+    fn millis(offset: Option<SyncOffset>) -> Option<f64> {
+        offset.map(|o| match o {
+            SyncOffset::DelayAudio(d) => d.as_secs_f64() * 1000.0,
+            SyncOffset::DelayVideo(d) => -d.as_secs_f64() * 1000.0,
+        })
+    }
+
+    fn assert_near(t: f64, offset: Option<SyncOffset>, expected: f64) {
+        let ms = millis(offset).unwrap_or_else(|| panic!("no offset at {}", t));
+        assert!(
+            (ms - expected).abs() < 2.0,
+            "offset {}ms at {}s, expected {}ms",
+            ms,
+            t,
+            expected
+        );
+    }
+
+    #[test]
+    fn skewed_clocks() {
+        // Video RTP clock 0.1% fast, audio 0.05% slow, with SRs every 5s, which is 5ms
+        // off in between SRs if the rate wasn't estimated.
+        let sender = Sender::new(-0.0005, 0.001);
+        let mut groups = SyncGroups::new();
+
+        sender.run(
+            &mut groups,
+            120.0,
+            5.0,
+            |_, info| info,
+            |t, offset| {
+                if t >= 20.0 {
+                    assert_near(t, offset, 80.0);
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn audio_late() {
+        let mut sender = Sender::new(0.0, 0.0);
+        sender.audio_delay = 0.200;
+        let mut groups = SyncGroups::new();
+
+        let mut last = None;
+        sender.run(&mut groups, 30.0, 1.0, |_, info| info, |_, o| last = o);
+
+        let SyncOffset::DelayVideo(d) = last.unwrap() else {
+            panic!("expected to delay video");
+        };
+        assert!((d.as_secs_f64() - 0.080).abs() < 0.002, "{:?}", d);
+    }
+
+    #[test]
+    fn outlier_rejected() {
+        let sender = Sender::new(0.0, 0.001);
+        let mut groups = SyncGroups::new();
+
+        // A single SR with the NTP time a second off.
+        sender.run(
+            &mut groups,
+            60.0,
+            1.0,
+            |t, mut info| {
+                if (30.0..30.02).contains(&t) {
+                    info.ntp_time += Duration::from_secs(1);
+                }
+                info
+            },
+            |t, offset| {
+                if t >= 20.0 && !(30.0..31.0).contains(&t) {
+                    assert_near(t, offset, 80.0);
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn clock_step() {
+        let sender = Sender::new(0.0, 0.0);
+        let mut groups = SyncGroups::new();
+
+        // The sender NTP clock steps back 2s at 30s, and stays there.
+        let mut none = vec![];
+        sender.run(
+            &mut groups,
+            60.0,
+            1.0,
+            |t, mut info| {
+                if t >= 30.0 {
+                    info.ntp_time -= Duration::from_secs(2);
+                }
+                info
+            },
+            |t, offset| {
+                if t < 20.0 {
+                    return;
+                }
+                match offset {
+                    // Not during the step, and not wrong.
+                    None => none.push(t),
+                    Some(_) => assert_near(t, offset, 80.0),
+                }
+            },
+        );
+
+        // No offset from the first rejected SR until both streams restarted.
+        assert!(none.first().unwrap() >= &30.0);
+        assert!(none.last().unwrap() < &34.0, "{:?}", none.last());
+    }
+
+    #[test]
+    fn needs_same_cname() {
+        let sender = Sender::new(0.0, 0.0);
+        let mut groups = SyncGroups::new();
+
+        sender.run(&mut groups, 5.0, 1.0, |_, info| info, |_, _| {});
+        assert!(groups.offset(audio(), video()).is_some());
+        assert_eq!(groups.ssrcs("sender").count(), 2);
+
+        groups.set_cname(video(), "other");
+        assert_eq!(groups.offset(audio(), video()), None);
+        assert_eq!(groups.cname(video()), Some("other"));
+    }
+}
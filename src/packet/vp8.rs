@@ -26,16 +26,236 @@ pub struct Vp8CodecExtra {
     pub is_keyframe: bool,
 }
 
+/// VP8 payload descriptor and keyframe flag of a single RTP packet.
+///
+/// See [RFC 7741 section 4.2](https://datatracker.ietf.org/doc/html/rfc7741#section-4.2).
+/// Unlike [`Vp8CodecExtra`] this is parsed from one packet without any state, which
+/// makes it useful for forwarding, such as dropping temporal layers in an SFU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Vp8Meta {
+    /// N bit. The frame is not used as reference and can be discarded.
+    pub non_reference: bool,
+    /// S bit. The packet is the start of a VP8 partition.
+    pub start_of_partition: bool,
+    /// PID. Index of the partition the packet belongs to.
+    pub partition_index: u8,
+    /// Picture id, 7 or 15 bits, if present.
+    pub picture_id: Option<u16>,
+    /// M bit. Whether the picture id is 15 bits.
+    pub long_picture_id: bool,
+    /// TL0PICIDX, the running index of temporal layer 0 frames, if present.
+    pub tl0_pic_idx: Option<u8>,
+    /// TID. Temporal layer index, if present.
+    pub tid: Option<u8>,
+    /// Y bit. The frame only depends on the temporal layer 0 frame. Only set with a TID.
+    pub layer_sync: bool,
+    /// KEYIDX. Temporal key frame index, if present.
+    pub key_idx: Option<u8>,
+    /// Whether the packet starts a keyframe.
+    ///
+    /// Only ever set on the first packet of a frame, where the payload header is found.
+    pub is_keyframe: bool,
+}
+
+impl Vp8Meta {
+    /// Parse the payload descriptor of a VP8 RTP payload.
+    ///
+    /// Returns the descriptor and the VP8 payload following it. Errors if the descriptor
+    /// is longer than the packet, or there is no payload after it.
+    pub fn parse(packet: &[u8]) -> Result<(Vp8Meta, &[u8]), PacketError> {
+        //    0 1 2 3 4 5 6 7                      0 1 2 3 4 5 6 7
+        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
+        //    |X|R|N|S|R| PID | (REQUIRED)        |X|R|N|S|R| PID | (REQUIRED)
+        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
+        // X: |I|L|T|K| RSV   | (OPTIONAL)   X:   |I|L|T|K| RSV   | (OPTIONAL)
+        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
+        // I: |M| PictureID   | (OPTIONAL)   I:   |M| PictureID   | (OPTIONAL)
+        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
+        // L: |   tl0picidx   | (OPTIONAL)        |   PictureID   |
+        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
+        //T/K:|tid|Y| KEYIDX  | (OPTIONAL)   L:   |   tl0picidx   | (OPTIONAL)
+        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
+        //T/K:|tid|Y| KEYIDX  | (OPTIONAL)
+        //    +-+-+-+-+-+-+-+-+
+
+        let mut meta = Vp8Meta::default();
+        let mut reader = (packet, 0);
+
+        let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+        let x = b & 0x80 > 0;
+        meta.non_reference = b & 0x20 > 0;
+        meta.start_of_partition = b & 0x10 > 0;
+        meta.partition_index = b & 0x07;
+
+        let (mut i, mut l, mut t, mut k) = (false, false, false, false);
+        if x {
+            let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+            i = b & 0x80 > 0;
+            l = b & 0x40 > 0;
+            t = b & 0x20 > 0;
+            k = b & 0x10 > 0;
+        }
+
+        if i {
+            let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+            meta.long_picture_id = b & 0x80 > 0;
+            meta.picture_id = Some(if meta.long_picture_id {
+                let b2 = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+                ((b & 0x7f) as u16) << 8 | b2 as u16
+            } else {
+                b as u16
+            });
+        }
+
+        if l {
+            meta.tl0_pic_idx = Some(reader.get_u8().ok_or(PacketError::ErrShortPacket)?);
+        }
+
+        if t || k {
+            let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+            if t {
+                meta.tid = Some(b >> 6);
+                meta.layer_sync = b & 0x20 > 0;
+            }
+            if k {
+                meta.key_idx = Some(b & 0x1f);
+            }
+        }
+
+        let payload = &packet[reader.1 / 8..];
+        if payload.is_empty() {
+            return Err(PacketError::ErrShortPacket);
+        }
+
+        // VP8 Payload Header
+        // https://datatracker.ietf.org/doc/html/rfc7741#section-4.3
+        //
+        //  0 1 2 3 4 5 6 7
+        // +-+-+-+-+-+-+-+-+
+        // |Size0|H| VER |P|
+        // +-+-+-+-+-+-+-+-+
+        // |     Size1     |
+        // +-+-+-+-+-+-+-+-+
+        // |     Size2     |
+        // +-+-+-+-+-+-+-+-+
+        // | Octets 4..N of|
+        // | VP8 payload   |
+        // :               :
+        // +-+-+-+-+-+-+-+-+
+        // | OPTIONAL RTP  |
+        // | padding       |
+        // :               :
+        // +-+-+-+-+-+-+-+-+
+        //
+        // The header is present only in packets that have the S bit equal
+        // to one and the PID equal to zero in the payload descriptor
+        if meta.is_start_of_frame() {
+            // P is the inverse keyframe flag.
+            meta.is_keyframe = payload[0] & 1 == 0;
+        }
+
+        Ok((meta, payload))
+    }
+
+    /// Whether the packet is the first of a frame, the start of partition 0.
+    pub fn is_start_of_frame(&self) -> bool {
+        self.start_of_partition && self.partition_index == 0
+    }
+}
+
+/// Temporal layer patterns as (TID, Y bit), indexed by number of layers - 1.
+///
+/// These are the patterns used by libvpx/libwebrtc, where each frame in a higher layer
+/// references the closest lower layer frame.
+const TEMPORAL_PATTERNS: [&[(u8, bool)]; 3] = [
+    &[(0, false)],
+    &[(0, false), (1, true)],
+    &[(0, false), (2, true), (1, true), (2, false)],
+];
+
 /// Packetizes VP8 RTP packets.
-#[derive(Default, Debug, Copy, Clone)]
+///
+/// Every frame gets a 15 bit picture id. With temporal layers configured, the frames
+/// also get TL0PICIDX and TID following the pattern of the encoder.
+///
+/// ```
+/// use str0m::format::{Codec, CodecRegistry, Vp8Packetizer};
+///
+/// let mut registry = CodecRegistry::new();
+/// registry.register_packetizer(Codec::Vp8, || Vp8Packetizer::new().set_temporal_layers(3));
+/// ```
+#[derive(Debug, Copy, Clone)]
 pub struct Vp8Packetizer {
     enable_picture_id: bool,
     picture_id: u16,
+    temporal_layers: u8,
+    pattern_index: usize,
+    tl0_pic_idx: u8,
+}
+
+impl Vp8Packetizer {
+    /// Create a packetizer without temporal layers, starting at picture id 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of temporal layers produced by the encoder, 1 to 3.
+    ///
+    /// Defaults to 1, in which case no TL0PICIDX and TID are written.
+    pub fn set_temporal_layers(mut self, layers: u8) -> Self {
+        assert!(
+            (1..=3).contains(&layers),
+            "VP8 temporal layers must be 1 to 3"
+        );
+        self.temporal_layers = layers;
+        self
+    }
+
+    /// Picture id of the next frame. Only the lower 15 bits are used.
+    ///
+    /// Can be used to continue the picture id sequence of a previous packetizer.
+    pub fn set_picture_id(mut self, picture_id: u16) -> Self {
+        self.picture_id = picture_id & 0x7FFF;
+        self
+    }
+
+    /// Advance the per frame state, returning the descriptor fields of the frame.
+    fn next_frame(&mut self) -> (u16, u8, u8, bool) {
+        let picture_id = self.picture_id;
+        self.picture_id = (self.picture_id + 1) & 0x7FFF;
+
+        let pattern = TEMPORAL_PATTERNS[self.temporal_layers as usize - 1];
+        let (tid, layer_sync) = pattern[self.pattern_index];
+        self.pattern_index = (self.pattern_index + 1) % pattern.len();
+
+        if tid == 0 {
+            self.tl0_pic_idx = self.tl0_pic_idx.wrapping_add(1);
+        }
+
+        (picture_id, self.tl0_pic_idx, tid, layer_sync)
+    }
+}
+
+impl Default for Vp8Packetizer {
+    fn default() -> Self {
+        Self {
+            enable_picture_id: true,
+            picture_id: 0,
+            temporal_layers: 1,
+            pattern_index: 0,
+            // Incremented before the first layer 0 frame.
+            tl0_pic_idx: u8::MAX,
+        }
+    }
 }
 
 impl Packetizer for Vp8Packetizer {
     /// Payload fragments a VP8 packet across one or more byte arrays
     fn packetize(&mut self, mtu: usize, payload: &[u8]) -> Result<Vec<Vec<u8>>, PacketError> {
+        // The frame counts even if it can't be sent, so the receiver sees the gap
+        // in the picture ids.
+        let (picture_id, tl0_pic_idx, tid, layer_sync) = self.next_frame();
+
         if payload.is_empty() || mtu == 0 {
             return Ok(vec![]);
         }
@@ -51,6 +271,8 @@ impl Packetizer for Vp8Packetizer {
          *      +-+-+-+-+-+-+-+-+
          * I:   |M| PictureID   | (OPTIONAL)
          *      +-+-+-+-+-+-+-+-+
+         *      |   PictureID   |
+         *      +-+-+-+-+-+-+-+-+
          * L:   |   tl0picidx   | (OPTIONAL)
          *      +-+-+-+-+-+-+-+-+
          * T/K: |tid|Y| KEYIDX  | (OPTIONAL)
@@ -60,64 +282,52 @@ impl Packetizer for Vp8Packetizer {
          *     and MUST NOT be 1 otherwise.  The S bit MUST be set to 1 for the
          *     first packet of each encoded frame.
          */
-        let using_header_size = if self.enable_picture_id {
-            if self.picture_id == 0 || self.picture_id < 128 {
-                VP8_HEADER_SIZE + 2
-            } else {
-                VP8_HEADER_SIZE + 3
-            }
-        } else {
-            VP8_HEADER_SIZE
-        };
+        let mut header = [0u8; 6];
+        let mut header_len = VP8_HEADER_SIZE;
+
+        let layered = self.temporal_layers > 1;
+
+        if self.enable_picture_id || layered {
+            header[0] |= 0x80;
+            header_len += 1;
+        }
+
+        if self.enable_picture_id {
+            header[1] |= 0x80;
+            header[header_len] = 0x80 | (picture_id >> 8) as u8;
+            header[header_len + 1] = picture_id as u8;
+            header_len += 2;
+        }
 
-        let max_fragment_size = mtu as isize - using_header_size as isize;
-        let mut payload_data_remaining = payload.len() as isize;
-        let mut payload_data_index: usize = 0;
-        let mut payloads = vec![];
+        if layered {
+            header[1] |= 0x60;
+            header[header_len] = tl0_pic_idx;
+            header[header_len + 1] = tid << 6 | (layer_sync as u8) << 5;
+            header_len += 2;
+        }
 
         // Make sure the fragment/payload size is correct
-        if std::cmp::min(max_fragment_size, payload_data_remaining) <= 0 {
-            return Ok(payloads);
+        if mtu <= header_len {
+            return Ok(vec![]);
         }
+        let max_fragment_size = mtu - header_len;
 
-        let mut first = true;
-        while payload_data_remaining > 0 {
-            let current_fragment_size =
-                std::cmp::min(max_fragment_size, payload_data_remaining) as usize;
-            let mut out = Vec::with_capacity(using_header_size + current_fragment_size);
-            let mut buf = [0u8; 4];
-            if first {
-                buf[0] = 0x10;
-                first = false;
-            }
+        let mut payloads =
+            Vec::with_capacity((payload.len() + max_fragment_size - 1) / max_fragment_size);
 
-            if self.enable_picture_id {
-                if using_header_size == VP8_HEADER_SIZE + 2 {
-                    buf[0] |= 0x80;
-                    buf[1] |= 0x80;
-                    buf[2] |= (self.picture_id & 0x7F) as u8;
-                } else if using_header_size == VP8_HEADER_SIZE + 3 {
-                    buf[0] |= 0x80;
-                    buf[1] |= 0x80;
-                    buf[2] |= 0x80 | ((self.picture_id >> 8) & 0x7F) as u8;
-                    buf[3] |= (self.picture_id & 0xFF) as u8;
-                }
-            }
+        for (i, fragment) in payload.chunks(max_fragment_size).enumerate() {
+            let mut out = Vec::with_capacity(header_len + fragment.len());
+            out.extend_from_slice(&header[..header_len]);
 
-            out.extend_from_slice(&buf[..using_header_size]);
+            // S bit on the start of the first partition.
+            if i == 0 {
+                out[0] |= 0x10;
+            }
 
-            out.extend_from_slice(
-                &payload[payload_data_index..payload_data_index + current_fragment_size],
-            );
+            out.extend_from_slice(fragment);
             payloads.push(out);
-
-            payload_data_remaining -= current_fragment_size as isize;
-            payload_data_index += current_fragment_size;
         }
 
-        self.picture_id += 1;
-        self.picture_id &= 0x7FFF;
-
         Ok(payloads)
     }
 
@@ -184,136 +394,53 @@ impl Depacketizer for Vp8Depacketizer {
         if payload_len < 4 {
             return Err(PacketError::ErrShortPacket);
         }
-        //    0 1 2 3 4 5 6 7                      0 1 2 3 4 5 6 7
-        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
-        //    |X|R|N|S|R| PID | (REQUIRED)        |X|R|N|S|R| PID | (REQUIRED)
-        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
-        // X: |I|L|T|K| RSV   | (OPTIONAL)   X:   |I|L|T|K| RSV   | (OPTIONAL)
-        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
-        // I: |M| PictureID   | (OPTIONAL)   I:   |M| PictureID   | (OPTIONAL)
-        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
-        // L: |   tl0picidx   | (OPTIONAL)        |   PictureID   |
-        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
-        //T/K:|tid|Y| KEYIDX  | (OPTIONAL)   L:   |   tl0picidx   | (OPTIONAL)
-        //    +-+-+-+-+-+-+-+-+                   +-+-+-+-+-+-+-+-+
-        //T/K:|tid|Y| KEYIDX  | (OPTIONAL)
-        //    +-+-+-+-+-+-+-+-+
-
-        let mut reader = (packet, 0);
-        let mut payload_index = 0;
-
-        let mut b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-        payload_index += 1;
-
-        self.x = (b & 0x80) >> 7;
-        self.n = (b & 0x20) >> 5;
-        self.s = (b & 0x10) >> 4;
-        self.pid = b & 0x07;
-
-        if self.x == 1 {
-            b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            payload_index += 1;
-            self.i = (b & 0x80) >> 7;
-            self.l = (b & 0x40) >> 6;
-            self.t = (b & 0x20) >> 5;
-            self.k = (b & 0x10) >> 4;
-        }
 
-        if self.i == 1 {
-            b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            payload_index += 1;
-            // PID present?
-            if b & 0x80 > 0 {
-                // M == 1, PID is 16bit
-                let x = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-                self.picture_id = (((b & 0x7f) as u16) << 8) | (x as u16);
-                self.extended_pid = Some(extend_u15(self.extended_pid, self.picture_id));
-                payload_index += 1;
-            } else {
-                self.picture_id = b as u16;
-                self.extended_pid = Some(extend_u7(self.extended_pid, b));
-            }
-        }
+        let (meta, payload) = Vp8Meta::parse(packet)?;
 
-        if payload_index >= payload_len {
-            return Err(PacketError::ErrShortPacket);
-        }
+        self.x = (packet[0] & 0x80) >> 7;
+        self.n = meta.non_reference as u8;
+        self.s = meta.start_of_partition as u8;
+        self.pid = meta.partition_index;
 
-        if self.l == 1 {
-            self.tl0_pic_idx = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            self.extended_tl0_pic_idx =
-                Some(extend_u8(self.extended_tl0_pic_idx, self.tl0_pic_idx));
-            payload_index += 1;
-        }
+        self.i = meta.picture_id.is_some() as u8;
+        self.l = meta.tl0_pic_idx.is_some() as u8;
+        self.t = meta.tid.is_some() as u8;
+        self.k = meta.key_idx.is_some() as u8;
 
-        if payload_index >= payload_len {
-            return Err(PacketError::ErrShortPacket);
+        if let Some(picture_id) = meta.picture_id {
+            self.picture_id = picture_id;
+            self.extended_pid = Some(if meta.long_picture_id {
+                extend_u15(self.extended_pid, picture_id)
+            } else {
+                extend_u7(self.extended_pid, picture_id as u8)
+            });
         }
 
-        if self.t == 1 || self.k == 1 {
-            let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            if self.t == 1 {
-                self.tid = b >> 6;
-                self.y = (b >> 5) & 0x1;
-            }
-            if self.k == 1 {
-                self.key_idx = b & 0x1F;
-            }
-            payload_index += 1;
+        if let Some(tl0_pic_idx) = meta.tl0_pic_idx {
+            self.tl0_pic_idx = tl0_pic_idx;
+            self.extended_tl0_pic_idx = Some(extend_u8(self.extended_tl0_pic_idx, tl0_pic_idx));
         }
 
-        if payload_index >= packet.len() {
-            return Err(PacketError::ErrShortPacket);
-        }
+        self.tid = meta.tid.unwrap_or(0);
+        self.y = meta.layer_sync as u8;
+        self.key_idx = meta.key_idx.unwrap_or(0);
 
-        out.extend_from_slice(&packet[payload_index..]);
+        self.p = (!meta.is_keyframe) as u8;
 
-        // VP8 Payload Header
-        // https://datatracker.ietf.org/doc/html/rfc7741#section-4.3
-        //
-        //  0 1 2 3 4 5 6 7
-        // +-+-+-+-+-+-+-+-+
-        // |Size0|H| VER |P|
-        // +-+-+-+-+-+-+-+-+
-        // |     Size1     |
-        // +-+-+-+-+-+-+-+-+
-        // |     Size2     |
-        // +-+-+-+-+-+-+-+-+
-        // | Octets 4..N of|
-        // | VP8 payload   |
-        // :               :
-        // +-+-+-+-+-+-+-+-+
-        // | OPTIONAL RTP  |
-        // | padding       |
-        // :               :
-        // +-+-+-+-+-+-+-+-+
-        //
-        // The header is present only in packets that have the S bit equal
-        // to one and the PID equal to zero in the payload descriptor
-        self.p = if self.s == 1 && self.pid == 0 {
-            b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            payload_index += 1;
-            b & 1
-        } else {
-            1
-        };
+        out.extend_from_slice(payload);
 
         let is_keyframe = if let CodecExtra::Vp8(e) = extra {
-            e.is_keyframe | (self.p == 0)
+            e.is_keyframe | meta.is_keyframe
         } else {
-            self.p == 0
+            meta.is_keyframe
         };
 
         *extra = CodecExtra::Vp8(Vp8CodecExtra {
-            discardable: self.n == 1,
-            sync: self.y == 1,
+            discardable: meta.non_reference,
+            sync: meta.layer_sync,
             layer_index: self.tid,
-            picture_id: if self.i == 1 { self.extended_pid } else { None },
-            tl0_picture_id: if self.l == 1 {
-                self.extended_tl0_pic_idx
-            } else {
-                None
-            },
+            picture_id: meta.picture_id.and(self.extended_pid),
+            tl0_picture_id: meta.tl0_pic_idx.and(self.extended_tl0_pic_idx),
             is_keyframe,
         });
         Ok(())
@@ -331,6 +458,10 @@ impl Depacketizer for Vp8Depacketizer {
     fn is_partition_tail(&self, marker: bool, _payload: &[u8]) -> bool {
         marker
     }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
 }
 
 #[cfg(test)]
@@ -479,7 +610,10 @@ mod test {
         let tests: Vec<(&str, Vp8Packetizer, usize, Vec<&[u8]>, Vec<Vec<&[u8]>>)> = vec![
             (
                 "WithoutPictureID",
-                Vp8Packetizer::default(),
+                Vp8Packetizer {
+                    enable_picture_id: false,
+                    ..Default::default()
+                },
                 2,
                 vec![&[0x90, 0x90, 0x90], &[0x91, 0x91]],
                 vec![
@@ -488,24 +622,21 @@ mod test {
                 ],
             ),
             (
-                "WithPictureID_1byte",
-                Vp8Packetizer {
-                    enable_picture_id: true,
-                    picture_id: 0x20,
-                },
-                5,
+                "WithPictureID_Small",
+                Vp8Packetizer::new().set_picture_id(0x20),
+                6,
                 vec![&[0x90, 0x90, 0x90], &[0x91, 0x91]],
                 vec![
-                    vec![&[0x90, 0x80, 0x20, 0x90, 0x90], &[0x80, 0x80, 0x20, 0x90]],
-                    vec![&[0x90, 0x80, 0x21, 0x91, 0x91]],
+                    vec![
+                        &[0x90, 0x80, 0x80, 0x20, 0x90, 0x90],
+                        &[0x80, 0x80, 0x80, 0x20, 0x90],
+                    ],
+                    vec![&[0x90, 0x80, 0x80, 0x21, 0x91, 0x91]],
                 ],
             ),
             (
-                "WithPictureID_2bytes",
-                Vp8Packetizer {
-                    enable_picture_id: true,
-                    picture_id: 0x120,
-                },
+                "WithPictureID_Large",
+                Vp8Packetizer::new().set_picture_id(0x120),
                 6,
                 vec![&[0x90, 0x90, 0x90], &[0x91, 0x91]],
                 vec![
@@ -530,7 +661,10 @@ mod test {
 
     #[test]
     fn test_vp8_payload_eror() -> Result<(), PacketError> {
-        let mut pck = Vp8Packetizer::default();
+        let mut pck = Vp8Packetizer {
+            enable_picture_id: false,
+            ..Default::default()
+        };
         let empty = &[];
         let payload = &[0x90, 0x90, 0x90];
 
@@ -553,6 +687,225 @@ mod test {
         Ok(())
     }
 
+    fn depacketize_all(packets: &[Vec<u8>]) -> (Vec<u8>, Vp8CodecExtra) {
+        let mut depack = Vp8Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+        for p in packets {
+            depack.depacketize(p, &mut out, &mut extra).unwrap();
+        }
+        let CodecExtra::Vp8(e) = extra else {
+            panic!("vp8 extra");
+        };
+        (out, e)
+    }
+
+    #[test]
+    fn test_vp8_packetizer_round_trip() {
+        // Keyframe header followed by some frame data.
+        let mut frame = vec![0x10, 0x02, 0x00, 0x9d, 0x01, 0x2a, 0x80, 0x02, 0xe0, 0x01];
+        frame.extend((0..2500).map(|i| i as u8));
+
+        let mut pck = Vp8Packetizer::new();
+
+        for mtu in [100, 1000, 1200, 3000] {
+            let packets = pck.packetize(mtu, &frame).unwrap();
+
+            assert!(packets.iter().all(|p| p.len() <= mtu));
+
+            let markers: Vec<_> = (0..packets.len())
+                .map(|i| pck.is_marker(&packets[i], None, i == packets.len() - 1))
+                .collect();
+            assert_eq!(markers.iter().filter(|m| **m).count(), 1);
+            assert_eq!(markers.last(), Some(&true));
+
+            let depack = Vp8Depacketizer::default();
+            assert!(depack.is_partition_head(&packets[0]));
+            assert!(packets[1..].iter().all(|p| !depack.is_partition_head(p)));
+
+            let (out, extra) = depacketize_all(&packets);
+            assert_eq!(out, frame);
+            assert!(extra.is_keyframe);
+        }
+    }
+
+    #[test]
+    fn test_vp8_packetizer_picture_id_wraps() {
+        let mut pck = Vp8Packetizer::new().set_picture_id(0x7ffe);
+
+        let ids: Vec<_> = (0..3)
+            .map(|_| {
+                let packets = pck.packetize(1200, &[0x01, 0x02, 0x03]).unwrap();
+                let (meta, _) = Vp8Meta::parse(&packets[0]).unwrap();
+                assert!(meta.long_picture_id);
+                meta.picture_id.unwrap()
+            })
+            .collect();
+
+        assert_eq!(ids, [0x7ffe, 0x7fff, 0]);
+    }
+
+    #[test]
+    fn test_vp8_packetizer_picture_id_dropped_frames() {
+        let mut pck = Vp8Packetizer::new();
+
+        pck.packetize(1200, &[0x01, 0x02, 0x03]).unwrap();
+
+        // Frames that can't be sent still use up a picture id.
+        assert!(pck.packetize(1200, &[]).unwrap().is_empty());
+        assert!(pck.packetize(4, &[0x01, 0x02, 0x03]).unwrap().is_empty());
+
+        let packets = pck.packetize(1200, &[0x01, 0x02, 0x03]).unwrap();
+        let (meta, _) = Vp8Meta::parse(&packets[0]).unwrap();
+        assert_eq!(meta.picture_id, Some(3));
+    }
+
+    #[test]
+    fn test_vp8_packetizer_temporal_layers() {
+        let mut pck = Vp8Packetizer::new().set_temporal_layers(3);
+
+        let layers: Vec<_> = (0..6)
+            .map(|_| {
+                let packets = pck.packetize(8, &[0x01, 0x02, 0x03, 0x04, 0x05]).unwrap();
+                assert_eq!(packets.len(), 3);
+
+                let metas: Vec<_> = packets
+                    .iter()
+                    .map(|p| Vp8Meta::parse(p).unwrap().0)
+                    .collect();
+                assert!(metas[0].start_of_partition);
+                assert!(metas[1..].iter().all(|m| !m.start_of_partition));
+                assert!(metas.iter().all(|m| *m
+                    == Vp8Meta {
+                        start_of_partition: m.start_of_partition,
+                        ..metas[0]
+                    }));
+
+                let m = metas[0];
+                (m.tl0_pic_idx.unwrap(), m.tid.unwrap(), m.layer_sync)
+            })
+            .collect();
+
+        assert_eq!(
+            layers,
+            [
+                (0, 0, false),
+                (0, 2, true),
+                (0, 1, true),
+                (0, 2, false),
+                (1, 0, false),
+                (1, 2, true),
+            ]
+        );
+
+        let (_, extra) = depacketize_all(&pck.packetize(1200, &[0x01, 0x02, 0x03]).unwrap());
+        assert_eq!(extra.layer_index, 1);
+        assert_eq!(extra.tl0_picture_id, Some(1));
+        assert_eq!(extra.picture_id, Some(6));
+    }
+
+    #[test]
+    fn test_vp8_meta_rfc7741() {
+        // RFC 7741 section 4.2, all optional fields with a 7 bit picture id.
+        //  |1|0|0|1|0|000| |1|1|1|1|0000| |0|0010001| |00010010| |10|1|00011|
+        let packet = &[0x90, 0xf0, 0x11, 0x12, 0xa3, 0x00, 0x01];
+        let (meta, payload) = Vp8Meta::parse(packet).unwrap();
+        assert_eq!(
+            meta,
+            Vp8Meta {
+                non_reference: false,
+                start_of_partition: true,
+                partition_index: 0,
+                picture_id: Some(0x11),
+                long_picture_id: false,
+                tl0_pic_idx: Some(0x12),
+                tid: Some(2),
+                layer_sync: true,
+                key_idx: Some(3),
+                is_keyframe: true,
+            }
+        );
+        assert_eq!(payload, &[0x00, 0x01]);
+
+        // Same with a 15 bit picture id, M bit set.
+        let packet = &[0x90, 0xf0, 0x91, 0x22, 0x12, 0xa3, 0x01, 0x01];
+        let (meta, payload) = Vp8Meta::parse(packet).unwrap();
+        assert_eq!(meta.picture_id, Some(0x1122));
+        assert!(meta.long_picture_id);
+        assert_eq!(meta.tl0_pic_idx, Some(0x12));
+        assert!(!meta.is_keyframe);
+        assert_eq!(payload, &[0x01, 0x01]);
+
+        // Only the required byte. Non-reference frame, continuation of partition 1.
+        let packet = &[0x21, 0xaa, 0xbb];
+        let (meta, payload) = Vp8Meta::parse(packet).unwrap();
+        assert!(meta.non_reference);
+        assert!(!meta.start_of_partition);
+        assert_eq!(meta.partition_index, 1);
+        assert_eq!(meta.picture_id, None);
+        assert_eq!(meta.tid, None);
+        assert!(!meta.is_start_of_frame());
+        assert_eq!(payload, &[0xaa, 0xbb]);
+
+        // KEYIDX without TID.
+        let packet = &[0x80, 0x10, 0x1f, 0x00];
+        let (meta, _) = Vp8Meta::parse(packet).unwrap();
+        assert_eq!(meta.tid, None);
+        assert_eq!(meta.key_idx, Some(31));
+        assert!(!meta.layer_sync);
+    }
+
+    #[test]
+    fn test_vp8_meta_descriptor_longer_than_packet() {
+        let truncated: &[&[u8]] = &[
+            &[],
+            // X without extension byte.
+            &[0x80],
+            // I without picture id.
+            &[0x80, 0x80],
+            // M without second picture id byte.
+            &[0x80, 0x80, 0x81],
+            // L without TL0PICIDX.
+            &[0x80, 0x40],
+            // T/K without the byte.
+            &[0x80, 0x20],
+            &[0x80, 0x10],
+            // All descriptor, no payload.
+            &[0x90, 0xf0, 0x91, 0x22, 0x12, 0xa3],
+            &[0x10],
+        ];
+
+        for packet in truncated {
+            assert_eq!(
+                Vp8Meta::parse(packet),
+                Err(PacketError::ErrShortPacket),
+                "{:02x?}",
+                packet
+            );
+        }
+    }
+
+    #[test]
+    fn test_vp8_depacketizer_clears_optional_fields() {
+        let mut pck = Vp8Depacketizer::default();
+        let mut extra = CodecExtra::None;
+        let mut out = Vec::new();
+
+        pck.depacketize(&[0x90, 0xf0, 0x11, 0x12, 0xa3, 0x01], &mut out, &mut extra)
+            .unwrap();
+        assert_eq!(pck.t, 1);
+
+        // A following packet without X must not keep the extension flags.
+        pck.depacketize(&[0x00, 0x11, 0x22, 0x33], &mut out, &mut extra)
+            .unwrap();
+        assert_eq!((pck.i, pck.l, pck.t, pck.k), (0, 0, 0, 0));
+        let CodecExtra::Vp8(e) = extra else {
+            panic!("vp8 extra");
+        };
+        assert_eq!(e.picture_id, None);
+        assert_eq!(e.layer_index, 0);
+    }
+
     #[test]
     fn test_vp8_partition_head_checker_is_partition_head() -> Result<(), PacketError> {
         let vp8 = Vp8Depacketizer::default();
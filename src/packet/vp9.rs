@@ -1,4 +1,6 @@
-use super::{BitRead, CodecExtra, Depacketizer, MediaKind, PacketError, Packetizer};
+use super::{
+    BitRead, CodecExtra, CodecFrameInfo, Depacketizer, MediaKind, PacketError, Packetizer,
+};
 
 use std::fmt;
 use std::panic::RefUnwindSafe;
@@ -103,11 +105,315 @@ pub struct Vp9CodecExtra {
     pub is_keyframe: bool,
 }
 
+/// Payload descriptor of a single VP9 RTP packet.
+///
+/// See [RFC 9628 section 4.2](https://datatracker.ietf.org/doc/html/rfc9628#section-4.2).
+/// Unlike [`Vp9CodecExtra`] this is parsed from one packet without any state, which
+/// makes it useful for forwarding, such as selecting spatial and temporal layers in an SFU.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Vp9Meta {
+    /// P bit. The picture is predicted from an earlier picture.
+    pub inter_picture_predicted: bool,
+    /// F bit. Flexible mode, the references are signalled in every packet.
+    pub flexible_mode: bool,
+    /// B bit. The packet is the start of a layer frame.
+    pub start_of_frame: bool,
+    /// E bit. The packet is the end of a layer frame.
+    pub end_of_frame: bool,
+    /// Z bit. The frame is not used as reference by the upper spatial layers.
+    pub not_upper_layer_reference: bool,
+    /// Picture id, 7 or 15 bits, if present.
+    pub picture_id: Option<u16>,
+    /// M bit. Whether the picture id is 15 bits.
+    pub long_picture_id: bool,
+    /// TID. Temporal layer index, if layer indices are present.
+    pub tid: Option<u8>,
+    /// U bit. Switching up point to a higher temporal layer. Only set with a TID.
+    pub switching_up_point: bool,
+    /// SID. Spatial layer index, if layer indices are present.
+    pub sid: Option<u8>,
+    /// D bit. The frame depends on the frame of the spatial layer below. Only set with a SID.
+    pub inter_layer_dependency: bool,
+    /// TL0PICIDX, the running index of temporal layer 0 pictures. Only in non-flexible mode.
+    pub tl0_pic_idx: Option<u8>,
+    /// P_DIFF. Reference indices relative to the picture id. Only in flexible mode.
+    pub ref_indices: Vec<u8>,
+    /// Scalability structure (SS), if the V bit is set.
+    pub scalability_structure: Option<Vp9ScalabilityStructure>,
+    /// Whether the packet starts a keyframe.
+    ///
+    /// Only ever set on the first packet of the base spatial layer frame.
+    pub is_keyframe: bool,
+}
+
+/// VP9 scalability structure (SS).
+///
+/// Describes the layer topology of the stream. It is typically sent with every keyframe.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Vp9ScalabilityStructure {
+    /// Number of spatial layers, N_S + 1.
+    pub spatial_layers: u8,
+    /// Width and height of each spatial layer, if present (Y bit).
+    pub resolutions: Option<Vec<(u16, u16)>>,
+    /// Description of the pictures in a picture group, if present (G bit).
+    pub picture_group: Option<Vec<Vp9PictureGroupEntry>>,
+}
+
+/// One picture in the picture group of a [`Vp9ScalabilityStructure`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Vp9PictureGroupEntry {
+    /// Temporal layer index of the picture.
+    pub tid: u8,
+    /// Whether the picture is a switching up point.
+    pub switching_up_point: bool,
+    /// Reference indices (P_DIFF) of the picture.
+    pub ref_indices: Vec<u8>,
+}
+
+impl Vp9Meta {
+    /// Parse the payload descriptor of a VP9 RTP payload.
+    ///
+    /// Returns the descriptor and the VP9 payload following it. Errors if the descriptor
+    /// is longer than the packet.
+    pub fn parse(packet: &[u8]) -> Result<(Vp9Meta, &[u8]), PacketError> {
+        //      +-+-+-+-+-+-+-+-+
+        //      |I|P|L|F|B|E|V|Z| (REQUIRED)
+        //      +-+-+-+-+-+-+-+-+
+        // I:   |M| PICTURE ID  | (REQUIRED)
+        //      +-+-+-+-+-+-+-+-+
+        // M:   | EXTENDED PID  | (RECOMMENDED)
+        //      +-+-+-+-+-+-+-+-+
+        // L:   | TID |U| SID |D| (CONDITIONALLY RECOMMENDED)
+        //      +-+-+-+-+-+-+-+-+
+        //      |   TL0PICIDX   | (CONDITIONALLY REQUIRED, F=0)
+        //      +-+-+-+-+-+-+-+-+                             -\
+        // P,F: | P_DIFF      |N| (CONDITIONALLY REQUIRED)    - up to 3 times
+        //      +-+-+-+-+-+-+-+-+                             -/
+        // V:   | SS            |
+        //      | ..            |
+        //      +-+-+-+-+-+-+-+-+
+
+        let mut meta = Vp9Meta::default();
+        let mut reader = (packet, 0);
+
+        let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+        let i = b & 0x80 > 0;
+        meta.inter_picture_predicted = b & 0x40 > 0;
+        let l = b & 0x20 > 0;
+        meta.flexible_mode = b & 0x10 > 0;
+        meta.start_of_frame = b & 0x08 > 0;
+        meta.end_of_frame = b & 0x04 > 0;
+        let v = b & 0x02 > 0;
+        meta.not_upper_layer_reference = b & 0x01 > 0;
+
+        if i {
+            let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+            meta.long_picture_id = b & 0x80 > 0;
+            meta.picture_id = Some(if meta.long_picture_id {
+                let b2 = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+                ((b & 0x7f) as u16) << 8 | b2 as u16
+            } else {
+                b as u16
+            });
+        }
+
+        if l {
+            let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+            meta.tid = Some(b >> 5);
+            meta.switching_up_point = b & 0x10 > 0;
+            meta.sid = Some((b >> 1) & 0x07);
+            meta.inter_layer_dependency = b & 0x01 > 0;
+
+            if !meta.flexible_mode {
+                meta.tl0_pic_idx = Some(reader.get_u8().ok_or(PacketError::ErrShortPacket)?);
+            }
+        }
+
+        if meta.flexible_mode && meta.inter_picture_predicted {
+            loop {
+                let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+                if meta.ref_indices.len() == MAX_VP9REF_PICS {
+                    return Err(PacketError::ErrTooManyPDiff);
+                }
+                meta.ref_indices.push(b >> 1);
+                if b & 0x01 == 0 {
+                    break;
+                }
+            }
+        }
+
+        if v {
+            meta.scalability_structure = Some(Vp9ScalabilityStructure::parse(&mut reader)?);
+        }
+
+        meta.is_keyframe =
+            !meta.inter_picture_predicted && meta.start_of_frame && meta.sid.unwrap_or(0) == 0;
+
+        Ok((meta, &packet[reader.1 / 8..]))
+    }
+
+    /// Whether the packet is the end of a picture, i.e. the last layer frame of it.
+    ///
+    /// The RTP marker bit is only set on the last packet of a picture.
+    pub fn is_end_of_picture(&self, marker: bool) -> bool {
+        self.end_of_frame && marker
+    }
+}
+
+impl Vp9ScalabilityStructure {
+    fn to_bytes(&self) -> Result<Vec<u8>, PacketError> {
+        if self.spatial_layers == 0 || self.spatial_layers as usize > MAX_SPATIAL_LAYERS {
+            return Err(PacketError::ErrTooManySpatialLayers);
+        }
+
+        let mut out = vec![
+            (self.spatial_layers - 1) << 5
+                | (self.resolutions.is_some() as u8) << 4
+                | (self.picture_group.is_some() as u8) << 3,
+        ];
+
+        if let Some(resolutions) = &self.resolutions {
+            if resolutions.len() != self.spatial_layers as usize {
+                return Err(PacketError::ErrVp9InvalidFrameInfo);
+            }
+            for (width, height) in resolutions {
+                out.extend_from_slice(&width.to_be_bytes());
+                out.extend_from_slice(&height.to_be_bytes());
+            }
+        }
+
+        if let Some(pg) = &self.picture_group {
+            if pg.len() > u8::MAX as usize {
+                return Err(PacketError::ErrVp9InvalidFrameInfo);
+            }
+            out.push(pg.len() as u8);
+            for entry in pg {
+                if entry.ref_indices.len() > MAX_VP9REF_PICS {
+                    return Err(PacketError::ErrTooManyPDiff);
+                }
+                out.push(
+                    entry.tid << 5
+                        | (entry.switching_up_point as u8) << 4
+                        | (entry.ref_indices.len() as u8) << 2,
+                );
+                out.extend_from_slice(&entry.ref_indices);
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn parse(reader: &mut (&[u8], usize)) -> Result<Self, PacketError> {
+        //      +-+-+-+-+-+-+-+-+
+        // V:   | N_S |Y|G|-|-|-|
+        //      +-+-+-+-+-+-+-+-+              -|
+        // Y:   |     WIDTH     | (OPTIONAL)    .
+        //      +               +               .
+        //      |               | (OPTIONAL)    .
+        //      +-+-+-+-+-+-+-+-+               . N_S + 1 times
+        //      |     HEIGHT    | (OPTIONAL)    .
+        //      +               +               .
+        //      |               | (OPTIONAL)    .
+        //      +-+-+-+-+-+-+-+-+              -|
+        // G:   |      N_G      | (OPTIONAL)
+        //      +-+-+-+-+-+-+-+-+                           -|
+        // N_G: |  T  |U| R |-|-| (OPTIONAL)                 .
+        //      +-+-+-+-+-+-+-+-+              -|            . N_G times
+        //      |    P_DIFF     | (OPTIONAL)    . R times    .
+        //      +-+-+-+-+-+-+-+-+              -|           -|
+
+        let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+        let spatial_layers = (b >> 5) + 1;
+        let y = b & 0x10 > 0;
+        let g = b & 0x08 > 0;
+
+        let resolutions = if y {
+            let mut res = Vec::with_capacity(spatial_layers as usize);
+            for _ in 0..spatial_layers {
+                let width = reader.get_u16().ok_or(PacketError::ErrShortPacket)?;
+                let height = reader.get_u16().ok_or(PacketError::ErrShortPacket)?;
+                res.push((width, height));
+            }
+            Some(res)
+        } else {
+            None
+        };
+
+        let picture_group = if g {
+            let n_g = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+            let mut pg = Vec::with_capacity(n_g as usize);
+            for _ in 0..n_g {
+                let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+                let r = (b >> 2) & 0x03;
+                let mut ref_indices = Vec::with_capacity(r as usize);
+                for _ in 0..r {
+                    ref_indices.push(reader.get_u8().ok_or(PacketError::ErrShortPacket)?);
+                }
+                pg.push(Vp9PictureGroupEntry {
+                    tid: b >> 5,
+                    switching_up_point: b & 0x10 > 0,
+                    ref_indices,
+                });
+            }
+            Some(pg)
+        } else {
+            None
+        };
+
+        Ok(Vp9ScalabilityStructure {
+            spatial_layers,
+            resolutions,
+            picture_group,
+        })
+    }
+}
+
+/// Layers of a VP9 picture, given by the encoder when writing SVC.
+///
+/// The spatial layer frames are concatenated in the written data, in the order of
+/// [`Vp9FrameInfo::layers`]. This is the sending side counterpart of
+/// [`Vp9CodecExtra::layers_scheme`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Vp9FrameInfo {
+    /// F bit. Flexible mode, where the references are given by
+    /// [`Vp9LayerFrame::ref_indices`]. Otherwise TL0PICIDX is sent.
+    pub flexible_mode: bool,
+    /// TID. Temporal layer index of the picture.
+    pub tid: u8,
+    /// U bit. Switching up point to a higher temporal layer.
+    pub switching_up_point: bool,
+    /// The spatial layer frames of the picture.
+    pub layers: Vec<Vp9LayerFrame>,
+    /// Scalability structure (SS) to send with the picture, typically for keyframes.
+    pub scalability_structure: Option<Vp9ScalabilityStructure>,
+}
+
+/// One spatial layer frame of a [`Vp9FrameInfo`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Vp9LayerFrame {
+    /// SID. Spatial layer index.
+    pub sid: u8,
+    /// Number of bytes of the layer frame in the written data.
+    pub size: usize,
+    /// P bit. The frame is predicted from an earlier picture.
+    pub inter_picture_predicted: bool,
+    /// D bit. The frame depends on the frame of the spatial layer below.
+    pub inter_layer_dependency: bool,
+    /// Z bit. The frame is not used as reference by the upper spatial layers.
+    pub not_upper_layer_reference: bool,
+    /// P_DIFF. Reference indices relative to the picture id, up to 3. Only in flexible
+    /// mode, where it is required for predicted frames.
+    pub ref_indices: Vec<u8>,
+}
+
 /// Packetizes VP9 RTP packets.
+///
+/// Without a [`Vp9FrameInfo`], each frame is sent in flexible mode without layer indices.
 #[derive(Default, Clone)]
 pub struct Vp9Packetizer {
     picture_id: u16,
     initialized: bool,
+    tl0_pic_idx: u8,
     #[cfg(test)]
     initial_picture_id: u16,
 }
@@ -166,18 +472,7 @@ impl Packetizer for Vp9Packetizer {
             return Ok(vec![]);
         }
 
-        if !self.initialized {
-            #[cfg(test)]
-            {
-                self.picture_id = self.initial_picture_id;
-            }
-            #[cfg(not(test))]
-            {
-                use crate::util::NonCryptographicRng;
-                self.picture_id = NonCryptographicRng::u16() & 0x7FFF;
-            }
-            self.initialized = true;
-        }
+        self.init_picture_id();
 
         let max_fragment_size = mtu as isize - VP9HEADER_SIZE as isize;
         let mut payloads = vec![];
@@ -221,11 +516,156 @@ impl Packetizer for Vp9Packetizer {
         Ok(payloads)
     }
 
+    fn packetize_frame(
+        &mut self,
+        mtu: usize,
+        payload: &[u8],
+        info: &CodecFrameInfo,
+    ) -> Result<Vec<Vec<u8>>, PacketError> {
+        match info {
+            CodecFrameInfo::Vp9(info) => self.packetize_layers(mtu, payload, info),
+        }
+    }
+
     fn is_marker(&mut self, data: &[u8], previous: Option<&[u8]>, last: bool) -> bool {
         last
     }
 }
 
+impl Vp9Packetizer {
+    fn init_picture_id(&mut self) {
+        if !self.initialized {
+            #[cfg(test)]
+            {
+                self.picture_id = self.initial_picture_id;
+            }
+            #[cfg(not(test))]
+            {
+                use crate::util::NonCryptographicRng;
+                self.picture_id = NonCryptographicRng::u16() & 0x7FFF;
+            }
+            self.initialized = true;
+        }
+    }
+
+    /// Packetize a picture with layer indices, where each spatial layer frame starts
+    /// with a B bit and ends with an E bit.
+    fn packetize_layers(
+        &mut self,
+        mtu: usize,
+        payload: &[u8],
+        info: &Vp9FrameInfo,
+    ) -> Result<Vec<Vec<u8>>, PacketError> {
+        if info.layers.len() > MAX_SPATIAL_LAYERS {
+            return Err(PacketError::ErrTooManySpatialLayers);
+        }
+
+        let total: usize = info.layers.iter().map(|l| l.size).sum();
+        if total != payload.len() {
+            return Err(PacketError::ErrVp9InvalidFrameInfo);
+        }
+
+        let ss = match &info.scalability_structure {
+            Some(ss) => Some(ss.to_bytes()?),
+            None => None,
+        };
+
+        if payload.is_empty() || mtu == 0 {
+            return Ok(vec![]);
+        }
+
+        self.init_picture_id();
+
+        if !info.flexible_mode && info.tid == 0 {
+            self.tl0_pic_idx = self.tl0_pic_idx.wrapping_add(1);
+        }
+
+        let mut payloads = vec![];
+        let mut offset = 0;
+
+        for (i, layer) in info.layers.iter().enumerate() {
+            if layer.sid as usize >= MAX_SPATIAL_LAYERS {
+                return Err(PacketError::ErrTooManySpatialLayers);
+            }
+            if layer.ref_indices.len() > MAX_VP9REF_PICS {
+                return Err(PacketError::ErrTooManyPDiff);
+            }
+
+            let has_pdiff = info.flexible_mode && layer.inter_picture_predicted;
+            if has_pdiff && layer.ref_indices.is_empty() {
+                return Err(PacketError::ErrVp9InvalidFrameInfo);
+            }
+
+            //      +-+-+-+-+-+-+-+-+
+            //      |I|P|L|F|B|E|V|Z|
+            //      +-+-+-+-+-+-+-+-+
+            let mut header = vec![
+                0xa0 | (layer.inter_picture_predicted as u8) << 6
+                    | (info.flexible_mode as u8) << 4
+                    | layer.not_upper_layer_reference as u8,
+                0x80 | (self.picture_id >> 8) as u8,
+                self.picture_id as u8,
+                info.tid << 5
+                    | (info.switching_up_point as u8) << 4
+                    | layer.sid << 1
+                    | layer.inter_layer_dependency as u8,
+            ];
+
+            if !info.flexible_mode {
+                header.push(self.tl0_pic_idx);
+            }
+
+            if has_pdiff {
+                let last = layer.ref_indices.len() - 1;
+                for (j, pdiff) in layer.ref_indices.iter().enumerate() {
+                    header.push(pdiff << 1 | (j < last) as u8);
+                }
+            }
+
+            // The SS goes with the start of the picture.
+            let ss = ss.as_deref().filter(|_| i == 0).unwrap_or_default();
+
+            let data = &payload[offset..offset + layer.size];
+            offset += layer.size;
+
+            let mut index = 0;
+            while index < data.len() {
+                let first = index == 0;
+                let header_len = header.len() + if first { ss.len() } else { 0 };
+
+                if mtu <= header_len {
+                    return Ok(vec![]);
+                }
+
+                let len = (mtu - header_len).min(data.len() - index);
+                let last = index + len == data.len();
+
+                let mut out = Vec::with_capacity(header_len + len);
+                out.extend_from_slice(&header);
+                if first {
+                    out[0] |= 0x08; // B=1
+                    if !ss.is_empty() {
+                        out[0] |= 0x02; // V=1
+                        out.extend_from_slice(ss);
+                    }
+                }
+                if last {
+                    out[0] |= 0x04; // E=1
+                }
+                out.extend_from_slice(&data[index..index + len]);
+                payloads.push(out);
+
+                index += len;
+            }
+        }
+
+        self.picture_id += 1;
+        self.picture_id &= 0x7FFF;
+
+        Ok(payloads)
+    }
+}
+
 /// Depacketizes VP9 RTP packets.
 #[derive(PartialEq, Eq, Debug, Default, Clone)]
 pub struct Vp9Depacketizer {
@@ -293,13 +733,13 @@ impl Depacketizer for Vp9Depacketizer {
         out: &mut Vec<u8>,
         extra: &mut CodecExtra,
     ) -> Result<(), PacketError> {
-        if packet.is_empty() {
-            return Err(PacketError::ErrShortPacket);
-        }
+        let (meta, payload) = Vp9Meta::parse(packet)?;
 
-        let mut reader = (packet, 0);
-        let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
+        if meta.sid.unwrap_or(0) as usize >= MAX_SPATIAL_LAYERS {
+            return Err(PacketError::ErrTooManySpatialLayers);
+        }
 
+        let b = packet[0];
         self.i = (b & 0x80) != 0;
         self.p = (b & 0x40) != 0;
         self.l = (b & 0x20) != 0;
@@ -309,27 +749,30 @@ impl Depacketizer for Vp9Depacketizer {
         self.v = (b & 0x02) != 0;
         self.z = (b & 0x01) != 0;
 
-        let mut payload_index = 1;
-
-        if self.i {
-            payload_index = self.parse_picture_id(&mut reader, payload_index)?;
+        if let Some(picture_id) = meta.picture_id {
+            self.picture_id = picture_id;
         }
 
-        if self.l {
-            payload_index = self.parse_layer_info(&mut reader, payload_index)?;
+        if let (Some(tid), Some(sid)) = (meta.tid, meta.sid) {
+            self.tid = tid;
+            self.u = meta.switching_up_point;
+            self.sid = sid;
+            self.d = meta.inter_layer_dependency;
         }
 
-        if self.f && self.p {
-            payload_index = self.parse_ref_indices(&mut reader, payload_index)?;
+        if let Some(tl0picidx) = meta.tl0_pic_idx {
+            self.tl0picidx = tl0picidx;
         }
 
-        if self.v {
-            payload_index = self.parse_ssdata(&mut reader, payload_index)?;
+        self.pdiff = meta.ref_indices;
+
+        if let Some(ss) = meta.scalability_structure {
+            self.set_scalability_structure(ss)?;
         }
 
-        self.update_extra(extra, out.len(), packet.len(), payload_index)?;
+        self.update_extra(extra, out.len(), payload.len())?;
 
-        out.extend_from_slice(&packet[payload_index..]);
+        out.extend_from_slice(payload);
 
         Ok(())
     }
@@ -346,17 +789,19 @@ impl Depacketizer for Vp9Depacketizer {
     fn is_partition_tail(&self, marker: bool, _payload: &[u8]) -> bool {
         marker
     }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
 }
 
 impl Vp9Depacketizer {
     /// Updates provided [`CodecExtra`].
-    /// __MUST__ be called after the all transformations of `payload_index`.
     fn update_extra(
         &mut self,
         extra: &mut CodecExtra,
         out_len: usize,
-        packet_len: usize,
-        payload_index: usize,
+        payload_len: usize,
     ) -> Result<(), PacketError> {
         let mut vp9_extra = match extra {
             CodecExtra::Vp9(e) => *e,
@@ -364,7 +809,7 @@ impl Vp9Depacketizer {
         };
 
         if self.l {
-            let mut new_stop = out_len + packet_len - payload_index;
+            let mut new_stop = out_len + payload_len;
 
             if let Some(stop) = vp9_extra.layers_scheme[self.sid as usize] {
                 if stop != out_len {
@@ -394,222 +839,35 @@ impl Vp9Depacketizer {
         Ok(())
     }
 
-    // Picture ID:
-    //
-    //      +-+-+-+-+-+-+-+-+
-    // I:   |M| PICTURE ID  |   M:0 => picture id is 7 bits.
-    //      +-+-+-+-+-+-+-+-+   M:1 => picture id is 15 bits.
-    // M:   | EXTENDED PID  |
-    //      +-+-+-+-+-+-+-+-+
-    //
-    fn parse_picture_id(
+    /// Retains the scalability structure, which is only sent with some pictures.
+    fn set_scalability_structure(
         &mut self,
-        reader: &mut dyn BitRead,
-        mut payload_index: usize,
-    ) -> Result<usize, PacketError> {
-        if reader.remaining() == 0 {
-            return Err(PacketError::ErrShortPacket);
-        }
-        let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-        payload_index += 1;
-        // PID present?
-        if (b & 0x80) != 0 {
-            if reader.remaining() == 0 {
-                return Err(PacketError::ErrShortPacket);
-            }
-            // M == 1, PID is 15bit
-            let x = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            self.picture_id = (((b & 0x7f) as u16) << 8) | (x as u16);
-            payload_index += 1;
-        } else {
-            self.picture_id = (b & 0x7F) as u16;
-        }
-
-        Ok(payload_index)
-    }
-
-    fn parse_layer_info(
-        &mut self,
-        reader: &mut dyn BitRead,
-        mut payload_index: usize,
-    ) -> Result<usize, PacketError> {
-        payload_index = self.parse_layer_info_common(reader, payload_index)?;
-
-        if self.f {
-            Ok(payload_index)
-        } else {
-            self.parse_layer_info_non_flexible_mode(reader, payload_index)
-        }
-    }
-
-    // Layer indices (flexible mode):
-    //
-    //      +-+-+-+-+-+-+-+-+
-    // L:   |  T  |U|  S  |D|
-    //      +-+-+-+-+-+-+-+-+
-    //
-    fn parse_layer_info_common(
-        &mut self,
-        reader: &mut dyn BitRead,
-        mut payload_index: usize,
-    ) -> Result<usize, PacketError> {
-        if reader.remaining() == 0 {
-            return Err(PacketError::ErrShortPacket);
-        }
-        let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-        payload_index += 1;
-
-        self.tid = b >> 5;
-        self.u = b & 0x10 != 0;
-        self.sid = (b >> 1) & 0x7;
-        self.d = b & 0x01 != 0;
-
-        if self.sid as usize >= MAX_SPATIAL_LAYERS {
-            Err(PacketError::ErrTooManySpatialLayers)
-        } else {
-            Ok(payload_index)
-        }
-    }
-
-    // Layer indices (non-flexible mode):
-    //
-    //      +-+-+-+-+-+-+-+-+
-    // L:   |  T  |U|  S  |D|
-    //      +-+-+-+-+-+-+-+-+
-    //      |   tl0picidx   |
-    //      +-+-+-+-+-+-+-+-+
-    //
-    fn parse_layer_info_non_flexible_mode(
-        &mut self,
-        reader: &mut dyn BitRead,
-        mut payload_index: usize,
-    ) -> Result<usize, PacketError> {
-        if reader.remaining() == 0 {
-            return Err(PacketError::ErrShortPacket);
-        }
-        self.tl0picidx = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-        payload_index += 1;
-        Ok(payload_index)
-    }
-
-    // Reference indices:
-    //
-    //      +-+-+-+-+-+-+-+-+                P=1,F=1: At least one reference index
-    // P,F: | P_DIFF      |N|  up to 3 times          has to be specified.
-    //      +-+-+-+-+-+-+-+-+                    N=1: An additional P_DIFF follows
-    //                                                current P_DIFF.
-    //
-    fn parse_ref_indices(
-        &mut self,
-        reader: &mut dyn BitRead,
-        mut payload_index: usize,
-    ) -> Result<usize, PacketError> {
-        let mut b = 1u8;
-        while (b & 0x1) != 0 {
-            if reader.remaining() == 0 {
-                return Err(PacketError::ErrShortPacket);
-            }
-            b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            payload_index += 1;
-
-            self.pdiff.push(b >> 1);
-            if self.pdiff.len() >= MAX_VP9REF_PICS {
-                return Err(PacketError::ErrTooManyPDiff);
-            }
-        }
-
-        Ok(payload_index)
-    }
-
-    // Scalability structure (SS):
-    //
-    //      +-+-+-+-+-+-+-+-+
-    // V:   | N_S |Y|G|-|-|-|
-    //      +-+-+-+-+-+-+-+-+              -|
-    // Y:   |     WIDTH     | (OPTIONAL)    .
-    //      +               +               .
-    //      |               | (OPTIONAL)    .
-    //      +-+-+-+-+-+-+-+-+               . N_S + 1 times
-    //      |     HEIGHT    | (OPTIONAL)    .
-    //      +               +               .
-    //      |               | (OPTIONAL)    .
-    //      +-+-+-+-+-+-+-+-+              -|
-    // G:   |      N_G      | (OPTIONAL)
-    //      +-+-+-+-+-+-+-+-+                           -|
-    // N_G: |  T  |U| R |-|-| (OPTIONAL)                 .
-    //      +-+-+-+-+-+-+-+-+              -|            . N_G times
-    //      |    P_DIFF     | (OPTIONAL)    . R times    .
-    //      +-+-+-+-+-+-+-+-+              -|           -|
-    //
-    fn parse_ssdata(
-        &mut self,
-        reader: &mut dyn BitRead,
-        mut payload_index: usize,
-    ) -> Result<usize, PacketError> {
-        if reader.remaining() == 0 {
-            return Err(PacketError::ErrShortPacket);
-        }
-
-        let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-        payload_index += 1;
-
-        self.ns = b >> 5;
-        self.y = b & 0x10 != 0;
-        self.g = (b >> 1) & 0x7 != 0;
-
-        let ns = (self.ns + 1) as usize;
-        self.ng = 0;
-
-        if ns >= MAX_SPATIAL_LAYERS {
-            return Err(PacketError::ErrVP9CorruptedPacket);
+        ss: Vp9ScalabilityStructure,
+    ) -> Result<(), PacketError> {
+        if ss.spatial_layers as usize > MAX_SPATIAL_LAYERS {
+            return Err(PacketError::ErrTooManySpatialLayers);
         }
 
-        if self.y {
-            if reader.remaining() < 4 * ns {
-                return Err(PacketError::ErrShortPacket);
-            }
-
-            for i in 0..ns {
-                self.width[i] = Some(reader.get_u16().ok_or(PacketError::ErrShortPacket)?);
-                self.height[i] = Some(reader.get_u16().ok_or(PacketError::ErrShortPacket)?);
-            }
-            payload_index += 4 * ns;
-        }
+        self.ns = ss.spatial_layers - 1;
+        self.y = ss.resolutions.is_some();
+        self.g = ss.picture_group.is_some();
 
-        if self.g {
-            if reader.remaining() == 0 {
-                return Err(PacketError::ErrShortPacket);
+        if let Some(resolutions) = ss.resolutions {
+            self.width = [None; MAX_SPATIAL_LAYERS];
+            self.height = [None; MAX_SPATIAL_LAYERS];
+            for (i, (w, h)) in resolutions.into_iter().enumerate() {
+                self.width[i] = Some(w);
+                self.height[i] = Some(h);
             }
-
-            self.ng = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            payload_index += 1;
         }
 
-        for i in 0..self.ng as usize {
-            if reader.remaining() == 0 {
-                return Err(PacketError::ErrShortPacket);
-            }
-            let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-            payload_index += 1;
-
-            self.pgtid.push(b >> 5);
-            self.pgu.push(b & 0x10 != 0);
-
-            let r = ((b >> 2) & 0x3) as usize;
-            if reader.remaining() < r {
-                return Err(PacketError::ErrShortPacket);
-            }
-
-            self.pgpdiff.push(vec![]);
-            for _ in 0..r {
-                let b = reader.get_u8().ok_or(PacketError::ErrShortPacket)?;
-                payload_index += 1;
-
-                self.pgpdiff[i].push(b);
-            }
-        }
+        let pg = ss.picture_group.unwrap_or_default();
+        self.ng = pg.len() as u8;
+        self.pgtid = pg.iter().map(|p| p.tid).collect();
+        self.pgu = pg.iter().map(|p| p.switching_up_point).collect();
+        self.pgpdiff = pg.into_iter().map(|p| p.ref_indices).collect();
 
-        Ok(payload_index)
+        Ok(())
     }
 }
 
@@ -964,6 +1222,338 @@ mod test {
         Ok(())
     }
 
+    fn depacketize_all(packets: &[Vec<u8>]) -> (Vec<u8>, Vp9CodecExtra) {
+        let mut depack = Vp9Depacketizer::default();
+        let mut out = vec![];
+        let mut extra = CodecExtra::None;
+        for p in packets {
+            depack.depacketize(p, &mut out, &mut extra).unwrap();
+        }
+        let CodecExtra::Vp9(e) = extra else {
+            panic!("vp9 extra");
+        };
+        (out, e)
+    }
+
+    fn l3t1_keyframe() -> (Vec<u8>, Vp9FrameInfo) {
+        let sizes = [300, 700, 1500];
+        let data: Vec<u8> = (0..sizes.iter().sum::<usize>()).map(|i| i as u8).collect();
+
+        let layers = sizes
+            .iter()
+            .enumerate()
+            .map(|(sid, size)| Vp9LayerFrame {
+                sid: sid as u8,
+                size: *size,
+                inter_layer_dependency: sid > 0,
+                ..Default::default()
+            })
+            .collect();
+
+        let info = Vp9FrameInfo {
+            tid: 0,
+            layers,
+            scalability_structure: Some(Vp9ScalabilityStructure {
+                spatial_layers: 3,
+                resolutions: Some(vec![(320, 180), (640, 360), (1280, 720)]),
+                picture_group: Some(vec![Vp9PictureGroupEntry {
+                    tid: 0,
+                    switching_up_point: false,
+                    ref_indices: vec![1],
+                }]),
+            }),
+            ..Default::default()
+        };
+
+        (data, info)
+    }
+
+    #[test]
+    fn test_vp9_packetizer_layers_round_trip() -> Result<(), PacketError> {
+        let (data, info) = l3t1_keyframe();
+
+        let mut pck = Vp9Packetizer {
+            initial_picture_id: 0x1234,
+            ..Default::default()
+        };
+        let packets = pck.packetize_frame(1000, &data, &CodecFrameInfo::Vp9(info.clone()))?;
+
+        assert!(packets.iter().all(|p| p.len() <= 1000));
+
+        let metas: Vec<_> = packets
+            .iter()
+            .map(|p| Vp9Meta::parse(p).unwrap().0)
+            .collect();
+
+        // One packet for SL0 and SL1, two for SL2.
+        let layers: Vec<_> = metas
+            .iter()
+            .map(|m| (m.sid.unwrap(), m.start_of_frame, m.end_of_frame))
+            .collect();
+        assert_eq!(
+            layers,
+            [
+                (0, true, true),
+                (1, true, true),
+                (2, true, false),
+                (2, false, true)
+            ]
+        );
+
+        for m in &metas {
+            assert_eq!(m.picture_id, Some(0x1234));
+            assert_eq!(m.tid, Some(0));
+            assert_eq!(m.inter_layer_dependency, m.sid != Some(0));
+            assert!(!m.flexible_mode);
+        }
+        assert!(metas[0].is_keyframe);
+        assert_eq!(metas[0].scalability_structure, info.scalability_structure);
+        assert!(metas[1..].iter().all(|m| m.scalability_structure.is_none()));
+
+        let (out, extra) = depacketize_all(&packets);
+        assert_eq!(out, data);
+        assert!(extra.is_keyframe);
+        assert_eq!(extra.layers_scheme, [Some(300), Some(1000), Some(2500)]);
+        assert_eq!(extra.layers_widths, [Some(320), Some(640), Some(1280)]);
+
+        // TL0PICIDX increments with each base layer picture.
+        let tl0 = metas[0].tl0_pic_idx.unwrap();
+        let packets = pck.packetize_frame(1000, &data, &CodecFrameInfo::Vp9(info))?;
+        let (meta, _) = Vp9Meta::parse(&packets[0])?;
+        assert_eq!(meta.picture_id, Some(0x1235));
+        assert_eq!(meta.tl0_pic_idx, Some(tl0.wrapping_add(1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vp9_packetizer_layers_flexible() -> Result<(), PacketError> {
+        let info = Vp9FrameInfo {
+            flexible_mode: true,
+            tid: 1,
+            switching_up_point: true,
+            layers: vec![
+                Vp9LayerFrame {
+                    sid: 0,
+                    size: 3,
+                    inter_picture_predicted: true,
+                    ref_indices: vec![1, 2],
+                    ..Default::default()
+                },
+                Vp9LayerFrame {
+                    sid: 1,
+                    size: 2,
+                    inter_picture_predicted: true,
+                    not_upper_layer_reference: true,
+                    ref_indices: vec![1],
+                    ..Default::default()
+                },
+            ],
+            scalability_structure: None,
+        };
+
+        let mut pck = Vp9Packetizer {
+            initial_picture_id: 0x10,
+            ..Default::default()
+        };
+        let packets =
+            pck.packetize_frame(100, &[1, 2, 3, 4, 5], &CodecFrameInfo::Vp9(info.clone()))?;
+
+        assert_eq!(
+            packets,
+            [
+                vec![0xfc, 0x80, 0x10, 0x30, 0x03, 0x04, 1, 2, 3],
+                vec![0xfd, 0x80, 0x10, 0x32, 0x02, 4, 5],
+            ]
+        );
+
+        let (out, extra) = depacketize_all(&packets);
+        assert_eq!(out, [1, 2, 3, 4, 5]);
+        assert_eq!(extra.tid, Some(1));
+        assert!(!extra.is_keyframe);
+
+        // P_DIFF is required for predicted frames in flexible mode.
+        let mut invalid = info.clone();
+        invalid.layers[1].ref_indices.clear();
+        let result = pck.packetize_frame(100, &[1, 2, 3, 4, 5], &CodecFrameInfo::Vp9(invalid));
+        assert_eq!(result, Err(PacketError::ErrVp9InvalidFrameInfo));
+
+        // The layer sizes must add up to the data.
+        let result = pck.packetize_frame(100, &[1, 2, 3, 4], &CodecFrameInfo::Vp9(info));
+        assert_eq!(result, Err(PacketError::ErrVp9InvalidFrameInfo));
+
+        Ok(())
+    }
+
+    // First packets of a 3 spatial layer k-SVC (L3T3_KEY) keyframe picture. Only the base
+    // layer carries the SS, the upper layers depend on the layer below.
+    const KSVC_KEYFRAME_SL0: &[u8] = &[
+        0xAA, // I:1 P:0 L:1 F:0 B:1 E:0 V:1 Z:0
+        0x92, 0x34, // M:1 PID:0x1234
+        0x00, // TID:0 U:0 SID:0 D:0
+        0x05, // TL0PICIDX
+        0x58, // N_S:2 Y:1 G:1
+        0x01, 0x40, 0x00, 0xB4, // 320x180
+        0x02, 0x80, 0x01, 0x68, // 640x360
+        0x05, 0x00, 0x02, 0xD0, // 1280x720
+        0x04, // N_G
+        0x04, 0x04, // T:0 U:0 R:1 P_DIFF:4
+        0x54, 0x01, // T:2 U:1 R:1 P_DIFF:1
+        0x34, 0x02, // T:1 U:1 R:1 P_DIFF:2
+        0x54, 0x01, // T:2 U:1 R:1 P_DIFF:1
+        0x82, 0x49, 0x83, 0x42, // VP9 keyframe header
+    ];
+    const KSVC_KEYFRAME_SL1: &[u8] = &[0xAC, 0x92, 0x34, 0x03, 0x05, 0x86, 0x00];
+    const KSVC_KEYFRAME_SL2: &[u8] = &[0xAC, 0x92, 0x34, 0x05, 0x05, 0x87, 0x00, 0x01];
+    // Upper layer of a following delta picture, without inter-layer dependency.
+    const KSVC_DELTA_SL1: &[u8] = &[0xEC, 0x92, 0x35, 0x52, 0x05, 0x86, 0x02];
+
+    #[test]
+    fn test_vp9_meta_ksvc_keyframe() {
+        let (meta, payload) = Vp9Meta::parse(KSVC_KEYFRAME_SL0).unwrap();
+        assert_eq!(
+            meta,
+            Vp9Meta {
+                inter_picture_predicted: false,
+                flexible_mode: false,
+                start_of_frame: true,
+                end_of_frame: false,
+                not_upper_layer_reference: false,
+                picture_id: Some(0x1234),
+                long_picture_id: true,
+                tid: Some(0),
+                switching_up_point: false,
+                sid: Some(0),
+                inter_layer_dependency: false,
+                tl0_pic_idx: Some(5),
+                ref_indices: vec![],
+                scalability_structure: Some(Vp9ScalabilityStructure {
+                    spatial_layers: 3,
+                    resolutions: Some(vec![(320, 180), (640, 360), (1280, 720)]),
+                    picture_group: Some(vec![
+                        Vp9PictureGroupEntry {
+                            tid: 0,
+                            switching_up_point: false,
+                            ref_indices: vec![4],
+                        },
+                        Vp9PictureGroupEntry {
+                            tid: 2,
+                            switching_up_point: true,
+                            ref_indices: vec![1],
+                        },
+                        Vp9PictureGroupEntry {
+                            tid: 1,
+                            switching_up_point: true,
+                            ref_indices: vec![2],
+                        },
+                        Vp9PictureGroupEntry {
+                            tid: 2,
+                            switching_up_point: true,
+                            ref_indices: vec![1],
+                        },
+                    ]),
+                }),
+                is_keyframe: true,
+            }
+        );
+        assert_eq!(payload, &[0x82, 0x49, 0x83, 0x42]);
+        assert!(!meta.is_end_of_picture(false));
+
+        let (meta, payload) = Vp9Meta::parse(KSVC_KEYFRAME_SL1).unwrap();
+        assert_eq!(meta.sid, Some(1));
+        assert!(meta.inter_layer_dependency);
+        assert!(meta.end_of_frame);
+        assert!(!meta.is_keyframe);
+        assert!(!meta.is_end_of_picture(false));
+        assert_eq!(meta.scalability_structure, None);
+        assert_eq!(payload, &[0x86, 0x00]);
+
+        let (meta, _) = Vp9Meta::parse(KSVC_KEYFRAME_SL2).unwrap();
+        assert_eq!(meta.sid, Some(2));
+        assert!(meta.is_end_of_picture(true));
+
+        let (meta, _) = Vp9Meta::parse(KSVC_DELTA_SL1).unwrap();
+        assert!(meta.inter_picture_predicted);
+        assert!(!meta.inter_layer_dependency);
+        assert!(meta.switching_up_point);
+        assert_eq!(meta.tid, Some(2));
+        assert_eq!(meta.picture_id, Some(0x1235));
+        assert!(!meta.is_keyframe);
+    }
+
+    #[test]
+    fn test_vp9_depacketize_ksvc_keyframe() {
+        let mut d = Vp9Depacketizer::default();
+        let mut out = Vec::new();
+        let mut extra = CodecExtra::None;
+
+        for packet in [KSVC_KEYFRAME_SL0, KSVC_KEYFRAME_SL1, KSVC_KEYFRAME_SL2] {
+            d.depacketize(packet, &mut out, &mut extra).unwrap();
+        }
+
+        assert_eq!(out, [0x82, 0x49, 0x83, 0x42, 0x86, 0x00, 0x87, 0x00, 0x01]);
+        assert_eq!(d.ns, 2);
+        assert!(d.g);
+        assert_eq!(d.pgtid, [0, 2, 1, 2]);
+
+        let CodecExtra::Vp9(extra) = extra else {
+            panic!("Expected VP9 extra");
+        };
+        assert!(extra.is_keyframe);
+        assert_eq!(extra.layers_scheme, [Some(4), Some(6), Some(9)]);
+        assert_eq!(extra.layers_widths, [Some(320), Some(640), Some(1280)]);
+        assert_eq!(extra.layers_heights, [Some(180), Some(360), Some(720)]);
+
+        // The SS is retained for the following pictures.
+        let mut out = Vec::new();
+        let mut extra = CodecExtra::None;
+        d.depacketize(KSVC_DELTA_SL1, &mut out, &mut extra).unwrap();
+        let CodecExtra::Vp9(extra) = extra else {
+            panic!("Expected VP9 extra");
+        };
+        assert!(!extra.is_keyframe);
+        assert_eq!(extra.layers_widths, [Some(320), Some(640), Some(1280)]);
+    }
+
+    #[test]
+    fn test_vp9_meta_malformed() {
+        // SS claiming 8 spatial layers with resolutions, but only two are present.
+        let truncated = &[0x0A, 0xF0, 0x01, 0x40, 0x00, 0xB4, 0x02, 0x80, 0x01, 0x68];
+        assert_eq!(
+            Vp9Meta::parse(truncated).unwrap_err(),
+            PacketError::ErrShortPacket
+        );
+        let mut d = Vp9Depacketizer::default();
+        let err = d.depacketize(truncated, &mut vec![], &mut CodecExtra::None);
+        assert_eq!(err.unwrap_err(), PacketError::ErrShortPacket);
+
+        // 8 spatial layers are valid in the descriptor, but more than we can depacketize.
+        let mut eight = vec![0x0A, 0xF0];
+        for _ in 0..8 {
+            eight.extend_from_slice(&[0x01, 0x40, 0x00, 0xB4]);
+        }
+        let (meta, _) = Vp9Meta::parse(&eight).unwrap();
+        assert_eq!(meta.scalability_structure.unwrap().spatial_layers, 8);
+        let err = d.depacketize(&eight, &mut vec![], &mut CodecExtra::None);
+        assert_eq!(err.unwrap_err(), PacketError::ErrTooManySpatialLayers);
+
+        // Picture group entry missing its P_DIFF.
+        let truncated = &[0x0A, 0x08, 0x01, 0x04];
+        assert_eq!(
+            Vp9Meta::parse(truncated).unwrap_err(),
+            PacketError::ErrShortPacket
+        );
+
+        // Three reference indices is the maximum.
+        let (meta, payload) = Vp9Meta::parse(&[0xD0, 0x02, 0x03, 0x05, 0x06, 0xAA]).unwrap();
+        assert_eq!(meta.ref_indices, [1, 2, 3]);
+        assert_eq!(payload, &[0xAA]);
+        assert_eq!(
+            Vp9Meta::parse(&[0xD0, 0x02, 0x03, 0x05, 0x07, 0x08, 0xAA]).unwrap_err(),
+            PacketError::ErrTooManyPDiff
+        );
+    }
+
     #[test]
     fn test_vp9_partition_head_checker_is_partition_head() -> Result<(), PacketError> {
         let vp9 = Vp9Depacketizer::default();
@@ -0,0 +1,478 @@
+use super::Vp9Meta;
+
+/// Selects the VP9 SVC layers to forward, such as in an SFU.
+///
+/// Give every incoming packet's [`Vp9Meta`] to [`Vp9LayerSelector::select()`], in order,
+/// and forward the packets it says to with the marker bit it says.
+///
+/// * Temporal layers above the target are always dropped. Switching down is done at the
+///   next picture, and switching up at a picture with the switching up point (U bit), or
+///   a keyframe.
+/// * Spatial layers above the target are dropped. Switching down is done at the next
+///   picture. Switching up needs a temporal layer 0 frame that is not predicted from an
+///   earlier picture of its own layer, i.e. intra or only predicted from the layer below.
+///   With k-SVC those only come with keyframes, which is what
+///   [`Vp9Selection::needs_keyframe`] is about.
+/// * When the top forwarded spatial layer is below what the sender sends, the marker bit
+///   is moved to the end of the top forwarded layer frame, since that's the end of the
+///   picture for the receiver. A switch up without keyframe is only known once the upper
+///   layer frame starts, which means such a picture also has the marker on the layer below.
+///
+/// Packets without layer indices are treated as spatial and temporal layer 0.
+#[derive(Debug)]
+pub struct Vp9LayerSelector {
+    target_spatial: u8,
+    target_temporal: u8,
+    /// Spatial layer forwarded up to. None until the first decodable base layer frame.
+    spatial: Option<u8>,
+    /// Temporal layer forwarded up to.
+    temporal: u8,
+    /// Whether the current picture is forwarded at all.
+    picture_forwarded: bool,
+    /// Whether the current layer frame is forwarded.
+    frame_forwarded: bool,
+    /// Whether the current picture is a keyframe switching up the spatial layer.
+    picture_switching: bool,
+    /// Whether a keyframe has been asked for since the switch got stuck.
+    keyframe_asked: bool,
+}
+
+/// What to do with one packet, from [`Vp9LayerSelector::select()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vp9Selection {
+    /// Whether to forward the packet.
+    pub forward: bool,
+    /// The marker bit to forward the packet with.
+    pub marker: bool,
+    /// A switch to the target can't be done without a keyframe.
+    ///
+    /// Set once per stuck switch, the caller is expected to send a PLI or FIR.
+    pub needs_keyframe: bool,
+}
+
+impl Vp9LayerSelector {
+    /// Create a selector forwarding up to the given spatial and temporal layer.
+    pub fn new(target_spatial: u8, target_temporal: u8) -> Self {
+        Vp9LayerSelector {
+            target_spatial,
+            target_temporal,
+            spatial: None,
+            temporal: 0,
+            picture_forwarded: false,
+            frame_forwarded: false,
+            picture_switching: false,
+            keyframe_asked: false,
+        }
+    }
+
+    /// Change the target spatial and temporal layer.
+    pub fn set_target(&mut self, spatial: u8, temporal: u8) {
+        if (spatial, temporal) != (self.target_spatial, self.target_temporal) {
+            self.keyframe_asked = false;
+        }
+        self.target_spatial = spatial;
+        self.target_temporal = temporal;
+    }
+
+    /// The target spatial and temporal layer.
+    pub fn target(&self) -> (u8, u8) {
+        (self.target_spatial, self.target_temporal)
+    }
+
+    /// The spatial and temporal layer currently forwarded up to.
+    ///
+    /// None until the first decodable base layer frame.
+    pub fn current(&self) -> Option<(u8, u8)> {
+        self.spatial.map(|s| (s, self.temporal))
+    }
+
+    /// Decide on one packet, with the `marker` bit it was received with.
+    pub fn select(&mut self, meta: &Vp9Meta, marker: bool) -> Vp9Selection {
+        let sid = meta.sid.unwrap_or(0);
+        let tid = meta.tid.unwrap_or(0);
+        let mut needs_keyframe = false;
+
+        if meta.start_of_frame && sid == 0 {
+            self.start_picture(meta, tid);
+        }
+
+        if meta.start_of_frame {
+            self.frame_forwarded = self.picture_forwarded && self.start_frame(meta, sid);
+
+            let next = self.spatial.map(|s| s + 1).unwrap_or(0);
+            let stuck = self.picture_forwarded
+                && sid == next
+                && sid <= self.target_spatial
+                && !self.frame_forwarded;
+
+            if stuck && !self.keyframe_asked {
+                self.keyframe_asked = true;
+                needs_keyframe = true;
+            }
+        }
+
+        let forward = self.frame_forwarded;
+        // A keyframe has all layers, the switch up will carry on to the layer above.
+        let top =
+            self.spatial == Some(sid) && !(self.picture_switching && sid < self.target_spatial);
+
+        Vp9Selection {
+            forward,
+            marker: forward && (marker || top && meta.end_of_frame),
+            needs_keyframe,
+        }
+    }
+
+    fn start_picture(&mut self, meta: &Vp9Meta, tid: u8) {
+        // Lower layers never depend on higher, so switching down is always possible.
+        if let Some(spatial) = self.spatial {
+            self.spatial = Some(spatial.min(self.target_spatial));
+        }
+        self.temporal = self.temporal.min(self.target_temporal);
+
+        let switch_up = meta.is_keyframe || meta.switching_up_point;
+        if switch_up && tid > self.temporal && tid <= self.target_temporal {
+            // A keyframe resets the temporal prediction entirely, the U bit only from tid.
+            self.temporal = if meta.is_keyframe {
+                self.target_temporal
+            } else {
+                tid
+            };
+        }
+
+        self.picture_forwarded = tid <= self.temporal || meta.is_keyframe;
+        self.picture_switching = meta.is_keyframe && self.spatial < Some(self.target_spatial);
+    }
+
+    /// Whether to forward the layer frame starting with this packet.
+    fn start_frame(&mut self, meta: &Vp9Meta, sid: u8) -> bool {
+        if self.spatial.map_or(false, |s| sid <= s) {
+            return true;
+        }
+
+        // Only one layer up at a time, and only to a frame not needing earlier pictures
+        // of its layer. Inter-layer prediction is fine, the layer below was forwarded.
+        // Later pictures of lower temporal layers are predicted from temporal layer 0, which
+        // is why a switch at a higher temporal layer would break.
+        let next = self.spatial.map(|s| s + 1).unwrap_or(0);
+        let tid = meta.tid.unwrap_or(0);
+        if sid == next && sid <= self.target_spatial && tid == 0 && !meta.inter_picture_predicted {
+            self.spatial = Some(sid);
+            self.keyframe_asked = false;
+            return true;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// One packet of an annotated trace.
+    #[derive(Debug, Clone)]
+    struct Packet {
+        pic: usize,
+        meta: Vp9Meta,
+        marker: bool,
+        /// Layer frames (pic, sid) this layer frame needs to be decoded.
+        refs: Vec<(usize, u8)>,
+    }
+
+    const L3T3_TIDS: [u8; 4] = [0, 2, 1, 2];
+
+    /// A k-SVC L3T3 trace, 3 spatial layers of 2 packets each per picture.
+    ///
+    /// Pictures in `keyframes` are keyframes, where the upper spatial layers are only
+    /// predicted from the layer below. Other pictures only predict from earlier pictures
+    /// of the same spatial layer, following the temporal pattern 0, 2, 1, 2 from the last
+    /// keyframe:
+    ///
+    /// * tid 0 from the previous tid 0
+    /// * tid 1 from the previous tid 0
+    /// * tid 2 from the previous tid 1 or tid 0, whichever is later
+    ///
+    /// Pictures in `layer_syncs` have the upper spatial layers predicted only from the layer
+    /// below, as full SVC encoders can do without a keyframe.
+    fn trace(pictures: usize, keyframes: &[usize], layer_syncs: &[usize]) -> Vec<Packet> {
+        let mut packets = vec![];
+
+        for pic in 0..pictures {
+            // The temporal pattern restarts at every keyframe.
+            let keyframe = *keyframes.iter().filter(|k| **k <= pic).max().unwrap();
+            let tid_of = |p: usize| L3T3_TIDS[(p - keyframe) % 4];
+
+            let tid = tid_of(pic);
+            let is_keyframe = keyframe == pic;
+            let is_sync = layer_syncs.contains(&pic);
+
+            // The picture predicted from, which is the same for all spatial layers.
+            let prev = (keyframe..pic)
+                .rev()
+                .find(|p| {
+                    let t = tid_of(*p);
+                    if tid == 0 {
+                        t == 0
+                    } else {
+                        t < tid
+                    }
+                })
+                .filter(|_| !is_keyframe);
+
+            // The frame right after a tid 1 can't switch up from tid 0.
+            let switching_up_point = tid > 0 && (pic - keyframe) % 4 != 3;
+
+            for sid in 0..3 {
+                let inter_picture_predicted = !(is_keyframe || (is_sync && sid > 0));
+                let inter_layer_dependency = sid > 0 && (is_keyframe || is_sync);
+
+                let mut refs = vec![];
+                if inter_picture_predicted {
+                    refs.push((prev.unwrap(), sid));
+                }
+                if inter_layer_dependency {
+                    refs.push((pic, sid - 1));
+                }
+
+                for n in 0..2 {
+                    let meta = Vp9Meta {
+                        inter_picture_predicted,
+                        start_of_frame: n == 0,
+                        end_of_frame: n == 1,
+                        picture_id: Some(pic as u16),
+                        tid: Some(tid),
+                        switching_up_point,
+                        sid: Some(sid),
+                        inter_layer_dependency,
+                        tl0_pic_idx: Some((pic / 4) as u8),
+                        is_keyframe: is_keyframe && sid == 0 && n == 0,
+                        ..Default::default()
+                    };
+
+                    packets.push(Packet {
+                        pic,
+                        meta,
+                        marker: sid == 2 && n == 1,
+                        refs: refs.clone(),
+                    });
+                }
+            }
+        }
+
+        packets
+    }
+
+    struct Outcome {
+        /// Forwarded layer frames (pic, sid).
+        frames: Vec<(usize, u8)>,
+        /// Index in the trace of packets forwarded with marker.
+        markers: Vec<usize>,
+        /// Index in the trace of packets signalling needs keyframe.
+        keyframe_needed: Vec<usize>,
+    }
+
+    /// Run the trace, changing target at the given packet indices.
+    fn run(
+        selector: &mut Vp9LayerSelector,
+        packets: &[Packet],
+        changes: &[(usize, (u8, u8))],
+    ) -> Outcome {
+        let mut out = Outcome {
+            frames: vec![],
+            markers: vec![],
+            keyframe_needed: vec![],
+        };
+        let mut forwarded = vec![];
+
+        for (i, p) in packets.iter().enumerate() {
+            for (at, (s, t)) in changes {
+                if *at == i {
+                    selector.set_target(*s, *t);
+                }
+            }
+
+            let sel = selector.select(&p.meta, p.marker);
+            if sel.forward {
+                forwarded.push(i);
+            }
+            if sel.marker {
+                out.markers.push(i);
+            }
+            if sel.needs_keyframe {
+                out.keyframe_needed.push(i);
+            }
+        }
+
+        // Layer frames are forwarded whole, or not at all.
+        for i in (0..packets.len()).step_by(2) {
+            let first = forwarded.contains(&i);
+            assert_eq!(first, forwarded.contains(&(i + 1)), "Half frame at {}", i);
+            if first {
+                out.frames
+                    .push((packets[i].pic, packets[i].meta.sid.unwrap()));
+            }
+        }
+
+        assert_decodable(packets, &out.frames);
+        assert_markers(packets, &out);
+
+        out
+    }
+
+    fn assert_decodable(packets: &[Packet], frames: &[(usize, u8)]) {
+        for frame in frames {
+            let p = packets
+                .iter()
+                .find(|p| (p.pic, p.meta.sid.unwrap()) == *frame)
+                .unwrap();
+            for r in &p.refs {
+                assert!(
+                    frames.contains(r),
+                    "Frame {:?} forwarded without its reference {:?}",
+                    frame,
+                    r
+                );
+            }
+        }
+    }
+
+    /// Every forwarded picture has a marker on its last forwarded packet, and markers are
+    /// only at the end of layer frames.
+    fn assert_markers(packets: &[Packet], out: &Outcome) {
+        let mut pics: Vec<usize> = out.frames.iter().map(|(p, _)| *p).collect();
+        pics.dedup();
+
+        let mut expected = vec![];
+        for pic in pics {
+            let top = out.frames.iter().filter(|f| f.0 == pic).map(|f| f.1).max();
+            let last = packets
+                .iter()
+                .rposition(|p| p.pic == pic && p.meta.sid == top)
+                .unwrap();
+            expected.push(last);
+        }
+
+        for i in &expected {
+            assert!(out.markers.contains(i), "No marker at end of picture {}", i);
+        }
+        for i in &out.markers {
+            assert!(packets[*i].meta.end_of_frame, "Marker mid frame {}", i);
+        }
+    }
+
+    fn pictures(frames: &[(usize, u8)], sid: u8) -> Vec<usize> {
+        frames.iter().filter(|f| f.1 == sid).map(|f| f.0).collect()
+    }
+
+    #[test]
+    fn forward_all() {
+        let packets = trace(12, &[0], &[]);
+        let mut selector = Vp9LayerSelector::new(2, 2);
+
+        let out = run(&mut selector, &packets, &[]);
+
+        assert_eq!(out.frames.len(), 36);
+        assert_eq!(out.markers.len(), 12);
+        assert!(out.keyframe_needed.is_empty());
+        assert_eq!(selector.current(), Some((2, 2)));
+    }
+
+    #[test]
+    fn base_layer_only() {
+        let packets = trace(12, &[0], &[]);
+        let mut selector = Vp9LayerSelector::new(0, 0);
+
+        let out = run(&mut selector, &packets, &[]);
+
+        // Only S0T0, with the marker moved to the end of S0.
+        assert_eq!(out.frames, vec![(0, 0), (4, 0), (8, 0)]);
+        assert_eq!(out.markers, vec![1, 4 * 6 + 1, 8 * 6 + 1]);
+        assert_eq!(selector.current(), Some((0, 0)));
+    }
+
+    #[test]
+    fn wait_for_keyframe_to_start() {
+        // Joining mid-stream at picture 5, nothing is decodable before the keyframe at 8.
+        let packets: Vec<_> = trace(12, &[0, 8], &[]).split_off(5 * 6);
+        let mut selector = Vp9LayerSelector::new(1, 2);
+
+        let out = run(&mut selector, &packets, &[]);
+
+        assert_eq!(out.keyframe_needed, vec![0]);
+        assert_eq!(pictures(&out.frames, 0), vec![8, 9, 10, 11]);
+        assert_eq!(pictures(&out.frames, 1), vec![8, 9, 10, 11]);
+        assert!(pictures(&out.frames, 2).is_empty());
+    }
+
+    #[test]
+    fn spatial_switch_up_needs_keyframe() {
+        let packets = trace(16, &[0, 9], &[]);
+        let mut selector = Vp9LayerSelector::new(0, 2);
+
+        // Switch up to S2 in the middle of picture 2.
+        let out = run(&mut selector, &packets, &[(2 * 6 + 3, (2, 2))]);
+
+        // Asked for once, at the first S1 frame that couldn't be switched to.
+        assert_eq!(out.keyframe_needed, vec![3 * 6 + 2]);
+
+        assert_eq!(pictures(&out.frames, 0), (0..16).collect::<Vec<_>>());
+        assert_eq!(pictures(&out.frames, 1), (9..16).collect::<Vec<_>>());
+        assert_eq!(pictures(&out.frames, 2), (9..16).collect::<Vec<_>>());
+        assert_eq!(selector.current(), Some((2, 2)));
+    }
+
+    #[test]
+    fn spatial_switch_up_at_layer_sync() {
+        let packets = trace(12, &[0], &[2, 4]);
+        let mut selector = Vp9LayerSelector::new(0, 2);
+
+        let out = run(&mut selector, &packets, &[(2 * 6, (2, 2))]);
+
+        // Picture 2 is tid 1, switching there would leave picture 4 undecodable. Picture 4
+        // has S1 and S2 only predicted from the layer below.
+        assert_eq!(out.keyframe_needed, vec![2 * 6 + 2]);
+        assert_eq!(pictures(&out.frames, 1), (4..12).collect::<Vec<_>>());
+        assert_eq!(pictures(&out.frames, 2), (4..12).collect::<Vec<_>>());
+
+        // The switching picture also has the marker at the end of S0 and S1.
+        assert_eq!(out.markers.len(), 12 + 2);
+    }
+
+    #[test]
+    fn spatial_switch_down_next_picture() {
+        let packets = trace(8, &[0], &[]);
+        let mut selector = Vp9LayerSelector::new(2, 2);
+
+        // In the middle of the S1 frame of picture 3.
+        let out = run(&mut selector, &packets, &[(3 * 6 + 3, (0, 2))]);
+
+        assert!(out.keyframe_needed.is_empty());
+        assert_eq!(pictures(&out.frames, 0), (0..8).collect::<Vec<_>>());
+        assert_eq!(pictures(&out.frames, 2), (0..4).collect::<Vec<_>>());
+        assert_eq!(out.markers.len(), 8);
+        assert_eq!(selector.current(), Some((0, 2)));
+    }
+
+    #[test]
+    fn temporal_switch() {
+        let packets = trace(16, &[0], &[]);
+        let mut selector = Vp9LayerSelector::new(0, 0);
+
+        // Up to T2 in picture 2 (tid 1), the picture 3 (tid 2) is not a switching point
+        // since it's predicted from picture 2. Down to T1 in picture 9, which is still
+        // forwarded whole.
+        let out = run(
+            &mut selector,
+            &packets,
+            &[(2 * 6 + 1, (0, 2)), (9 * 6 + 1, (0, 1))],
+        );
+
+        assert!(out.keyframe_needed.is_empty());
+        assert_eq!(
+            pictures(&out.frames, 0),
+            vec![0, 4, 5, 6, 7, 8, 9, 10, 12, 14]
+        );
+        assert_eq!(selector.current(), Some((0, 1)));
+    }
+}
@@ -3,11 +3,13 @@ use std::iter::Sum;
 use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
 use std::time::Duration;
 
+use serde::Serialize;
+
 /// A data rate expressed as bits per second(bps).
 ///
 /// Internally the value is tracked as a floating point number for accuracy in the presence of
 /// repeated calculations that can yield decimal values.
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize)]
 pub struct Bitrate(f64);
 
 impl Bitrate {
@@ -0,0 +1,436 @@
+/// Color space information of a video stream.
+///
+/// Sent in the color-space header extension, typically only on keyframes. The enum values
+/// follow ITU-T H.273, as used by WebRTC.
+///
+/// <https://webrtc.googlesource.com/src/+/refs/heads/main/docs/native-code/rtp-hdrext/color-space>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpace {
+    /// The color primaries.
+    pub primaries: ColorPrimaries,
+    /// The transfer characteristics.
+    pub transfer: TransferCharacteristics,
+    /// The matrix coefficients.
+    pub matrix: MatrixCoefficients,
+    /// Whether the full or limited range is used.
+    pub range: ColorRange,
+    /// Horizontal chroma siting.
+    pub chroma_siting_horizontal: ChromaSiting,
+    /// Vertical chroma siting.
+    pub chroma_siting_vertical: ChromaSiting,
+    /// Optional HDR metadata. Makes the extension 28 bytes instead of 4, which
+    /// means the two byte extension form must be used.
+    pub hdr_metadata: Option<HdrMetadata>,
+}
+
+/// HDR mastering metadata and content light levels (SMPTE ST 2086 and CTA-861.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HdrMetadata {
+    /// Chromaticity of the red primary.
+    pub primary_r: Chromaticity,
+    /// Chromaticity of the green primary.
+    pub primary_g: Chromaticity,
+    /// Chromaticity of the blue primary.
+    pub primary_b: Chromaticity,
+    /// Chromaticity of the white point.
+    pub white_point: Chromaticity,
+    /// Max luminance of the mastering display in cd/m².
+    pub luminance_max: u16,
+    /// Min luminance of the mastering display in 0.0001 cd/m².
+    pub luminance_min: u16,
+    /// Max content light level (MaxCLL) in cd/m².
+    pub max_content_light_level: u16,
+    /// Max frame average light level (MaxFALL) in cd/m².
+    pub max_frame_average_light_level: u16,
+}
+
+/// CIE 1931 xy chromaticity coordinates in units of 0.00002, i.e. 50000 is 1.0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Chromaticity {
+    /// The x coordinate, 0..=50000.
+    pub x: u16,
+    /// The y coordinate, 0..=50000.
+    pub y: u16,
+}
+
+/// Max value of a [`Chromaticity`] coordinate, which corresponds to 1.0.
+const CHROMATICITY_MAX: u16 = 50_000;
+
+/// Color primaries (ITU-T H.273 table 2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum ColorPrimaries {
+    Bt709 = 1,
+    Unspecified = 2,
+    Bt470M = 4,
+    Bt470Bg = 5,
+    Smpte170M = 6,
+    Smpte240M = 7,
+    Film = 8,
+    Bt2020 = 9,
+    SmpteSt428 = 10,
+    SmpteSt431 = 11,
+    SmpteSt432 = 12,
+    JedecP22 = 22,
+}
+
+/// Transfer characteristics (ITU-T H.273 table 3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum TransferCharacteristics {
+    Bt709 = 1,
+    Unspecified = 2,
+    Gamma22 = 4,
+    Gamma28 = 5,
+    Smpte170M = 6,
+    Smpte240M = 7,
+    Linear = 8,
+    Log = 9,
+    LogSqrt = 10,
+    Iec61966_2_4 = 11,
+    Bt1361Ecg = 12,
+    Iec61966_2_1 = 13,
+    Bt2020_10 = 14,
+    Bt2020_12 = 15,
+    /// Perceptual quantizer (PQ), used for HDR10.
+    SmpteSt2084 = 16,
+    SmpteSt428 = 17,
+    /// Hybrid log-gamma (HLG).
+    AribStdB67 = 18,
+}
+
+/// Matrix coefficients (ITU-T H.273 table 4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum MatrixCoefficients {
+    Rgb = 0,
+    Bt709 = 1,
+    Unspecified = 2,
+    Fcc = 4,
+    Bt470Bg = 5,
+    Smpte170M = 6,
+    Smpte240M = 7,
+    YCoCg = 8,
+    Bt2020Ncl = 9,
+    Bt2020Cl = 10,
+    Smpte2085 = 11,
+    ChromaDerivedNcl = 12,
+    ChromaDerivedCl = 13,
+    Bt2100Ictcp = 14,
+}
+
+/// Color range of the samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRange {
+    /// Range not specified (called "invalid" in WebRTC).
+    Unspecified = 0,
+    /// Limited (studio swing) range, i.e. Y is 16..=235 for 8 bit.
+    Limited = 1,
+    /// Full range, i.e. Y is 0..=255 for 8 bit.
+    Full = 2,
+    /// Range derived from the transfer characteristics and matrix coefficients.
+    Derived = 3,
+}
+
+/// Chroma siting relative to the luma samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSiting {
+    /// Siting not specified.
+    Unspecified = 0,
+    /// Chroma sample collocated with the left/top luma sample.
+    Collocated = 1,
+    /// Chroma sample halfway between luma samples.
+    Half = 2,
+}
+
+impl ColorPrimaries {
+    fn from_u8(v: u8) -> Option<Self> {
+        use ColorPrimaries::*;
+        Some(match v {
+            1 => Bt709,
+            2 => Unspecified,
+            4 => Bt470M,
+            5 => Bt470Bg,
+            6 => Smpte170M,
+            7 => Smpte240M,
+            8 => Film,
+            9 => Bt2020,
+            10 => SmpteSt428,
+            11 => SmpteSt431,
+            12 => SmpteSt432,
+            22 => JedecP22,
+            _ => return None,
+        })
+    }
+}
+
+impl TransferCharacteristics {
+    fn from_u8(v: u8) -> Option<Self> {
+        use TransferCharacteristics::*;
+        Some(match v {
+            1 => Bt709,
+            2 => Unspecified,
+            4 => Gamma22,
+            5 => Gamma28,
+            6 => Smpte170M,
+            7 => Smpte240M,
+            8 => Linear,
+            9 => Log,
+            10 => LogSqrt,
+            11 => Iec61966_2_4,
+            12 => Bt1361Ecg,
+            13 => Iec61966_2_1,
+            14 => Bt2020_10,
+            15 => Bt2020_12,
+            16 => SmpteSt2084,
+            17 => SmpteSt428,
+            18 => AribStdB67,
+            _ => return None,
+        })
+    }
+}
+
+impl MatrixCoefficients {
+    fn from_u8(v: u8) -> Option<Self> {
+        use MatrixCoefficients::*;
+        Some(match v {
+            0 => Rgb,
+            1 => Bt709,
+            2 => Unspecified,
+            4 => Fcc,
+            5 => Bt470Bg,
+            6 => Smpte170M,
+            7 => Smpte240M,
+            8 => YCoCg,
+            9 => Bt2020Ncl,
+            10 => Bt2020Cl,
+            11 => Smpte2085,
+            12 => ChromaDerivedNcl,
+            13 => ChromaDerivedCl,
+            14 => Bt2100Ictcp,
+            _ => return None,
+        })
+    }
+}
+
+impl ColorRange {
+    fn from_u8(v: u8) -> Option<Self> {
+        use ColorRange::*;
+        Some(match v {
+            0 => Unspecified,
+            1 => Limited,
+            2 => Full,
+            3 => Derived,
+            _ => return None,
+        })
+    }
+}
+
+impl ChromaSiting {
+    fn from_u8(v: u8) -> Option<Self> {
+        use ChromaSiting::*;
+        Some(match v {
+            0 => Unspecified,
+            1 => Collocated,
+            2 => Half,
+            _ => return None,
+        })
+    }
+}
+
+impl ColorSpace {
+    /// Size of the extension without HDR metadata.
+    pub(crate) const SHORT_LEN: usize = 4;
+
+    /// Size of the extension with HDR metadata.
+    pub(crate) const LONG_LEN: usize = 28;
+
+    /// Number of bytes this value serializes to.
+    pub(crate) fn len(&self) -> usize {
+        if self.hdr_metadata.is_some() {
+            Self::LONG_LEN
+        } else {
+            Self::SHORT_LEN
+        }
+    }
+
+    /// Write the extension to the buffer. Returns None if the HDR metadata is out of range.
+    pub(crate) fn write_to(&self, buf: &mut [u8]) -> Option<usize> {
+        buf[0] = self.primaries as u8;
+        buf[1] = self.transfer as u8;
+        buf[2] = self.matrix as u8;
+        // 0 0 R R H H V V
+        buf[3] = (self.range as u8) << 4
+            | (self.chroma_siting_horizontal as u8) << 2
+            | self.chroma_siting_vertical as u8;
+
+        let Some(hdr) = &self.hdr_metadata else {
+            return Some(Self::SHORT_LEN);
+        };
+
+        if !hdr.is_valid() {
+            return None;
+        }
+
+        let values = [
+            hdr.primary_r.x,
+            hdr.primary_r.y,
+            hdr.primary_g.x,
+            hdr.primary_g.y,
+            hdr.primary_b.x,
+            hdr.primary_b.y,
+            hdr.white_point.x,
+            hdr.white_point.y,
+            hdr.luminance_max,
+            hdr.luminance_min,
+            hdr.max_content_light_level,
+            hdr.max_frame_average_light_level,
+        ];
+
+        for (i, v) in values.iter().enumerate() {
+            let o = Self::SHORT_LEN + i * 2;
+            buf[o..o + 2].copy_from_slice(&v.to_be_bytes());
+        }
+
+        Some(Self::LONG_LEN)
+    }
+
+    /// Parse the extension. Returns None for unknown enum values or an unexpected length.
+    pub(crate) fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() != Self::SHORT_LEN && buf.len() != Self::LONG_LEN {
+            return None;
+        }
+
+        let hdr_metadata = if buf.len() == Self::LONG_LEN {
+            let v = |i: usize| {
+                let o = Self::SHORT_LEN + i * 2;
+                u16::from_be_bytes([buf[o], buf[o + 1]])
+            };
+            let c = |i: usize| Chromaticity {
+                x: v(i),
+                y: v(i + 1),
+            };
+
+            let hdr = HdrMetadata {
+                primary_r: c(0),
+                primary_g: c(2),
+                primary_b: c(4),
+                white_point: c(6),
+                luminance_max: v(8),
+                luminance_min: v(9),
+                max_content_light_level: v(10),
+                max_frame_average_light_level: v(11),
+            };
+
+            if !hdr.is_valid() {
+                return None;
+            }
+
+            Some(hdr)
+        } else {
+            None
+        };
+
+        Some(ColorSpace {
+            primaries: ColorPrimaries::from_u8(buf[0])?,
+            transfer: TransferCharacteristics::from_u8(buf[1])?,
+            matrix: MatrixCoefficients::from_u8(buf[2])?,
+            range: ColorRange::from_u8((buf[3] >> 4) & 0b11)?,
+            chroma_siting_horizontal: ChromaSiting::from_u8((buf[3] >> 2) & 0b11)?,
+            chroma_siting_vertical: ChromaSiting::from_u8(buf[3] & 0b11)?,
+            hdr_metadata,
+        })
+    }
+}
+
+impl HdrMetadata {
+    fn is_valid(&self) -> bool {
+        [
+            self.primary_r,
+            self.primary_g,
+            self.primary_b,
+            self.white_point,
+        ]
+        .iter()
+        .all(|c| c.x <= CHROMATICITY_MAX && c.y <= CHROMATICITY_MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bt709() -> ColorSpace {
+        ColorSpace {
+            primaries: ColorPrimaries::Bt709,
+            transfer: TransferCharacteristics::Bt709,
+            matrix: MatrixCoefficients::Bt709,
+            range: ColorRange::Limited,
+            chroma_siting_horizontal: ChromaSiting::Collocated,
+            chroma_siting_vertical: ChromaSiting::Half,
+            hdr_metadata: None,
+        }
+    }
+
+    fn hdr10() -> ColorSpace {
+        ColorSpace {
+            primaries: ColorPrimaries::Bt2020,
+            transfer: TransferCharacteristics::SmpteSt2084,
+            matrix: MatrixCoefficients::Bt2020Ncl,
+            range: ColorRange::Full,
+            chroma_siting_horizontal: ChromaSiting::Unspecified,
+            chroma_siting_vertical: ChromaSiting::Unspecified,
+            hdr_metadata: Some(HdrMetadata {
+                primary_r: Chromaticity { x: 35400, y: 14600 },
+                primary_g: Chromaticity { x: 8500, y: 39850 },
+                primary_b: Chromaticity { x: 6550, y: 2300 },
+                white_point: Chromaticity { x: 15635, y: 16450 },
+                luminance_max: 1000,
+                luminance_min: 50,
+                max_content_light_level: 1000,
+                max_frame_average_light_level: 400,
+            }),
+        }
+    }
+
+    #[test]
+    fn short_form() {
+        let cs = bt709();
+        let mut buf = [0; 28];
+        assert_eq!(cs.write_to(&mut buf), Some(4));
+        assert_eq!(&buf[..4], &[1, 1, 1, 0b0001_0110]);
+        assert_eq!(ColorSpace::parse(&buf[..4]), Some(cs));
+    }
+
+    #[test]
+    fn long_form() {
+        let cs = hdr10();
+        let mut buf = [0; 28];
+        assert_eq!(cs.write_to(&mut buf), Some(28));
+        assert_eq!(&buf[..6], &[9, 16, 9, 0b0010_0000, 0x8a, 0x48]);
+        assert_eq!(ColorSpace::parse(&buf), Some(cs));
+    }
+
+    #[test]
+    fn unknown_values() {
+        // Reserved primaries.
+        assert_eq!(ColorSpace::parse(&[3, 1, 1, 0]), None);
+        // Reserved transfer.
+        assert_eq!(ColorSpace::parse(&[1, 19, 1, 0]), None);
+        // Reserved matrix.
+        assert_eq!(ColorSpace::parse(&[1, 1, 15, 0]), None);
+        // Chroma siting 3 is not defined.
+        assert_eq!(ColorSpace::parse(&[1, 1, 1, 0b0000_0011]), None);
+        // Wrong length.
+        assert_eq!(ColorSpace::parse(&[1, 1, 1, 0, 0]), None);
+
+        // Chromaticity above 1.0.
+        let mut cs = hdr10();
+        cs.hdr_metadata.as_mut().unwrap().white_point.x = 50_001;
+        let mut buf = [0; 28];
+        assert_eq!(cs.write_to(&mut buf), None);
+        hdr10().write_to(&mut buf);
+        buf[16..18].copy_from_slice(&50_001_u16.to_be_bytes());
+        assert_eq!(ColorSpace::parse(&buf), None);
+    }
+}
@@ -0,0 +1,805 @@
+use std::sync::Arc;
+
+/// The AV1 dependency descriptor header extension.
+///
+/// Describes how a frame depends on other frames, and to which decode targets (combinations
+/// of spatial and temporal layers) it belongs. This makes it possible for an SFU to select
+/// layers of AV1 SVC/simulcast without looking at the payload.
+///
+/// The mandatory fields are always present. The remaining information is expressed via a
+/// template structure, which is typically only attached to keyframes. Packets without the
+/// structure are interpreted using the last structure seen on the same stream.
+///
+/// <https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyDescriptor {
+    /// The packet is the first packet of the frame.
+    pub start_of_frame: bool,
+    /// The packet is the last packet of the frame.
+    pub end_of_frame: bool,
+    /// Id of the frame dependency template used by this frame, 0..=63.
+    pub template_id: u8,
+    /// Running frame number, used together with the frame diffs.
+    pub frame_number: u16,
+    /// The template structure in effect for this frame.
+    ///
+    /// This is either attached to this packet, or the last one seen on the stream.
+    pub structure: Option<Arc<FrameDependencyStructure>>,
+    /// Whether the structure is attached to (serialized in) this packet.
+    pub structure_attached: bool,
+    /// Bitmask of the decode targets that are currently active. Bit 0 is decode target 0.
+    pub active_decode_targets: Option<u32>,
+    /// Dependency information of this frame.
+    ///
+    /// This is the template given by the template id, with any per frame overrides applied.
+    /// `None` if no structure is known.
+    pub frame: Option<FrameDependency>,
+
+    /// Extension as received, kept while it can't be interpreted for lack of structure.
+    raw: Option<Vec<u8>>,
+}
+
+/// The template structure of the dependency descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameDependencyStructure {
+    /// Offset of the template ids, 0..=63.
+    pub template_id_offset: u8,
+    /// Number of decode targets, 1..=32.
+    pub decode_target_count: u8,
+    /// Number of chains, 0..=decode_target_count.
+    pub chain_count: u8,
+    /// For each decode target, the chain protecting it. Empty if there are no chains.
+    pub decode_target_protected_by_chain: Vec<u8>,
+    /// The frame dependency templates. The first template must be spatial and temporal
+    /// layer 0, and the templates must be ordered by spatial, then temporal layer.
+    pub templates: Vec<FrameDependency>,
+    /// Max render resolution per spatial layer. Empty if not sent.
+    pub resolutions: Vec<RenderResolution>,
+}
+
+/// Dependency information for a frame, or a template thereof.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameDependency {
+    /// Spatial layer id, 0..=3.
+    pub spatial_id: u8,
+    /// Temporal layer id, 0..=7.
+    pub temporal_id: u8,
+    /// Decode target indication for each decode target.
+    pub dtis: Vec<Dti>,
+    /// Differences in frame number to the frames this frame depends on.
+    pub fdiffs: Vec<u16>,
+    /// Differences in frame number to the previous frame in each chain.
+    pub chain_fdiffs: Vec<u8>,
+}
+
+/// Max render resolution of a spatial layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderResolution {
+    /// Width in pixels, 1..=65536.
+    pub width: u32,
+    /// Height in pixels, 1..=65536.
+    pub height: u32,
+}
+
+/// Decode target indication. How a frame relates to a decode target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dti {
+    /// The frame is not part of the decode target.
+    NotPresent = 0,
+    /// The frame is part of the decode target, but no later frame depends on it.
+    Discardable = 1,
+    /// A decoder can start decoding the decode target from this frame.
+    Switch = 2,
+    /// The frame is part of the decode target and later frames depend on it.
+    Required = 3,
+}
+
+impl From<u32> for Dti {
+    fn from(v: u32) -> Self {
+        match v & 0b11 {
+            0 => Dti::NotPresent,
+            1 => Dti::Discardable,
+            2 => Dti::Switch,
+            _ => Dti::Required,
+        }
+    }
+}
+
+const MAX_TEMPLATES: usize = 64;
+const MAX_DECODE_TARGETS: usize = 32;
+const MAX_SPATIAL_ID: u8 = 3;
+const MAX_TEMPORAL_ID: u8 = 7;
+
+impl DependencyDescriptor {
+    /// Creates a descriptor with only the mandatory fields.
+    pub fn new(
+        start_of_frame: bool,
+        end_of_frame: bool,
+        template_id: u8,
+        frame_number: u16,
+    ) -> Self {
+        DependencyDescriptor {
+            start_of_frame,
+            end_of_frame,
+            template_id: template_id & 0x3f,
+            frame_number,
+            structure: None,
+            structure_attached: false,
+            active_decode_targets: None,
+            frame: None,
+            raw: None,
+        }
+    }
+
+    /// Max render resolution of the frame, if sent in the structure.
+    pub fn resolution(&self) -> Option<RenderResolution> {
+        let s = self.structure.as_ref()?;
+        let f = self.frame.as_ref()?;
+        s.resolutions.get(f.spatial_id as usize).copied()
+    }
+
+    /// Parse the extension, using `structure` if none is attached.
+    pub(crate) fn parse(
+        buf: &[u8],
+        structure: Option<&Arc<FrameDependencyStructure>>,
+    ) -> Option<Self> {
+        if buf.len() < 3 {
+            return None;
+        }
+
+        let mut r = BitReader::new(buf);
+
+        let mut dd =
+            DependencyDescriptor::new(r.bit()?, r.bit()?, r.bits(6)? as u8, r.bits(16)? as u16);
+        dd.structure = structure.cloned();
+
+        let mut active_present = false;
+        let mut custom_dtis = false;
+        let mut custom_fdiffs = false;
+        let mut custom_chains = false;
+
+        if buf.len() > 3 {
+            let structure_present = r.bit()?;
+            active_present = r.bit()?;
+            custom_dtis = r.bit()?;
+            custom_fdiffs = r.bit()?;
+            custom_chains = r.bit()?;
+
+            if structure_present {
+                let s = FrameDependencyStructure::parse(&mut r)?;
+                dd.active_decode_targets = Some(mask(s.decode_target_count));
+                dd.structure = Some(Arc::new(s));
+                dd.structure_attached = true;
+            }
+        }
+
+        let Some(s) = dd.structure.clone() else {
+            // Can't interpret the rest without a structure. Keep the extended fields so we
+            // can try again once we know the structure for the stream.
+            if buf.len() > 3 {
+                dd.raw = Some(buf.to_vec());
+            }
+            return Some(dd);
+        };
+
+        if active_present {
+            dd.active_decode_targets = Some(r.bits(s.decode_target_count as usize)?);
+        }
+
+        let index = (dd.template_id as usize + MAX_TEMPLATES - s.template_id_offset as usize)
+            % MAX_TEMPLATES;
+
+        let Some(template) = s.templates.get(index) else {
+            trace!("Dependency descriptor template out of range: {}", index);
+            return None;
+        };
+
+        let mut frame = template.clone();
+
+        if custom_dtis {
+            frame.dtis = (0..s.decode_target_count)
+                .map(|_| r.bits(2).map(Dti::from))
+                .collect::<Option<_>>()?;
+        }
+
+        if custom_fdiffs {
+            frame.fdiffs.clear();
+            loop {
+                let size = r.bits(2)? as usize;
+                if size == 0 {
+                    break;
+                }
+                frame.fdiffs.push(r.bits(size * 4)? as u16 + 1);
+            }
+        }
+
+        if custom_chains {
+            frame.chain_fdiffs = (0..s.chain_count)
+                .map(|_| r.bits(8).map(|v| v as u8))
+                .collect::<Option<_>>()?;
+        }
+
+        dd.frame = Some(frame);
+
+        Some(dd)
+    }
+
+    /// Serialize the extension.
+    ///
+    /// Without a structure, only the mandatory fields are written. With a structure, the
+    /// frame dependency is written as overrides of the template where it differs.
+    pub(crate) fn to_bytes(&self) -> Option<Vec<u8>> {
+        let mut w = BitWriter::default();
+
+        w.bit(self.start_of_frame);
+        w.bit(self.end_of_frame);
+        w.bits(self.template_id as u32, 6);
+        w.bits(self.frame_number as u32, 16);
+
+        let Some(s) = &self.structure else {
+            return Some(w.finish());
+        };
+
+        let index = (self.template_id as usize + MAX_TEMPLATES - s.template_id_offset as usize)
+            % MAX_TEMPLATES;
+        let template = s.templates.get(index)?;
+        let frame = self.frame.as_ref().unwrap_or(template);
+
+        let custom_dtis = frame.dtis != template.dtis;
+        let custom_fdiffs = frame.fdiffs != template.fdiffs;
+        let custom_chains = frame.chain_fdiffs != template.chain_fdiffs;
+
+        // After an attached structure, all decode targets are active unless told otherwise.
+        let active = self
+            .active_decode_targets
+            .filter(|m| !self.structure_attached || *m != mask(s.decode_target_count));
+
+        let extended = self.structure_attached
+            || active.is_some()
+            || custom_dtis
+            || custom_fdiffs
+            || custom_chains;
+
+        if !extended {
+            return Some(w.finish());
+        }
+
+        w.bit(self.structure_attached);
+        w.bit(active.is_some());
+        w.bit(custom_dtis);
+        w.bit(custom_fdiffs);
+        w.bit(custom_chains);
+
+        if self.structure_attached {
+            s.write(&mut w)?;
+        }
+
+        if let Some(m) = active {
+            w.bits(m, s.decode_target_count as usize);
+        }
+
+        if custom_dtis {
+            if frame.dtis.len() != s.decode_target_count as usize {
+                return None;
+            }
+            for dti in &frame.dtis {
+                w.bits(*dti as u32, 2);
+            }
+        }
+
+        if custom_fdiffs {
+            for fdiff in &frame.fdiffs {
+                let v = (*fdiff as u32).checked_sub(1)?;
+                let size = match v {
+                    0..=0xf => 1,
+                    0x10..=0xff => 2,
+                    0x100..=0xfff => 3,
+                    _ => return None,
+                };
+                w.bits(size, 2);
+                w.bits(v, size as usize * 4);
+            }
+            w.bits(0, 2);
+        }
+
+        if custom_chains {
+            if frame.chain_fdiffs.len() != s.chain_count as usize {
+                return None;
+            }
+            for c in &frame.chain_fdiffs {
+                w.bits(*c as u32, 8);
+            }
+        }
+
+        Some(w.finish())
+    }
+}
+
+fn mask(decode_target_count: u8) -> u32 {
+    ((1_u64 << decode_target_count) - 1) as u32
+}
+
+impl FrameDependencyStructure {
+    fn parse(r: &mut BitReader) -> Option<Self> {
+        let template_id_offset = r.bits(6)? as u8;
+        let decode_target_count = r.bits(5)? as u8 + 1;
+        let dt_cnt = decode_target_count as usize;
+
+        // template_layers()
+        let mut templates = vec![];
+        let mut spatial_id = 0;
+        let mut temporal_id = 0;
+        loop {
+            if templates.len() == MAX_TEMPLATES {
+                return None;
+            }
+            templates.push(FrameDependency {
+                spatial_id,
+                temporal_id,
+                ..Default::default()
+            });
+            match r.bits(2)? {
+                0 => {}
+                1 => temporal_id += 1,
+                2 => {
+                    temporal_id = 0;
+                    spatial_id += 1;
+                }
+                _ => break,
+            }
+            if spatial_id > MAX_SPATIAL_ID || temporal_id > MAX_TEMPORAL_ID {
+                return None;
+            }
+        }
+
+        // template_dtis()
+        for t in &mut templates {
+            t.dtis = (0..dt_cnt)
+                .map(|_| r.bits(2).map(Dti::from))
+                .collect::<Option<_>>()?;
+        }
+
+        // template_fdiffs()
+        for t in &mut templates {
+            while r.bit()? {
+                t.fdiffs.push(r.bits(4)? as u16 + 1);
+            }
+        }
+
+        // template_chains()
+        let chain_count = r.ns(dt_cnt as u32 + 1)? as u8;
+        let mut decode_target_protected_by_chain = vec![];
+        if chain_count > 0 {
+            for _ in 0..dt_cnt {
+                decode_target_protected_by_chain.push(r.ns(chain_count as u32)? as u8);
+            }
+            for t in &mut templates {
+                t.chain_fdiffs = (0..chain_count)
+                    .map(|_| r.bits(4).map(|v| v as u8))
+                    .collect::<Option<_>>()?;
+            }
+        }
+
+        // render_resolutions()
+        let mut resolutions = vec![];
+        if r.bit()? {
+            for _ in 0..=spatial_id {
+                resolutions.push(RenderResolution {
+                    width: r.bits(16)? + 1,
+                    height: r.bits(16)? + 1,
+                });
+            }
+        }
+
+        Some(FrameDependencyStructure {
+            template_id_offset,
+            decode_target_count,
+            chain_count,
+            decode_target_protected_by_chain,
+            templates,
+            resolutions,
+        })
+    }
+
+    fn write(&self, w: &mut BitWriter) -> Option<()> {
+        let dt_cnt = self.decode_target_count as usize;
+        if dt_cnt == 0 || dt_cnt > MAX_DECODE_TARGETS {
+            return None;
+        }
+        if self.templates.is_empty() || self.templates.len() > MAX_TEMPLATES {
+            return None;
+        }
+
+        w.bits(self.template_id_offset as u32, 6);
+        w.bits(dt_cnt as u32 - 1, 5);
+
+        // template_layers()
+        let first = &self.templates[0];
+        if first.spatial_id != 0 || first.temporal_id != 0 {
+            return None;
+        }
+        for (i, t) in self.templates.iter().enumerate() {
+            let Some(next) = self.templates.get(i + 1) else {
+                w.bits(3, 2);
+                break;
+            };
+            let idc = if next.spatial_id == t.spatial_id && next.temporal_id == t.temporal_id {
+                0
+            } else if next.spatial_id == t.spatial_id && next.temporal_id == t.temporal_id + 1 {
+                1
+            } else if next.spatial_id == t.spatial_id + 1 && next.temporal_id == 0 {
+                2
+            } else {
+                return None;
+            };
+            w.bits(idc, 2);
+        }
+
+        // template_dtis()
+        for t in &self.templates {
+            if t.dtis.len() != dt_cnt {
+                return None;
+            }
+            for dti in &t.dtis {
+                w.bits(*dti as u32, 2);
+            }
+        }
+
+        // template_fdiffs()
+        for t in &self.templates {
+            for fdiff in &t.fdiffs {
+                if !(1..=16).contains(fdiff) {
+                    return None;
+                }
+                w.bit(true);
+                w.bits(*fdiff as u32 - 1, 4);
+            }
+            w.bit(false);
+        }
+
+        // template_chains()
+        let chain_cnt = self.chain_count as u32;
+        if chain_cnt > dt_cnt as u32 {
+            return None;
+        }
+        w.ns(dt_cnt as u32 + 1, chain_cnt);
+        if chain_cnt > 0 {
+            if self.decode_target_protected_by_chain.len() != dt_cnt {
+                return None;
+            }
+            for c in &self.decode_target_protected_by_chain {
+                if *c as u32 >= chain_cnt {
+                    return None;
+                }
+                w.ns(chain_cnt, *c as u32);
+            }
+            for t in &self.templates {
+                if t.chain_fdiffs.len() != chain_cnt as usize {
+                    return None;
+                }
+                for c in &t.chain_fdiffs {
+                    if *c > 0xf {
+                        return None;
+                    }
+                    w.bits(*c as u32, 4);
+                }
+            }
+        }
+
+        // render_resolutions()
+        let spatial_layers = self.templates.last().map(|t| t.spatial_id as usize + 1)?;
+        w.bit(!self.resolutions.is_empty());
+        if !self.resolutions.is_empty() {
+            if self.resolutions.len() != spatial_layers {
+                return None;
+            }
+            for r in &self.resolutions {
+                if !(1..=65536).contains(&r.width) || !(1..=65536).contains(&r.height) {
+                    return None;
+                }
+                w.bits(r.width - 1, 16);
+                w.bits(r.height - 1, 16);
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Keeps the template structure of a stream to interpret dependency descriptors
+/// that don't carry it themselves.
+#[derive(Debug, Default)]
+pub(crate) struct DependencyDescriptorReader {
+    structure: Option<Arc<FrameDependencyStructure>>,
+}
+
+impl DependencyDescriptorReader {
+    /// Update the cached structure from a parsed descriptor, or use the cached
+    /// structure to interpret a descriptor that lacked one.
+    pub fn resolve(&mut self, dd: &mut Arc<DependencyDescriptor>) {
+        if dd.structure_attached {
+            self.structure = dd.structure.clone();
+            return;
+        }
+
+        if dd.frame.is_some() || self.structure.is_none() {
+            return;
+        }
+
+        // Only the mandatory fields when there is nothing else kept.
+        let Some(raw) = dd.raw.clone().or_else(|| dd.to_bytes()) else {
+            return;
+        };
+
+        if let Some(v) = DependencyDescriptor::parse(&raw, self.structure.as_ref()) {
+            *dd = Arc::new(v);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        BitReader { buf, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<bool> {
+        let byte = self.buf.get(self.pos / 8)?;
+        let v = byte & (0x80 >> (self.pos % 8)) > 0;
+        self.pos += 1;
+        Some(v)
+    }
+
+    /// Read n bits (max 32) as a big endian unsigned integer.
+    fn bits(&mut self, n: usize) -> Option<u32> {
+        let mut v = 0_u32;
+        for _ in 0..n {
+            v = v << 1 | self.bit()? as u32;
+        }
+        Some(v)
+    }
+
+    /// Non-symmetric unsigned integer in the range 0..n.
+    fn ns(&mut self, n: u32) -> Option<u32> {
+        let w = 32 - n.leading_zeros();
+        let m = (1 << w) - n;
+        let v = self.bits(w as usize - 1)?;
+        if v < m {
+            return Some(v);
+        }
+        let extra = self.bit()? as u32;
+        Some((v << 1) - m + extra)
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl BitWriter {
+    fn bit(&mut self, v: bool) {
+        if self.pos % 8 == 0 {
+            self.buf.push(0);
+        }
+        if v {
+            *self.buf.last_mut().unwrap() |= 0x80 >> (self.pos % 8);
+        }
+        self.pos += 1;
+    }
+
+    fn bits(&mut self, v: u32, n: usize) {
+        for i in (0..n).rev() {
+            self.bit(v >> i & 1 == 1);
+        }
+    }
+
+    fn ns(&mut self, n: u32, v: u32) {
+        let w = 32 - n.leading_zeros();
+        let m = (1 << w) - n;
+        if v < m {
+            self.bits(v, w as usize - 1);
+        } else {
+            let x = v + m;
+            self.bits(x >> 1, w as usize - 1);
+            self.bit(x & 1 == 1);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use Dti::*;
+
+    /// L1T3 structure as used by libwebrtc's scalability mode "L1T3".
+    fn l1t3() -> FrameDependencyStructure {
+        let t = |temporal_id, dtis: [Dti; 3], fdiffs: &[u16], chain| FrameDependency {
+            spatial_id: 0,
+            temporal_id,
+            dtis: dtis.to_vec(),
+            fdiffs: fdiffs.to_vec(),
+            chain_fdiffs: vec![chain],
+        };
+        FrameDependencyStructure {
+            template_id_offset: 0,
+            decode_target_count: 3,
+            chain_count: 1,
+            decode_target_protected_by_chain: vec![0, 0, 0],
+            templates: vec![
+                t(0, [Switch, Switch, Switch], &[], 0),
+                t(0, [Switch, Switch, Switch], &[4], 4),
+                t(1, [NotPresent, Switch, Switch], &[2], 2),
+                t(2, [NotPresent, NotPresent, Discardable], &[1], 1),
+                t(2, [NotPresent, NotPresent, Discardable], &[1], 3),
+            ],
+            resolutions: vec![RenderResolution {
+                width: 1280,
+                height: 720,
+            }],
+        }
+    }
+
+    #[test]
+    fn mandatory_fields() {
+        let buf = [0x85, 0x12, 0x34];
+        let dd = DependencyDescriptor::parse(&buf, None).unwrap();
+        assert!(dd.start_of_frame);
+        assert!(!dd.end_of_frame);
+        assert_eq!(dd.template_id, 5);
+        assert_eq!(dd.frame_number, 0x1234);
+        assert_eq!(dd.frame, None);
+
+        let dd = DependencyDescriptor::new(true, false, 5, 0x1234);
+        assert_eq!(dd.to_bytes().unwrap(), buf);
+    }
+
+    #[test]
+    fn structure_l1t1_vector() {
+        // Hand assembled from the spec: one decode target, one template,
+        // no chains and a 640x480 render resolution.
+        //
+        // 1 1 000000 0000000000000001  mandatory: S, E, template 0, frame 1
+        // 1 0 0 0 0                    structure present
+        // 000000 00000                 template_id_offset 0, dt_cnt 1
+        // 11                           one template, next_layer_idc 3
+        // 10                           template 0 DTI switch
+        // 0                            no fdiffs
+        // 0                            ns(2) = 0 chains
+        // 1 <639:16> <479:16>          resolutions
+        let buf = [0xc0, 0x00, 0x01, 0x80, 0x00, 0xe2, 0x04, 0xfe, 0x03, 0xbe];
+
+        let dd = DependencyDescriptor::parse(&buf, None).unwrap();
+        assert!(dd.structure_attached);
+        assert_eq!(dd.active_decode_targets, Some(1));
+        let s = dd.structure.as_ref().unwrap();
+        assert_eq!(s.decode_target_count, 1);
+        assert_eq!(s.chain_count, 0);
+        assert_eq!(s.templates.len(), 1);
+        assert_eq!(s.templates[0].dtis, vec![Switch]);
+        assert_eq!(
+            dd.resolution(),
+            Some(RenderResolution {
+                width: 640,
+                height: 480
+            })
+        );
+
+        assert_eq!(dd.to_bytes().unwrap(), buf);
+    }
+
+    #[test]
+    fn structure_roundtrip_and_cache() {
+        let s = Arc::new(l1t3());
+
+        let mut key = DependencyDescriptor::new(true, true, 0, 100);
+        key.structure = Some(s.clone());
+        key.structure_attached = true;
+        key.active_decode_targets = Some(0b111);
+        key.frame = Some(s.templates[0].clone());
+
+        let buf = key.to_bytes().unwrap();
+        let parsed = DependencyDescriptor::parse(&buf, None).unwrap();
+        assert_eq!(parsed, key);
+
+        let mut reader = DependencyDescriptorReader::default();
+        let mut parsed = Arc::new(parsed);
+        reader.resolve(&mut parsed);
+
+        // A delta frame on TL2 that only has the mandatory fields.
+        let mut delta = DependencyDescriptor::new(true, true, 3, 101);
+        delta.structure = Some(s.clone());
+        let buf = delta.to_bytes().unwrap();
+        assert_eq!(buf.len(), 3);
+
+        // Without the structure, it can't be interpreted.
+        let mut parsed = Arc::new(DependencyDescriptor::parse(&buf, None).unwrap());
+        assert_eq!(parsed.frame, None);
+
+        reader.resolve(&mut parsed);
+        let frame = parsed.frame.as_ref().unwrap();
+        assert_eq!(frame.temporal_id, 2);
+        assert_eq!(frame.dtis, vec![NotPresent, NotPresent, Discardable]);
+        assert_eq!(frame.fdiffs, vec![1]);
+    }
+
+    #[test]
+    fn custom_overrides() {
+        let s = Arc::new(l1t3());
+
+        let mut dd = DependencyDescriptor::new(false, true, 2, 7);
+        dd.structure = Some(s.clone());
+        dd.active_decode_targets = Some(0b011);
+        dd.frame = Some(FrameDependency {
+            spatial_id: 0,
+            temporal_id: 1,
+            dtis: vec![NotPresent, Required, Switch],
+            fdiffs: vec![2, 300],
+            chain_fdiffs: vec![200],
+        });
+
+        let buf = dd.to_bytes().unwrap();
+        let parsed = DependencyDescriptor::parse(&buf, Some(&s)).unwrap();
+        assert_eq!(parsed.active_decode_targets, Some(0b011));
+        assert_eq!(parsed.frame, dd.frame);
+    }
+
+    #[test]
+    fn ns_roundtrip() {
+        for n in 1..40 {
+            for v in 0..n {
+                let mut w = BitWriter::default();
+                w.ns(n, v);
+                let buf = w.finish();
+                let mut r = BitReader::new(&buf);
+                assert_eq!(r.ns(n), Some(v), "n: {n} v: {v}");
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_does_not_panic() {
+        let s = Arc::new(l1t3());
+
+        let mut key = DependencyDescriptor::new(true, true, 0, 100);
+        key.structure = Some(s.clone());
+        key.structure_attached = true;
+        let buf = key.to_bytes().unwrap();
+
+        // Truncated at every length.
+        for i in 0..buf.len() {
+            let _ = DependencyDescriptor::parse(&buf[..i], None);
+            let _ = DependencyDescriptor::parse(&buf[..i], Some(&s));
+        }
+
+        // Every single bit flipped.
+        for i in 0..buf.len() * 8 {
+            let mut b = buf.clone();
+            b[i / 8] ^= 0x80 >> (i % 8);
+            let _ = DependencyDescriptor::parse(&b, None);
+            let _ = DependencyDescriptor::parse(&b, Some(&s));
+        }
+
+        // Garbage.
+        for i in 0..=255_u8 {
+            let b: Vec<u8> = (0..20)
+                .map(|j| i.wrapping_mul(31).wrapping_add(j * 7))
+                .collect();
+            let _ = DependencyDescriptor::parse(&b, None);
+            let _ = DependencyDescriptor::parse(&b, Some(&s));
+        }
+
+        // Template id out of range of the cached structure.
+        assert!(DependencyDescriptor::parse(&[0xff, 0, 0], Some(&s)).is_none());
+    }
+}
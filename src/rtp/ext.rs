@@ -16,8 +16,11 @@ use crate::util::InstantExt;
 
 use crate::rtp_::Frequency;
 
+use super::color_space::ColorSpace as ColorSpaceValue;
+use super::dependency_descriptor::DependencyDescriptor as DependencyDescriptorValue;
+use super::header::extend_u24;
 use super::mtime::MediaTime;
-use super::{Mid, Rid};
+use super::{Direction, Mid, Rid};
 
 /// RTP header extensions.
 #[derive(Debug, Clone)]
@@ -59,6 +62,16 @@ pub enum Extension {
     FrameMarking,
     /// <http://www.webrtc.org/experiments/rtp-hdrext/color-space>
     ColorSpace,
+    /// <http://www.webrtc.org/experiments/rtp-hdrext/abs-capture-time>
+    ///
+    /// NTP timestamp of when the media was captured by the original sender. Unlike the RTP
+    /// timestamp, this survives being forwarded through one or more SFUs.
+    AbsoluteCaptureTime,
+    /// <https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension>
+    ///
+    /// Frame dependencies and decode targets of AV1 SVC, for forwarding without looking at
+    /// the payload.
+    DependencyDescriptor,
 
     /// Not recognized URI, but it could still be user parseable.
     #[doc(hidden)]
@@ -141,6 +154,19 @@ impl Extension {
     fn requires_two_byte_form(&self, ev: &ExtensionValues) -> bool {
         match self {
             Extension::UnknownUri(_, serializer) => serializer.requires_two_byte_form(ev),
+            // The HDR metadata makes it 28 bytes, which doesn't fit the one byte form.
+            Extension::ColorSpace => ev
+                .color_space
+                .as_ref()
+                .map(|c| c.len() > 16)
+                .unwrap_or(false),
+            // An attached template structure is typically larger than 16 bytes.
+            Extension::DependencyDescriptor => ev
+                .dependency_descriptor
+                .as_ref()
+                .and_then(|d| d.to_bytes())
+                .map(|b| b.len() > 16)
+                .unwrap_or(false),
             _ => false,
         }
     }
@@ -221,6 +247,14 @@ const EXT_URI: &[(Extension, &str)] = &[
         Extension::ColorSpace,
         "http://www.webrtc.org/experiments/rtp-hdrext/color-space",
     ),
+    (
+        Extension::AbsoluteCaptureTime,
+        "http://www.webrtc.org/experiments/rtp-hdrext/abs-capture-time",
+    ),
+    (
+        Extension::DependencyDescriptor,
+        "https://aomediacodec.github.io/av1-rtp-spec/#dependency-descriptor-rtp-header-extension",
+    ),
 ];
 
 impl Extension {
@@ -292,6 +326,8 @@ impl Extension {
                 | TransportSequenceNumber
                 | TransmissionTimeOffset
                 | PlayoutDelay
+                | AbsoluteCaptureTime
+                | FrameMarking
         )
     }
 
@@ -316,6 +352,8 @@ impl Extension {
                 | VideoTiming
                 | FrameMarking
                 | ColorSpace
+                | AbsoluteCaptureTime
+                | DependencyDescriptor
         )
     }
 }
@@ -341,18 +379,26 @@ impl Extension {
 
 /// Mapping between RTP extension id to what extension that is.
 #[derive(Clone, PartialEq, Eq)]
-pub struct ExtensionMap([Option<MapEntry>; MAX_ID as usize]); // index 0 is extmap:1.
+pub struct ExtensionMap {
+    entries: [Option<MapEntry>; MAX_ID as usize], // index 0 is extmap:1.
+    allow_mixed: bool,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct MapEntry {
     ext: Extension,
     locked: bool,
+    /// Direction from our side, i.e. sendonly means we write it, but don't expect to read it.
+    direction: Direction,
 }
 
 impl ExtensionMap {
     /// Create an empty map.
     pub fn empty() -> Self {
-        ExtensionMap(std::array::from_fn(|_| None))
+        ExtensionMap {
+            entries: std::array::from_fn(|_| None),
+            allow_mixed: true,
+        }
     }
 
     /// Creates a map with the "standard" mappings.
@@ -374,7 +420,7 @@ impl ExtensionMap {
     }
 
     pub(crate) fn clear(&mut self) {
-        for i in &mut self.0 {
+        for i in &mut self.entries {
             *i = None;
         }
     }
@@ -389,9 +435,51 @@ impl ExtensionMap {
         }
         let idx = id as usize - 1;
 
-        let m = MapEntry { ext, locked: false };
+        let m = MapEntry {
+            ext,
+            locked: false,
+            direction: Direction::SendRecv,
+        };
+
+        self.entries[idx] = Some(m);
+    }
+
+    /// The direction of the extension with the id, as seen from our side.
+    ///
+    /// Set from the direction attribute of `a=extmap:<id>/<direction>` in SDP negotiation.
+    /// Extensions that are `recvonly` or `inactive` are not written to outgoing RTP headers.
+    pub fn direction(&self, id: u8) -> Option<Direction> {
+        if id >= 1 && id <= MAX_ID {
+            self.entries[id as usize - 1].as_ref().map(|m| m.direction)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set_direction(&mut self, id: u8, direction: Direction) {
+        if id >= 1 && id <= MAX_ID {
+            if let Some(m) = &mut self.entries[id as usize - 1] {
+                m.direction = direction;
+            }
+        }
+    }
+
+    /// Whether one-byte and two-byte header extensions can be mixed in the same RTP stream.
+    ///
+    /// This is `a=extmap-allow-mixed` (RFC 8285). Without it, the form is fixed by the
+    /// mapped ids, and values that don't fit the one-byte form are left out.
+    ///
+    /// Defaults to `true`.
+    pub fn allow_mixed(&self) -> bool {
+        self.allow_mixed
+    }
 
-        self.0[idx] = Some(m);
+    /// Set whether one-byte and two-byte header extensions can be mixed.
+    ///
+    /// For the SDP API, this is what we offer. The per-media map is then negotiated
+    /// with the remote peer.
+    pub fn set_allow_mixed(&mut self, allow_mixed: bool) {
+        self.allow_mixed = allow_mixed;
     }
 
     /// Look up the extension for the id.
@@ -399,7 +487,7 @@ impl ExtensionMap {
     /// The id must be in 1..=MAX_ID (1-indexed).
     pub fn lookup(&self, id: u8) -> Option<&Extension> {
         if id >= 1 && id <= MAX_ID {
-            self.0[id as usize - 1].as_ref().map(|m| &m.ext)
+            self.entries[id as usize - 1].as_ref().map(|m| &m.ext)
         } else {
             debug!("Lookup RTP extension out of range 1-{}: {}", MAX_ID, id);
             None
@@ -410,7 +498,7 @@ impl ExtensionMap {
     ///
     /// The returned id will be 1-based.
     pub fn id_of(&self, e: Extension) -> Option<u8> {
-        self.0
+        self.entries
             .iter()
             .position(|x| x.as_ref().map(|e| &e.ext) == Some(&e))
             .map(|p| p as u8 + 1)
@@ -418,7 +506,7 @@ impl ExtensionMap {
 
     /// Returns an iterator over the elements of the extension map
     pub fn iter(&self) -> impl Iterator<Item = (u8, &Extension)> + '_ {
-        self.0
+        self.entries
             .iter()
             .enumerate()
             .filter_map(|(i, e)| e.as_ref().map(|e| (i, e)))
@@ -450,12 +538,22 @@ impl ExtensionMap {
 
     pub(crate) fn cloned_with_type(&self, audio: bool) -> Self {
         let mut x = ExtensionMap::empty();
+        x.allow_mixed = self.allow_mixed;
         for (id, ext) in self.iter_by_media_type(audio) {
             x.set(id, ext.clone());
         }
         x
     }
 
+    /// Iterator over the extensions we write to outgoing RTP headers.
+    fn iter_sending(&self) -> impl Iterator<Item = (usize, &MapEntry)> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| e.as_ref().map(|e| (i, e)))
+            .filter(|(_, e)| e.direction.is_sending())
+    }
+
     // https://tools.ietf.org/html/rfc5285
     pub(crate) fn parse(
         &self,
@@ -517,10 +615,12 @@ impl ExtensionMap {
     }
 
     pub(crate) fn form(&self, ev: &ExtensionValues) -> ExtensionsForm {
-        if self
-            .iter()
-            .any(|(id, ext)| id > MAX_ID_ONE_BYTE_FORM || ext.requires_two_byte_form(ev))
-        {
+        // Without allow-mixed, the form must stay the same for every packet in the stream.
+        let mixed = self.allow_mixed;
+
+        if self.iter_sending().any(|(idx, e)| {
+            idx as u8 + 1 > MAX_ID_ONE_BYTE_FORM || mixed && e.ext.requires_two_byte_form(ev)
+        }) {
             ExtensionsForm::TwoByte
         } else {
             ExtensionsForm::OneByte
@@ -536,26 +636,29 @@ impl ExtensionMap {
         let orig_len = ext_buf.len();
         let mut b = ext_buf;
 
-        for (idx, x) in self.0.iter().enumerate() {
-            if let Some(v) = x {
-                match form {
-                    ExtensionsForm::OneByte => {
-                        if let Some(n) = v.ext.write_to(&mut b[1..], ev) {
-                            assert!(n <= 16);
-                            assert!(n > 0);
-                            b[0] = (idx as u8 + 1) << 4 | (n as u8 - 1);
-                            b = &mut b[1 + n..];
+        for (idx, v) in self.iter_sending() {
+            match form {
+                ExtensionsForm::OneByte => {
+                    if let Some(n) = v.ext.write_to(&mut b[1..], ev) {
+                        assert!(n > 0);
+                        if n > 16 {
+                            // Only without allow-mixed. The value doesn't fit the one-byte
+                            // form, and is left out (the written bytes are overwritten).
+                            assert!(!self.allow_mixed);
+                            continue;
                         }
+                        b[0] = (idx as u8 + 1) << 4 | (n as u8 - 1);
+                        b = &mut b[1 + n..];
                     }
-                    ExtensionsForm::TwoByte => {
-                        if let Some(n) = v.ext.write_to(&mut b[2..], ev) {
-                            b[0] = (idx + 1) as u8;
-                            b[1] = n as u8;
-                            b = &mut b[2 + n..];
-                        }
+                }
+                ExtensionsForm::TwoByte => {
+                    if let Some(n) = v.ext.write_to(&mut b[2..], ev) {
+                        b[0] = (idx + 1) as u8;
+                        b[1] = n as u8;
+                        b = &mut b[2 + n..];
                     }
-                };
-            }
+                }
+            };
         }
 
         orig_len - b.len()
@@ -577,7 +680,7 @@ impl ExtensionMap {
         let new_index = id as usize - 1;
 
         let Some(old_index) = self
-            .0
+            .entries
             .iter()
             .enumerate()
             .find(|(_, m)| m.as_ref().map(|m| &m.ext) == Some(ext))
@@ -587,7 +690,7 @@ impl ExtensionMap {
         };
 
         // Unwrap OK because index is checking just above.
-        let old = self.0[old_index].as_mut().unwrap();
+        let old = self.entries[old_index].as_mut().unwrap();
 
         let is_change = new_index != old_index;
 
@@ -607,7 +710,7 @@ impl ExtensionMap {
             return;
         }
 
-        self.0.swap(old_index, new_index);
+        self.entries.swap(old_index, new_index);
     }
 }
 
@@ -625,18 +728,17 @@ impl Extension {
                 // This should be a 64 second offset from unix epoch.
                 let dur = time_abs.to_unix_duration();
 
-                // Rebase to the 6.18 format.
-                let time_24 = MediaTime::from(dur)
-                    .rebase(Frequency::FIXED_POINT_6_18)
-                    .numer() as u32;
+                let time_24 = abs_send_time_to_24(MediaTime::from(dur));
 
                 buf[..3].copy_from_slice(&time_24.to_be_bytes()[1..]);
                 Some(3)
             }
             AudioLevel => {
                 let v1 = ev.audio_level?;
-                let v2 = ev.voice_activity?;
-                buf[0] = if v2 { 0x80 } else { 0 } | (-(0x7f & v1) as u8);
+                let v2 = ev.voice_activity.unwrap_or(false);
+                // The level is sent as the 7 bit value of -dBov, where 127 is silence.
+                let level = (v1 as i16).clamp(-127, 0).unsigned_abs() as u8;
+                buf[0] = if v2 { 0x80 } else { 0 } | level;
                 Some(1)
             }
             TransmissionTimeOffset => {
@@ -645,8 +747,12 @@ impl Extension {
                 Some(4)
             }
             VideoOrientation => {
+                // 0 0 0 0 C F R R
                 let v = ev.video_orientation?;
-                buf[0] = v as u8;
+                let flip = ev.video_flip.unwrap_or(false);
+                let back_camera = ev.video_back_camera.unwrap_or(false);
+                buf[0] =
+                    if back_camera { 0b1000 } else { 0 } | if flip { 0b0100 } else { 0 } | v as u8;
                 Some(1)
             }
             TransportSequenceNumber => {
@@ -655,10 +761,13 @@ impl Extension {
                 Some(2)
             }
             PlayoutDelay => {
-                let v1 = ev.play_delay_min?.rebase(Frequency::HUNDREDTHS);
-                let v2 = ev.play_delay_max?.rebase(Frequency::HUNDREDTHS);
-                let min = (v1.numer() & 0xfff) as u32;
-                let max = (v2.numer() & 0xfff) as u32;
+                let (v1, v2) = *ev.playout_delay.as_deref()?;
+                let min = playout_delay_to_12(v1);
+                let max = playout_delay_to_12(v2);
+                if min > max || max > PLAYOUT_DELAY_MAX {
+                    debug!("Invalid playout delay: {:?} - {:?}", v1, v2);
+                    return None;
+                }
                 buf[0] = (min >> 4) as u8;
                 buf[1] = (min << 4) as u8 | (max >> 8) as u8;
                 buf[2] = max as u8;
@@ -676,9 +785,8 @@ impl Extension {
                 buf[3..5].copy_from_slice(&v.encode_finish.to_be_bytes());
                 buf[5..7].copy_from_slice(&v.packetize_complete.to_be_bytes());
                 buf[7..9].copy_from_slice(&v.last_left_pacer.to_be_bytes());
-                // Reserved for network
-                buf[9..11].copy_from_slice(&0_u16.to_be_bytes());
-                buf[11..13].copy_from_slice(&0_u16.to_be_bytes());
+                buf[9..11].copy_from_slice(&v.network.to_be_bytes());
+                buf[11..13].copy_from_slice(&v.network2.to_be_bytes());
                 Some(13)
             }
             RtpStreamId => {
@@ -700,13 +808,38 @@ impl Extension {
                 Some(l)
             }
             FrameMarking => {
-                let v = ev.frame_mark?;
-                buf[..4].copy_from_slice(&v.to_be_bytes());
-                Some(4)
+                let v = ev.frame_marking?;
+                Some(v.write_to(buf))
             }
             ColorSpace => {
-                // TODO HDR color space
-                None
+                let v = ev.color_space.as_deref()?;
+                let n = v.write_to(buf);
+                if n.is_none() {
+                    debug!("Invalid color space HDR metadata: {:?}", v);
+                }
+                n
+            }
+            AbsoluteCaptureTime => {
+                let v = ev.abs_capture_time.as_deref()?;
+                buf[..8].copy_from_slice(&v.capture_time.to_be_bytes());
+                if let Some(offset) = v.clock_offset {
+                    buf[8..16].copy_from_slice(&offset.to_be_bytes());
+                    Some(16)
+                } else {
+                    Some(8)
+                }
+            }
+            DependencyDescriptor => {
+                let v = ev.dependency_descriptor.as_ref()?;
+                let Some(bytes) = v.to_bytes() else {
+                    debug!("Invalid dependency descriptor: {:?}", v);
+                    return None;
+                };
+                if bytes.len() > 255 || bytes.len() > buf.len() {
+                    return None;
+                }
+                buf[..bytes.len()].copy_from_slice(&bytes);
+                Some(bytes.len())
             }
             UnknownUri(_, serializer) => {
                 let n = serializer.write_to(buf, ev);
@@ -732,15 +865,10 @@ impl Extension {
                 }
                 let time_24 = u32::from_be_bytes([0, buf[0], buf[1], buf[2]]);
 
-                // Rebase to micros
-                let time_micros = MediaTime::from_fixed_point_6_18(time_24 as u64)
-                    .rebase(Frequency::MICROS)
-                    .numer();
-
                 // This should be the duration in 0-64 seconds from a fixed 64 second offset
                 // from UNIX EPOCH. For now, we must save this as offset from _something else_ and
                 // fix the correct value when we have the exact Instant::now() to relate it to.
-                let time_dur = Duration::from_micros(time_micros);
+                let time_dur = abs_send_time_from_24(time_24);
 
                 let time_tmp = already_happened() + time_dur;
                 ev.abs_send_time = Some(time_tmp);
@@ -766,6 +894,8 @@ impl Extension {
                     return None;
                 }
                 ev.video_orientation = Some(super::ext::VideoOrientation::from(buf[0] & 3));
+                ev.video_flip = Some(buf[0] & 0b0100 > 0);
+                ev.video_back_camera = Some(buf[0] & 0b1000 > 0);
             }
             // 2
             TransportSequenceNumber => {
@@ -779,10 +909,12 @@ impl Extension {
                 if buf.len() < 3 {
                     return None;
                 }
-                let min = (buf[0] as u32) << 4 | (buf[1] as u32) >> 4;
-                let max = ((buf[1] & 0xf) as u32) << 8 | buf[2] as u32;
-                ev.play_delay_min = Some(MediaTime::from_hundredths(min as u64));
-                ev.play_delay_max = Some(MediaTime::from_hundredths(max as u64));
+                let min = (buf[0] as u64) << 4 | (buf[1] as u64) >> 4;
+                let max = ((buf[1] & 0xf) as u64) << 8 | buf[2] as u64;
+                ev.playout_delay = Some(Box::new((
+                    Duration::from_millis(min * 10),
+                    Duration::from_millis(max * 10),
+                )));
             }
             // 1
             VideoContentType => {
@@ -793,7 +925,7 @@ impl Extension {
             }
             // 13
             VideoTiming => {
-                if buf.len() < 9 {
+                if buf.len() < 13 {
                     return None;
                 }
                 ev.video_timing = Some(self::VideoTiming {
@@ -802,8 +934,8 @@ impl Extension {
                     encode_finish: u16::from_be_bytes([buf[3], buf[4]]),
                     packetize_complete: u16::from_be_bytes([buf[5], buf[6]]),
                     last_left_pacer: u16::from_be_bytes([buf[7], buf[8]]),
-                    //  9 - 10 // reserved for network
-                    // 11 - 12 // reserved for network
+                    network: u16::from_be_bytes([buf[9], buf[10]]),
+                    network2: u16::from_be_bytes([buf[11], buf[12]]),
                 });
             }
             RtpStreamId => {
@@ -818,14 +950,39 @@ impl Extension {
                 let s = from_utf8(buf).ok()?;
                 ev.mid = Some(s.into());
             }
+            // 1 or 3
             FrameMarking => {
-                if buf.len() < 4 {
-                    return None;
-                }
-                ev.frame_mark = Some(u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]));
+                ev.frame_marking = Some(self::FrameMarking::parse(buf)?);
             }
+            // 4 or 28
             ColorSpace => {
-                // TODO HDR color space
+                let Some(v) = ColorSpaceValue::parse(buf) else {
+                    trace!("Failed to parse color space: {:02x?}", buf);
+                    return None;
+                };
+                ev.color_space = Some(Box::new(v));
+            }
+            // 8 or 16
+            AbsoluteCaptureTime => {
+                let clock_offset = match buf.len() {
+                    8 => None,
+                    16 => Some(i64::from_be_bytes(buf[8..16].try_into().unwrap())),
+                    _ => return None,
+                };
+                ev.abs_capture_time = Some(Box::new(AbsCaptureTime {
+                    capture_time: u64::from_be_bytes(buf[..8].try_into().unwrap()),
+                    clock_offset,
+                }));
+            }
+            // 3 or more
+            DependencyDescriptor => {
+                // Without the template structure of the stream, only the mandatory fields
+                // can be interpreted here. The rest is resolved per stream on receive.
+                let Some(v) = DependencyDescriptorValue::parse(buf, None) else {
+                    trace!("Failed to parse dependency descriptor: {:02x?}", buf);
+                    return None;
+                };
+                ev.dependency_descriptor = Some(Arc::new(v));
             }
             UnknownUri(_, serializer) => {
                 let success = serializer.parse_value(buf, ev);
@@ -844,15 +1001,76 @@ impl Extension {
 /// This is metadata that is available also without decrypting the SRTP packets.
 #[derive(Clone, Default, PartialEq, Eq)]
 pub struct ExtensionValues {
-    /// Audio level is measured in negative decibel. 0 is max and a "normal" value might be -30.
+    /// Audio level is measured in negative decibel (dBov). 0 is max and a "normal" value
+    /// might be -30. -127 means silence.
+    ///
+    /// When writing, values are clamped to the range -127..=0.
     pub audio_level: Option<i8>,
 
     /// Indication that there is sound from a voice.
+    ///
+    /// Sent in the same header extension as the audio level. If the level is set but this is
+    /// not, the voice activity flag is written as `false`.
     pub voice_activity: Option<bool>,
 
     /// Tell a receiver what rotation a video need to replay correctly.
+    ///
+    /// Senders can change this at any frame, typically when a mobile device is rotated.
     pub video_orientation: Option<VideoOrientation>,
 
+    /// Tell a receiver the video must be horizontally flipped (mirrored) to replay correctly.
+    ///
+    /// Sent in the same header extension as the video orientation. If the orientation is set
+    /// but this is not, the flip flag is written as `false`.
+    pub video_flip: Option<bool>,
+
+    /// Hint to the receiver's jitter buffer of the (min, max) playout delay.
+    ///
+    /// The values are sent in 10 ms units and are rounded to the closest 10 ms when written.
+    /// The extension is not written unless min ≤ max ≤ 40.95 seconds. (0, 0) means the
+    /// receiver should render as soon as possible, which is useful for interactive
+    /// screen share.
+    ///
+    /// <https://webrtc.googlesource.com/src/+/refs/heads/main/docs/native-code/rtp-hdrext/playout-delay>
+    pub playout_delay: Option<Box<(Duration, Duration)>>,
+
+    /// Indication that the video comes from a back facing camera.
+    ///
+    /// Sent in the same header extension as the video orientation. If the orientation is set
+    /// but this is not, the camera flag is written as `false` (front facing).
+    pub video_back_camera: Option<bool>,
+
+    /// Capture time of the media as set by the original sender.
+    ///
+    /// An SFU forwarding the packet should keep the capture time as is, but may update
+    /// the clock offset to reflect its own clock.
+    ///
+    /// Boxed, like the color space and playout delay, to keep the common case small.
+    pub abs_capture_time: Option<Box<AbsCaptureTime>>,
+
+    /// Color space of the video.
+    ///
+    /// Senders typically only attach this to keyframes. When set with HDR metadata,
+    /// the two byte extension form is used.
+    pub color_space: Option<Box<ColorSpaceValue>>,
+
+    /// Timing of the steps between capture and send, for debugging latency.
+    ///
+    /// When set, str0m fills in the packetization and pacer exit deltas.
+    pub video_timing: Option<VideoTiming>,
+
+    /// Codec agnostic frame boundaries and layer information.
+    ///
+    /// An SFU can use this to drop packets of discardable frames or higher layers
+    /// without looking at the (possibly encrypted) payload.
+    pub frame_marking: Option<FrameMarking>,
+
+    /// AV1 frame dependencies and decode targets.
+    ///
+    /// On receive, descriptors without an attached template structure are interpreted using
+    /// the last structure seen on the same stream.
+    pub dependency_descriptor: Option<Arc<DependencyDescriptorValue>>,
+
     // The values below are considered internal until we have a reason to expose them.
     // Generally we want to avoid expose experimental features unless there are strong
     // reasons to do so.
@@ -860,31 +1078,29 @@ pub struct ExtensionValues {
     pub video_content_type: Option<u8>, // 0 = unspecified, 1 = screenshare
     #[doc(hidden)]
     pub tx_time_offs: Option<u32>,
-    #[doc(hidden)]
+    /// Send time from the abs-send-time extension.
+    ///
+    /// On receive this is unwrapped, i.e. it keeps increasing past the 64 second
+    /// wrap-around of the 24 bit value on the wire.
     pub abs_send_time: Option<Instant>,
     #[doc(hidden)]
     pub transport_cc: Option<u16>, // (buf[0] << 8) | buf[1];
     #[doc(hidden)]
-    // https://webrtc.googlesource.com/src/+/refs/heads/master/docs/native-code/rtp-hdrext/playout-delay
-    pub play_delay_min: Option<MediaTime>,
-    #[doc(hidden)]
-    pub play_delay_max: Option<MediaTime>,
-    #[doc(hidden)]
-    pub video_timing: Option<VideoTiming>,
-    #[doc(hidden)]
     pub rid: Option<Rid>,
     #[doc(hidden)]
     pub rid_repair: Option<Rid>,
     #[doc(hidden)]
     pub mid: Option<Mid>,
-    #[doc(hidden)]
-    pub frame_mark: Option<u32>,
 
     /// User values for [`ExtensionSerializer`] to parse into and write from.
     pub user_values: UserExtensionValues,
 }
 impl ExtensionValues {
-    pub(crate) fn update_absolute_send_time(&mut self, now: Instant) {
+    pub(crate) fn update_absolute_send_time(
+        &mut self,
+        now: Instant,
+        unwrapper: &mut AbsSendTimeUnwrapper,
+    ) {
         let Some(v) = self.abs_send_time else {
             return;
         };
@@ -893,21 +1109,169 @@ impl ExtensionValues {
         let relative_64_secs = v - already_happened();
         assert!(relative_64_secs <= Duration::from_secs(64));
 
-        let now_since_epoch = now.to_unix_duration();
+        let unwrapped: Duration = unwrapper
+            .unwrap(abs_send_time_to_24(relative_64_secs.into()))
+            .into();
+
+        // Only the first value is placed relative to now. The following ones are offset
+        // from it on the unwrapped timeline, which doesn't jump back at the wrap-around.
+        let (first, first_at) = *unwrapper
+            .anchor
+            .get_or_insert_with(|| (unwrapped, closest_before(now, relative_64_secs)));
+
+        let at = if unwrapped >= first {
+            first_at + (unwrapped - first)
+        } else {
+            // Reordered before the first packet.
+            first_at - (first - unwrapped)
+        };
+
+        self.abs_send_time = Some(at);
+    }
+}
+
+/// The time at `relative_64_secs` into the latest 64 second period (since UNIX EPOCH)
+/// that is not after `now`.
+fn closest_before(now: Instant, relative_64_secs: Duration) -> Instant {
+    let now_since_epoch = now.to_unix_duration();
 
-        let closest_64 = now_since_epoch.saturating_sub(Duration::from_micros(
-            now_since_epoch.as_micros() as u64 % 64_000_000,
-        ));
+    let closest_64 = now_since_epoch.saturating_sub(Duration::from_micros(
+        now_since_epoch.as_micros() as u64 % 64_000_000,
+    ));
 
-        let since_beginning = closest_64.saturating_sub(epoch_to_beginning());
+    let since_beginning = closest_64.saturating_sub(epoch_to_beginning());
 
-        let mut offset = already_happened() + since_beginning;
+    let mut offset = already_happened() + since_beginning;
 
-        if offset + relative_64_secs > now {
-            offset -= Duration::from_secs(64);
+    if offset + relative_64_secs > now {
+        offset -= Duration::from_secs(64);
+    }
+
+    offset + relative_64_secs
+}
+
+/// Value of the abs-capture-time header extension.
+///
+/// Both values are 64 bit Q32.32 fixed point numbers, i.e. the same format as the
+/// NTP timestamp in RTCP sender reports.
+///
+/// <https://webrtc.googlesource.com/src/+/refs/heads/main/docs/native-code/rtp-hdrext/abs-capture-time>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbsCaptureTime {
+    /// NTP timestamp of when the first audio or video frame in the packet was captured,
+    /// in the clock of the original capturer.
+    pub capture_time: u64,
+
+    /// Estimated offset between the capturer's clock and the clock of the sender
+    /// of this packet, in signed seconds (Q32.32).
+    ///
+    /// Adding this to the capture time gives the capture time in the sender's clock.
+    pub clock_offset: Option<i64>,
+}
+
+impl AbsCaptureTime {
+    /// Creates a value from the time the media was captured.
+    pub fn new(capture_time: Instant) -> Self {
+        AbsCaptureTime {
+            capture_time: capture_time.as_ntp_64(),
+            clock_offset: None,
         }
+    }
+
+    /// The capture time as an [`Instant`].
+    ///
+    /// This is only meaningful if the capturer's clock is synchronized with ours.
+    pub fn capture_instant(&self) -> Instant {
+        Instant::from_ntp_64(self.capture_time)
+    }
+
+    /// Update the estimated clock offset, keeping the capture time.
+    ///
+    /// This is what a forwarding SFU would do with its own estimate of the
+    /// offset between the capturer's clock and its own.
+    pub fn set_clock_offset(&mut self, offset_secs: f64) {
+        self.clock_offset = Some((offset_secs * F32) as i64);
+    }
+
+    /// The estimated clock offset in seconds, if set.
+    pub fn clock_offset_secs(&self) -> Option<f64> {
+        self.clock_offset.map(|v| v as f64 / F32)
+    }
+}
+
+/// 2^32 as float.
+const F32: f64 = 4_294_967_296.0;
+
+/// Max playout delay in 10ms units (40.95 seconds).
+const PLAYOUT_DELAY_MAX: u32 = 0xfff;
+
+/// Convert a playout delay to 10ms units, rounded to the closest.
+fn playout_delay_to_12(d: Duration) -> u32 {
+    ((d.as_micros() + 5_000) / 10_000).min(u32::MAX as u128) as u32
+}
+
+/// Number of 6.18 fixed point units before the abs-send-time wraps around (64 seconds).
+const ABS_SEND_TIME_WRAP: u64 = 1 << 24;
+
+/// Convert a time to the 24 bit 6.18 fixed point format used by abs-send-time.
+///
+/// The value is rounded to the nearest unit (1/262144 s) and wraps around every 64 seconds.
+pub(crate) fn abs_send_time_to_24(t: MediaTime) -> u32 {
+    let denom = t.denom() as u128;
+    let units = t.numer() as u128 * Frequency::FIXED_POINT_6_18.get() as u128;
+
+    // Round half up.
+    let rounded = (units * 2 + denom) / (denom * 2);
+
+    (rounded % ABS_SEND_TIME_WRAP as u128) as u32
+}
+
+/// Convert a 24 bit 6.18 fixed point abs-send-time to a duration in the 0-64 second range.
+pub(crate) fn abs_send_time_from_24(time_24: u32) -> Duration {
+    let units = (time_24 as u64 % ABS_SEND_TIME_WRAP) as u128;
+    let denom = Frequency::FIXED_POINT_6_18.get() as u128;
+
+    // Round to nearest nanosecond.
+    let nanos = (units * 2_000_000_000 + denom) / (denom * 2);
+
+    Duration::from_nanos(nanos as u64)
+}
+
+/// Unwraps the 24 bit abs-send-time into a continuous timeline.
+///
+/// The abs-send-time wraps around every 64 seconds, which makes the raw value useless
+/// for comparing packets over a longer period. The unwrapper keeps track of the number
+/// of wrap-arounds seen so far, which works as long as consecutive values are less than
+/// 32 seconds apart.
+#[derive(Debug, Default)]
+pub struct AbsSendTimeUnwrapper {
+    last: Option<u64>,
+    /// First unwrapped value and the time it was placed at on receive.
+    anchor: Option<(Duration, Instant)>,
+}
 
-        self.abs_send_time = Some(offset + relative_64_secs);
+impl AbsSendTimeUnwrapper {
+    /// Creates a new unwrapper.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unwrap the next raw 24 bit value into a continuous media time in the
+    /// [`Frequency::FIXED_POINT_6_18`] time base.
+    pub fn unwrap(&mut self, time_24: u32) -> MediaTime {
+        let extended = extend_u24(self.last, time_24);
+        self.last = Some(extended);
+        MediaTime::from_fixed_point_6_18(extended)
+    }
+
+    /// Like [`AbsSendTimeUnwrapper::unwrap`], but reads the raw value straight from
+    /// an abs-send-time header extension element.
+    pub fn unwrap_bytes(&mut self, buf: &[u8]) -> Option<MediaTime> {
+        if buf.len() < 3 {
+            return None;
+        }
+        let time_24 = u32::from_be_bytes([0, buf[0], buf[1], buf[2]]);
+        Some(self.unwrap(time_24))
     }
 }
 
@@ -1060,14 +1424,23 @@ impl fmt::Debug for ExtensionValues {
         if let Some(t) = self.video_orientation {
             write!(f, " video_orientation: {t:?}")?;
         }
+        if let Some(t) = self.video_flip {
+            write!(f, " video_flip: {t}")?;
+        }
+        if let Some(t) = self.video_back_camera {
+            write!(f, " video_back_camera: {t}")?;
+        }
         if let Some(t) = self.transport_cc {
             write!(f, " transport_cc: {t}")?;
         }
-        if let Some(t) = self.play_delay_min {
-            write!(f, " play_delay_min: {}", t.as_seconds())?;
+        if let Some((min, max)) = self.playout_delay.as_deref() {
+            write!(f, " playout_delay: {:?}-{:?}", min, max)?;
         }
-        if let Some(t) = self.play_delay_max {
-            write!(f, " play_delay_max: {}", t.as_seconds())?;
+        if let Some(t) = &self.abs_capture_time {
+            write!(f, " abs_capture_time: {t:?}")?;
+        }
+        if let Some(t) = &self.color_space {
+            write!(f, " color_space: {t:?}")?;
         }
         if let Some(t) = self.video_content_type {
             write!(f, " video_content_type: {t}")?;
@@ -1075,8 +1448,11 @@ impl fmt::Debug for ExtensionValues {
         if let Some(t) = &self.video_timing {
             write!(f, " video_timing: {t:?}")?;
         }
-        if let Some(t) = &self.frame_mark {
-            write!(f, " frame_mark: {t}")?;
+        if let Some(t) = &self.frame_marking {
+            write!(f, " frame_marking: {t:?}")?;
+        }
+        if let Some(t) = &self.dependency_descriptor {
+            write!(f, " dependency_descriptor: {t:?}")?;
         }
 
         write!(f, " }}")?;
@@ -1084,15 +1460,132 @@ impl fmt::Debug for ExtensionValues {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Value of the video-timing header extension.
+///
+/// The deltas are in milliseconds relative to the capture time of the frame, and
+/// saturate at 65.535 seconds.
+///
+/// <https://webrtc.googlesource.com/src/+/refs/heads/main/docs/native-code/rtp-hdrext/video-timing>
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct VideoTiming {
-    // 0x01 = extension is set due to timer.
-    // 0x02 - extension is set because the frame is larger than usual.
+    /// 0x01 = extension is set due to timer.
+    /// 0x02 = extension is set because the frame is larger than usual.
     pub flags: u8,
+    /// Delta to when the encoder started on the frame.
     pub encode_start: u16,
+    /// Delta to when the encoder finished the frame.
     pub encode_finish: u16,
+    /// Delta to when the frame was packetized. Set by str0m.
     pub packetize_complete: u16,
+    /// Delta to when the packet left the pacer. Set by str0m.
     pub last_left_pacer: u16,
+    /// Reserved for network elements.
+    pub network: u16,
+    /// Reserved for network elements.
+    pub network2: u16,
+}
+
+impl VideoTiming {
+    /// Set the encode start delta from the capture time and the time encoding started.
+    pub fn set_encode_start(&mut self, capture: Instant, at: Instant) {
+        self.encode_start = video_timing_delta(capture, at);
+    }
+
+    /// Set the encode finish delta from the capture time and the time encoding finished.
+    pub fn set_encode_finish(&mut self, capture: Instant, at: Instant) {
+        self.encode_finish = video_timing_delta(capture, at);
+    }
+
+    pub(crate) fn set_packetize_complete(&mut self, capture: Instant, at: Instant) {
+        self.packetize_complete = video_timing_delta(capture, at);
+    }
+
+    pub(crate) fn set_pacer_exit(&mut self, capture: Instant, at: Instant) {
+        self.last_left_pacer = video_timing_delta(capture, at);
+    }
+}
+
+/// Value of the frame marking header extension.
+///
+/// <https://datatracker.ietf.org/doc/html/draft-ietf-avtext-framemarking-07>
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameMarking {
+    /// The packet is the first packet of the frame.
+    pub start_of_frame: bool,
+    /// The packet is the last packet of the frame.
+    pub end_of_frame: bool,
+    /// The frame can be decoded without any previous frames.
+    pub independent: bool,
+    /// No other frame depends on this frame, which means it can be dropped.
+    pub discardable: bool,
+    /// Layer information for scalable streams. If this is `None`, the short one byte form
+    /// is used, which is what audio and non-scalable video should use.
+    pub layers: Option<FrameMarkingLayers>,
+}
+
+/// Layer information of the frame marking header extension for scalable streams.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameMarkingLayers {
+    /// The frame is a base layer sync point, i.e. it only depends on the base layer.
+    pub base_layer_sync: bool,
+    /// Temporal layer id, 0..=7. 0 is the base layer.
+    pub tid: u8,
+    /// Layer id, the meaning of which is codec specific (spatial or quality layer).
+    pub lid: u8,
+    /// Temporal layer 0 picture index, a running index of base layer frames.
+    pub tl0_pic_idx: u8,
+}
+
+impl FrameMarking {
+    fn write_to(&self, buf: &mut [u8]) -> usize {
+        // |S|E|I|D|B| TID |
+        buf[0] = (self.start_of_frame as u8) << 7
+            | (self.end_of_frame as u8) << 6
+            | (self.independent as u8) << 5
+            | (self.discardable as u8) << 4;
+
+        let Some(l) = self.layers else {
+            return 1;
+        };
+
+        buf[0] |= (l.base_layer_sync as u8) << 3 | (l.tid & 0b111);
+        buf[1] = l.lid;
+        buf[2] = l.tl0_pic_idx;
+
+        3
+    }
+
+    fn parse(buf: &[u8]) -> Option<Self> {
+        let layers = match buf.len() {
+            1 => None,
+            3 => Some(FrameMarkingLayers {
+                base_layer_sync: buf[0] & 0b1000 > 0,
+                tid: buf[0] & 0b111,
+                lid: buf[1],
+                tl0_pic_idx: buf[2],
+            }),
+            _ => return None,
+        };
+
+        Some(FrameMarking {
+            start_of_frame: buf[0] & 0x80 > 0,
+            end_of_frame: buf[0] & 0x40 > 0,
+            independent: buf[0] & 0x20 > 0,
+            discardable: buf[0] & 0x10 > 0,
+            layers,
+        })
+    }
+
+    /// The temporal layer id, or 0 for non-scalable streams.
+    pub fn tid(&self) -> u8 {
+        self.layers.map(|l| l.tid).unwrap_or(0)
+    }
+}
+
+fn video_timing_delta(capture: Instant, at: Instant) -> u16 {
+    at.saturating_duration_since(capture)
+        .as_millis()
+        .min(u16::MAX as u128) as u16
 }
 
 impl fmt::Display for Extension {
@@ -1115,6 +1608,8 @@ impl fmt::Display for Extension {
                 RtpMid => "mid",
                 FrameMarking => "frame-marking07",
                 ColorSpace => "color-space",
+                AbsoluteCaptureTime => "abs-capture-time",
+                DependencyDescriptor => "dependency-descriptor",
                 UnknownUri(uri, _) => uri,
             }
         )
@@ -1125,7 +1620,7 @@ impl fmt::Debug for ExtensionMap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Extensions(")?;
         let joined = self
-            .0
+            .entries
             .iter()
             .enumerate()
             .filter_map(|(i, v)| v.as_ref().map(|v| (i + 1, v)))
@@ -1178,6 +1673,8 @@ impl PartialEq for Extension {
             (Extension::RtpMid, Extension::RtpMid) => true,
             (Extension::FrameMarking, Extension::FrameMarking) => true,
             (Extension::ColorSpace, Extension::ColorSpace) => true,
+            (Extension::AbsoluteCaptureTime, Extension::AbsoluteCaptureTime) => true,
+            (Extension::DependencyDescriptor, Extension::DependencyDescriptor) => true,
             (Extension::UnknownUri(uri1, _), Extension::UnknownUri(uri2, _)) => uri1 == uri2,
             _ => false,
         }
@@ -1208,7 +1705,10 @@ mod test {
         exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
 
         // Let's pretend a 50 millisecond network latency.
-        ev2.update_absolute_send_time(now + Duration::from_millis(50));
+        ev2.update_absolute_send_time(
+            now + Duration::from_millis(50),
+            &mut AbsSendTimeUnwrapper::new(),
+        );
 
         let now2 = ev2.abs_send_time.unwrap();
 
@@ -1236,7 +1736,10 @@ mod test {
         exts.parse(&buf, ExtensionsForm::TwoByte, &mut ev2);
 
         // Let's pretend a 50 millisecond network latency.
-        ev2.update_absolute_send_time(now + Duration::from_millis(50));
+        ev2.update_absolute_send_time(
+            now + Duration::from_millis(50),
+            &mut AbsSendTimeUnwrapper::new(),
+        );
 
         let now2 = ev2.abs_send_time.unwrap();
 
@@ -1245,13 +1748,169 @@ mod test {
         assert!(abs < Duration::from_millis(1));
     }
 
+    #[test]
+    fn abs_send_time_rounding() {
+        // 1/3 second is 87381.33 units, which must round down.
+        assert_eq!(abs_send_time_to_24(MediaTime::from_micros(333_333)), 87381);
+        // 2/3 second is 174762.67 units, which must round up.
+        assert_eq!(abs_send_time_to_24(MediaTime::from_micros(666_667)), 174763);
+        // Wraps at 64 seconds.
+        assert_eq!(abs_send_time_to_24(MediaTime::from_secs(64)), 0);
+        assert_eq!(abs_send_time_to_24(MediaTime::from_secs(65)), 262_144);
+
+        // Round trip is within one unit for a spread of values.
+        for micros in (0..64_000_000).step_by(999_983) {
+            let t = MediaTime::from_micros(micros);
+            let back = abs_send_time_from_24(abs_send_time_to_24(t));
+            let diff = (back.as_secs_f64() - t.as_seconds()).abs();
+            assert!(diff < 1.0 / 262_144.0, "{micros} {diff}");
+        }
+    }
+
+    #[test]
+    fn abs_send_time_unwrap() {
+        let mut unwrapper = AbsSendTimeUnwrapper::new();
+
+        let t1 = unwrapper.unwrap(abs_send_time_to_24(MediaTime::from_secs(60)));
+        assert_eq!(t1, MediaTime::from_secs(60));
+
+        // 66 seconds wraps to 2 seconds, but should continue the timeline.
+        let t2 = unwrapper.unwrap(abs_send_time_to_24(MediaTime::from_secs(66)));
+        assert_eq!(t2, MediaTime::from_secs(66));
+
+        // Slight reordering backwards across the wrap.
+        let t3 = unwrapper.unwrap(abs_send_time_to_24(MediaTime::from_secs(63)));
+        assert_eq!(t3, MediaTime::from_secs(63));
+
+        let t4 = unwrapper
+            .unwrap_bytes(&abs_send_time_to_24(MediaTime::from_secs(200)).to_be_bytes()[1..]);
+        // A jump of more than 32 seconds can't be told apart from a wrap-around.
+        // 200 seconds is raw 8 seconds, which is closest to 63 as 72 seconds.
+        assert_eq!(t4, Some(MediaTime::from_secs(72)));
+
+        assert_eq!(unwrapper.unwrap_bytes(&[0, 1]), None);
+    }
+
+    #[test]
+    fn abs_send_time_received_across_wrap() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(4, Extension::AbsoluteSendTime);
+
+        let start = Instant::now() + Duration::from_secs(1000);
+        let mut unwrapper = AbsSendTimeUnwrapper::new();
+        let mut first = None;
+
+        // A packet every 500ms for 200 seconds passes the wrap-around three times.
+        for i in 0..400 {
+            let sent = start + Duration::from_millis(i * 500);
+            // The receiver clock is 40ms behind, and the network delay is 30-50ms.
+            let now = sent + Duration::from_millis(30 + (i % 3) * 10) - Duration::from_millis(40);
+            let ev = ExtensionValues {
+                abs_send_time: Some(sent),
+                ..Default::default()
+            };
+
+            let mut buf = [0_u8; 8];
+            exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+
+            let mut ev2 = ExtensionValues::default();
+            exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+            ev2.update_absolute_send_time(now, &mut unwrapper);
+
+            let received = ev2.abs_send_time.unwrap();
+            let first = *first.get_or_insert(received);
+
+            let elapsed = received - first;
+            let expected = Duration::from_millis(i * 500);
+            let diff = elapsed.max(expected) - elapsed.min(expected);
+            assert!(diff < Duration::from_millis(1), "{i} {elapsed:?}");
+        }
+    }
+
+    #[test]
+    fn audio_level_roundtrip() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(1, Extension::AudioLevel);
+
+        for level in -127..=0 {
+            for vad in [false, true] {
+                let ev = ExtensionValues {
+                    audio_level: Some(level),
+                    voice_activity: Some(vad),
+                    ..Default::default()
+                };
+
+                let mut buf = vec![0_u8; 8];
+                exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+                assert_eq!(buf[1], if vad { 0x80 } else { 0 } | (-level as u8));
+
+                let mut ev2 = ExtensionValues::default();
+                exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+
+                assert_eq!(ev2.audio_level, Some(level));
+                assert_eq!(ev2.voice_activity, Some(vad));
+            }
+        }
+    }
+
+    #[test]
+    fn audio_level_clamped() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(1, Extension::AudioLevel);
+
+        for (level, expected) in [(-128, -127), (1, 0), (127, 0)] {
+            let ev = ExtensionValues {
+                audio_level: Some(level),
+                ..Default::default()
+            };
+
+            let mut buf = vec![0_u8; 8];
+            exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+
+            let mut ev2 = ExtensionValues::default();
+            exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+
+            assert_eq!(ev2.audio_level, Some(expected));
+            assert_eq!(ev2.voice_activity, Some(false));
+        }
+    }
+
+    #[test]
+    fn video_orientation_all_bits() {
+        use super::VideoOrientation::*;
+
+        let mut exts = ExtensionMap::empty();
+        exts.set(13, Extension::VideoOrientation);
+
+        for (rotation, bits) in [(Deg0, 0), (Deg270, 1), (Deg180, 2), (Deg90, 3)] {
+            for flip in [false, true] {
+                for back_camera in [false, true] {
+                    let ev = ExtensionValues {
+                        video_orientation: Some(rotation),
+                        video_flip: Some(flip),
+                        video_back_camera: Some(back_camera),
+                        ..Default::default()
+                    };
+
+                    let mut buf = vec![0_u8; 8];
+                    exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+                    let expected = (back_camera as u8) << 3 | (flip as u8) << 2 | bits;
+                    assert_eq!(buf[1], expected);
+
+                    let mut ev2 = ExtensionValues::default();
+                    exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+                    assert_eq!(ev, ev2);
+                }
+            }
+        }
+    }
+
     #[test]
     fn playout_delay() {
         let mut exts = ExtensionMap::empty();
         exts.set(2, Extension::PlayoutDelay);
         let ev = ExtensionValues {
-            play_delay_min: Some(MediaTime::from_hundredths(100)),
-            play_delay_max: Some(MediaTime::from_hundredths(200)),
+            playout_delay: Some(Box::new((Duration::from_secs(1), Duration::from_secs(2)))),
             ..Default::default()
         };
 
@@ -1261,8 +1920,507 @@ mod test {
         let mut ev2 = ExtensionValues::default();
         exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
 
-        assert_eq!(ev.play_delay_min, ev2.play_delay_min);
-        assert_eq!(ev.play_delay_max, ev2.play_delay_max);
+        assert_eq!(ev.playout_delay, ev2.playout_delay);
+    }
+
+    #[test]
+    fn abs_capture_time() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(5, Extension::AbsoluteCaptureTime);
+        let ev = ExtensionValues {
+            abs_capture_time: Some(Box::new(AbsCaptureTime {
+                capture_time: 0x0123_4567_89ab_cdef,
+                clock_offset: None,
+            })),
+            ..Default::default()
+        };
+
+        let mut buf = vec![0_u8; 20];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(n, 9);
+        assert_eq!(buf[0], 0x57);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev.abs_capture_time, ev2.abs_capture_time);
+
+        // With the clock offset the element is 16 bytes, which still fits the one byte form.
+        let ev = ExtensionValues {
+            abs_capture_time: Some(Box::new(AbsCaptureTime {
+                capture_time: 0x0123_4567_89ab_cdef,
+                clock_offset: Some(-(1 << 31)),
+            })),
+            ..Default::default()
+        };
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(n, 17);
+        assert_eq!(buf[0], 0x5f);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev.abs_capture_time, ev2.abs_capture_time);
+        assert_eq!(
+            ev2.abs_capture_time.unwrap().clock_offset_secs(),
+            Some(-0.5)
+        );
+    }
+
+    #[test]
+    fn abs_capture_time_truncated() {
+        let mut ev = ExtensionValues::default();
+        assert!(Extension::AbsoluteCaptureTime
+            .parse_value(&[0; 12], &mut ev)
+            .is_none());
+        assert_eq!(ev.abs_capture_time, None);
+    }
+
+    #[test]
+    fn abs_capture_time_forwarded() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(5, Extension::AbsoluteCaptureTime);
+
+        let capture = AbsCaptureTime::new(Instant::now());
+        let ev = ExtensionValues {
+            abs_capture_time: Some(Box::new(capture)),
+            ..Default::default()
+        };
+
+        let mut buf = vec![0_u8; 20];
+        exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+
+        // Simulated SFU hop: parse, update the clock offset, and write again.
+        let mut hop = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut hop);
+        hop.abs_capture_time
+            .as_mut()
+            .unwrap()
+            .set_clock_offset(0.25);
+
+        let mut buf2 = vec![0_u8; 20];
+        exts.write_to(&mut buf2[..], &hop, ExtensionsForm::OneByte);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf2, ExtensionsForm::OneByte, &mut ev2);
+
+        let v = ev2.abs_capture_time.unwrap();
+        assert_eq!(v.capture_time, capture.capture_time);
+        assert_eq!(v.clock_offset_secs(), Some(0.25));
+        assert_eq!(buf[1..9], buf2[1..9]);
+    }
+
+    #[test]
+    fn color_space_form() {
+        use crate::rtp_::{ChromaSiting, ColorPrimaries, ColorRange, HdrMetadata};
+        use crate::rtp_::{MatrixCoefficients, TransferCharacteristics};
+
+        let mut exts = ExtensionMap::empty();
+        exts.set(8, Extension::ColorSpace);
+
+        let mut cs = ColorSpaceValue {
+            primaries: ColorPrimaries::Bt2020,
+            transfer: TransferCharacteristics::AribStdB67,
+            matrix: MatrixCoefficients::Bt2020Ncl,
+            range: ColorRange::Limited,
+            chroma_siting_horizontal: ChromaSiting::Collocated,
+            chroma_siting_vertical: ChromaSiting::Collocated,
+            hdr_metadata: None,
+        };
+
+        let ev = ExtensionValues {
+            color_space: Some(Box::new(cs)),
+            ..Default::default()
+        };
+        assert_eq!(exts.form(&ev), ExtensionsForm::OneByte);
+
+        cs.hdr_metadata = Some(HdrMetadata {
+            primary_r: Default::default(),
+            primary_g: Default::default(),
+            primary_b: Default::default(),
+            white_point: Default::default(),
+            luminance_max: 4000,
+            luminance_min: 1,
+            max_content_light_level: 0,
+            max_frame_average_light_level: 0,
+        });
+        let ev = ExtensionValues {
+            color_space: Some(Box::new(cs)),
+            ..Default::default()
+        };
+        assert_eq!(exts.form(&ev), ExtensionsForm::TwoByte);
+
+        let mut buf = vec![0_u8; 32];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::TwoByte);
+        assert_eq!(n, 30);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::TwoByte, &mut ev2);
+        assert_eq!(ev2.color_space.as_deref(), Some(&cs));
+    }
+
+    #[test]
+    fn color_space_not_mixed() {
+        use crate::rtp_::{ChromaSiting, ColorPrimaries, ColorRange, HdrMetadata};
+        use crate::rtp_::{MatrixCoefficients, TransferCharacteristics};
+
+        let mut exts = ExtensionMap::empty();
+        exts.set(3, Extension::VideoOrientation);
+        exts.set(8, Extension::ColorSpace);
+        exts.set_allow_mixed(false);
+
+        let cs = ColorSpaceValue {
+            primaries: ColorPrimaries::Bt2020,
+            transfer: TransferCharacteristics::AribStdB67,
+            matrix: MatrixCoefficients::Bt2020Ncl,
+            range: ColorRange::Limited,
+            chroma_siting_horizontal: ChromaSiting::Collocated,
+            chroma_siting_vertical: ChromaSiting::Collocated,
+            hdr_metadata: Some(HdrMetadata {
+                primary_r: Default::default(),
+                primary_g: Default::default(),
+                primary_b: Default::default(),
+                white_point: Default::default(),
+                luminance_max: 4000,
+                luminance_min: 1,
+                max_content_light_level: 0,
+                max_frame_average_light_level: 0,
+            }),
+        };
+        let ev = ExtensionValues {
+            video_orientation: Some(VideoOrientation::Deg90),
+            color_space: Some(Box::new(cs)),
+            ..Default::default()
+        };
+
+        // Without mixing, the form doesn't change because of the HDR metadata.
+        assert_eq!(exts.form(&ev), ExtensionsForm::OneByte);
+
+        // The color space doesn't fit, and is left out.
+        let mut buf = [0_u8; 32];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(n, 2);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf[..n], ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev2.video_orientation, Some(VideoOrientation::Deg90));
+        assert_eq!(ev2.color_space, None);
+
+        // Ids above 14 make it two-byte for every packet.
+        exts.set(15, Extension::TransportSequenceNumber);
+        assert_eq!(exts.form(&ev), ExtensionsForm::TwoByte);
+    }
+
+    #[test]
+    fn direction_not_sending() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(3, Extension::VideoOrientation);
+        exts.set(15, Extension::TransportSequenceNumber);
+        exts.set_direction(15, Direction::RecvOnly);
+
+        let ev = ExtensionValues {
+            video_orientation: Some(VideoOrientation::Deg180),
+            transport_cc: Some(1234),
+            ..Default::default()
+        };
+
+        // Id 15 is not written, so the one-byte form is enough.
+        assert_eq!(exts.form(&ev), ExtensionsForm::OneByte);
+
+        let mut buf = [0_u8; 16];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(n, 2);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf[..n], ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev2.video_orientation, Some(VideoOrientation::Deg180));
+        assert_eq!(ev2.transport_cc, None);
+    }
+
+    #[test]
+    fn dependency_descriptor_form() {
+        use crate::rtp_::{Dti, FrameDependency, FrameDependencyStructure};
+
+        let mut exts = ExtensionMap::empty();
+        exts.set(12, Extension::DependencyDescriptor);
+
+        let dd = DependencyDescriptorValue::new(true, true, 1, 4711);
+        let ev = ExtensionValues {
+            dependency_descriptor: Some(Arc::new(dd.clone())),
+            ..Default::default()
+        };
+        assert_eq!(exts.form(&ev), ExtensionsForm::OneByte);
+
+        let mut buf = vec![0_u8; 8];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(n, 4);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev2.dependency_descriptor, ev.dependency_descriptor);
+
+        // 32 decode targets makes the structure too big for the one byte form.
+        let template = |temporal_id, fdiffs: Vec<u16>| FrameDependency {
+            spatial_id: 0,
+            temporal_id,
+            dtis: vec![Dti::Switch; 32],
+            fdiffs,
+            chain_fdiffs: vec![],
+        };
+        let structure = FrameDependencyStructure {
+            template_id_offset: 1,
+            decode_target_count: 32,
+            chain_count: 0,
+            decode_target_protected_by_chain: vec![],
+            templates: vec![template(0, vec![]), template(1, vec![1])],
+            resolutions: vec![],
+        };
+
+        let mut dd = dd;
+        dd.structure = Some(Arc::new(structure));
+        dd.structure_attached = true;
+        dd.active_decode_targets = Some(u32::MAX);
+        dd.frame = Some(template(0, vec![]));
+
+        let ev = ExtensionValues {
+            dependency_descriptor: Some(Arc::new(dd)),
+            ..Default::default()
+        };
+        assert_eq!(exts.form(&ev), ExtensionsForm::TwoByte);
+
+        let mut buf = vec![0_u8; 64];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::TwoByte);
+        assert!(n > 18);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::TwoByte, &mut ev2);
+        assert_eq!(ev2.dependency_descriptor, ev.dependency_descriptor);
+    }
+
+    #[test]
+    fn video_timing() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(7, Extension::VideoTiming);
+
+        let capture = Instant::now();
+        let mut timing = VideoTiming {
+            flags: 0x01,
+            ..Default::default()
+        };
+        timing.set_encode_start(capture, capture + Duration::from_millis(3));
+        timing.set_encode_finish(capture, capture + Duration::from_millis(12));
+        timing.set_packetize_complete(capture, capture + Duration::from_millis(14));
+
+        let ev = ExtensionValues {
+            video_timing: Some(timing),
+            ..Default::default()
+        };
+
+        let mut buf = vec![0_u8; 16];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(n, 14);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev2.video_timing, Some(timing));
+
+        // The pacer stamps its field last, right before sending.
+        let mut timing2 = ev2.video_timing.unwrap();
+        timing2.set_pacer_exit(capture, capture + Duration::from_millis(20));
+        let ev3 = ExtensionValues {
+            video_timing: Some(timing2),
+            ..Default::default()
+        };
+        exts.write_to(&mut buf[..], &ev3, ExtensionsForm::OneByte);
+
+        let mut ev4 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev4);
+        let v = ev4.video_timing.unwrap();
+        assert_eq!(v.flags, 0x01);
+        assert_eq!(v.encode_start, 3);
+        assert_eq!(v.encode_finish, 12);
+        assert_eq!(v.packetize_complete, 14);
+        assert_eq!(v.last_left_pacer, 20);
+
+        // Truncated element.
+        let mut ev5 = ExtensionValues::default();
+        assert!(Extension::VideoTiming
+            .parse_value(&buf[1..10], &mut ev5)
+            .is_none());
+
+        // Saturates.
+        timing2.set_pacer_exit(capture, capture + Duration::from_secs(100));
+        assert_eq!(timing2.last_left_pacer, u16::MAX);
+    }
+
+    #[test]
+    fn frame_marking_short() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(11, Extension::FrameMarking);
+        let ev = ExtensionValues {
+            frame_marking: Some(FrameMarking {
+                start_of_frame: true,
+                end_of_frame: true,
+                independent: false,
+                discardable: true,
+                layers: None,
+            }),
+            ..Default::default()
+        };
+
+        let mut buf = vec![0_u8; 8];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(&buf[..n], &[0xb0, 0b1101_0000]);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev.frame_marking, ev2.frame_marking);
+        assert_eq!(ev2.frame_marking.unwrap().tid(), 0);
+    }
+
+    #[test]
+    fn frame_marking_long() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(11, Extension::FrameMarking);
+        let ev = ExtensionValues {
+            frame_marking: Some(FrameMarking {
+                start_of_frame: true,
+                end_of_frame: false,
+                independent: true,
+                discardable: false,
+                layers: Some(FrameMarkingLayers {
+                    base_layer_sync: true,
+                    tid: 5,
+                    lid: 2,
+                    tl0_pic_idx: 200,
+                }),
+            }),
+            ..Default::default()
+        };
+
+        let mut buf = vec![0_u8; 8];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(&buf[..n], &[0xb2, 0b1010_1101, 2, 200]);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+        assert_eq!(ev.frame_marking, ev2.frame_marking);
+
+        // Only the 1 and 3 byte variants are valid.
+        let mut ev3 = ExtensionValues::default();
+        assert!(Extension::FrameMarking
+            .parse_value(&[0x80, 0], &mut ev3)
+            .is_none());
+        assert_eq!(ev3.frame_marking, None);
+    }
+
+    #[test]
+    fn frame_marking_filter_temporal_layers() {
+        use crate::rtp_::RtpHeader;
+
+        let mut exts = ExtensionMap::empty();
+        exts.set(11, Extension::FrameMarking);
+
+        // A synthetic L1T3 stream with the temporal layer pattern 0, 2, 1, 2, ...
+        let packets: Vec<Vec<u8>> = (0..16_u16)
+            .map(|i| {
+                let tid = [0, 2, 1, 2][i as usize % 4];
+                let header = RtpHeader {
+                    sequence_number: i,
+                    timestamp: i as u32 * 3000,
+                    ext_vals: ExtensionValues {
+                        frame_marking: Some(FrameMarking {
+                            start_of_frame: true,
+                            end_of_frame: true,
+                            independent: i == 0,
+                            discardable: tid == 2,
+                            layers: Some(FrameMarkingLayers {
+                                base_layer_sync: tid == 1,
+                                tid,
+                                lid: 0,
+                                tl0_pic_idx: (i / 4) as u8,
+                            }),
+                        }),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                let mut buf = vec![0; 100];
+                let n = header.write_to(&mut buf, &exts);
+                buf.truncate(n);
+                buf
+            })
+            .collect();
+
+        // The SFU only looks at the header to drop everything above TID 1.
+        let forwarded: Vec<_> = packets
+            .iter()
+            .filter_map(|p| RtpHeader::parse(p, &exts))
+            .filter(|h| {
+                h.ext_vals
+                    .frame_marking
+                    .map(|f| f.tid() <= 1)
+                    .unwrap_or(true)
+            })
+            .collect();
+
+        assert_eq!(forwarded.len(), 8);
+        for h in &forwarded {
+            let f = h.ext_vals.frame_marking.unwrap();
+            assert!(!f.discardable);
+            assert_eq!(h.sequence_number % 2, 0);
+        }
+    }
+
+    #[test]
+    fn playout_delay_minimal() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(2, Extension::PlayoutDelay);
+        let ev = ExtensionValues {
+            playout_delay: Some(Box::new((Duration::ZERO, Duration::ZERO))),
+            ..Default::default()
+        };
+
+        let mut buf = vec![0_u8; 8];
+        let n = exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+        assert_eq!(&buf[..n], &[0x22, 0, 0, 0]);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+
+        assert_eq!(
+            ev2.playout_delay.as_deref(),
+            Some(&(Duration::ZERO, Duration::ZERO))
+        );
+    }
+
+    #[test]
+    fn playout_delay_quantized() {
+        let mut exts = ExtensionMap::empty();
+        exts.set(2, Extension::PlayoutDelay);
+
+        let roundtrip = |min: u64, max: u64| {
+            let ev = ExtensionValues {
+                playout_delay: Some(Box::new((
+                    Duration::from_millis(min),
+                    Duration::from_millis(max),
+                ))),
+                ..Default::default()
+            };
+            let mut buf = vec![0_u8; 8];
+            exts.write_to(&mut buf[..], &ev, ExtensionsForm::OneByte);
+            let mut ev2 = ExtensionValues::default();
+            exts.parse(&buf, ExtensionsForm::OneByte, &mut ev2);
+            ev2.playout_delay
+                .map(|d| (d.0.as_millis() as u64, d.1.as_millis() as u64))
+        };
+
+        assert_eq!(roundtrip(14, 15), Some((10, 20)));
+        assert_eq!(roundtrip(0, 40_950), Some((0, 40_950)));
+        assert_eq!(roundtrip(40_950, 40_950), Some((40_950, 40_950)));
+
+        // min > max
+        assert_eq!(roundtrip(200, 100), None);
+        // max > 40.95s
+        assert_eq!(roundtrip(0, 41_000), None);
     }
 
     #[test]
@@ -1369,7 +2527,7 @@ mod test {
         // First apply e2
         e1.remap(&e2.iter_video().collect::<Vec<_>>());
 
-        println!("{:#?}", e1.0);
+        println!("{:#?}", e1.entries);
         assert_eq!(
             e1.iter_video().collect::<Vec<_>>(),
             vec![(12, &VideoOrientation), (14, &TransportSequenceNumber)]
@@ -1378,7 +2536,7 @@ mod test {
         // Now attempt e3
         e1.remap(&e3.iter_audio().collect::<Vec<_>>());
 
-        println!("{:#?}", e1.0);
+        println!("{:#?}", e1.entries);
         // At this point we should have not allowed the change, but remain as it was in first apply.
         assert_eq!(
             e1.iter_video().collect::<Vec<_>>(),
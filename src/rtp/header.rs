@@ -1,6 +1,7 @@
 #![allow(clippy::unusual_byte_groupings)]
 
-use super::ext::{ExtensionMap, ExtensionValues, ExtensionsForm};
+use super::ext::{ExtensionMap, ExtensionValues};
+use super::ExtensionsForm;
 use super::{Pt, SeqNo, Ssrc, MAX_BLANK_PADDING_PAYLOAD_SIZE};
 
 /// Parsed header from an RTP packet.
@@ -305,6 +306,9 @@ mk_extend!(extend_u8, u8, 8);
 mk_extend!(extend_u16, u16, 16);
 mk_extend!(extend_u32, u32, 32);
 
+// abs-send-time is 24 bits hosted in u32.
+mk_extend!(extend_u24, u32, 24);
+
 // we 'host' 7 bits in u8 but we ignore the most significant one
 mk_extend!(extend_u7, u8, 7);
 mk_extend!(extend_u15, u16, 15);
@@ -469,13 +473,13 @@ mod test {
         let buf3 = mk_header(47_002, 14_000, -44, false, &exts);
 
         let p1 = &[
-            144, 33, 183, 152, 0, 0, 39, 16, 0, 0, 0, 44, 0xBE, 0xDE, 0, 1, 48, 170, 0, 0,
+            144, 33, 183, 152, 0, 0, 39, 16, 0, 0, 0, 44, 0xBE, 0xDE, 0, 1, 48, 42, 0, 0,
         ];
         let p2 = &[
-            144, 161, 183, 153, 0, 0, 46, 224, 0, 0, 0, 44, 0xBE, 0xDE, 0, 1, 48, 171, 0, 0,
+            144, 161, 183, 153, 0, 0, 46, 224, 0, 0, 0, 44, 0xBE, 0xDE, 0, 1, 48, 43, 0, 0,
         ];
         let p3 = &[
-            144, 33, 183, 154, 0, 0, 54, 176, 0, 0, 0, 44, 0xBE, 0xDE, 0, 1, 48, 172, 0, 0,
+            144, 33, 183, 154, 0, 0, 54, 176, 0, 0, 0, 44, 0xBE, 0xDE, 0, 1, 48, 44, 0, 0,
         ];
 
         assert_eq!(&buf1, p1);
@@ -515,13 +519,13 @@ mod test {
         let buf3 = mk_header(47_002, 14_000, -44, false, &exts);
 
         let p1 = &[
-            144, 33, 183, 152, 0, 0, 39, 16, 0, 0, 0, 44, 0x10, 0x00, 0, 1, 15, 1, 170, 0,
+            144, 33, 183, 152, 0, 0, 39, 16, 0, 0, 0, 44, 0x10, 0x00, 0, 1, 15, 1, 42, 0,
         ];
         let p2 = &[
-            144, 161, 183, 153, 0, 0, 46, 224, 0, 0, 0, 44, 0x10, 0x00, 0, 1, 15, 1, 171, 0,
+            144, 161, 183, 153, 0, 0, 46, 224, 0, 0, 0, 44, 0x10, 0x00, 0, 1, 15, 1, 43, 0,
         ];
         let p3 = &[
-            144, 33, 183, 154, 0, 0, 54, 176, 0, 0, 0, 44, 0x10, 0x00, 0, 1, 15, 1, 172, 0,
+            144, 33, 183, 154, 0, 0, 54, 176, 0, 0, 0, 44, 0x10, 0x00, 0, 1, 15, 1, 44, 0,
         ];
 
         assert_eq!(&buf1, p1);
@@ -551,7 +555,7 @@ mod test {
         ];
 
         let h1 = RtpHeader::parse(&hb1, &exts).unwrap();
-        let abs1 = already_happened() + Duration::from_micros(63531654);
+        let abs1 = already_happened() + Duration::from_nanos(63_531_654_358);
 
         assert_eq!(
             h1,
@@ -577,7 +581,7 @@ mod test {
         );
 
         let h2 = RtpHeader::parse(&hb2, &exts).unwrap();
-        let abs2 = already_happened() + Duration::from_micros(63631652);
+        let abs2 = already_happened() + Duration::from_nanos(63_631_652_832);
 
         assert_eq!(
             h2,
@@ -603,7 +607,7 @@ mod test {
         );
 
         let h3 = RtpHeader::parse(&hb3, &exts).unwrap();
-        let abs3 = already_happened() + Duration::from_micros(63531654);
+        let abs3 = already_happened() + Duration::from_nanos(63_531_654_358);
 
         assert_eq!(
             h3,
@@ -651,7 +655,7 @@ mod test {
         ];
 
         let h1 = RtpHeader::parse(&hb1, &exts).unwrap();
-        let abs1 = already_happened() + Duration::from_micros(63531654);
+        let abs1 = already_happened() + Duration::from_nanos(63_531_654_358);
 
         assert_eq!(
             h1,
@@ -677,7 +681,7 @@ mod test {
         );
 
         let h2 = RtpHeader::parse(&hb2, &exts).unwrap();
-        let abs2 = already_happened() + Duration::from_micros(63631652);
+        let abs2 = already_happened() + Duration::from_nanos(63_631_652_832);
 
         assert_eq!(
             h2,
@@ -703,7 +707,7 @@ mod test {
         );
 
         let h3 = RtpHeader::parse(&hb3, &exts).unwrap();
-        let abs3 = already_happened() + Duration::from_micros(63531654);
+        let abs3 = already_happened() + Duration::from_nanos(63_531_654_358);
 
         assert_eq!(
             h3,
@@ -751,4 +755,23 @@ mod test {
         assert_eq!(Ok(vec![]), truncate(vec![1]));
         assert_eq!(Ok(vec![]), truncate(vec![]));
     }
+
+    #[test]
+    fn unpad_pathological_counts() {
+        // Some stacks set the P bit with a count of 0. That can't be padding since the
+        // count includes itself, so the payload is left as is.
+        let mut payload = vec![1, 2, 3, 0];
+        assert!(RtpHeader::unpad_payload(&mut payload));
+        assert_eq!(payload, [1, 2, 3, 0]);
+
+        // A count of 1 is only the count byte itself.
+        let mut payload = vec![1, 2, 3, 1];
+        assert!(RtpHeader::unpad_payload(&mut payload));
+        assert_eq!(payload, [1, 2, 3]);
+
+        // Padding-only packet.
+        let mut payload = vec![0, 0, 3];
+        assert!(RtpHeader::unpad_payload(&mut payload));
+        assert!(payload.is_empty());
+    }
 }
@@ -1,9 +1,11 @@
+use std::collections::HashSet;
 use std::fmt;
 use std::ops::Deref;
 use std::str::from_utf8;
 
 use serde::{Deserialize, Serialize};
 
+use crate::crypto::random_bytes;
 use crate::io::Id;
 use crate::util::NonCryptographicRng;
 
@@ -77,6 +79,9 @@ macro_rules! str_id {
 
 macro_rules! num_id {
     ($id:ident, $t:tt) => {
+        num_id!($id, $t, "{}");
+    };
+    ($id:ident, $t:tt, $display:literal) => {
         impl $id {
             /// Creates a new random id.
             pub fn new() -> Self {
@@ -100,7 +105,7 @@ macro_rules! num_id {
 
         impl fmt::Display for $id {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                write!(f, "{}", self.0)
+                write!(f, $display, self.0)
             }
         }
     };
@@ -134,9 +139,147 @@ str_id!(Rid, "Rid", 8, 3);
 /// Uniquely identifies a sending source of data. Each video/audio stream would be associated
 /// with at least one synchronization source. Multiple sources for the same stream happens
 /// for RTX (resend) and simulcast.
+///
+/// Displays as zero-padded hex, like Wireshark.
+///
+/// ```
+/// # use str0m::rtp::Ssrc;
+/// let ssrc: Ssrc = 5678.into();
+/// assert_eq!(ssrc.to_string(), "0x0000162e");
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Ssrc(u32);
-num_id!(Ssrc, u32);
+num_id!(Ssrc, u32, "{:#010x}");
+
+impl Ssrc {
+    /// Creates a new random SSRC using a cryptographically secure random generator.
+    ///
+    /// Without the openssl feature, the SSRC is from a non-cryptographic generator.
+    ///
+    /// RFC 3550 requires SSRCs to be chosen randomly. To also avoid collisions with SSRCs
+    /// already in use, see [`SsrcAllocator`].
+    pub fn new_random() -> Ssrc {
+        let mut buf = [0; 4];
+        random_bytes(&mut buf);
+        Ssrc(u32::from_be_bytes(buf))
+    }
+}
+
+/// Allocator of SSRCs that never hands out an SSRC already in use.
+///
+/// Keeps a set of SSRCs in use, both allocated ones and any reserved, such as remote SSRCs
+/// that have been seen. New SSRCs are drawn with [`Ssrc::new_random`] until one is free.
+/// SSRC 0 is never allocated.
+///
+/// ```
+/// # use str0m::rtp::{Ssrc, SsrcAllocator};
+/// let mut alloc = SsrcAllocator::new();
+///
+/// // A remote SSRC we have seen.
+/// alloc.reserve(42.into());
+///
+/// let (ssrc, rtx) = alloc.allocate_pair();
+/// assert_ne!(ssrc, rtx);
+/// assert!(alloc.is_in_use(ssrc));
+///
+/// alloc.release(ssrc);
+/// assert!(!alloc.is_in_use(ssrc));
+/// ```
+#[derive(Default)]
+pub struct SsrcAllocator {
+    in_use: HashSet<Ssrc>,
+    /// Random values drawn in bulk, since each call to the secure generator is costly.
+    random: Vec<u32>,
+}
+
+/// Number of random SSRCs to draw in one go.
+const RANDOM_BATCH: usize = 256;
+
+impl SsrcAllocator {
+    /// Creates a new allocator with no SSRCs in use.
+    pub fn new() -> Self {
+        SsrcAllocator::default()
+    }
+
+    /// Creates a new allocator with a set of SSRCs already in use.
+    pub fn with_in_use(in_use: impl IntoIterator<Item = Ssrc>) -> Self {
+        SsrcAllocator {
+            in_use: in_use.into_iter().collect(),
+            random: vec![],
+        }
+    }
+
+    /// Allocate a new random SSRC that is not in use.
+    ///
+    /// The SSRC is in use until [`SsrcAllocator::release`].
+    pub fn allocate(&mut self) -> Ssrc {
+        loop {
+            let ssrc = self.next_random();
+
+            if *ssrc == 0 {
+                continue;
+            }
+
+            if self.in_use.insert(ssrc) {
+                break ssrc;
+            }
+        }
+    }
+
+    fn next_random(&mut self) -> Ssrc {
+        if self.random.is_empty() {
+            let mut buf = [0; RANDOM_BATCH * 4];
+            random_bytes(&mut buf);
+
+            let values = buf
+                .chunks_exact(4)
+                .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]));
+            self.random.extend(values);
+        }
+
+        Ssrc(self.random.pop().expect("random values after refill"))
+    }
+
+    /// Allocate a main and RTX SSRC pair.
+    pub fn allocate_pair(&mut self) -> (Ssrc, Ssrc) {
+        (self.allocate(), self.allocate())
+    }
+
+    /// Mark an SSRC as in use, such as a remote SSRC.
+    ///
+    /// Returns `false` if the SSRC was already in use.
+    pub fn reserve(&mut self, ssrc: Ssrc) -> bool {
+        self.in_use.insert(ssrc)
+    }
+
+    /// Release an SSRC for use again.
+    pub fn release(&mut self, ssrc: Ssrc) {
+        self.in_use.remove(&ssrc);
+    }
+
+    /// Whether the SSRC is in use.
+    pub fn is_in_use(&self, ssrc: Ssrc) -> bool {
+        self.in_use.contains(&ssrc)
+    }
+
+    /// Number of SSRCs in use.
+    pub fn len(&self) -> usize {
+        self.in_use.len()
+    }
+
+    /// Whether no SSRCs are in use.
+    pub fn is_empty(&self) -> bool {
+        self.in_use.is_empty()
+    }
+}
+
+impl fmt::Debug for SsrcAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SsrcAllocator")
+            .field("in_use", &self.in_use.len())
+            .finish()
+    }
+}
 
 /// Paylad type.
 ///
@@ -248,3 +391,73 @@ impl Pt {
         Pt(v)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ssrc_display_hex() {
+        assert_eq!(Ssrc::from(0).to_string(), "0x00000000");
+        assert_eq!(Ssrc::from(5678).to_string(), "0x0000162e");
+        assert_eq!(Ssrc::from(u32::MAX).to_string(), "0xffffffff");
+
+        // Other ids are still decimal.
+        assert_eq!(Pt::from(96).to_string(), "96");
+    }
+
+    #[test]
+    fn allocate_no_duplicates() {
+        let mut alloc = SsrcAllocator::new();
+        let mut seen = HashSet::new();
+
+        for _ in 0..5_000 {
+            let ssrc = alloc.allocate();
+            assert_ne!(*ssrc, 0);
+            assert!(seen.insert(ssrc), "duplicate SSRC {}", ssrc);
+        }
+
+        assert_eq!(alloc.len(), 5_000);
+    }
+
+    #[test]
+    fn allocate_excludes_in_use() {
+        allocate_excluding(5_000);
+    }
+
+    // Slow, run with --ignored.
+    #[test]
+    #[ignore]
+    fn allocate_excludes_in_use_collisions() {
+        // With this many SSRCs in use, random draws hit the in use set ~200 times.
+        allocate_excluding(1_000_000);
+    }
+
+    fn allocate_excluding(n: usize) {
+        let mut in_use = SsrcAllocator::new();
+        let in_use: HashSet<Ssrc> = (0..n).map(|_| in_use.allocate()).collect();
+        let mut alloc = SsrcAllocator::with_in_use(in_use.iter().copied());
+
+        for _ in 0..n {
+            let ssrc = alloc.allocate();
+            assert!(!in_use.contains(&ssrc), "allocated SSRC in use {}", ssrc);
+        }
+    }
+
+    #[test]
+    fn reserve_and_release() {
+        let mut alloc = SsrcAllocator::new();
+
+        assert!(alloc.reserve(17.into()));
+        assert!(!alloc.reserve(17.into()));
+        assert!(alloc.is_in_use(17.into()));
+
+        alloc.release(17.into());
+        assert!(!alloc.is_in_use(17.into()));
+        assert!(alloc.is_empty());
+
+        let (ssrc, rtx) = alloc.allocate_pair();
+        assert_ne!(ssrc, rtx);
+        assert_eq!(alloc.len(), 2);
+    }
+}
@@ -3,11 +3,22 @@ use std::io;
 use thiserror::Error;
 
 mod id;
-pub use id::{Mid, Pt, Rid, SeqNo, SessionId, Ssrc};
+pub use id::{Mid, Pt, Rid, SeqNo, SessionId, Ssrc, SsrcAllocator};
 
 mod ext;
+pub(crate) use ext::ExtensionsForm;
+pub use ext::{AbsCaptureTime, AbsSendTimeUnwrapper, UserExtensionValues};
 pub use ext::{Extension, ExtensionMap, ExtensionSerializer, ExtensionValues};
-pub use ext::{UserExtensionValues, VideoOrientation};
+pub use ext::{FrameMarking, FrameMarkingLayers, VideoOrientation, VideoTiming};
+
+mod color_space;
+pub use color_space::{ChromaSiting, Chromaticity, ColorRange, ColorSpace, HdrMetadata};
+pub use color_space::{ColorPrimaries, MatrixCoefficients, TransferCharacteristics};
+
+mod dependency_descriptor;
+pub(crate) use dependency_descriptor::DependencyDescriptorReader;
+pub use dependency_descriptor::{DependencyDescriptor, Dti, FrameDependency};
+pub use dependency_descriptor::{FrameDependencyStructure, RenderResolution};
 
 mod dir;
 pub use dir::Direction;
@@ -20,9 +31,14 @@ mod header;
 pub use header::RtpHeader;
 pub(crate) use header::{extend_u15, extend_u16, extend_u32, extend_u7, extend_u8};
 
+mod replay;
+
 mod srtp;
 pub(crate) use srtp::SrtpContext;
-pub(crate) use srtp::{SRTCP_OVERHEAD, SRTP_BLOCK_SIZE, SRTP_OVERHEAD};
+pub(crate) use srtp::{
+    DEFAULT_MAX_SSRCS, DEFAULT_REPLAY_WINDOW, DEFAULT_SSRC_IDLE, MAX_SRTP_KEY_LIFETIME,
+    SRTCP_OVERHEAD, SRTP_BLOCK_SIZE, SRTP_OVERHEAD,
+};
 
 mod rtcp;
 pub use rtcp::*;
@@ -50,6 +66,18 @@ pub enum RtpError {
     /// Failed to parse RTP header.
     #[error("Failed to parse RTP header")]
     ParseHeader,
+
+    /// All 2^31 SRTCP indexes are used, a rekey is required to send more SRTCP.
+    #[error("SRTCP index exhausted")]
+    SrtcpIndexExhausted,
+
+    /// No room after the packet for the SRTP trailer when protecting in place.
+    #[error("No room for SRTP trailer")]
+    SrtpNoRoom,
+
+    /// Raw RTCP written by the user isn't a single RTCP packet that fits a datagram.
+    #[error("Invalid raw RTCP: {0}")]
+    InvalidRawRtcp(&'static str),
 }
 
 impl From<CryptoError> for RtpError {
@@ -58,6 +86,10 @@ impl From<CryptoError> for RtpError {
             #[cfg(feature = "openssl")]
             CryptoError::OpenSsl(e) => RtpError::OpenSsl(e),
             CryptoError::Io(e) => RtpError::Io(e),
+            // Only arise in DTLS.
+            e @ (CryptoError::KeyMismatch | CryptoError::FingerprintMismatch) => {
+                RtpError::Io(io::Error::new(io::ErrorKind::Other, e))
+            }
         }
     }
 }
@@ -0,0 +1,359 @@
+/// Replay protection for SRTP/SRTCP as described in RFC 3711 section 3.3.2.
+///
+/// Keeps a sliding window of indexes (extended sequence number for SRTP, SRTCP index
+/// for SRTCP) seen below the highest index received. Anything already seen, or older
+/// than the window, is a replay.
+///
+/// An index jumping further ahead than the window size does not move the window,
+/// much like the probation in the receive register. It is held until the next index
+/// in sequence confirms the jump. This way one wild packet doesn't make us drop
+/// everything that follows it. The held indexes are remembered to catch replays of them.
+///
+/// At most [`MAX_AHEAD`] indexes are held, further ones are dropped until the window moves.
+#[derive(Debug, Clone)]
+pub(crate) struct ReplayWindow {
+    /// Highest index authenticated so far.
+    max: Option<u64>,
+    /// Indexes that jumped ahead of the window, awaiting confirmation.
+    ahead: Vec<u64>,
+    /// One bit per index in the window, addressed by `index % size`.
+    bits: Vec<u64>,
+}
+
+/// The smallest window allowed by RFC 3711.
+pub(crate) const MIN_REPLAY_WINDOW: usize = 64;
+
+/// How many indexes ahead of the window we hold at most.
+const MAX_AHEAD: usize = 8;
+
+impl ReplayWindow {
+    /// Create a new window for `size` indexes.
+    ///
+    /// The size is rounded up to a multiple of 64, and is never smaller than 64.
+    pub fn new(size: usize) -> Self {
+        let words = (size.max(MIN_REPLAY_WINDOW) + 63) / 64;
+
+        ReplayWindow {
+            max: None,
+            ahead: vec![],
+            bits: vec![0; words],
+        }
+    }
+
+    /// Create a window where everything up to and including `index` counts as received.
+    ///
+    /// Used when state for an SSRC was dropped and comes back, only newer packets are let in.
+    pub fn new_received_up_to(size: usize, index: u64) -> Self {
+        let mut w = ReplayWindow::new(size);
+        w.max = Some(index);
+        w.bits.fill(u64::MAX);
+        w
+    }
+
+    /// Highest index authenticated so far.
+    pub fn max(&self) -> Option<u64> {
+        self.max
+    }
+
+    fn size(&self) -> u64 {
+        self.bits.len() as u64 * 64
+    }
+
+    /// Tests whether `index` is acceptable, without updating the window.
+    ///
+    /// This is done before any decryption/authentication work.
+    pub fn check(&self, index: u64) -> bool {
+        let Some(max) = self.max else {
+            return true;
+        };
+
+        if self.ahead.contains(&index) {
+            return false;
+        }
+
+        if index > max {
+            return index - max < self.size()
+                || self.ahead.contains(&(index - 1))
+                || self.ahead.len() < MAX_AHEAD;
+        }
+
+        if max - index >= self.size() {
+            // Too old to tell, which per RFC means we must drop it.
+            return false;
+        }
+
+        !self.is_set(index)
+    }
+
+    /// Mark `index` as received.
+    ///
+    /// Must only be done once the packet is authenticated, and after [`ReplayWindow::check`].
+    pub fn update(&mut self, index: u64) {
+        let Some(max) = self.max else {
+            self.max = Some(index);
+            self.set(index);
+            return;
+        };
+
+        if index > max {
+            let delta = index - max;
+
+            if delta >= self.size() {
+                if !self.ahead.contains(&(index - 1)) {
+                    self.ahead.push(index);
+                    return;
+                }
+
+                self.bits.fill(0);
+            } else {
+                self.clear_range(max + 1, index);
+            }
+
+            self.max = Some(index);
+
+            // Held indexes that are now in the window are remembered by the bits.
+            // Older ones are rejected as too old anyway.
+            let size = self.size();
+            let ahead = std::mem::take(&mut self.ahead);
+            for i in ahead {
+                if i > index {
+                    self.ahead.push(i);
+                } else if index - i < size {
+                    self.set(i);
+                }
+            }
+        }
+
+        self.set(index);
+    }
+
+    fn is_set(&self, index: u64) -> bool {
+        let (word, bit) = self.pos(index);
+        self.bits[word] & (1 << bit) > 0
+    }
+
+    fn set(&mut self, index: u64) {
+        let (word, bit) = self.pos(index);
+        self.bits[word] |= 1 << bit;
+    }
+
+    /// Clear the bits for `from..to`, a word at a time.
+    fn clear_range(&mut self, mut from: u64, to: u64) {
+        while from < to {
+            let (word, bit) = self.pos(from);
+            let n = (64 - bit).min(to - from);
+            let mask = if n == 64 {
+                u64::MAX
+            } else {
+                ((1 << n) - 1) << bit
+            };
+            self.bits[word] &= !mask;
+            from += n;
+        }
+    }
+
+    fn pos(&self, index: u64) -> (usize, u64) {
+        let n = index % self.size();
+        ((n / 64) as usize, n % 64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn first_packet_accepted() {
+        let w = ReplayWindow::new(64);
+        assert!(w.check(0));
+        assert!(w.check(12345));
+    }
+
+    #[test]
+    fn duplicate() {
+        let mut w = ReplayWindow::new(64);
+
+        for i in 100..110 {
+            assert!(w.check(i));
+            w.update(i);
+        }
+
+        for i in 100..110 {
+            assert!(!w.check(i), "duplicate {} accepted", i);
+        }
+
+        assert!(w.check(110));
+    }
+
+    #[test]
+    fn ancient() {
+        let mut w = ReplayWindow::new(64);
+
+        w.update(1000);
+
+        // Inside the window, never seen.
+        assert!(w.check(1000 - 63));
+        // Outside the window.
+        assert!(!w.check(1000 - 64));
+        assert!(!w.check(0));
+    }
+
+    #[test]
+    fn out_of_order_within_window() {
+        let mut w = ReplayWindow::new(128);
+
+        w.update(10);
+        w.update(20);
+
+        // Never seen, in the window behind max.
+        for i in 11..20 {
+            assert!(w.check(i));
+        }
+        w.update(15);
+        assert!(!w.check(15));
+        assert!(w.check(16));
+    }
+
+    #[test]
+    fn future_within_window() {
+        let mut w = ReplayWindow::new(64);
+
+        w.update(10);
+        assert!(w.check(50));
+        w.update(50);
+
+        // Bits for the skipped indexes must not be stale from before.
+        for i in 11..50 {
+            assert!(w.check(i), "{} should be accepted", i);
+        }
+        assert!(!w.check(10));
+        assert!(!w.check(50));
+    }
+
+    #[test]
+    fn future_across_words() {
+        let mut w = ReplayWindow::new(256);
+
+        for i in 0..256 {
+            w.update(i);
+        }
+
+        // Wraps around, and clears three whole words and part of a fourth.
+        w.update(255 + 200);
+
+        for i in 256..(255 + 200) {
+            assert!(w.check(i), "{} should be accepted", i);
+        }
+        for i in (255 + 200 - 255)..256 {
+            assert!(!w.check(i), "{} should be rejected", i);
+        }
+    }
+
+    #[test]
+    fn large_jump_clears_window() {
+        let mut w = ReplayWindow::new(64);
+
+        for i in 0..64 {
+            w.update(i);
+        }
+
+        // The jump is confirmed by the next index in sequence.
+        w.update(64 + 1000);
+        w.update(64 + 1001);
+
+        assert!(!w.check(64 + 1000));
+        assert!(!w.check(64 + 1001));
+
+        // Wrapping onto the same bit positions as old packets must not reject them.
+        for i in (64 + 1001 - 63)..(64 + 1000) {
+            assert!(w.check(i), "{} should be accepted", i);
+        }
+    }
+
+    #[test]
+    fn wild_jump_on_probation() {
+        let mut w = ReplayWindow::new(64);
+
+        for i in 0..10 {
+            w.update(i);
+        }
+
+        // A single wild index is accepted once, but doesn't move the window.
+        assert!(w.check(5000));
+        w.update(5000);
+        assert!(!w.check(5000));
+
+        assert!(w.check(10));
+        w.update(10);
+        assert!(!w.check(9));
+    }
+
+    #[test]
+    fn wild_jumps_are_remembered() {
+        let mut w = ReplayWindow::new(64);
+
+        for i in 0..10 {
+            w.update(i);
+        }
+
+        w.update(5000);
+        w.update(9000);
+
+        // The first wild index is still a replay after a second one arrived.
+        assert!(!w.check(5000));
+        assert!(!w.check(9000));
+
+        // Confirming the first jump moves the window, the second is still held.
+        w.update(5001);
+        assert_eq!(w.max(), Some(5001));
+        assert!(!w.check(5000));
+        assert!(!w.check(9000));
+        assert!(w.check(4999));
+
+        w.update(9001);
+        assert!(!w.check(9000));
+    }
+
+    #[test]
+    fn wild_jumps_are_bounded() {
+        let mut w = ReplayWindow::new(64);
+        w.update(0);
+
+        for i in 0..MAX_AHEAD as u64 {
+            assert!(w.check(1000 * (i + 1)));
+            w.update(1000 * (i + 1));
+        }
+
+        // Full, only confirmations of the held ones get in.
+        assert!(!w.check(100_000));
+        assert!(w.check(1001));
+        w.update(1001);
+
+        // The confirmed one no longer takes up room.
+        assert!(w.check(100_000));
+    }
+
+    #[test]
+    fn received_up_to() {
+        let mut w = ReplayWindow::new_received_up_to(64, 1000);
+
+        assert_eq!(w.max(), Some(1000));
+        assert!(!w.check(1000));
+        assert!(!w.check(990));
+        assert!(!w.check(10));
+        assert!(w.check(1001));
+
+        w.update(1005);
+        for i in 1001..1005 {
+            assert!(w.check(i), "{} should be accepted", i);
+        }
+        assert!(!w.check(1000));
+    }
+
+    #[test]
+    fn size_is_rounded() {
+        assert_eq!(ReplayWindow::new(0).size(), 64);
+        assert_eq!(ReplayWindow::new(65).size(), 128);
+        assert_eq!(ReplayWindow::new(1024).size(), 1024);
+    }
+}
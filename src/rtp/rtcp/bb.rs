@@ -1,10 +1,15 @@
-use super::{FeedbackMessageType, ReportList, RtcpHeader, RtcpPacket, RtcpType, Ssrc};
+use std::str::from_utf8;
+
+use super::{pad_bytes_to_word, FeedbackMessageType, ReportList, RtcpHeader, RtcpPacket};
+use super::{RtcpType, Ssrc};
 
 /// RTCP packet BY
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Goodbye {
     /// The SSRC that are no longer in use.
     pub reports: ReportList<Ssrc>,
+    /// Optional reason for leaving, at most 255 bytes.
+    pub reason: Option<String>,
 }
 
 impl RtcpPacket for Goodbye {
@@ -17,18 +22,32 @@ impl RtcpPacket for Goodbye {
     }
 
     fn length_words(&self) -> usize {
-        // each ssrc is one word
-        1 + self.reports.len()
+        // each ssrc is one word, the reason is a length byte and the text, padded.
+        let reason = self
+            .reason
+            .as_ref()
+            .map(|r| pad_bytes_to_word(1 + r.len()) / 4)
+            .unwrap_or(0);
+        1 + self.reports.len() + reason
     }
 
     fn write_to(&self, buf: &mut [u8]) -> usize {
+        let len = self.length_words() * 4;
         self.header().write_to(&mut buf[..4]);
-        let buf = &mut buf[4..];
+        let buf = &mut buf[4..len];
         for (i, s) in self.reports.iter().enumerate() {
             buf[i * 4..(i + 1) * 4].copy_from_slice(&s.to_be_bytes());
         }
 
-        self.length_words() * 4
+        if let Some(reason) = &self.reason {
+            let buf = &mut buf[self.reports.len() * 4..];
+            let bytes = reason.as_bytes();
+            buf[0] = bytes.len() as u8;
+            buf[1..1 + bytes.len()].copy_from_slice(bytes);
+            buf[1 + bytes.len()..].fill(0);
+        }
+
+        len
     }
 }
 
@@ -55,8 +74,12 @@ impl<'a> TryFrom<(usize, &'a [u8])> for Goodbye {
             buf = &buf[4..];
         }
 
-        // TODO parse the optional reason string here.
+        // Optional reason, a length byte followed by the text.
+        let reason = buf.split_first().and_then(|(len, rest)| {
+            let text = rest.get(..*len as usize).filter(|t| !t.is_empty())?;
+            from_utf8(text).ok().map(|s| s.to_string())
+        });
 
-        Ok(Goodbye { reports })
+        Ok(Goodbye { reports, reason })
     }
 }
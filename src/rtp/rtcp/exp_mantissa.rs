@@ -0,0 +1,136 @@
+//! Bitrates as a 6 bit exponent and a mantissa, as used by REMB and TMMBR/TMMBN.
+//!
+//! REMB uses an 18 bit mantissa, TMMBR/TMMBN 17 bits. The value is `mantissa * 2^exp`.
+
+/// Largest exponent that fits in 6 bits.
+const MAX_EXP: u8 = 63;
+
+/// Encode `bits` into an exponent and a mantissa of `mantissa_bits` bits.
+///
+/// The mantissa is rounded to the nearest value, not truncated. Truncating lowers the
+/// bitrate a little every time it is passed on and re-encoded.
+///
+/// The smallest exponent possible is used, which makes re-encoding a decoded value give
+/// back the same exponent and mantissa.
+pub fn encode_exp_mantissa(bits: u64, mantissa_bits: u8) -> (u8, u32) {
+    assert!(mantissa_bits > 0 && mantissa_bits < 32);
+
+    let max_mantissa = (1_u64 << mantissa_bits) - 1;
+
+    // u128 to not overflow when rounding values close to u64::MAX.
+    let bits = bits as u128;
+
+    let mut exp = 0;
+    loop {
+        let mantissa = if exp == 0 {
+            bits
+        } else {
+            (bits + (1 << (exp - 1))) >> exp
+        };
+
+        if mantissa <= max_mantissa as u128 || exp == MAX_EXP {
+            // Saturate at the format maximum.
+            let mantissa = (mantissa as u64).min(max_mantissa);
+            return (exp, mantissa as u32);
+        }
+
+        exp += 1;
+    }
+}
+
+/// Decode an exponent and mantissa into a bitrate in bits per second.
+///
+/// Values too large for a u64 saturate at `u64::MAX`.
+pub fn decode_exp_mantissa(exp: u8, mantissa: u32) -> u64 {
+    let exp = exp.min(MAX_EXP);
+    let mantissa = mantissa as u64;
+
+    if mantissa == 0 {
+        return 0;
+    }
+
+    if mantissa.leading_zeros() < exp as u32 {
+        return u64::MAX;
+    }
+
+    mantissa << exp
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn small_values_are_exact() {
+        for bits in [0, 1, 1000, (1 << 17) - 1] {
+            assert_eq!(encode_exp_mantissa(bits, 17), (0, bits as u32));
+            assert_eq!(decode_exp_mantissa(0, bits as u32), bits);
+        }
+    }
+
+    #[test]
+    fn rounds_to_nearest() {
+        // 2^18 + 3 needs one bit of exponent, 3/2 rounds up.
+        let bits = (1 << 18) + 3;
+        let (exp, mantissa) = encode_exp_mantissa(bits, 18);
+        assert_eq!((exp, mantissa), (1, (1 << 17) + 2));
+        assert_eq!(decode_exp_mantissa(exp, mantissa), bits + 1);
+
+        // 2^19 + 1 needs two bits of exponent, 1/4 rounds down.
+        let (exp, mantissa) = encode_exp_mantissa((1 << 19) + 1, 18);
+        assert_eq!((exp, mantissa), (2, 1 << 17));
+        assert_eq!(decode_exp_mantissa(exp, mantissa), 1 << 19);
+
+        // Rounding up overflowing the mantissa moves to the next exponent.
+        let (exp, mantissa) = encode_exp_mantissa((1 << 19) - 1, 18);
+        assert_eq!((exp, mantissa), (2, 1 << 17));
+    }
+
+    #[test]
+    fn saturates() {
+        // Rounds up to 2^64, which doesn't fit.
+        let (exp, mantissa) = encode_exp_mantissa(u64::MAX, 18);
+        assert_eq!((exp, mantissa), (47, 1 << 17));
+        assert_eq!(decode_exp_mantissa(exp, mantissa), u64::MAX);
+
+        assert_eq!(decode_exp_mantissa(63, (1 << 18) - 1), u64::MAX);
+        assert_eq!(decode_exp_mantissa(63, 0), 0);
+    }
+
+    #[test]
+    fn encode_decode_property() {
+        let mut rng = fastrand::Rng::with_seed(42);
+
+        for mantissa_bits in [17, 18] {
+            for _ in 0..100_000 {
+                // Spread over all magnitudes, not just the large ones.
+                let bits = rng.u64(..) >> rng.u32(0..64);
+
+                let (exp, mantissa) = encode_exp_mantissa(bits, mantissa_bits);
+                assert!(mantissa < 1 << mantissa_bits);
+
+                let decoded = decode_exp_mantissa(exp, mantissa);
+
+                // Never more than one mantissa step from the original. Saturation at
+                // u64::MAX is within this, since the step is 2^exp.
+                let step = 1_u64 << exp;
+                assert!(
+                    decoded.abs_diff(bits) <= step,
+                    "{} -> ({}, {}) -> {}",
+                    bits,
+                    exp,
+                    mantissa,
+                    decoded
+                );
+
+                // Re-encoding is a fixed point.
+                assert_eq!(
+                    encode_exp_mantissa(decoded, mantissa_bits),
+                    (exp, mantissa),
+                    "{}",
+                    bits
+                );
+            }
+        }
+    }
+}
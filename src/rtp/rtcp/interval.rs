@@ -0,0 +1,216 @@
+use std::time::Duration;
+
+use crate::rtp_::{Bitrate, DataSize};
+
+/// Share of the session bandwidth for RTCP, RFC 3550 section 6.2.
+const DEFAULT_FRACTION: f64 = 0.05;
+
+/// RFC 3550 minimum interval.
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Keeps streams from timing out at the remote, even with very little bandwidth.
+const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Share of the RTCP bandwidth for senders, when they are few.
+const SENDER_FRACTION: f64 = 0.25;
+
+/// Bandwidth and interval bounds for the regular RTCP reports.
+///
+/// The interval between reports is computed as in [RFC 3550 section 6.3][1], from the
+/// bandwidth available for RTCP, the average size of the compound packets and the number
+/// of SSRCs in the session. The result is kept within the minimum and maximum interval.
+/// There is no randomization of the interval, with only two endpoints there are no
+/// synchronized reports to avoid.
+///
+/// Set with [`RtcConfig::set_rtcp_config`][crate::RtcConfig::set_rtcp_config].
+///
+/// [1]: https://www.rfc-editor.org/rfc/rfc3550#section-6.3
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RtcpConfig {
+    session_bitrate: Bitrate,
+    fraction: f64,
+    min_interval: Duration,
+    max_interval: Duration,
+    reduced_min_interval: Option<Duration>,
+}
+
+impl RtcpConfig {
+    /// RTCP for a session of `session_bitrate`, the total for all media in both directions.
+    ///
+    /// Defaults to 5% for RTCP, and reports between 5 and 30 seconds apart.
+    pub fn new(session_bitrate: Bitrate) -> Self {
+        RtcpConfig {
+            session_bitrate,
+            fraction: DEFAULT_FRACTION,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            max_interval: DEFAULT_MAX_INTERVAL,
+            reduced_min_interval: None,
+        }
+    }
+
+    /// Sets the share of the session bandwidth used for RTCP.
+    ///
+    /// Panics unless the fraction is above 0 and at most 1.
+    pub fn set_fraction(mut self, fraction: f64) -> Self {
+        assert!(
+            fraction > 0.0 && fraction <= 1.0,
+            "RTCP fraction must be in (0, 1]"
+        );
+        self.fraction = fraction;
+        self
+    }
+
+    /// Sets the minimum and maximum interval between reports.
+    ///
+    /// Panics if min is greater than max, or less than the reduced minimum.
+    pub fn set_interval_bounds(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "RTCP min interval must not exceed max");
+        assert!(
+            self.reduced_min_interval.map_or(true, |r| r <= min),
+            "RTCP reduced min interval must not exceed min"
+        );
+        self.min_interval = min;
+        self.max_interval = max;
+        self
+    }
+
+    /// Sets a reduced minimum interval, used instead of the minimum interval.
+    ///
+    /// For feedback profiles (AVPF) where timely reports matter more than the bandwidth.
+    /// [RFC 3550 section 6.2][1] suggests 360 divided by the session bandwidth in kbit/s,
+    /// in seconds.
+    ///
+    /// Panics if the reduced minimum is greater than the minimum interval.
+    ///
+    /// [1]: https://www.rfc-editor.org/rfc/rfc3550#section-6.2
+    pub fn set_reduced_min_interval(mut self, reduced: Option<Duration>) -> Self {
+        assert!(
+            reduced.map_or(true, |r| r <= self.min_interval),
+            "RTCP reduced min interval must not exceed min"
+        );
+        self.reduced_min_interval = reduced;
+        self
+    }
+
+    /// The total bandwidth of the session.
+    pub fn session_bitrate(&self) -> Bitrate {
+        self.session_bitrate
+    }
+
+    /// The share of the session bandwidth used for RTCP.
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+
+    /// The bandwidth available for RTCP.
+    pub fn rtcp_bitrate(&self) -> Bitrate {
+        self.session_bitrate * self.fraction
+    }
+
+    /// The minimum interval between reports.
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    /// The maximum interval between reports.
+    pub fn max_interval(&self) -> Duration {
+        self.max_interval
+    }
+
+    /// The reduced minimum interval, if set.
+    pub fn reduced_min_interval(&self) -> Option<Duration> {
+        self.reduced_min_interval
+    }
+
+    /// Interval between our reports, RFC 3550 section 6.3.1 without the randomization.
+    ///
+    /// * `members` is the number of SSRCs in the session, ours and the remote's.
+    /// * `senders` is how many of those are sending RTP.
+    /// * `we_sent` is whether we are one of the senders.
+    /// * `avg_size` is the average compound RTCP size, including UDP and IP headers.
+    pub(crate) fn interval(
+        &self,
+        members: usize,
+        senders: usize,
+        we_sent: bool,
+        avg_size: DataSize,
+    ) -> Duration {
+        let members = members.max(1);
+        let mut bitrate = self.rtcp_bitrate();
+        let mut n = members;
+
+        // Few senders get a quarter of the bandwidth, so their reports are timely.
+        if senders > 0 && (senders as f64) <= members as f64 * SENDER_FRACTION {
+            if we_sent {
+                bitrate = bitrate * SENDER_FRACTION;
+                n = senders;
+            } else {
+                bitrate = bitrate * (1.0 - SENDER_FRACTION);
+                n = members - senders;
+            }
+        }
+
+        let min = self.reduced_min_interval.unwrap_or(self.min_interval);
+
+        if bitrate.as_f64() <= 0.0 {
+            return self.max_interval;
+        }
+
+        let t = (avg_size * n as u64) / bitrate;
+
+        t.clamp(min, self.max_interval)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interval_stretches_with_members() {
+        // 10kbit/s for RTCP.
+        let config = RtcpConfig::new(Bitrate::kbps(200))
+            .set_interval_bounds(Duration::from_secs(1), Duration::from_secs(60));
+        assert_eq!(config.rtcp_bitrate(), Bitrate::kbps(10));
+
+        let size = DataSize::bytes(125);
+
+        // 125 bytes is 0.1s of the budget per member, all sending.
+        let t = |members| config.interval(members, members, true, size);
+        assert_eq!(t(2), Duration::from_secs(1));
+        assert_eq!(t(20), Duration::from_secs(2));
+        assert_eq!(t(50), Duration::from_secs(5));
+        assert_eq!(t(1000), Duration::from_secs(60));
+
+        // Few senders share a quarter, the receivers the rest.
+        let sender = config.interval(100, 10, true, size);
+        let receiver = config.interval(100, 10, false, size);
+        assert_eq!(sender, Duration::from_secs(4));
+        assert_eq!(receiver, Duration::from_secs(12));
+    }
+
+    #[test]
+    fn reduced_minimum() {
+        let config = RtcpConfig::new(Bitrate::kbps(24))
+            .set_interval_bounds(Duration::from_secs(5), Duration::from_secs(30))
+            .set_reduced_min_interval(Some(Duration::from_millis(100)));
+
+        // 1.2kbit/s for RTCP, 100 bytes takes 0.67s per member.
+        let t = config.interval(2, 2, true, DataSize::bytes(100));
+        assert_eq!(t.as_millis(), 1333);
+
+        // Without the reduced minimum, the 5 second minimum applies.
+        let config = config.set_reduced_min_interval(None);
+        assert_eq!(
+            config.interval(2, 2, true, DataSize::bytes(100)),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn min_above_max() {
+        RtcpConfig::new(Bitrate::kbps(24))
+            .set_interval_bounds(Duration::from_secs(10), Duration::from_secs(5));
+    }
+}
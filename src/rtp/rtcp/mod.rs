@@ -37,7 +37,7 @@ mod fir;
 pub use fir::{Fir, FirEntry};
 
 mod twcc;
-pub use twcc::{Twcc, TwccRecvRegister, TwccSendRecord, TwccSendRegister};
+pub use twcc::{Twcc, TwccRecvRegister, TwccSendRecord, TwccSendRegister, TwccSeqAllocator};
 
 mod rtcpfb;
 pub use rtcpfb::RtcpFb;
@@ -45,6 +45,15 @@ pub use rtcpfb::RtcpFb;
 mod remb;
 pub use remb::Remb;
 
+mod exp_mantissa;
+pub use exp_mantissa::{decode_exp_mantissa, encode_exp_mantissa};
+
+mod raw;
+pub use raw::RawRtcp;
+
+mod interval;
+pub use interval::RtcpConfig;
+
 use super::extend_u16;
 use super::SeqNo;
 use super::Ssrc;
@@ -91,22 +100,32 @@ pub enum Rtcp {
 }
 
 impl Rtcp {
-    pub(crate) fn read_packet(buf: &[u8], feedback: &mut VecDeque<Rtcp>) {
+    /// Parse a compound RTCP packet.
+    ///
+    /// Packets that can't be parsed into [`Rtcp`] are put in `unhandled` verbatim.
+    pub(crate) fn read_packet(
+        buf: &[u8],
+        feedback: &mut VecDeque<Rtcp>,
+        unhandled: &mut VecDeque<RawRtcp>,
+    ) {
         let mut buf = buf;
         loop {
             if buf.is_empty() {
                 break;
             }
 
-            let header: RtcpHeader = match buf.try_into() {
-                Ok(v) => v,
-                Err(e) => {
-                    debug!("{}", e);
-                    break;
-                }
-            };
+            // The length is read without knowing the packet type, to be able to step over
+            // packets we don't parse.
+            if buf.len() < 4 {
+                debug!("Need 4 bytes for RTCP header");
+                break;
+            }
+            if buf[0] >> 6 != 2 {
+                debug!("RTCP header version should be 2");
+                break;
+            }
             let has_padding = buf[0] & 0b00_1_00000 > 0;
-            let full_length = header.length_words() * 4;
+            let full_length = raw::length_bytes(buf);
 
             if full_length > buf.len() {
                 // this length is incorrect.
@@ -126,42 +145,83 @@ impl Rtcp {
 
             match (&buf[..unpadded_length]).try_into() {
                 Ok(v) => feedback.push_back(v),
-                Err(e) => debug!("{}", e),
+                Err(e) => {
+                    debug!("{}", e);
+                    unhandled.push_back(RawRtcp::from_incoming(&buf[..full_length]));
+                }
             }
 
             buf = &buf[full_length..];
         }
     }
 
+    /// Write a compound RTCP packet.
+    ///
+    /// The `raw` packets are appended verbatim after the `feedback`, as many as fit.
+    ///
+    /// With `compound_ssrc`, a packet without SR/RR starts with an empty RR from that SSRC.
+    /// Without it, feedback can go as reduced-size RTCP (RFC 5506).
     pub(crate) fn write_packet(
         feedback: &mut VecDeque<Rtcp>,
+        raw: &mut VecDeque<RawRtcp>,
+        sdes: &[Descriptions],
+        compound_ssrc: Option<Ssrc>,
         buf: &mut [u8],
         mut output: impl FnMut(Rtcp),
     ) -> usize {
-        if feedback.is_empty() {
+        if feedback.is_empty() && raw.is_empty() {
             return 0;
         }
 
         // Total length, in bytes, shrunk to be on the pad_to boundary.
         let total_len = buf.len();
 
+        // The SDES goes in every compound packet, but must leave room for the rest.
+        let sdes_len: usize = sdes.iter().map(|d| d.length_words() * 4).sum();
+        let (sdes, sdes_len) = if sdes_len > total_len / 2 {
+            (&[][..], 0)
+        } else {
+            (sdes, sdes_len)
+        };
+
+        // Room for an empty RR.
+        let rr_len = if compound_ssrc.is_some() { 8 } else { 0 };
+
         // Capacity in words
-        let word_capacity = total_len / 4;
+        let word_capacity = (total_len - sdes_len - rr_len) / 4;
 
         // Pack RTCP feedback packets. Merge together ones of the same type.
         Rtcp::pack(feedback, word_capacity);
 
+        // A compound RTCP must start with a SR/RR, which can be without report blocks.
+        if let Some(sender_ssrc) = compound_ssrc {
+            if feedback.front().map_or(true, |fb| fb.order_no() > 1) {
+                feedback.push_front(Rtcp::ReceiverReport(ReceiverReport {
+                    sender_ssrc,
+                    reports: ReportList::new(),
+                }));
+            }
+        }
+
         let mut offset = 0;
+        let mut sdes_written = sdes.is_empty();
         while let Some(fb) = feedback.front() {
             // Length of next item.
             let item_len = fb.length_words() * 4;
 
-            // Capacity left in the buffer.
-            let capacity = total_len - offset;
+            // Capacity left in the buffer, minus the SDES still to come.
+            let reserved = if sdes_written { 0 } else { sdes_len };
+            let capacity = total_len - offset - reserved;
             if capacity < item_len {
                 break;
             }
 
+            // SDES right after the SR/RR.
+            if !sdes_written && fb.order_no() > 1 {
+                offset += write_sdes(sdes, &mut buf[offset..], &mut output);
+                sdes_written = true;
+            }
+
             // We definitely can fit the next RTCP item.
             let fb = feedback.pop_front().unwrap();
             let written = fb.write_to(&mut buf[offset..]);
@@ -178,6 +238,28 @@ impl Rtcp {
             offset += item_len;
         }
 
+        if !sdes_written {
+            let raw_fits = raw.front().map_or(false, |r| {
+                r.length_words() * 4 <= total_len - offset - sdes_len
+            });
+
+            // Don't send a packet with only the SDES.
+            if offset == 0 && !raw_fits {
+                return 0;
+            }
+
+            offset += write_sdes(sdes, &mut buf[offset..], &mut output);
+        }
+
+        while let Some(r) = raw.front() {
+            if total_len - offset < r.length_words() * 4 {
+                break;
+            }
+
+            let r = raw.pop_front().unwrap();
+            offset += r.write_to(&mut buf[offset..]);
+        }
+
         offset
     }
 
@@ -201,8 +283,8 @@ impl Rtcp {
                 n > 0
             }
 
-            // Stack source descriptions.
-            (Rtcp::Goodbye(g1), Rtcp::Goodbye(g2)) => {
+            // Stack goodbyes with the same reason.
+            (Rtcp::Goodbye(g1), Rtcp::Goodbye(g2)) if g1.reason == g2.reason => {
                 let n = g1.reports.append_all_possible(&mut g2.reports, words_left);
                 n > 0
             }
@@ -348,6 +430,15 @@ impl Rtcp {
     }
 }
 
+fn write_sdes(sdes: &[Descriptions], buf: &mut [u8], output: &mut impl FnMut(Rtcp)) -> usize {
+    let mut offset = 0;
+    for d in sdes {
+        offset += d.write_to(&mut buf[offset..]);
+        output(Rtcp::SourceDescription(d.clone()));
+    }
+    offset
+}
+
 impl RtcpPacket for Rtcp {
     fn header(&self) -> RtcpHeader {
         match self {
@@ -496,7 +587,14 @@ mod test {
         twcc.delta.push_back(Delta::Small(0x84));
         queue.push_back(Rtcp::Twcc(twcc));
         let mut buf = vec![0; 1500];
-        let n = Rtcp::write_packet(&mut queue, &mut buf, |_| {});
+        let n = Rtcp::write_packet(
+            &mut queue,
+            &mut VecDeque::new(),
+            &[],
+            None,
+            &mut buf,
+            |_| {},
+        );
         buf.truncate(n);
         println!("{buf:02x?}");
         assert_eq!(
@@ -578,11 +676,18 @@ mod test {
         feedback.push_back(rr(5));
 
         let mut buf = vec![0_u8; 1360];
-        let n = Rtcp::write_packet(&mut feedback, &mut buf, |_| {});
+        let n = Rtcp::write_packet(
+            &mut feedback,
+            &mut VecDeque::new(),
+            &[],
+            None,
+            &mut buf,
+            |_| {},
+        );
         buf.truncate(n);
 
         let mut parsed = VecDeque::new();
-        Rtcp::read_packet(&buf, &mut parsed);
+        Rtcp::read_packet(&buf, &mut parsed, &mut VecDeque::new());
 
         let Rtcp::SenderReport(s) = parsed.get(0).unwrap() else {
             panic!("Not a SenderReport in Rtcp");
@@ -603,6 +708,212 @@ mod test {
         assert!(abs < Duration::from_millis(1));
     }
 
+    #[test]
+    fn roundtrip_raw() {
+        // APP with name "test" and 4 bytes of data.
+        let app = vec![
+            0x81, 204, 0, 3, 0, 0, 0, 1, b't', b'e', b's', b't', 1, 2, 3, 4,
+        ];
+        // Unknown packet type.
+        let unknown = vec![0x80, 210, 0, 1, 0, 0, 0, 1];
+
+        let mut feedback = VecDeque::new();
+        feedback.push_back(rr(3));
+        let mut raw = VecDeque::new();
+        raw.push_back(RawRtcp::new(app.clone(), 1360).unwrap());
+        raw.push_back(RawRtcp::new(unknown.clone(), 1360).unwrap());
+
+        let mut buf = vec![0_u8; 1360];
+        let n = Rtcp::write_packet(&mut feedback, &mut raw, &[], None, &mut buf, |_| {});
+        buf.truncate(n);
+        assert!(raw.is_empty());
+
+        let mut parsed = VecDeque::new();
+        let mut unhandled = VecDeque::new();
+        Rtcp::read_packet(&buf, &mut parsed, &mut unhandled);
+
+        assert_eq!(parsed, [rr(3)]);
+        assert_eq!(unhandled.len(), 2);
+        assert_eq!(unhandled[0].data(), app);
+        assert_eq!(unhandled[0].packet_type(), 204);
+        assert_eq!(unhandled[0].count(), 1);
+        assert_eq!(unhandled[0].sender_ssrc(), Some(1.into()));
+        assert_eq!(unhandled[1].data(), unknown);
+    }
+
+    #[test]
+    fn roundtrip_bye_reason() {
+        let bye = |ssrc: u32, reason: Option<&str>| {
+            let mut reports = ReportList::new();
+            reports.push(ssrc.into());
+            Rtcp::Goodbye(Goodbye {
+                reports,
+                reason: reason.map(String::from),
+            })
+        };
+
+        let mut feedback = VecDeque::new();
+        feedback.push_back(bye(1, Some("closing")));
+        feedback.push_back(bye(2, Some("closing")));
+        feedback.push_back(bye(3, None));
+        feedback.push_back(rr(4));
+
+        let mut buf = vec![0_u8; 1360];
+        let n = Rtcp::write_packet(
+            &mut feedback,
+            &mut VecDeque::new(),
+            &[],
+            None,
+            &mut buf,
+            |_| {},
+        );
+        buf.truncate(n);
+        assert!(feedback.is_empty());
+
+        let mut parsed = VecDeque::new();
+        Rtcp::read_packet(&buf, &mut parsed, &mut VecDeque::new());
+
+        // Same reason merges, BYE goes last.
+        let mut both = ReportList::new();
+        both.push(1.into());
+        both.push(2.into());
+        assert_eq!(
+            parsed,
+            [
+                rr(4),
+                Rtcp::Goodbye(Goodbye {
+                    reports: both,
+                    reason: Some("closing".into()),
+                }),
+                bye(3, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_validation() {
+        assert!(RawRtcp::new(vec![0x80, 204, 0, 0], 1360).is_err());
+        assert!(RawRtcp::new(vec![0x80, 204, 0, 1, 0, 0, 0, 1, 0], 1360).is_err());
+        assert!(RawRtcp::new(vec![0x40, 204, 0, 1, 0, 0, 0, 1], 1360).is_err());
+        assert!(RawRtcp::new(vec![0x80, 204, 0, 2, 0, 0, 0, 1], 1360).is_err());
+        assert!(RawRtcp::new(vec![0x80, 204, 0, 1, 0, 0, 0, 1], 4).is_err());
+        assert!(RawRtcp::new(vec![0x80, 204, 0, 1, 0, 0, 0, 1], 1360).is_ok());
+    }
+
+    #[test]
+    fn sdes_in_every_compound() {
+        let chunk = |ssrc: u32| {
+            let mut values = ReportList::new();
+            values.push((SdesType::CNAME, "abcdefghijklmnop".to_string()));
+            Sdes {
+                ssrc: ssrc.into(),
+                values,
+            }
+        };
+        let mut reports = ReportList::new();
+        reports.push(chunk(1));
+        reports.push(chunk(2));
+        let sdes = [Descriptions {
+            reports: Box::new(reports),
+        }];
+
+        // Too many reports for one packet.
+        let mut feedback: VecDeque<_> = (0..100).map(rr).collect();
+        feedback.push_back(Rtcp::Pli(Pli {
+            sender_ssrc: 1.into(),
+            ssrc: 7.into(),
+        }));
+
+        let mut packets = 0;
+        loop {
+            let mut buf = vec![0_u8; 1000];
+            let n = Rtcp::write_packet(
+                &mut feedback,
+                &mut VecDeque::new(),
+                &sdes,
+                None,
+                &mut buf,
+                |_| {},
+            );
+            if n == 0 {
+                break;
+            }
+            buf.truncate(n);
+            packets += 1;
+
+            let mut parsed = VecDeque::new();
+            Rtcp::read_packet(&buf, &mut parsed, &mut VecDeque::new());
+
+            // Exactly one SDES, right after the reports.
+            let pos = parsed
+                .iter()
+                .position(|p| !matches!(p, Rtcp::ReceiverReport(_)))
+                .unwrap();
+            assert!(pos > 0);
+            assert_eq!(parsed[pos], Rtcp::SourceDescription(sdes[0].clone()));
+            let count = parsed
+                .iter()
+                .filter(|p| matches!(p, Rtcp::SourceDescription(_)))
+                .count();
+            assert_eq!(count, 1);
+        }
+
+        assert!(packets > 2);
+        assert!(feedback.is_empty());
+    }
+
+    #[test]
+    fn compound_empty_rr() {
+        let pli = Rtcp::Pli(Pli {
+            sender_ssrc: 1.into(),
+            ssrc: 7.into(),
+        });
+
+        let write = |compound_ssrc: Option<Ssrc>| {
+            let mut feedback = VecDeque::from([pli.clone()]);
+            let mut buf = vec![0_u8; 1000];
+            let n = Rtcp::write_packet(
+                &mut feedback,
+                &mut VecDeque::new(),
+                &[],
+                compound_ssrc,
+                &mut buf,
+                |_| {},
+            );
+            buf.truncate(n);
+
+            let mut parsed = VecDeque::new();
+            Rtcp::read_packet(&buf, &mut parsed, &mut VecDeque::new());
+            parsed
+        };
+
+        // Reduced-size.
+        assert_eq!(write(None), std::slice::from_ref(&pli));
+
+        // Compound starts with an RR without reports.
+        let empty = Rtcp::ReceiverReport(ReceiverReport {
+            sender_ssrc: 1.into(),
+            reports: ReportList::new(),
+        });
+        assert_eq!(write(Some(1.into())), [empty, pli.clone()]);
+
+        // No extra RR when there already is one.
+        let mut feedback = VecDeque::from([pli.clone(), rr(3)]);
+        let mut buf = vec![0_u8; 1000];
+        let n = Rtcp::write_packet(
+            &mut feedback,
+            &mut VecDeque::new(),
+            &[],
+            Some(1.into()),
+            &mut buf,
+            |_| {},
+        );
+        buf.truncate(n);
+        let mut parsed = VecDeque::new();
+        Rtcp::read_packet(&buf, &mut parsed, &mut VecDeque::new());
+        assert_eq!(parsed, [rr(3), pli]);
+    }
+
     fn sr(ssrc: u32, ntp_time: Instant) -> Rtcp {
         Rtcp::SenderReport(SenderReport {
             sender_info: SenderInfo {
@@ -699,7 +1010,7 @@ mod test {
 
         for t in TESTS {
             parsed.clear();
-            Rtcp::read_packet(t, &mut parsed);
+            Rtcp::read_packet(t, &mut parsed, &mut VecDeque::new());
         }
     }
 }
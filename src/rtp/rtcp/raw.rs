@@ -0,0 +1,92 @@
+use super::Ssrc;
+
+/// An RTCP packet that str0m passes through without interpreting it.
+///
+/// Incoming, these are the packets in a compound RTCP that str0m doesn't parse, such as
+/// APP, unknown packet types or feedback formats it doesn't handle. Outgoing, these are
+/// pre-serialized packets written via
+/// [`DirectApi::write_raw_rtcp()`][crate::change::DirectApi::write_raw_rtcp].
+///
+/// The bytes are a single RTCP packet, header included. Any padding is kept as is.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RawRtcp {
+    data: Vec<u8>,
+}
+
+impl RawRtcp {
+    /// Smallest packet: header and sender SSRC. SRTCP needs the SSRC.
+    const MIN_LEN: usize = 8;
+
+    /// Check the bytes are a single RTCP packet of at most `max_len` bytes.
+    ///
+    /// Only length and alignment are validated, the contents are not.
+    pub(crate) fn new(data: Vec<u8>, max_len: usize) -> Result<Self, &'static str> {
+        if data.len() < Self::MIN_LEN {
+            return Err("Raw RTCP less than 8 bytes");
+        }
+        if data.len() % 4 != 0 {
+            return Err("Raw RTCP not a multiple of 4 bytes");
+        }
+        if data.len() > max_len {
+            return Err("Raw RTCP too large");
+        }
+        if data[0] >> 6 != 2 {
+            return Err("Raw RTCP version should be 2");
+        }
+        if length_bytes(&data) != data.len() {
+            return Err("Raw RTCP length field doesn't match");
+        }
+
+        Ok(RawRtcp { data })
+    }
+
+    /// Incoming packet the parser didn't understand. Length is already checked.
+    pub(crate) fn from_incoming(buf: &[u8]) -> Self {
+        RawRtcp { data: buf.to_vec() }
+    }
+
+    /// The RTCP packet type, i.e. 204 for APP.
+    pub fn packet_type(&self) -> u8 {
+        self.data[1]
+    }
+
+    /// The 5 bit count, feedback format or subtype, depending on the packet type.
+    pub fn count(&self) -> u8 {
+        self.data[0] & 0b0001_1111
+    }
+
+    /// SSRC of the sender of this packet, if the packet is long enough to hold one.
+    pub fn sender_ssrc(&self) -> Option<Ssrc> {
+        let b = self.data.get(4..8)?;
+        Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]).into())
+    }
+
+    /// The entire packet, header included.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(crate) fn length_words(&self) -> usize {
+        self.data.len() / 4
+    }
+
+    pub(crate) fn write_to(&self, buf: &mut [u8]) -> usize {
+        buf[..self.data.len()].copy_from_slice(&self.data);
+        self.data.len()
+    }
+}
+
+/// Length in bytes of the RTCP packet starting at `buf`, as given by the header.
+pub(crate) fn length_bytes(buf: &[u8]) -> usize {
+    (u16::from_be_bytes([buf[2], buf[3]]) as usize + 1) * 4
+}
+
+impl std::fmt::Debug for RawRtcp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawRtcp")
+            .field("packet_type", &self.packet_type())
+            .field("count", &self.count())
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
@@ -1,10 +1,10 @@
 use crate::rtp::Ssrc;
 
 use super::RtcpType;
+use super::{decode_exp_mantissa, encode_exp_mantissa};
 use super::{FeedbackMessageType, PayloadType, RtcpHeader, RtcpPacket};
 
-const BITRATE_MAX: f32 = 2.417_842_4e24; //0x3FFFFp+63;
-const MANTISSA_MAX: u32 = 0x7FFFFF;
+const MANTISSA_BITS: u8 = 18;
 const REMB_OFFSET: usize = 16;
 
 const UNIQUE_IDENTIFIER: [u8; 4] = [b'R', b'E', b'M', b'B'];
@@ -71,22 +71,8 @@ impl RtcpPacket for Remb {
     }
 
     fn write_to(&self, buf: &mut [u8]) -> usize {
-        let mut exp = 0;
-        let mut bitrate = self.bitrate;
-        if bitrate >= BITRATE_MAX {
-            bitrate = BITRATE_MAX
-        }
-
-        if bitrate < 0.0 {
-            bitrate = 0.0;
-        }
-
-        while bitrate >= (1 << 18) as f32 {
-            bitrate /= 2.0;
-            exp += 1;
-        }
-
-        let mantissa = bitrate.floor() as u32;
+        // Saturating cast, negative is 0.
+        let (exp, mantissa) = encode_exp_mantissa(self.bitrate as u64, MANTISSA_BITS);
 
         self.header().write_to(&mut buf[..4]);
         buf[4..8].copy_from_slice(&self.sender_ssrc.to_be_bytes());
@@ -138,25 +124,14 @@ impl<'a> TryFrom<&'a [u8]> for Remb {
 
         // Get the 6-bit exponent value.
         let b17 = buf[13];
-        let mut exp = (b17 as u64) >> 2;
-        exp += 127; // bias for IEEE754
-        exp += 23; // IEEE754 biases the decimal to the left, abs-send-time biases it to the right
+        let exp = b17 >> 2;
 
         // The remaining 2-bits plus the next 16-bits are the mantissa.
         let b18 = buf[14];
         let b19 = buf[15];
-        let mut mantissa = ((b17 & 3) as u32) << 16 | (b18 as u32) << 8 | b19 as u32;
-
-        if mantissa != 0 {
-            // ieee754 requires an implicit leading bit
-            while (mantissa & (MANTISSA_MAX + 1)) == 0 {
-                exp -= 1;
-                mantissa *= 2;
-            }
-        }
+        let mantissa = ((b17 & 3) as u32) << 16 | (b18 as u32) << 8 | b19 as u32;
 
-        // bitrate = mantissa * 2^exp
-        let bitrate = f32::from_bits(((exp as u32) << 23) | (mantissa & MANTISSA_MAX));
+        let bitrate = decode_exp_mantissa(exp, mantissa) as f32;
 
         let mut ssrcs = vec![];
         for i in 0..ssrcs_len {
@@ -245,11 +220,21 @@ mod tests {
         let output_len = packet.write_to(&mut output);
         assert_eq!(input, output[0..output_len]);
 
-        // If we subtract the bitrate by 1, we'll round down a lower mantissa
+        // If we subtract the bitrate by 1, we round to the same mantissa, not down.
         packet.bitrate -= 1.0;
 
         // bitrate = 8927167
-        // mantissa = 139486
+        // mantissa = 139486.98 => 139487
+        // exp = 6
+
+        let output_len = packet.write_to(&mut output);
+        assert_eq!(input, output[0..output_len]);
+
+        // Less than half a step above the lower mantissa rounds down.
+        packet.bitrate -= 32.0;
+
+        // bitrate = 8927135
+        // mantissa = 139486.48 => 139486
         // exp = 6
 
         let output_len = packet.write_to(&mut output);
@@ -279,33 +264,32 @@ mod tests {
             ssrcs: vec![],
         };
 
-        // mantissa = 262143 = 0x3FFFF
-        // exp = 63
+        // Saturates to u64::MAX, which rounds up to 2^64.
+        // mantissa = 131072 = 0x20000
+        // exp = 47
 
         let expected = [
-            143, 206, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 82, 69, 77, 66, 0, 255, 255, 255,
+            143, 206, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 82, 69, 77, 66, 0, 190, 0, 0,
         ];
 
         let mut output = [0; 1500];
         let output_len = packet.write_to(&mut output);
         assert_eq!(expected, output[0..output_len]);
 
-        // mantissa = 262143
-        // exp = 63
-        // bitrate = 0xFFFFC00000000000
-
         let packet = Remb::try_from(&output[4..output_len]).unwrap();
-        assert_eq!(f32::from_bits(0x67FFFFC0), packet.bitrate);
+        assert_eq!(u64::MAX as f32, packet.bitrate);
 
         // Make sure we marshal to the same result again.
         let output_len = packet.write_to(&mut output);
         assert_eq!(expected, output[0..output_len]);
 
-        // Finally, try unmarshalling one number higher than we used to be able to handle.
+        // Finally, unmarshal the format maximum, which saturates.
+        // mantissa = 262143 = 0x3FFFF
+        // exp = 63
         let input = [
-            143, 206, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 82, 69, 77, 66, 0, 188, 0, 0,
+            143, 206, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 82, 69, 77, 66, 0, 255, 255, 255,
         ];
         let packet = Remb::try_from(&input[4..]).unwrap();
-        assert_eq!(f32::from_bits(0x62800000), packet.bitrate);
+        assert_eq!(u64::MAX as f32, packet.bitrate);
     }
 }
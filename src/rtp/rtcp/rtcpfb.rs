@@ -10,7 +10,7 @@ pub enum RtcpFb {
     DlrrItem(DlrrItem),                // rx <- tx
     Rrtr((Rrtr, Ssrc)),                // rx -> tx
     SourceDescription(Sdes),           // tx -> rx
-    Goodbye(Ssrc),                     // tx -> rx
+    Goodbye(Ssrc, Option<String>),     // tx -> rx
     Nack(Ssrc, ReportList<NackEntry>), // rx -> tx
     Pli(Ssrc),                         // rx -> tx
     Fir(FirEntry),                     // rx -> tx
@@ -24,7 +24,7 @@ impl RtcpFb {
             self,
             RtcpFb::SenderInfo(_)
                 | RtcpFb::SourceDescription(_)
-                | RtcpFb::Goodbye(_)
+                | RtcpFb::Goodbye(..)
                 | RtcpFb::DlrrItem(_)
         )
     }
@@ -55,7 +55,12 @@ impl RtcpFb {
                     q.extend(v.reports.into_iter().map(RtcpFb::SourceDescription));
                 }
                 Rtcp::Goodbye(v) => {
-                    q.extend(v.reports.into_iter().map(RtcpFb::Goodbye));
+                    let reason = v.reason;
+                    q.extend(
+                        v.reports
+                            .into_iter()
+                            .map(|ssrc| RtcpFb::Goodbye(ssrc, reason.clone())),
+                    );
                 }
                 Rtcp::Nack(v) => {
                     q.push(RtcpFb::Nack(v.ssrc, v.reports));
@@ -84,7 +89,7 @@ impl RtcpFb {
             RtcpFb::DlrrItem(v) => v.ssrc,
             RtcpFb::Rrtr((_, ssrc)) => *ssrc,
             RtcpFb::SourceDescription(v) => v.ssrc,
-            RtcpFb::Goodbye(v) => *v,
+            RtcpFb::Goodbye(v, _) => *v,
             RtcpFb::Nack(v, _) => *v,
             RtcpFb::Pli(v) => *v,
             RtcpFb::Fir(v) => v.ssrc,
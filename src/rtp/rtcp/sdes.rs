@@ -127,7 +127,9 @@ impl WordSized for Sdes {
             .iter()
             // 2 here for 2 byte encoding of type + length
             .map(|(_, s)| 2 + s.as_bytes().len())
-            .sum::<usize>();
+            .sum::<usize>()
+            // END item, always at least one byte.
+            + 1;
 
         let padded = pad_bytes_to_word(byte_size);
 
@@ -274,4 +276,19 @@ mod test {
 
         assert_eq!(s1, s2);
     }
+
+    #[test]
+    fn word_aligned_value() {
+        // SSRC and item make 16 bytes, the END needs another word.
+        let mut s1 = Sdes {
+            ssrc: 1.into(),
+            values: ReportList::new(),
+        };
+        s1.values.push((SdesType::CNAME, "abcdefghij".into()));
+
+        let mut buf = vec![0; 50];
+        let n = s1.write_to(&mut buf);
+        assert_eq!(n, 20);
+        assert_eq!(s1.word_size(), 5);
+    }
 }
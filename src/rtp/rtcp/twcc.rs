@@ -985,6 +985,27 @@ impl<'a> TryFrom<&'a [u8]> for PacketChunk {
     }
 }
 
+/// Allocator of transport-wide sequence numbers.
+///
+/// There is one allocator per session, shared by all outgoing RTP packets regardless
+/// of SSRC. Media, RTX and padding packets all draw from the same counter, which makes
+/// a single gapless sequence space for the remote TWCC feedback to refer to.
+#[derive(Debug)]
+pub struct TwccSeqAllocator {
+    next: SeqNo,
+}
+
+impl TwccSeqAllocator {
+    pub fn new() -> Self {
+        TwccSeqAllocator { next: 0.into() }
+    }
+
+    /// Hand out the next transport-wide sequence number.
+    pub fn next_seq(&mut self) -> SeqNo {
+        self.next.inc()
+    }
+}
+
 #[derive(Debug)]
 pub struct TwccSendRegister {
     /// How many send records to keep.
@@ -1021,6 +1042,12 @@ pub struct TwccSendRecord {
     /// Size in bytes of the payload sent.
     size: u16,
 
+    /// Whether the packet was padding (blank or a spurious resend) for probing.
+    is_padding: bool,
+
+    /// Whether the packet was in flight when the network route changed.
+    route_lost: bool,
+
     recv_report: Option<TwccRecvReport>,
 }
 
@@ -1039,6 +1066,11 @@ impl TwccSendRecord {
         self.size as usize
     }
 
+    /// Whether the packet was padding rather than media.
+    pub fn is_padding(&self) -> bool {
+        self.is_padding
+    }
+
     /// The time indicated by the remote side for when they received the packet.
     pub fn remote_recv_time(&self) -> Option<Instant> {
         self.recv_report.as_ref().and_then(|r| r.remote_recv_time)
@@ -1070,7 +1102,7 @@ impl TwccSendRegister {
         }
     }
 
-    pub fn register_seq(&mut self, seq: SeqNo, now: Instant, size: usize) {
+    pub fn register_seq(&mut self, seq: SeqNo, now: Instant, size: usize, is_padding: bool) {
         self.last_registered = seq;
         self.queue.push_back(TwccSendRecord {
             seq,
@@ -1078,6 +1110,8 @@ impl TwccSendRegister {
             // In practice the max sizes is constrained by the MTU and will max out around 1200
             // bytes, hence this cast is fine.
             size: size as u16,
+            is_padding,
+            route_lost: false,
             // The recv report, derived from TWCC feedback later.
             recv_report: None,
         });
@@ -1159,6 +1193,19 @@ impl TwccSendRegister {
         Some(first_seq_no..=last_seq_no)
     }
 
+    /// Mark all packets not yet reported on as lost to a route change.
+    ///
+    /// Packets sent on the old path are likely never to arrive. That is not congestion on
+    /// the new path and is left out of [`TwccSendRegister::loss`].
+    pub fn mark_in_flight_route_lost(&mut self) {
+        for r in self.queue.iter_mut().rev() {
+            if r.recv_report.is_some() {
+                break;
+            }
+            r.route_lost = true;
+        }
+    }
+
     pub fn send_record(&self, seq: SeqNo) -> Option<&TwccSendRecord> {
         let index = self.queue.binary_search_by_key(&seq, |r| r.seq).ok()?;
 
@@ -1182,7 +1229,9 @@ impl TwccSendRegister {
             // themselves are lost. In this case considering packets that haven't been reported as
             // lost will incorrectly conclude that there is in fact egress loss.
             .filter(|s| s.recv_report.is_some())
-            .take_while(|s| s.local_send_time >= lower_bound);
+            .take_while(|s| s.local_send_time >= lower_bound)
+            // Lost to a route change is not congestion.
+            .filter(|s| !s.route_lost);
 
         let (total, lost) = packets.fold((0, 0), |(total, lost), s| {
             let was_lost = s
@@ -1356,6 +1405,19 @@ mod test {
     use PacketChunk::*;
     use PacketStatus::*;
 
+    #[test]
+    fn seq_allocator_is_gapless() {
+        let mut alloc = TwccSeqAllocator::new();
+
+        let seqs: Vec<_> = (0..70_000).map(|_| alloc.next_seq()).collect();
+
+        assert_eq!(seqs[0], 0.into());
+        assert!(seqs.windows(2).all(|w| w[0].is_next(w[1])));
+
+        // The RTP header value wraps, but the extended value keeps increasing.
+        assert_eq!(seqs[65_536].as_u16(), 0);
+    }
+
     #[test]
     fn register_write_parse_small_delta() {
         let mut reg = TwccRecvRegister::new(100);
@@ -1770,7 +1832,7 @@ mod test {
         let mut now = Instant::now();
 
         for i in 0..50 {
-            reg.register_seq(i.into(), now, 0);
+            reg.register_seq(i.into(), now, 0, false);
             now = now + Duration::from_micros(15);
         }
 
@@ -1923,7 +1985,7 @@ mod test {
         let mut reg = TwccSendRegister::new(25);
         let mut now = Instant::now();
         for i in 0..25 {
-            reg.register_seq(i.into(), now, 0);
+            reg.register_seq(i.into(), now, 0, false);
             now = now + Duration::from_micros(15);
         }
 
@@ -1969,7 +2031,7 @@ mod test {
         let mut reg = TwccSendRegister::new(25);
         let mut now = Instant::now();
         for i in 0..9 {
-            reg.register_seq(i.into(), now, 0);
+            reg.register_seq(i.into(), now, 0, false);
             now = now + Duration::from_millis(15);
         }
 
@@ -2015,6 +2077,41 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_twcc_send_register_route_lost() {
+        let mut reg = TwccSendRegister::new(25);
+        let mut now = Instant::now();
+        for i in 0..10 {
+            reg.register_seq(i.into(), now, 0, false);
+            now = now + Duration::from_millis(15);
+        }
+
+        let report = |base_seq: u16, status: PacketStatus| Twcc {
+            sender_ssrc: Ssrc::new(),
+            ssrc: Ssrc::new(),
+            base_seq,
+            status_count: 5,
+            reference_time: 35,
+            feedback_count: 0,
+            chunks: [PacketChunk::Run(status, 5)].into(),
+            delta: if status == PacketStatus::NotReceived {
+                vec![].into()
+            } else {
+                vec![Delta::Small(10); 5].into()
+            },
+        };
+
+        // The first half arrived before the route changed.
+        reg.apply_report(report(0, PacketStatus::ReceivedSmallDelta), now);
+        reg.mark_in_flight_route_lost();
+
+        // The second half was in flight on the old route and never arrives.
+        now = now + Duration::from_millis(20);
+        reg.apply_report(report(5, PacketStatus::NotReceived), now);
+
+        assert_eq!(reg.loss(Duration::from_secs(1), now), Some(0.0));
+    }
+
     #[test]
     fn test_twcc_recv_register_loss() {
         let mut reg = TwccRecvRegister::new(25);
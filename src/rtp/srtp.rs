@@ -1,9 +1,14 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt;
+use std::time::{Duration, Instant};
 
-use crate::crypto::{self, new_aead_aes_128_gcm, new_aes_128_cm_sha1_80, KeyingMaterial};
-use crate::crypto::{aead_aes_128_gcm, aes_128_cm_sha1_80, SrtpProfile};
+use crate::crypto::{self, CryptoError, KeyingMaterial, SrtpKeys, SrtpProfile};
+use crate::crypto::{aead_aes_128_gcm, aead_aes_256_gcm, aes_128_cm_sha1_80};
+use crate::crypto::{new_aead_aes_128_gcm, new_aead_aes_256_gcm, new_aes_128_cm_sha1_80};
 
 use super::header::RtpHeader;
+use super::replay::ReplayWindow;
+use super::RtpError;
 
 // Common among various profiles(defined in RFC3711 Section 4.3)
 const LABEL_RTP_AES: u8 = 0;
@@ -24,24 +29,87 @@ const LABEL_RTCP_SALT: u8 = 5;
 
 pub const SRTP_BLOCK_SIZE: usize = 16;
 const SRTCP_INDEX_LEN: usize = 4;
+const MAX_SRTCP_INDEX: u32 = 0x7fff_ffff;
 const MAX_TAG_LEN: usize = aead_aes_128_gcm::TAG_LEN;
 pub const SRTCP_OVERHEAD: usize = MAX_TAG_LEN + SRTCP_INDEX_LEN;
 pub const SRTP_OVERHEAD: usize = MAX_TAG_LEN;
 
+/// Length of the zeroed tag for [`SrtpProfile::NullDebug`], same as SHA1_80.
+const NULL_DEBUG_TAG_LEN: usize = aes_128_cm_sha1_80::HMAC_TAG_LEN;
+
+/// Default size of the SRTP/SRTCP replay windows.
+pub const DEFAULT_REPLAY_WINDOW: usize = 1024;
+
+/// Default max number of remote SSRCs to keep SRTP receive state for.
+pub const DEFAULT_MAX_SSRCS: usize = 1000;
+
+/// Default time after which receive state for a silent SSRC is dropped.
+pub const DEFAULT_SSRC_IDLE: Duration = Duration::from_secs(300);
+
+/// How often to look for idle SSRCs.
+const SSRC_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of SRTP packets in sequence before a dropped SSRC, or a new one when at the
+/// max number of SSRCs, is accepted.
+///
+/// See https://www.rfc-editor.org/rfc/rfc3550#appendix-A.1
+const SSRC_PROBATION: u8 = 2;
+
+/// Max number of SRTP packets per master key, RFC 3711 section 9.2.
+pub const MAX_SRTP_KEY_LIFETIME: u64 = 1 << 48;
+
+/// Share of a key lifetime (in 1/10) that can be used before it is considered expiring.
+const KEY_EXPIRING_TENTHS: u64 = 9;
+
 impl SrtpContext {
     /// Create an SRTP context for the relevant profile using the provided keying material.
+    ///
+    /// `left` picks the client (DTLS active) half of the material.
     pub fn new(profile: SrtpProfile, mat: &KeyingMaterial, left: bool) -> Self {
+        #[cfg(feature = "_internal_test_exports")]
+        if profile == SrtpProfile::PassThrough {
+            return Self::from_keys(profile, &SrtpKeys::new(vec![], vec![]));
+        }
+
+        let (keys, _) = mat.derive(profile, left);
+        Self::from_keys(profile, &keys)
+    }
+
+    /// Create an SRTP context for the relevant profile using the master key and salt
+    /// for one direction.
+    pub fn from_keys(profile: SrtpProfile, keys: &SrtpKeys) -> Self {
         match profile {
+            SrtpProfile::NullDebug => {
+                warn!("SRTP NULL_DEBUG profile: packets are NOT encrypted or authenticated");
+
+                SrtpContext {
+                    rtp: Derived::NullDebug,
+                    rtcp: Derived::NullDebug,
+                    srtcp_index: 0,
+                    require_encrypted_rtcp: true,
+                    replay: Replay::default(),
+                    roc: Roc::default(),
+                    packets: Packets::default(),
+                    previous: None,
+                    ssrcs: Ssrcs::default(),
+                }
+            }
             #[cfg(feature = "_internal_test_exports")]
             SrtpProfile::PassThrough => SrtpContext {
                 rtp: Derived::PassThrough,
                 rtcp: Derived::PassThrough,
                 srtcp_index: 0,
+                require_encrypted_rtcp: true,
+                replay: Replay::default(),
+                roc: Roc::default(),
+                packets: Packets::default(),
+                previous: None,
+                ssrcs: Ssrcs::default(),
             },
             SrtpProfile::Aes128CmSha1_80 => {
                 use aes_128_cm_sha1_80::{KEY_LEN, SALT_LEN};
 
-                let key = SrtpKey::<KEY_LEN, SALT_LEN>::new(mat, left);
+                let key = SrtpKey::<KEY_LEN, SALT_LEN>::new(keys);
 
                 let (rtp, rtcp) = Derived::aes_128_cm_sha1_80(&key);
 
@@ -49,12 +117,18 @@ impl SrtpContext {
                     rtp,
                     rtcp,
                     srtcp_index: 0,
+                    require_encrypted_rtcp: true,
+                    replay: Replay::default(),
+                    roc: Roc::default(),
+                    packets: Packets::default(),
+                    previous: None,
+                    ssrcs: Ssrcs::default(),
                 }
             }
             SrtpProfile::AeadAes128Gcm => {
                 use aead_aes_128_gcm::{KEY_LEN, SALT_LEN};
 
-                let key = SrtpKey::<KEY_LEN, SALT_LEN>::new(mat, left);
+                let key = SrtpKey::<KEY_LEN, SALT_LEN>::new(keys);
 
                 let (rtp, rtcp) = Derived::aead_aes_128_gcm(&key);
 
@@ -62,6 +136,31 @@ impl SrtpContext {
                     rtp,
                     rtcp,
                     srtcp_index: 0,
+                    require_encrypted_rtcp: true,
+                    replay: Replay::default(),
+                    roc: Roc::default(),
+                    packets: Packets::default(),
+                    previous: None,
+                    ssrcs: Ssrcs::default(),
+                }
+            }
+            SrtpProfile::AeadAes256Gcm => {
+                use aead_aes_256_gcm::{KEY_LEN, SALT_LEN};
+
+                let key = SrtpKey::<KEY_LEN, SALT_LEN>::new(keys);
+
+                let (rtp, rtcp) = Derived::aead_aes_256_gcm(&key);
+
+                SrtpContext {
+                    rtp,
+                    rtcp,
+                    srtcp_index: 0,
+                    require_encrypted_rtcp: true,
+                    replay: Replay::default(),
+                    roc: Roc::default(),
+                    packets: Packets::default(),
+                    previous: None,
+                    ssrcs: Ssrcs::default(),
                 }
             }
         }
@@ -87,6 +186,41 @@ impl SrtpContext {
                 dec: new_aead_aes_128_gcm(rtcp_key, false),
             },
             srtcp_index,
+            require_encrypted_rtcp: true,
+            replay: Replay::default(),
+            roc: Roc::default(),
+            packets: Packets::default(),
+            previous: None,
+            ssrcs: Ssrcs::default(),
+        }
+    }
+
+    #[cfg(test)]
+    fn new_aead_aes_256_gcm(
+        rtp_key: [u8; aead_aes_256_gcm::KEY_LEN],
+        rtp_salt: [u8; aead_aes_256_gcm::SALT_LEN],
+        rtcp_key: [u8; aead_aes_256_gcm::KEY_LEN],
+        rtcp_salt: [u8; aead_aes_256_gcm::SALT_LEN],
+        srtcp_index: u32,
+    ) -> Self {
+        Self {
+            rtp: Derived::AeadAes256Gcm {
+                salt: rtp_salt,
+                enc: new_aead_aes_256_gcm(rtp_key, true),
+                dec: new_aead_aes_256_gcm(rtp_key, false),
+            },
+            rtcp: Derived::AeadAes256Gcm {
+                salt: rtcp_salt,
+                enc: new_aead_aes_256_gcm(rtcp_key, true),
+                dec: new_aead_aes_256_gcm(rtcp_key, false),
+            },
+            srtcp_index,
+            require_encrypted_rtcp: true,
+            replay: Replay::default(),
+            roc: Roc::default(),
+            packets: Packets::default(),
+            previous: None,
+            ssrcs: Ssrcs::default(),
         }
     }
 }
@@ -99,9 +233,319 @@ pub struct SrtpContext {
     rtcp: Derived,
     /// Counter for outgoing SRTCP packets.
     srtcp_index: u32,
+    /// Drop incoming SRTCP that is not encrypted.
+    require_encrypted_rtcp: bool,
+    /// Replay protection for incoming SRTP/SRTCP.
+    replay: Replay,
+    /// Rollover counter state for incoming SRTP.
+    roc: Roc,
+    /// Packets protected/unprotected using this key.
+    packets: Packets,
+    /// Incoming context replaced by a rekey, kept for packets still in flight.
+    previous: Option<Box<SrtpContext>>,
+    /// Lifecycle of the per SSRC state in `roc` and `replay`.
+    ssrcs: Ssrcs,
+}
+
+/// Keeps the per SSRC receive state bounded. State for an SSRC is dropped when idle,
+/// on BYE, or when too many SSRCs are in use (least recently used first).
+#[derive(Debug, Clone)]
+struct Ssrcs {
+    max: usize,
+    idle: Duration,
+    /// Order of the last authenticated packet per SSRC, the key in `order`.
+    used: HashMap<u32, u64>,
+    /// SSRC and time of the last authenticated packet, least recently used first.
+    order: BTreeMap<u64, (u32, Option<Instant>)>,
+    counter: u64,
+    /// SSRCs on probation, oldest first. At most `max`, and not counted in `used`.
+    probation: VecDeque<(u32, Probation)>,
+    /// Time from the last handle_timeout.
+    now: Option<Instant>,
+    last_sweep: Option<Instant>,
+    /// Highest SRTP/SRTCP index of SSRCs whose state was dropped, oldest first.
+    forgotten: VecDeque<(u32, Option<u64>, Option<u64>)>,
+    /// Number of SSRCs dropped because of `max`.
+    evicted: u64,
+}
+
+impl Default for Ssrcs {
+    fn default() -> Self {
+        Ssrcs {
+            max: DEFAULT_MAX_SSRCS,
+            idle: DEFAULT_SSRC_IDLE,
+            used: HashMap::new(),
+            order: BTreeMap::new(),
+            counter: 0,
+            probation: VecDeque::new(),
+            now: None,
+            last_sweep: None,
+            forgotten: VecDeque::new(),
+            evicted: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Probation {
+    /// The SRTP index expected next.
+    next: u64,
+    /// Packets in sequence left before the probation is over.
+    left: u8,
+}
+
+impl Ssrcs {
+    /// Mark `ssrc` as the most recently used.
+    fn touch(&mut self, ssrc: u32) -> bool {
+        self.counter += 1;
+        let prev = self.used.insert(ssrc, self.counter);
+        if let Some(prev) = prev {
+            self.order.remove(&prev);
+        }
+        self.order.insert(self.counter, (ssrc, self.now));
+        prev.is_none()
+    }
+
+    fn remove(&mut self, ssrc: u32) {
+        if let Some(order) = self.used.remove(&ssrc) {
+            self.order.remove(&order);
+        }
+        self.probation.retain(|(s, _)| *s != ssrc);
+    }
+
+    /// The least recently used SSRC, other than `except`.
+    fn lru(&self, except: u32) -> Option<u32> {
+        self.order
+            .values()
+            .map(|(ssrc, _)| *ssrc)
+            .find(|ssrc| *ssrc != except)
+    }
+
+    /// SSRCs silent for longer than `idle`.
+    fn expired(&self, now: Instant) -> Vec<u32> {
+        let mut expired = vec![];
+        for (ssrc, at) in self.order.values() {
+            // Used before the first handle_timeout, which never expires.
+            let Some(at) = at else {
+                continue;
+            };
+            // Ordered by use, so the rest are more recent.
+            if now < *at + self.idle {
+                break;
+            }
+            expired.push(*ssrc);
+        }
+        expired
+    }
+}
+
+/// Number of packets processed under one master key. RFC 3711 section 9.2 limits
+/// these to 2^48 for SRTP and 2^31 for SRTCP.
+#[derive(Debug, Default, Clone, Copy)]
+struct Packets {
+    rtp: u64,
+    rtcp: u64,
+}
+
+/// Per SSRC state to estimate the SRTP index of incoming packets.
+/// See RFC 3711 section 3.3.1 and appendix A.
+#[derive(Debug, Default, Clone)]
+struct Roc {
+    /// Highest authenticated SRTP index (ROC and s_l).
+    highest: HashMap<u32, u64>,
+    /// ROC signaled out of band, used for the first packet.
+    initial: HashMap<u32, u64>,
+}
+
+/// Replay windows per SSRC, see RFC 3711 section 3.3.2.
+#[derive(Debug)]
+struct Replay {
+    size: usize,
+    rtp: HashMap<u32, ReplayWindow>,
+    rtcp: HashMap<u32, ReplayWindow>,
+    /// Number of SRTP packets dropped as replays.
+    dropped_rtp: u64,
+    /// Number of SRTCP packets dropped as replays.
+    dropped_rtcp: u64,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Replay {
+            size: DEFAULT_REPLAY_WINDOW,
+            rtp: HashMap::new(),
+            rtcp: HashMap::new(),
+            dropped_rtp: 0,
+            dropped_rtcp: 0,
+        }
+    }
 }
 
 impl SrtpContext {
+    /// Set the size of the replay windows used when unprotecting.
+    pub fn set_replay_window(&mut self, size: usize) {
+        self.replay.size = size;
+        self.replay.rtp.clear();
+        self.replay.rtcp.clear();
+    }
+
+    /// Switch an incoming context over to new keys.
+    ///
+    /// The rollover counters and SRTP replay windows carry over, since the sender keeps
+    /// its sequence numbers across the rekey. The SRTCP index restarts with the new key.
+    /// The current keys are kept to unprotect packets sent before the switch, until
+    /// [`SrtpContext::drop_previous`] is called.
+    pub fn rekey_rx(&mut self, mut next: SrtpContext) {
+        next.require_encrypted_rtcp = self.require_encrypted_rtcp;
+        next.roc = self.roc.clone();
+        next.replay.size = self.replay.size;
+        next.replay.rtp = self.replay.rtp.clone();
+        next.replay.dropped_rtp = self.replay.dropped_rtp;
+        next.replay.dropped_rtcp = self.replay.dropped_rtcp;
+        next.ssrcs = self.ssrcs.clone();
+
+        let mut previous = std::mem::replace(self, next);
+        // Only ever keep one generation back.
+        previous.previous = None;
+        self.previous = Some(Box::new(previous));
+    }
+
+    /// Limit the per SSRC receive state to `max` SSRCs, and drop it for SSRCs that
+    /// are silent for longer than `idle`.
+    pub fn set_ssrc_limits(&mut self, max: usize, idle: Duration) {
+        self.ssrcs.max = max.max(1);
+        self.ssrcs.idle = idle;
+    }
+
+    /// Advance time, and drop state for idle SSRCs.
+    pub fn handle_timeout(&mut self, now: Instant) {
+        self.ssrcs.now = Some(now);
+
+        if let Some(previous) = &mut self.previous {
+            previous.handle_timeout(now);
+        }
+
+        if self
+            .ssrcs
+            .last_sweep
+            .map_or(false, |t| now < t + SSRC_SWEEP_INTERVAL)
+        {
+            return;
+        }
+        self.ssrcs.last_sweep = Some(now);
+
+        for ssrc in self.ssrcs.expired(now) {
+            debug!("Drop SRTP state for idle SSRC {}", ssrc);
+            self.forget_ssrc(ssrc);
+        }
+    }
+
+    /// Drop the state for an SSRC, such as on BYE.
+    pub fn remove_ssrc(&mut self, ssrc: u32) {
+        self.forget_ssrc(ssrc);
+
+        if let Some(previous) = &mut self.previous {
+            previous.remove_ssrc(ssrc);
+        }
+    }
+
+    /// Number of SSRCs dropped because of the max number of SSRCs.
+    pub fn evicted_ssrcs(&self) -> u64 {
+        self.ssrcs.evicted
+    }
+
+    fn forget_ssrc(&mut self, ssrc: u32) {
+        let rtp = self.roc.highest.remove(&ssrc);
+        self.roc.initial.remove(&ssrc);
+        self.replay.rtp.remove(&ssrc);
+        let rtcp = self.replay.rtcp.remove(&ssrc).and_then(|w| w.max());
+        self.ssrcs.remove(ssrc);
+
+        if rtp.is_none() && rtcp.is_none() {
+            return;
+        }
+
+        // Remember just enough to pick up the ROC and reject replays, should it come back.
+        // SSRCs are reused, e.g. Chrome after BYE.
+        let forgotten = &mut self.ssrcs.forgotten;
+        forgotten.retain(|(s, _, _)| *s != ssrc);
+        forgotten.push_back((ssrc, rtp, rtcp));
+        while forgotten.len() > self.ssrcs.max {
+            forgotten.pop_front();
+        }
+    }
+
+    /// Bring back what is remembered for an SSRC that was dropped.
+    ///
+    /// Only packets newer than the highest ones before it was dropped are accepted, and
+    /// SRTP only after the probation.
+    fn restore_ssrc(&mut self, ssrc: u32) {
+        if self.ssrcs.used.contains_key(&ssrc) {
+            return;
+        }
+
+        let forgotten = &mut self.ssrcs.forgotten;
+        let Some(pos) = forgotten.iter().position(|(s, _, _)| *s == ssrc) else {
+            return;
+        };
+        let (_, rtp, rtcp) = forgotten.remove(pos).expect("position in forgotten");
+
+        let size = self.replay.size;
+
+        if let Some(index) = rtp {
+            self.roc.highest.insert(ssrc, index);
+            let window = ReplayWindow::new_received_up_to(size, index);
+            self.replay.rtp.insert(ssrc, window);
+            self.start_probation(ssrc, index + 1);
+        }
+
+        if let Some(index) = rtcp {
+            let window = ReplayWindow::new_received_up_to(size, index);
+            self.replay.rtcp.insert(ssrc, window);
+        }
+
+        self.ssrc_used(ssrc);
+    }
+
+    fn ssrc_used(&mut self, ssrc: u32) {
+        let is_new = self.ssrcs.touch(ssrc);
+
+        if !is_new || self.ssrcs.used.len() <= self.ssrcs.max {
+            return;
+        }
+
+        if let Some(lru) = self.ssrcs.lru(ssrc) {
+            debug!("Evict SRTP state for SSRC {}", lru);
+            self.forget_ssrc(lru);
+            self.ssrcs.evicted += 1;
+        }
+    }
+
+    /// Forget the keys replaced by [`SrtpContext::rekey_rx`].
+    pub fn drop_previous(&mut self) {
+        self.previous = None;
+    }
+
+    /// Whether the key is close to its lifetime. `lifetime` is the max number of
+    /// SRTP packets, SRTCP is always limited by the 31 bit SRTCP index.
+    pub fn key_expiring(&self, lifetime: u64) -> bool {
+        let near = |count: u64, max: u64| count >= max / 10 * KEY_EXPIRING_TENTHS;
+
+        near(self.packets.rtp, lifetime) || near(self.packets.rtcp, MAX_SRTCP_INDEX as u64 + 1)
+    }
+
+    /// Number of SRTP packets dropped as replays.
+    pub fn replayed_rtp(&self) -> u64 {
+        self.replay.dropped_rtp
+    }
+
+    /// Number of SRTCP packets dropped as replays.
+    pub fn replayed_rtcp(&self) -> u64 {
+        self.replay.dropped_rtcp
+    }
+
+    /// Copying version of [`SrtpContext::protect_rtp_in_place`].
+    #[cfg(any(test, feature = "_internal_test_exports"))]
     pub fn protect_rtp(
         &mut self,
         buf: &[u8],
@@ -138,9 +582,16 @@ impl SrtpContext {
         let hlen = header.header_len;
         let input = &buf[hlen..];
 
+        self.packets.rtp += 1;
+
         match &mut self.rtp {
             #[cfg(feature = "_internal_test_exports")]
             Derived::PassThrough => input.to_vec(),
+            Derived::NullDebug => {
+                let mut output = buf.to_vec();
+                output.resize(buf.len() + NULL_DEBUG_TAG_LEN, 0);
+                output
+            }
             Derived::Aes128CmSha1_80 { key, salt, enc, .. } => {
                 assert!(
                     input.len() % SRTP_BLOCK_SIZE == 0,
@@ -162,33 +613,243 @@ impl SrtpContext {
                 output
             }
             Derived::AeadAes128Gcm { salt, enc, .. } => {
-                use aead_aes_128_gcm::TAG_LEN;
-                let roc = (srtp_index >> 16) as u32;
+                aead_protect_rtp(*salt, buf, header, srtp_index, |iv, aad, input, output| {
+                    enc.encrypt(iv, aad, input, output)
+                })
+            }
+            Derived::AeadAes256Gcm { salt, enc, .. } => {
+                aead_protect_rtp(*salt, buf, header, srtp_index, |iv, aad, input, output| {
+                    enc.encrypt(iv, aad, input, output)
+                })
+            }
+        }
+    }
+
+    /// Protect the RTP packet in `buf[..len]` in place.
+    ///
+    /// `buf` must have room for [`SRTP_OVERHEAD`] bytes after the packet. The header is
+    /// authenticated but not encrypted, and is left as is. The payload is encrypted and
+    /// followed by the authentication tag.
+    ///
+    /// Returns the length of the SRTP packet.
+    pub fn protect_rtp_in_place(
+        &mut self,
+        buf: &mut [u8],
+        len: usize,
+        header: &RtpHeader,
+        srtp_index: u64, // same as ext_seq
+    ) -> Result<usize, RtpError> {
+        if buf.len() < len + SRTP_OVERHEAD {
+            return Err(RtpError::SrtpNoRoom);
+        }
+
+        let hlen = header.header_len;
+
+        self.packets.rtp += 1;
+
+        match &mut self.rtp {
+            #[cfg(feature = "_internal_test_exports")]
+            Derived::PassThrough => Ok(len),
+            Derived::NullDebug => {
+                buf[len..len + NULL_DEBUG_TAG_LEN].fill(0);
+                Ok(len + NULL_DEBUG_TAG_LEN)
+            }
+            Derived::Aes128CmSha1_80 { key, salt, enc, .. } => {
+                assert!(
+                    (len - hlen) % SRTP_BLOCK_SIZE == 0,
+                    "RTP body should be padded to 16 byte block size, {header:?} with body length {} was not", len - hlen
+                );
+                use aes_128_cm_sha1_80::HMAC_TAG_LEN;
+
+                let iv = aes_128_cm_sha1_80::rtp_iv(*salt, *header.ssrc, srtp_index);
+
+                enc.encrypt_in_place(&iv, &mut buf[hlen..len])?;
+
+                aes_128_cm_sha1_80::rtp_hmac(key, buf, srtp_index, len);
+
+                Ok(len + HMAC_TAG_LEN)
+            }
+            Derived::AeadAes128Gcm { salt, enc, .. } => {
+                aead_protect_rtp_in_place(*salt, buf, len, header, srtp_index, |iv, aad, buf| {
+                    enc.encrypt_in_place(iv, aad, buf)
+                })
+            }
+            Derived::AeadAes256Gcm { salt, enc, .. } => {
+                aead_protect_rtp_in_place(*salt, buf, len, header, srtp_index, |iv, aad, buf| {
+                    enc.encrypt_in_place(iv, aad, buf)
+                })
+            }
+        }
+    }
 
-                let iv = aead_aes_128_gcm::rtp_iv(*salt, *header.ssrc, roc, header.sequence_number);
-                let aad = &buf[..hlen];
+    /// Set the rollover counter (ROC) for the next incoming packet on `ssrc`.
+    ///
+    /// This is for receivers joining an on-going session, where the ROC is signaled
+    /// out of band.
+    pub fn reset_roc(&mut self, ssrc: u32, roc: u64) {
+        self.roc.highest.remove(&ssrc);
+        self.roc.initial.insert(ssrc, roc);
+        self.replay.rtp.remove(&ssrc);
+        self.ssrcs.forgotten.retain(|(s, _, _)| *s != ssrc);
+        self.ssrc_used(ssrc);
+    }
 
-                // Input and output lengths for encryption: https://www.rfc-editor.org/rfc/rfc7714#section-5.2.1
-                let mut output = vec![0_u8; buf.len() + TAG_LEN];
-                enc.encrypt(&iv, aad, input, &mut output[hlen..])
-                    .expect("rtp encrypt");
+    /// Estimate the SRTP index of an incoming packet.
+    ///
+    /// Picks the closest of ROC-1, ROC and ROC+1 to the highest authenticated index, which
+    /// means packets reordered around a sequence number wrap still decrypt.
+    fn rtp_index(&self, ssrc: u32, seq: u16) -> u64 {
+        let Some(highest) = self.roc.highest.get(&ssrc) else {
+            let roc = self.roc.initial.get(&ssrc).copied().unwrap_or(0);
+            return roc << 16 | seq as u64;
+        };
 
-                output[..hlen].copy_from_slice(aad);
+        let roc = highest >> 16;
+        let s_l = *highest as u16;
 
-                output
+        let v = if s_l < 0x8000 {
+            if seq > s_l && seq - s_l > 0x8000 {
+                // Before the wrap, unless this is the first ROC.
+                roc.saturating_sub(1)
+            } else {
+                roc
+            }
+        } else if s_l - 0x8000 > seq {
+            roc + 1
+        } else {
+            roc
+        };
+
+        v << 16 | seq as u64
+    }
+
+    pub fn unprotect_rtp(&mut self, buf: &[u8], header: &RtpHeader) -> Option<Vec<u8>> {
+        let ssrc = *header.ssrc;
+        self.restore_ssrc(ssrc);
+        let srtp_index = self.rtp_index(ssrc, header.sequence_number);
+
+        // Check the replay list before doing any decryption work.
+        if !self.check_rtp_replay(ssrc, srtp_index) {
+            return None;
+        }
+
+        let output = match self.do_unprotect_rtp(buf, header, srtp_index) {
+            Some(v) => {
+                self.packets.rtp += 1;
+                v
+            }
+            // Sent with the keys from before a rekey.
+            None => self.previous.as_mut()?.unprotect_rtp(buf, header)?,
+        };
+
+        if self.in_probation(ssrc, srtp_index) {
+            return None;
+        }
+
+        self.rtp_authenticated(ssrc, srtp_index);
+
+        Some(output)
+    }
+
+    fn check_rtp_replay(&mut self, ssrc: u32, srtp_index: u64) -> bool {
+        let Some(window) = self.replay.rtp.get(&ssrc) else {
+            return true;
+        };
+
+        if !window.check(srtp_index) {
+            trace!("Drop replayed SRTP {} {}", ssrc, srtp_index);
+            self.replay.dropped_rtp += 1;
+            return false;
+        }
+
+        true
+    }
+
+    /// Whether an authenticated packet is held back by the probation of its SSRC.
+    ///
+    /// SSRCs that come back after their state was dropped are on probation. So are new
+    /// ones when at the max number of SSRCs, so that single packets can't evict the state
+    /// of other SSRCs. The probation is over after SSRC_PROBATION packets in sequence.
+    fn in_probation(&mut self, ssrc: u32, srtp_index: u64) -> bool {
+        let probation = &mut self.ssrcs.probation;
+        let pos = match probation.iter().position(|(s, _)| *s == ssrc) {
+            Some(pos) => pos,
+            None => {
+                let known = self.replay.rtp.contains_key(&ssrc)
+                    || self.roc.initial.contains_key(&ssrc)
+                    || self.ssrcs.used.contains_key(&ssrc);
+                if known || self.ssrcs.used.len() < self.ssrcs.max {
+                    return false;
+                }
+                self.start_probation(ssrc, srtp_index);
+                self.ssrcs.probation.len() - 1
             }
+        };
+
+        let (_, probation) = &mut self.ssrcs.probation[pos];
+
+        if probation.next == srtp_index {
+            probation.left -= 1;
+        } else {
+            probation.left = SSRC_PROBATION - 1;
+        }
+        probation.next = srtp_index + 1;
+
+        if probation.left == 0 {
+            self.ssrcs.probation.remove(pos);
+            return false;
+        }
+
+        trace!("Hold SRTP {} {} in probation", ssrc, srtp_index);
+        true
+    }
+
+    fn start_probation(&mut self, ssrc: u32, next: u64) {
+        let probation = &mut self.ssrcs.probation;
+        probation.retain(|(s, _)| *s != ssrc);
+        probation.push_back((
+            ssrc,
+            Probation {
+                next,
+                left: SSRC_PROBATION,
+            },
+        ));
+        while probation.len() > self.ssrcs.max {
+            probation.pop_front();
         }
     }
 
-    pub fn unprotect_rtp(
+    fn rtp_authenticated(&mut self, ssrc: u32, srtp_index: u64) {
+        // Only authenticated packets may update the replay list and ROC.
+        let size = self.replay.size;
+        self.replay
+            .rtp
+            .entry(ssrc)
+            .or_insert_with(|| ReplayWindow::new(size))
+            .update(srtp_index);
+
+        self.roc.initial.remove(&ssrc);
+        let highest = self.roc.highest.entry(ssrc).or_insert(srtp_index);
+        *highest = (*highest).max(srtp_index);
+
+        self.ssrc_used(ssrc);
+    }
+
+    fn do_unprotect_rtp(
         &mut self,
         buf: &[u8],
         header: &RtpHeader,
-        srtp_index: u64, // same as ext_seq
+        srtp_index: u64,
     ) -> Option<Vec<u8>> {
+        let profile = self.rtp.profile();
+
         match &mut self.rtp {
             #[cfg(feature = "_internal_test_exports")]
             Derived::PassThrough => Some(buf.to_vec()),
+            Derived::NullDebug => {
+                let end = buf.len().checked_sub(NULL_DEBUG_TAG_LEN)?;
+                buf.get(header.header_len..end).map(|b| b.to_vec())
+            }
             Derived::Aes128CmSha1_80 { key, salt, dec, .. } => {
                 use aes_128_cm_sha1_80::HMAC_TAG_LEN;
 
@@ -220,44 +881,55 @@ impl SrtpContext {
 
                 Some(output)
             }
-            Derived::AeadAes128Gcm { salt, dec, .. } => {
-                use aead_aes_128_gcm::TAG_LEN;
-
-                if buf.len() < TAG_LEN {
-                    return None;
-                }
-
-                let roc: u32 = (srtp_index >> 16) as u32;
-                let seq = header.sequence_number;
-
-                let iv = aead_aes_128_gcm::rtp_iv(*salt, *header.ssrc, roc, seq);
-
-                let (aad, input) = buf.split_at(header.header_len);
-                // Input and output lengths for decryption: https://www.rfc-editor.org/rfc/rfc7714#section-5.2.2
-                let mut output = vec![0; input.len() - TAG_LEN];
-
-                match dec.decrypt(&iv, &[aad], input, &mut output) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        warn!("Failed to decrypt SRTP ({}): {:?}", self.rtp.profile(), e);
-                        return None;
-                    }
-                };
-
-                Some(output)
-            }
+            Derived::AeadAes128Gcm { salt, dec, .. } => aead_unprotect_rtp(
+                *salt,
+                buf,
+                header,
+                srtp_index,
+                profile,
+                |iv, aads, input, output| dec.decrypt(iv, aads, input, output),
+            ),
+            Derived::AeadAes256Gcm { salt, dec, .. } => aead_unprotect_rtp(
+                *salt,
+                buf,
+                header,
+                srtp_index,
+                profile,
+                |iv, aads, input, output| dec.decrypt(iv, aads, input, output),
+            ),
         }
     }
 
-    pub fn protect_rtcp(&mut self, buf: &[u8]) -> Vec<u8> {
-        let srtcp_index = self.srtcp_index;
+    /// Whether all 2^31 SRTCP indexes have been used. Sending more SRTCP requires a rekey.
+    pub fn srtcp_index_exhausted(&self) -> bool {
+        self.srtcp_index > MAX_SRTCP_INDEX
+    }
 
+    /// Require incoming SRTCP to be encrypted (E-flag set).
+    #[cfg(test)]
+    pub fn set_require_encrypted_rtcp(&mut self, require: bool) {
+        self.require_encrypted_rtcp = require;
+    }
+
+    pub fn protect_rtcp(&mut self, buf: &[u8]) -> Result<Vec<u8>, RtpError> {
         // https://tools.ietf.org/html/rfc3711#page-15
         // The SRTCP index MUST be set to zero before the first SRTCP
         // packet is sent, and MUST be incremented by one,
         // modulo 2^31, after each SRTCP packet is sent.
-        self.srtcp_index = (self.srtcp_index + 1) % 2_u32.pow(31);
+        //
+        // Wrapping would reuse an index (and IV) under the same key, so we stop instead.
+        if self.srtcp_index_exhausted() {
+            return Err(RtpError::SrtcpIndexExhausted);
+        }
+
+        let srtcp_index = self.srtcp_index;
+        self.srtcp_index += 1;
+        self.packets.rtcp += 1;
+
+        Ok(self.do_protect_rtcp(buf, srtcp_index))
+    }
 
+    fn do_protect_rtcp(&mut self, buf: &[u8], srtcp_index: u32) -> Vec<u8> {
         // e is always encrypted, rest is 31 byte index.
         let e_and_si = 0x8000_0000 | srtcp_index;
         let ssrc = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
@@ -269,6 +941,12 @@ impl SrtpContext {
         match &mut self.rtcp {
             #[cfg(feature = "_internal_test_exports")]
             Derived::PassThrough => buf.to_vec(),
+            Derived::NullDebug => {
+                let mut output = buf.to_vec();
+                output.extend_from_slice(&e_and_si.to_be_bytes());
+                output.resize(output.len() + NULL_DEBUG_TAG_LEN, 0);
+                output
+            }
             Derived::Aes128CmSha1_80 { key, salt, enc, .. } => {
                 use aes_128_cm_sha1_80::HMAC_TAG_LEN;
 
@@ -290,27 +968,14 @@ impl SrtpContext {
                 output
             }
             Derived::AeadAes128Gcm { salt, enc, .. } => {
-                use aead_aes_128_gcm::{RTCP_AAD_LEN, TAG_LEN};
-                let iv = aead_aes_128_gcm::rtcp_iv(*salt, ssrc, srtcp_index);
-
-                let mut aad = [0; RTCP_AAD_LEN];
-                aad[..8].copy_from_slice(&buf[..8]);
-                aad[8..12].copy_from_slice(&e_and_si.to_be_bytes());
-
-                let mut output = vec![0_u8; buf.len() + SRTCP_INDEX_LEN + TAG_LEN];
-                output[0..8].copy_from_slice(&buf[0..8]);
-                let input = &buf[8..];
-
-                let enc_start = 8;
-                let enc_end = input.len() + 8 + TAG_LEN;
-                let encout = &mut output[enc_start..enc_end];
-
-                enc.encrypt(&iv, &aad, input, encout).expect("rtcp encrypt");
-
-                let to = &mut output[enc_end..];
-                to[0..4].copy_from_slice(&e_and_si.to_be_bytes());
-
-                output
+                aead_protect_rtcp(*salt, buf, srtcp_index, |iv, aad, input, output| {
+                    enc.encrypt(iv, aad, input, output)
+                })
+            }
+            Derived::AeadAes256Gcm { salt, enc, .. } => {
+                aead_protect_rtcp(*salt, buf, srtcp_index, |iv, aad, input, output| {
+                    enc.encrypt(iv, aad, input, output)
+                })
             }
         }
     }
@@ -324,26 +989,80 @@ impl SrtpContext {
     //                  |--------------------------------------|
     //                              encrypted (aes)
     pub fn unprotect_rtcp(&mut self, buf: &[u8]) -> Option<Vec<u8>> {
-        match &mut self.rtcp {
-            #[cfg(feature = "_internal_test_exports")]
-            Derived::PassThrough => Some(buf.to_vec()),
-            Derived::Aes128CmSha1_80 { key, salt, dec, .. } => {
-                use aes_128_cm_sha1_80::HMAC_TAG_LEN;
+        let Some((ssrc, srtcp_index, encrypted)) = self.rtcp.srtcp_index(buf) else {
+            return self.do_unprotect_rtcp(buf);
+        };
 
-                if buf.len() < HMAC_TAG_LEN + SRTCP_INDEX_LEN {
-                    return None;
-                }
+        if !encrypted && self.require_encrypted_rtcp {
+            trace!("Drop unencrypted SRTCP {} {}", ssrc, srtcp_index);
+            return None;
+        }
 
-                let hmac_start = buf.len() - HMAC_TAG_LEN;
+        self.restore_ssrc(ssrc);
 
-                if !aes_128_cm_sha1_80::rtcp_verify(key, &buf[..hmac_start], &buf[hmac_start..]) {
-                    trace!("unprotect_rtcp hmac verify fail");
-                    return None;
+        // Check the replay list before doing any decryption work.
+        if let Some(window) = self.replay.rtcp.get(&ssrc) {
+            if !window.check(srtcp_index as u64) {
+                // The SRTCP index restarts on rekey, this might be from the previous keys.
+                if let Some(previous) = &mut self.previous {
+                    return previous.unprotect_rtcp(buf);
                 }
 
-                let idx_start = hmac_start - SRTCP_INDEX_LEN;
+                trace!("Drop replayed SRTCP {} {}", ssrc, srtcp_index);
+                self.replay.dropped_rtcp += 1;
+                return None;
+            }
+        }
 
-                let srtcp_index_be = [
+        let Some(output) = self.do_unprotect_rtcp(buf) else {
+            // Sent with the keys from before a rekey.
+            return self.previous.as_mut()?.unprotect_rtcp(buf);
+        };
+
+        self.packets.rtcp += 1;
+
+        // Only authenticated packets may update the replay list.
+        let size = self.replay.size;
+        self.replay
+            .rtcp
+            .entry(ssrc)
+            .or_insert_with(|| ReplayWindow::new(size))
+            .update(srtcp_index as u64);
+
+        self.ssrc_used(ssrc);
+
+        Some(output)
+    }
+
+    fn do_unprotect_rtcp(&mut self, buf: &[u8]) -> Option<Vec<u8>> {
+        let profile = self.rtcp.profile();
+
+        match &mut self.rtcp {
+            #[cfg(feature = "_internal_test_exports")]
+            Derived::PassThrough => Some(buf.to_vec()),
+            Derived::NullDebug => {
+                let end = buf
+                    .len()
+                    .checked_sub(SRTCP_INDEX_LEN + NULL_DEBUG_TAG_LEN)?;
+                (end >= 8).then(|| buf[..end].to_vec())
+            }
+            Derived::Aes128CmSha1_80 { key, salt, dec, .. } => {
+                use aes_128_cm_sha1_80::HMAC_TAG_LEN;
+
+                if buf.len() < HMAC_TAG_LEN + SRTCP_INDEX_LEN {
+                    return None;
+                }
+
+                let hmac_start = buf.len() - HMAC_TAG_LEN;
+
+                if !aes_128_cm_sha1_80::rtcp_verify(key, &buf[..hmac_start], &buf[hmac_start..]) {
+                    trace!("unprotect_rtcp hmac verify fail");
+                    return None;
+                }
+
+                let idx_start = hmac_start - SRTCP_INDEX_LEN;
+
+                let srtcp_index_be = [
                     buf[idx_start],
                     buf[idx_start + 1],
                     buf[idx_start + 2],
@@ -382,82 +1101,215 @@ impl SrtpContext {
                 Some(output)
             }
             Derived::AeadAes128Gcm { salt, dec, .. } => {
-                use aead_aes_128_gcm::{RTCP_AAD_LEN, TAG_LEN};
+                aead_unprotect_rtcp(*salt, buf, profile, |iv, aads, input, output| {
+                    dec.decrypt(iv, aads, input, output)
+                })
+            }
+            Derived::AeadAes256Gcm { salt, dec, .. } => {
+                aead_unprotect_rtcp(*salt, buf, profile, |iv, aads, input, output| {
+                    dec.decrypt(iv, aads, input, output)
+                })
+            }
+        }
+    }
+}
 
-                if buf.len() < SRTCP_INDEX_LEN + TAG_LEN {
-                    // Too short
-                    return None;
-                }
+type AeadIv = [u8; aead_aes_128_gcm::IV_LEN];
 
-                let idx_start = buf.len() - SRTCP_INDEX_LEN;
+// The AEAD profiles (AEAD_AES_128_GCM and AEAD_AES_256_GCM) only differ in key length,
+// the packet handling below is shared between them.
+// See: https://www.rfc-editor.org/rfc/rfc7714
 
-                // Assume no MKI
-                let e_and_si = u32::from_be_bytes(
-                    buf[idx_start..buf.len()]
-                        .try_into()
-                        // This is ok because SRTCP_INDEX_LEN is 4 bytes and the buffer is at least
-                        // that long.
-                        .expect("SRTCP_INDEX_LEN to be 4"),
-                );
-                let is_encrypted = e_and_si & 0x8000_0000 > 0;
+#[cfg(any(test, feature = "_internal_test_exports"))]
+fn aead_protect_rtp(
+    salt: aead_aes_128_gcm::RtpSalt,
+    buf: &[u8],
+    header: &RtpHeader,
+    srtp_index: u64,
+    encrypt: impl FnOnce(&AeadIv, &[u8], &[u8], &mut [u8]) -> Result<(), CryptoError>,
+) -> Vec<u8> {
+    use aead_aes_128_gcm::TAG_LEN;
+    let hlen = header.header_len;
+    let input = &buf[hlen..];
+    let roc = (srtp_index >> 16) as u32;
 
-                // The Encrypted Portion of an SRTCP packet consists of the encryption
-                // of the RTCP payload of the equivalent compound RTCP packet, from the
-                // first RTCP packet, i.e., from the ninth (9) octet to the end of the
-                // compound packet.
-                let input = if is_encrypted {
-                    &buf[8..idx_start]
-                } else {
-                    // No, encryption but we still pass the tag down to decrypt so it can verify
-                    // it.
-                    &buf[idx_start - TAG_LEN..idx_start]
-                };
+    let iv = aead_aes_128_gcm::rtp_iv(salt, *header.ssrc, roc, header.sequence_number);
+    let aad = &buf[..hlen];
 
-                // The SRTCP index is a 31-bit counter for the SRTCP packet.
-                let srtcp_index = e_and_si & 0x7fff_ffff;
-                let ssrc = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    // Input and output lengths for encryption: https://www.rfc-editor.org/rfc/rfc7714#section-5.2.1
+    let mut output = vec![0_u8; buf.len() + TAG_LEN];
+    encrypt(&iv, aad, input, &mut output[hlen..]).expect("rtp encrypt");
 
-                let iv = aead_aes_128_gcm::rtcp_iv(*salt, ssrc, srtcp_index);
-                // Declared out here for lifetime purposes, only used in the first branch of the if.
-                let mut encrypted_aad = [0; RTCP_AAD_LEN];
-                let mut aads: [&[u8]; 2] = [&[], &[]];
+    output[..hlen].copy_from_slice(aad);
 
-                if is_encrypted {
-                    encrypted_aad[0..8].copy_from_slice(&buf[0..8]);
-                    encrypted_aad[8..12].copy_from_slice(&e_and_si.to_be_bytes());
+    output
+}
 
-                    aads[0] = encrypted_aad.as_slice();
-                } else {
-                    // The whole packet is AAD
-                    aads[0] = &buf[0..idx_start - TAG_LEN];
-                    aads[1] = &buf[idx_start..];
-                };
+fn aead_unprotect_rtp(
+    salt: aead_aes_128_gcm::RtpSalt,
+    buf: &[u8],
+    header: &RtpHeader,
+    srtp_index: u64,
+    profile: SrtpProfile,
+    decrypt: impl FnOnce(&AeadIv, &[&[u8]], &[u8], &mut [u8]) -> Result<usize, CryptoError>,
+) -> Option<Vec<u8>> {
+    use aead_aes_128_gcm::TAG_LEN;
+
+    if buf.len() < header.header_len + TAG_LEN {
+        return None;
+    }
 
-                let mut output = vec![0_u8; buf.len() - TAG_LEN - SRTCP_INDEX_LEN];
-                output[0..8].copy_from_slice(&buf[0..8]);
+    let roc: u32 = (srtp_index >> 16) as u32;
+    let seq = header.sequence_number;
 
-                let count = match dec.decrypt(&iv, &aads, input, &mut output[8..]) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        warn!("Failed to decrypt SRTCP ({}): {:?}", self.rtcp.profile(), e);
-                        return None;
-                    }
-                };
+    let iv = aead_aes_128_gcm::rtp_iv(salt, *header.ssrc, roc, seq);
 
-                if is_encrypted {
-                    output.truncate(8 + count);
-                } else {
-                    // decrypt didn't error, the data is authenticated.
-                    output.copy_from_slice(&buf[0..buf.len() - SRTCP_INDEX_LEN - TAG_LEN])
-                }
+    let (aad, input) = buf.split_at(header.header_len);
+    // Input and output lengths for decryption: https://www.rfc-editor.org/rfc/rfc7714#section-5.2.2
+    let mut output = vec![0; input.len() - TAG_LEN];
 
-                Some(output)
-            }
+    if let Err(e) = decrypt(&iv, &[aad], input, &mut output) {
+        warn!("Failed to decrypt SRTP ({}): {:?}", profile, e);
+        return None;
+    }
+
+    Some(output)
+}
+
+fn aead_protect_rtp_in_place(
+    salt: aead_aes_128_gcm::RtpSalt,
+    buf: &mut [u8],
+    len: usize,
+    header: &RtpHeader,
+    srtp_index: u64,
+    encrypt: impl FnOnce(&AeadIv, &[u8], &mut [u8]) -> Result<(), CryptoError>,
+) -> Result<usize, RtpError> {
+    use aead_aes_128_gcm::TAG_LEN;
+    let roc = (srtp_index >> 16) as u32;
+
+    let iv = aead_aes_128_gcm::rtp_iv(salt, *header.ssrc, roc, header.sequence_number);
+
+    let (aad, rest) = buf.split_at_mut(header.header_len);
+    let body_len = len - header.header_len;
+
+    encrypt(&iv, aad, &mut rest[..body_len + TAG_LEN])?;
+
+    Ok(len + TAG_LEN)
+}
+
+fn aead_protect_rtcp(
+    salt: aead_aes_128_gcm::RtpSalt,
+    buf: &[u8],
+    srtcp_index: u32,
+    encrypt: impl FnOnce(&AeadIv, &[u8], &[u8], &mut [u8]) -> Result<(), CryptoError>,
+) -> Vec<u8> {
+    use aead_aes_128_gcm::{RTCP_AAD_LEN, TAG_LEN};
+
+    // e is always encrypted, rest is 31 byte index.
+    let e_and_si = 0x8000_0000 | srtcp_index;
+    let ssrc = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+
+    let iv = aead_aes_128_gcm::rtcp_iv(salt, ssrc, srtcp_index);
+
+    let mut aad = [0; RTCP_AAD_LEN];
+    aad[..8].copy_from_slice(&buf[..8]);
+    aad[8..12].copy_from_slice(&e_and_si.to_be_bytes());
+
+    let mut output = vec![0_u8; buf.len() + SRTCP_INDEX_LEN + TAG_LEN];
+    output[0..8].copy_from_slice(&buf[0..8]);
+    let input = &buf[8..];
+
+    let enc_start = 8;
+    let enc_end = input.len() + 8 + TAG_LEN;
+    let encout = &mut output[enc_start..enc_end];
+
+    encrypt(&iv, &aad, input, encout).expect("rtcp encrypt");
+
+    let to = &mut output[enc_end..];
+    to[0..4].copy_from_slice(&e_and_si.to_be_bytes());
+
+    output
+}
+
+fn aead_unprotect_rtcp(
+    salt: aead_aes_128_gcm::RtpSalt,
+    buf: &[u8],
+    profile: SrtpProfile,
+    decrypt: impl FnOnce(&AeadIv, &[&[u8]], &[u8], &mut [u8]) -> Result<usize, CryptoError>,
+) -> Option<Vec<u8>> {
+    use aead_aes_128_gcm::{RTCP_AAD_LEN, TAG_LEN};
+
+    if buf.len() < 8 + SRTCP_INDEX_LEN + TAG_LEN {
+        // Too short
+        return None;
+    }
+
+    let idx_start = buf.len() - SRTCP_INDEX_LEN;
+
+    // Assume no MKI
+    let e_and_si = u32::from_be_bytes(
+        buf[idx_start..buf.len()]
+            .try_into()
+            // This is ok because SRTCP_INDEX_LEN is 4 bytes and the buffer is at least
+            // that long.
+            .expect("SRTCP_INDEX_LEN to be 4"),
+    );
+    let is_encrypted = e_and_si & 0x8000_0000 > 0;
+
+    // The Encrypted Portion of an SRTCP packet consists of the encryption
+    // of the RTCP payload of the equivalent compound RTCP packet, from the
+    // first RTCP packet, i.e., from the ninth (9) octet to the end of the
+    // compound packet.
+    let input = if is_encrypted {
+        &buf[8..idx_start]
+    } else {
+        // No, encryption but we still pass the tag down to decrypt so it can verify
+        // it.
+        &buf[idx_start - TAG_LEN..idx_start]
+    };
+
+    // The SRTCP index is a 31-bit counter for the SRTCP packet.
+    let srtcp_index = e_and_si & 0x7fff_ffff;
+    let ssrc = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+
+    let iv = aead_aes_128_gcm::rtcp_iv(salt, ssrc, srtcp_index);
+    // Declared out here for lifetime purposes, only used in the first branch of the if.
+    let mut encrypted_aad = [0; RTCP_AAD_LEN];
+    let mut aads: [&[u8]; 2] = [&[], &[]];
+
+    if is_encrypted {
+        encrypted_aad[0..8].copy_from_slice(&buf[0..8]);
+        encrypted_aad[8..12].copy_from_slice(&e_and_si.to_be_bytes());
+
+        aads[0] = encrypted_aad.as_slice();
+    } else {
+        // The whole packet is AAD
+        aads[0] = &buf[0..idx_start - TAG_LEN];
+        aads[1] = &buf[idx_start..];
+    };
+
+    let mut output = vec![0_u8; buf.len() - TAG_LEN - SRTCP_INDEX_LEN];
+    output[0..8].copy_from_slice(&buf[0..8]);
+
+    let count = match decrypt(&iv, &aads, input, &mut output[8..]) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Failed to decrypt SRTCP ({}): {:?}", profile, e);
+            return None;
         }
+    };
+
+    if is_encrypted {
+        output.truncate(8 + count);
+    } else {
+        // decrypt didn't error, the data is authenticated.
+        output.copy_from_slice(&buf[0..buf.len() - SRTCP_INDEX_LEN - TAG_LEN])
     }
+
+    Some(output)
 }
 
-/// SrtpKeys created from DTLS SrtpKeyMaterial.
+/// Master key and salt for one direction, see [`KeyingMaterial::derive`].
 #[derive(Debug)]
 struct SrtpKey<const ML: usize, const SL: usize> {
     master: [u8; ML],
@@ -465,35 +1317,30 @@ struct SrtpKey<const ML: usize, const SL: usize> {
 }
 
 impl<const ML: usize, const SL: usize> SrtpKey<ML, SL> {
-    pub fn new(mat: &KeyingMaterial, left: bool) -> Self {
-        // layout in SrtpKeyMaterial is [key_input, key_output, salt_input, salt_output]
-
-        // Invariant
-        assert!(
-            mat.len() == ML * 2 + SL * 2,
-            "The KeyingMaterial provided to SrtpKey::new must be ML * 2 + SL * 2 in length"
-        );
-
-        // offset 0, offset 1
-        let (o0, o1) = if left { (0, 0) } else { (ML, SL) };
-
-        let mut master = [0; ML];
-        let mut salt = [0; SL];
-
-        master[0..ML].copy_from_slice(&mat[o0..(o0 + ML)]);
-        salt[0..SL].copy_from_slice(&mat[(ML + ML + o1)..(ML + ML + o1 + SL)]);
+    pub fn new(keys: &SrtpKeys) -> Self {
+        let master = keys
+            .master()
+            .try_into()
+            .expect("SRTP master key length to match profile");
+        let salt = keys
+            .salt()
+            .try_into()
+            .expect("SRTP master salt length to match profile");
 
         SrtpKey { master, salt }
     }
 
     fn derive(&self, label: u8, out: &mut [u8]) {
-        // AEC-CM (128 bits) defined in RFC3711
-        assert!(ML == 16, "Only valid for 128 bit master keys");
-        assert!(SL <= 14, "Only valid for 128 bit master keys");
+        // AES-CM (128 bits) defined in RFC3711, AES-CM (256 bits) defined in RFC6188.
+        assert!(
+            ML == 16 || ML == 32,
+            "Only valid for 128 or 256 bit master keys"
+        );
+        assert!(SL <= 14, "Only valid for salts up to 112 bits");
         let mut i = 0; // index in out
 
         // input layout: [salt[SL] || label, round[2]] (|| is xor 7th byte)
-        let mut input = [0; ML];
+        let mut input = [0; SRTP_BLOCK_SIZE];
 
         input[0..SL].copy_from_slice(&self.salt[..]);
         input[7] ^= label;
@@ -510,8 +1357,12 @@ impl<const ML: usize, const SL: usize> SrtpKey<ML, SL> {
             // splice in round at bottom of input
             input[14..].copy_from_slice(&round.to_be_bytes()[..]);
 
-            // default key derivation function, which uses AES-128 in Counter Mode
-            crypto::srtp_aes_128_ecb_round(&self.master, &input[..], &mut buf[..]);
+            // default key derivation function, which uses AES in Counter Mode
+            if ML == 32 {
+                crypto::srtp_aes_256_ecb_round(&self.master, &input[..], &mut buf[..]);
+            } else {
+                crypto::srtp_aes_128_ecb_round(&self.master, &input[..], &mut buf[..]);
+            }
 
             // Copy to output. Even if we get 32 bytes of output with AES 128 ECB, we
             // only use the first 16. That matches the tests in the RFC.
@@ -532,6 +1383,7 @@ impl<const ML: usize, const SL: usize> SrtpKey<ML, SL> {
 enum Derived {
     #[cfg(feature = "_internal_test_exports")]
     PassThrough,
+    NullDebug,
     Aes128CmSha1_80 {
         key: [u8; 20],
         salt: aes_128_cm_sha1_80::RtpSalt,
@@ -543,6 +1395,11 @@ enum Derived {
         enc: Box<dyn aead_aes_128_gcm::CipherCtx>,
         dec: Box<dyn aead_aes_128_gcm::CipherCtx>,
     },
+    AeadAes256Gcm {
+        salt: aead_aes_256_gcm::RtpSalt,
+        enc: Box<dyn aead_aes_256_gcm::CipherCtx>,
+        dec: Box<dyn aead_aes_256_gcm::CipherCtx>,
+    },
 }
 
 impl Derived {
@@ -598,116 +1455,749 @@ impl Derived {
         (rtp, rtcp)
     }
 
-    fn aead_aes_128_gcm(
-        srtp_key: &SrtpKey<{ aead_aes_128_gcm::KEY_LEN }, { aead_aes_128_gcm::SALT_LEN }>,
-    ) -> (Derived, Derived) {
-        use aead_aes_128_gcm::*;
+    fn aead_aes_128_gcm(
+        srtp_key: &SrtpKey<{ aead_aes_128_gcm::KEY_LEN }, { aead_aes_128_gcm::SALT_LEN }>,
+    ) -> (Derived, Derived) {
+        use aead_aes_128_gcm::*;
+
+        // RTP session key
+        let mut rtp_aes = [0; KEY_LEN];
+        srtp_key.derive(LABEL_RTP_AES, &mut rtp_aes[..]);
+
+        // RTP session salt
+        let mut rtp_salt = [0; SALT_LEN];
+        srtp_key.derive(LABEL_RTP_SALT, &mut rtp_salt[..]);
+
+        // RTCP session key
+        let mut rtcp_aes = [0; KEY_LEN];
+        srtp_key.derive(LABEL_RTCP_AES, &mut rtcp_aes[..]);
+
+        // RTCP session salt
+        let mut rtcp_salt = [0; SALT_LEN];
+        srtp_key.derive(LABEL_RTCP_SALT, &mut rtcp_salt[..]);
+
+        let rtp = Derived::AeadAes128Gcm {
+            salt: rtp_salt,
+            enc: new_aead_aes_128_gcm(rtp_aes, true),
+            dec: new_aead_aes_128_gcm(rtp_aes, false),
+        };
+
+        let rtcp = Derived::AeadAes128Gcm {
+            salt: rtcp_salt,
+            enc: new_aead_aes_128_gcm(rtcp_aes, true),
+            dec: new_aead_aes_128_gcm(rtcp_aes, false),
+        };
+
+        (rtp, rtcp)
+    }
+
+    fn aead_aes_256_gcm(
+        srtp_key: &SrtpKey<{ aead_aes_256_gcm::KEY_LEN }, { aead_aes_256_gcm::SALT_LEN }>,
+    ) -> (Derived, Derived) {
+        use aead_aes_256_gcm::*;
+
+        // RTP session key
+        let mut rtp_aes = [0; KEY_LEN];
+        srtp_key.derive(LABEL_RTP_AES, &mut rtp_aes[..]);
+
+        // RTP session salt
+        let mut rtp_salt = [0; SALT_LEN];
+        srtp_key.derive(LABEL_RTP_SALT, &mut rtp_salt[..]);
+
+        // RTCP session key
+        let mut rtcp_aes = [0; KEY_LEN];
+        srtp_key.derive(LABEL_RTCP_AES, &mut rtcp_aes[..]);
+
+        // RTCP session salt
+        let mut rtcp_salt = [0; SALT_LEN];
+        srtp_key.derive(LABEL_RTCP_SALT, &mut rtcp_salt[..]);
+
+        let rtp = Derived::AeadAes256Gcm {
+            salt: rtp_salt,
+            enc: new_aead_aes_256_gcm(rtp_aes, true),
+            dec: new_aead_aes_256_gcm(rtp_aes, false),
+        };
+
+        let rtcp = Derived::AeadAes256Gcm {
+            salt: rtcp_salt,
+            enc: new_aead_aes_256_gcm(rtcp_aes, true),
+            dec: new_aead_aes_256_gcm(rtcp_aes, false),
+        };
+
+        (rtp, rtcp)
+    }
+
+    /// Sender SSRC, SRTCP index and E-flag of an incoming SRTCP packet.
+    fn srtcp_index(&self, buf: &[u8]) -> Option<(u32, u32, bool)> {
+        // Length of whatever follows the SRTCP index.
+        let trailer = match self {
+            #[cfg(feature = "_internal_test_exports")]
+            Derived::PassThrough => return None,
+            Derived::NullDebug => NULL_DEBUG_TAG_LEN,
+            Derived::Aes128CmSha1_80 { .. } => aes_128_cm_sha1_80::HMAC_TAG_LEN,
+            Derived::AeadAes128Gcm { .. } | Derived::AeadAes256Gcm { .. } => 0,
+        };
+
+        if buf.len() < 8 + SRTCP_INDEX_LEN + trailer {
+            return None;
+        }
+
+        let ssrc = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+
+        let idx_start = buf.len() - trailer - SRTCP_INDEX_LEN;
+        let e_and_si = u32::from_be_bytes(
+            buf[idx_start..idx_start + SRTCP_INDEX_LEN]
+                .try_into()
+                .expect("SRTCP_INDEX_LEN to be 4"),
+        );
+
+        Some((ssrc, e_and_si & MAX_SRTCP_INDEX, e_and_si & 0x8000_0000 > 0))
+    }
+
+    fn profile(&self) -> SrtpProfile {
+        match self {
+            #[cfg(feature = "_internal_test_exports")]
+            Derived::PassThrough => SrtpProfile::PassThrough,
+            Derived::NullDebug => SrtpProfile::NullDebug,
+            Derived::Aes128CmSha1_80 { .. } => SrtpProfile::Aes128CmSha1_80,
+            Derived::AeadAes128Gcm { .. } => SrtpProfile::AeadAes128Gcm,
+            Derived::AeadAes256Gcm { .. } => SrtpProfile::AeadAes256Gcm,
+        }
+    }
+}
+
+impl fmt::Debug for Derived {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Derived")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn derive_key() {
+        // https://tools.ietf.org/html/rfc3711#appendix-B.3
+        //
+        // Key Derivation Test Vectors.
+
+        let master = [
+            0xE1, 0xF9, 0x7A, 0x0D, 0x3E, 0x01, 0x8B, 0xE0, //
+            0xD6, 0x4F, 0xA3, 0x2C, 0x06, 0xDE, 0x41, 0x39,
+        ];
+
+        let salt = [
+            0x0E, 0xC6, 0x75, 0xAD, 0x49, 0x8A, 0xFE, //
+            0xEB, 0xB6, 0x96, 0x0B, 0x3A, 0xAB, 0xE6,
+        ];
+
+        let sk = SrtpKey { master, salt };
+
+        // aes crypto key
+        let mut out = [0_u8; 16];
+        sk.derive(0, &mut out[..]);
+
+        assert_eq!(
+            out,
+            [
+                0xC6, 0x1E, 0x7A, 0x93, 0x74, 0x4F, 0x39, 0xEE, //
+                0x10, 0x73, 0x4A, 0xFE, 0x3F, 0xF7, 0xA0, 0x87
+            ]
+        );
+
+        // hmac
+        let mut out = [0_u8; 20];
+        sk.derive(1, &mut out[..]);
+
+        assert_eq!(
+            out,
+            [
+                0xCE, 0xBE, 0x32, 0x1F, 0x6F, 0xF7, 0x71, 0x6B, //
+                0x6F, 0xD4, 0xAB, 0x49, 0xAF, 0x25, 0x6A, 0x15, //
+                0x6D, 0x38, 0xBA, 0xA4
+            ]
+        );
+
+        // salt
+        let mut out = [0_u8; 14];
+        sk.derive(2, &mut out[..]);
+
+        assert_eq!(
+            out,
+            [
+                0x30, 0xCB, 0xBC, 0x08, 0x86, 0x3D, 0x8C, //
+                0x85, 0xD4, 0x9D, 0xB3, 0x4A, 0x9A, 0xE1
+            ]
+        );
+    }
+
+    #[test]
+    fn derive_key_256() {
+        // https://www.rfc-editor.org/rfc/rfc6188#section-7.2
+        //
+        // Key Derivation Test Vectors for AES-256.
+
+        let master = [
+            0xf0, 0xf0, 0x49, 0x14, 0xb5, 0x13, 0xf2, 0x76, //
+            0x3a, 0x1b, 0x1f, 0xa1, 0x30, 0xf1, 0x0e, 0x29, //
+            0x98, 0xf6, 0xf6, 0xe4, 0x3e, 0x43, 0x09, 0xd1, //
+            0xe6, 0x22, 0xa0, 0xe3, 0x32, 0xb9, 0xf1, 0xb6,
+        ];
+
+        let salt = [
+            0x3b, 0x04, 0x80, 0x3d, 0xe5, 0x1e, 0xe7, //
+            0xc9, 0x64, 0x23, 0xab, 0x5b, 0x78, 0xd2,
+        ];
+
+        let sk = SrtpKey { master, salt };
+
+        // aes crypto key
+        let mut out = [0_u8; 32];
+        sk.derive(0, &mut out[..]);
+
+        assert_eq!(
+            out,
+            [
+                0x5b, 0xa1, 0x06, 0x4e, 0x30, 0xec, 0x51, 0x61, //
+                0x3c, 0xad, 0x92, 0x6c, 0x5a, 0x28, 0xef, 0x73, //
+                0x1e, 0xc7, 0xfb, 0x39, 0x7f, 0x70, 0xa9, 0x60, //
+                0x65, 0x3c, 0xaf, 0x06, 0x55, 0x4c, 0xd8, 0xc4
+            ]
+        );
+
+        // hmac
+        let mut out = [0_u8; 20];
+        sk.derive(1, &mut out[..]);
+
+        assert_eq!(
+            out,
+            [
+                0xfd, 0x9c, 0x32, 0xd3, 0x9e, 0xd5, 0xfb, 0xb5, //
+                0xa9, 0xdc, 0x96, 0xb3, 0x08, 0x18, 0x45, 0x4d, //
+                0x13, 0x13, 0xdc, 0x05
+            ]
+        );
+
+        // salt
+        let mut out = [0_u8; 14];
+        sk.derive(2, &mut out[..]);
+
+        assert_eq!(
+            out,
+            [
+                0xfa, 0x31, 0x79, 0x16, 0x85, 0xca, 0x44, //
+                0x4a, 0x9e, 0x07, 0xc6, 0xc6, 0x4e, 0x93
+            ]
+        );
+    }
+
+    #[test]
+    fn unprotect_rtp_across_seq_wrap() {
+        use crate::rtp_::ExtensionMap;
+
+        let profiles = [
+            SrtpProfile::Aes128CmSha1_80,
+            SrtpProfile::AeadAes128Gcm,
+            SrtpProfile::AeadAes256Gcm,
+        ];
+
+        for profile in profiles {
+            let len = profile.keying_material_len();
+            let mat = KeyingMaterial::new((0..len).map(|i| (i * 7) as u8).collect());
+
+            let mut tx = SrtpContext::new(profile, &mat, true);
+            let mut rx = SrtpContext::new(profile, &mat, true);
+
+            // Index as sent. The ROC increments exactly at 65535 -> 0.
+            let packet = |tx: &mut SrtpContext, index: u64| {
+                let seq = (index as u16).to_be_bytes();
+                let mut buf = vec![0x80, 96, seq[0], seq[1], 0, 0, 0, 1, 0, 0, 0, 42];
+                buf.extend_from_slice(&[index as u8; 16]);
+
+                let header = RtpHeader::parse(&buf, &ExtensionMap::empty()).unwrap();
+                tx.protect_rtp(&buf, &header, index)
+            };
+
+            let sent: Vec<_> = [65_534, 65_535, 65_536, 65_537]
+                .into_iter()
+                .map(|i| (i, packet(&mut tx, i)))
+                .collect();
+
+            // Receive order is 65534, 1, 65535, 0.
+            for pos in [0, 3, 1, 2] {
+                let (index, buf) = &sent[pos];
+                let header = RtpHeader::parse(buf, &ExtensionMap::empty()).unwrap();
+
+                let data = rx
+                    .unprotect_rtp(buf, &header)
+                    .unwrap_or_else(|| panic!("{} failed to unprotect {}", profile, index));
+
+                assert_eq!(data, [*index as u8; 16]);
+            }
+        }
+    }
+
+    #[test]
+    fn rtp_index_estimate() {
+        let mat = KeyingMaterial::new(vec![0; 60]);
+        let mut ctx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+
+        // No packet yet, ROC 0.
+        assert_eq!(ctx.rtp_index(1, 65_000), 65_000);
+
+        ctx.roc.highest.insert(1, 65_534);
+        assert_eq!(ctx.rtp_index(1, 1), 65_537);
+        assert_eq!(ctx.rtp_index(1, 65_535), 65_535);
+
+        ctx.roc.highest.insert(1, 65_537);
+        assert_eq!(ctx.rtp_index(1, 65_535), 65_535);
+        assert_eq!(ctx.rtp_index(1, 30_000), 65_536 + 30_000);
+
+        // There is no ROC before 0.
+        ctx.roc.highest.insert(1, 10);
+        assert_eq!(ctx.rtp_index(1, 65_000), 65_000);
+
+        // Signaled out of band.
+        ctx.reset_roc(1, 3);
+        assert_eq!(ctx.rtp_index(1, 17), 3 << 16 | 17);
+    }
+
+    #[test]
+    fn rekey_without_loss() {
+        use crate::rtp_::ExtensionMap;
+
+        let profiles = [
+            SrtpProfile::Aes128CmSha1_80,
+            SrtpProfile::AeadAes128Gcm,
+            SrtpProfile::AeadAes256Gcm,
+        ];
+
+        // Sender report, ssrc 42.
+        let rtcp = [0x80, 0xc8, 0x00, 0x01, 0x00, 0x00, 0x00, 0x2a];
+
+        for profile in profiles {
+            let len = profile.keying_material_len();
+            let mat1 = KeyingMaterial::new((0..len).map(|i| (i * 7) as u8).collect());
+            let mat2 = KeyingMaterial::new((0..len).map(|i| (i * 11) as u8).collect());
+
+            let mut tx = SrtpContext::new(profile, &mat1, true);
+            let mut rx = SrtpContext::new(profile, &mat1, true);
+
+            let packet = |tx: &mut SrtpContext, index: u64| {
+                let seq = (index as u16).to_be_bytes();
+                let mut buf = vec![0x80, 96, seq[0], seq[1], 0, 0, 0, 1, 0, 0, 0, 42];
+                buf.extend_from_slice(&[index as u8; 16]);
+
+                let header = RtpHeader::parse(&buf, &ExtensionMap::empty()).unwrap();
+                tx.protect_rtp(&buf, &header, index)
+            };
+
+            let unprotect = |rx: &mut SrtpContext, buf: &[u8]| {
+                let header = RtpHeader::parse(buf, &ExtensionMap::empty()).unwrap();
+                rx.unprotect_rtp(buf, &header)
+            };
+
+            // Seq numbers continue across the rekey, which happens during a wrap.
+            let old: Vec<_> = (65_530..65_536).map(|i| packet(&mut tx, i)).collect();
+            let old_rtcp: Vec<_> = (0..3).map(|_| tx.protect_rtcp(&rtcp).unwrap()).collect();
+
+            tx = SrtpContext::new(profile, &mat2, true);
+            let new: Vec<_> = (65_536..65_542).map(|i| packet(&mut tx, i)).collect();
+            let new_rtcp: Vec<_> = (0..3).map(|_| tx.protect_rtcp(&rtcp).unwrap()).collect();
+
+            // Receive half of the old packets before the switch.
+            for (i, buf) in old[..3].iter().enumerate() {
+                let data = unprotect(&mut rx, buf).unwrap();
+                assert_eq!(data, [(65_530 + i) as u8; 16]);
+            }
+            assert_eq!(rx.unprotect_rtcp(&old_rtcp[0]).unwrap(), rtcp);
+
+            rx.rekey_rx(SrtpContext::new(profile, &mat2, true));
+
+            // New and in flight old packets, interleaved.
+            for index in [65_536, 65_533, 65_537, 65_534, 65_535, 65_538] {
+                let buf = if index < 65_536 {
+                    &old[index - 65_530]
+                } else {
+                    &new[index - 65_536]
+                };
+
+                let data = unprotect(&mut rx, buf)
+                    .unwrap_or_else(|| panic!("{} failed to unprotect {}", profile, index));
+                assert_eq!(data, [index as u8; 16]);
+            }
+
+            // The SRTCP index restarts with the new key.
+            assert_eq!(rx.unprotect_rtcp(&new_rtcp[0]).unwrap(), rtcp);
+            assert_eq!(rx.unprotect_rtcp(&new_rtcp[1]).unwrap(), rtcp);
+            assert_eq!(rx.unprotect_rtcp(&old_rtcp[1]).unwrap(), rtcp);
+            assert_eq!(rx.unprotect_rtcp(&old_rtcp[2]).unwrap(), rtcp);
+
+            // Replays are still caught.
+            assert!(unprotect(&mut rx, &old[4]).is_none());
+            assert!(unprotect(&mut rx, &new[1]).is_none());
+            assert!(rx.unprotect_rtcp(&old_rtcp[1]).is_none());
+            assert!(rx.unprotect_rtcp(&new_rtcp[0]).is_none());
+
+            rx.drop_previous();
+
+            // Gone with the previous keys.
+            assert!(rx.unprotect_rtcp(&old_rtcp[0]).is_none());
+            assert!(unprotect(&mut rx, &new[3]).is_some());
+        }
+    }
+
+    #[test]
+    fn key_expiring() {
+        let mat = KeyingMaterial::new(vec![0; 60]);
+        let mut ctx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+
+        assert!(!ctx.key_expiring(MAX_SRTP_KEY_LIFETIME));
+        assert!(!ctx.key_expiring(100));
+
+        ctx.packets.rtp = 89;
+        assert!(!ctx.key_expiring(100));
+
+        ctx.packets.rtp = 90;
+        assert!(ctx.key_expiring(100));
+        assert!(!ctx.key_expiring(MAX_SRTP_KEY_LIFETIME));
+
+        ctx.packets.rtcp = (MAX_SRTCP_INDEX as u64 + 1) / 10 * 9;
+        assert!(ctx.key_expiring(MAX_SRTP_KEY_LIFETIME));
+    }
+
+    fn test_rtp_packet(index: u64, body_len: usize) -> Vec<u8> {
+        let seq = (index as u16).to_be_bytes();
+        let mut buf = vec![0x80, 96, seq[0], seq[1], 0, 0, 0, 1, 0, 0, 0, 42];
+        buf.extend((0..body_len).map(|i| (i as u64 + index) as u8));
+        buf
+    }
+
+    #[test]
+    fn protect_rtp_in_place_equivalence() {
+        use crate::rtp_::ExtensionMap;
+
+        let profiles = [
+            SrtpProfile::Aes128CmSha1_80,
+            SrtpProfile::AeadAes128Gcm,
+            SrtpProfile::AeadAes256Gcm,
+        ];
+
+        for profile in profiles {
+            let len = profile.keying_material_len();
+            let mat = KeyingMaterial::new((0..len).map(|i| (i * 5) as u8).collect());
+
+            let mut tx_copy = SrtpContext::new(profile, &mat, true);
+            let mut tx_in_place = SrtpContext::new(profile, &mat, true);
+            let mut rx = SrtpContext::new(profile, &mat, true);
+
+            for index in 65_530..65_545 {
+                let packet = test_rtp_packet(index, 16 * (index as usize % 5));
+                let header = RtpHeader::parse(&packet, &ExtensionMap::empty()).unwrap();
+
+                let copied = tx_copy.protect_rtp(&packet, &header, index);
+
+                let mut buf = packet.clone();
+                buf.resize(packet.len() + SRTP_OVERHEAD, 0);
+                let n = tx_in_place
+                    .protect_rtp_in_place(&mut buf, packet.len(), &header, index)
+                    .unwrap();
+
+                assert_eq!(&buf[..n], copied, "{} protect {}", profile, index);
+
+                // Tampering is caught, and doesn't update the replay window.
+                let mut tampered = copied.clone();
+                *tampered.last_mut().unwrap() ^= 1;
+                assert!(rx.unprotect_rtp(&tampered, &header).is_none());
+
+                let payload = rx.unprotect_rtp(&copied, &header).unwrap();
+                assert_eq!(
+                    payload,
+                    packet[header.header_len..],
+                    "{} unprotect {}",
+                    profile,
+                    index
+                );
+
+                // Replays are caught.
+                assert!(rx.unprotect_rtp(&copied, &header).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn null_debug() {
+        use crate::rtp_::ExtensionMap;
+
+        let profile = SrtpProfile::NullDebug;
+
+        // Only ever set programmatically.
+        assert!(!SrtpProfile::ALL.contains(&profile));
+        assert_eq!(SrtpProfile::from_id(profile.id()), None);
+
+        let mat = KeyingMaterial::new(vec![]);
+        let mut tx = SrtpContext::new(profile, &mat, true);
+        let mut rx = SrtpContext::new(profile, &mat, false);
+
+        let mut sent = vec![];
+        for index in [65_534, 65_535, 65_536, 65_537] {
+            let packet = test_rtp_packet(index, 16);
+            let header = RtpHeader::parse(&packet, &ExtensionMap::empty()).unwrap();
+
+            let mut buf = packet.clone();
+            buf.resize(packet.len() + SRTP_OVERHEAD, 0);
+            let n = tx
+                .protect_rtp_in_place(&mut buf, packet.len(), &header, index)
+                .unwrap();
+
+            // Readable as is, followed by a zeroed tag.
+            assert_eq!(&buf[..packet.len()], packet);
+            assert_eq!(&buf[packet.len()..n], [0; NULL_DEBUG_TAG_LEN]);
+
+            sent.push((index, header, buf[..n].to_vec()));
+        }
+
+        // Same ROC and replay handling as the real profiles.
+        for pos in [0, 3, 1, 2] {
+            let (index, header, buf) = &sent[pos];
+            let data = rx.unprotect_rtp(buf, header).unwrap();
+            assert_eq!(data, test_rtp_packet(*index, 16)[12..]);
+        }
+
+        let (_, header, buf) = &sent[3];
+        assert!(rx.unprotect_rtp(buf, header).is_none());
+        assert_eq!(rx.replayed_rtp(), 1);
+
+        // Sender report, ssrc 42.
+        let rtcp = [0x80, 0xc8, 0x00, 0x01, 0x00, 0x00, 0x00, 0x2a];
+        for i in 0..2_u32 {
+            let out = tx.protect_rtcp(&rtcp).unwrap();
+
+            assert_eq!(&out[..8], rtcp);
+            assert_eq!(out[8..12], (0x8000_0000 | i).to_be_bytes());
+            assert_eq!(out.len(), 8 + SRTCP_INDEX_LEN + NULL_DEBUG_TAG_LEN);
+
+            assert_eq!(rx.unprotect_rtcp(&out).unwrap(), rtcp);
+            assert!(rx.unprotect_rtcp(&out).is_none());
+        }
+    }
+
+    #[test]
+    fn protect_rtp_in_place_no_room() {
+        use crate::rtp_::ExtensionMap;
+
+        let mat = KeyingMaterial::new(vec![0; 60]);
+        let mut ctx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+
+        let mut buf = test_rtp_packet(1, 16);
+        let header = RtpHeader::parse(&buf, &ExtensionMap::empty()).unwrap();
+        let len = buf.len();
+
+        buf.resize(len + SRTP_OVERHEAD - 1, 0);
+        assert!(matches!(
+            ctx.protect_rtp_in_place(&mut buf, len, &header, 1),
+            Err(RtpError::SrtpNoRoom)
+        ));
+    }
+
+    #[test]
+    fn srtcp_index_and_e_flag() {
+        let profiles = [
+            (
+                SrtpProfile::Aes128CmSha1_80,
+                aes_128_cm_sha1_80::HMAC_TAG_LEN,
+            ),
+            (SrtpProfile::AeadAes128Gcm, 0),
+            (SrtpProfile::AeadAes256Gcm, 0),
+        ];
+
+        // Sender report, ssrc 42.
+        let rtcp = [0x80, 0xc8, 0x00, 0x01, 0x00, 0x00, 0x00, 0x2a];
+
+        for (profile, after_index) in profiles {
+            let len = profile.keying_material_len();
+            let mat = KeyingMaterial::new((0..len).map(|i| (i * 3) as u8).collect());
+
+            let mut tx = SrtpContext::new(profile, &mat, true);
+            let mut rx = SrtpContext::new(profile, &mat, true);
+
+            for i in 0..3_u32 {
+                let out = tx.protect_rtcp(&rtcp).unwrap();
+
+                // The overhead reserved when writing RTCP must cover all profiles.
+                assert!(out.len() <= rtcp.len() + SRTCP_OVERHEAD);
+
+                let idx = out.len() - after_index - SRTCP_INDEX_LEN;
+                let e_and_si = u32::from_be_bytes(out[idx..idx + 4].try_into().unwrap());
+                assert_eq!(e_and_si, 0x8000_0000 | i, "{}", profile);
+
+                assert_eq!(rx.unprotect_rtcp(&out).unwrap(), rtcp);
+            }
+        }
+    }
+
+    #[test]
+    fn srtcp_index_exhausted() {
+        let mat = KeyingMaterial::new(vec![1; 60]);
+        let mut ctx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
 
-        // RTP session key
-        let mut rtp_aes = [0; KEY_LEN];
-        srtp_key.derive(LABEL_RTP_AES, &mut rtp_aes[..]);
+        let rtcp = [0x80, 0xc8, 0x00, 0x01, 0x00, 0x00, 0x00, 0x2a];
 
-        // RTP session salt
-        let mut rtp_salt = [0; SALT_LEN];
-        srtp_key.derive(LABEL_RTP_SALT, &mut rtp_salt[..]);
+        ctx.srtcp_index = MAX_SRTCP_INDEX;
+        assert!(ctx.protect_rtcp(&rtcp).is_ok());
+        assert!(ctx.srtcp_index_exhausted());
 
-        // RTCP session key
-        let mut rtcp_aes = [0; KEY_LEN];
-        srtp_key.derive(LABEL_RTCP_AES, &mut rtcp_aes[..]);
+        // No wrap to 0, which would reuse an index.
+        assert!(matches!(
+            ctx.protect_rtcp(&rtcp),
+            Err(RtpError::SrtcpIndexExhausted)
+        ));
+    }
 
-        // RTCP session salt
-        let mut rtcp_salt = [0; SALT_LEN];
-        srtp_key.derive(LABEL_RTCP_SALT, &mut rtcp_salt[..]);
+    fn ssrc_packet(tx: &mut SrtpContext, ssrc: u32, index: u64) -> Vec<u8> {
+        use crate::rtp_::ExtensionMap;
 
-        let rtp = Derived::AeadAes128Gcm {
-            salt: rtp_salt,
-            enc: new_aead_aes_128_gcm(rtp_aes, true),
-            dec: new_aead_aes_128_gcm(rtp_aes, false),
-        };
+        let seq = (index as u16).to_be_bytes();
+        let s = ssrc.to_be_bytes();
+        let mut buf = vec![0x80, 96, seq[0], seq[1], 0, 0, 0, 1, s[0], s[1], s[2], s[3]];
+        buf.extend_from_slice(&[index as u8; 16]);
 
-        let rtcp = Derived::AeadAes128Gcm {
-            salt: rtcp_salt,
-            enc: new_aead_aes_128_gcm(rtcp_aes, true),
-            dec: new_aead_aes_128_gcm(rtcp_aes, false),
-        };
+        let header = RtpHeader::parse(&buf, &ExtensionMap::empty()).unwrap();
+        tx.protect_rtp(&buf, &header, index)
+    }
 
-        (rtp, rtcp)
+    fn ssrc_unprotect(rx: &mut SrtpContext, buf: &[u8]) -> Option<Vec<u8>> {
+        use crate::rtp_::ExtensionMap;
+
+        let header = RtpHeader::parse(buf, &ExtensionMap::empty()).unwrap();
+        rx.unprotect_rtp(buf, &header)
     }
 
-    fn profile(&self) -> SrtpProfile {
-        match self {
-            #[cfg(feature = "_internal_test_exports")]
-            Derived::PassThrough => SrtpProfile::PassThrough,
-            Derived::Aes128CmSha1_80 { .. } => SrtpProfile::Aes128CmSha1_80,
-            Derived::AeadAes128Gcm { .. } => SrtpProfile::AeadAes128Gcm,
+    #[test]
+    fn ssrc_churn_is_bounded() {
+        let mat = KeyingMaterial::new((0..60).collect());
+        let mut tx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+        let mut rx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+        rx.set_ssrc_limits(100, DEFAULT_SSRC_IDLE);
+
+        let now = Instant::now();
+
+        for ssrc in 0..10_000 {
+            rx.handle_timeout(now);
+            let buf = ssrc_packet(&mut tx, ssrc, 1);
+            let first = ssrc_unprotect(&mut rx, &buf);
+            let buf = ssrc_packet(&mut tx, ssrc, 2);
+            assert!(ssrc_unprotect(&mut rx, &buf).is_some());
+
+            // When full, a new SSRC is on probation for the first packet.
+            assert_eq!(first.is_none(), ssrc >= 100);
+
+            assert!(rx.roc.highest.len() <= 100);
+            assert!(rx.replay.rtp.len() <= 100);
+            assert!(rx.ssrcs.used.len() <= 100);
+            assert!(rx.ssrcs.forgotten.len() <= 100);
         }
-    }
-}
 
-impl fmt::Debug for Derived {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Derived")
-    }
-}
+        assert_eq!(rx.evicted_ssrcs(), 9_900);
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        // The most recent ones are kept.
+        assert!(rx.roc.highest.contains_key(&9_999));
+        assert!(!rx.roc.highest.contains_key(&0));
+    }
 
     #[test]
-    fn derive_key() {
-        // https://tools.ietf.org/html/rfc3711#appendix-B.3
-        //
-        // Key Derivation Test Vectors.
+    fn ssrc_idle_and_bye() {
+        let mat = KeyingMaterial::new((0..60).collect());
+        let mut tx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+        let mut rx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+        rx.set_ssrc_limits(100, Duration::from_secs(10));
+
+        let now = Instant::now();
+        rx.handle_timeout(now);
+
+        // Up to just before a wrap.
+        let sent: Vec<_> = (65_530..65_540)
+            .map(|i| (ssrc_packet(&mut tx, 1, i), ssrc_packet(&mut tx, 2, i)))
+            .collect();
+
+        for (a, b) in &sent[..6] {
+            assert!(ssrc_unprotect(&mut rx, a).is_some());
+            assert!(ssrc_unprotect(&mut rx, b).is_some());
+        }
 
-        let master = [
-            0xE1, 0xF9, 0x7A, 0x0D, 0x3E, 0x01, 0x8B, 0xE0, //
-            0xD6, 0x4F, 0xA3, 0x2C, 0x06, 0xDE, 0x41, 0x39,
-        ];
+        // Keep 2 alive, let 1 go idle.
+        rx.handle_timeout(now + Duration::from_secs(6));
+        assert!(ssrc_unprotect(&mut rx, &sent[6].1).is_some());
+        rx.handle_timeout(now + Duration::from_secs(11));
 
-        let salt = [
-            0x0E, 0xC6, 0x75, 0xAD, 0x49, 0x8A, 0xFE, //
-            0xEB, 0xB6, 0x96, 0x0B, 0x3A, 0xAB, 0xE6,
-        ];
+        assert!(!rx.roc.highest.contains_key(&1));
+        assert!(rx.roc.highest.contains_key(&2));
 
-        let sk = SrtpKey { master, salt };
+        rx.remove_ssrc(2);
+        assert!(!rx.roc.highest.contains_key(&2));
+        assert!(rx.ssrcs.used.is_empty());
 
-        // aes crypto key
-        let mut out = [0_u8; 16];
-        sk.derive(0, &mut out[..]);
+        for (ssrc, last) in [(1, 5), (2, 6)] {
+            let packet = |i: usize| {
+                let (a, b) = &sent[i];
+                if ssrc == 1 {
+                    a
+                } else {
+                    b
+                }
+            };
 
-        assert_eq!(
-            out,
-            [
-                0xC6, 0x1E, 0x7A, 0x93, 0x74, 0x4F, 0x39, 0xEE, //
-                0x10, 0x73, 0x4A, 0xFE, 0x3F, 0xF7, 0xA0, 0x87
-            ]
-        );
+            // Anything up to the last packet before the state was dropped is a replay.
+            for i in 0..=last {
+                assert!(ssrc_unprotect(&mut rx, packet(i)).is_none());
+            }
 
-        // hmac
-        let mut out = [0_u8; 20];
-        sk.derive(1, &mut out[..]);
+            // The first newer packet is held by the probation.
+            assert!(ssrc_unprotect(&mut rx, packet(last + 1)).is_none());
 
-        assert_eq!(
-            out,
-            [
-                0xCE, 0xBE, 0x32, 0x1F, 0x6F, 0xF7, 0x71, 0x6B, //
-                0x6F, 0xD4, 0xAB, 0x49, 0xAF, 0x25, 0x6A, 0x15, //
-                0x6D, 0x38, 0xBA, 0xA4
-            ]
-        );
+            // Then newer packets are accepted, with the ROC picked up across the wrap.
+            for i in last + 2..10 {
+                let data = ssrc_unprotect(&mut rx, packet(i))
+                    .unwrap_or_else(|| panic!("ssrc {} failed to unprotect {}", ssrc, i));
+                assert_eq!(data, [(65_530 + i) as u8; 16]);
+            }
+        }
 
-        // salt
-        let mut out = [0_u8; 14];
-        sk.derive(2, &mut out[..]);
+        assert_eq!(rx.evicted_ssrcs(), 0);
+    }
 
-        assert_eq!(
-            out,
-            [
-                0x30, 0xCB, 0xBC, 0x08, 0x86, 0x3D, 0x8C, //
-                0x85, 0xD4, 0x9D, 0xB3, 0x4A, 0x9A, 0xE1
-            ]
-        );
+    #[test]
+    fn ssrc_probation_when_full() {
+        let mat = KeyingMaterial::new((0..60).collect());
+        let mut tx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+        let mut rx = SrtpContext::new(SrtpProfile::Aes128CmSha1_80, &mat, true);
+        rx.set_ssrc_limits(2, DEFAULT_SSRC_IDLE);
+        rx.handle_timeout(Instant::now());
+
+        for ssrc in [1, 2] {
+            let buf = ssrc_packet(&mut tx, ssrc, 1);
+            assert!(ssrc_unprotect(&mut rx, &buf).is_some());
+        }
+
+        // Single packets from new SSRCs don't push out the ones in use.
+        for ssrc in 3..10 {
+            let buf = ssrc_packet(&mut tx, ssrc, 1);
+            assert!(ssrc_unprotect(&mut rx, &buf).is_none());
+        }
+        assert!(rx.ssrcs.probation.len() <= 2);
+        assert_eq!(rx.evicted_ssrcs(), 0);
+
+        // Out of sequence doesn't end the probation.
+        let buf = ssrc_packet(&mut tx, 9, 3);
+        assert!(ssrc_unprotect(&mut rx, &buf).is_none());
+
+        // In sequence does, and evicts the least recently used.
+        let buf = ssrc_packet(&mut tx, 9, 4);
+        assert!(ssrc_unprotect(&mut rx, &buf).is_some());
+        assert_eq!(rx.evicted_ssrcs(), 1);
+        assert!(!rx.roc.highest.contains_key(&1));
+        assert!(rx.roc.highest.contains_key(&2));
     }
 
     mod test_aes128_cm_sha1_80 {
@@ -756,7 +2246,7 @@ mod test {
             println!("{decrypted:02x?}");
 
             // Take us back to where we started.
-            let encrypted = ctx_rx.protect_rtcp(&decrypted);
+            let encrypted = ctx_rx.protect_rtcp(&decrypted).unwrap();
             assert_eq!(encrypted, SRTCP);
         }
     }
@@ -768,7 +2258,7 @@ mod test {
 
         use super::aead_aes_128_gcm::*;
 
-        mod rfc7714 {
+        pub(super) mod rfc7714 {
             // Test vectors from RFC7714
 
             // Session Key (RTP and RTCP)
@@ -778,12 +2268,12 @@ mod test {
             ];
 
             // Session Salt (RTP and RTCP)
-            pub(super) const SALT: [u8; 12] = [
+            pub(in super::super) const SALT: [u8; 12] = [
                 0x51, 0x75, 0x69, 0x64, 0x20, 0x70, 0x72, 0x6f, 0x20, 0x71, 0x75, 0x6f,
             ];
 
             /// Full plaintext RTP packet. First 12 octets is the header
-            pub(super) const PLAINTEXT_RTP_PACKET: &[u8] = &[
+            pub(in super::super) const PLAINTEXT_RTP_PACKET: &[u8] = &[
                 0x80, 0x40, 0xf1, 0x7b, 0x80, 0x41, 0xf8, 0xd3, 0x55, 0x01, 0xa0, 0xb2, 0x47, 0x61,
                 0x6c, 0x6c, 0x69, 0x61, 0x20, 0x65, 0x73, 0x74, 0x20, 0x6f, 0x6d, 0x6e, 0x69, 0x73,
                 0x20, 0x64, 0x69, 0x76, 0x69, 0x73, 0x61, 0x20, 0x69, 0x6e, 0x20, 0x70, 0x61, 0x72,
@@ -800,7 +2290,7 @@ mod test {
             ];
 
             // Full plaintext RTCP packet
-            pub(super) const PLAINTEXT_RTCP_PACKET: &[u8] = &[
+            pub(in super::super) const PLAINTEXT_RTCP_PACKET: &[u8] = &[
                 0x81, 0xc8, 0x00, 0x0d, 0x4d, 0x61, 0x72, 0x73, 0x4e, 0x54, 0x50, 0x31, 0x4e, 0x54,
                 0x50, 0x32, 0x52, 0x54, 0x50, 0x20, 0x00, 0x00, 0x04, 0x2a, 0x00, 0x00, 0xe9, 0x30,
                 0x4c, 0x75, 0x6e, 0x61, 0xde, 0xad, 0xbe, 0xef, 0xde, 0xad, 0xbe, 0xef, 0xde, 0xad,
@@ -858,7 +2348,7 @@ mod test {
                     .expect("header to parse");
 
             let out = context
-                .unprotect_rtp(rfc7714::PROTECTED_RTP_PACKET, &header, 0)
+                .unprotect_rtp(rfc7714::PROTECTED_RTP_PACKET, &header)
                 .expect("decrypt rtp");
 
             assert_eq!(
@@ -884,7 +2374,7 @@ mod test {
             let header = RtpHeader::parse(&encrypted[..12], &ExtensionMap::empty())
                 .expect("header to parse");
             let decrypted = context
-                .unprotect_rtp(&encrypted, &header, 0)
+                .unprotect_rtp(&encrypted, &header)
                 .expect("rtp unprotect");
 
             // And verify we get the input back.
@@ -908,7 +2398,7 @@ mod test {
             let header =
                 RtpHeader::parse(&header_buf, &ExtensionMap::empty()).expect("header to parse");
 
-            let result = context.unprotect_rtp(rfc7714::PROTECTED_RTP_PACKET, &header, 0);
+            let result = context.unprotect_rtp(rfc7714::PROTECTED_RTP_PACKET, &header);
             assert!(result.is_none(), "Should fail to decrypt a SRTP packet that has mismatched authenicated additional data");
         }
 
@@ -927,7 +2417,7 @@ mod test {
             let header =
                 RtpHeader::parse(&input[..12], &ExtensionMap::empty()).expect("header to parse");
 
-            let result = context.unprotect_rtp(&input, &header, 0);
+            let result = context.unprotect_rtp(&input, &header);
             assert!(
                 result.is_none(),
                 "Should fail to decrypt a SRTP packet with null tag"
@@ -938,7 +2428,9 @@ mod test {
         fn protect_rtcp_rfc_7714_test() {
             let mut context = make_rtcp_context();
 
-            let out = context.protect_rtcp(rfc7714::PLAINTEXT_RTCP_PACKET);
+            let out = context
+                .protect_rtcp(rfc7714::PLAINTEXT_RTCP_PACKET)
+                .unwrap();
 
             assert!(
                 out == rfc7714::PROTECTED_RTCP_PACKET,
@@ -951,6 +2443,7 @@ mod test {
         #[test]
         fn unprotect_rtcp_rfc_auth_only_7714_test() {
             let mut context = make_rtcp_context();
+            context.set_require_encrypted_rtcp(false);
 
             let out = context
                 .unprotect_rtcp(rfc7714::TAGGED_RTCP_PACKET)
@@ -959,6 +2452,62 @@ mod test {
             assert_eq!(out, rfc7714::PLAINTEXT_RTCP_PACKET);
         }
 
+        #[test]
+        fn unprotect_rtp_drops_replay() {
+            let mut context = make_rtp_context();
+            let header =
+                RtpHeader::parse(&rfc7714::PROTECTED_RTP_PACKET[..12], &ExtensionMap::empty())
+                    .expect("header to parse");
+
+            // A packet failing authentication must not enter the replay list.
+            let mut broken = rfc7714::PROTECTED_RTP_PACKET.to_vec();
+            let len = broken.len();
+            broken[len - 1] ^= 0xff;
+            assert!(context.unprotect_rtp(&broken, &header).is_none());
+            assert_eq!(context.replayed_rtp(), 0);
+
+            assert!(context
+                .unprotect_rtp(rfc7714::PROTECTED_RTP_PACKET, &header)
+                .is_some());
+
+            let replayed = context.unprotect_rtp(rfc7714::PROTECTED_RTP_PACKET, &header);
+            assert!(replayed.is_none(), "Replayed SRTP packet accepted");
+            assert_eq!(context.replayed_rtp(), 1);
+        }
+
+        #[test]
+        fn unprotect_rtcp_drops_replay() {
+            let mut context = make_rtcp_context();
+            context.set_require_encrypted_rtcp(false);
+
+            assert!(context
+                .unprotect_rtcp(rfc7714::TAGGED_RTCP_PACKET)
+                .is_some());
+
+            let replayed = context.unprotect_rtcp(rfc7714::TAGGED_RTCP_PACKET);
+            assert!(replayed.is_none(), "Replayed SRTCP packet accepted");
+            assert_eq!(context.replayed_rtcp(), 1);
+
+            // Same index, encrypted this time. Still a replay.
+            let replayed = context.unprotect_rtcp(rfc7714::PROTECTED_RTCP_PACKET);
+            assert!(replayed.is_none(), "Replayed SRTCP packet accepted");
+            assert_eq!(context.replayed_rtcp(), 2);
+        }
+
+        #[test]
+        fn unprotect_rtcp_requires_encryption() {
+            let mut context = make_rtcp_context();
+
+            // Authenticated, but the E-flag is clear.
+            assert!(context
+                .unprotect_rtcp(rfc7714::TAGGED_RTCP_PACKET)
+                .is_none());
+
+            assert!(context
+                .unprotect_rtcp(rfc7714::PROTECTED_RTCP_PACKET)
+                .is_some());
+        }
+
         fn make_rtp_context() -> SrtpContext {
             SrtpContext::new_aead_aes_128_gcm(
                 rfc7714::KEY,
@@ -979,4 +2528,163 @@ mod test {
             )
         }
     }
+
+    mod test_aead_aes_256_gcm {
+        use crate::rtp_::ExtensionMap;
+
+        use super::*;
+
+        use super::aead_aes_128_gcm::TAG_LEN;
+
+        mod rfc7714 {
+            // Test vectors from RFC7714 section 16.2 and 17.2. The plaintexts and salt are the
+            // same as for AEAD_AES_128_GCM.
+            pub(in super::super) use super::super::test_aead_aes_128_gcm::rfc7714::{
+                PLAINTEXT_RTCP_PACKET, PLAINTEXT_RTP_PACKET, SALT,
+            };
+
+            // Session Key (RTP and RTCP)
+            pub(in super::super) const KEY: [u8; 32] = [
+                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, //
+                0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, //
+                0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, //
+                0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+            ];
+
+            /// Full encrypted RTP packet. First 12 octets is the header.
+            pub(in super::super) const PROTECTED_RTP_PACKET: &[u8] = &[
+                0x80, 0x40, 0xf1, 0x7b, 0x80, 0x41, 0xf8, 0xd3, 0x55, 0x01, 0xa0, 0xb2, 0x32, 0xb1,
+                0xde, 0x78, 0xa8, 0x22, 0xfe, 0x12, 0xef, 0x9f, 0x78, 0xfa, 0x33, 0x2e, 0x33, 0xaa,
+                0xb1, 0x80, 0x12, 0x38, 0x9a, 0x58, 0xe2, 0xf3, 0xb5, 0x0b, 0x2a, 0x02, 0x76, 0xff,
+                0xae, 0x0f, 0x1b, 0xa6, 0x37, 0x99, 0xb8, 0x7b, 0x7a, 0xa3, 0xdb, 0x36, 0xdf, 0xff,
+                0xd6, 0xb0, 0xf9, 0xbb, 0x78, 0x78, 0xd7, 0xa7, 0x6c, 0x13,
+            ];
+
+            /// Full encrypted RTCP packet
+            pub(in super::super) const PROTECTED_RTCP_PACKET: &[u8] = &[
+                0x81, 0xc8, 0x00, 0x0d, 0x4d, 0x61, 0x72, 0x73, 0xd5, 0x0a, 0xe4, 0xd1, 0xf5, 0xce,
+                0x5d, 0x30, 0x4b, 0xa2, 0x97, 0xe4, 0x7d, 0x47, 0x0c, 0x28, 0x2c, 0x3e, 0xce, 0x5d,
+                0xbf, 0xfe, 0x0a, 0x50, 0xa2, 0xea, 0xa5, 0xc1, 0x11, 0x05, 0x55, 0xbe, 0x84, 0x15,
+                0xf6, 0x58, 0xc6, 0x1d, 0xe0, 0x47, 0x6f, 0x1b, 0x6f, 0xad, 0x1d, 0x1e, 0xb3, 0x0c,
+                0x44, 0x46, 0x83, 0x9f, 0x57, 0xff, 0x6f, 0x6c, 0xb2, 0x6a, 0xc3, 0xbe, 0x80, 0x00,
+                0x05, 0xd4,
+            ];
+
+            // A RTCP packet that hasn't been encrypted, only authenticated.
+            pub(in super::super) const TAGGED_RTCP_PACKET: &[u8] = &[
+                // RTCP Packet
+                0x81, 0xc8, 0x00, 0x0d, 0x4d, 0x61, 0x72, 0x73, 0x4e, 0x54, 0x50, 0x31, 0x4e, 0x54,
+                0x50, 0x32, 0x52, 0x54, 0x50, 0x20, 0x00, 0x00, 0x04, 0x2a, 0x00, 0x00, 0xe9, 0x30,
+                0x4c, 0x75, 0x6e, 0x61, 0xde, 0xad, 0xbe, 0xef, 0xde, 0xad, 0xbe, 0xef, 0xde, 0xad,
+                0xbe, 0xef, 0xde, 0xad, 0xbe, 0xef, 0xde, 0xad, 0xbe, 0xef, //
+                // Tag
+                0x91, 0xdb, 0x4a, 0xfb, 0xfe, 0xee, 0x5a, 0x97, 0x8f, 0xab, 0x43, 0x93, 0xed, 0x26,
+                0x15, 0xfe, //
+                // SRTCP Index
+                0x00, 0x00, 0x05, 0xd4,
+            ];
+        }
+
+        #[test]
+        fn protect_rtp_rfc_7714_test() {
+            let mut context = make_rtp_context();
+
+            let header =
+                RtpHeader::parse(&rfc7714::PLAINTEXT_RTP_PACKET[..12], &ExtensionMap::empty())
+                    .expect("header to parse");
+            let out = context.protect_rtp(rfc7714::PLAINTEXT_RTP_PACKET, &header, 0);
+
+            assert_eq!(out, rfc7714::PROTECTED_RTP_PACKET);
+        }
+
+        #[test]
+        fn unprotect_rtp_rfc_7714_test() {
+            let mut context = make_rtp_context();
+            let header =
+                RtpHeader::parse(&rfc7714::PROTECTED_RTP_PACKET[..12], &ExtensionMap::empty())
+                    .expect("header to parse");
+
+            let out = context
+                .unprotect_rtp(rfc7714::PROTECTED_RTP_PACKET, &header)
+                .expect("decrypt rtp");
+
+            assert_eq!(out, rfc7714::PLAINTEXT_RTP_PACKET[12..]);
+        }
+
+        #[test]
+        fn unprotect_rtp_should_fail_with_broken_null_tag() {
+            let mut context = make_rtp_context();
+
+            let input = {
+                let mut input = rfc7714::PROTECTED_RTP_PACKET.to_vec();
+                let len = input.len();
+                input[len - TAG_LEN..].copy_from_slice(&[0; TAG_LEN]);
+
+                input
+            };
+
+            let header =
+                RtpHeader::parse(&input[..12], &ExtensionMap::empty()).expect("header to parse");
+
+            let result = context.unprotect_rtp(&input, &header);
+            assert!(
+                result.is_none(),
+                "Should fail to decrypt a SRTP packet with null tag"
+            );
+        }
+
+        #[test]
+        fn protect_rtcp_rfc_7714_test() {
+            let mut context = make_rtcp_context();
+
+            let out = context
+                .protect_rtcp(rfc7714::PLAINTEXT_RTCP_PACKET)
+                .unwrap();
+
+            assert_eq!(out, rfc7714::PROTECTED_RTCP_PACKET);
+        }
+
+        #[test]
+        fn unprotect_rtcp_rfc_7714_test() {
+            let mut context = make_rtcp_context();
+
+            let out = context
+                .unprotect_rtcp(rfc7714::PROTECTED_RTCP_PACKET)
+                .expect("Unprotect RTCP");
+
+            assert_eq!(out, rfc7714::PLAINTEXT_RTCP_PACKET);
+        }
+
+        #[test]
+        fn unprotect_rtcp_rfc_auth_only_7714_test() {
+            let mut context = make_rtcp_context();
+            context.set_require_encrypted_rtcp(false);
+
+            let out = context
+                .unprotect_rtcp(rfc7714::TAGGED_RTCP_PACKET)
+                .expect("Unprotect RTCP");
+
+            assert_eq!(out, rfc7714::PLAINTEXT_RTCP_PACKET);
+        }
+
+        fn make_rtp_context() -> SrtpContext {
+            SrtpContext::new_aead_aes_256_gcm(
+                rfc7714::KEY,
+                rfc7714::SALT,
+                rfc7714::KEY,
+                rfc7714::SALT,
+                0,
+            )
+        }
+
+        fn make_rtcp_context() -> SrtpContext {
+            SrtpContext::new_aead_aes_256_gcm(
+                rfc7714::KEY,
+                rfc7714::SALT,
+                rfc7714::KEY,
+                rfc7714::SALT,
+                0x000005d4,
+            )
+        }
+    }
 }
@@ -204,6 +204,93 @@ impl VideoLayersAllocation {
             simulcast_streams,
         })
     }
+
+    // Returns None if the allocation can't be expressed in the header extension.
+    fn to_bytes(&self) -> Option<Vec<u8>> {
+        let streams = &self.simulcast_streams;
+
+        // Bit 0 is the lowest spatial layer.
+        let bitmasks: Vec<u8> = streams
+            .iter()
+            .map(|stream| {
+                stream
+                    .spatial_layers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, layer)| !layer.temporal_layers.is_empty())
+                    .fold(0, |mask, (index, _)| mask | 1 << index)
+            })
+            .collect();
+
+        if bitmasks.iter().all(|&mask| mask == 0) {
+            // Special case when everything is inactive.
+            return Some(vec![0]);
+        }
+
+        if streams.len() > 4
+            || self.current_simulcast_stream_index > 3
+            || streams.iter().any(|s| s.spatial_layers.len() > 4)
+        {
+            return None;
+        }
+
+        let active: Vec<&SpatialLayerAllocation> = streams
+            .iter()
+            .flat_map(|stream| stream.spatial_layers.iter())
+            .filter(|layer| !layer.temporal_layers.is_empty())
+            .collect();
+
+        if active.iter().any(|layer| layer.temporal_layers.len() > 4) {
+            return None;
+        }
+
+        let mut buf = vec![];
+
+        // First byte, with a shared spatial layer bitmask if possible.
+        let shared = bitmasks.iter().all(|&mask| mask == bitmasks[0]);
+        let shared_bitmask = if shared { bitmasks[0] } else { 0 };
+        buf.push(
+            self.current_simulcast_stream_index << 6
+                | (streams.len() as u8 - 1) << 4
+                | shared_bitmask,
+        );
+
+        // Spatial layer bitmasks, 4 bits per simulcast stream
+        if !shared {
+            for pair in bitmasks.chunks(2) {
+                buf.push(pair[0] << 4 | pair.get(1).copied().unwrap_or(0));
+            }
+        }
+
+        // Temporal layer counts (minus 1), 2 bits per active spatial layer
+        for chunk in active.chunks(4) {
+            let byte = chunk.iter().enumerate().fold(0, |byte, (index, layer)| {
+                byte | (layer.temporal_layers.len() as u8 - 1) << (6 - 2 * index)
+            });
+            buf.push(byte);
+        }
+
+        // Temporal layer bitrates
+        for temporal_layer in active.iter().flat_map(|layer| &layer.temporal_layers) {
+            if temporal_layer.cumulative_kbps >= (1u64 << 63) {
+                return None;
+            }
+            write_leb_u63(&mut buf, temporal_layer.cumulative_kbps);
+        }
+
+        // Resolutions and framerates are either there for all active spatial layers, or none.
+        let resolutions_and_framerates: Option<Vec<&ResolutionAndFramerate>> = active
+            .iter()
+            .map(|layer| layer.resolution_and_framerate.as_ref())
+            .collect();
+        for r in resolutions_and_framerates.into_iter().flatten() {
+            buf.extend_from_slice(&r.width.saturating_sub(1).to_be_bytes());
+            buf.extend_from_slice(&r.height.saturating_sub(1).to_be_bytes());
+            buf.push(r.framerate);
+        }
+
+        Some(buf)
+    }
 }
 
 /// Serializer of the Video Layers Allocation Header Extension
@@ -211,12 +298,19 @@ impl VideoLayersAllocation {
 pub struct Serializer;
 
 impl ExtensionSerializer for Serializer {
-    fn write_to(&self, _buf: &mut [u8], ev: &ExtensionValues) -> usize {
-        if ev.user_values.get::<VideoLayersAllocation>().is_some() {
-            // Writing the VLA header extension is currently not supported.
-            todo!();
+    fn write_to(&self, buf: &mut [u8], ev: &ExtensionValues) -> usize {
+        let Some(vla) = ev.user_values.get::<VideoLayersAllocation>() else {
+            return 0;
+        };
+        let Some(bytes) = vla.to_bytes() else {
+            debug!("Invalid video layers allocation: {:?}", vla);
+            return 0;
+        };
+        if bytes.len() > 255 || bytes.len() > buf.len() {
+            return 0;
         }
-        0
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        bytes.len()
     }
 
     fn parse_value(&self, buf: &[u8], ev: &mut ExtensionValues) -> bool {
@@ -235,9 +329,13 @@ impl ExtensionSerializer for Serializer {
         false
     }
 
-    fn requires_two_byte_form(&self, _ev: &ExtensionValues) -> bool {
-        // Writing isn't implemented yet
-        false
+    fn requires_two_byte_form(&self, ev: &ExtensionValues) -> bool {
+        // Bitrates and resolutions for a few layers quickly go past 16 bytes.
+        ev.user_values
+            .get::<VideoLayersAllocation>()
+            .and_then(|vla| vla.to_bytes())
+            .map(|bytes| bytes.len() > 16)
+            .unwrap_or(false)
     }
 }
 
@@ -261,6 +359,18 @@ fn parse_leb_u63(bytes: &[u8]) -> (u64, &[u8]) {
     (0, bytes)
 }
 
+// Writes at most 9 bytes, the inverse of parse_leb_u63.
+fn write_leb_u63(buf: &mut Vec<u8>, mut value: u64) {
+    for _ in 0..8 {
+        if value < 0x80 {
+            break;
+        }
+        buf.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
 // If successful, the size of the left will be mid,
 // and the size of the right while be buf.len()-mid.
 #[allow(dead_code)]
@@ -777,4 +887,154 @@ mod test {
             })
         );
     }
+
+    fn allocation(
+        current_simulcast_stream_index: u8,
+        streams: &[&[usize]],
+        with_resolutions: bool,
+    ) -> VideoLayersAllocation {
+        let mut kbps = 0;
+        VideoLayersAllocation {
+            current_simulcast_stream_index,
+            simulcast_streams: streams
+                .iter()
+                .map(|spatial_layers| SimulcastStreamAllocation {
+                    spatial_layers: spatial_layers
+                        .iter()
+                        .enumerate()
+                        .map(|(spatial_index, &temporal_count)| SpatialLayerAllocation {
+                            temporal_layers: (0..temporal_count)
+                                .map(|_| {
+                                    // Large enough to need multi byte LEB128.
+                                    kbps += 150;
+                                    TemporalLayerAllocation {
+                                        cumulative_kbps: kbps,
+                                    }
+                                })
+                                .collect(),
+                            resolution_and_framerate: (with_resolutions && temporal_count > 0)
+                                .then(|| ResolutionAndFramerate {
+                                    width: 320 << spatial_index,
+                                    height: 180 << spatial_index,
+                                    framerate: 30,
+                                }),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_write_vla_roundtrip_spatial_and_temporal_layers() {
+        for spatial_count in 1..=3 {
+            for temporal_count in 1..=3 {
+                for with_resolutions in [false, true] {
+                    let layers = vec![temporal_count; spatial_count];
+                    let vla = allocation(0, &[&layers], with_resolutions);
+
+                    let bytes = vla.to_bytes().unwrap();
+                    assert_eq!(
+                        VideoLayersAllocation::parse(&bytes),
+                        Some(vla),
+                        "spatial: {spatial_count} temporal: {temporal_count}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_vla_roundtrip_simulcast() {
+        // Shared spatial layer bitmask.
+        let vla = allocation(2, &[&[2], &[2], &[3]], true);
+        let bytes = vla.to_bytes().unwrap();
+        assert_eq!(bytes[0] & 0b1111, 0b0001);
+        assert_eq!(VideoLayersAllocation::parse(&bytes), Some(vla));
+
+        // Differing spatial layer bitmasks, including an inactive layer.
+        let vla = allocation(1, &[&[1], &[0, 2], &[3, 1, 2]], false);
+        let bytes = vla.to_bytes().unwrap();
+        assert_eq!(bytes[0] & 0b1111, 0);
+        assert_eq!(VideoLayersAllocation::parse(&bytes), Some(vla));
+    }
+
+    #[test]
+    fn test_write_vla_matches_parsed() {
+        let buf = [
+            0b0110_0001,
+            0b0101_0100,
+            100,
+            101,
+            110,
+            111,
+            120,
+            121,
+            1,
+            63,
+            0,
+            179,
+            15,
+            2,
+            127,
+            1,
+            103,
+            30,
+            4,
+            255,
+            2,
+            207,
+            60,
+        ];
+        let vla = VideoLayersAllocation::parse(&buf).unwrap();
+        assert_eq!(vla.to_bytes().unwrap(), buf);
+    }
+
+    #[test]
+    fn test_write_vla_inactive() {
+        let vla = allocation(0, &[&[0], &[0]], false);
+        assert_eq!(vla.to_bytes().unwrap(), [0]);
+    }
+
+    #[test]
+    fn test_write_vla_invalid() {
+        // Max 4 temporal layers.
+        assert_eq!(allocation(0, &[&[5]], false).to_bytes(), None);
+        // Max 4 simulcast streams.
+        assert_eq!(allocation(0, &[&[1][..]; 5], false).to_bytes(), None);
+    }
+
+    #[test]
+    fn test_write_leb_u63() {
+        for value in [0, 1, 127, 128, 16384, 1_000_000, (1 << 63) - 1] {
+            let mut buf = vec![];
+            write_leb_u63(&mut buf, value);
+            buf.push(5);
+            assert_eq!(parse_leb_u63(&buf), (value, &[5][..]));
+        }
+    }
+
+    #[test]
+    fn test_vla_two_byte_form() {
+        use crate::rtp_::{Extension, ExtensionMap, ExtensionsForm};
+
+        let mut exts = ExtensionMap::empty();
+        exts.set(10, Extension::with_serializer(URI, Serializer));
+
+        let mut ev = ExtensionValues::default();
+        ev.user_values.set(allocation(0, &[&[1]], false));
+        assert_eq!(exts.form(&ev), ExtensionsForm::OneByte);
+
+        let vla = allocation(0, &[&[3, 3, 3]], true);
+        ev.user_values.set(vla.clone());
+        assert_eq!(exts.form(&ev), ExtensionsForm::TwoByte);
+
+        let mut buf = vec![0_u8; 64];
+        let n = exts.write_to(&mut buf, &ev, ExtensionsForm::TwoByte);
+        assert!(n > 18);
+
+        let mut ev2 = ExtensionValues::default();
+        exts.parse(&buf[..n], ExtensionsForm::TwoByte, &mut ev2);
+        assert_eq!(ev2.user_values.get::<VideoLayersAllocation>(), Some(&vla));
+    }
 }
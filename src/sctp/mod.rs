@@ -9,7 +9,7 @@ use std::time::Instant;
 
 use sctp_proto::{Association, AssociationHandle, ClientConfig, DatagramEvent};
 use sctp_proto::{Endpoint, EndpointConfig, Stream, StreamEvent, Transmit};
-use sctp_proto::{Event, Payload, PayloadProtocolIdentifier, ServerConfig};
+use sctp_proto::{Event, Payload, PayloadProtocolIdentifier, ServerConfig, TransportConfig};
 use thiserror::Error;
 
 pub use sctp_proto::Error as ProtoError;
@@ -20,6 +20,12 @@ use dcep::DcepOpen;
 
 use dcep::DcepAck;
 
+/// Largest message we send and receive, as signalled in `a=max-message-size`.
+pub(crate) const MAX_MESSAGE_SIZE: usize = 262_144;
+
+/// The remote max message size when `a=max-message-size` is missing (RFC 8841 6.1).
+const DEFAULT_REMOTE_MAX_MESSAGE_SIZE: usize = 65_536;
+
 /// Errors from the SCTP subsystem.
 #[derive(Debug, Error, Eq, Clone, PartialEq)]
 pub enum SctpError {
@@ -52,6 +58,7 @@ pub(crate) struct RtcSctp {
     pushed_back_transmit: Option<VecDeque<Vec<u8>>>,
     last_now: Instant,
     client: bool,
+    remote_max_message_size: usize,
 }
 
 /// This is okay because there is no way for a user of Rtc to interact with the Sctp subsystem
@@ -129,11 +136,14 @@ enum StreamEntryState {
 }
 
 /// (Low level) configuration for a data channel.
-#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ChannelConfig {
     /// The label to use for the user to identify the channel.
     pub label: String,
     /// Whether channel is guaranteed ordered delivery of messages.
+    ///
+    /// Defaults to `true`. Unordered messages are delivered as soon as they are complete,
+    /// without waiting for earlier ones.
     pub ordered: bool,
     /// The reliability setting, which can allow to drop messages.
     pub reliability: Reliability,
@@ -146,20 +156,37 @@ pub struct ChannelConfig {
     pub protocol: String,
 }
 
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            label: String::new(),
+            ordered: true,
+            reliability: Reliability::default(),
+            negotiated: None,
+            protocol: String::new(),
+        }
+    }
+}
+
 /// Reliability setting of a data channel.
+///
+/// With partial reliability (RFC 3758), messages that are given up on are skipped with a
+/// FORWARD-TSN, and never delivered. Messages larger than one SCTP chunk (about 1 kB)
+/// are only given up on when their last chunk is lost, otherwise they are retransmitted
+/// and delivered whole.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Reliability {
-    /// Packets are delivered in order, with retransmits.
+    /// Packets are retransmitted until they arrive.
     #[default]
     Reliable,
-    /// Packets delivered out of order with a max lifetime.
+    /// Packets are retransmitted for a max lifetime.
     MaxPacketLifetime {
-        /// The lifetime of a packet in milliseconds.
+        /// The lifetime of a packet in milliseconds, counted from when it is first sent.
         lifetime: u16,
     },
-    /// Packets delivered out of order with a max number of retransmits.
+    /// Packets are retransmitted a max number of times.
     MaxRetransmits {
-        /// Number of retransmits before giving up.
+        /// Number of retransmits before giving up. With 0, packets are sent once.
         retransmits: u16,
     },
 }
@@ -178,11 +205,15 @@ impl StreamEntry {
     fn configure_reliability(&mut self, stream: &mut Stream) -> bool {
         let dcep: DcepOpen = self.config.as_ref().expect("config to be set").into();
 
-        let ret = stream.set_reliability_params(
-            dcep.unordered,
-            dcep.channel_type,
-            dcep.reliability_parameter,
-        );
+        // sctp-proto gives up on a chunk once it has been sent this many times,
+        // which includes the first transmission.
+        let mut reliability_parameter = dcep.reliability_parameter;
+        if dcep.channel_type == ReliabilityType::Rexmit {
+            reliability_parameter = reliability_parameter.saturating_add(1);
+        }
+
+        let ret =
+            stream.set_reliability_params(dcep.unordered, dcep.channel_type, reliability_parameter);
 
         if let Err(e) = ret {
             warn!(
@@ -204,7 +235,8 @@ impl RtcSctp {
         // DTLS above MTU 1200: 1277
         // Let's try 1120, see if we can avoid warnings.
         config.max_payload_size(1120);
-        let server_config = ServerConfig::default();
+        let mut server_config = ServerConfig::default();
+        server_config.transport = transport_config();
         let endpoint = Endpoint::new(Arc::new(config), Some(Arc::new(server_config)));
         let fake_addr = "1.1.1.1:5000".parse().unwrap();
 
@@ -218,6 +250,8 @@ impl RtcSctp {
             pushed_back_transmit: None,
             last_now: Instant::now(), // placeholder until init()
             client: false,
+            // Without SDP, assume the remote is like us.
+            remote_max_message_size: MAX_MESSAGE_SIZE,
         }
     }
 
@@ -233,9 +267,12 @@ impl RtcSctp {
 
         if client {
             info!("New local association");
+            let client_config = ClientConfig {
+                transport: transport_config(),
+            };
             let (handle, assoc) = self
                 .endpoint
-                .connect(ClientConfig::default(), self.fake_addr)
+                .connect(client_config, self.fake_addr)
                 .expect("be able to create an association");
             self.handle = handle;
             self.assoc = Some(assoc);
@@ -249,6 +286,19 @@ impl RtcSctp {
         self.client
     }
 
+    /// Set the max message size from the remote `a=max-message-size`.
+    ///
+    /// A missing attribute means 64 kB, and 0 means the remote takes any size.
+    pub fn set_remote_max_message_size(&mut self, size: Option<usize>) {
+        let size = match size {
+            None => DEFAULT_REMOTE_MAX_MESSAGE_SIZE,
+            Some(0) => MAX_MESSAGE_SIZE,
+            Some(v) => v.min(MAX_MESSAGE_SIZE),
+        };
+        debug!("Remote max message size: {}", size);
+        self.remote_max_message_size = size;
+    }
+
     /// Opens a new stream.
     pub fn open_stream(&mut self, id: u16, config: ChannelConfig) {
         // The channel might already have arrived via SCTP, and if it is negotiated out-of-band
@@ -340,6 +390,10 @@ impl RtcSctp {
             return Err(SctpError::WriteBeforeEstablished);
         }
 
+        if buf.len() > self.remote_max_message_size {
+            return Err(SctpError::Proto(ProtoError::ErrOutboundPacketTooLarge));
+        }
+
         let mut stream = assoc.stream(id)?;
 
         let ppi = if binary {
@@ -682,6 +736,12 @@ impl RtcSctp {
     }
 }
 
+fn transport_config() -> Arc<TransportConfig> {
+    // The default receive window of 1 MB fits whole messages, so reassembly doesn't stall.
+    let config = TransportConfig::default().with_max_message_size(MAX_MESSAGE_SIZE as u32);
+    Arc::new(config)
+}
+
 fn transmit_to_vec(t: Transmit) -> Option<VecDeque<Vec<u8>>> {
     let Payload::RawEncode(v) = t.payload else {
         return None;
@@ -3,7 +3,7 @@
 use combine::EasyParser;
 use std::collections::HashSet;
 use std::fmt::{self};
-use std::num::ParseFloatError;
+use std::num::{NonZeroU16, ParseFloatError};
 use std::ops::Deref;
 use std::str::FromStr;
 
@@ -39,10 +39,19 @@ impl Sdp {
         }
     }
 
-    pub(crate) fn fingerprint(&self) -> Option<Fingerprint> {
-        self.session
-            .fingerprint()
-            .or_else(|| self.media_lines.iter().find_map(|m| m.fingerprint()))
+    /// All fingerprints at session level, or else from the first m-line that has any.
+    ///
+    /// There can be several, i.e. for different hash functions or a certificate chain.
+    pub(crate) fn fingerprints(&self) -> Vec<Fingerprint> {
+        let session = self.session.fingerprints();
+        if !session.is_empty() {
+            return session;
+        }
+        self.media_lines
+            .iter()
+            .map(|m| m.fingerprints())
+            .find(|f| !f.is_empty())
+            .unwrap_or_default()
     }
 
     pub(crate) fn ice_creds(&self) -> Option<IceCreds> {
@@ -77,31 +86,34 @@ impl Sdp {
         //    for PT 96 in one m-line it must be in all m-lines.
         // 2. Compare with previous m-lines that remote isn't narrowing/expanding PT and/or extmaps.
 
-        let group = self
+        for media in &self.media_lines {
+            if let Some(error) = media.check_consistent() {
+                return Some(error);
+            }
+        }
+
+        let groups: Vec<_> = self
             .session
             .attrs
             .iter()
-            .find(|a| matches!(a, SessionAttribute::Group { .. }));
+            .filter_map(|a| match a {
+                SessionAttribute::Group { mids, .. } => Some(mids),
+                _ => None,
+            })
+            .collect();
 
-        if let Some(SessionAttribute::Group { mids, .. }) = group {
-            if !mids.len() == self.media_lines.len() {
-                return Some(format!(
-                    "a=group mid count doesn't match m-line count {} != {}",
-                    mids.len(),
-                    self.media_lines.len()
-                ));
-            }
-            for (media, mid) in self.media_lines.iter().zip(mids.iter()) {
-                media.check_consistent()?;
-                let m = media.mid();
-                if m != *mid {
-                    return Some(format!("Mid order not matching a=group {m} != {mid}"));
-                }
-            }
-        } else {
+        if groups.is_empty() {
             return Some("Session attribute a=group missing".into());
         }
 
+        // There can be several groups, in any order, and m-lines that are in none of them.
+        // Bundle-only m-lines (port 0) are in a group too.
+        for mid in groups.iter().flat_map(|mids| mids.iter()) {
+            if !self.media_lines.iter().any(|m| m.mid() == *mid) {
+                return Some(format!("a=group mid without m-line: {mid}"));
+            }
+        }
+
         None
     }
 }
@@ -157,13 +169,14 @@ impl Session {
         })
     }
 
-    pub fn fingerprint(&self) -> Option<Fingerprint> {
-        for a in &self.attrs {
-            if let SessionAttribute::Fingerprint(v) = a {
-                return Some(v.clone());
-            }
-        }
-        None
+    pub fn fingerprints(&self) -> Vec<Fingerprint> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                SessionAttribute::Fingerprint(v) => Some(v.clone()),
+                _ => None,
+            })
+            .collect()
     }
 
     pub fn ice_lite(&self) -> bool {
@@ -187,6 +200,12 @@ impl Session {
             .iter()
             .any(|a| matches!(a, SessionAttribute::EndOfCandidates))
     }
+
+    pub fn extmap_allow_mixed(&self) -> bool {
+        self.attrs
+            .iter()
+            .any(|a| matches!(a, SessionAttribute::ExtmapAllowMixed))
+    }
 }
 
 /// Attributes before the first m= line.
@@ -208,6 +227,7 @@ pub enum SessionAttribute {
     Setup(Setup), // active, passive, actpass, holdconn
     Candidate(Candidate),
     EndOfCandidates,
+    ExtmapAllowMixed, // a=extmap-allow-mixed
     Unused(String),
 }
 
@@ -294,12 +314,10 @@ impl MediaLine {
         let fbs: Vec<_> = self
             .attrs
             .iter()
-            .filter_map(|a| {
-                if let MediaAttribute::RtcpFb { pt, value } = a {
-                    Some((pt, value))
-                } else {
-                    None
-                }
+            .filter_map(|a| match a {
+                MediaAttribute::RtcpFb { pt, value } => Some((Some(*pt), value)),
+                MediaAttribute::RtcpFbAll { value } => Some((None, value)),
+                _ => None,
             })
             .collect();
 
@@ -328,33 +346,45 @@ impl MediaLine {
                                 .any(|(cpt, c)| cpt == *pt && c.codec == Codec::Rtx);
                             if is_rtx {
                                 p.resend = Some(**pt);
+                                p.rtx_time = values.iter().find_map(|fp| match fp {
+                                    FormatParam::RtxTime(v) => NonZeroU16::new(*v),
+                                    _ => None,
+                                });
                             }
                         }
                     }
                 }
             }
 
-            // rtcp feedback mechanisms
+            // rtcp feedback mechanisms, only what the SDP says, not our defaults.
+            p.fb_transport_cc = false;
+            p.fb_remb = false;
+            p.fb_fir = false;
+            p.fb_nack = false;
+            p.fb_pli = false;
+
             for (pt, value) in fbs.iter() {
-                if **pt == p.pt {
-                    match &value[..] {
-                        "goog-remb" => {
+                if pt.map_or(true, |pt| pt == p.pt) {
+                    let tokens: Vec<_> = value.split_whitespace().collect();
+                    match &tokens[..] {
+                        ["goog-remb"] => {
                             p.fb_remb = true;
                         }
-                        "transport-cc" => {
+                        ["transport-cc"] => {
                             p.fb_transport_cc = true;
                         }
-                        "ccm fir" => {
+                        ["ccm", "fir"] => {
                             p.fb_fir = true;
                         }
-                        "nack" => {
+                        ["nack"] => {
                             p.fb_nack = true;
                         }
-                        "nack pli" => {
+                        ["nack", "pli"] => {
                             p.fb_pli = true;
                         }
                         _ => {
-                            //
+                            // Kept in the attributes, but not something we act on.
+                            trace!("Ignore unknown rtcp-fb for {}: {}", p.pt, value);
                         }
                     }
                 }
@@ -364,6 +394,32 @@ impl MediaLine {
         params
     }
 
+    /// RTX payload types that don't resend any of the [`rtp_params()`][Self::rtp_params],
+    /// because apt is missing or points to a PT that isn't there.
+    pub fn dangling_rtx_pts(&self) -> Vec<Pt> {
+        let params = self.rtp_params();
+
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                MediaAttribute::RtpMap { pt, value } if value.codec == Codec::Rtx => Some(*pt),
+                _ => None,
+            })
+            .filter(|pt| !params.iter().any(|p| p.resend == Some(*pt)))
+            .collect()
+    }
+
+    /// The `a=fmtp` values for a payload type, empty if there is none.
+    pub fn fmtp(&self, pt: Pt) -> &[FormatParam] {
+        self.attrs
+            .iter()
+            .find_map(|a| match a {
+                MediaAttribute::Fmtp { pt: p, values } if *p == pt => Some(&values[..]),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
+
     pub fn check_consistent(&self) -> Option<String> {
         use MediaAttribute::*;
 
@@ -405,6 +461,11 @@ impl MediaLine {
             return Some(format!("Expected at least one PT for mid: {}", self.mid()));
         }
 
+        // The PTs of a removed m-line are ignored.
+        if self.disabled {
+            return None;
+        }
+
         for m in &self.pts {
             let rtp_count = self
                 .attrs
@@ -431,6 +492,13 @@ impl MediaLine {
         None
     }
 
+    /// Whether RTP and RTCP are multiplexed on the same port.
+    pub fn rtcp_mux(&self) -> bool {
+        self.attrs
+            .iter()
+            .any(|a| matches!(a, MediaAttribute::RtcpMux | MediaAttribute::RtcpMuxOnly))
+    }
+
     pub fn setup(&self) -> Option<Setup> {
         let setup = self.attrs.iter().find_map(|m| {
             if let MediaAttribute::Setup(v) = m {
@@ -443,6 +511,16 @@ impl MediaLine {
         Some(*setup)
     }
 
+    pub fn max_message_size(&self) -> Option<usize> {
+        self.attrs.iter().find_map(|m| {
+            if let MediaAttribute::MaxMessageSize(v) = m {
+                Some(*v)
+            } else {
+                None
+            }
+        })
+    }
+
     pub fn ice_creds(&self) -> Option<IceCreds> {
         let ufrag = self.attrs.iter().find_map(|m| {
             if let MediaAttribute::IceUfrag(v) = m {
@@ -465,13 +543,14 @@ impl MediaLine {
             pass: pass.to_string(),
         })
     }
-    pub fn fingerprint(&self) -> Option<Fingerprint> {
-        for a in &self.attrs {
-            if let MediaAttribute::Fingerprint(v) = a {
-                return Some(v.clone());
-            }
-        }
-        None
+    pub fn fingerprints(&self) -> Vec<Fingerprint> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                MediaAttribute::Fingerprint(v) => Some(v.clone()),
+                _ => None,
+            })
+            .collect()
     }
 
     /// This hoovers the ice candidates from all m-lines, lots of dupes.
@@ -498,7 +577,7 @@ impl MediaLine {
         let mut ret = vec![];
 
         for a in &self.attrs {
-            if let MediaAttribute::ExtMap { id, ext } = a {
+            if let MediaAttribute::ExtMap { id, ext, .. } = a {
                 ret.push((*id, ext));
             }
         }
@@ -506,6 +585,31 @@ impl MediaLine {
         ret
     }
 
+    /// The direction of an extmap, `sendrecv` if not stated.
+    pub fn extmap_direction(&self, id: u8) -> Direction {
+        self.attrs
+            .iter()
+            .find_map(|a| match a {
+                MediaAttribute::ExtMap {
+                    id: i, direction, ..
+                } if *i == id => Some(direction.unwrap_or(Direction::SendRecv)),
+                _ => None,
+            })
+            .unwrap_or(Direction::SendRecv)
+    }
+
+    pub fn extmap_allow_mixed(&self) -> bool {
+        self.attrs
+            .iter()
+            .any(|a| matches!(a, MediaAttribute::ExtmapAllowMixed))
+    }
+
+    pub fn rtcp_rsize(&self) -> bool {
+        self.attrs
+            .iter()
+            .any(|a| matches!(a, MediaAttribute::RtcpRsize))
+    }
+
     pub fn rids(&self) -> Vec<Rid> {
         let mut ret = vec![];
         for a in &self.attrs {
@@ -516,6 +620,19 @@ impl MediaLine {
         ret
     }
 
+    /// The a=rid line for a rid in a direction ("send" or "recv").
+    fn rid_line(&self, rid: &str, dir: &str) -> Option<(&[Pt], &RidRestrictions)> {
+        self.attrs.iter().find_map(|a| match a {
+            MediaAttribute::Rid {
+                id,
+                direction,
+                pt,
+                restriction,
+            } if id.0 == rid && *direction == dir => Some((&pt[..], restriction)),
+            _ => None,
+        })
+    }
+
     pub fn simulcast(&self) -> Option<Simulcast> {
         let mut found = None;
 
@@ -523,26 +640,40 @@ impl MediaLine {
             if let MediaAttribute::Simulcast(s) = a {
                 found = Some(s.clone());
             }
-            if let MediaAttribute::Rid {
-                pt, restriction, ..
-            } = a
-            {
-                if !pt.is_empty() {
-                    warn!("Not currently supporting PT via a=rid");
-                }
-                if !restriction.is_empty() {
-                    warn!("Not currently supporting restrictions via a=rid");
-                }
-            }
         }
 
-        if found.is_some() {
-            return found;
+        // Here we could handle munged SDPs and we used to, but we have dropped support for this.
+        let mut s = found?;
+
+        // RFC 8853: A rid in a=simulcast without an a=rid line in the same direction
+        // is removed.
+        s.send
+            .retain_rids(|r| self.rid_line(&r.0, "send").is_some());
+        s.recv
+            .retain_rids(|r| self.rid_line(&r.0, "recv").is_some());
+
+        if s.send.is_empty() && s.recv.is_empty() {
+            debug!("Ignore a=simulcast without matching a=rid lines");
+            return None;
         }
 
-        // Here we could handle munged SDPs and we used to, but we have dropped support for this.
+        for (r, dir) in s
+            .send
+            .rids()
+            .map(|r| (r, "send"))
+            .chain(s.recv.rids().map(|r| (r, "recv")))
+        {
+            // Unwrap is OK, since we just retained those with a=rid lines.
+            let (pt, restriction) = self.rid_line(&r.0, dir).unwrap();
+            if !pt.is_empty() {
+                warn!("Not currently supporting PT via a=rid");
+            }
+            if !restriction.is_empty() {
+                s.restrictions.push((r.0.clone(), restriction.clone()));
+            }
+        }
 
-        None
+        Some(s)
     }
 
     pub fn ssrc_info(&self) -> Vec<SsrcInfo> {
@@ -598,19 +729,33 @@ impl MediaLine {
         // Match this second to ensure we preserve order of a=ssrc.
         for a in &self.attrs {
             match a {
-                MediaAttribute::SsrcGroup { semantics, ssrcs } => {
-                    if semantics.to_lowercase() != "fid" {
-                        continue;
+                MediaAttribute::SsrcGroup { semantics, ssrcs }
+                    if semantics.eq_ignore_ascii_case("sim") =>
+                {
+                    // a=ssrc-group:SIM 1111 2222 3333
+                    for (i, ssrc) in ssrcs.iter().enumerate() {
+                        by_ssrc(&mut v, *ssrc).sim_layer = Some(i);
                     }
-
+                }
+                MediaAttribute::SsrcGroup { semantics, ssrcs } => {
                     // a=ssrc-group:FID 659652645 98148385
-                    // Should be two SSRC after FID.
+                    // a=ssrc-group:FEC-FR 659652645 3385843236
+                    // Should be two SSRC after FID or FEC-FR.
                     if ssrcs.len() != 2 {
                         continue;
                     }
 
-                    let info = by_ssrc(&mut v, ssrcs[1]);
-                    info.repairs = Some(ssrcs[0]);
+                    match semantics.to_lowercase().as_str() {
+                        "fid" => {
+                            let info = by_ssrc(&mut v, ssrcs[1]);
+                            info.repairs = Some(ssrcs[0]);
+                        }
+                        "fec-fr" => {
+                            let info = by_ssrc(&mut v, ssrcs[1]);
+                            info.fec_for = Some(ssrcs[0]);
+                        }
+                        _ => {}
+                    }
                 }
                 _ => {}
             }
@@ -625,6 +770,10 @@ pub struct SsrcInfo {
     pub ssrc: Ssrc,
     /// the other ssrc this ssrc is repairing
     pub repairs: Option<Ssrc>,
+    /// the other ssrc this ssrc is sending FlexFEC for
+    pub fec_for: Option<Ssrc>,
+    /// position in a=ssrc-group:SIM, for simulcast without rid
+    pub sim_layer: Option<usize>,
     pub cname: Option<String>,
     pub stream_id: Option<String>,
     pub track_id: Option<String>,
@@ -635,6 +784,8 @@ impl Default for SsrcInfo {
         Self {
             ssrc: 0.into(),
             repairs: None,
+            fec_for: None,
+            sim_layer: None,
             cname: None,
             stream_id: None,
             track_id: None,
@@ -689,6 +840,106 @@ impl PartialEq for F32Eq {
 }
 impl Eq for F32Eq {}
 
+impl fmt::Display for F32Eq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Restrictions of an `a=rid` line.
+///
+/// `a=rid:hi send max-width=1280;max-height=720;max-fps=30`
+///
+/// Defined in https://www.rfc-editor.org/rfc/rfc8851#section-5
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RidRestrictions {
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub max_fps: Option<F32Eq>,
+    pub max_fs: Option<u32>,
+    pub max_br: Option<u32>,
+    pub max_pps: Option<u32>,
+    pub max_bpp: Option<F32Eq>,
+    pub depend: Vec<Rid>,
+    /// Restrictions we don't understand. They are ignored (RFC 8851), and left out
+    /// when we write the restrictions in an answer.
+    pub unknown: Vec<(String, String)>,
+}
+
+impl RidRestrictions {
+    pub fn from_key_vals(key_vals: Vec<(String, String)>) -> Self {
+        let mut r = RidRestrictions::default();
+
+        for (k, v) in key_vals {
+            fn num<T: FromStr>(k: &str, v: &str) -> Option<T> {
+                let n = v.parse().ok();
+                if n.is_none() {
+                    debug!("Ignore a=rid restriction with bad value: {}={}", k, v);
+                }
+                n
+            }
+
+            match k.as_str() {
+                "max-width" => r.max_width = num(&k, &v),
+                "max-height" => r.max_height = num(&k, &v),
+                "max-fps" => r.max_fps = num(&k, &v).map(F32Eq),
+                "max-fs" => r.max_fs = num(&k, &v),
+                "max-br" => r.max_br = num(&k, &v),
+                "max-pps" => r.max_pps = num(&k, &v),
+                "max-bpp" => r.max_bpp = num(&k, &v).map(F32Eq),
+                "depend" => r.depend = v.split(',').map(Rid::from).collect(),
+                _ => r.unknown.push((k, v)),
+            }
+        }
+
+        r
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The restrictions we understand, for writing in an answer.
+    pub fn known(&self) -> Self {
+        RidRestrictions {
+            unknown: vec![],
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for RidRestrictions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut kvs: Vec<(&str, String)> = vec![];
+
+        fn push<T: fmt::Display>(kvs: &mut Vec<(&str, String)>, k: &'static str, v: &Option<T>) {
+            if let Some(v) = v {
+                kvs.push((k, v.to_string()));
+            }
+        }
+
+        push(&mut kvs, "max-width", &self.max_width);
+        push(&mut kvs, "max-height", &self.max_height);
+        push(&mut kvs, "max-fps", &self.max_fps);
+        push(&mut kvs, "max-fs", &self.max_fs);
+        push(&mut kvs, "max-br", &self.max_br);
+        push(&mut kvs, "max-pps", &self.max_pps);
+        push(&mut kvs, "max-bpp", &self.max_bpp);
+
+        if !self.depend.is_empty() {
+            let depend: Vec<_> = self.depend.iter().map(|r| r.to_string()).collect();
+            kvs.push(("depend", depend.join(",")));
+        }
+
+        for (k, v) in &self.unknown {
+            kvs.push((k, v.clone()));
+        }
+
+        let joined: Vec<_> = kvs.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        write!(f, "{}", joined.join(";"))
+    }
+}
+
 impl FromStr for F32Eq {
     type Err = ParseFloatError;
 
@@ -806,14 +1057,17 @@ pub enum MediaAttribute {
     MaxMessageSize(usize),
     // a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level
     // a=extmap:2 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time
+    // a=extmap:3/recvonly urn:3gpp:video-orientation
     ExtMap {
         id: u8, // 1-14 inclusive,
         ext: Extension,
+        direction: Option<Direction>,
     },
-    RecvOnly, // a=recvonly
-    SendRecv, // a=sendrecv
-    SendOnly, // a=sendonly
-    Inactive, // a=inactive
+    ExtmapAllowMixed, // a=extmap-allow-mixed
+    RecvOnly,         // a=recvonly
+    SendRecv,         // a=sendrecv
+    SendOnly,         // a=sendonly
+    Inactive,         // a=inactive
     // a=msid:5UUdwiuY7OML2EkQtF38pJtNP5v7In1LhjEK f78dde68-7055-4e20-bb37-433803dd1ed1
     // a=msid:- 78dde68-7055-4e20-bb37-433803dd1ed1
     Msid(Msid),
@@ -832,6 +1086,10 @@ pub enum MediaAttribute {
         pt: Pt,        // 111
         value: String, // nack, nack pli, ccm fir...
     },
+    // rtcp-fb for all payload types, a=rtcp-fb:* nack
+    RtcpFbAll {
+        value: String,
+    },
     // format parameters, seems to be one of these
     Fmtp {
         pt: Pt,                   // 111
@@ -845,7 +1103,7 @@ pub enum MediaAttribute {
         direction: &'static str, // send or recv
         // No pt means the rid applies to all
         pt: Vec<Pt>, // 111, 112 (rtpmap no)
-        restriction: Vec<(String, String)>,
+        restriction: RidRestrictions,
     },
     // a=rid:hi send
     // a=rid:lo send
@@ -872,7 +1130,7 @@ impl MediaAttribute {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FormatParam {
     /// The minimum duration of media represented by a packet.
     ///
@@ -885,6 +1143,18 @@ pub enum FormatParam {
     /// Specifies that the decoder can do Opus DTX
     UseDtx(bool),
 
+    /// The maximum duration of media represented by a Opus packet.
+    MaxPTime(u8),
+
+    /// The maximum Opus output sample rate the decoder renders, in Hz.
+    MaxPlaybackRate(u32),
+
+    /// The maximum Opus average bitrate the decoder wants, in bits per second.
+    MaxAverageBitrate(u32),
+
+    /// Specifies that the decoder prefers Opus in stereo.
+    Stereo(bool),
+
     /// Whether h264 sending media encoded at a different level in the offerer-to-answerer
     /// direction than the level in the answerer-to-offerer direction, is allowed.
     LevelAsymmetryAllowed(bool),
@@ -906,10 +1176,19 @@ pub enum FormatParam {
     /// VP9 profile id
     ProfileId(u32),
 
+    /// H265 max difference in decoding order number. DONL/DOND fields are present when > 0.
+    SpropMaxDonDiff(u32),
+
     /// RTX (resend) codecs, which PT it concerns.
     Apt(Pt),
 
-    /// Unrecognized fmtp.
+    /// RTX (resend) codecs, how long in milliseconds the sender keeps packets.
+    RtxTime(u16),
+
+    /// A key we don't interpret, kept as is.
+    Other(String, String),
+
+    /// Unparseable fmtp.
     Unknown,
 }
 
@@ -927,6 +1206,31 @@ impl FormatParam {
             }
             "useinbandfec" => UseInbandFec(v == "1"),
             "usedtx" => UseDtx(v == "1"),
+            "maxptime" => {
+                if let Ok(v) = v.parse() {
+                    MaxPTime(v)
+                } else {
+                    trace!("Failed to parse: {}", k);
+                    Unknown
+                }
+            }
+            "maxplaybackrate" => {
+                if let Ok(v) = v.parse() {
+                    MaxPlaybackRate(v)
+                } else {
+                    trace!("Failed to parse: {}", k);
+                    Unknown
+                }
+            }
+            "maxaveragebitrate" => {
+                if let Ok(v) = v.parse() {
+                    MaxAverageBitrate(v)
+                } else {
+                    trace!("Failed to parse: {}", k);
+                    Unknown
+                }
+            }
+            "stereo" => Stereo(v == "1"),
             "level-asymmetry-allowed" => LevelAsymmetryAllowed(v == "1"),
             "packetization-mode" => {
                 if let Ok(v) = v.parse() {
@@ -952,6 +1256,14 @@ impl FormatParam {
                     Unknown
                 }
             }
+            "sprop-max-don-diff" => {
+                if let Ok(v) = v.parse() {
+                    SpropMaxDonDiff(v)
+                } else {
+                    trace!("Failed to parse: {}", k);
+                    Unknown
+                }
+            }
             "apt" => {
                 if let Ok(v) = v.parse::<u8>() {
                     Apt(v.into())
@@ -960,7 +1272,15 @@ impl FormatParam {
                     Unknown
                 }
             }
-            _ => Unknown,
+            "rtx-time" => {
+                if let Ok(v) = v.parse() {
+                    RtxTime(v)
+                } else {
+                    trace!("Failed to parse: {}", k);
+                    Unknown
+                }
+            }
+            _ => Other(k.to_string(), v.to_string()),
         }
     }
 }
@@ -972,13 +1292,20 @@ impl fmt::Display for FormatParam {
             MinPTime(v) => write!(f, "minptime={v}"),
             UseInbandFec(v) => write!(f, "useinbandfec={}", i32::from(*v)),
             UseDtx(v) => write!(f, "usedtx={}", i32::from(*v)),
+            MaxPTime(v) => write!(f, "maxptime={v}"),
+            MaxPlaybackRate(v) => write!(f, "maxplaybackrate={v}"),
+            MaxAverageBitrate(v) => write!(f, "maxaveragebitrate={v}"),
+            Stereo(v) => write!(f, "stereo={}", i32::from(*v)),
             LevelAsymmetryAllowed(v) => {
                 write!(f, "level-asymmetry-allowed={}", i32::from(*v))
             }
             PacketizationMode(v) => write!(f, "packetization-mode={}", *v),
             ProfileLevelId(v) => write!(f, "profile-level-id={:06x}", *v),
             ProfileId(v) => write!(f, "profile-id={}", *v),
+            SpropMaxDonDiff(v) => write!(f, "sprop-max-don-diff={}", *v),
             Apt(v) => write!(f, "apt={v}"),
+            RtxTime(v) => write!(f, "rtx-time={v}"),
+            Other(k, v) => write!(f, "{k}={v}"),
             Unknown => Ok(()),
         }
     }
@@ -986,6 +1313,16 @@ impl fmt::Display for FormatParam {
 
 impl PayloadParams {
     pub(crate) fn as_media_attrs(&self, attrs: &mut Vec<MediaAttribute>) {
+        self.as_media_attrs_with_fmtp(attrs, self.spec.format.to_format_param());
+    }
+
+    /// Like [`as_media_attrs()`][Self::as_media_attrs], but with other `a=fmtp` values than
+    /// the ones of the format.
+    pub(crate) fn as_media_attrs_with_fmtp(
+        &self,
+        attrs: &mut Vec<MediaAttribute>,
+        fmtps: Vec<FormatParam>,
+    ) {
         attrs.push(MediaAttribute::RtpMap {
             pt: self.pt,
             value: self.spec.into(),
@@ -1022,7 +1359,6 @@ impl PayloadParams {
             });
         }
 
-        let fmtps = self.spec.format.to_format_param();
         if !fmtps.is_empty() {
             attrs.push(MediaAttribute::Fmtp {
                 pt: self.pt,
@@ -1039,10 +1375,11 @@ impl PayloadParams {
                     channels: None,
                 },
             });
-            attrs.push(MediaAttribute::Fmtp {
-                pt,
-                values: vec![FormatParam::Apt(self.pt)],
-            });
+            let mut values = vec![FormatParam::Apt(self.pt)];
+            if let Some(v) = self.rtx_time {
+                values.push(FormatParam::RtxTime(v.get()));
+            }
+            attrs.push(MediaAttribute::Fmtp { pt, values });
         }
     }
 }
@@ -1079,6 +1416,8 @@ impl From<CodecSpec> for RtpMap {
 pub struct Simulcast {
     pub send: SimulcastGroups,
     pub recv: SimulcastGroups,
+    /// Restrictions from the a=rid lines, by rid.
+    pub restrictions: Vec<(String, RidRestrictions)>,
     /// If this is created synthetically for a munged SDP.
     pub is_munged: bool,
 }
@@ -1088,23 +1427,79 @@ impl Simulcast {
         Simulcast {
             send: self.recv,
             recv: self.send,
+            restrictions: self.restrictions,
             is_munged: self.is_munged,
         }
     }
+
+    pub fn restrictions(&self, rid: &str) -> Option<&RidRestrictions> {
+        self.restrictions
+            .iter()
+            .find(|(r, _)| r == rid)
+            .map(|(_, r)| r)
+    }
+
+    /// Narrow an answer to what was offered, both from our side.
+    ///
+    /// An answer can remove rids and alternatives, but never add any (RFC 8853).
+    /// Returns `None` if nothing is left.
+    pub fn narrow_to(mut self, offered: &Simulcast) -> Option<Self> {
+        fn has(g: &SimulcastGroups, rid: &RestrictionId) -> bool {
+            g.rids().any(|r| r.0 == rid.0)
+        }
+
+        self.send.retain_rids(|r| has(&offered.send, r));
+        self.recv.retain_rids(|r| has(&offered.recv, r));
+
+        if self.send.is_empty() && self.recv.is_empty() {
+            return None;
+        }
+
+        Some(self)
+    }
 }
 
 /// RID organization inside a=simulcast line.
 ///
 /// `a=simulcast send 2;3,4` would result in
-/// `SimulcastGroups(vec![RestrictionId("2"), RestrictionId("3")])`
+/// `SimulcastGroups(vec![SimulcastAlternatives(["2"]), SimulcastAlternatives(["3", "4"])])`
+///
+/// Each group is one simulcast stream. Sending, we use the first alternative, receiving
+/// we expect any of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulcastGroups(pub Vec<SimulcastAlternatives>);
+
+/// Alternative rids for one simulcast stream, in order of preference.
 ///
-/// The choice between 3 and 4 for the rid is currently ignored and the first option is always
-/// selected.
+/// Never empty.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct SimulcastGroups(pub Vec<RestrictionId>);
+pub struct SimulcastAlternatives(pub Vec<RestrictionId>);
+
+impl SimulcastGroups {
+    /// All rids, including alternatives.
+    pub fn rids(&self) -> impl Iterator<Item = &RestrictionId> {
+        self.0.iter().flat_map(|a| a.0.iter())
+    }
+
+    /// Retain the rids (and alternatives) matching the predicate.
+    ///
+    /// Groups left without alternatives are removed.
+    pub fn retain_rids(&mut self, mut f: impl FnMut(&RestrictionId) -> bool) {
+        for a in &mut self.0 {
+            a.0.retain(&mut f);
+        }
+        self.0.retain(|a| !a.0.is_empty());
+    }
+}
+
+impl SimulcastAlternatives {
+    pub fn first(&self) -> &RestrictionId {
+        &self.0[0]
+    }
+}
 
 impl Deref for SimulcastGroups {
-    type Target = [RestrictionId];
+    type Target = [SimulcastAlternatives];
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -1120,10 +1515,11 @@ pub struct Msid {
 impl fmt::Display for SimulcastGroups {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for (idx, a) in self.0.iter().enumerate() {
+            let alts: Vec<_> = a.0.iter().map(|r| r.to_sdp()).collect();
             if idx + 1 == self.0.len() {
-                write!(f, "{}", a.to_sdp())?;
+                write!(f, "{}", alts.join(","))?;
             } else {
-                write!(f, "{};", a.to_sdp())?;
+                write!(f, "{};", alts.join(","))?;
             }
         }
         Ok(())
@@ -1190,6 +1586,7 @@ impl fmt::Display for SessionAttribute {
             Setup(v) => write!(f, "a=setup:{}\r\n", v.setup_line())?,
             Candidate(c) => write!(f, "a={}\r\n", c.to_sdp_string())?,
             EndOfCandidates => write!(f, "a=end-of-candidates\r\n")?,
+            ExtmapAllowMixed => write!(f, "a=extmap-allow-mixed\r\n")?,
             Unused(v) => write!(f, "a={v}\r\n")?,
         }
         Ok(())
@@ -1198,7 +1595,8 @@ impl fmt::Display for SessionAttribute {
 
 impl fmt::Display for MediaLine {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "m={} 9 {} ", self.typ, self.proto,)?;
+        let port = if self.disabled { 0 } else { 9 };
+        write!(f, "m={} {} {} ", self.typ, port, self.proto)?;
         let len = self.pts.len();
         if self.typ.is_channel() {
             write!(f, "webrtc-datachannel\r\n")?;
@@ -1260,20 +1658,21 @@ impl fmt::Display for MediaAttribute {
             Mid(v) => write!(f, "a=mid:{v}\r\n")?,
             SctpPort(v) => write!(f, "a=sctp-port:{v}\r\n")?,
             MaxMessageSize(v) => write!(f, "a=max-message-size:{v}\r\n")?,
-            ExtMap { id, ext } => {
+            ExtMap { id, ext, direction } => {
                 if !ext.is_serialized() {
                     return Ok(());
                 }
                 write!(f, "a=extmap:{}", id)?;
-                // if let Some(d) = &e.direction {
-                //     write!(f, "/{d}")?;
-                // }
+                if let Some(d) = direction {
+                    write!(f, "/{d}")?;
+                }
                 write!(f, " {}", ext.as_uri())?;
                 // if let Some(e) = &e.ext {
                 //     write!(f, " {}", e)?;
                 // }
                 write!(f, "\r\n")?;
             }
+            ExtmapAllowMixed => write!(f, "a=extmap-allow-mixed\r\n")?,
             RecvOnly => write!(f, "a=recvonly\r\n")?,
             SendRecv => write!(f, "a=sendrecv\r\n")?,
             SendOnly => write!(f, "a=sendonly\r\n")?,
@@ -1294,6 +1693,7 @@ impl fmt::Display for MediaAttribute {
                 write!(f, "\r\n")?;
             }
             RtcpFb { pt, value } => write!(f, "a=rtcp-fb:{pt} {value}\r\n")?,
+            RtcpFbAll { value } => write!(f, "a=rtcp-fb:* {value}\r\n")?,
             Fmtp { pt, values } => {
                 write!(f, "a=fmtp:{pt} ")?;
                 for (idx, v) in values.iter().enumerate() {
@@ -1322,19 +1722,13 @@ impl fmt::Display for MediaAttribute {
                         write!(f, "{p},")?;
                     }
                 }
-                for (idx, (k, v)) in restriction.iter().enumerate() {
-                    if idx == 0 {
-                        if pt.is_empty() {
-                            write!(f, " ")?;
-                        } else {
-                            write!(f, ";")?;
-                        }
-                    }
-                    if idx + 1 == restriction.len() {
-                        write!(f, "{k}={v}")?;
+                if !restriction.is_empty() {
+                    if pt.is_empty() {
+                        write!(f, " ")?;
                     } else {
-                        write!(f, "{k}={v};")?;
+                        write!(f, ";")?;
                     }
+                    write!(f, "{restriction}")?;
                 }
                 write!(f, "\r\n")?;
             }
@@ -1344,13 +1738,10 @@ impl fmt::Display for MediaAttribute {
                     send,
                     recv,
                     is_munged,
+                    ..
                 } = x;
-                assert!(
-                    !(send.0.is_empty() && recv.0.is_empty()),
-                    "Empty a=simulcast"
-                );
-                if *is_munged {
-                    // don't write
+                if *is_munged || send.0.is_empty() && recv.0.is_empty() {
+                    // don't write, empty if all rids were malformed.
                     return Ok(());
                 }
                 write!(f, "a=simulcast:")?;
@@ -1417,6 +1808,180 @@ mod test {
         assert_eq!(f.to_string(), "minptime=10;useinbandfec=1");
     }
 
+    #[test]
+    fn ssrc_info_sim_group() {
+        let group = |semantics: &str, ssrcs: &[u32]| MediaAttribute::SsrcGroup {
+            semantics: semantics.into(),
+            ssrcs: ssrcs.iter().map(|s| (*s).into()).collect(),
+        };
+
+        let line = MediaLine {
+            typ: MediaType::Video,
+            disabled: false,
+            proto: Proto::Srtp,
+            pts: vec![],
+            bw: None,
+            attrs: vec![
+                group("SIM", &[1, 2, 3]),
+                group("FID", &[3, 13]),
+                group("FID", &[1, 11]),
+                group("FID", &[2, 12]),
+            ],
+        };
+
+        let infos = line.ssrc_info();
+        let info = |ssrc: u32| infos.iter().find(|i| *i.ssrc == ssrc).unwrap();
+
+        for (layer, ssrc) in [1, 2, 3].into_iter().enumerate() {
+            assert_eq!(info(ssrc).sim_layer, Some(layer));
+            assert_eq!(info(ssrc + 10).repairs, Some(ssrc.into()));
+            assert_eq!(info(ssrc + 10).sim_layer, None);
+        }
+    }
+
+    #[test]
+    fn rtcp_fb_per_payload_type() {
+        let input = "v=0\r\n\
+        o=- 7710052215259647220 2 IN IP4 0.0.0.0\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        m=video 9 UDP/TLS/RTP/SAVPF 96 97 98\r\n\
+        c=IN IP4 0.0.0.0\r\n\
+        a=mid:0\r\n\
+        a=sendrecv\r\n\
+        a=rtcp-fb:* nack\r\n\
+        a=rtpmap:96 VP8/90000\r\n\
+        a=rtcp-fb:96 transport-cc\r\n\
+        a=rtcp-fb:96 nack  pli\r\n\
+        a=rtcp-fb:96 x-unknown 1\r\n\
+        a=rtpmap:97 rtx/90000\r\n\
+        a=fmtp:97 apt=96\r\n\
+        a=rtcp-fb:97 goog-remb\r\n\
+        a=rtpmap:98 VP9/90000\r\n\
+        a=rtcp-fb:98 ccm fir\r\n\
+        ";
+
+        let sdp = Sdp::parse(input).unwrap();
+        let m = &sdp.media_lines[0];
+
+        // Unknown feedback is kept in the attributes.
+        assert!(m.to_string().contains("a=rtcp-fb:96 x-unknown 1\r\n"));
+
+        let params = m.rtp_params();
+        assert_eq!(params.len(), 2);
+
+        // The wildcard applies to both, the RTX feedback to none.
+        let vp8 = &params[0];
+        assert_eq!(vp8.resend, Some(97.into()));
+        assert!(vp8.fb_nack && vp8.fb_pli && vp8.fb_transport_cc);
+        assert!(!vp8.fb_fir && !vp8.fb_remb);
+
+        let vp9 = &params[1];
+        assert!(vp9.fb_nack && vp9.fb_fir);
+        assert!(!vp9.fb_pli && !vp9.fb_transport_cc && !vp9.fb_remb);
+    }
+
+    #[test]
+    fn rtx_time_and_dangling_rtx() {
+        let input = "v=0\r\n\
+        o=- 7710052215259647220 2 IN IP4 0.0.0.0\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        m=video 9 UDP/TLS/RTP/SAVPF 96 97 98 99\r\n\
+        c=IN IP4 0.0.0.0\r\n\
+        a=mid:0\r\n\
+        a=sendrecv\r\n\
+        a=rtpmap:96 VP8/90000\r\n\
+        a=rtpmap:97 rtx/90000\r\n\
+        a=fmtp:97 apt=96;rtx-time=3000\r\n\
+        a=rtpmap:98 rtx/90000\r\n\
+        a=rtpmap:99 rtx/90000\r\n\
+        a=fmtp:99 apt=100\r\n\
+        ";
+
+        let sdp = Sdp::parse(input).unwrap();
+        let m = &sdp.media_lines[0];
+
+        assert!(m.to_string().contains("a=fmtp:97 apt=96;rtx-time=3000\r\n"));
+
+        let params = m.rtp_params();
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0].resend, Some(97.into()));
+        assert_eq!(
+            params[0].rtx_time(),
+            Some(std::time::Duration::from_secs(3))
+        );
+
+        // 98 has no apt, 99 is for a PT that isn't there.
+        assert_eq!(m.dangling_rtx_pts(), vec![98.into(), 99.into()]);
+
+        let mut attrs = vec![];
+        params[0].as_media_attrs(&mut attrs);
+        assert!(attrs.contains(&MediaAttribute::Fmtp {
+            pt: 97.into(),
+            values: vec![FormatParam::Apt(96.into()), FormatParam::RtxTime(3000)],
+        }));
+    }
+
+    #[test]
+    fn consistency_bundle_mid_count() {
+        let sdp = |group: &str, second: &str| {
+            let input = format!(
+                "v=0\r\n\
+                o=- 7710052215259647220 2 IN IP4 0.0.0.0\r\n\
+                s=-\r\n\
+                t=0 0\r\n\
+                a=group:BUNDLE {group}\r\n\
+                m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+                c=IN IP4 0.0.0.0\r\n\
+                a=mid:0\r\n\
+                a=sendrecv\r\n\
+                a=rtcp-mux\r\n\
+                a=rtpmap:111 opus/48000/2\r\n\
+                {second}"
+            );
+            Sdp::parse(&input).unwrap()
+        };
+
+        let video = "m=video 9 UDP/TLS/RTP/SAVPF 96\r\n\
+            c=IN IP4 0.0.0.0\r\n\
+            a=mid:1\r\n\
+            a=sendrecv\r\n\
+            a=rtcp-mux\r\n\
+            a=rtpmap:96 VP8/90000\r\n";
+
+        assert!(sdp("0 1", video).assert_consistency().is_ok());
+
+        // In any order.
+        assert!(sdp("1 0", video).assert_consistency().is_ok());
+
+        // An m-line outside the BUNDLE.
+        assert!(sdp("0", video).assert_consistency().is_ok());
+
+        // Several BUNDLE groups.
+        let two = "0\r\na=group:BUNDLE 1";
+        assert!(sdp(two, video).assert_consistency().is_ok());
+
+        // A removed m-line is not in the BUNDLE.
+        let removed = video.replace("m=video 9", "m=video 0");
+        assert!(sdp("0", &removed).assert_consistency().is_ok());
+
+        // A bundle-only m-line is.
+        let bundle_only = removed.replace("a=mid:1\r\n", "a=mid:1\r\na=bundle-only\r\n");
+        assert!(sdp("0 1", &bundle_only).assert_consistency().is_ok());
+
+        // Every mid in the BUNDLE must have an m-line.
+        assert!(sdp("0 1 2", video).assert_consistency().is_err());
+        assert!(sdp("0 2", video).assert_consistency().is_err());
+    }
+
+    #[test]
+    fn fmtp_param_sprop_max_don_diff() {
+        let f = FormatParams::parse_line("sprop-max-don-diff=2");
+        assert_eq!(f.sprop_max_don_diff, Some(2));
+        assert_eq!(f.to_string(), "sprop-max-don-diff=2");
+    }
+
     #[test]
     fn parse_error() {
         let input = "v=0\r\n\
@@ -1489,12 +2054,12 @@ mod test {
                         MediaAttribute::Fingerprint(Fingerprint { hash_func: "sha-256".into(), bytes: vec![140, 100, 237, 3, 118, 208, 61, 180, 136, 8, 145, 100, 8, 128, 168, 198, 90, 191, 139, 78, 56, 39, 150, 202, 8, 73, 37, 115, 70, 96, 32, 220] }),
                         MediaAttribute::Setup(Setup::ActPass),
                         MediaAttribute::Mid("0".into()),
-                        MediaAttribute::ExtMap{ id: 1, ext: Extension::AudioLevel },
-                        MediaAttribute::ExtMap{ id: 2, ext: Extension::AbsoluteSendTime },
-                        MediaAttribute::ExtMap{ id: 3, ext: Extension::TransportSequenceNumber },
-                        MediaAttribute::ExtMap{ id: 4, ext: Extension::RtpMid },
-                        MediaAttribute::ExtMap{ id: 5, ext: Extension::RtpStreamId },
-                        MediaAttribute::ExtMap{ id: 6, ext: Extension::RepairedRtpStreamId },
+                        MediaAttribute::ExtMap{ id: 1, ext: Extension::AudioLevel, direction: None },
+                        MediaAttribute::ExtMap{ id: 2, ext: Extension::AbsoluteSendTime, direction: None },
+                        MediaAttribute::ExtMap{ id: 3, ext: Extension::TransportSequenceNumber, direction: None },
+                        MediaAttribute::ExtMap{ id: 4, ext: Extension::RtpMid, direction: None },
+                        MediaAttribute::ExtMap{ id: 5, ext: Extension::RtpStreamId, direction: None },
+                        MediaAttribute::ExtMap{ id: 6, ext: Extension::RepairedRtpStreamId, direction: None },
                         MediaAttribute::SendRecv,
                         MediaAttribute::Msid(Msid { stream_id: "5UUdwiuY7OML2EkQtF38pJtNP5v7In1LhjEK".into(), track_id: "f78dde68-7055-4e20-bb37-433803dd1ed1".into() }),
                         MediaAttribute::RtcpMux,
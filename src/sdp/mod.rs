@@ -6,9 +6,10 @@ use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
 mod data;
+pub(crate) use data::RestrictionId;
 pub(crate) use data::{FormatParam, Sdp, Session, SessionAttribute, Setup};
 pub(crate) use data::{MediaAttribute, MediaLine, MediaType, Msid, Proto};
-pub(crate) use data::{Simulcast, SimulcastGroups};
+pub(crate) use data::{Simulcast, SimulcastAlternatives, SimulcastGroups};
 pub(crate) use parser::parse_candidate;
 
 #[cfg(test)]
@@ -1,4 +1,4 @@
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use {
     combine::error::*,
     combine::parser::char::*,
@@ -11,7 +11,7 @@ use {
 use crate::crypto::Fingerprint;
 use crate::rtp_::{Direction, Extension, Frequency, Mid, Pt, SessionId, Ssrc};
 use crate::sdp::SdpError;
-use crate::{Candidate, CandidateKind};
+use crate::{Candidate, CandidateKind, TcpType};
 
 use super::data::*;
 
@@ -182,6 +182,10 @@ where
     // a=end-of-candidates
     let endof = attribute_line_flag("end-of-candidates").map(|_| SessionAttribute::EndOfCandidates);
 
+    // a=extmap-allow-mixed
+    let allow_mixed =
+        attribute_line_flag("extmap-allow-mixed").map(|_| SessionAttribute::ExtmapAllowMixed);
+
     // tls-id
     // identity
     // extmap
@@ -198,6 +202,7 @@ where
         attempt(setup),
         attempt(cand),
         attempt(endof),
+        attempt(allow_mixed),
         unused,
     ))
 }
@@ -232,6 +237,20 @@ where
         })
     };
 
+    // An IP, or an mDNS hostname that is resolved later.
+    let connection_addr = not_sp().and_then(|s| {
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            Ok((ip, None))
+        } else if s.to_ascii_lowercase().ends_with(".local") {
+            Ok((IpAddr::V4(Ipv4Addr::UNSPECIFIED), Some(s)))
+        } else {
+            Err(StreamErrorFor::<Input>::message_format(format!(
+                "invalid address: {}",
+                s
+            )))
+        }
+    });
+
     let kind = choice((
         string("host").map(|_| CandidateKind::Host),
         string("prflx").map(|_| CandidateKind::PeerReflexive),
@@ -262,12 +281,19 @@ where
                 .map_err(StreamErrorFor::<Input>::message_format)
         }),
         token(' '),
-        ip_addr(),
+        connection_addr,
         token(' '),
         port(),
         string(" typ "),
         kind,
-        optional((attempt(string(" tcptype ")), not_sp())),
+        optional((
+            attempt(string(" tcptype ")),
+            not_sp().and_then(|s| {
+                TcpType::try_from(s.as_str()).map_err(|_| {
+                    StreamErrorFor::<Input>::message_format(format!("invalid tcptype: {}", s))
+                })
+            }),
+        )),
         optional((
             attempt(string(" raddr ")),
             ip_addr(),
@@ -290,19 +316,19 @@ where
                 _,
                 prio,
                 _,
-                addr,
+                (addr, mdns_name),
                 _,
                 port,
                 _,
                 kind,
-                _,     // (" tcptype ", tcptype)
-                raddr, // (" raddr ", addr, " rport ", port)
-                _,     // (" generation ", generation)
-                _,     // (" network-id ", network_id)
-                ufrag, // (" ufrag ", ufrag)
-                _,     // ("network-cost", network_cost)
+                tcptype, // (" tcptype ", tcptype)
+                raddr,   // (" raddr ", addr, " rport ", port)
+                _,       // (" generation ", generation)
+                _,       // (" network-id ", network_id)
+                ufrag,   // (" ufrag ", ufrag)
+                _,       // ("network-cost", network_cost)
             )| {
-                Candidate::parsed(
+                let mut c = Candidate::parsed(
                     found,
                     comp_id,
                     proto,
@@ -311,7 +337,12 @@ where
                     kind,
                     raddr.map(|(_, addr, _, port)| SocketAddr::from((addr, port))),
                     ufrag.map(|(_, u)| u),
-                )
+                    tcptype.map(|(_, t)| t),
+                );
+                if let Some(name) = mdns_name {
+                    c.set_mdns_name(name);
+                }
+                c
             },
         )
 }
@@ -519,7 +550,15 @@ where
             optional((token(' '), any_value())),
         ),
     )
-    .map(|(id, _dir_opt, _, ext, _ext_opt)| MediaAttribute::ExtMap { id, ext });
+    .map(|(id, dir_opt, _, ext, _ext_opt)| MediaAttribute::ExtMap {
+        id,
+        ext,
+        direction: dir_opt.map(|(_, d)| d),
+    });
+
+    // a=extmap-allow-mixed
+    let allow_mixed =
+        attribute_line_flag("extmap-allow-mixed").map(|_| MediaAttribute::ExtmapAllowMixed);
 
     let direction = choice((
         attempt(attribute_line_flag("recvonly").map(|_| MediaAttribute::RecvOnly)),
@@ -594,9 +633,15 @@ where
     // a=rtcp-fb:111 ccm fir
     // a=rtcp-fb:111 nack
     // a=rtcp-fb:111 nack pli
-    let rtcp_fb = attribute_line("rtcp-fb", (pt(), token(' '), any_value()))
+    let rtcp_fb_pt = attribute_line("rtcp-fb", (pt(), token(' '), any_value()))
         .map(|(pt, _, value)| MediaAttribute::RtcpFb { pt, value });
 
+    // a=rtcp-fb:* nack
+    let rtcp_fb_all = attribute_line("rtcp-fb", (token('*'), token(' '), any_value()))
+        .map(|(_, _, value)| MediaAttribute::RtcpFbAll { value });
+
+    let rtcp_fb = choice((attempt(rtcp_fb_pt), attempt(rtcp_fb_all)));
+
     let fmtp_param = sep_by1(
         key_val().map(|(k, v)| FormatParam::parse(&k, &v)),
         token(';'),
@@ -618,10 +663,12 @@ where
     let fmtp = choice((attempt(fmtp1), attempt(fmtp2)));
 
     // a=rid:<rid-id> <direction> [pt=<fmt-list>;]<restriction>=<value>
+    //
+    // A line with a malformed rid-id doesn't parse, and is ignored as unused (RFC 8851).
     let rid = attribute_line(
         "rid",
         (
-            name().map(RestrictionId::new_active),
+            rid_id().map(RestrictionId::new_active),
             token(' '),
             choice((string("send"), string("recv"))),
             optional((
@@ -652,19 +699,24 @@ where
             id,
             direction,
             pt,
-            restriction,
+            restriction: RidRestrictions::from_key_vals(restriction),
         }
     });
 
+    // Tokens are parsed leniently, and malformed rid-ids are removed after.
     let simul1 = |direction: &'static str| {
         (
             string(direction),
             token(' '),
+            // Older Firefox: a=simulcast: send rid=h;m;l
+            optional(attempt(string("rid="))),
             sep_by1::<Vec<Vec<(String, bool)>>, _, _, _>(
                 sep_by1(
                     optional(token('~'))
-                        .and(name())
-                        .map(|x| (x.1, x.0.is_none())),
+                        .and(many1(satisfy(|c| {
+                            !matches!(c, ';' | ',' | ' ' | '\r' | '\n')
+                        })))
+                        .map(|x: (_, String)| (x.1, x.0.is_none())),
                     token(','),
                 ),
                 token(';'),
@@ -679,39 +731,49 @@ where
     ));
 
     // a=simulcast:<send/recv> <alt A>;<alt B>,<or C> <send/recv> [same]
-    let simulcast = attribute_line("simulcast", simul2).map(|(s1, maybe_s2)| {
-        let mut send = SimulcastGroups(vec![]);
-        let mut recv = SimulcastGroups(vec![]);
-
-        fn to_simul(to: &mut SimulcastGroups, groups: Vec<Vec<(String, bool)>>) {
-            for group in groups {
-                // TODO: Properly support rid alternatives, for now we ignore any alternatives
-                // provided and use the first rid.
-                let first = group.into_iter().next();
-
-                if let Some(rid) = first.map(|(rid, active)| RestrictionId::new(rid, active)) {
-                    to.0.push(rid);
+    let simulcast =
+        attribute_line("simulcast", (optional(token(' ')), simul2)).map(|(_, (s1, maybe_s2))| {
+            let mut send = SimulcastGroups(vec![]);
+            let mut recv = SimulcastGroups(vec![]);
+
+            fn to_simul(to: &mut SimulcastGroups, groups: Vec<Vec<(String, bool)>>) {
+                for group in groups {
+                    let alternatives: Vec<_> = group
+                        .into_iter()
+                        .filter(|(rid, _)| {
+                            let valid = is_rid_id(rid);
+                            if !valid {
+                                debug!("Ignore malformed rid in a=simulcast: {}", rid);
+                            }
+                            valid
+                        })
+                        .map(|(rid, active)| RestrictionId::new(rid, active))
+                        .collect();
+
+                    if !alternatives.is_empty() {
+                        to.0.push(SimulcastAlternatives(alternatives));
+                    }
                 }
             }
-        }
 
-        {
-            let to = if s1.0 == "send" { &mut send } else { &mut recv };
-            to_simul(to, s1.2);
-        }
+            {
+                let to = if s1.0 == "send" { &mut send } else { &mut recv };
+                to_simul(to, s1.3);
+            }
 
-        if let Some(s2) = maybe_s2 {
-            let s2 = s2.1;
-            let to = if s2.0 == "send" { &mut send } else { &mut recv };
-            to_simul(to, s2.2);
-        }
+            if let Some(s2) = maybe_s2 {
+                let s2 = s2.1;
+                let to = if s2.0 == "send" { &mut send } else { &mut recv };
+                to_simul(to, s2.3);
+            }
 
-        MediaAttribute::Simulcast(Simulcast {
-            send,
-            recv,
-            is_munged: false,
-        })
-    });
+            MediaAttribute::Simulcast(Simulcast {
+                send,
+                recv,
+                restrictions: vec![],
+                is_munged: false,
+            })
+        });
 
     // a=ssrc-group:FID 1111 2222
     let ssrc_group = attribute_line(
@@ -762,7 +824,8 @@ where
         attempt(mid),
         attempt(sctp_port),
         attempt(max_message_size),
-        attempt(extmap),
+        // Nested, since the choice tuple has a max size.
+        attempt(extmap).or(attempt(allow_mixed)),
         attempt(direction),
         attempt(msid),
         attempt(rtcp),
@@ -806,13 +869,21 @@ where
         .message("sdp line")
 }
 
-/// alphanumeric name.
-fn name<Input>() -> impl Parser<Input, Output = String>
+/// rid-id = 1*(alpha-numeric / "-" / "_")
+fn rid_id<Input>() -> impl Parser<Input, Output = String>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    many1(satisfy(|c: char| c.is_alphanumeric()))
+    many1(satisfy(is_rid_char))
+}
+
+fn is_rid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn is_rid_id(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(is_rid_char)
 }
 
 /// Not SP, \r or \n
@@ -929,6 +1000,18 @@ mod test {
         assert_eq!("a=rid:lo send\r\n", x.0.to_string());
     }
 
+    #[test]
+    fn media_attribute_line_rtcp_fb_all() {
+        let x = media_attribute_line().parse("a=rtcp-fb:* nack").unwrap();
+        assert_eq!(
+            x.0,
+            MediaAttribute::RtcpFbAll {
+                value: "nack".into()
+            }
+        );
+        assert_eq!("a=rtcp-fb:* nack\r\n", x.0.to_string());
+    }
+
     #[test]
     fn media_attribute_line_rid_pt() {
         let x = media_attribute_line()
@@ -942,12 +1025,44 @@ mod test {
         let x = media_attribute_line()
             .parse("a=rid:lo send max-br=64000;max-height=360")
             .unwrap();
+        // Written in a fixed order.
         assert_eq!(
-            "a=rid:lo send max-br=64000;max-height=360\r\n",
+            "a=rid:lo send max-height=360;max-br=64000\r\n",
             x.0.to_string()
         );
     }
 
+    #[test]
+    fn media_attribute_line_rid_restr_typed() {
+        let x = media_attribute_line()
+            .parse("a=rid:h-1 recv max-width=1280;max-height=720;max-fps=29.97;depend=l,m;x-foo=bar;max-br=abc")
+            .unwrap();
+        let MediaAttribute::Rid {
+            id, restriction, ..
+        } = x.0
+        else {
+            panic!("Expected a=rid");
+        };
+        assert_eq!(id.0, "h-1");
+        assert_eq!(restriction.max_width, Some(1280));
+        assert_eq!(restriction.max_height, Some(720));
+        assert_eq!(
+            restriction.max_fps.map(|f| f.to_string()),
+            Some("29.97".into())
+        );
+        assert_eq!(restriction.depend, vec!["l".into(), "m".into()]);
+        // Unknown keys are kept, bad values of known keys are ignored.
+        assert_eq!(restriction.unknown, vec![("x-foo".into(), "bar".into())]);
+        assert_eq!(restriction.max_br, None);
+    }
+
+    #[test]
+    fn media_attribute_line_rid_malformed() {
+        // Not a valid rid-id, and ignored.
+        let x = media_attribute_line().parse("a=rid:h.1 send").unwrap();
+        assert!(matches!(x.0, MediaAttribute::Unused(_)));
+    }
+
     #[test]
     fn media_attribute_line_rid_pt_restr() {
         let x = media_attribute_line()
@@ -972,7 +1087,23 @@ mod test {
         let x = media_attribute_line()
             .parse("a=simulcast:send 2,3;4")
             .unwrap();
-        assert_eq!("a=simulcast:send 2;4\r\n", x.0.to_string());
+        assert_eq!("a=simulcast:send 2,3;4\r\n", x.0.to_string());
+    }
+
+    #[test]
+    fn media_attribute_line_simulcast_malformed() {
+        let x = media_attribute_line()
+            .parse("a=simulcast:send h.1,h;~m;l!")
+            .unwrap();
+        assert_eq!("a=simulcast:send h;~m\r\n", x.0.to_string());
+    }
+
+    #[test]
+    fn media_attribute_line_simulcast_old_firefox() {
+        let x = media_attribute_line()
+            .parse("a=simulcast: send rid=h;m;l")
+            .unwrap();
+        assert_eq!("a=simulcast:send h;m;l\r\n", x.0.to_string());
     }
 
     #[test]
@@ -1,27 +1,36 @@
 use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
-use crate::bwe::BweKind;
+use std::collections::HashMap;
+
+use crate::bwe::{BweConfig, BweEstimate, BweKind, BweResetReason};
+use crate::change::RtcpMuxPolicy;
 use crate::crypto::KeyingMaterial;
 use crate::crypto::SrtpProfile;
-use crate::format::CodecConfig;
 use crate::format::PayloadParams;
-use crate::io::{DatagramSend, DATAGRAM_MTU, DATAGRAM_MTU_WARN};
+use crate::format::{Codec, CodecConfig, CodecRegistry};
+use crate::io::{DatagramSend, Id, DATAGRAM_MTU, DATAGRAM_MTU_WARN};
 use crate::media::KeyframeRequestKind;
-use crate::media::Media;
+use crate::media::{Media, MediaFeatures, MediaKind};
 use crate::media::{MediaAdded, MediaChanged};
-use crate::packet::SendSideBandwithEstimator;
-use crate::packet::{LeakyBucketPacer, NullPacer, Pacer, PacerImpl};
+use crate::packet::{parse_red, ReceiveSideBandwithEstimator, SendSideBandwithEstimator};
+use crate::packet::{EgressRates, LeakyBucketPacer, NullPacer, Pacer, PacerImpl, SendClass};
 use crate::rtp::RawPacket;
+use crate::rtp_::AbsSendTimeUnwrapper;
 use crate::rtp_::Direction;
+use crate::rtp_::Frequency;
 use crate::rtp_::Pt;
+use crate::rtp_::RtpError;
 use crate::rtp_::SeqNo;
+use crate::rtp_::TwccSeqAllocator;
 use crate::rtp_::SRTCP_OVERHEAD;
+use crate::rtp_::SRTP_OVERHEAD;
 use crate::rtp_::{extend_u16, RtpHeader, SessionId, TwccRecvRegister, TwccSendRegister};
-use crate::rtp_::{Bitrate, ExtensionMap, Mid, Rtcp, RtcpFb};
+use crate::rtp_::{Bitrate, DataSize, ExtensionMap, Mid, Remb, Rtcp, RtcpFb};
+use crate::rtp_::{Descriptions, Goodbye, RawRtcp, ReportList, RtcpConfig, Sdes, SdesType};
 use crate::rtp_::{SrtpContext, Ssrc};
-use crate::stats::StatsSnapshot;
-use crate::streams::{RtpPacket, Streams};
+use crate::stats::{BweStats, RtcStats, StatsSnapshot};
+use crate::streams::{RegisterUpdateReceipt, RtpPacket, Streams};
 use crate::util::{already_happened, not_happening, Soonest};
 use crate::Event;
 use crate::{net, Reason};
@@ -42,6 +51,29 @@ const PACING_FACTOR: f64 = 1.1;
 /// the total number BWE events to only fire when there is a substantial change.
 const ESTIMATE_TOLERANCE: f64 = 0.05;
 
+/// Max time between BWE events, even if the estimate is stable.
+const ESTIMATE_MAX_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Span of TWCC feedback considered for the loss based estimate.
+const LOSS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Loss above which the loss based estimate decreases, below which it increases.
+/// As in <https://datatracker.ietf.org/doc/html/draft-ietf-rmcat-gcc-02#section-6>.
+const LOSS_HIGH: f32 = 0.1;
+const LOSS_LOW: f32 = 0.02;
+
+/// How long incoming packets protected with the keys from before a rekey are accepted.
+const SRTP_REKEY_GRACE: Duration = Duration::from_secs(5);
+
+/// Starting point for the average compound RTCP size, with UDP and IP headers.
+const RTCP_INITIAL_AVG_SIZE: DataSize = DataSize::bytes(100);
+
+/// UDP and IPv4 headers, counted in the RTCP size as RFC 3550 section 6.2 says.
+const UDP_IP_OVERHEAD: u64 = 28;
+
+/// Largest unencrypted compound RTCP, rounded to nearest multiple of 4 bytes.
+const ENCRYPTABLE_MTU: usize = (DATAGRAM_MTU - SRTCP_OVERHEAD) & !3;
+
 pub(crate) struct Session {
     id: SessionId,
 
@@ -58,8 +90,14 @@ pub(crate) struct Session {
 
     reordering_size_audio: usize,
     reordering_size_video: usize,
+    h264_length_prefixed: bool,
+    codec_registry: CodecRegistry,
     pub send_buffer_audio: usize,
     pub send_buffer_video: usize,
+    srtp_replay_window: usize,
+    srtp_key_lifetime: u64,
+    srtp_max_ssrcs: usize,
+    srtp_ssrc_idle_timeout: Duration,
 
     /// Extension mappings are _per BUNDLE_, but we can only have one a=group BUNDLE
     /// in WebRTC (one ice connection), so they are effectively per session.
@@ -70,9 +108,16 @@ pub(crate) struct Session {
 
     srtp_rx: Option<SrtpContext>,
     srtp_tx: Option<SrtpContext>,
+    /// SRTCP from the DTLS on ICE component 2, when RTCP is not muxed with RTP.
+    srtcp_rx: Option<SrtpContext>,
+    srtcp_tx: Option<SrtpContext>,
+    /// When to forget the incoming keys replaced by a rekey.
+    srtp_rx_previous_until: Option<Instant>,
+    /// Whether Event::SrtpKeyExpiring has been emitted for the current keys.
+    srtp_key_expiring: bool,
     last_nack: Instant,
     last_twcc: Instant,
-    twcc: u64,
+    twcc: TwccSeqAllocator,
     twcc_rx_register: TwccRecvRegister,
     twcc_tx_register: TwccSendRegister,
 
@@ -80,24 +125,68 @@ pub(crate) struct Session {
 
     enable_twcc_feedback: bool,
 
+    /// Estimate of the incoming bitrate sent as REMB, when the remote doesn't do TWCC.
+    remb_rx: Option<ReceiveSideBandwithEstimator>,
+
+    /// Unwraps the incoming abs-send-time, which is in the clock of the remote sender.
+    abs_send_time: AbsSendTimeUnwrapper,
+
     /// A pacer for sending RTP at specific rate.
     pacer: PacerImpl,
 
+    /// Send rates per SSRC and kind of traffic, for stats.
+    egress_rates: EgressRates,
+
     // temporary buffer when getting the next (unencrypted) RTP packet from Media line.
     poll_packet_buf: Vec<u8>,
 
-    // Next packet for RtpPacket event.
-    pending_packet: Option<RtpPacket>,
+    // Next packets for RtpPacket event.
+    pending_packets: VecDeque<RtpPacket>,
+
+    // Payload types of RED and ULPFEC, when receiving ULPFEC.
+    ulpfec_receive: Option<(Pt, Pt)>,
 
     pub ice_lite: bool,
 
     /// Whether we are running in RTP-mode.
     pub rtp_mode: bool,
 
+    /// Default features for new media.
+    pub media_features: MediaFeatures,
+
+    /// Bandwidth and bounds for the SR/RR interval. None uses fixed intervals.
+    rtcp_config: Option<RtcpConfig>,
+    /// Average size of our compound RTCP, for the interval.
+    rtcp_avg_size: DataSize,
+    /// Whether feedback can be sent as reduced-size RTCP (RFC 5506), without SR/RR and SDES.
+    rtcp_reduced_size: bool,
+    /// Whether we accept a peer that doesn't do a=rtcp-mux.
+    rtcp_mux_policy: RtcpMuxPolicy,
+    /// Whether RTCP is muxed with RTP (RFC 5761). None until negotiated, which is muxed.
+    rtcp_mux: Option<bool>,
+
+    /// CNAME shared by all our SSRCs, in SDP and SDES. Fixed for the session.
+    pub cname: String,
+    sdes_name: Option<String>,
+    sdes_tool: Option<String>,
+
     feedback_tx: VecDeque<Rtcp>,
     feedback_rx: VecDeque<Rtcp>,
 
+    // Pre-serialized RTCP from the user, and incoming RTCP we don't parse.
+    feedback_tx_raw: VecDeque<RawRtcp>,
+    feedback_rx_raw: VecDeque<RawRtcp>,
+
     raw_packets: Option<VecDeque<Box<RawPacket>>>,
+
+    /// Set by close(). No more RTP, only the feedback ending with BYE.
+    closing: bool,
+
+    /// Incoming RTP dropped since it didn't map to any stream.
+    rtp_unmapped: u64,
+
+    /// Incoming SSRCs in no a=ssrc-group:SIM, received as single streams.
+    ssrc_group_fallbacks: u64,
 }
 
 impl Session {
@@ -108,16 +197,22 @@ impl Session {
         while *id > MAX_ID {
             id = (*id >> 1).into();
         }
-        let (pacer, bwe) = if let Some(rate) = config.bwe_initial_bitrate {
+        let (pacer, bwe) = if let Some(bwe_config) = config.bwe_config {
+            let rate = bwe_config.start_bitrate();
             let pacer = PacerImpl::LeakyBucket(LeakyBucketPacer::new(rate * PACING_FACTOR * 2.0));
 
-            let send_side_bwe = SendSideBandwithEstimator::new(rate);
+            let send_side_bwe = SendSideBandwithEstimator::new(&bwe_config);
             let bwe = Bwe {
                 bwe: send_side_bwe,
+                config: bwe_config,
                 desired_bitrate: Bitrate::ZERO,
                 current_bitrate: rate,
+                bounds: HashMap::new(),
+                loss_estimate: None,
+                now: None,
 
-                last_emitted_estimate: Bitrate::ZERO,
+                reset: None,
+                last_emitted: None,
             };
 
             (pacer, Some(bwe))
@@ -132,8 +227,14 @@ impl Session {
             app: None,
             reordering_size_audio: config.reordering_size_audio,
             reordering_size_video: config.reordering_size_video,
+            h264_length_prefixed: config.h264_length_prefixed,
+            codec_registry: config.codec_registry.clone(),
             send_buffer_audio: config.send_buffer_audio,
             send_buffer_video: config.send_buffer_video,
+            srtp_replay_window: config.srtp_replay_window,
+            srtp_key_lifetime: config.srtp_key_lifetime,
+            srtp_max_ssrcs: config.srtp_max_ssrcs,
+            srtp_ssrc_idle_timeout: config.srtp_ssrc_idle_timeout,
             exts: config.exts.clone(),
 
             // Both sending and receiving starts from the configured codecs.
@@ -142,25 +243,50 @@ impl Session {
 
             srtp_rx: None,
             srtp_tx: None,
+            srtcp_rx: None,
+            srtcp_tx: None,
+            srtp_rx_previous_until: None,
+            srtp_key_expiring: false,
             last_nack: already_happened(),
             last_twcc: already_happened(),
-            twcc: 0,
+            twcc: TwccSeqAllocator::new(),
             twcc_rx_register: TwccRecvRegister::new(100),
             twcc_tx_register: TwccSendRegister::new(1000),
             bwe,
             enable_twcc_feedback: false,
+            remb_rx: None,
+            abs_send_time: AbsSendTimeUnwrapper::new(),
             pacer,
+            egress_rates: EgressRates::new(),
             poll_packet_buf: vec![0; 2000],
-            pending_packet: None,
+            pending_packets: VecDeque::new(),
+            ulpfec_receive: config.ulpfec_receive,
             ice_lite: config.ice_lite,
             rtp_mode: config.rtp_mode,
+            media_features: config.media_features,
+            rtcp_config: config.rtcp_config,
+            rtcp_avg_size: RTCP_INITIAL_AVG_SIZE,
+            rtcp_reduced_size: true,
+            rtcp_mux_policy: config.rtcp_mux_policy,
+            rtcp_mux: None,
+            cname: config
+                .cname
+                .clone()
+                .unwrap_or_else(|| Id::<16>::random().to_string()),
+            sdes_name: config.sdes_name.clone(),
+            sdes_tool: config.sdes_tool.clone(),
             feedback_tx: VecDeque::new(),
             feedback_rx: VecDeque::new(),
+            feedback_tx_raw: VecDeque::new(),
+            feedback_rx_raw: VecDeque::new(),
             raw_packets: if config.enable_raw_packets {
                 Some(VecDeque::new())
             } else {
                 None
             },
+            closing: false,
+            rtp_unmapped: 0,
+            ssrc_group_fallbacks: 0,
         }
     }
 
@@ -186,25 +312,108 @@ impl Session {
         &self.app
     }
 
+    /// Set the SRTP keys, or switch to new ones if there already are keys.
     pub fn set_keying_material(
         &mut self,
         mat: KeyingMaterial,
         srtp_profile: SrtpProfile,
         active: bool,
+        now: Instant,
     ) {
         // TODO: rename this to `initialise_srtp_context`?
         // Whether we're active or passive determines if we use the left or right
         // hand side of the key material to derive input/output.
         let left = active;
 
-        self.srtp_rx = Some(SrtpContext::new(srtp_profile, &mat, !left));
+        let mut srtp_rx = SrtpContext::new(srtp_profile, &mat, !left);
+
+        if let Some(current) = &mut self.srtp_rx {
+            // Rekey. Packets sent before the remote switched can still be in flight.
+            debug!(
+                "SRTP rekey, keep previous keys until {:?}",
+                now + SRTP_REKEY_GRACE
+            );
+            current.rekey_rx(srtp_rx);
+            self.srtp_rx_previous_until = Some(now + SRTP_REKEY_GRACE);
+        } else {
+            srtp_rx.set_replay_window(self.srtp_replay_window);
+            srtp_rx.set_ssrc_limits(self.srtp_max_ssrcs, self.srtp_ssrc_idle_timeout);
+            self.srtp_rx = Some(srtp_rx);
+        }
+
+        // Sending switches to the new keys with the next packet.
         self.srtp_tx = Some(SrtpContext::new(srtp_profile, &mat, left));
+        self.srtp_key_expiring = false;
+    }
+
+    /// Set the SRTCP keys from the DTLS on the RTCP component, when RTCP isn't muxed.
+    pub fn set_rtcp_keying_material(
+        &mut self,
+        mat: KeyingMaterial,
+        srtp_profile: SrtpProfile,
+        active: bool,
+    ) {
+        let left = active;
+
+        let mut srtcp_rx = SrtpContext::new(srtp_profile, &mat, !left);
+        srtcp_rx.set_replay_window(self.srtp_replay_window);
+        srtcp_rx.set_ssrc_limits(self.srtp_max_ssrcs, self.srtp_ssrc_idle_timeout);
+
+        self.srtcp_rx = Some(srtcp_rx);
+        self.srtcp_tx = Some(SrtpContext::new(srtp_profile, &mat, left));
+    }
+
+    fn srtp_key_expiring(&self) -> bool {
+        [&self.srtp_rx, &self.srtp_tx, &self.srtcp_rx, &self.srtcp_tx]
+            .into_iter()
+            .flatten()
+            .any(|s| s.key_expiring(self.srtp_key_lifetime))
+    }
+
+    /// The context for outgoing SRTCP, which is the SRTP one when muxed.
+    fn srtcp_tx(&self) -> Option<&SrtpContext> {
+        if self.rtcp_mux() {
+            self.srtp_tx.as_ref()
+        } else {
+            self.srtcp_tx.as_ref()
+        }
     }
 
     pub fn handle_timeout(&mut self, now: Instant) -> Result<(), RtcError> {
+        // Sending more SRTCP requires a rekey.
+        if self.srtcp_tx().map_or(false, |s| s.srtcp_index_exhausted()) {
+            return Err(RtpError::SrtcpIndexExhausted.into());
+        }
+
+        if self.srtp_rx_previous_until.map_or(false, |t| now >= t) {
+            if let Some(srtp) = &mut self.srtp_rx {
+                srtp.drop_previous();
+            }
+            self.srtp_rx_previous_until = None;
+        }
+
+        if let Some(srtp) = &mut self.srtp_rx {
+            srtp.handle_timeout(now);
+        }
+
+        if let Some(srtp) = &mut self.srtcp_rx {
+            srtp.handle_timeout(now);
+        }
+
+        // The final reports were made by close(), nothing more to send.
+        if self.closing {
+            return Ok(());
+        }
+
         // Payload any waiting samples
         self.do_payload(now)?;
 
+        if let Some(config) = &self.rtcp_config {
+            let (members, senders, we_sent) = self.streams.rtcp_members();
+            let interval = config.interval(members, senders, we_sent, self.rtcp_avg_size);
+            self.streams.set_report_interval(Some(interval));
+        }
+
         let sender_ssrc = self.streams.first_ssrc_local();
 
         let do_nack = now >= self.nack_at().unwrap_or(not_happening());
@@ -234,6 +443,21 @@ impl Session {
             bwe.handle_timeout(now);
         }
 
+        if let Some(remb_rx) = &mut self.remb_rx {
+            remb_rx.handle_timeout(now);
+
+            if let Some((bitrate, ssrcs)) = remb_rx.poll_remb(now) {
+                let remb = Remb {
+                    sender_ssrc,
+                    ssrc: 0.into(),
+                    bitrate: bitrate.as_f64() as f32,
+                    ssrcs: ssrcs.into_iter().map(|s| *s).collect(),
+                };
+                trace!("Created feedback REMB: {:?}", remb);
+                self.feedback_tx.push_back(Rtcp::Remb(remb));
+            }
+        }
+
         Ok(())
     }
 
@@ -305,14 +529,19 @@ impl Session {
         // B) Mid+PT - when not doing simulcast, the PT identifies whether
         //             this is a repair stream.
 
-        let Some(mid) = header.ext_vals.mid else {
+        // Senders using a=ssrc-group:SIM typically don't send mid.
+        let Some(mid) = header.ext_vals.mid.or_else(|| self.single_sim_group_mid()) else {
             return;
         };
         let rid = header.ext_vals.rid.or(header.ext_vals.rid_repair);
 
         // The media the mid points out. Bail if the mid points to something
-        // we don't know about.
-        let Some(media) = self.medias.iter_mut().find(|m| m.mid() == mid) else {
+        // we don't know about, or that is removed.
+        let Some(media) = self
+            .medias
+            .iter_mut()
+            .find(|m| m.mid() == mid && !m.is_disabled())
+        else {
             return;
         };
 
@@ -327,7 +556,22 @@ impl Session {
             return;
         };
 
-        if let Some(rid) = rid {
+        if media.has_sim_group() && rid.is_none() {
+            // The SSRC is in no SIM group. Receive it as a single stream, unless it's
+            // RTX, which we can't tell what it repairs.
+            if payload.pt() == header.payload_type {
+                warn!(
+                    "SSRC {} not in any SIM group, single stream for mid: {}",
+                    header.ssrc, mid
+                );
+                self.ssrc_group_fallbacks += 1;
+
+                let suppress_nack = payload.resend().is_none() || !media.features().nack;
+                let reason = Some("Not in SIM group");
+                self.streams
+                    .expect_stream_rx(header.ssrc, None, mid, None, suppress_nack, reason);
+            }
+        } else if let Some(rid) = rid {
             // Case A - use the rid_repair header to identify RTX.
             let is_main = header.ext_vals.rid.is_some();
 
@@ -342,20 +586,41 @@ impl Session {
         }
     }
 
+    /// The mid of the only media with a=ssrc-group:SIM, if there is exactly one.
+    fn single_sim_group_mid(&self) -> Option<Mid> {
+        let mut iter = self.medias.iter().filter(|m| m.has_sim_group());
+        let media = iter.next()?;
+        iter.next().is_none().then(|| media.mid())
+    }
+
     pub(crate) fn handle_rtp(&mut self, now: Instant, mut header: RtpHeader, buf: &[u8]) {
         // Rewrite absolute-send-time (if present) to be relative to now.
-        header.ext_vals.update_absolute_send_time(now);
+        header
+            .ext_vals
+            .update_absolute_send_time(now, &mut self.abs_send_time);
 
         trace!("Handle RTP: {:?}", header);
+
+        // The ssrc is the _main_ ssrc (no the rtx, that might be in the header).
+        let mid_ssrc = self.mid_and_ssrc_for_header(&header);
+
         if let Some(transport_cc) = header.ext_vals.transport_cc {
-            let prev = self.twcc_rx_register.max_seq();
-            let extended = extend_u16(Some(*prev), transport_cc);
-            self.twcc_rx_register.update_seq(extended.into(), now);
+            // Media with TWCC turned off is not recorded.
+            let twcc = mid_ssrc
+                .and_then(|(mid, _)| self.medias.iter().find(|m| m.mid() == mid))
+                .map_or(true, |m| m.features().twcc);
+
+            if twcc && self.enable_twcc_feedback {
+                let prev = self.twcc_rx_register.max_seq();
+                let extended = extend_u16(Some(*prev), transport_cc);
+                self.twcc_rx_register.update_seq(extended.into(), now);
+            }
         }
 
-        // The ssrc is the _main_ ssrc (no the rtx, that might be in the header).
-        let Some((mid, ssrc)) = self.mid_and_ssrc_for_header(&header) else {
+        let Some((mid, ssrc)) = mid_ssrc else {
+            // For simulcast, this is packets before the rid is seen for the SSRC.
             debug!("No mid/SSRC for header: {:?}", header);
+            self.rtp_unmapped += 1;
             return;
         };
 
@@ -366,30 +631,63 @@ impl Session {
                 return;
             }
         };
+        srtp.handle_timeout(now);
 
-        // Both of these unwraps are fine because mid_and_ssrc_for_header guarantees it.
-        let media = self.medias.iter_mut().find(|m| m.mid() == mid).unwrap();
+        // This unwrap is fine because mid_and_ssrc_for_header guarantees it.
         let stream = self.streams.stream_rx(&ssrc).unwrap();
 
-        let params = match main_payload_params(&self.codec_config, header.payload_type) {
-            Some(p) => p,
-            None => {
+        // FlexFEC has a sequence number series of its own, separate from the main stream.
+        if stream.fec() == Some(header.ssrc) {
+            let Some(mut data) = srtp.unprotect_rtp(buf, &header) else {
+                trace!("Failed to unprotect SRTP");
+                return;
+            };
+
+            if header.has_padding && !RtpHeader::unpad_payload(&mut data) {
+                trace!("unpadding of unprotected payload failed");
+                return;
+            }
+
+            if let Some(raw_packets) = &mut self.raw_packets {
+                raw_packets.push_back(Box::new(RawPacket::RtpRx(header.clone(), data.clone())));
+            }
+
+            let recovered = stream.add_flexfec(&data);
+            self.handle_rtp_recovered(now, recovered);
+            return;
+        }
+
+        // RED carries the media, or ULPFEC, in the sequence number series of the main
+        // stream. The payload type inside is only known once decrypted.
+        let red = self
+            .ulpfec_receive
+            .filter(|(red_pt, _)| *red_pt == header.payload_type);
+
+        let (clock_rate, is_repair) = if red.is_some() {
+            // ULPFEC is only used for video.
+            (Frequency::NINETY_KHZ, false)
+        } else {
+            let Some(params) = main_payload_params(&self.codec_config, header.payload_type) else {
                 trace!(
                     "No payload params could be found (main or RTX) for {:?}",
                     header.payload_type
                 );
                 return;
-            }
+            };
+            (params.spec().clock_rate, params.pt() != header.payload_type)
         };
-        let clock_rate = params.spec().clock_rate;
-        let pt = params.pt();
-        let is_repair = pt != header.payload_type;
+
+        // A ROC signaled out of band applies to the next packet of the main stream.
+        if !is_repair {
+            if let Some(roc) = stream.pending_reset_roc() {
+                srtp.reset_roc(*header.ssrc, roc);
+            }
+        }
 
         // is_repair controls whether update is updating the main register or the RTX register.
-        // Either way we get a seq_no_outer which is used to decrypt the SRTP.
         let receipt_outer = stream.update(now, &header, clock_rate, is_repair);
 
-        let mut data = match srtp.unprotect_rtp(buf, &header, *receipt_outer.seq_no) {
+        let mut data = match srtp.unprotect_rtp(buf, &header) {
             Some(v) => v,
             None => {
                 trace!("Failed to unprotect SRTP");
@@ -397,6 +695,25 @@ impl Session {
             }
         };
 
+        if let Some(remb_rx) = &mut self.remb_rx {
+            remb_rx.update(
+                now,
+                ssrc,
+                header.ext_vals.abs_send_time,
+                (header.timestamp, clock_rate),
+                buf.len().into(),
+            );
+        }
+
+        let mut recovered = vec![];
+
+        // FlexFEC protects the main stream packets as sent, with padding.
+        if stream.fec().is_some() && !is_repair && red.is_none() {
+            let mut protected = buf[..header.header_len].to_vec();
+            protected.extend_from_slice(&data);
+            recovered = stream.add_flexfec_media(protected);
+        }
+
         if header.has_padding && !RtpHeader::unpad_payload(&mut data) {
             // Unpadding failed. Broken data?
             trace!("unpadding of unprotected payload failed");
@@ -407,6 +724,43 @@ impl Session {
             raw_packets.push_back(Box::new(RawPacket::RtpRx(header.clone(), data.clone())));
         }
 
+        if let Some((_, ulpfec_pt)) = red {
+            // Video RED has only the primary block.
+            let Some((inner_pt, _, primary)) = parse_red(&data).ok().and_then(|mut b| b.pop())
+            else {
+                trace!("Failed to parse RED payload");
+                return;
+            };
+            let start = data.len() - primary.len();
+
+            if inner_pt == ulpfec_pt {
+                let recovered = stream.ulpfec().add_ulpfec(&data[start..]);
+                self.handle_rtp_recovered(now, recovered);
+                return;
+            }
+
+            header.payload_type = inner_pt;
+            data.drain(..start);
+
+            // ULPFEC protects the media packet as it was before wrapping in RED.
+            let mut protected = buf[..header.header_len].to_vec();
+            protected[0] &= !0x20; // no padding
+            protected[1] = (protected[1] & 0x80) | *inner_pt;
+            protected.extend_from_slice(&data);
+
+            recovered = stream.ulpfec().add_media(protected);
+        }
+
+        let Some(params) = main_payload_params(&self.codec_config, header.payload_type) else {
+            trace!(
+                "No payload params could be found for RED {:?}",
+                header.payload_type
+            );
+            return;
+        };
+        let pt = params.pt();
+        let codec = params.spec().codec;
+
         // RTX packets must be rewritten to be a normal packet. This only changes the
         // the seq_no, however MediaTime might be different when interpreted against the
         // the "main" register.
@@ -430,34 +784,122 @@ impl Session {
             receipt_outer
         };
 
-        let Some(packet) = stream.handle_rtp(now, header, data, receipt.seq_no, receipt.time)
+        self.handle_rtp_receipt(now, mid, ssrc, codec, header, data, receipt, false);
+        self.handle_rtp_recovered(now, recovered);
+    }
+
+    /// Handle packets recovered with ULPFEC or FlexFEC, complete decrypted RTP packets.
+    fn handle_rtp_recovered(&mut self, now: Instant, packets: Vec<Vec<u8>>) {
+        for buf in packets {
+            let Some(header) = RtpHeader::parse(&buf, &self.exts) else {
+                trace!("Failed to parse recovered RTP header");
+                continue;
+            };
+            let Some((mid, ssrc)) = self.streams.mid_ssrc_rx_by_ssrc_or_rtx(header.ssrc) else {
+                continue;
+            };
+            let Some(params) = main_payload_params(&self.codec_config, header.payload_type) else {
+                trace!("No payload params for recovered {:?}", header.payload_type);
+                continue;
+            };
+            let clock_rate = params.spec().clock_rate;
+            let codec = params.spec().codec;
+
+            let stream = self.streams.stream_rx(&ssrc).unwrap();
+            let receipt = stream.update(now, &header, clock_rate, false);
+
+            // The packet might have arrived some other way, like RTX.
+            if !receipt.is_new_packet {
+                continue;
+            }
+
+            let mut data = buf[header.header_len..].to_vec();
+            if header.has_padding && !RtpHeader::unpad_payload(&mut data) {
+                trace!("unpadding of recovered payload failed");
+                continue;
+            }
+
+            self.handle_rtp_receipt(now, mid, ssrc, codec, header, data, receipt, true);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn handle_rtp_receipt(
+        &mut self,
+        now: Instant,
+        mid: Mid,
+        ssrc: Ssrc,
+        codec: Codec,
+        header: RtpHeader,
+        data: Vec<u8>,
+        receipt: RegisterUpdateReceipt,
+        recovered: bool,
+    ) {
+        // Both of these unwraps are fine because the callers found the mid and SSRC.
+        let media = self.medias.iter_mut().find(|m| m.mid() == mid).unwrap();
+        let stream = self.streams.stream_rx(&ssrc).unwrap();
+
+        if codec == Codec::Vp9 {
+            stream.update_vp9_structure(&data);
+        }
+
+        if codec == Codec::Opus && receipt.is_new_packet {
+            stream.update_opus_dtx(receipt.seq_no, receipt.time, header.marker, &data);
+        }
+
+        let Some(mut packet) = stream.handle_rtp(now, header, data, receipt.seq_no, receipt.time)
         else {
             return;
         };
+        packet.recovered = recovered;
 
-        if self.rtp_mode {
+        if media.rtp_mode() {
             // In RTP mode, we store the packet temporarily here for the next poll_output().
             // However only if this is a packet not seen before. This filters out spurious resends for padding.
             if receipt.is_new_packet {
-                self.pending_packet = Some(packet);
+                self.pending_packets.push_back(packet);
             }
         } else {
+            if stream.take_depack_reset() {
+                media.reset_depayloaders(stream.rid());
+            }
+
             // In non-RTP mode, we let the Media use a Depayloader.
             media.depayload(
                 stream.rid(),
                 packet,
                 self.reordering_size_audio,
                 self.reordering_size_video,
+                self.h264_length_prefixed,
+                &self.codec_registry,
                 &self.codec_config,
             );
         }
     }
 
     fn handle_rtcp(&mut self, now: Instant, buf: &[u8]) -> Option<()> {
-        let srtp: &mut SrtpContext = self.srtp_rx.as_mut()?;
+        let srtp: &mut SrtpContext = if self.rtcp_mux() {
+            self.srtp_rx.as_mut()?
+        } else {
+            self.srtcp_rx.as_mut()?
+        };
+        srtp.handle_timeout(now);
         let unprotected = srtp.unprotect_rtcp(buf)?;
 
-        Rtcp::read_packet(&unprotected, &mut self.feedback_rx);
+        Rtcp::read_packet(
+            &unprotected,
+            &mut self.feedback_rx,
+            &mut self.feedback_rx_raw,
+        );
+
+        // The remote is done with these SSRCs, no need to keep SRTP state for them.
+        for fb in &self.feedback_rx {
+            if let Rtcp::Goodbye(g) = fb {
+                for ssrc in &g.reports {
+                    srtp.remove_ssrc(**ssrc);
+                }
+            }
+        }
         let mut need_configure_pacer = false;
 
         if let Some(raw_packets) = &mut self.raw_packets {
@@ -472,10 +914,12 @@ impl Session {
                 let range = self.twcc_tx_register.apply_report(twcc, now);
 
                 if let Some(bwe) = &mut self.bwe {
+                    let loss = self.twcc_tx_register.loss(LOSS_WINDOW, now);
                     let records = range.and_then(|range| self.twcc_tx_register.send_records(range));
 
                     if let Some(records) = records {
                         bwe.update(records, now);
+                        bwe.update_loss(loss);
                     }
                 }
                 need_configure_pacer = true;
@@ -509,10 +953,8 @@ impl Session {
     }
 
     pub fn poll_event(&mut self) -> Option<Event> {
-        if let Some(bitrate_estimate) = self.bwe.as_mut().and_then(|bwe| bwe.poll_estimate()) {
-            return Some(Event::EgressBitrateEstimate(BweKind::Twcc(
-                bitrate_estimate,
-            )));
+        if let Some(estimate) = self.bwe.as_mut().and_then(|bwe| bwe.poll_estimate()) {
+            return Some(Event::EgressBitrateEstimate(BweKind::Twcc(estimate)));
         }
 
         // If we're not ready to flow media, don't send any events.
@@ -520,22 +962,49 @@ impl Session {
             return None;
         }
 
+        if !self.srtp_key_expiring && self.srtp_key_expiring() {
+            self.srtp_key_expiring = true;
+            return Some(Event::SrtpKeyExpiring);
+        }
+
         if let Some(raw_packets) = &mut self.raw_packets {
             if let Some(p) = raw_packets.pop_front() {
                 return Some(Event::RawPacket(p));
             }
         }
 
-        // This must be before pending_packet.take() since we need to emit the unpaused event
+        // Before the first packet of the layer.
+        if let Some(layer) = self.streams.poll_layer_discovered() {
+            return Some(Event::LayerDiscovered(layer));
+        }
+
+        // This must be before pending_packets.pop_front() since we need to emit the unpaused event
         // before the first packet causing the unpause.
         if let Some(paused) = self.streams.poll_stream_paused() {
             return Some(Event::StreamPaused(paused));
         }
 
-        if self.rtp_mode {
-            if let Some(packet) = self.pending_packet.take() {
-                return Some(Event::RtpPacket(packet));
-            }
+        // Like paused, this must be emitted before the packet causing the restart.
+        if let Some(restarted) = self.streams.poll_stream_restarted() {
+            return Some(Event::StreamRestarted(restarted));
+        }
+
+        if let Some(ended) = self.streams.poll_stream_ended() {
+            return Some(Event::StreamEnded(ended));
+        }
+
+        // Before the packet ending the silence.
+        if let Some(silence) = self.streams.poll_stream_silence() {
+            return Some(Event::StreamSilence(silence));
+        }
+
+        // Only media in RTP mode queue packets here.
+        if let Some(packet) = self.pending_packets.pop_front() {
+            return Some(Event::RtpPacket(packet));
+        }
+
+        if let Some(raw) = self.feedback_rx_raw.pop_front() {
+            return Some(Event::RtcpPacket(raw));
         }
 
         if let Some(req) = self.streams.poll_keyframe_request() {
@@ -571,12 +1040,12 @@ impl Session {
     }
 
     pub fn poll_event_fallible(&mut self) -> Result<Option<Event>, RtcError> {
-        // Not relevant in rtp_mode, where the packets are picked up by poll_event().
-        if self.rtp_mode {
-            return Ok(None);
-        }
-
         for media in &mut self.medias {
+            // Not relevant in rtp_mode, where the packets are picked up by poll_event().
+            if media.rtp_mode() {
+                continue;
+            }
+
             if let Some(e) = media.poll_sample(&self.codec_config)? {
                 return Ok(Some(Event::MediaData(e)));
             }
@@ -585,6 +1054,13 @@ impl Session {
         Ok(None)
     }
 
+    /// Queue a pre-serialized RTCP packet to be sent verbatim in the next compound RTCP.
+    pub fn enqueue_raw_rtcp(&mut self, data: Vec<u8>) -> Result<(), RtpError> {
+        let raw = RawRtcp::new(data, ENCRYPTABLE_MTU).map_err(RtpError::InvalidRawRtcp)?;
+        self.feedback_tx_raw.push_back(raw);
+        Ok(())
+    }
+
     fn ready_for_srtp(&self) -> bool {
         self.srtp_rx.is_some() && self.srtp_tx.is_some()
     }
@@ -595,8 +1071,11 @@ impl Session {
             return None;
         }
 
+        // Without a=rtcp-mux, RTCP is polled separately to go on the RTCP component.
+        let rtcp_mux = self.rtcp_mux();
+
         let x = None
-            .or_else(|| self.poll_feedback())
+            .or_else(|| rtcp_mux.then(|| self.poll_feedback(now)).flatten())
             .or_else(|| self.poll_packet(now));
 
         if let Some(x) = &x {
@@ -611,17 +1090,83 @@ impl Session {
         x
     }
 
-    fn poll_feedback(&mut self) -> Option<net::DatagramSend> {
-        if self.feedback_tx.is_empty() {
+    /// RTCP to send on ICE component 2, when RTCP is not muxed with RTP.
+    pub fn poll_rtcp_datagram(&mut self, now: Instant) -> Option<net::DatagramSend> {
+        if now == already_happened() || self.rtcp_mux() {
+            return None;
+        }
+
+        // Hold on to the feedback until the DTLS on the RTCP component is done.
+        self.srtcp_tx.as_ref()?;
+
+        self.poll_feedback(now)
+    }
+
+    /// SDES with our CNAME for every SSRC we send, RTX included, as required in each
+    /// compound RTCP.
+    ///
+    /// NAME and TOOL, if configured, are only in the first chunk since all SSRCs share
+    /// the CNAME.
+    fn local_sdes(&mut self) -> Vec<Descriptions> {
+        // RTX is associated with the main SSRC by having the same CNAME.
+        let mut ssrcs: Vec<Ssrc> = self
+            .streams
+            .streams_tx()
+            .flat_map(|s| [Some(s.ssrc()), s.rtx()])
+            .flatten()
+            .collect();
+
+        // Receive only, describe the SSRC used in the receiver reports.
+        if ssrcs.is_empty() {
+            ssrcs.push(self.streams.first_ssrc_local());
+        }
+        ssrcs.sort();
+
+        let chunks = ssrcs.into_iter().enumerate().map(|(i, ssrc)| {
+            let mut values = ReportList::new();
+            values.push((SdesType::CNAME, self.cname.clone()));
+            if i == 0 {
+                if let Some(name) = &self.sdes_name {
+                    values.push((SdesType::NAME, name.clone()));
+                }
+                if let Some(tool) = &self.sdes_tool {
+                    values.push((SdesType::TOOL, tool.clone()));
+                }
+            }
+            Sdes { ssrc, values }
+        });
+
+        ReportList::lists_from_iter(chunks)
+            .into_iter()
+            .map(|reports| Descriptions {
+                reports: Box::new(reports),
+            })
+            .collect()
+    }
+
+    fn poll_feedback(&mut self, now: Instant) -> Option<net::DatagramSend> {
+        if self.feedback_tx.is_empty() && self.feedback_tx_raw.is_empty() {
             return None;
         }
 
-        // Round to nearest multiple of 4 bytes.
-        const ENCRYPTABLE_MTU: usize = (DATAGRAM_MTU - SRTCP_OVERHEAD) & !3;
         assert!(ENCRYPTABLE_MTU % 4 == 0);
 
         let mut data = vec![0_u8; ENCRYPTABLE_MTU];
 
+        let has_report = self
+            .feedback_tx
+            .iter()
+            .any(|fb| matches!(fb, Rtcp::SenderReport(_) | Rtcp::ReceiverReport(_)));
+
+        // Reduced-size, feedback without SR/RR goes on its own.
+        let sdes = if has_report || !self.rtcp_reduced_size {
+            self.local_sdes()
+        } else {
+            vec![]
+        };
+
+        let compound_ssrc = (!self.rtcp_reduced_size).then(|| self.streams.first_ssrc_local());
+
         let mut raw_packets = self.raw_packets.as_mut();
         let output = move |fb| {
             if let Some(raw_packets) = &mut raw_packets {
@@ -629,7 +1174,14 @@ impl Session {
             }
         };
 
-        let len = Rtcp::write_packet(&mut self.feedback_tx, &mut data, output);
+        let len = Rtcp::write_packet(
+            &mut self.feedback_tx,
+            &mut self.feedback_tx_raw,
+            &sdes,
+            compound_ssrc,
+            &mut data,
+            output,
+        );
 
         if len == 0 {
             return None;
@@ -637,18 +1189,41 @@ impl Session {
 
         data.truncate(len);
 
-        let srtp = self.srtp_tx.as_mut()?;
-        let protected = srtp.protect_rtcp(&data);
+        let srtp = if self.rtcp_mux() {
+            self.srtp_tx.as_mut()?
+        } else {
+            self.srtcp_tx.as_mut()?
+        };
+        let protected = match srtp.protect_rtcp(&data) {
+            Ok(v) => v,
+            Err(e) => {
+                // Surfaced as an error in handle_timeout.
+                debug!("Failed to protect RTCP: {}", e);
+                return None;
+            }
+        };
 
         assert!(
             protected.len() < DATAGRAM_MTU,
             "Encrypted SRTCP should be less than MTU"
         );
 
+        self.egress_rates
+            .register_rtcp(now, DataSize::from(protected.len()));
+
+        // Moving average as in RFC 3550 section 6.3.3.
+        let size = protected.len() as u64 + UDP_IP_OVERHEAD;
+        let avg = self.rtcp_avg_size.as_bytes_usize() as u64;
+        self.rtcp_avg_size = DataSize::bytes((avg * 15 + size) / 16);
+
         Some(protected.into())
     }
 
     fn poll_packet(&mut self, now: Instant) -> Option<DatagramSend> {
+        if self.closing {
+            return None;
+        }
+
         let srtp_tx = self.srtp_tx.as_mut()?;
 
         // Figure out which, if any, queue to poll
@@ -660,19 +1235,22 @@ impl Session {
             .expect("index is media");
 
         let buf = &mut self.poll_packet_buf;
-        let twcc_seq = self.twcc;
 
         // TODO: allow for sending simulcast
         let stream = self.streams.stream_tx_by_mid_rid(media.mid(), None)?;
+        let ssrc = stream.ssrc();
 
         let params = &self.codec_config;
         let exts = media.remote_extmap();
+        let twcc_enabled = media.features().twcc && media.feedback_caps().twcc;
         let receipt = stream.poll_packet(now, exts, &mut self.twcc, params, buf)?;
 
         let PacketReceipt {
             header,
             seq_no,
+            twcc_seq,
             is_padding,
+            is_rtx,
             payload_size,
         } = receipt;
 
@@ -685,16 +1263,36 @@ impl Session {
             crate::log_stat!("PACKET_SENT", header.ssrc, payload_size, kind);
         }
 
-        self.pacer.register_send(now, payload_size.into(), mid);
+        let class = if is_padding {
+            SendClass::Padding
+        } else if is_rtx {
+            SendClass::Rtx
+        } else {
+            SendClass::Media
+        };
+        self.pacer
+            .register_send(now, payload_size.into(), mid, class);
+        self.egress_rates
+            .register_rtp(now, ssrc, class, payload_size.into());
 
         if let Some(raw_packets) = &mut self.raw_packets {
             raw_packets.push_back(Box::new(RawPacket::RtpTx(header.clone(), buf.clone())));
         }
 
-        let protected = srtp_tx.protect_rtp(buf, &header, *seq_no);
-
-        self.twcc_tx_register
-            .register_seq(twcc_seq.into(), now, payload_size);
+        // Encrypt the packet buffer directly, it is then handed over as the datagram.
+        let len = buf.len();
+        buf.resize(len + SRTP_OVERHEAD, 0);
+        let protected_len = srtp_tx
+            .protect_rtp_in_place(buf, len, &header, *seq_no)
+            .expect("room for SRTP trailer");
+        buf.truncate(protected_len);
+        let protected = std::mem::take(buf);
+
+        // Without TWCC the remote doesn't report the packet.
+        if twcc_enabled {
+            self.twcc_tx_register
+                .register_seq(twcc_seq, now, payload_size, is_padding);
+        }
 
         // Technically we should wait for the next handle_timeout, but this speeds things up a bit
         // avoiding an extra poll_timeout.
@@ -704,18 +1302,24 @@ impl Session {
     }
 
     pub fn poll_timeout(&mut self) -> (Option<Instant>, Reason) {
+        if self.closing {
+            return (None, Reason::NotHappening);
+        }
+
         let feedback_at = self.regular_feedback_at();
         let nack_at = self.nack_at();
         let twcc_at = self.twcc_at();
         let pacing_at = self.pacer.poll_timeout();
         let packetize_at = self.medias.iter().flat_map(|m| m.poll_timeout()).next();
         let bwe_at = self.bwe.as_ref().map(|bwe| bwe.poll_timeout());
+        let remb_at = self.remb_rx.as_ref().and_then(|r| r.poll_timeout());
         let paused_at = self.paused_at();
         let send_stream_at = self.streams.send_stream();
 
         (feedback_at, Reason::Feedback)
             .soonest((nack_at, Reason::Nack))
             .soonest((twcc_at, Reason::Twcc))
+            .soonest((remb_at, Reason::Remb))
             .soonest((pacing_at, Reason::Pacing))
             .soonest((packetize_at, Reason::Packetize))
             .soonest((bwe_at, Reason::Bwe))
@@ -724,7 +1328,7 @@ impl Session {
     }
 
     pub fn has_mid(&self, mid: Mid) -> bool {
-        self.medias.iter().any(|m| m.mid() == mid)
+        self.medias.iter().any(|m| m.mid() == mid) || self.app.map_or(false, |(m, _)| m == mid)
     }
 
     fn regular_feedback_at(&self) -> Option<Instant> {
@@ -757,6 +1361,50 @@ impl Session {
             debug!("Enable TWCC feedback");
             self.enable_twcc_feedback = true;
         }
+
+        // The remote estimates itself from TWCC.
+        self.remb_rx = None;
+    }
+
+    pub fn rtcp_reduced_size(&self) -> bool {
+        self.rtcp_reduced_size
+    }
+
+    pub fn set_rtcp_reduced_size(&mut self, enabled: bool) {
+        if self.rtcp_reduced_size != enabled {
+            debug!("Reduced-size RTCP: {}", enabled);
+            self.rtcp_reduced_size = enabled;
+        }
+    }
+
+    pub fn rtcp_mux_policy(&self) -> RtcpMuxPolicy {
+        self.rtcp_mux_policy
+    }
+
+    /// Whether RTCP is muxed with RTP. This is the case until negotiated otherwise.
+    pub fn rtcp_mux(&self) -> bool {
+        self.rtcp_mux.unwrap_or(true)
+    }
+
+    /// The negotiated a=rtcp-mux, or None before the first negotiation.
+    pub fn rtcp_mux_negotiated(&self) -> Option<bool> {
+        self.rtcp_mux
+    }
+
+    pub fn set_rtcp_mux(&mut self, enabled: bool) {
+        if self.rtcp_mux != Some(enabled) {
+            debug!("RTCP mux: {}", enabled);
+            self.rtcp_mux = Some(enabled);
+        }
+    }
+
+    pub fn enable_remb_feedback(&mut self) {
+        if self.enable_twcc_feedback || self.remb_rx.is_some() {
+            return;
+        }
+
+        debug!("Enable REMB feedback");
+        self.remb_rx = Some(ReceiveSideBandwithEstimator::new());
     }
 
     pub fn visit_stats(&mut self, now: Instant, snapshot: &mut StatsSnapshot) {
@@ -770,10 +1418,90 @@ impl Session {
 
         snapshot.tx = snapshot.egress.values().map(|s| s.bytes).sum();
         snapshot.rx = snapshot.ingress.values().map(|s| s.bytes).sum();
-        snapshot.bwe_tx = self.bwe.as_ref().and_then(|bwe| bwe.last_estimate());
+        snapshot.bwe_tx = self
+            .bwe
+            .as_ref()
+            .and_then(|bwe| bwe.estimate())
+            .map(|e| e.target);
 
         snapshot.egress_loss_fraction = self.twcc_tx_register.loss(Duration::from_secs(1), now);
         snapshot.ingress_loss_fraction = self.twcc_rx_register.loss();
+
+        if let Some(srtp) = &self.srtp_rx {
+            snapshot.rtp_replayed_rx = srtp.replayed_rtp();
+            snapshot.rtcp_replayed_rx = srtp.replayed_rtcp();
+            snapshot.srtp_ssrcs_evicted = srtp.evicted_ssrcs();
+        }
+
+        if let Some(srtcp) = self.srtcp_rx.as_ref().filter(|_| !self.rtcp_mux()) {
+            snapshot.rtcp_replayed_rx = srtcp.replayed_rtcp();
+        }
+
+        let totals = self.pacer.budget().totals();
+        snapshot.media_bytes_tx = totals.media;
+        snapshot.rtx_bytes_tx = totals.rtx;
+        snapshot.padding_bytes_tx = totals.padding;
+
+        self.egress_rates.prune(now);
+
+        let total = &mut snapshot.egress_total;
+        self.egress_rates.totals().fill(total, now);
+        for (ssrc, egress) in &mut snapshot.egress_ssrc {
+            if let Some(rates) = self.egress_rates.ssrc(*ssrc) {
+                rates.fill(egress, now);
+            }
+            total.packets += egress.packets;
+            total.nacks_received += egress.nacks_received;
+            total.plis_received += egress.plis_received;
+        }
+    }
+
+    /// Fill in the streams and BWE of a [`RtcStats`] snapshot.
+    pub fn fill_stats(&mut self, now: Instant, stats: &mut RtcStats) {
+        stats.rtp_packets_unmapped = self.rtp_unmapped;
+        stats.ssrc_group_fallbacks = self.ssrc_group_fallbacks;
+
+        let media_of = |medias: &[Media], mid: Mid| {
+            medias
+                .iter()
+                .find(|m| m.mid() == mid)
+                .map(|m| (m.kind(), m.features()))
+                .unwrap_or((MediaKind::Video, MediaFeatures::all()))
+        };
+
+        stats.outbound.reserve(self.streams.streams_tx().count());
+        for stream in self.streams.streams_tx() {
+            let (kind, features) = media_of(&self.medias, stream.mid());
+            let mut s = stream.outbound_stats(kind);
+            if let Some(rates) = self.egress_rates.ssrc(s.ssrc) {
+                s.bitrate = rates.media.rate(now);
+            }
+            if !features.rtx {
+                s.retransmitted_bytes_sent = None;
+                s.retransmitted_packets_sent = None;
+            }
+            stats.outbound.push(s);
+        }
+
+        stats.inbound.reserve(self.streams.streams_rx().count());
+        for stream in self.streams.streams_rx() {
+            let (kind, features) = media_of(&self.medias, stream.mid());
+            let mut s = stream.inbound_stats(kind);
+            if !features.nack {
+                s.nack_count = None;
+            }
+            stats.inbound.push(s);
+        }
+
+        stats.bwe = self
+            .bwe
+            .as_ref()
+            .and_then(|bwe| bwe.estimate())
+            .map(|e| BweStats {
+                available_outgoing_bitrate: e.target,
+                acked_bitrate: e.acked,
+                loss_limited: e.loss_limited,
+            });
     }
 
     pub fn set_bwe_current_bitrate(&mut self, current_bitrate: Bitrate) {
@@ -790,14 +1518,37 @@ impl Session {
         }
     }
 
-    pub fn reset_bwe(&mut self, init_bitrate: Bitrate) {
+    pub fn set_bwe_bitrate_bounds(&mut self, mid: Mid, min: Bitrate, max: Bitrate) {
         if let Some(bwe) = self.bwe.as_mut() {
-            bwe.reset(init_bitrate);
+            bwe.bounds.insert(mid, (min, max));
+            self.configure_pacer();
         }
     }
 
-    pub fn line_count(&self) -> usize {
-        self.medias.len() + if self.app.is_some() { 1 } else { 0 }
+    pub fn bwe_estimate(&self) -> Option<BweEstimate> {
+        self.bwe.as_ref().and_then(|bwe| bwe.estimate())
+    }
+
+    pub fn set_bwe_config(&mut self, config: BweConfig) {
+        if let Some(bwe) = self.bwe.as_mut() {
+            bwe.set_config(config);
+            self.configure_pacer();
+        }
+    }
+
+    /// The network path changed, everything learned about the old one is void.
+    pub fn handle_route_change(&mut self, now: Instant) {
+        self.reset_bwe(BweResetReason::RouteChange);
+        self.twcc_tx_register.mark_in_flight_route_lost();
+        self.pacer.handle_route_change(now);
+    }
+
+    pub fn reset_bwe(&mut self, reason: BweResetReason) {
+        if let Some(bwe) = self.bwe.as_mut() {
+            debug!("Reset BWE: {:?}", reason);
+            bwe.reset(reason);
+            self.configure_pacer();
+        }
     }
 
     pub fn add_media(&mut self, media: Media) {
@@ -808,9 +1559,87 @@ impl Session {
         &self.medias
     }
 
+    /// Start closing the session.
+    ///
+    /// Pending feedback goes first, then reports for all streams with SDES, and BYE for
+    /// our SSRCs last. After that no more RTP is sent.
+    pub fn close(&mut self, now: Instant, reason: Option<&str>) {
+        if self.closing {
+            return;
+        }
+        self.closing = true;
+
+        let sender_ssrc = self.streams.first_ssrc_local();
+        self.streams
+            .create_final_reports(now, sender_ssrc, &mut self.feedback_tx);
+
+        let ssrcs = self.streams.ssrcs_for_bye(None);
+        self.enqueue_bye(ssrcs, reason);
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    /// Whether close() is done sending the BYE.
+    pub fn is_closed(&self) -> bool {
+        self.closing && self.feedback_tx.is_empty() && self.feedback_tx_raw.is_empty()
+    }
+
+    fn enqueue_bye(&mut self, ssrcs: Vec<Ssrc>, reason: Option<&str>) {
+        // The reason is at most 255 bytes.
+        let reason = reason.map(|r| {
+            let mut end = r.len().min(255);
+            while !r.is_char_boundary(end) {
+                end -= 1;
+            }
+            r[..end].to_string()
+        });
+
+        for reports in ReportList::lists_from_iter(ssrcs) {
+            self.feedback_tx.push_back(Rtcp::Goodbye(Goodbye {
+                reports,
+                reason: reason.clone(),
+            }));
+        }
+    }
+
     pub fn remove_media(&mut self, mid: Mid) {
         self.medias.retain(|media| media.mid() != mid);
+        self.end_media(mid);
+    }
+
+    /// Disable media removed by an SDP negotiation.
+    ///
+    /// Unlike [`remove_media()`][Self::remove_media], the media stays for its m-line.
+    pub fn disable_media(&mut self, mid: Mid) {
+        let Some(media) = self.media_by_mid_mut(mid) else {
+            return;
+        };
+        if media.is_disabled() {
+            return;
+        }
+
+        info!("Disable media: {}", mid);
+        media.set_disabled();
+        self.end_media(mid);
+    }
+
+    /// BYE for our SSRCs of the media, and remove all its streams.
+    fn end_media(&mut self, mid: Mid) {
+        // The remote can tear down its receive state straight away.
+        let ssrcs = self.streams.ssrcs_for_bye(Some(mid));
+        if !ssrcs.is_empty() {
+            self.enqueue_bye(ssrcs, None);
+        }
+
         self.streams.remove_streams_by_mid(mid);
+
+        if let Some(bwe) = self.bwe.as_mut() {
+            if bwe.bounds.remove(&mid).is_some() {
+                self.configure_pacer();
+            }
+        }
     }
 
     fn configure_pacer(&mut self) {
@@ -819,8 +1648,8 @@ impl Session {
         };
 
         let padding_rate = bwe
-            .last_estimate()
-            .map(|estimate| estimate.min(bwe.desired_bitrate))
+            .estimate()
+            .map(|estimate| estimate.target.min(bwe.desired_bitrate()))
             .unwrap_or(Bitrate::ZERO);
 
         self.pacer.set_padding_rate(padding_rate);
@@ -831,7 +1660,7 @@ impl Session {
         // pacing rate of 275KBit/s which means we'll only ever pad about 25Kbit/s. If the estimate
         // is actually 600Kbit/s we need to use that for the pacing rate to ensure we send as much as
         // we think the link capacity can sustain, if not the estimate is a lie.
-        let pacing_rate = (bwe.current_bitrate * PACING_FACTOR).max(padding_rate);
+        let pacing_rate = (bwe.current_bitrate() * PACING_FACTOR).max(padding_rate);
         self.pacer.set_pacing_rate(pacing_rate);
     }
 
@@ -845,7 +1674,13 @@ impl Session {
 
     fn do_payload(&mut self, now: Instant) -> Result<(), RtcError> {
         for m in &mut self.medias {
-            m.do_payload(now, &mut self.streams, &self.codec_config)?;
+            m.do_payload(
+                now,
+                &mut self.streams,
+                &self.codec_registry,
+                &self.codec_config,
+                self.h264_length_prefixed,
+            )?;
         }
 
         Ok(())
@@ -887,19 +1722,87 @@ impl Session {
 
 struct Bwe {
     bwe: SendSideBandwithEstimator,
+    config: BweConfig,
     desired_bitrate: Bitrate,
     current_bitrate: Bitrate,
-
-    last_emitted_estimate: Bitrate,
+    /// Min and max bitrate per media line.
+    bounds: HashMap<Mid, (Bitrate, Bitrate)>,
+    /// Estimate from loss, when lower than the delay based one.
+    loss_estimate: Option<Bitrate>,
+    now: Option<Instant>,
+
+    /// Reset not yet reflected in an emitted estimate.
+    reset: Option<BweResetReason>,
+    last_emitted: Option<(BweEstimate, Instant)>,
 }
 
 impl Bwe {
     fn handle_timeout(&mut self, now: Instant) {
         self.bwe.handle_timeout(now);
+        self.now = Some(now);
     }
 
-    pub fn reset(&mut self, init_bitrate: Bitrate) {
-        self.bwe = SendSideBandwithEstimator::new(init_bitrate);
+    fn set_config(&mut self, config: BweConfig) {
+        self.bwe
+            .set_bounds(config.min_bitrate(), config.max_bitrate());
+        self.config = config;
+    }
+
+    fn reset(&mut self, reason: BweResetReason) {
+        let start_bitrate = match reason {
+            BweResetReason::RouteChange => self.config.start_bitrate(),
+            BweResetReason::Application(bitrate) => bitrate,
+        };
+
+        self.bwe.reset(&self.config, start_bitrate);
+        self.loss_estimate = None;
+        self.reset = Some(reason);
+    }
+
+    fn desired_bitrate(&self) -> Bitrate {
+        let max: f64 = self.bounds.values().map(|(_, max)| max.as_f64()).sum();
+        self.desired_bitrate.max(Bitrate::from(max))
+    }
+
+    fn current_bitrate(&self) -> Bitrate {
+        let min: f64 = self.bounds.values().map(|(min, _)| min.as_f64()).sum();
+        self.current_bitrate.max(Bitrate::from(min))
+    }
+
+    /// Loss based control, applied on top of the delay based estimate.
+    fn update_loss(&mut self, loss: Option<f32>) {
+        let (Some(delay_estimate), Some(loss)) = (self.bwe.last_estimate(), loss) else {
+            return;
+        };
+
+        let current = self.loss_estimate.unwrap_or(delay_estimate);
+
+        let next = if loss > LOSS_HIGH {
+            current * (1.0 - 0.5 * loss as f64)
+        } else if loss < LOSS_LOW {
+            current * 1.05
+        } else {
+            current
+        };
+
+        // Only limiting while below the delay based estimate.
+        self.loss_estimate = (next < delay_estimate).then_some(next);
+    }
+
+    fn estimate(&self) -> Option<BweEstimate> {
+        let delay_estimate = self.bwe.last_estimate()?;
+
+        let (target, loss_limited) = match self.loss_estimate {
+            Some(loss_estimate) if loss_estimate < delay_estimate => (loss_estimate, true),
+            _ => (delay_estimate, false),
+        };
+
+        Some(BweEstimate {
+            target: target.clamp(self.config.min_bitrate(), self.config.max_bitrate()),
+            acked: self.bwe.acked_bitrate(),
+            loss_limited,
+            reset: self.reset,
+        })
     }
 
     pub fn update<'t>(
@@ -908,36 +1811,46 @@ impl Bwe {
         now: Instant,
     ) {
         self.bwe.update(records, now);
+        self.now = Some(now);
     }
 
-    fn poll_estimate(&mut self) -> Option<Bitrate> {
-        let estimate = self.bwe.last_estimate()?;
+    fn poll_estimate(&mut self) -> Option<BweEstimate> {
+        let estimate = self.estimate()?;
 
-        let min = self.last_emitted_estimate * (1.0 - ESTIMATE_TOLERANCE);
-        let max = self.last_emitted_estimate * (1.0 + ESTIMATE_TOLERANCE);
+        let now = self.now?;
 
-        if estimate < min || estimate > max {
-            self.last_emitted_estimate = estimate;
-            Some(estimate)
-        } else {
-            // Estimate is within tolerances.
-            None
+        if let (Some((last, at)), None) = (self.last_emitted, self.reset) {
+            let min = last.target * (1.0 - ESTIMATE_TOLERANCE);
+            let max = last.target * (1.0 + ESTIMATE_TOLERANCE);
+
+            let changed = estimate.target < min
+                || estimate.target > max
+                || estimate.loss_limited != last.loss_limited;
+
+            if !changed && now < at + ESTIMATE_MAX_INTERVAL {
+                // Estimate is within tolerances.
+                return None;
+            }
         }
+
+        self.last_emitted = Some((estimate, now));
+        self.reset = None;
+
+        Some(estimate)
     }
 
     fn poll_timeout(&self) -> Instant {
         self.bwe.poll_timeout()
     }
-
-    fn last_estimate(&self) -> Option<Bitrate> {
-        self.bwe.last_estimate()
-    }
 }
 
 pub struct PacketReceipt {
     pub header: RtpHeader,
     pub seq_no: SeqNo,
+    pub twcc_seq: SeqNo,
     pub is_padding: bool,
+    /// Resend in response to NACK. Resends used as padding are padding.
+    pub is_rtx: bool,
     pub payload_size: usize,
 }
 
@@ -2,10 +2,17 @@
 
 use std::{
     collections::{HashMap, VecDeque},
+    fmt,
+    net::SocketAddr,
     time::{Duration, Instant},
 };
 
-use crate::rtp_::{Mid, Rid};
+use serde::{Serialize, Serializer};
+
+use crate::ice_::{CandidateKind, IceConnectionState};
+use crate::io::Protocol;
+use crate::media::MediaKind;
+use crate::rtp_::{Mid, Rid, SenderInfo, Ssrc};
 use crate::Bitrate;
 
 pub(crate) struct Stats {
@@ -24,6 +31,15 @@ pub(crate) struct StatsSnapshot {
     pub ingress: HashMap<(Mid, Option<Rid>), MediaIngressStats>,
     pub egress: HashMap<(Mid, Option<Rid>), MediaEgressStats>,
     pub bwe_tx: Option<Bitrate>,
+    pub rtp_replayed_rx: u64,
+    pub rtcp_replayed_rx: u64,
+    pub srtp_ssrcs_evicted: u64,
+    pub media_bytes_tx: u64,
+    pub rtx_bytes_tx: u64,
+    pub padding_bytes_tx: u64,
+    pub egress_total: EgressStats,
+    pub egress_ssrc: HashMap<Ssrc, EgressStats>,
+    pub ingress_ssrc: HashMap<Ssrc, IngressStats>,
     timestamp: Instant,
 }
 
@@ -39,6 +55,15 @@ impl StatsSnapshot {
             ingress: HashMap::new(),
             egress: HashMap::new(),
             bwe_tx: None,
+            rtp_replayed_rx: 0,
+            rtcp_replayed_rx: 0,
+            srtp_ssrcs_evicted: 0,
+            media_bytes_tx: 0,
+            rtx_bytes_tx: 0,
+            padding_bytes_tx: 0,
+            egress_total: EgressStats::default(),
+            egress_ssrc: HashMap::new(),
+            ingress_ssrc: HashMap::new(),
             timestamp,
         }
     }
@@ -51,6 +76,7 @@ pub(crate) enum StatsEvent {
     Peer(PeerStats),
     MediaEgress(MediaEgressStats),
     MediaIngress(MediaIngressStats),
+    Ingress(IngressStats),
 }
 
 /// Peer statistics in [`Event::PeerStats`][crate::Event::PeerStats].
@@ -74,6 +100,76 @@ pub struct PeerStats {
     pub egress_loss_fraction: Option<f32>,
     /// The ingress loss since the last stats event.
     pub ingress_loss_fraction: Option<f32>,
+    /// Total incoming SRTP packets dropped by replay protection.
+    pub rtp_replayed_rx: u64,
+    /// Total incoming SRTCP packets dropped by replay protection.
+    pub rtcp_replayed_rx: u64,
+    /// Total remote SSRCs whose SRTP state was dropped to stay within
+    /// [`RtcConfig::srtp_max_ssrcs()`][crate::RtcConfig::srtp_max_ssrcs].
+    pub srtp_ssrcs_evicted: u64,
+    /// Total RTP payload bytes sent as media, including FEC.
+    pub media_bytes_tx: u64,
+    /// Total RTP payload bytes resent in response to NACK.
+    pub rtx_bytes_tx: u64,
+    /// Total RTP payload bytes sent as padding, blank or resent history.
+    pub padding_bytes_tx: u64,
+    /// Send rates and counters for all outgoing streams together.
+    pub egress: EgressStats,
+    /// Send rates and counters per SSRC of the outgoing streams.
+    ///
+    /// RTX and padding are counted on the SSRC of the main stream.
+    pub egress_ssrc: HashMap<Ssrc, EgressStats>,
+}
+
+/// Send rates in [`PeerStats`].
+///
+/// The plain rates are over a sliding window of the last second. The `_ewma` variants
+/// are exponentially smoothed with a time constant of about one second. Rates count
+/// RTP payload bytes, and for RTCP the whole encrypted packet.
+#[derive(Debug, Clone)]
+pub struct EgressStats {
+    /// Media rate, including FEC.
+    pub bitrate: Bitrate,
+    /// Rate of resends in response to NACK.
+    pub rtx_bitrate: Bitrate,
+    /// Rate of padding, blank or resent history.
+    pub padding_bitrate: Bitrate,
+    /// Rate of RTCP.
+    ///
+    /// Compound RTCP covers many SSRCs, so this is only set in the totals.
+    pub rtcp_bitrate: Bitrate,
+    /// Smoothed [`EgressStats::bitrate`].
+    pub bitrate_ewma: Bitrate,
+    /// Smoothed [`EgressStats::rtx_bitrate`].
+    pub rtx_bitrate_ewma: Bitrate,
+    /// Smoothed [`EgressStats::padding_bitrate`].
+    pub padding_bitrate_ewma: Bitrate,
+    /// Smoothed [`EgressStats::rtcp_bitrate`].
+    pub rtcp_bitrate_ewma: Bitrate,
+    /// Total number of RTP packets sent, including retransmissions.
+    pub packets: u64,
+    /// Number of NACKs received.
+    pub nacks_received: u64,
+    /// Number of PLIs received.
+    pub plis_received: u64,
+}
+
+impl Default for EgressStats {
+    fn default() -> Self {
+        EgressStats {
+            bitrate: Bitrate::ZERO,
+            rtx_bitrate: Bitrate::ZERO,
+            padding_bitrate: Bitrate::ZERO,
+            rtcp_bitrate: Bitrate::ZERO,
+            bitrate_ewma: Bitrate::ZERO,
+            rtx_bitrate_ewma: Bitrate::ZERO,
+            padding_bitrate_ewma: Bitrate::ZERO,
+            rtcp_bitrate_ewma: Bitrate::ZERO,
+            packets: 0,
+            nacks_received: 0,
+            plis_received: 0,
+        }
+    }
 }
 
 /// Outgoing media statistics in [`Event::MediaEgressStats`][crate::Event::MediaEgressStats].
@@ -184,6 +280,44 @@ impl MediaIngressStats {
     }
 }
 
+/// Incoming statistics per SSRC in [`Event::IngressStats`][crate::Event::IngressStats].
+///
+/// Loss and jitter are the values sent in the last RTCP receiver report for the SSRC, so
+/// they match what the remote sees. They are `None` until the first receiver report.
+#[derive(Debug, Clone)]
+pub struct IngressStats {
+    /// The SSRC of the incoming stream.
+    pub ssrc: Ssrc,
+    /// The identifier of the media the stream belongs to.
+    pub mid: Mid,
+    /// The Rid identifier in case of simulcast.
+    pub rid: Option<Rid>,
+    /// Total bytes received, including retransmissions.
+    pub bytes: u64,
+    /// Total number of rtp packets received, including retransmissions.
+    pub packets: u64,
+    /// Interarrival jitter (ms).
+    pub jitter: Option<f32>,
+    /// Fraction of packets lost in the interval before the last receiver report.
+    pub fraction_lost: Option<f32>,
+    /// Cumulative number of packets lost. Negative if duplicates outnumber losses.
+    pub packets_lost: Option<i64>,
+    /// The last sender report received for the stream.
+    ///
+    /// Aligns the RTP time of the stream with the sender's NTP clock.
+    pub last_sender_info: Option<SenderInfo>,
+    /// When the last sender report was received.
+    pub last_sender_info_at: Option<Instant>,
+    /// Number of nacks sent.
+    pub nacks_sent: u64,
+    /// Number of plis sent.
+    pub plis_sent: u64,
+    /// Number of firs sent.
+    pub firs_sent: u64,
+    /// Timestamp when this event was generated.
+    pub timestamp: Instant,
+}
+
 /// Stats as reported by the remote side (via RTCP SenderReports).
 #[derive(Debug, Clone)]
 pub struct RemoteEgressStats {
@@ -232,6 +366,14 @@ impl Stats {
             bwe_tx: snapshot.bwe_tx,
             egress_loss_fraction: snapshot.egress_loss_fraction,
             ingress_loss_fraction: snapshot.ingress_loss_fraction,
+            rtp_replayed_rx: snapshot.rtp_replayed_rx,
+            rtcp_replayed_rx: snapshot.rtcp_replayed_rx,
+            srtp_ssrcs_evicted: snapshot.srtp_ssrcs_evicted,
+            media_bytes_tx: snapshot.media_bytes_tx,
+            rtx_bytes_tx: snapshot.rtx_bytes_tx,
+            padding_bytes_tx: snapshot.padding_bytes_tx,
+            egress: snapshot.egress_total.clone(),
+            egress_ssrc: snapshot.egress_ssrc.drain().collect(),
         };
 
         self.events.push_back(StatsEvent::Peer(event));
@@ -244,6 +386,10 @@ impl Stats {
             self.events.push_back(StatsEvent::MediaEgress(event));
         }
 
+        for (_, event) in snapshot.ingress_ssrc.drain() {
+            self.events.push_back(StatsEvent::Ingress(event));
+        }
+
         self.last_now = Some(snapshot.timestamp);
     }
 
@@ -260,3 +406,292 @@ impl Stats {
         self.events.pop_front()
     }
 }
+
+/// Schedule of the [`Event::RtcStats`][crate::Event::RtcStats] snapshots.
+///
+/// Besides the interval, a snapshot is due right away when streams are added or removed,
+/// or the candidate pair changes.
+pub(crate) struct RtcStatsSchedule {
+    interval: Duration,
+    /// Time of the next periodic snapshot. Set on the first timeout.
+    next: Option<Instant>,
+    /// Outgoing and incoming streams at the last check.
+    streams: (usize, usize),
+    due: bool,
+}
+
+impl RtcStatsSchedule {
+    pub fn new(interval: Duration) -> Self {
+        RtcStatsSchedule {
+            interval,
+            next: None,
+            streams: (0, 0),
+            due: false,
+        }
+    }
+
+    pub fn handle_timeout(&mut self, now: Instant) {
+        let Some(next) = self.next else {
+            // Learn our first ever `now`
+            self.next = Some(now + self.interval);
+            return;
+        };
+
+        if now >= next {
+            self.next = Some(now + self.interval);
+            self.due = true;
+        }
+    }
+
+    /// Make a snapshot due at the next poll, outside the interval.
+    pub fn trigger(&mut self) {
+        self.due = true;
+    }
+
+    /// Trigger a snapshot if the number of streams changed since last check.
+    pub fn check_streams(&mut self, streams: (usize, usize)) {
+        if streams != self.streams {
+            self.streams = streams;
+            self.due = true;
+        }
+    }
+
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        self.next
+    }
+
+    /// Whether a snapshot is due. Resets the flag.
+    pub fn poll_due(&mut self) -> bool {
+        std::mem::take(&mut self.due)
+    }
+}
+
+/// Snapshot of all statistics, as returned by [`Rtc::stats()`][crate::Rtc::stats].
+///
+/// Everything is taken at the same instant, [`RtcStats::timestamp`]. The structs follow the
+/// [WebRTC statistics][1] dictionaries, with the names in snake case and the same units, so
+/// a dashboard built on `getStats()` can show them with little mapping.
+///
+/// The snapshot serializes with serde. [`Instant`] has no meaning outside the process and is
+/// left out.
+///
+/// [1]: https://www.w3.org/TR/webrtc-stats/
+#[derive(Debug, Clone, Serialize)]
+pub struct RtcStats {
+    /// When the snapshot was taken.
+    #[serde(skip)]
+    pub timestamp: Instant,
+    /// Total bytes sent on the transport, all traffic.
+    ///
+    /// Spec equivalent to `RTCTransportStats.bytesSent`.
+    pub bytes_sent: u64,
+    /// Total bytes received on the transport, all traffic.
+    ///
+    /// Spec equivalent to `RTCTransportStats.bytesReceived`.
+    pub bytes_received: u64,
+    /// The candidate pair used for sending, if one is nominated.
+    pub candidate_pair: Option<CandidatePairStats>,
+    /// All candidate pairs the ICE agent checks, including the nominated one.
+    pub candidate_pairs: Vec<CandidatePairStats>,
+    /// State of the bandwidth estimation, if enabled and estimating.
+    pub bwe: Option<BweStats>,
+    /// One entry per outgoing stream.
+    pub outbound: Vec<OutboundRtpStats>,
+    /// One entry per incoming stream.
+    pub inbound: Vec<InboundRtpStats>,
+    /// Incoming RTP packets dropped since they didn't map to any stream.
+    ///
+    /// For simulcast without signalled SSRCs, this counts packets arriving before the
+    /// rid is seen for their SSRC.
+    pub rtp_packets_unmapped: u64,
+    /// Incoming SSRCs that are in no `a=ssrc-group:SIM`, for media using such groups
+    /// for simulcast. These are received as single streams without rid.
+    pub ssrc_group_fallbacks: u64,
+}
+
+/// A candidate pair in [`RtcStats`].
+///
+/// Spec equivalent to `RTCIceCandidatePairStats` with its local and remote `RTCIceCandidateStats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CandidatePairStats {
+    /// The ICE connection state.
+    pub state: IceConnectionState,
+    /// State of the connectivity checks for the pair.
+    ///
+    /// Spec equivalent to `RTCIceCandidatePairStats.state`.
+    pub check_state: CandidatePairState,
+    /// Whether the pair is nominated.
+    pub nominated: bool,
+    /// Whether the pair is used for sending.
+    pub selected: bool,
+    /// The pair priority.
+    pub priority: u64,
+    /// Transport protocol, `udp`, `tcp` etc.
+    #[serde(serialize_with = "display")]
+    pub protocol: Protocol,
+    /// Local address packets are sent from.
+    pub local_address: SocketAddr,
+    /// Type of the local candidate, `host`, `srflx` etc.
+    #[serde(serialize_with = "display")]
+    pub local_candidate_type: CandidateKind,
+    /// Remote address packets are sent to.
+    pub remote_address: SocketAddr,
+    /// Type of the remote candidate.
+    #[serde(serialize_with = "display")]
+    pub remote_candidate_type: CandidateKind,
+    /// Number of connectivity checks and consent checks sent.
+    pub requests_sent: u64,
+    /// Number of connectivity checks received.
+    pub requests_received: u64,
+    /// Number of responses to our checks received.
+    pub responses_received: u64,
+    /// Round trip time in seconds of the last check that got a response.
+    pub current_round_trip_time: Option<f64>,
+    /// Smoothed round trip time in seconds.
+    pub round_trip_time_ewma: Option<f64>,
+    /// Bytes sent on the pair while selected, not counting STUN.
+    pub bytes_sent: u64,
+    /// Bytes received on the pair while selected, not counting STUN.
+    pub bytes_received: u64,
+    /// When the pair was first selected after a nomination.
+    #[serde(skip)]
+    pub nominated_at: Option<Instant>,
+}
+
+/// State of the checks in [`CandidatePairStats`].
+///
+/// Failed pairs are removed, and frozen is not used since all media is bundled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CandidatePairState {
+    /// No check has been sent.
+    Waiting,
+    /// A check has been sent, but there's no response yet.
+    InProgress,
+    /// A check got a response.
+    Succeeded,
+}
+
+/// Bandwidth estimation in [`RtcStats`].
+#[derive(Debug, Clone, Serialize)]
+pub struct BweStats {
+    /// The estimated bitrate available for sending.
+    ///
+    /// Spec equivalent to `RTCIceCandidatePairStats.availableOutgoingBitrate`.
+    pub available_outgoing_bitrate: Bitrate,
+    /// The bitrate the remote has acknowledged receiving, if known.
+    pub acked_bitrate: Option<Bitrate>,
+    /// Whether the estimate is held down by packet loss rather than by delay.
+    pub loss_limited: bool,
+}
+
+/// An outgoing stream in [`RtcStats`].
+///
+/// Spec equivalent to `RTCOutboundRtpStreamStats`, with the loss and round trip time of
+/// `RTCRemoteInboundRtpStreamStats` as reported by the remote in receiver reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboundRtpStats {
+    /// SSRC of the stream.
+    pub ssrc: Ssrc,
+    /// SSRC of the RTX stream used for resends, if any.
+    pub rtx_ssrc: Option<Ssrc>,
+    /// `audio` or `video`.
+    #[serde(serialize_with = "display")]
+    pub kind: MediaKind,
+    /// The media the stream belongs to.
+    #[serde(serialize_with = "display")]
+    pub mid: Mid,
+    /// The Rid identifier in case of simulcast.
+    #[serde(serialize_with = "display_opt")]
+    pub rid: Option<Rid>,
+    /// Total bytes sent, including retransmissions.
+    pub bytes_sent: u64,
+    /// Total packets sent, including retransmissions.
+    pub packets_sent: u64,
+    /// Bytes sent as retransmissions. None if RTX is off for the media.
+    pub retransmitted_bytes_sent: Option<u64>,
+    /// Packets sent as retransmissions. None if RTX is off for the media.
+    pub retransmitted_packets_sent: Option<u64>,
+    /// Media send rate over the last second.
+    ///
+    /// This is what is actually sent. The encoder target is set by the application, see
+    /// [`BweStats::available_outgoing_bitrate`] for what the network allows.
+    pub bitrate: Bitrate,
+    /// Number of NACKs received.
+    pub nack_count: u64,
+    /// Number of PLIs received.
+    pub pli_count: u64,
+    /// Number of FIRs received.
+    pub fir_count: u64,
+    /// Fraction lost in the last receiver report from the remote.
+    pub fraction_lost: Option<f32>,
+    /// Cumulative packets lost in the last receiver report from the remote.
+    pub packets_lost: Option<i64>,
+    /// Round trip time in seconds, from the last receiver report.
+    pub round_trip_time: Option<f64>,
+}
+
+/// An incoming stream in [`RtcStats`].
+///
+/// Spec equivalent to `RTCInboundRtpStreamStats`, with the last sender report as in
+/// `RTCRemoteOutboundRtpStreamStats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct InboundRtpStats {
+    /// SSRC of the stream.
+    pub ssrc: Ssrc,
+    /// `audio` or `video`.
+    #[serde(serialize_with = "display")]
+    pub kind: MediaKind,
+    /// The media the stream belongs to.
+    #[serde(serialize_with = "display")]
+    pub mid: Mid,
+    /// The Rid identifier in case of simulcast.
+    #[serde(serialize_with = "display_opt")]
+    pub rid: Option<Rid>,
+    /// Total bytes received, including retransmissions.
+    pub bytes_received: u64,
+    /// Total packets received, including retransmissions.
+    pub packets_received: u64,
+    /// Interarrival jitter in seconds, as sent in the last receiver report.
+    pub jitter: Option<f64>,
+    /// Fraction lost, as sent in the last receiver report.
+    pub fraction_lost: Option<f32>,
+    /// Cumulative packets lost, as sent in the last receiver report.
+    pub packets_lost: Option<i64>,
+    /// Number of NACKs sent. None if NACK is off for the media.
+    pub nack_count: Option<u64>,
+    /// Number of PLIs sent.
+    pub pli_count: u64,
+    /// Number of FIRs sent.
+    pub fir_count: u64,
+    /// The last sender report received for the stream.
+    pub last_sender_report: Option<SenderReportStats>,
+}
+
+/// Sender report in [`InboundRtpStats`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SenderReportStats {
+    /// When the sender report was received.
+    #[serde(skip)]
+    pub received_at: Instant,
+    /// The sender's NTP time of the report.
+    #[serde(skip)]
+    pub remote_timestamp: Instant,
+    /// The RTP time corresponding to `remote_timestamp`.
+    pub rtp_time: u64,
+    /// Packets sent by the remote, as reported. Wraps at 32 bits.
+    pub packets_sent: u32,
+    /// Bytes sent by the remote, as reported. Wraps at 32 bits.
+    pub bytes_sent: u32,
+}
+
+fn display<T: fmt::Display, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(v)
+}
+
+fn display_opt<T: fmt::Display, S: Serializer>(v: &Option<T>, s: S) -> Result<S::Ok, S::Error> {
+    match v {
+        Some(v) => s.collect_str(v),
+        None => s.serialize_none(),
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::rtp_::Ssrc;
+
+/// Size of the fixed RTP header.
+pub(crate) const RTP_HEADER_SIZE: usize = 12;
+
+/// Size of the ULPFEC header.
+const ULPFEC_HEADER_SIZE: usize = 10;
+
+/// Number of received media packets kept to recover with.
+const MAX_MEDIA_PACKETS: usize = 256;
+
+/// Number of FEC packets kept while waiting for more media packets.
+const MAX_FEC_PACKETS: usize = 32;
+
+/// Receive side of XOR based forward error correction, ULPFEC as described in
+/// [RFC 5109](https://datatracker.ietf.org/doc/html/rfc5109) and FlexFEC-03.
+///
+/// For ULPFEC only the level 0 protection is used, which is all libWebRTC sends. Media
+/// packets are given as complete RTP packets (for ULPFEC unwrapped from RED), and
+/// recovered packets are complete RTP packets.
+#[derive(Debug)]
+pub(crate) struct FecReceiver {
+    ssrc: Ssrc,
+
+    /// Received (or recovered) media packets, header and payload, by sequence number.
+    media: HashMap<u16, Vec<u8>>,
+
+    /// Order of the media packets to evict the oldest.
+    media_order: VecDeque<u16>,
+
+    /// FEC packets that can't recover anything yet.
+    fec: VecDeque<FecPacket>,
+}
+
+/// A parsed FEC packet, ULPFEC or FlexFEC.
+#[derive(Debug)]
+pub(crate) struct FecPacket {
+    /// The recovery bit string. The first two octets of the RTP header, the timestamp
+    /// and the length of the packet after the fixed header.
+    pub bits: [u8; 8],
+
+    /// Sequence number of the first protected packet.
+    pub seq_no_base: u16,
+
+    /// Protected packets, bit N (msb first) is seq_no_base + N.
+    pub mask: u128,
+
+    /// Number of bits in the mask.
+    pub mask_len: u16,
+
+    /// XOR of the protected packets after the fixed RTP header.
+    pub payload: Vec<u8>,
+}
+
+impl FecPacket {
+    pub fn parse_ulpfec(buf: &[u8]) -> Option<FecPacket> {
+        //  0                   1                   2                   3
+        //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        // |E|L|P|X|  CC   |M| PT recovery |            SN base            |
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        // |                          TS recovery                          |
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        // |        length recovery        |
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        //
+        // Followed by the level 0 header, protection length and a 16 or 48 bit mask.
+        if buf.len() < ULPFEC_HEADER_SIZE {
+            return None;
+        }
+
+        // E is reserved for a future extension of the header.
+        if buf[0] & 0x80 > 0 {
+            return None;
+        }
+
+        let long_mask = buf[0] & 0x40 > 0;
+        let mask_size = if long_mask { 6 } else { 2 };
+        let level_header_size = 2 + mask_size;
+
+        let rest = &buf[ULPFEC_HEADER_SIZE..];
+        if rest.len() < level_header_size {
+            return None;
+        }
+
+        let protection_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+
+        let mut mask = 0_u128;
+        for b in &rest[2..level_header_size] {
+            mask = mask << 8 | *b as u128;
+        }
+
+        // The level 0 payload must have the protected length.
+        let payload = &rest[level_header_size..];
+        if payload.len() < protection_len {
+            return None;
+        }
+
+        let mut bits = [0; 8];
+        bits[..2].copy_from_slice(&buf[..2]);
+        bits[2..6].copy_from_slice(&buf[4..8]);
+        bits[6..].copy_from_slice(&buf[8..10]);
+
+        Some(FecPacket {
+            bits,
+            seq_no_base: u16::from_be_bytes([buf[2], buf[3]]),
+            mask,
+            mask_len: mask_size as u16 * 8,
+            payload: payload[..protection_len].to_vec(),
+        })
+    }
+
+    pub fn protected(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..self.mask_len)
+            .filter(|i| self.mask & (1 << (self.mask_len - 1 - i)) > 0)
+            .map(|i| self.seq_no_base.wrapping_add(i))
+    }
+}
+
+impl FecReceiver {
+    pub fn new(ssrc: Ssrc) -> Self {
+        FecReceiver {
+            ssrc,
+            media: HashMap::new(),
+            media_order: VecDeque::new(),
+            fec: VecDeque::new(),
+        }
+    }
+
+    /// Add a received media packet, RTP header and payload.
+    ///
+    /// Returns the packets this made recoverable.
+    pub fn add_media(&mut self, packet: Vec<u8>) -> Vec<Vec<u8>> {
+        if packet.len() < RTP_HEADER_SIZE {
+            return vec![];
+        }
+        self.insert_media(packet);
+        self.recover()
+    }
+
+    /// Add a received ULPFEC packet, the payload unwrapped from RED.
+    ///
+    /// Returns the recovered packets.
+    pub fn add_ulpfec(&mut self, payload: &[u8]) -> Vec<Vec<u8>> {
+        let Some(fec) = FecPacket::parse_ulpfec(payload) else {
+            debug!("Failed to parse ULPFEC packet");
+            return vec![];
+        };
+
+        self.add_fec(fec)
+    }
+
+    /// Add a received FEC packet.
+    ///
+    /// Returns the recovered packets.
+    pub fn add_fec(&mut self, fec: FecPacket) -> Vec<Vec<u8>> {
+        if self.fec.len() == MAX_FEC_PACKETS {
+            self.fec.pop_front();
+        }
+        self.fec.push_back(fec);
+
+        self.recover()
+    }
+
+    fn insert_media(&mut self, packet: Vec<u8>) {
+        let seq_no = u16::from_be_bytes([packet[2], packet[3]]);
+
+        if self.media.insert(seq_no, packet).is_some() {
+            return;
+        }
+
+        self.media_order.push_back(seq_no);
+        if self.media_order.len() > MAX_MEDIA_PACKETS {
+            if let Some(old) = self.media_order.pop_front() {
+                self.media.remove(&old);
+            }
+        }
+    }
+
+    fn recover(&mut self) -> Vec<Vec<u8>> {
+        let mut recovered = vec![];
+
+        // Each recovered packet might make another FEC packet usable.
+        'outer: loop {
+            let mut i = 0;
+
+            while i < self.fec.len() {
+                let (first, more) = {
+                    let mut missing = self.fec[i]
+                        .protected()
+                        .filter(|s| !self.media.contains_key(s));
+                    (missing.next(), missing.next().is_some())
+                };
+
+                let Some(seq_no) = first else {
+                    // Everything is received, nothing to do with this FEC.
+                    self.fec.remove(i);
+                    continue;
+                };
+
+                if more {
+                    // More than one missing, wait for more packets.
+                    i += 1;
+                    continue;
+                }
+
+                let fec = self.fec.remove(i).expect("fec at index");
+
+                match self.recover_packet(&fec, seq_no) {
+                    Some(packet) => {
+                        trace!("FEC recovered packet: {}", seq_no);
+                        self.insert_media(packet.clone());
+                        recovered.push(packet);
+                        continue 'outer;
+                    }
+                    None => {
+                        debug!("FEC failed to recover packet: {}", seq_no);
+                    }
+                }
+            }
+
+            break;
+        }
+
+        recovered
+    }
+
+    fn recover_packet(&self, fec: &FecPacket, seq_no: u16) -> Option<Vec<u8>> {
+        let mut bits = fec.bits;
+
+        let protection_len = fec.payload.len();
+        let mut payload = fec.payload.clone();
+
+        for s in fec.protected().filter(|s| *s != seq_no) {
+            let packet = self.media.get(&s)?;
+            let after_header = &packet[RTP_HEADER_SIZE..];
+
+            // Only the first protection length bytes are protected, a longer packet
+            // means this FEC can't be used.
+            if after_header.len() > protection_len {
+                return None;
+            }
+
+            let len = after_header.len() as u16;
+
+            bits[0] ^= packet[0];
+            bits[1] ^= packet[1];
+            for (b, p) in bits[2..6].iter_mut().zip(&packet[4..8]) {
+                *b ^= p;
+            }
+            for (b, p) in bits[6..].iter_mut().zip(len.to_be_bytes()) {
+                *b ^= p;
+            }
+
+            for (b, p) in payload.iter_mut().zip(after_header) {
+                *b ^= p;
+            }
+        }
+
+        let len = u16::from_be_bytes([bits[6], bits[7]]) as usize;
+        if len > protection_len {
+            return None;
+        }
+
+        let mut packet = Vec::with_capacity(RTP_HEADER_SIZE + len);
+
+        // Version 2 and the recovered P, X and CC.
+        packet.push(0x80 | (bits[0] & 0x3f));
+        packet.push(bits[1]);
+        packet.extend_from_slice(&seq_no.to_be_bytes());
+        packet.extend_from_slice(&bits[2..6]);
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(&payload[..len]);
+
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SSRC: u32 = 0x1234_5678;
+
+    // Three VP8 media packets, seq 1000-1002, and the ULPFEC packet protecting them
+    // with a 16 bit mask in the layout libWebRTC sends. The FEC is the payload after
+    // the RED header.
+    const MEDIA: [&[u8]; 3] = [
+        &[
+            0x80, 0x60, 0x03, 0xe8, 0x00, 0x01, 0x5f, 0x90, 0x12, 0x34, 0x56, 0x78, 0x90, 0xe0,
+            0x80, 0x01, 0x00, 0x10, 0x9d, 0x01, 0x2a,
+        ],
+        &[
+            0x80, 0x60, 0x03, 0xe9, 0x00, 0x01, 0x5f, 0x90, 0x12, 0x34, 0x56, 0x78, 0x80, 0xe0,
+            0x80, 0x01, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x11, 0x22,
+        ],
+        &[
+            0x80, 0xe0, 0x03, 0xea, 0x00, 0x01, 0x5f, 0x90, 0x12, 0x34, 0x56, 0x78, 0x80, 0xe0,
+            0x80, 0x01, 0x42,
+        ],
+    ];
+
+    const FEC: &[u8] = &[
+        // FEC header.
+        0x00, 0xe0, 0x03, 0xe8, 0x00, 0x01, 0x5f, 0x90, 0x00, 0x00,
+        // Level 0 header, protection length 12 and mask for 1000-1002.
+        0x00, 0x0c, 0xe0, 0x00, // Level 0 payload.
+        0x90, 0xe0, 0x80, 0x01, 0xe8, 0xab, 0x51, 0xdc, 0xc4, 0xff, 0x11, 0x22,
+    ];
+
+    #[test]
+    fn recover_each_dropped_packet() {
+        for (drop, dropped) in MEDIA.iter().enumerate() {
+            let mut rx = FecReceiver::new(SSRC.into());
+
+            for (i, m) in MEDIA.iter().enumerate() {
+                if i != drop {
+                    assert!(rx.add_media(m.to_vec()).is_empty());
+                }
+            }
+
+            let recovered = rx.add_ulpfec(FEC);
+            assert_eq!(recovered, [dropped.to_vec()], "drop {}", drop);
+        }
+    }
+
+    #[test]
+    fn recover_when_fec_arrives_first() {
+        let mut rx = FecReceiver::new(SSRC.into());
+
+        assert!(rx.add_media(MEDIA[0].to_vec()).is_empty());
+        assert!(rx.add_ulpfec(FEC).is_empty());
+
+        // 1001 is lost, 1002 makes it recoverable.
+        let recovered = rx.add_media(MEDIA[2].to_vec());
+        assert_eq!(recovered, [MEDIA[1].to_vec()]);
+    }
+
+    #[test]
+    fn give_up_on_two_missing() {
+        let mut rx = FecReceiver::new(SSRC.into());
+
+        assert!(rx.add_media(MEDIA[0].to_vec()).is_empty());
+        assert!(rx.add_ulpfec(FEC).is_empty());
+    }
+
+    #[test]
+    fn nothing_missing() {
+        let mut rx = FecReceiver::new(SSRC.into());
+
+        for m in MEDIA {
+            rx.add_media(m.to_vec());
+        }
+        assert!(rx.add_ulpfec(FEC).is_empty());
+        assert!(rx.fec.is_empty());
+    }
+
+    #[test]
+    fn reject_length_beyond_protection() {
+        let mut fec = FEC.to_vec();
+        // Protection length 4 can't recover anything.
+        fec[11] = 0x04;
+
+        let mut rx = FecReceiver::new(SSRC.into());
+        rx.add_media(MEDIA[0].to_vec());
+        rx.add_media(MEDIA[2].to_vec());
+        assert!(rx.add_ulpfec(&fec).is_empty());
+    }
+
+    #[test]
+    fn reject_malformed() {
+        assert!(FecPacket::parse_ulpfec(&FEC[..9]).is_none());
+        assert!(FecPacket::parse_ulpfec(&FEC[..13]).is_none());
+        // Payload shorter than protection length.
+        assert!(FecPacket::parse_ulpfec(&FEC[..20]).is_none());
+
+        let mut fec = FEC.to_vec();
+        fec[0] |= 0x80;
+        assert!(FecPacket::parse_ulpfec(&fec).is_none());
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::rtp_::{Pt, SeqNo, Ssrc};
+use crate::util::NonCryptographicRng;
+
+use super::fec::{FecPacket, RTP_HEADER_SIZE};
+
+/// Size of the FlexFEC header up until the first part of the mask.
+const FLEXFEC_HEADER_SIZE: usize = 18;
+
+/// Most media packets protected in one block.
+const MAX_BLOCK_SIZE: usize = 48;
+
+/// Most packets the mask can describe, 15 + 31 + 63 bits.
+const MAX_MASK_LEN: u16 = 109;
+
+/// Protection rate for a fraction of lost packets.
+const LOSS_FACTOR: f32 = 2.0;
+
+/// Configuration of FlexFEC for a [`StreamTx`][crate::rtp::StreamTx].
+///
+/// FEC packets are sent on a separate SSRC, associated with the media SSRC in the SDP with
+/// `a=ssrc-group:FEC-FR`. Each FEC packet protects a group of media packets, which can be
+/// recovered when one of the group is lost.
+///
+/// The protection rate, FEC packets per media packet, follows the loss reported by the
+/// remote peer, or given using
+/// [`StreamTx::set_flexfec_loss`][crate::rtp::StreamTx::set_flexfec_loss].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlexfecConfig {
+    ssrc: Ssrc,
+    pt: Pt,
+    interleaved: bool,
+    min_rate: f32,
+    max_rate: f32,
+}
+
+impl FlexfecConfig {
+    /// Create a config for FEC packets with `ssrc` and payload type `pt`, which is the
+    /// `flexfec-03` in the SDP.
+    pub fn new(ssrc: Ssrc, pt: Pt) -> Self {
+        FlexfecConfig {
+            ssrc,
+            pt,
+            interleaved: false,
+            min_rate: 0.1,
+            max_rate: 0.5,
+        }
+    }
+
+    /// Protect every k-th media packet with the same FEC packet instead of consecutive
+    /// packets. This handles burst loss better.
+    ///
+    /// Defaults to false.
+    pub fn set_interleaved(mut self, interleaved: bool) -> Self {
+        self.interleaved = interleaved;
+
+        self
+    }
+
+    /// Range of the protection rate, FEC packets per media packet. The rate is within this
+    /// range depending on the loss.
+    ///
+    /// Defaults to 0.1 to 0.5. Use the same value for both to have a fixed rate.
+    pub fn set_rate(mut self, min: f32, max: f32) -> Self {
+        self.min_rate = min.clamp(0.0, 1.0);
+        self.max_rate = max.clamp(self.min_rate, 1.0);
+
+        self
+    }
+
+    /// SSRC of the FEC packets.
+    pub fn ssrc(&self) -> Ssrc {
+        self.ssrc
+    }
+
+    /// Payload type of the FEC packets.
+    pub fn pt(&self) -> Pt {
+        self.pt
+    }
+
+    fn rate_for_loss(&self, loss: f32) -> f32 {
+        (loss * LOSS_FACTOR).clamp(self.min_rate, self.max_rate)
+    }
+}
+
+/// Generates FlexFEC packets for media packets sent on a [`StreamTx`][super::StreamTx].
+#[derive(Debug)]
+pub(crate) struct FlexfecSender {
+    config: FlexfecConfig,
+
+    /// Current protection rate.
+    rate: f32,
+
+    /// Next sequence number of the FEC stream.
+    seq_no: SeqNo,
+
+    /// Media packets of the block being collected.
+    block: Vec<Vec<u8>>,
+
+    /// FEC packets to send.
+    queue: VecDeque<FecToSend>,
+}
+
+#[derive(Debug)]
+pub(crate) struct FecToSend {
+    pub queued_at: Instant,
+    pub time: u32,
+    pub payload: Vec<u8>,
+}
+
+impl FlexfecSender {
+    pub fn new(config: FlexfecConfig) -> Self {
+        FlexfecSender {
+            config,
+            rate: config.min_rate,
+            seq_no: (NonCryptographicRng::u16() as u64).into(),
+            block: vec![],
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> FlexfecConfig {
+        self.config
+    }
+
+    pub fn set_loss(&mut self, loss: f32) {
+        self.rate = self.config.rate_for_loss(loss);
+    }
+
+    /// Add a sent media packet, RTP header and payload (unencrypted).
+    ///
+    /// The FEC packets are generated at the end of a frame, or when the block is full.
+    pub fn add_media(&mut self, now: Instant, packet: &[u8]) {
+        if packet.len() < RTP_HEADER_SIZE {
+            return;
+        }
+
+        if let Some(first) = self.block.first() {
+            // The mask can only describe packets that follow the first.
+            let offset = seq_no(packet).wrapping_sub(seq_no(first));
+            if offset == 0 || offset >= MAX_MASK_LEN {
+                self.generate(now);
+            }
+        }
+
+        self.block.push(packet.to_vec());
+
+        let marker = packet[1] & 0x80 > 0;
+        if marker || self.block.len() == MAX_BLOCK_SIZE {
+            self.generate(now);
+        }
+    }
+
+    pub fn queue(&self) -> &VecDeque<FecToSend> {
+        &self.queue
+    }
+
+    pub fn poll(&mut self) -> Option<(SeqNo, FecToSend)> {
+        let fec = self.queue.pop_front()?;
+        Some((self.seq_no.inc(), fec))
+    }
+
+    pub fn clear(&mut self) {
+        self.block.clear();
+        self.queue.clear();
+    }
+
+    fn generate(&mut self, now: Instant) {
+        let block = std::mem::take(&mut self.block);
+        let n = block.len();
+        let k = fec_count(n, self.rate);
+
+        let Some(last) = block.last() else {
+            return;
+        };
+        let time = u32::from_be_bytes([last[4], last[5], last[6], last[7]]);
+
+        for j in 0..k {
+            let group: Vec<&[u8]> = block
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    if self.config.interleaved {
+                        i % k == j
+                    } else {
+                        i * k / n == j
+                    }
+                })
+                .map(|(_, p)| p.as_slice())
+                .collect();
+
+            let payload = write_flexfec(&group, seq_no(&block[0]));
+
+            self.queue.push_back(FecToSend {
+                queued_at: now,
+                time,
+                payload,
+            });
+        }
+    }
+}
+
+/// Number of FEC packets to protect n media packets.
+fn fec_count(n: usize, rate: f32) -> usize {
+    let k = (n as f32 * rate).round() as usize;
+
+    // Any protection means at least one FEC packet.
+    let k = if rate > 0.0 { k.max(1) } else { k };
+
+    k.min(n)
+}
+
+fn seq_no(packet: &[u8]) -> u16 {
+    u16::from_be_bytes([packet[2], packet[3]])
+}
+
+/// Write the FlexFEC-03 payload protecting the packets.
+fn write_flexfec(packets: &[&[u8]], seq_no_base: u16) -> Vec<u8> {
+    //  0                   1                   2                   3
+    //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |R|F|P|X|  CC   |M| PT recovery |        length recovery        |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                          TS recovery                          |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |   SSRCCount   |                    reserved                   |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |                             SSRC_i                            |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |           SN base_i           |k|          Mask [0-14]        |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |k|                   Mask [15-45] (optional)                   |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    // |k|                                                             |
+    // +-+                   Mask [46-108] (optional)                  |
+    // |                                                               |
+    // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    let protection_len = packets
+        .iter()
+        .map(|p| p.len() - RTP_HEADER_SIZE)
+        .max()
+        .unwrap_or(0);
+
+    let mut out = vec![0; FLEXFEC_HEADER_SIZE];
+    let mut payload = vec![0; protection_len];
+    let mut mask = 0_u128;
+    let mut max_offset = 0;
+
+    for p in packets {
+        out[0] ^= p[0];
+        out[1] ^= p[1];
+        let len = (p.len() - RTP_HEADER_SIZE) as u16;
+        for (b, v) in out[2..4].iter_mut().zip(len.to_be_bytes()) {
+            *b ^= v;
+        }
+        for (b, v) in out[4..8].iter_mut().zip(&p[4..8]) {
+            *b ^= v;
+        }
+        for (b, v) in payload.iter_mut().zip(&p[RTP_HEADER_SIZE..]) {
+            *b ^= v;
+        }
+
+        let offset = seq_no(p).wrapping_sub(seq_no_base);
+        mask |= 1 << (MAX_MASK_LEN - 1 - offset);
+        max_offset = max_offset.max(offset);
+    }
+
+    // R and F are 0, the rest is the recovered P, X and CC.
+    out[0] &= 0x3f;
+    out[8] = 1;
+    out[12..16].copy_from_slice(&packets[0][8..12]);
+    out[16..18].copy_from_slice(&seq_no_base.to_be_bytes());
+
+    // The mask is in up to three parts, each with the k bit set on the last part.
+    let part1 = (mask >> 94) as u16 & 0x7fff;
+    let part2 = (mask >> 63) as u32 & 0x7fff_ffff;
+    let part3 = mask as u64 & 0x7fff_ffff_ffff_ffff;
+
+    if max_offset < 15 {
+        out.extend_from_slice(&(0x8000 | part1).to_be_bytes());
+    } else if max_offset < 46 {
+        out.extend_from_slice(&part1.to_be_bytes());
+        out.extend_from_slice(&(0x8000_0000 | part2).to_be_bytes());
+    } else {
+        out.extend_from_slice(&part1.to_be_bytes());
+        out.extend_from_slice(&part2.to_be_bytes());
+        out.extend_from_slice(&(0x8000_0000_0000_0000 | part3).to_be_bytes());
+    }
+
+    out.extend_from_slice(&payload);
+
+    out
+}
+
+/// Parse a FlexFEC-03 payload protecting the media SSRC.
+pub(crate) fn parse_flexfec(buf: &[u8], ssrc: Ssrc) -> Option<FecPacket> {
+    if buf.len() < FLEXFEC_HEADER_SIZE + 2 {
+        return None;
+    }
+
+    // R is for retransmissions and F for fixed masks, neither are used by libWebRTC.
+    if buf[0] & 0xc0 > 0 {
+        return None;
+    }
+
+    // Protecting several SSRCs is not supported.
+    if buf[8] != 1 || buf[12..16] != ssrc.to_be_bytes() {
+        return None;
+    }
+
+    let mut rest = &buf[FLEXFEC_HEADER_SIZE..];
+
+    let v = u16::from_be_bytes([rest[0], rest[1]]);
+    rest = &rest[2..];
+    let mut mask = (v & 0x7fff) as u128;
+    let mut mask_len = 15;
+
+    if v & 0x8000 == 0 {
+        if rest.len() < 4 {
+            return None;
+        }
+        let v = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        rest = &rest[4..];
+        mask = mask << 31 | (v & 0x7fff_ffff) as u128;
+        mask_len = 46;
+
+        if v & 0x8000_0000 == 0 {
+            if rest.len() < 8 {
+                return None;
+            }
+            let mut b = [0; 8];
+            b.copy_from_slice(&rest[..8]);
+            let v = u64::from_be_bytes(b);
+            rest = &rest[8..];
+
+            // The last part must have the k bit.
+            if v & 0x8000_0000_0000_0000 == 0 {
+                return None;
+            }
+            mask = mask << 63 | (v & 0x7fff_ffff_ffff_ffff) as u128;
+            mask_len = MAX_MASK_LEN;
+        }
+    }
+
+    let mut bits = [0; 8];
+    bits[..2].copy_from_slice(&buf[..2]);
+    bits[2..6].copy_from_slice(&buf[4..8]);
+    bits[6..].copy_from_slice(&buf[2..4]);
+
+    Some(FecPacket {
+        bits,
+        seq_no_base: u16::from_be_bytes([buf[16], buf[17]]),
+        mask,
+        mask_len,
+        payload: rest.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::super::fec::FecReceiver;
+    use super::*;
+
+    const SSRC: u32 = 0xdead_beef;
+
+    /// Deterministic pseudo random numbers for the tests.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            // xorshift64
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn chance(&mut self, p: f32) -> bool {
+            (self.next() % 10_000) < (p * 10_000.0) as u64
+        }
+    }
+
+    fn media_packets(rng: &mut Rng, count: usize) -> Vec<Vec<u8>> {
+        let mut seq_no = 65_000_u16;
+        let mut time = 4_000_000_000_u32;
+
+        (0..count)
+            .map(|i| {
+                // Frames of 1 to 12 packets.
+                let marker = i == count - 1 || rng.next() % 6 == 0;
+
+                // Some packets with padding and header extensions.
+                let padding = rng.next() % 8 == 0;
+                let extension = rng.next() % 2 == 0;
+
+                let mut p = vec![0x80, 96];
+                if padding {
+                    p[0] |= 0x20;
+                }
+                if extension {
+                    p[0] |= 0x10;
+                }
+                if marker {
+                    p[1] |= 0x80;
+                }
+                p.extend_from_slice(&seq_no.to_be_bytes());
+                p.extend_from_slice(&time.to_be_bytes());
+                p.extend_from_slice(&SSRC.to_be_bytes());
+
+                if extension {
+                    p.extend_from_slice(&[0xbe, 0xde, 0x00, 0x01, 0x10, 0xff, 0x00, 0x00]);
+                }
+
+                let len = 1 + rng.next() as usize % 1100;
+                p.extend((0..len).map(|_| rng.next() as u8));
+
+                if padding {
+                    p.extend_from_slice(&[0, 0, 0, 4]);
+                }
+
+                seq_no = seq_no.wrapping_add(1);
+                if marker {
+                    time = time.wrapping_add(3000);
+                }
+
+                p
+            })
+            .collect()
+    }
+
+    /// The FEC packets and the index of the media packet after which they are sent.
+    fn send(config: FlexfecConfig, loss: f32, media: &[Vec<u8>]) -> Vec<(usize, Vec<u8>)> {
+        let now = Instant::now();
+        let mut sender = FlexfecSender::new(config);
+        sender.set_loss(loss);
+
+        let mut fec = vec![];
+        for (i, m) in media.iter().enumerate() {
+            sender.add_media(now, m);
+            while let Some((_, f)) = sender.poll() {
+                fec.push((i, f.payload));
+            }
+        }
+        fec
+    }
+
+    fn run(config: FlexfecConfig, loss: f32, seed: u64) -> (usize, usize) {
+        let mut rng = Rng(seed);
+        let media = media_packets(&mut rng, 2000);
+        let fec = send(config, loss, &media);
+
+        let mut rx = FecReceiver::new(SSRC.into());
+        let mut received = HashSet::new();
+        let mut recovered = vec![];
+        let mut fec_iter = fec.iter().peekable();
+
+        for (i, m) in media.iter().enumerate() {
+            if !rng.chance(loss) {
+                received.insert(i);
+                recovered.extend(rx.add_media(m.clone()));
+            }
+
+            while let Some((_, f)) = fec_iter.next_if(|(after, _)| *after == i) {
+                if !rng.chance(loss) {
+                    let f = parse_flexfec(f, SSRC.into()).unwrap();
+                    recovered.extend(rx.add_fec(f));
+                }
+            }
+        }
+
+        for r in &recovered {
+            let i = seq_no(r).wrapping_sub(65_000) as usize;
+            assert!(!received.contains(&i), "recovered a received packet");
+            assert_eq!(r, &media[i], "recovered packet {} not identical", i);
+        }
+
+        (media.len() - received.len(), recovered.len())
+    }
+
+    #[test]
+    fn recover_random_loss() {
+        for (loss, seed) in [(0.05, 1), (0.1, 2), (0.15, 3), (0.2, 4)] {
+            for interleaved in [false, true] {
+                let config = FlexfecConfig::new(1.into(), 118.into()).set_interleaved(interleaved);
+
+                let (lost, recovered) = run(config, loss, seed);
+                assert!(lost > 0);
+                assert!(
+                    recovered > 0,
+                    "nothing recovered at {} loss, interleaved {}",
+                    loss,
+                    interleaved
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn full_protection_recovers_single_loss() {
+        let mut rng = Rng(42);
+        let media = media_packets(&mut rng, 100);
+
+        // One FEC packet per media packet group of one is a copy, recovering anything.
+        let config = FlexfecConfig::new(1.into(), 118.into()).set_rate(1.0, 1.0);
+        let fec = send(config, 0.0, &media);
+        assert_eq!(fec.len(), media.len());
+
+        let mut rx = FecReceiver::new(SSRC.into());
+        for (_, f) in &fec {
+            let recovered = rx.add_fec(parse_flexfec(f, SSRC.into()).unwrap());
+            assert_eq!(recovered.len(), 1);
+        }
+    }
+
+    #[test]
+    fn mask_sizes() {
+        let mut rng = Rng(7);
+        let media = media_packets(&mut rng, 109);
+
+        for count in [1, 15, 16, 46, 47, 109] {
+            let group: Vec<&[u8]> = media[..count].iter().map(|p| p.as_slice()).collect();
+            let payload = write_flexfec(&group, seq_no(&media[0]));
+
+            let fec = parse_flexfec(&payload, SSRC.into()).unwrap();
+            let protected: Vec<u16> = fec.protected().collect();
+            let expected: Vec<u16> = media[..count].iter().map(|p| seq_no(p)).collect();
+            assert_eq!(protected, expected);
+        }
+    }
+
+    #[test]
+    fn fec_count_follows_rate() {
+        assert_eq!(fec_count(10, 0.0), 0);
+        assert_eq!(fec_count(10, 0.01), 1);
+        assert_eq!(fec_count(10, 0.25), 3);
+        assert_eq!(fec_count(10, 1.0), 10);
+        assert_eq!(fec_count(1, 0.5), 1);
+    }
+
+    #[test]
+    fn rate_follows_loss() {
+        let config = FlexfecConfig::new(1.into(), 118.into());
+        let mut sender = FlexfecSender::new(config);
+        assert_eq!(sender.rate, 0.1);
+
+        sender.set_loss(0.1);
+        assert_eq!(sender.rate, 0.2);
+
+        sender.set_loss(0.9);
+        assert_eq!(sender.rate, 0.5);
+    }
+
+    #[test]
+    fn reject_unsupported() {
+        let mut rng = Rng(9);
+        let media = media_packets(&mut rng, 2);
+        let group: Vec<&[u8]> = media.iter().map(|p| p.as_slice()).collect();
+        let payload = write_flexfec(&group, seq_no(&media[0]));
+
+        // Other SSRC.
+        assert!(parse_flexfec(&payload, 1.into()).is_none());
+
+        // Retransmission bit.
+        let mut p = payload.clone();
+        p[0] |= 0x80;
+        assert!(parse_flexfec(&p, SSRC.into()).is_none());
+
+        // Missing mask.
+        assert!(parse_flexfec(&payload[..19], SSRC.into()).is_none());
+    }
+}
@@ -5,20 +5,26 @@ use std::time::Instant;
 
 use crate::format::CodecConfig;
 use crate::format::PayloadParams;
-use crate::media::{KeyframeRequest, Media};
+use crate::media::{FeedbackCaps, KeyframeRequest, Media};
 use crate::rtp_::Ssrc;
 use crate::rtp_::{Bitrate, Pt};
 use crate::rtp_::{MediaTime, SenderInfo};
 use crate::rtp_::{Mid, Rid, SeqNo};
 use crate::rtp_::{Rtcp, RtpHeader};
-use crate::util::{already_happened, NonCryptographicRng};
+use crate::util::already_happened;
 
+pub use self::flexfec::FlexfecConfig;
+pub(crate) use self::receive::RegisterUpdateReceipt;
 pub use self::receive::StreamRx;
+pub use self::rtx::{rtx_unwrap, rtx_wrap};
 pub use self::send::StreamTx;
 
+mod fec;
+mod flexfec;
 mod receive;
 pub(crate) mod register;
 pub(crate) mod register_nack;
+mod rtx;
 mod rtx_cache;
 pub(crate) mod rtx_cache_buf;
 mod send;
@@ -33,8 +39,11 @@ pub(crate) use send::DEFAULT_RTX_CACHE_DURATION;
 const RR_INTERVAL_VIDEO: Duration = Duration::from_millis(1000);
 const RR_INTERVAL_AUDIO: Duration = Duration::from_millis(5000);
 
-fn rr_interval(audio: bool) -> Duration {
-    if audio {
+/// Configured interval, or the default for the kind of media.
+fn rr_interval(audio: bool, configured: Option<Duration>) -> Duration {
+    if let Some(interval) = configured {
+        interval
+    } else if audio {
         RR_INTERVAL_AUDIO
     } else {
         RR_INTERVAL_VIDEO
@@ -73,11 +82,20 @@ pub struct RtpPacket {
     /// If no Sender Report(SR) has been received or this packet is being sent by str0m this is [`None`].
     pub last_sender_info: Option<SenderInfo>,
 
+    /// Whether this packet was recovered using forward error correction (ULPFEC)
+    /// instead of being received.
+    pub recovered: bool,
+
     /// Whether this packet can be nacked.
     ///
     /// This is often false for audio, but might also be false for discardable frames when
     /// using temporal encoding as in a VP8 simulcast situation.
     pub(crate) nackable: bool,
+
+    /// For outgoing packets, the wallclock (capture time) given to `write_rtp`.
+    ///
+    /// Used as the reference for the video timing deltas.
+    pub(crate) wallclock: Option<Instant>,
 }
 
 /// Event when an encoded stream is considered paused/unpaused.
@@ -98,6 +116,91 @@ pub struct StreamPaused {
     pub paused: bool,
 }
 
+/// Event when an encoded stream restarted.
+///
+/// The sequence numbers or RTP timestamps of the stream jumped, and kept going from
+/// the new values. This happens when the remote restarts the encoder, or reuses the SSRC
+/// after a crash. The receive state (loss, jitter, NACK) has been re-initialized.
+#[derive(Debug)]
+pub struct StreamRestarted {
+    /// The main SSRC of the encoded stream that restarted.
+    pub ssrc: Ssrc,
+
+    /// The mid the encoded stream belongs to.
+    pub mid: Mid,
+
+    /// The rid, if the encoded stream has a rid.
+    pub rid: Option<Rid>,
+}
+
+/// Event when the remote ended an encoded stream with an RTCP BYE.
+///
+/// The receive state (loss, jitter, NACK) has been torn down. Should packets arrive again
+/// on the SSRC, the stream starts over, with a [`StreamPaused`] event saying it's unpaused.
+#[derive(Debug)]
+pub struct StreamEnded {
+    /// The main SSRC of the encoded stream that ended.
+    pub ssrc: Ssrc,
+
+    /// The mid the encoded stream belongs to.
+    pub mid: Mid,
+
+    /// The rid, if the encoded stream has a rid.
+    pub rid: Option<Rid>,
+
+    /// The reason given in the BYE, if any.
+    pub reason: Option<String>,
+}
+
+/// Event when an incoming Opus stream was silent using DTX (discontinuous transmission).
+///
+/// A silence starts with a DTX packet, or with a gap in RTP time before a packet with the
+/// marker bit, which the sender sets on the first packet after DTX. It ends with the first
+/// packet that isn't DTX, and this event is emitted before that packet. Packets missing
+/// during the silence were never sent, and are neither NACKed nor counted as lost.
+///
+/// Useful for segmenting the audio, such as for transcription.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamSilence {
+    /// The main SSRC of the encoded stream.
+    pub ssrc: Ssrc,
+
+    /// The mid the encoded stream belongs to.
+    pub mid: Mid,
+
+    /// The rid, if the encoded stream has a rid.
+    pub rid: Option<Rid>,
+
+    /// RTP time when the silence started.
+    pub start: MediaTime,
+
+    /// RTP time of the first packet after the silence.
+    pub end: MediaTime,
+
+    /// Number of sequence numbers skipped by the sender during the silence.
+    pub skipped: u64,
+}
+
+/// Event when an incoming simulcast layer was bound to an SSRC.
+///
+/// The SSRC is learned from the rid header extension when no SSRC was signalled. Emitted
+/// for the first SSRC of the layer, and again if the sender restarts the layer on a new
+/// SSRC. The layer is received as [`StreamRx`] looked up by mid and rid.
+///
+/// Older senders signal the layers with `a=ssrc-group:SIM` instead, and don't send rid.
+/// Such layers get the rid `0`, `1`, `2`… after their position in the group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerDiscovered {
+    /// The mid the layer belongs to.
+    pub mid: Mid,
+
+    /// The rid of the layer.
+    pub rid: Rid,
+
+    /// The main SSRC the layer is received on.
+    pub ssrc: Ssrc,
+}
+
 /// 255 is out of range for a real PT, which is 7 bit.
 const BLANK_PACKET_DEFAULT_PT: Pt = Pt::new_with_value(255);
 
@@ -113,7 +216,9 @@ impl RtpPacket {
             payload: vec![], // This payload is never used. See RtpHeader::create_padding_packet
             nackable: false,
             last_sender_info: None,
+            recovered: false,
             timestamp: already_happened(),
+            wallclock: None,
         }
     }
 }
@@ -145,6 +250,12 @@ pub(crate) struct Streams {
     /// Whether nack reports are enabled. This is an optimization to avoid too frequent
     /// Session::nack_at() when we don't need to send nacks.
     any_nack_active: Option<bool>,
+
+    /// Interval between SR/RR from the RTCP config. None uses the defaults per kind.
+    report_interval: Option<Duration>,
+
+    /// Simulcast layers bound to new SSRCs, to be emitted as events.
+    layers_discovered: VecDeque<LayerDiscovered>,
 }
 
 impl Default for Streams {
@@ -156,6 +267,8 @@ impl Default for Streams {
             default_ssrc_tx: 0.into(), // this will be changed
             mids_to_report: Vec::with_capacity(10),
             any_nack_active: None,
+            report_interval: None,
+            layers_discovered: VecDeque::new(),
         }
     }
 }
@@ -241,6 +354,8 @@ impl Streams {
     ) {
         let maybe_stream = self.stream_rx_by_mid_rid(mid, rid);
 
+        let ssrc_prev = maybe_stream.as_ref().map(|s| s.ssrc());
+
         if let Some(stream) = maybe_stream {
             let ssrc_from = stream.ssrc();
             let rtx_from = stream.rtx();
@@ -264,8 +379,17 @@ impl Streams {
             }
         }
 
-        // If we don't have an RTX PT configured, we don't want NACK.
-        let suppress_nack = payload.resend.is_none();
+        // A layer is discovered when it is new, or restarted on another SSRC.
+        if let Some(rid) = rid.filter(|_| ssrc_prev != Some(ssrc_main)) {
+            self.layers_discovered.push_back(LayerDiscovered {
+                mid,
+                rid,
+                ssrc: ssrc_main,
+            });
+        }
+
+        // If we don't have an RTX PT configured, or NACK is off, we don't want NACK.
+        let suppress_nack = payload.resend.is_none() || !media.features().nack;
 
         // If stream already exists, this might only "fill in" the RTX.
         self.expect_stream_rx(ssrc_main, rtx, mid, rid, suppress_nack, Some(reason));
@@ -297,12 +421,31 @@ impl Streams {
         stream
     }
 
+    /// Associate a FlexFEC SSRC with an existing StreamRx.
+    pub fn expect_stream_rx_fec(&mut self, ssrc: Ssrc, fec: Ssrc) -> Option<&mut StreamRx> {
+        let stream = self.streams_rx.get_mut(&ssrc)?;
+
+        stream.maybe_reset_fec(fec);
+        associate_ssrc_mid(
+            &mut self.source_keys_rx,
+            fec,
+            stream.mid(),
+            ssrc,
+            Some("FlexFEC"),
+        );
+
+        Some(stream)
+    }
+
     pub fn remove_stream_rx(&mut self, ssrc: Ssrc) -> bool {
         let stream = self.streams_rx.remove(&ssrc);
         let existed = stream.is_some();
-        if let Some(rtx) = stream.and_then(|s| s.rtx()) {
+        if let Some(rtx) = stream.as_ref().and_then(|s| s.rtx()) {
             self.source_keys_rx.remove(&rtx);
         }
+        if let Some(fec) = stream.and_then(|s| s.fec()) {
+            self.source_keys_rx.remove(&fec);
+        }
 
         self.source_keys_rx.remove(&ssrc);
 
@@ -365,13 +508,29 @@ impl Streams {
     }
 
     pub(crate) fn regular_feedback_at(&self) -> Option<Instant> {
-        let r = self.streams_rx.values().map(|s| s.receiver_report_at());
-        let s = self.streams_tx.values().map(|s| s.sender_report_at());
+        let i = self.report_interval;
+        let r = self.streams_rx.values().map(|s| s.receiver_report_at(i));
+        let s = self.streams_tx.values().map(|s| s.sender_report_at(i));
         r.chain(s).min()
     }
 
+    pub(crate) fn set_report_interval(&mut self, interval: Option<Duration>) {
+        self.report_interval = interval;
+    }
+
+    /// SSRCs in the session, how many of them send, and whether we do.
+    ///
+    /// Each side has an SSRC for its reports, even when not sending.
+    pub(crate) fn rtcp_members(&self) -> (usize, usize, bool) {
+        let senders = self.streams_tx.len() + self.streams_rx.len();
+        let members = senders
+            + usize::from(self.streams_tx.is_empty())
+            + usize::from(self.streams_rx.is_empty());
+        (members, senders, !self.streams_tx.is_empty())
+    }
+
     pub(crate) fn paused_at(&self) -> Option<Instant> {
-        self.streams_rx.values().find_map(|s| s.paused_at())
+        self.streams_rx.values().filter_map(|s| s.paused_at()).min()
     }
 
     pub(crate) fn send_stream(&self) -> Option<Instant> {
@@ -397,21 +556,28 @@ impl Streams {
     ) {
         self.mids_to_report.clear(); // Clear for checking StreamRx.
         for stream in self.streams_rx.values() {
-            if stream.need_rr(now) {
+            if stream.need_rr(now, self.report_interval) {
                 self.mids_to_report.push(stream.mid());
             }
         }
 
         for stream in self.streams_rx.values_mut() {
-            stream.maybe_create_keyframe_request(sender_ssrc, feedback);
-            stream.maybe_create_remb_request(sender_ssrc, feedback);
+            // Only send the feedback negotiated for the media.
+            let caps = medias
+                .iter()
+                .find(|m| m.mid() == stream.mid())
+                .map(|m| m.feedback_caps())
+                .unwrap_or(FeedbackCaps::all());
+
+            stream.maybe_create_keyframe_request(sender_ssrc, caps, feedback);
+            stream.maybe_create_remb_request(sender_ssrc, caps, feedback);
 
             // All StreamRx belonging to the same Mid are reported together.
             if self.mids_to_report.contains(&stream.mid()) {
                 stream.create_rr_and_update(now, sender_ssrc, feedback);
             }
 
-            if do_nack {
+            if do_nack && caps.nack {
                 stream.maybe_create_nack(sender_ssrc, feedback);
             }
 
@@ -420,7 +586,7 @@ impl Streams {
 
         self.mids_to_report.clear(); // start over for StreamTx.
         for stream in self.streams_tx.values() {
-            if stream.need_sr(now) {
+            if stream.need_sr(now, self.report_interval) {
                 self.mids_to_report.push(stream.mid());
             }
         }
@@ -459,10 +625,32 @@ impl Streams {
             .find_map(|s| s.poll_remb_request().map(|b| (s.mid(), b)))
     }
 
+    pub(crate) fn add_layer_discovered(&mut self, layer: LayerDiscovered) {
+        self.layers_discovered.push_back(layer);
+    }
+
+    pub(crate) fn poll_layer_discovered(&mut self) -> Option<LayerDiscovered> {
+        self.layers_discovered.pop_front()
+    }
+
     pub(crate) fn poll_stream_paused(&mut self) -> Option<StreamPaused> {
         self.streams_rx.values_mut().find_map(|s| s.poll_paused())
     }
 
+    pub(crate) fn poll_stream_restarted(&mut self) -> Option<StreamRestarted> {
+        self.streams_rx
+            .values_mut()
+            .find_map(|s| s.poll_restarted())
+    }
+
+    pub(crate) fn poll_stream_ended(&mut self) -> Option<StreamEnded> {
+        self.streams_rx.values_mut().find_map(|s| s.poll_ended())
+    }
+
+    pub(crate) fn poll_stream_silence(&mut self) -> Option<StreamSilence> {
+        self.streams_rx.values_mut().find_map(|s| s.poll_silence())
+    }
+
     pub(crate) fn has_stream_rx(&self, ssrc: Ssrc) -> bool {
         self.streams_rx.contains_key(&ssrc)
     }
@@ -471,6 +659,11 @@ impl Streams {
         self.streams_tx.contains_key(&ssrc)
     }
 
+    /// Number of outgoing and incoming streams.
+    pub(crate) fn stream_count(&self) -> (usize, usize) {
+        (self.streams_tx.len(), self.streams_rx.len())
+    }
+
     pub(crate) fn streams_rx(&mut self) -> impl Iterator<Item = &mut StreamRx> {
         self.streams_rx.values_mut()
     }
@@ -487,9 +680,23 @@ impl Streams {
             .collect()
     }
 
+    /// Send stream SSRCs with their FlexFEC SSRC.
+    pub(crate) fn fecs_tx(&self, mid: Mid) -> Vec<(Ssrc, Ssrc)> {
+        self.streams_tx
+            .values()
+            .filter(|s| s.mid() == mid)
+            .filter_map(|s| Some((s.ssrc(), s.flexfec()?.ssrc())))
+            .collect()
+    }
+
     pub(crate) fn new_ssrc(&self) -> Ssrc {
         loop {
-            let ssrc: Ssrc = (NonCryptographicRng::u32()).into();
+            let ssrc = Ssrc::new_random();
+
+            // 0 is used as "no SSRC".
+            if *ssrc == 0 {
+                continue;
+            }
 
             let has_ssrc = self.has_stream_rx(ssrc) || self.has_stream_tx(ssrc);
 
@@ -508,6 +715,16 @@ impl Streams {
                 continue;
             }
 
+            // And FlexFEC.
+            let has_fec_rx = self.streams_rx.values().any(|s| s.fec() == Some(ssrc));
+            let has_fec_tx = self
+                .streams_tx
+                .values()
+                .any(|s| s.flexfec().map(|f| f.ssrc()) == Some(ssrc));
+            if has_fec_rx || has_fec_tx {
+                continue;
+            }
+
             // Not used
             break ssrc;
         }
@@ -569,6 +786,38 @@ impl Streams {
             .find(|s| s.mid() == mid && (rid.is_none() || s.rid() == rid))
     }
 
+    /// Reports for all streams, for the last RTCP before BYE.
+    pub(crate) fn create_final_reports(
+        &mut self,
+        now: Instant,
+        sender_ssrc: Ssrc,
+        feedback: &mut VecDeque<Rtcp>,
+    ) {
+        for stream in self.streams_rx.values_mut() {
+            stream.create_rr_and_update(now, sender_ssrc, feedback);
+        }
+        for stream in self.streams_tx.values_mut() {
+            stream.create_sr_and_update(now, feedback);
+        }
+    }
+
+    /// Our SSRCs for a BYE, RTX included. The SSRC used for reports if we don't send.
+    pub(crate) fn ssrcs_for_bye(&mut self, mid: Option<Mid>) -> Vec<Ssrc> {
+        let ssrcs: Vec<Ssrc> = self
+            .streams_tx
+            .values()
+            .filter(|s| mid.map_or(true, |m| s.mid() == m))
+            .flat_map(|s| [Some(s.ssrc()), s.rtx()])
+            .flatten()
+            .collect();
+
+        if ssrcs.is_empty() && mid.is_none() {
+            return vec![self.first_ssrc_local()];
+        }
+
+        ssrcs
+    }
+
     pub(crate) fn remove_streams_by_mid(&mut self, mid: Mid) {
         self.streams_tx.retain(|_, s| s.mid() != mid);
         self.streams_rx.retain(|_, s| s.mid() != mid);
@@ -719,6 +968,7 @@ impl fmt::Debug for RtpPacket {
             .field("header", &self.header)
             .field("payload", &self.payload.len())
             .field("nackable", &self.nackable)
+            .field("recovered", &self.recovered)
             .field("timestamp", &self.timestamp)
             .finish()
     }
@@ -1,20 +1,28 @@
 use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
-use crate::media::KeyframeRequestKind;
+use crate::format::{OpusMeta, Vp9Meta, Vp9ScalabilityStructure};
+use crate::media::{FeedbackCaps, KeyframeRequestKind, MediaKind};
 use crate::rtp_::{
     extend_u32, Bitrate, DlrrItem, ExtendedReport, Fir, FirEntry, Frequency, MediaTime, Remb,
 };
-use crate::rtp_::{Mid, Pli, Pt, ReceiverReport};
+use crate::rtp_::{DependencyDescriptorReader, SdesType, Ssrc};
+use crate::rtp_::{Mid, Pli, Pt, ReceiverReport, ReceptionReport};
 use crate::rtp_::{ReportBlock, ReportList, Rid, Rrtr, Rtcp, RtcpFb, RtpHeader, SenderInfo, SeqNo};
-use crate::rtp_::{SdesType, Ssrc};
-use crate::stats::{MediaIngressStats, StatsSnapshot};
+use crate::stats::StatsSnapshot;
+use crate::stats::{InboundRtpStats, IngressStats, MediaIngressStats, SenderReportStats};
 use crate::util::InstantExt;
-use crate::util::{already_happened, calculate_rtt_ms};
+use crate::util::{already_happened, calculate_rtt_ms, not_happening};
 
+use super::fec::FecReceiver;
+use super::flexfec::parse_flexfec;
 use super::register::ReceiverRegister;
-use super::StreamPaused;
+use super::rtx::un_rtx_header;
 use super::{rr_interval, RtpPacket};
+use super::{StreamEnded, StreamPaused, StreamRestarted, StreamSilence};
+
+/// How long an ended stream keeps its receive state, in case packets arrive again.
+const FREE_ENDED_AFTER: Duration = Duration::from_secs(10);
 
 /// Incoming encoded stream.
 ///
@@ -106,8 +114,47 @@ pub struct StreamRx {
     /// Whether we need to emit a paused event for the current paused state.
     need_paused_event: bool,
 
+    /// Whether we need to emit a restarted event.
+    need_restarted_event: bool,
+
+    /// The remote sent BYE, and no packets have arrived since.
+    ended: bool,
+
+    /// Reason of the BYE, if we need to emit an ended event.
+    need_ended_event: Option<Option<String>>,
+
+    /// When to free the receive state of an ended stream, unless packets arrive again.
+    free_ended_at: Option<Instant>,
+
+    /// Whether the depacketizing of the stream must be reset after a restart.
+    need_depack_reset: bool,
+
     /// The configured threshold before considering the lack of packets as going into paused.
     pause_threshold: Duration,
+
+    /// Template structure for interpreting AV1 dependency descriptors.
+    dependency_descriptor: DependencyDescriptorReader,
+
+    /// Last VP9 scalability structure received on this stream.
+    vp9_structure: Option<Vp9ScalabilityStructure>,
+
+    /// Recovery of lost packets using ULPFEC, created on the first RED packet.
+    ulpfec: Option<FecReceiver>,
+
+    /// Identifier of a FlexFEC stream protecting this stream, from `a=ssrc-group:FEC-FR`.
+    fec: Option<Ssrc>,
+
+    /// Sequence number, end time and whether it was DTX, of the last Opus packet.
+    last_opus: Option<(SeqNo, MediaTime, bool)>,
+
+    /// Start time and skipped sequence numbers of an ongoing DTX silence.
+    silence: Option<(MediaTime, u64)>,
+
+    /// Ended silences to emit events for.
+    silences: VecDeque<StreamSilence>,
+
+    /// Recovery of lost packets using FlexFEC.
+    flexfec: Option<FecReceiver>,
 }
 
 /// Holder of stats.
@@ -127,6 +174,8 @@ pub(crate) struct StreamRxStats {
     rtt: Option<f32>,
     /// fraction of packets lost from the last RR, if any
     loss: Option<f32>,
+    /// the last reception report sent in an RR, if any
+    report: Option<ReceptionReport>,
 }
 
 impl StreamRx {
@@ -156,7 +205,20 @@ impl StreamRx {
             check_paused_at: None,
             paused: true,
             need_paused_event: false,
+            need_restarted_event: false,
+            ended: false,
+            need_ended_event: None,
+            free_ended_at: None,
+            need_depack_reset: false,
             pause_threshold: Duration::from_millis(1500),
+            dependency_descriptor: DependencyDescriptorReader::default(),
+            vp9_structure: None,
+            ulpfec: None,
+            fec: None,
+            flexfec: None,
+            last_opus: None,
+            silence: None,
+            silences: VecDeque::new(),
         }
     }
 
@@ -170,6 +232,11 @@ impl StreamRx {
         self.rtx
     }
 
+    /// The FlexFEC SSRC protecting this encoded stream.
+    pub fn fec(&self) -> Option<Ssrc> {
+        self.fec
+    }
+
     /// Mid for this stream.
     ///
     /// In SDP this corresponds to m-line and "Media".
@@ -221,9 +288,20 @@ impl StreamRx {
         self.suppress_nack = suppress;
     }
 
-    pub(crate) fn receiver_report_at(&self) -> Instant {
+    /// The VP9 scalability structure (SS) last received on this stream.
+    ///
+    /// The SS describes the spatial and temporal layers of the stream. It is sent by the
+    /// remote peer with keyframes, and is `None` until the first one arrives.
+    pub fn vp9_scalability_structure(&self) -> Option<&Vp9ScalabilityStructure> {
+        self.vp9_structure.as_ref()
+    }
+
+    pub(crate) fn receiver_report_at(&self, interval: Option<Duration>) -> Instant {
+        if self.ended {
+            return not_happening();
+        }
         let is_audio = self.rtx.is_none(); // this is maybe not correct, but it's all we got.
-        self.last_receiver_report + rr_interval(is_audio)
+        self.last_receiver_report + rr_interval(is_audio, interval)
     }
 
     pub(crate) fn handle_rtcp(&mut self, now: Instant, fb: RtcpFb) {
@@ -253,15 +331,47 @@ impl StreamRx {
             DlrrItem(v) => {
                 self.set_dlrr_item(now, v);
             }
-            Goodbye(_v) => {
-                // We get Goodbye at weird times, like SDP renegotiation, which makes
-                // pausing on the BYE not a good idea. Chrome also reuses the SSRC it
-                // just sent BYE on. Very not helpful.
+            Goodbye(_, reason) => {
+                self.end(now, reason);
             }
             _ => {}
         }
     }
 
+    /// The remote sent BYE for this stream.
+    ///
+    /// We get Goodbye at weird times, like SDP renegotiation, and Chrome reuses the SSRC
+    /// it just sent BYE on. The stream is only marked as ended, and carries on as before
+    /// should packets arrive again. The receive state is freed if they don't.
+    fn end(&mut self, now: Instant, reason: Option<String>) {
+        if self.ended {
+            return;
+        }
+
+        // No paused event, the ended event says more.
+        self.check_paused_at = None;
+        self.paused = true;
+        self.need_paused_event = false;
+
+        self.ended = true;
+        self.need_ended_event = Some(reason);
+        self.free_ended_at = Some(now + FREE_ENDED_AFTER);
+    }
+
+    /// Tear down the receive state of an ended stream. It starts over should packets
+    /// arrive again.
+    fn free_ended(&mut self) {
+        self.free_ended_at = None;
+
+        self.register = None;
+        self.register_rtx = None;
+        self.sender_info = None;
+        self.pending_request_keyframe = None;
+        self.ulpfec = None;
+        self.flexfec = None;
+        self.need_depack_reset = true;
+    }
+
     fn set_sender_info(&mut self, now: Instant, mut info: SenderInfo) {
         // Extend the incoming time given our knowledge of last time.
         let extended = {
@@ -290,10 +400,15 @@ impl StreamRx {
     }
 
     pub(crate) fn paused_at(&self) -> Option<Instant> {
-        self.check_paused_at
+        // An ended stream has no paused check, only the freeing of its state.
+        self.check_paused_at.or(self.free_ended_at)
     }
 
     pub(crate) fn handle_timeout(&mut self, now: Instant) {
+        if self.free_ended_at.map(|t| now >= t).unwrap_or(false) {
+            self.free_ended();
+        }
+
         // No scheduled paused check?
         if self.check_paused_at.is_none() {
             return;
@@ -320,6 +435,8 @@ impl StreamRx {
         is_repair: bool,
     ) -> RegisterUpdateReceipt {
         self.last_used = now;
+        self.ended = false;
+        self.free_ended_at = None;
 
         if self.paused {
             self.paused = false;
@@ -355,6 +472,14 @@ impl StreamRx {
 
         let is_new_packet = register.update(seq_no, now, header.timestamp, clock_rate.get());
 
+        // A restart of the RTX register is not interesting outside the register itself.
+        if register.take_restarted() && !is_repair {
+            self.need_restarted_event = true;
+            self.need_depack_reset = true;
+            self.ulpfec = None;
+            self.flexfec = None;
+        }
+
         let previous_time = self.last_time.map(|t| t.numer());
         let time_u32 = extend_u32(previous_time, header.timestamp);
         let time = MediaTime::new(time_u32, clock_rate);
@@ -374,7 +499,7 @@ impl StreamRx {
     pub(crate) fn handle_rtp(
         &mut self,
         now: Instant,
-        header: RtpHeader,
+        mut header: RtpHeader,
         data: Vec<u8>,
         seq_no: SeqNo,
         time: MediaTime,
@@ -391,6 +516,10 @@ impl StreamRx {
             }
         }
 
+        if let Some(dd) = &mut header.ext_vals.dependency_descriptor {
+            self.dependency_descriptor.resolve(dd);
+        }
+
         let packet = RtpPacket {
             seq_no,
             time,
@@ -398,7 +527,9 @@ impl StreamRx {
             payload: data,
             nackable: false,
             last_sender_info: self.sender_info.map(|(_, s)| s),
+            recovered: false,
             timestamp: now,
+            wallclock: None,
         };
 
         self.stats.bytes += packet.payload.len() as u64;
@@ -407,31 +538,128 @@ impl StreamRx {
         Some(packet)
     }
 
-    pub(crate) fn un_rtx(&self, header: &mut RtpHeader, data: &mut Vec<u8>, pt: Pt) {
-        let mut orig_seq_no_16 = 0;
+    /// Whether the stream restarted since the last call.
+    pub(crate) fn take_depack_reset(&mut self) -> bool {
+        std::mem::take(&mut self.need_depack_reset)
+    }
+
+    pub(crate) fn update_vp9_structure(&mut self, payload: &[u8]) {
+        // Only parse the descriptor when the V bit says there is an SS.
+        if payload.first().map(|b| b & 0x02 == 0).unwrap_or(true) {
+            return;
+        }
 
-        let n = RtpHeader::read_original_sequence_number(data, &mut orig_seq_no_16);
-        data.drain(0..n);
+        match Vp9Meta::parse(payload) {
+            Ok((meta, _)) => {
+                if let Some(ss) = meta.scalability_structure {
+                    self.vp9_structure = Some(ss);
+                }
+            }
+            Err(e) => debug!("Failed to parse VP9 scalability structure: {}", e),
+        }
+    }
 
-        trace!(
-            "Repaired seq no {} -> {}",
-            header.sequence_number,
-            orig_seq_no_16
-        );
+    /// Track DTX silences of an Opus stream.
+    ///
+    /// Packets missing in a silence were not sent, and are marked as received in the
+    /// register to not be NACKed or counted as lost. This must happen before the next NACK
+    /// report, which is why it's done as the packet after the silence is handled.
+    pub(crate) fn update_opus_dtx(
+        &mut self,
+        seq_no: SeqNo,
+        time: MediaTime,
+        marker: bool,
+        payload: &[u8],
+    ) {
+        let Ok(meta) = OpusMeta::parse(payload) else {
+            return;
+        };
+
+        if let Some((last_seq, last_end, last_dtx)) = self.last_opus {
+            // Late or repeated packets don't change the silence.
+            if seq_no <= last_seq {
+                return;
+            }
+
+            // A gap in time is a silence after DTX, or if the sender says so with the marker.
+            if time > last_end && (last_dtx || marker) {
+                let skipped = *seq_no - *last_seq - 1;
+                if skipped > 0 {
+                    if let Some(register) = &mut self.register {
+                        register.skip((*last_seq + 1).into(), seq_no);
+                    }
+                }
+                self.silence.get_or_insert((last_end, 0)).1 += skipped;
+            }
+        }
 
-        header.sequence_number = orig_seq_no_16;
+        if meta.is_dtx {
+            self.silence.get_or_insert((time, 0));
+        } else if let Some((start, skipped)) = self.silence.take() {
+            debug!(
+                "Silence in SSRC {} from {:?} to {:?}, skipped {}",
+                self.ssrc, start, time, skipped
+            );
+            self.silences.push_back(StreamSilence {
+                ssrc: self.ssrc,
+                mid: self.mid,
+                rid: self.rid,
+                start,
+                end: time,
+                skipped,
+            });
+        }
+
+        let duration = MediaTime::new(meta.samples() as u64, Frequency::FORTY_EIGHT_KHZ);
+        let end = time + duration.rebase(time.frequency());
+        self.last_opus = Some((seq_no, end, meta.is_dtx));
+    }
 
-        header.ssrc = self.ssrc;
-        header.payload_type = pt;
-        header.ext_vals.rid = header.ext_vals.rid_repair.take();
+    pub(crate) fn ulpfec(&mut self) -> &mut FecReceiver {
+        let ssrc = self.ssrc;
+        self.ulpfec.get_or_insert_with(|| FecReceiver::new(ssrc))
+    }
+
+    /// Add a received FlexFEC payload.
+    ///
+    /// Returns the recovered packets.
+    pub(crate) fn add_flexfec(&mut self, payload: &[u8]) -> Vec<Vec<u8>> {
+        let ssrc = self.ssrc;
+
+        let Some(fec) = parse_flexfec(payload, ssrc) else {
+            debug!("Failed to parse FlexFEC packet");
+            return vec![];
+        };
+
+        let receiver = self.flexfec.get_or_insert_with(|| FecReceiver::new(ssrc));
+        receiver.add_fec(fec)
+    }
+
+    /// Add a received media packet protected by FlexFEC, header and payload with padding.
+    ///
+    /// Returns the packets this made recoverable.
+    pub(crate) fn add_flexfec_media(&mut self, packet: Vec<u8>) -> Vec<Vec<u8>> {
+        let ssrc = self.ssrc;
+        let receiver = self.flexfec.get_or_insert_with(|| FecReceiver::new(ssrc));
+        receiver.add_media(packet)
+    }
+
+    pub(crate) fn un_rtx(&self, header: &mut RtpHeader, data: &mut Vec<u8>, pt: Pt) {
+        un_rtx_header(header, data, self.ssrc, pt);
     }
 
     pub(crate) fn maybe_create_keyframe_request(
         &mut self,
         sender_ssrc: Ssrc,
+        caps: FeedbackCaps,
         feedback: &mut VecDeque<Rtcp>,
     ) {
-        let Some(kind) = self.pending_request_keyframe.take() else {
+        let Some(requested) = self.pending_request_keyframe.take() else {
+            return;
+        };
+
+        let Some(kind) = caps.keyframe_request(requested) else {
+            debug!("Drop {:?}, neither PLI nor FIR negotiated", requested);
             return;
         };
 
@@ -459,12 +687,18 @@ impl StreamRx {
     pub(crate) fn maybe_create_remb_request(
         &mut self,
         sender_ssrc: Ssrc,
+        caps: FeedbackCaps,
         feedback: &mut VecDeque<Rtcp>,
     ) {
         let Some(bitrate) = self.pending_request_remb.take() else {
             return;
         };
 
+        if !caps.remb {
+            debug!("Drop REMB request, goog-remb not negotiated");
+            return;
+        }
+
         feedback.push_back(Rtcp::Remb(Remb {
             sender_ssrc,
             ssrc: 0.into(),
@@ -479,8 +713,8 @@ impl StreamRx {
         x
     }
 
-    pub(crate) fn need_rr(&self, now: Instant) -> bool {
-        now >= self.receiver_report_at()
+    pub(crate) fn need_rr(&self, now: Instant, interval: Option<Duration>) -> bool {
+        now >= self.receiver_report_at(interval)
     }
 
     pub(crate) fn create_rr_and_update(
@@ -492,9 +726,8 @@ impl StreamRx {
         let mut rr = self.create_receiver_report(now);
         rr.sender_ssrc = sender_ssrc;
 
-        if !rr.reports.is_empty() {
-            let l = rr.reports[rr.reports.len() - 1].fraction_lost;
-            self.stats.update_loss(l);
+        if let Some(report) = rr.reports.iter().last() {
+            self.stats.update_report(*report);
         }
 
         let xr = self.create_extended_receiver_report(now);
@@ -573,7 +806,8 @@ impl StreamRx {
         sender_ssrc: Ssrc,
         feedback: &mut VecDeque<Rtcp>,
     ) -> Option<()> {
-        if !self.nack_enabled() {
+        // No resends from a remote that said BYE.
+        if !self.nack_enabled() || self.ended {
             return None;
         }
 
@@ -593,6 +827,64 @@ impl StreamRx {
 
     pub(crate) fn visit_stats(&mut self, snapshot: &mut StatsSnapshot, now: Instant) {
         self.stats.fill(snapshot, self.mid, self.rid, now);
+
+        if self.stats.bytes == 0 {
+            return;
+        }
+
+        let report = self.stats.report;
+
+        let stats = IngressStats {
+            ssrc: self.ssrc,
+            mid: self.mid,
+            rid: self.rid,
+            bytes: self.stats.bytes,
+            packets: self.stats.packets,
+            // The register keeps jitter in microseconds.
+            jitter: report.map(|r| r.jitter as f32 / 1000.0),
+            fraction_lost: self.stats.loss,
+            // The RR field is a signed 24 bit number.
+            packets_lost: report.map(|r| (((r.packets_lost << 8) as i32) >> 8) as i64),
+            last_sender_info: self.sender_info.map(|(_, s)| s),
+            last_sender_info_at: self.sender_info.map(|(t, _)| t),
+            nacks_sent: self.stats.nacks,
+            plis_sent: self.stats.plis,
+            firs_sent: self.stats.firs,
+            timestamp: now,
+        };
+
+        snapshot.ingress_ssrc.insert(self.ssrc, stats);
+    }
+
+    /// Counters for [`Rtc::stats()`][crate::Rtc::stats].
+    pub(crate) fn inbound_stats(&self, kind: MediaKind) -> InboundRtpStats {
+        let s = &self.stats;
+
+        InboundRtpStats {
+            ssrc: self.ssrc,
+            kind,
+            mid: self.mid,
+            rid: self.rid,
+            bytes_received: s.bytes,
+            packets_received: s.packets,
+            // The register keeps jitter in microseconds.
+            jitter: s.report.map(|r| r.jitter as f64 / 1_000_000.0),
+            fraction_lost: s.loss,
+            // The RR field is a signed 24 bit number.
+            packets_lost: s
+                .report
+                .map(|r| (((r.packets_lost << 8) as i32) >> 8) as i64),
+            nack_count: Some(s.nacks),
+            pli_count: s.plis,
+            fir_count: s.firs,
+            last_sender_report: self.sender_info.map(|(at, info)| SenderReportStats {
+                received_at: at,
+                remote_timestamp: info.ntp_time,
+                rtp_time: info.rtp_time.numer(),
+                packets_sent: info.sender_packet_count,
+                bytes_sent: info.sender_octet_count,
+            }),
+        }
     }
 
     pub(crate) fn poll_paused(&mut self) -> Option<StreamPaused> {
@@ -618,6 +910,45 @@ impl StreamRx {
         })
     }
 
+    pub(crate) fn poll_restarted(&mut self) -> Option<StreamRestarted> {
+        if !self.need_restarted_event {
+            return None;
+        }
+
+        self.need_restarted_event = false;
+
+        info!(
+            "Restarted StreamRx with mid: {} rid: {:?} and SSRC: {}",
+            self.mid, self.rid, self.ssrc
+        );
+
+        Some(StreamRestarted {
+            ssrc: self.ssrc,
+            mid: self.mid,
+            rid: self.rid,
+        })
+    }
+
+    pub(crate) fn poll_silence(&mut self) -> Option<StreamSilence> {
+        self.silences.pop_front()
+    }
+
+    pub(crate) fn poll_ended(&mut self) -> Option<StreamEnded> {
+        let reason = self.need_ended_event.take()?;
+
+        info!(
+            "Ended StreamRx with mid: {} rid: {:?} and SSRC: {} reason: {:?}",
+            self.mid, self.rid, self.ssrc, reason
+        );
+
+        Some(StreamEnded {
+            ssrc: self.ssrc,
+            mid: self.mid,
+            rid: self.rid,
+            reason,
+        })
+    }
+
     pub(crate) fn reset_buffers(&mut self) {
         if let Some(r) = &mut self.register {
             r.clear();
@@ -664,6 +995,17 @@ impl StreamRx {
         self.register_rtx = None;
     }
 
+    pub(crate) fn maybe_reset_fec(&mut self, fec: Ssrc) {
+        if self.fec == Some(fec) {
+            return;
+        }
+
+        debug!("SSRC {} associated with FlexFEC: {}", self.ssrc, fec);
+
+        self.fec = Some(fec);
+        self.flexfec = None;
+    }
+
     /// Reset the current rollover counter (ROC).
     ///
     /// This is used in scenarios where we use a single sequence number across all
@@ -682,11 +1024,17 @@ impl StreamRx {
         self.register_rtx = None;
         self.reset_roc = Some(roc);
     }
+
+    /// ROC set by [`StreamRx::reset_roc`], not yet applied to an incoming packet.
+    pub(crate) fn pending_reset_roc(&self) -> Option<u64> {
+        self.reset_roc
+    }
 }
 
 impl StreamRxStats {
-    fn update_loss(&mut self, fraction_lost: u8) {
-        self.loss = Some(fraction_lost as f32 / u8::MAX as f32)
+    fn update_report(&mut self, report: ReceptionReport) {
+        self.loss = Some(report.fraction_lost as f32 / u8::MAX as f32);
+        self.report = Some(report);
     }
 
     pub(crate) fn fill(
@@ -4,10 +4,27 @@ use crate::rtp_::{Nack, ReceptionReport, SeqNo};
 
 use super::register_nack::NackRegister;
 
+/// Largest jump in sequence number that is considered part of the same stream.
+///
+/// See https://www.rfc-editor.org/rfc/rfc3550#appendix-A.1
+const MAX_DROPOUT: i64 = 3000;
+
+/// Largest regression in RTP time, in seconds, that is considered part of the same stream.
+const MAX_TIME_REGRESSION: i64 = 10;
+
+/// Number of sequential packets after a discontinuity before we consider the stream restarted.
+const MIN_SEQUENTIAL: usize = 2;
+
 #[derive(Debug)]
 pub struct ReceiverRegister {
     nack: NackRegister,
 
+    /// Packets after a discontinuity, before we consider the stream restarted.
+    probation: Option<Probation>,
+
+    /// Set when the stream restarted, until read by `take_restarted()`.
+    restarted: bool,
+
     /// First sequence number received
     first: Option<SeqNo>,
 
@@ -28,6 +45,14 @@ pub struct ReceiverRegister {
     jitter: f32,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Probation {
+    /// Last sequence number in the probation.
+    last: SeqNo,
+    /// Number of sequential packets seen so far.
+    count: usize,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct TimePoint {
     arrival: Instant,
@@ -64,6 +89,8 @@ impl ReceiverRegister {
     pub fn new() -> Self {
         ReceiverRegister {
             nack: NackRegister::new(),
+            probation: None,
+            restarted: false,
             first: None,
             count: 0,
             time_point_prior: None,
@@ -74,6 +101,35 @@ impl ReceiverRegister {
     }
 
     pub fn update(&mut self, seq: SeqNo, arrival: Instant, rtp_time: u32, clock_rate: u32) -> bool {
+        if self.is_discontinuity(seq, rtp_time, clock_rate) {
+            let probation = match self.probation {
+                Some(p) if p.last.is_next(seq) => Probation {
+                    last: seq,
+                    count: p.count + 1,
+                },
+                _ => Probation {
+                    last: seq,
+                    count: 1,
+                },
+            };
+
+            if probation.count < MIN_SEQUENTIAL {
+                // Until the probation is over, the packet is not registered. This stops a single
+                // wildly off packet from messing up the state.
+                trace!("Discontinuity at seq {}, in probation", seq);
+                self.probation = Some(probation);
+                return true;
+            }
+
+            info!(
+                "Stream restarted after {} sequential packets at seq {}",
+                MIN_SEQUENTIAL, seq
+            );
+            self.clear();
+            self.restarted = true;
+        }
+        self.probation = None;
+
         if self.first.is_none() {
             self.first = Some(seq);
         }
@@ -89,6 +145,19 @@ impl ReceiverRegister {
         new
     }
 
+    /// Mark sequence numbers from `from` up to, not including, `to` as received.
+    ///
+    /// For packets the sender deliberately didn't send, such as during DTX. They are
+    /// neither NACKed nor counted as lost. Only the sequence numbers still in the NACK
+    /// window are affected.
+    pub fn skip(&mut self, from: SeqNo, to: SeqNo) {
+        for seq in *from..*to {
+            if self.nack.update(seq.into()) {
+                self.count += 1;
+            }
+        }
+    }
+
     /// Generates a NACK report
     pub fn nack_report(&mut self) -> Option<impl Iterator<Item = Nack>> {
         self.nack.nack_reports()
@@ -119,8 +188,14 @@ impl ReceiverRegister {
         self.nack.max_seq()
     }
 
+    /// Whether the stream restarted since the last call.
+    pub fn take_restarted(&mut self) -> bool {
+        std::mem::take(&mut self.restarted)
+    }
+
     pub fn clear(&mut self) {
         self.nack = NackRegister::new();
+        self.probation = None;
         self.count = 0;
         self.first = None;
         self.time_point_prior = None;
@@ -129,6 +204,28 @@ impl ReceiverRegister {
         self.jitter = 0.0;
     }
 
+    /// Whether a packet is too far off the current state to belong to the same stream.
+    ///
+    /// This happens when the remote restarts the stream, but also for single corrupt
+    /// packets, which is why a discontinuity starts a probation.
+    fn is_discontinuity(&self, seq: SeqNo, rtp_time: u32, clock_rate: u32) -> bool {
+        let Some(max_seq) = self.max_seq() else {
+            return false;
+        };
+
+        let seq_delta = *seq as i64 - *max_seq as i64;
+        if seq_delta.abs() > MAX_DROPOUT {
+            return true;
+        }
+
+        let Some(prior) = self.time_point_prior else {
+            return false;
+        };
+
+        let regression = prior.rtp_time.wrapping_sub(rtp_time) as i32 as i64;
+        regression > MAX_TIME_REGRESSION * clock_rate as i64
+    }
+
     fn update_time(&mut self, arrival: Instant, rtp_time: u32, clock_rate: u32) {
         let tp = TimePoint {
             arrival,
@@ -340,4 +437,92 @@ mod test {
         assert_eq!(19, report.max_seq);
         assert_eq!(0, report.jitter);
     }
+
+    #[test]
+    fn restart_after_seq_jump() {
+        let mut r = ReceiverRegister::new();
+        let now = Instant::now();
+
+        for i in 10..20 {
+            r.update((i as u64).into(), now, i * 900, 90_000);
+        }
+        assert!(!r.take_restarted());
+
+        // Remote restarts with a new random seq and timestamp.
+        r.update(40_000.into(), now, 5_000_000, 90_000);
+        assert!(!r.take_restarted());
+        assert_eq!(r.max_seq(), Some(19.into()));
+
+        r.update(40_001.into(), now, 5_000_900, 90_000);
+        assert!(r.take_restarted());
+        assert!(!r.take_restarted());
+        assert_eq!(r.max_seq(), Some(40_001.into()));
+
+        r.update(40_002.into(), now, 5_001_800, 90_000);
+
+        // State is re-initialized, no loss between the streams.
+        let report = r.reception_report().expect("some report");
+        assert_eq!(0, report.fraction_lost);
+        assert_eq!(0, report.packets_lost);
+        assert_eq!(40_002, report.max_seq);
+    }
+
+    #[test]
+    fn restart_after_time_regression() {
+        let mut r = ReceiverRegister::new();
+        let now = Instant::now();
+
+        for i in 10..20 {
+            r.update((i as u64).into(), now, 10_000_000 + i * 900, 90_000);
+        }
+
+        // Sequence numbers continue, but RTP time goes back by much more than 10s.
+        r.update(20.into(), now, 900, 90_000);
+        assert!(!r.take_restarted());
+        r.update(21.into(), now, 1800, 90_000);
+        assert!(r.take_restarted());
+    }
+
+    #[test]
+    fn single_corrupt_packet_no_restart() {
+        let mut r = ReceiverRegister::new();
+        let mut control = ReceiverRegister::new();
+        let start = Instant::now();
+        let dur = Duration::from_micros(10_000);
+
+        for i in 10..20 {
+            r.update((i as u64).into(), start + i * dur, i * 900, 90_000);
+            control.update((i as u64).into(), start + i * dur, i * 900, 90_000);
+        }
+
+        r.update(30_000.into(), start + 20 * dur, 123_456_789, 90_000);
+
+        for i in 20..30 {
+            r.update((i as u64).into(), start + i * dur, i * 900, 90_000);
+            control.update((i as u64).into(), start + i * dur, i * 900, 90_000);
+        }
+        assert!(!r.take_restarted());
+
+        // The corrupt packet left no trace.
+        let report = r.reception_report().expect("some report");
+        assert_eq!(report, control.reception_report().unwrap());
+        assert_eq!(0, report.packets_lost);
+        assert_eq!(29, report.max_seq);
+    }
+
+    #[test]
+    fn probation_must_be_sequential() {
+        let mut r = ReceiverRegister::new();
+        let now = Instant::now();
+
+        for i in 10..20 {
+            r.update((i as u64).into(), now, i * 900, 90_000);
+        }
+
+        // Two wild packets that don't follow each other.
+        r.update(30_000.into(), now, 0, 90_000);
+        r.update(50_000.into(), now, 0, 90_000);
+        assert!(!r.take_restarted());
+        assert_eq!(r.max_seq(), Some(19.into()));
+    }
 }
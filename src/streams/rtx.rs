@@ -0,0 +1,187 @@
+use crate::format::PayloadParams;
+use crate::rtp_::{Pt, RtpHeader, SeqNo, Ssrc};
+
+use super::RtpPacket;
+
+/// Wrap a packet for retransmission on an RTX stream (RFC 4588).
+///
+/// The original sequence number is prepended to the payload, and the header is moved to
+/// the RTX SSRC, PT and sequence number. Header extensions are carried over, with the rid
+/// moved to the repaired rid.
+pub fn rtx_wrap(original: &RtpPacket, rtx_ssrc: Ssrc, rtx_pt: Pt, rtx_seq: SeqNo) -> RtpPacket {
+    let mut header = original.header.clone();
+    rtx_header(&mut header, rtx_ssrc, rtx_pt, rtx_seq);
+
+    let mut payload = vec![0; 2 + original.payload.len()];
+    let n = RtpHeader::write_original_sequence_number(&mut payload, original.seq_no);
+    payload[n..].copy_from_slice(&original.payload);
+
+    RtpPacket {
+        seq_no: rtx_seq,
+        time: original.time,
+        header,
+        payload,
+        timestamp: original.timestamp,
+        last_sender_info: original.last_sender_info,
+        recovered: original.recovered,
+        nackable: false,
+        wallclock: original.wallclock,
+    }
+}
+
+/// Unwrap a packet received on an RTX stream (RFC 4588) to the original packet.
+///
+/// * `ssrc` is the main SSRC the RTX stream repairs (as associated via the ssrc-group FID or
+///   the repaired rid).
+/// * `params` are used to map the RTX PT to the main PT (the `apt`).
+/// * `previous` is the last extended sequence number of the main stream, used to extend the
+///   original sequence number.
+///
+/// Returns `None` if the packet doesn't carry media, such as RTX padding used for probing,
+/// or if the RTX PT has no main PT.
+pub fn rtx_unwrap(
+    rtx: &RtpPacket,
+    ssrc: Ssrc,
+    params: &[PayloadParams],
+    previous: Option<SeqNo>,
+) -> Option<RtpPacket> {
+    // Padding packets have no original sequence number.
+    if rtx.payload.len() < 2 {
+        return None;
+    }
+
+    let pt = params
+        .iter()
+        .find(|p| p.resend() == Some(rtx.header.payload_type))?
+        .pt();
+
+    let mut header = rtx.header.clone();
+    let mut payload = rtx.payload.clone();
+    un_rtx_header(&mut header, &mut payload, ssrc, pt);
+
+    Some(RtpPacket {
+        seq_no: header.sequence_number(previous),
+        time: rtx.time,
+        header,
+        payload,
+        timestamp: rtx.timestamp,
+        last_sender_info: rtx.last_sender_info,
+        recovered: rtx.recovered,
+        nackable: false,
+        wallclock: rtx.wallclock,
+    })
+}
+
+/// Rewrite a (cloned) header of a main stream packet to be sent on the RTX stream.
+pub(crate) fn rtx_header(header: &mut RtpHeader, rtx_ssrc: Ssrc, rtx_pt: Pt, rtx_seq: SeqNo) {
+    header.payload_type = rtx_pt;
+    header.ssrc = rtx_ssrc;
+    header.sequence_number = *rtx_seq as u16;
+
+    header.ext_vals.rid_repair = header.ext_vals.rid.take();
+}
+
+/// Rewrite the header of an RTX packet to the main stream, and remove the original
+/// sequence number from the payload.
+pub(crate) fn un_rtx_header(header: &mut RtpHeader, data: &mut Vec<u8>, ssrc: Ssrc, pt: Pt) {
+    let mut orig_seq_no_16 = 0;
+
+    let n = RtpHeader::read_original_sequence_number(data, &mut orig_seq_no_16);
+    data.drain(0..n);
+
+    trace!(
+        "Repaired seq no {} -> {}",
+        header.sequence_number,
+        orig_seq_no_16
+    );
+
+    header.sequence_number = orig_seq_no_16;
+
+    header.ssrc = ssrc;
+    header.payload_type = pt;
+    header.ext_vals.rid = header.ext_vals.rid_repair.take();
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use crate::format::{Codec, CodecSpec, FormatParams};
+    use crate::rtp_::{Frequency, MediaTime, Mid};
+
+    use super::*;
+
+    fn params() -> Vec<PayloadParams> {
+        vec![PayloadParams::new(
+            96.into(),
+            Some(97.into()),
+            CodecSpec {
+                codec: Codec::Vp8,
+                clock_rate: Frequency::NINETY_KHZ,
+                channels: None,
+                format: FormatParams::default(),
+            },
+        )]
+    }
+
+    fn packet(seq_no: u64, payload: Vec<u8>) -> RtpPacket {
+        let mut header = RtpHeader {
+            payload_type: 96.into(),
+            sequence_number: seq_no as u16,
+            timestamp: 1234,
+            ssrc: 1.into(),
+            marker: true,
+            ..Default::default()
+        };
+        header.ext_vals.mid = Some(Mid::from("0"));
+        header.ext_vals.rid = Some("h".into());
+        header.ext_vals.audio_level = Some(-30);
+
+        RtpPacket {
+            seq_no: seq_no.into(),
+            time: MediaTime::from_90khz(1234),
+            header,
+            payload,
+            timestamp: Instant::now(),
+            last_sender_info: None,
+            recovered: false,
+            nackable: true,
+            wallclock: None,
+        }
+    }
+
+    #[test]
+    fn wrap_unwrap_identity() {
+        let original = packet(65_540, vec![1, 2, 3, 4]);
+
+        let rtx = rtx_wrap(&original, 2.into(), 97.into(), 10.into());
+        assert_eq!(rtx.header.ssrc, 2.into());
+        assert_eq!(*rtx.header.payload_type, 97);
+        assert_eq!(rtx.header.sequence_number, 10);
+        assert_eq!(rtx.payload, [0, 4, 1, 2, 3, 4]);
+        assert_eq!(rtx.header.ext_vals.rid, None);
+        assert_eq!(rtx.header.ext_vals.rid_repair, Some("h".into()));
+        assert_eq!(rtx.header.ext_vals.audio_level, Some(-30));
+
+        let unwrapped = rtx_unwrap(&rtx, 1.into(), &params(), Some(65_539.into())).unwrap();
+
+        // The original is nackable, which is a sender side property.
+        let mut original = original;
+        original.nackable = false;
+        assert_eq!(unwrapped, original);
+    }
+
+    #[test]
+    fn unwrap_padding_is_no_media() {
+        let mut padding = packet(0, vec![]);
+        padding.header.payload_type = 97.into();
+        assert!(rtx_unwrap(&padding, 1.into(), &params(), None).is_none());
+    }
+
+    #[test]
+    fn unwrap_unknown_pt() {
+        let original = packet(1, vec![1, 2, 3]);
+        let rtx = rtx_wrap(&original, 2.into(), 98.into(), 10.into());
+        assert!(rtx_unwrap(&rtx, 1.into(), &params(), None).is_none());
+    }
+}
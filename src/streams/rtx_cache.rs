@@ -92,7 +92,9 @@ mod test {
             payload: millis.to_be_bytes().to_vec(),
             timestamp: after(now, millis),
             last_sender_info: None,
+            recovered: false,
             nackable: true,
+            wallclock: None,
         }
     }
 
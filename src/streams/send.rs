@@ -14,20 +14,22 @@ use crate::packet::QueuePriority;
 use crate::packet::QueueSnapshot;
 use crate::packet::QueueState;
 use crate::rtp_::Bitrate;
-use crate::rtp_::{extend_u16, Descriptions, ReportList, Rtcp};
+use crate::rtp_::MAX_BLANK_PADDING_PAYLOAD_SIZE;
+use crate::rtp_::{extend_u16, ReportList, Rtcp};
 use crate::rtp_::{ExtensionMap, ReceptionReport, RtpHeader};
 use crate::rtp_::{ExtensionValues, Frequency, MediaTime, Mid, NackEntry};
 use crate::rtp_::{Pt, Rid, RtcpFb, SenderInfo, SenderReport, Ssrc};
-use crate::rtp_::{Sdes, SdesType, MAX_BLANK_PADDING_PAYLOAD_SIZE};
-use crate::rtp_::{SeqNo, SRTP_BLOCK_SIZE};
+use crate::rtp_::{SeqNo, TwccSeqAllocator, SRTP_BLOCK_SIZE};
 use crate::session::PacketReceipt;
-use crate::stats::MediaEgressStats;
 use crate::stats::StatsSnapshot;
+use crate::stats::{MediaEgressStats, OutboundRtpStats};
 use crate::util::value_history::ValueHistory;
 use crate::util::{already_happened, calculate_rtt_ms, not_happening};
 use crate::util::{InstantExt, NonCryptographicRng};
 use crate::RtcError;
 
+use super::flexfec::{FlexfecConfig, FlexfecSender};
+use super::rtx::rtx_header;
 use super::rtx_cache::RtxCache;
 use super::send_queue::SendQueue;
 use super::{rr_interval, RtpPacket};
@@ -60,9 +62,6 @@ pub struct StreamTx {
     /// Set on first handle_timeout.
     kind: Option<MediaKind>,
 
-    /// Set on first handle_timeout.
-    cname: Option<String>,
-
     /// The last main payload clock rate that was sent.
     clock_rate: Option<Frequency>,
 
@@ -78,6 +77,9 @@ pub struct StreamTx {
     /// Last written media + wallclock time.
     rtp_and_wallclock: Option<(u32, Instant)>,
 
+    /// RTP time and wallclock of the first SR, for SRs before any written media.
+    sr_anchor: Option<(u32, Instant)>,
+
     /// Queue of packets to send.
     ///
     /// The packets here do not have correct sequence numbers, header extension values etc.
@@ -99,8 +101,8 @@ pub struct StreamTx {
     blank_packet: RtpPacket,
 
     /// Cache of sent packets to be able to answer to NACKs as well as
-    /// sending spurious resends as padding.
-    rtx_cache: RtxCache,
+    /// sending spurious resends as padding. None if RTX is turned off for the media.
+    rtx_cache: Option<RtxCache>,
 
     /// Last time we produced a SR.
     last_sender_report: Instant,
@@ -120,6 +122,9 @@ pub struct StreamTx {
     // The _main_ PT to use for padding. This is main PT, since the poll_packet() loop
     // figures out the param.resend() RTX PT using main.
     pt_for_padding: Option<Pt>,
+
+    /// Generates FlexFEC packets protecting the sent media, if enabled.
+    flexfec: Option<FlexfecSender>,
 }
 
 /// Holder of stats.
@@ -146,6 +151,8 @@ pub(crate) struct StreamTxStats {
     rtt: Option<f32>,
     /// losses collecter from RR (known packets, lost ratio)
     losses: Vec<(u64, f32)>,
+    /// the last reception report received, if any
+    last_report: Option<ReceptionReport>,
     bytes_transmitted: ValueHistory<u64>,
     bytes_retransmitted: ValueHistory<u64>,
 }
@@ -166,24 +173,25 @@ impl StreamTx {
             mid,
             rid,
             kind: None,
-            cname: None,
             clock_rate: None,
             seq_no,
             seq_no_rtx,
             last_used: already_happened(),
             rtp_and_wallclock: None,
+            sr_anchor: None,
             send_queue: SendQueue::new(),
             unpaced: None,
             resends: VecDeque::new(),
             padding: 0,
             blank_packet: RtpPacket::blank(),
-            rtx_cache: RtxCache::new(2000, DEFAULT_RTX_CACHE_DURATION),
+            rtx_cache: Some(RtxCache::new(2000, DEFAULT_RTX_CACHE_DURATION)),
             last_sender_report: already_happened(),
             pending_request_keyframe: None,
             pending_request_remb: None,
             stats: StreamTxStats::default(),
             rtx_ratio: (0.0, already_happened()),
             pt_for_padding: None,
+            flexfec: None,
         }
     }
 
@@ -218,7 +226,12 @@ impl StreamTx {
     /// The default is 1024 packets over 3 seconds.
     pub fn set_rtx_cache(&mut self, max_packets: usize, max_age: Duration) {
         // Dump old cache to avoid having to deal with resizing logic inside the cache impl.
-        self.rtx_cache = RtxCache::new(max_packets, max_age);
+        self.rtx_cache = Some(RtxCache::new(max_packets, max_age));
+    }
+
+    /// Drop the RTX cache. Sent packets are not kept and NACKs are not answered.
+    pub(crate) fn disable_rtx_cache(&mut self) {
+        self.rtx_cache = None;
     }
 
     /// Set whether this stream is unpaced or not.
@@ -231,6 +244,35 @@ impl StreamTx {
         self.unpaced = Some(unpaced);
     }
 
+    /// Enable or disable FlexFEC for this stream.
+    ///
+    /// The FEC packets are sent on the SSRC of the config, which is announced in the SDP as
+    /// `a=ssrc-group:FEC-FR <ssrc> <fec ssrc>`. Only regular media packets are protected,
+    /// not resends or padding.
+    ///
+    /// Disabling drops any FEC packets not yet sent.
+    pub fn set_flexfec(&mut self, config: Option<FlexfecConfig>) {
+        if self.flexfec.as_ref().map(|f| f.config()) == config {
+            return;
+        }
+        self.flexfec = config.map(FlexfecSender::new);
+    }
+
+    /// The FlexFEC config, if enabled.
+    pub fn flexfec(&self) -> Option<FlexfecConfig> {
+        self.flexfec.as_ref().map(|f| f.config())
+    }
+
+    /// Set the fraction of lost packets (0.0 to 1.0) that drives the FlexFEC protection rate.
+    ///
+    /// This is updated by the receiver reports from the remote peer. Use this to drive the
+    /// rate from other loss statistics.
+    pub fn set_flexfec_loss(&mut self, loss: f32) {
+        if let Some(flexfec) = &mut self.flexfec {
+            flexfec.set_loss(loss);
+        }
+    }
+
     /// Write RTP packet to a send stream.
     ///
     /// The `payload` argument is expected to be only the RTP payload, not the RTP packet header.
@@ -265,6 +307,22 @@ impl StreamTx {
         nackable: bool,
         payload: Vec<u8>,
     ) -> Result<(), RtcError> {
+        let header = self.header_for(pt, seq_no, time, marker, ext_vals);
+
+        self.enqueue(header, seq_no, time, wallclock, nackable, payload);
+
+        Ok(())
+    }
+
+    fn enqueue(
+        &mut self,
+        header: RtpHeader,
+        seq_no: SeqNo,
+        time: u32,
+        wallclock: Instant,
+        nackable: bool,
+        payload: Vec<u8>,
+    ) {
         let first_call = self.rtp_and_wallclock.is_none();
 
         if first_call && seq_no.roc() > 0 {
@@ -276,16 +334,6 @@ impl StreamTx {
         let media_time = MediaTime::from_secs(time as u64);
         self.rtp_and_wallclock = Some((time, wallclock));
 
-        let header = RtpHeader {
-            sequence_number: *seq_no as u16,
-            marker,
-            payload_type: pt,
-            timestamp: time,
-            ssrc: self.ssrc,
-            ext_vals,
-            ..Default::default()
-        };
-
         let packet = RtpPacket {
             seq_no,
             time: media_time,
@@ -301,15 +349,95 @@ impl StreamTx {
             // timestamp of all packets that are about to be sent.
             timestamp: not_happening(),
 
-            // This is only relevant for incoming RTP packets.
+            // These are only relevant for incoming RTP packets.
             last_sender_info: None,
+            recovered: false,
+
+            wallclock: Some(wallclock),
         };
 
         self.send_queue.push(packet);
+    }
+
+    /// Write a padding-only RTP packet to a send stream.
+    ///
+    /// The packet has the P bit set and a payload of `pad_len` zero bytes, where the last byte
+    /// is `pad_len`. It consumes `seq_no` like any other packet on the stream and is sent
+    /// with the negotiated header extensions. Since the count byte is itself padding, a
+    /// `pad_len` of 0 can't be expressed and is written as 1.
+    ///
+    /// * `pt` Payload type. Declared in the Media this encoded stream belongs to.
+    /// * `seq_no` Sequence number to use for this packet.
+    /// * `time` Time in the clock rate of the media, typically the same as the last media packet.
+    /// * `wallclock` Real world time that corresponds to the media time.
+    /// * `pad_len` Number of padding bytes, including the count byte.
+    pub fn write_padding(
+        &mut self,
+        pt: Pt,
+        seq_no: SeqNo,
+        time: u32,
+        wallclock: Instant,
+        pad_len: u8,
+    ) -> Result<(), RtcError> {
+        let pad_len = pad_len.max(1);
+
+        let mut payload = vec![0; pad_len as usize];
+        payload[pad_len as usize - 1] = pad_len;
+
+        let header = RtpHeader {
+            has_padding: true,
+            ..self.header_for(pt, seq_no, time, false, ExtensionValues::default())
+        };
+
+        self.enqueue(header, seq_no, time, wallclock, false, payload);
 
         Ok(())
     }
 
+    fn header_for(
+        &self,
+        pt: Pt,
+        seq_no: SeqNo,
+        time: u32,
+        marker: bool,
+        ext_vals: ExtensionValues,
+    ) -> RtpHeader {
+        RtpHeader {
+            sequence_number: *seq_no as u16,
+            marker,
+            payload_type: pt,
+            timestamp: time,
+            ssrc: self.ssrc,
+            ext_vals,
+            ..Default::default()
+        }
+    }
+
+    /// Size of the RTP header, with extensions, of packets sent with these values.
+    ///
+    /// This includes the extensions set by [`StreamTx::poll_packet`] and the original
+    /// sequence number of RTX resends.
+    pub(crate) fn rtp_header_size(
+        &self,
+        now: Instant,
+        pt: Pt,
+        ext_vals: &ExtensionValues,
+        exts: &ExtensionMap,
+    ) -> usize {
+        let mut header = self.header_for(pt, 0.into(), 0, false, ext_vals.clone());
+        header.ext_vals.mid = Some(self.mid);
+        header.ext_vals.rid = self.rid;
+        header.ext_vals.abs_send_time = Some(now);
+        header.ext_vals.transport_cc = Some(0);
+
+        let mut buf = vec![0; DATAGRAM_MAX_PACKET_SIZE];
+        let header_len = header.write_to(&mut buf, exts);
+
+        let original_seq_len = if self.rtx.is_some() { 2 } else { 0 };
+
+        header_len + original_seq_len
+    }
+
     fn padding_enabled(&self) -> bool {
         self.rtx.is_some() && self.pt_for_padding.is_some()
     }
@@ -318,20 +446,24 @@ impl StreamTx {
         &mut self,
         now: Instant,
         exts: &ExtensionMap,
-        twcc: &mut u64,
+        twcc: &mut TwccSeqAllocator,
         params: &[PayloadParams],
         buf: &mut Vec<u8>,
     ) -> Option<PacketReceipt> {
+        if let Some(receipt) = self.poll_packet_fec(now, exts, twcc, buf) {
+            return Some(receipt);
+        }
+
         let mid = self.mid;
         let rid = self.rid;
         let ssrc_rtx = self.rtx;
 
-        let (next, is_padding) = if let Some(next) = self.poll_packet_resend(now) {
-            (next, false)
+        let (next, is_padding, is_rtx) = if let Some(next) = self.poll_packet_resend(now) {
+            (next, false, true)
         } else if let Some(next) = self.poll_packet_regular(now) {
-            (next, false)
+            (next, false, false)
         } else if let Some(next) = self.poll_packet_padding(now) {
-            (next, true)
+            (next, true, false)
         } else {
             return None;
         };
@@ -409,11 +541,10 @@ impl StreamTx {
                 let mut header = header_ref.clone();
 
                 // Update clone of header (to not change the cached value).
-                header.payload_type = pt_rtx;
-                header.ssrc = ssrc_rtx.expect("Should have RTX SSRC for resends");
-                header.sequence_number = *next.seq_no as u16;
+                let ssrc_rtx = ssrc_rtx.expect("Should have RTX SSRC for resends");
+                rtx_header(&mut header, ssrc_rtx, pt_rtx, next.seq_no);
 
-                header.ext_vals.rid = None;
+                // Blank packets have no rid of their own.
                 header.ext_vals.rid_repair = rid;
 
                 header
@@ -422,9 +553,19 @@ impl StreamTx {
 
         // These need to match `Extension::is_supported()` so we are sending what we are
         // declaring we support.
+        //
+        // The transport-wide sequence number is shared across all SSRCs and is set
+        // for media, RTX and padding alike.
+        let twcc_seq = twcc.next_seq();
         header.ext_vals.abs_send_time = Some(now);
-        header.ext_vals.transport_cc = Some(*twcc as u16);
-        *twcc += 1;
+        header.ext_vals.transport_cc = Some(twcc_seq.as_u16());
+
+        // The pacer exit is the last of the video timing deltas we can fill in.
+        if let (Some(timing), Some(wallclock)) =
+            (&mut header.ext_vals.video_timing, next.pkt.wallclock)
+        {
+            timing.set_pacer_exit(wallclock, now);
+        }
 
         buf.resize(DATAGRAM_MAX_PACKET_SIZE, 0);
 
@@ -448,13 +589,17 @@ impl StreamTx {
                 let body_len = pkt.payload.len();
                 body_out[..body_len].copy_from_slice(&pkt.payload);
 
-                // pad for SRTP
-                let pad_len = RtpHeader::pad_packet(
-                    &mut buf[..],
-                    header_len,
-                    body_len + original_seq_len,
-                    SRTP_BLOCK_SIZE,
-                );
+                // pad for SRTP, unless the payload already is padding.
+                let pad_len = if header.has_padding {
+                    0
+                } else {
+                    RtpHeader::pad_packet(
+                        &mut buf[..],
+                        header_len,
+                        body_len + original_seq_len,
+                        SRTP_BLOCK_SIZE,
+                    )
+                };
 
                 body_len + original_seq_len + pad_len
             }
@@ -470,6 +615,9 @@ impl StreamTx {
                     return None;
                 }
 
+                // The P bit is set in buf, this makes the receipt header agree.
+                header.has_padding = true;
+
                 len
             }
         };
@@ -509,25 +657,90 @@ impl StreamTx {
             self.clock_rate = set_cr;
         }
 
+        // Protect regular media, not padding written by the user.
+        if let Some(flexfec) = &mut self.flexfec {
+            if pop_send_queue && !header.has_padding {
+                flexfec.add_media(now, buf);
+            }
+        }
+
         if pop_send_queue {
             // poll_packet_regular leaves the packet in the head of the send_queue
             let pkt = self
                 .send_queue
                 .pop(now)
                 .expect("head of send_queue to be there");
-            if pkt.nackable {
-                self.rtx_cache.cache_sent_packet(pkt, now);
+            if let Some(rtx_cache) = &mut self.rtx_cache {
+                if pkt.nackable {
+                    rtx_cache.cache_sent_packet(pkt, now);
+                }
             }
         }
 
+        // Padding-only packets written by the user are also padding.
+        let is_padding = is_padding || header.has_padding;
+
         Some(PacketReceipt {
             header,
             seq_no,
+            twcc_seq,
             is_padding,
+            is_rtx,
             payload_size: body_len,
         })
     }
 
+    fn poll_packet_fec(
+        &mut self,
+        now: Instant,
+        exts: &ExtensionMap,
+        twcc: &mut TwccSeqAllocator,
+        buf: &mut Vec<u8>,
+    ) -> Option<PacketReceipt> {
+        let flexfec = self.flexfec.as_mut()?;
+        let config = flexfec.config();
+        let (seq_no, fec) = flexfec.poll()?;
+
+        let mut header = RtpHeader {
+            sequence_number: *seq_no as u16,
+            payload_type: config.pt(),
+            timestamp: fec.time,
+            ssrc: config.ssrc(),
+            ..Default::default()
+        };
+
+        let twcc_seq = twcc.next_seq();
+        header.ext_vals.mid = Some(self.mid);
+        header.ext_vals.abs_send_time = Some(now);
+        header.ext_vals.transport_cc = Some(twcc_seq.as_u16());
+
+        buf.resize(DATAGRAM_MAX_PACKET_SIZE, 0);
+
+        let header_len = header.write_to(buf, exts);
+        header.header_len = header_len;
+
+        let body_len = fec.payload.len();
+        if header_len + body_len + SRTP_BLOCK_SIZE > buf.len() {
+            warn!("FlexFEC packet too large: {}", header_len + body_len);
+            return None;
+        }
+        buf[header_len..(header_len + body_len)].copy_from_slice(&fec.payload);
+
+        let pad_len = RtpHeader::pad_packet(&mut buf[..], header_len, body_len, SRTP_BLOCK_SIZE);
+        buf.truncate(header_len + body_len + pad_len);
+
+        self.last_used = now;
+
+        Some(PacketReceipt {
+            header,
+            seq_no,
+            twcc_seq,
+            is_padding: false,
+            is_rtx: false,
+            payload_size: body_len + pad_len,
+        })
+    }
+
     fn rtx_ratio_downsampled(&mut self, now: Instant) -> f32 {
         let (value, ts) = self.rtx_ratio;
         if now - ts < Duration::from_millis(50) {
@@ -556,7 +769,10 @@ impl StreamTx {
         let seq_no = loop {
             let resend = self.resends.pop_front()?;
 
-            let pkt = self.rtx_cache.get_cached_packet_by_seq_no(resend.seq_no);
+            let pkt = self
+                .rtx_cache
+                .as_mut()?
+                .get_cached_packet_by_seq_no(resend.seq_no);
 
             // The seq_no could simply be too old to exist in the buffer, in which
             // case we will not do a resend.
@@ -572,7 +788,11 @@ impl StreamTx {
         };
 
         // Borrow checker gymnastics.
-        let pkt = self.rtx_cache.get_cached_packet_by_seq_no(seq_no).unwrap();
+        let pkt = self
+            .rtx_cache
+            .as_mut()?
+            .get_cached_packet_by_seq_no(seq_no)
+            .unwrap();
 
         let len = pkt.payload.len() as u64;
         self.stats.update_packet_counts(len, true);
@@ -623,12 +843,17 @@ impl StreamTx {
         #[allow(clippy::unnecessary_operation)]
         'outer: {
             if self.padding > MIN_SPURIOUS_PADDING_SIZE {
-                // Find a historic packet that is smaller than this max size. The max size
-                // is a headroom since we can accept slightly larger padding than asked for.
-                let max_size = (self.padding * 2).min(DATAGRAM_MTU_WARN - MAX_RTP_OVERHEAD);
+                // Find a historic packet that fits in the remaining padding budget. Resending
+                // real payload is better than blank padding since the receiver can use it.
+                let max_size = self.padding.min(DATAGRAM_MTU_WARN - MAX_RTP_OVERHEAD);
+
+                let pkt = self
+                    .rtx_cache
+                    .as_mut()
+                    .and_then(|c| c.get_cached_packet_smaller_than(max_size));
 
-                let Some(pkt) = self.rtx_cache.get_cached_packet_smaller_than(max_size) else {
-                    // Couldn't find spurious packet, try a blank packet instead.
+                let Some(pkt) = pkt else {
+                    // Cache is empty, or all packets are too large. Use a blank packet instead.
                     break 'outer;
                 };
 
@@ -666,12 +891,12 @@ impl StreamTx {
         })
     }
 
-    pub(crate) fn sender_report_at(&self) -> Instant {
+    pub(crate) fn sender_report_at(&self, interval: Option<Duration>) -> Instant {
         let Some(kind) = self.kind else {
             // First handle_timeout sets the kind. No sender report until then.
             return not_happening();
         };
-        self.last_sender_report + rr_interval(kind.is_audio())
+        self.last_sender_report + rr_interval(kind.is_audio(), interval)
     }
 
     pub(crate) fn poll_keyframe_request(&mut self) -> Option<KeyframeRequestKind> {
@@ -685,7 +910,10 @@ impl StreamTx {
     pub(crate) fn handle_rtcp(&mut self, now: Instant, fb: RtcpFb) {
         use RtcpFb::*;
         match fb {
-            ReceptionReport(r) => self.stats.update_with_rr(now, r),
+            ReceptionReport(r) => {
+                self.set_flexfec_loss(r.fraction_lost as f32 / u8::MAX as f32);
+                self.stats.update_with_rr(now, r);
+            }
             Nack(_, list) => {
                 self.stats.increase_nacks();
                 let entries = list.into_iter();
@@ -714,12 +942,13 @@ impl StreamTx {
     ) -> Option<()> {
         // Turning NackEntry into SeqNo we need to know a SeqNo "close by" to lengthen the 16 bit
         // sequence number into the 64 bit we have in SeqNo.
-        let seq_no = self.rtx_cache.last_cached_seq_no()?;
+        let rtx_cache = self.rtx_cache.as_mut()?;
+        let seq_no = rtx_cache.last_cached_seq_no()?;
         let iter = entries.flat_map(|n| n.into_iter(seq_no));
 
         // Schedule all resends. They will be handled on next poll_packet
         for seq_no in iter {
-            let Some(packet) = self.rtx_cache.get_cached_packet_by_seq_no(seq_no) else {
+            let Some(packet) = rtx_cache.get_cached_packet_by_seq_no(seq_no) else {
                 // Packet was not available in RTX cache, it has probably expired.
                 continue;
             };
@@ -735,20 +964,21 @@ impl StreamTx {
         Some(())
     }
 
-    pub(crate) fn need_sr(&self, now: Instant) -> bool {
-        now >= self.sender_report_at()
+    pub(crate) fn need_sr(&self, now: Instant, interval: Option<Duration>) -> bool {
+        now >= self.sender_report_at(interval)
     }
 
     pub(crate) fn create_sr_and_update(&mut self, now: Instant, feedback: &mut VecDeque<Rtcp>) {
+        // Without any media, the SRs go on from the first one, to be consistent.
+        if self.rtp_and_wallclock.is_none() {
+            self.sr_anchor.get_or_insert((0, now));
+        }
+
         let sr = self.create_sender_report(now);
 
         trace!("Created feedback SR: {:?}", sr);
         feedback.push_back(Rtcp::SenderReport(sr));
 
-        if let Some(ds) = self.create_sdes() {
-            feedback.push_back(Rtcp::SourceDescription(ds));
-        }
-
         // Update timestamp to move time when next is created.
         self.last_sender_report = now;
     }
@@ -760,23 +990,6 @@ impl StreamTx {
         }
     }
 
-    fn create_sdes(&self) -> Option<Descriptions> {
-        // CNAME is set on first handle_timeout. No SDES before that.
-        let cname = self.cname.as_ref()?;
-        let mut s = Sdes {
-            ssrc: self.ssrc,
-            values: ReportList::new(),
-        };
-        s.values.push((SdesType::CNAME, cname.to_string()));
-
-        let mut d = Descriptions {
-            reports: Box::new(ReportList::new()),
-        };
-        d.reports.push(s);
-
-        Some(d)
-    }
-
     fn sender_info(&self, now: Instant) -> SenderInfo {
         let rtp_time = self.current_rtp_time(now).unwrap_or(MediaTime::ZERO);
 
@@ -792,24 +1005,23 @@ impl StreamTx {
     fn current_rtp_time(&self, now: Instant) -> Option<MediaTime> {
         // This is the RTP time and the wallclock from the last written media.
         // We use that as an offset to current time (now), to calculate the
-        // current RTP time.
-        let (t_u32, w) = self.rtp_and_wallclock?;
+        // current RTP time. This keeps the RTP time moving while the stream
+        // is paused.
+        let (t_u32, w) = self.rtp_and_wallclock.or(self.sr_anchor)?;
 
         let clock_rate = self.clock_rate?;
-        let t = MediaTime::new(t_u32 as u64, clock_rate);
+        let ticks = |d: Duration| (d.as_secs_f64() * clock_rate.get() as f64).round() as u64;
 
-        // Wallclock needs to be in the past.
-        if w > now {
+        // The RTP time wraps like in the packets.
+        let t = if w <= now {
+            t_u32.wrapping_add(ticks(now - w) as u32)
+        } else {
             let delta = w - now;
             debug!("write_rtp wallclock is in the future: {:?}", delta);
-            return None;
-        }
-        let offset = now - w;
-
-        // This might be in the wrong base.
-        let rtp_time = t + offset.into();
+            t_u32.wrapping_sub(ticks(delta) as u32)
+        };
 
-        Some(rtp_time.rebase(clock_rate))
+        Some(MediaTime::new(t as u64, clock_rate))
     }
 
     pub(crate) fn next_seq_no(&mut self) -> SeqNo {
@@ -818,7 +1030,7 @@ impl StreamTx {
 
     pub(crate) fn last_packet(&self) -> Option<&[u8]> {
         if self.send_queue.is_empty() {
-            self.rtx_cache.last_packet()
+            self.rtx_cache.as_ref().and_then(|c| c.last_packet())
         } else {
             self.send_queue.last().map(|q| q.payload.as_ref())
         }
@@ -826,6 +1038,40 @@ impl StreamTx {
 
     pub(crate) fn visit_stats(&mut self, snapshot: &mut StatsSnapshot, now: Instant) {
         self.stats.fill(snapshot, self.mid, self.rid, now);
+
+        let egress = snapshot.egress_ssrc.entry(self.ssrc).or_default();
+        egress.packets = self.stats.packets;
+        egress.nacks_received = self.stats.nacks;
+        egress.plis_received = self.stats.plis;
+    }
+
+    /// Counters for [`Rtc::stats()`][crate::Rtc::stats]. The rate is filled in by the session.
+    pub(crate) fn outbound_stats(&self, kind: MediaKind) -> OutboundRtpStats {
+        let s = &self.stats;
+
+        OutboundRtpStats {
+            ssrc: self.ssrc,
+            rtx_ssrc: self.rtx,
+            kind,
+            mid: self.mid,
+            rid: self.rid,
+            bytes_sent: s.bytes,
+            packets_sent: s.packets,
+            retransmitted_bytes_sent: Some(s.bytes_resent),
+            retransmitted_packets_sent: Some(s.packets_resent),
+            bitrate: Bitrate::ZERO,
+            nack_count: s.nacks,
+            pli_count: s.plis,
+            fir_count: s.firs,
+            fraction_lost: s
+                .last_report
+                .map(|r| r.fraction_lost as f32 / u8::MAX as f32),
+            // The RR field is a signed 24 bit number.
+            packets_lost: s
+                .last_report
+                .map(|r| (((r.packets_lost << 8) as i32) >> 8) as i64),
+            round_trip_time: s.rtt.map(|ms| ms as f64 / 1000.0),
+        }
     }
 
     pub(crate) fn queue_state(&mut self, now: Instant) -> QueueState {
@@ -848,6 +1094,10 @@ impl StreamTx {
             snapshot.merge(&snapshot_padding);
         }
 
+        if let Some(snapshot_fec) = self.queue_state_fec(now) {
+            snapshot.merge(&snapshot_fec);
+        }
+
         QueueState {
             mid: self.mid,
             unpaced,
@@ -882,6 +1132,31 @@ impl StreamTx {
         Some(snapshot)
     }
 
+    fn queue_state_fec(&self, now: Instant) -> Option<QueueSnapshot> {
+        let queue = self.flexfec.as_ref()?.queue();
+        if queue.is_empty() {
+            return None;
+        }
+
+        let mut snapshot = queue
+            .iter()
+            .fold(QueueSnapshot::default(), |mut snapshot, f| {
+                snapshot.total_queue_time_origin += now.duration_since(f.queued_at);
+                snapshot.size += f.payload.len();
+                snapshot.packet_count += 1;
+                snapshot.first_unsent = snapshot
+                    .first_unsent
+                    .map(|i| i.min(f.queued_at))
+                    .or(Some(f.queued_at));
+
+                snapshot
+            });
+        snapshot.created_at = now;
+        snapshot.update_priority(QueuePriority::Media);
+
+        Some(snapshot)
+    }
+
     fn queue_state_padding(&self, now: Instant) -> Option<QueueSnapshot> {
         if self.padding == 0 {
             return None;
@@ -933,7 +1208,19 @@ impl StreamTx {
     fn on_first_timeout(&mut self, media: &Media, config: &CodecConfig) {
         // Always set on first timeout.
         self.kind = Some(media.kind());
-        self.cname = Some(media.cname().to_string());
+
+        // The clock rate for SRs before the first packet, which then sets it.
+        if self.clock_rate.is_none() {
+            let negotiated = config
+                .all_for_kind(media.kind())
+                .find(|p| media.remote_pts().contains(&p.pt()));
+
+            self.clock_rate = Some(match negotiated {
+                Some(p) => p.spec().clock_rate,
+                None if media.kind().is_audio() => Frequency::FORTY_EIGHT_KHZ,
+                None => Frequency::NINETY_KHZ,
+            });
+        }
 
         // Set on first timeout, if not set already by configuration.
         if self.unpaced.is_none() {
@@ -961,9 +1248,14 @@ impl StreamTx {
 
     pub(crate) fn reset_buffers(&mut self) {
         self.send_queue.clear();
-        self.rtx_cache.clear();
+        if let Some(rtx_cache) = &mut self.rtx_cache {
+            rtx_cache.clear();
+        }
         self.resends.clear();
         self.padding = 0;
+        if let Some(flexfec) = &mut self.flexfec {
+            flexfec.clear();
+        }
     }
 }
 
@@ -993,6 +1285,7 @@ impl StreamTxStats {
         let ntp_time = now.to_ntp_duration();
         let rtt = calculate_rtt_ms(ntp_time, r.last_sr_delay, r.last_sr_time);
         self.rtt = rtt;
+        self.last_report = Some(r);
 
         let ext_seq = {
             let prev = self.losses.last().map(|s| s.0).unwrap_or(r.max_seq as u64);
@@ -1075,3 +1368,286 @@ struct Resend {
     queued_at: Instant,
     payload_size: usize,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::format::{Codec, CodecSpec, FormatParams};
+
+    use super::*;
+
+    const BUDGET: usize = 10_000;
+
+    fn setup(cached_sizes: &[usize]) -> (StreamTx, Vec<PayloadParams>, Instant) {
+        let now = Instant::now();
+
+        let mut stream = StreamTx::new(1.into(), Some(2.into()), Mid::from("0"), None);
+        stream.pt_for_padding = Some(96.into());
+
+        for (i, size) in cached_sizes.iter().enumerate() {
+            let pkt = RtpPacket {
+                seq_no: (i as u64).into(),
+                time: MediaTime::from_90khz(0),
+                header: RtpHeader {
+                    payload_type: 96.into(),
+                    sequence_number: i as u16,
+                    ssrc: 1.into(),
+                    ..Default::default()
+                },
+                payload: vec![1; *size],
+                nackable: true,
+                last_sender_info: None,
+                recovered: false,
+                timestamp: now,
+                wallclock: None,
+            };
+            stream
+                .rtx_cache
+                .as_mut()
+                .unwrap()
+                .cache_sent_packet(pkt, now);
+        }
+
+        let params = vec![PayloadParams::new(
+            96.into(),
+            Some(97.into()),
+            CodecSpec {
+                codec: Codec::Vp8,
+                clock_rate: Frequency::NINETY_KHZ,
+                channels: None,
+                format: FormatParams::default(),
+            },
+        )];
+
+        (stream, params, now)
+    }
+
+    fn drain_padding(
+        stream: &mut StreamTx,
+        params: &[PayloadParams],
+        now: Instant,
+    ) -> Vec<PacketReceipt> {
+        let exts = ExtensionMap::standard();
+        let mut twcc = TwccSeqAllocator::new();
+        let mut buf = vec![];
+
+        let mut receipts = vec![];
+        while let Some(receipt) = stream.poll_packet(now, &exts, &mut twcc, params, &mut buf) {
+            receipts.push(receipt);
+        }
+        receipts
+    }
+
+    fn assert_budget_filled(receipts: &[PacketReceipt]) {
+        let total: usize = receipts.iter().map(|r| r.payload_size).sum();
+        let tolerance = DATAGRAM_MTU_WARN;
+        assert!(
+            total + tolerance >= BUDGET && total <= BUDGET + tolerance,
+            "total: {total}"
+        );
+    }
+
+    #[test]
+    fn padding_from_rtx_cache() {
+        let (mut stream, params, now) = setup(&[300, 1000, 600]);
+
+        stream.generate_padding(BUDGET);
+        let receipts = drain_padding(&mut stream, &params, now);
+
+        assert_budget_filled(&receipts);
+
+        for r in &receipts {
+            assert!(r.is_padding);
+            assert_eq!(r.header.ssrc, 2.into());
+            assert_eq!(*r.header.payload_type, 97);
+            assert!(r.header.ext_vals.transport_cc.is_some());
+        }
+
+        // Real payloads were resent rather than blank padding.
+        assert!(receipts
+            .iter()
+            .any(|r| r.payload_size > MAX_BLANK_PADDING_PAYLOAD_SIZE));
+
+        // Each padding packet gets a unique transport-wide seq.
+        let mut twcc: Vec<_> = receipts.iter().map(|r| r.twcc_seq).collect();
+        twcc.dedup();
+        assert_eq!(twcc.len(), receipts.len());
+    }
+
+    #[test]
+    fn padding_blank_when_cache_empty() {
+        let (mut stream, params, now) = setup(&[]);
+
+        stream.generate_padding(BUDGET);
+        let receipts = drain_padding(&mut stream, &params, now);
+
+        assert_budget_filled(&receipts);
+
+        for r in &receipts {
+            assert!(r.is_padding);
+            assert!(r.header.has_padding);
+            assert!(r.payload_size <= 255);
+        }
+    }
+
+    #[test]
+    fn padding_blank_when_cached_too_large() {
+        let (mut stream, params, now) = setup(&[1100]);
+
+        stream.generate_padding(500);
+        let receipts = drain_padding(&mut stream, &params, now);
+
+        assert!(!receipts.is_empty());
+        for r in &receipts {
+            assert!(r.header.has_padding, "{:?}", r.header);
+        }
+    }
+
+    #[test]
+    fn rtp_header_size_matches_sent() {
+        let (mut stream, params, now) = setup(&[]);
+        let exts = ExtensionMap::standard();
+        let mut twcc = TwccSeqAllocator::new();
+        let mut buf = vec![];
+
+        let ext_vals = ExtensionValues {
+            audio_level: Some(-30),
+            voice_activity: Some(true),
+            ..Default::default()
+        };
+
+        let size = stream.rtp_header_size(now, 96.into(), &ext_vals, &exts);
+
+        stream
+            .write_rtp(
+                96.into(),
+                10.into(),
+                1234,
+                now,
+                true,
+                ext_vals,
+                true,
+                vec![1; 100],
+            )
+            .unwrap();
+        stream.send_queue.handle_timeout(now);
+
+        let receipt = stream
+            .poll_packet(now, &exts, &mut twcc, &params, &mut buf)
+            .unwrap();
+
+        // Room for the original sequence number of an RTX resend.
+        assert_eq!(size, receipt.header.header_len + 2);
+    }
+
+    #[test]
+    fn write_padding_only_packet() {
+        let (mut stream, params, now) = setup(&[]);
+        let exts = ExtensionMap::standard();
+        let mut twcc = TwccSeqAllocator::new();
+        let mut buf = vec![];
+
+        for (i, pad_len) in [0_u8, 1, 17, 255].into_iter().enumerate() {
+            let seq_no = (100 + i as u64).into();
+            stream
+                .write_padding(96.into(), seq_no, 1234, now, pad_len)
+                .unwrap();
+            stream.send_queue.handle_timeout(now);
+
+            let receipt = stream
+                .poll_packet(now, &exts, &mut twcc, &params, &mut buf)
+                .unwrap();
+            assert!(receipt.is_padding);
+
+            let header = RtpHeader::parse(&buf, &exts).unwrap();
+            assert!(header.has_padding);
+            assert_eq!(header.ssrc, 1.into());
+            assert_eq!(header.sequence_number, 100 + i as u16);
+            assert_eq!(header.ext_vals.mid, Some(Mid::from("0")));
+            assert!(header.ext_vals.transport_cc.is_some());
+
+            // N=0 can't be expressed, the count byte is itself padding.
+            let expected_len = pad_len.max(1) as usize;
+            let payload = &buf[header.header_len..];
+            assert_eq!(payload.len(), expected_len);
+            assert_eq!(payload[expected_len - 1] as usize, expected_len);
+            assert!(payload[..expected_len - 1].iter().all(|b| *b == 0));
+
+            let mut payload = payload.to_vec();
+            assert!(RtpHeader::unpad_payload(&mut payload));
+            assert!(payload.is_empty());
+        }
+    }
+
+    fn sr_rtp_time(stream: &mut StreamTx, now: Instant) -> u32 {
+        let mut feedback = VecDeque::new();
+        stream.create_sr_and_update(now, &mut feedback);
+        let Some(Rtcp::SenderReport(sr)) = feedback.pop_front() else {
+            panic!("expected SR");
+        };
+        sr.sender_info.rtp_time.numer() as u32
+    }
+
+    #[test]
+    fn sr_rtp_time_during_pause() {
+        let (mut stream, params, now) = setup(&[]);
+        let exts = ExtensionMap::standard();
+        let mut twcc = TwccSeqAllocator::new();
+        let mut buf = vec![];
+
+        // Close to wrapping around.
+        let time = u32::MAX - 100_000;
+        stream
+            .write_rtp(
+                96.into(),
+                0.into(),
+                time,
+                now,
+                true,
+                ExtensionValues::default(),
+                true,
+                vec![1; 100],
+            )
+            .unwrap();
+        stream.send_queue.handle_timeout(now);
+        stream
+            .poll_packet(now, &exts, &mut twcc, &params, &mut buf)
+            .unwrap();
+
+        assert_eq!(sr_rtp_time(&mut stream, now), time);
+
+        // The stream pauses for 10s, the SRs keep moving at 90kHz.
+        let later = now + Duration::from_secs(10);
+        assert_eq!(sr_rtp_time(&mut stream, later), time.wrapping_add(900_000));
+
+        // A wallclock ahead of now goes backwards from it.
+        let ahead = later + Duration::from_millis(100);
+        stream
+            .write_rtp(
+                96.into(),
+                1.into(),
+                1_000_000,
+                ahead,
+                true,
+                ExtensionValues::default(),
+                true,
+                vec![1; 100],
+            )
+            .unwrap();
+        assert_eq!(sr_rtp_time(&mut stream, later), 1_000_000 - 9_000);
+    }
+
+    #[test]
+    fn sr_rtp_time_before_any_packet() {
+        let now = Instant::now();
+        let mut stream = StreamTx::new(1.into(), None, Mid::from("0"), None);
+
+        let media = Media::default();
+        let config = CodecConfig::new_with_defaults();
+        stream.handle_timeout(now, || (&media, &config));
+
+        // The first SR sets the RTP time, the next goes on from it at the video clock rate.
+        let first = sr_rtp_time(&mut stream, now);
+        let next = sr_rtp_time(&mut stream, now + Duration::from_secs(5));
+        assert_eq!(next, first.wrapping_add(450_000));
+    }
+}
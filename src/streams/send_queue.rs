@@ -214,7 +214,9 @@ mod test {
             payload: vec![],
             timestamp: Instant::now(),
             last_sender_info: None,
+            recovered: false,
             nackable: true,
+            wallclock: None,
         });
 
         assert!(queue.peek().is_none());
@@ -249,7 +251,9 @@ mod test {
             payload: vec![42, 42],
             timestamp: start,
             last_sender_info: None,
+            recovered: false,
             nackable: true,
+            wallclock: None,
         });
 
         queue.handle_timeout(start);
@@ -0,0 +1,296 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use str0m::bwe::{Bitrate, BweConfig, BweEstimate, BweKind, BweResetReason};
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::net::{Protocol, Receive, RouteChangeReason};
+use str0m::{Candidate, Event, Input, Output, Rtc, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, progress_with_loss, TestRtc};
+
+fn run(loss: f32) -> Result<(TestRtc, TestRtc), RtcError> {
+    let (mut l, mut r, mid) = connect(BweConfig::new(Bitrate::kbps(300)))?;
+    send(&mut l, &mut r, mid, loss, Duration::from_secs(10))?;
+    Ok((l, r))
+}
+
+fn connect(config: BweConfig) -> Result<(TestRtc, TestRtc, Mid), RtcError> {
+    let l_rtc = Rtc::builder().set_bwe_config(Some(config)).build();
+    let r_rtc = Rtc::builder().build();
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), l_rtc);
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), r_rtc);
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    l.bwe()
+        .set_bitrate_bounds(mid, Bitrate::kbps(100), Bitrate::kbps(1500));
+
+    Ok((l, r, mid))
+}
+
+fn send(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    mid: Mid,
+    loss: f32,
+    duration: Duration,
+) -> Result<(), RtcError> {
+    let pt = l.params_vp8().pt();
+    let end = l.duration() + duration;
+    let data = [1_u8; 1000];
+
+    loop {
+        // Only write when the next progress polls l.
+        if l.last < r.last {
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid).unwrap().write(pt, wallclock, time, data)?;
+        }
+
+        if loss > 0.0 {
+            progress_with_loss(l, r, loss)?;
+        } else {
+            progress(l, r)?;
+        }
+
+        if l.duration() > end {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn estimates(l: &TestRtc) -> Vec<(Instant, BweEstimate)> {
+    l.events
+        .iter()
+        .filter_map(|(t, e)| match e {
+            Event::EgressBitrateEstimate(BweKind::Twcc(v)) => Some((*t, *v)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+pub fn bwe_estimate_events() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, _r) = run(0.0)?;
+
+    let estimates = estimates(&l);
+    assert!(!estimates.is_empty(), "Should have estimates");
+
+    // Stable estimates are still emitted at a max interval.
+    for w in estimates.windows(2) {
+        assert!(w[1].0 - w[0].0 <= Duration::from_millis(1100));
+    }
+
+    let (_, last) = estimates.last().unwrap();
+    assert!(last.acked.is_some());
+    assert!(!last.loss_limited);
+
+    // The estimate can also be polled.
+    let polled = l.bwe().estimate().expect("estimate");
+    assert!(!polled.loss_limited);
+    assert!(polled.target > Bitrate::ZERO);
+
+    Ok(())
+}
+
+#[test]
+pub fn bwe_loss_limited() -> Result<(), RtcError> {
+    init_log();
+
+    let (l, _r) = run(0.3)?;
+
+    let estimates = estimates(&l);
+    assert!(
+        estimates.iter().any(|(_, e)| e.loss_limited),
+        "Should be limited by loss"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn bwe_clamped() -> Result<(), RtcError> {
+    init_log();
+
+    let config = BweConfig::new(Bitrate::kbps(300))
+        .set_min_bitrate(Bitrate::kbps(250))
+        .set_max_bitrate(Bitrate::kbps(600));
+
+    let (mut l, mut r, mid) = connect(config)?;
+    send(&mut l, &mut r, mid, 0.0, Duration::from_secs(5))?;
+    send(&mut l, &mut r, mid, 0.3, Duration::from_secs(5))?;
+
+    let estimates = estimates(&l);
+    assert!(!estimates.is_empty(), "Should have estimates");
+
+    let targets = estimates.iter().map(|(_, e)| e.target);
+    assert!(targets.clone().any(|t| t == Bitrate::kbps(600)));
+    for t in targets {
+        assert!(t >= Bitrate::kbps(250) && t <= Bitrate::kbps(600), "{}", t);
+    }
+
+    // Tightened at runtime.
+    l.bwe()
+        .set_config(config.set_max_bitrate(Bitrate::kbps(400)));
+    let polled = l.bwe().estimate().unwrap();
+    assert!(polled.target <= Bitrate::kbps(400));
+
+    Ok(())
+}
+
+#[test]
+pub fn bwe_reset() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = connect(BweConfig::new(Bitrate::kbps(300)))?;
+    send(&mut l, &mut r, mid, 0.0, Duration::from_secs(5))?;
+
+    let before = estimates(&l).len();
+    assert!(l.bwe().estimate().unwrap().target > Bitrate::kbps(300));
+
+    let reason = BweResetReason::Application(Bitrate::kbps(200));
+    l.bwe().reset(reason);
+    send(&mut l, &mut r, mid, 0.0, Duration::from_secs(1))?;
+
+    let after = estimates(&l).split_off(before);
+    let (_, first) = after[0];
+    assert_eq!(first.reset, Some(reason));
+    assert_eq!(first.target, Bitrate::kbps(200));
+    assert!(after[1..].iter().all(|(_, e)| e.reset.is_none()));
+
+    Ok(())
+}
+
+#[test]
+pub fn bwe_route_change() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = connect(BweConfig::new(Bitrate::kbps(300)))?;
+    send(&mut l, &mut r, mid, 0.0, Duration::from_secs(3))?;
+
+    // The old path goes dead, everything L sends on it is lost.
+    let dead = (Ipv4Addr::new(2, 2, 2, 2), 2000).into();
+    let pt = l.params_vp8().pt();
+    let data = [1_u8; 1000];
+
+    let write_and_progress = |l: &mut TestRtc, r: &mut TestRtc| -> Result<(), RtcError> {
+        if l.last < r.last {
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid).unwrap().write(pt, wallclock, time, data)?;
+        }
+        progress_dropping(l, r, dead)
+    };
+
+    let until = l.duration() + Duration::from_millis(300);
+    while l.duration() < until {
+        write_and_progress(&mut l, &mut r)?;
+    }
+
+    // A new, better, path turns up.
+    let l6 = Candidate::host((Ipv6Addr::new(1, 1, 1, 1, 1, 1, 1, 1), 1000).into(), "udp")?;
+    let r6 = Candidate::host((Ipv6Addr::new(2, 2, 2, 2, 2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(l6.clone());
+    r.add_local_candidate(r6.clone());
+    l.add_remote_candidate(r6);
+    r.add_remote_candidate(l6);
+
+    let until = l.duration() + Duration::from_secs(3);
+    while l.duration() < until {
+        write_and_progress(&mut l, &mut r)?;
+    }
+
+    let (changed_at, change) = l
+        .events
+        .iter()
+        .find_map(|(t, e)| match e {
+            Event::NetworkRouteChange(c) => Some((*t, *c)),
+            _ => None,
+        })
+        .expect("NetworkRouteChange");
+
+    assert_eq!(change.proto, Protocol::Udp);
+    assert_eq!(
+        change.new_remote,
+        (Ipv6Addr::new(2, 2, 2, 2, 2, 2, 2, 2), 2000).into()
+    );
+    assert_eq!(change.reason, RouteChangeReason::CandidatePair);
+
+    let after: Vec<_> = estimates(&l)
+        .into_iter()
+        .filter(|(t, _)| *t >= changed_at)
+        .collect();
+
+    assert_eq!(after[0].1.reset, Some(BweResetReason::RouteChange));
+
+    // What was lost on the dead path is not congestion on the new one.
+    assert!(after.len() > 1);
+    assert!(after.iter().all(|(_, e)| !e.loss_limited), "{:?}", after);
+
+    Ok(())
+}
+
+/// Like `progress`, but drops whatever L sends to `dead`.
+fn progress_dropping(l: &mut TestRtc, r: &mut TestRtc, dead: SocketAddr) -> Result<(), RtcError> {
+    let from_l = l.last < r.last;
+    let (f, t) = if from_l { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                if from_l && v.destination == dead {
+                    continue;
+                }
+                let data = v.contents;
+                let input = Input::Receive(
+                    f.last,
+                    Receive {
+                        proto: v.proto,
+                        source: v.source,
+                        destination: v.destination,
+                        contents: (&*data).try_into()?,
+                    },
+                );
+                t.span.in_scope(|| t.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
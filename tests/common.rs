@@ -11,6 +11,7 @@ use str0m::format::Codec;
 use str0m::format::PayloadParams;
 use str0m::net::Protocol;
 use str0m::net::Receive;
+use str0m::net::Transmit;
 use str0m::rtp::ExtensionMap;
 use str0m::rtp::RtpHeader;
 use str0m::Candidate;
@@ -154,6 +155,45 @@ pub fn progress_with_loss(l: &mut TestRtc, r: &mut TestRtc, loss: f32) -> Result
     Ok(())
 }
 
+/// Like [`progress`], but every datagram goes through `deliver` first.
+///
+/// The closure is told whether the datagram comes from `l`, and can change it (to rewrite
+/// addresses), or return `false` to drop it.
+pub fn progress_with(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    mut deliver: impl FnMut(bool, &mut Transmit) -> bool,
+) -> Result<(), RtcError> {
+    let from_l = l.last < r.last;
+    let (f, t) = if from_l { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(mut v) => {
+                if !deliver(from_l, &mut v) {
+                    continue;
+                }
+                let receive = Receive::new(v.proto, v.source, v.destination, &v.contents)?;
+                let input = Input::Receive(f.last, receive);
+                t.span.in_scope(|| t.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Perform a change to the session via an offer and answer.
 ///
 /// The closure is passed the [`SdpApi`] for the offer side to make any changes, these are then
@@ -231,6 +271,10 @@ pub fn connect_l_r() -> (TestRtc, TestRtc) {
         .set_reordering_size_audio(0)
         .build();
 
+    connect_l_r_with_rtc(rtc1, rtc2)
+}
+
+pub fn connect_l_r_with_rtc(rtc1: Rtc, rtc2: Rtc) -> (TestRtc, TestRtc) {
     let mut l = TestRtc::new_with_rtc(info_span!("L"), rtc1);
     let mut r = TestRtc::new_with_rtc(info_span!("R"), rtc2);
 
@@ -161,7 +161,7 @@ impl Server {
         l.rtc.sdp_api().accept_answer(pending, answer)?;
 
         loop {
-            if l.is_connected() || r.is_connected() {
+            if l.is_connected() && r.is_connected() {
                 break;
             }
             progress(&mut l, &mut r)?;
@@ -0,0 +1,227 @@
+use std::net::Ipv4Addr;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use str0m::channel::{ChannelConfig, ChannelId, Reliability};
+use str0m::{Candidate, Event, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, progress_with, TestRtc};
+
+#[test]
+pub fn data_channel_max_retransmits_zero() -> Result<(), RtcError> {
+    init_log();
+
+    let run = run_outage(
+        unordered(Reliability::MaxRetransmits { retransmits: 0 }),
+        500,
+        4,
+    )?;
+
+    // Everything sent during the outage is lost, and nothing else.
+    let expected: Vec<_> = (0..run.sent.len() as u32)
+        .filter(|s| !run.in_outage(*s))
+        .collect();
+    assert!(expected.len() < run.sent.len());
+    assert_eq!(run.sorted(), expected);
+
+    Ok(())
+}
+
+#[test]
+pub fn data_channel_max_retransmits_one() -> Result<(), RtcError> {
+    init_log();
+
+    // A short outage is recovered from with a single retransmit.
+    let run = run_outage(
+        unordered(Reliability::MaxRetransmits { retransmits: 1 }),
+        100,
+        4,
+    )?;
+
+    assert_eq!(run.sorted(), run.all());
+
+    Ok(())
+}
+
+#[test]
+pub fn data_channel_max_packet_lifetime() -> Result<(), RtcError> {
+    init_log();
+
+    let config = unordered(Reliability::MaxPacketLifetime { lifetime: 200 });
+    let run = run_outage(config, 3000, 4)?;
+
+    let lost: Vec<_> = run
+        .all()
+        .into_iter()
+        .filter(|s| !run.received.contains(s))
+        .collect();
+    assert!(!lost.is_empty());
+    assert!(lost.iter().all(|s| run.in_outage(*s)), "{lost:?}");
+
+    Ok(())
+}
+
+#[test]
+pub fn data_channel_ordered() -> Result<(), RtcError> {
+    init_log();
+
+    let run = run_outage(ChannelConfig::default(), 500, 4)?;
+
+    assert_eq!(run.received, run.all());
+
+    Ok(())
+}
+
+#[test]
+pub fn data_channel_unordered() -> Result<(), RtcError> {
+    init_log();
+
+    let run = run_outage(unordered(Reliability::Reliable), 500, 4)?;
+
+    // Messages after the outage don't wait for the retransmits.
+    assert_ne!(run.received, run.all());
+    assert_eq!(run.sorted(), run.all());
+
+    Ok(())
+}
+
+#[test]
+pub fn data_channel_partial_fragmented() -> Result<(), RtcError> {
+    init_log();
+
+    // Large messages are never delivered in part.
+    let config = unordered(Reliability::MaxRetransmits { retransmits: 0 });
+    let run = run_outage(config, 500, 5000)?;
+
+    let expected: Vec<_> = (0..run.sent.len() as u32)
+        .filter(|s| !run.in_outage(*s) || run.received.contains(s))
+        .collect();
+    assert_eq!(run.sorted(), expected);
+
+    Ok(())
+}
+
+fn unordered(reliability: Reliability) -> ChannelConfig {
+    ChannelConfig {
+        ordered: false,
+        reliability,
+        ..Default::default()
+    }
+}
+
+struct OutageRun {
+    /// When each message was written, indexed by the sequence number it carries.
+    sent: Vec<Instant>,
+    /// When all datagrams from L to R were dropped.
+    outage: Range<Instant>,
+    /// The sequence numbers as they were received.
+    received: Vec<u32>,
+}
+
+impl OutageRun {
+    fn in_outage(&self, seq: u32) -> bool {
+        self.outage.contains(&self.sent[seq as usize])
+    }
+
+    fn all(&self) -> Vec<u32> {
+        (0..self.sent.len() as u32).collect()
+    }
+
+    fn sorted(&self) -> Vec<u32> {
+        let mut v = self.received.clone();
+        v.sort();
+        v
+    }
+}
+
+/// Write messages of `size` on a channel from L to R, with an outage of `outage_ms` after
+/// one second, and one second of messages after it.
+fn run_outage(config: ChannelConfig, outage_ms: u64, size: usize) -> Result<OutageRun, RtcError> {
+    let (mut l, mut r, cid) = setup(config)?;
+
+    let start = l.last;
+    let outage =
+        start + Duration::from_millis(1000)..start + Duration::from_millis(1000 + outage_ms);
+    let end = outage.end + Duration::from_millis(1000);
+
+    let mut sent = vec![];
+
+    while l.last < end {
+        let seq = sent.len() as u32;
+        l.channel(cid).unwrap().write(true, &payload(seq, size))?;
+        sent.push(l.last);
+
+        for _ in 0..2 {
+            // Application data records (23) from L.
+            let lost = outage.contains(&l.last);
+            progress_with(&mut l, &mut r, |from_l, v| {
+                !(lost && from_l && v.contents[0] == 23)
+            })?;
+        }
+    }
+
+    // Settle any retransmits.
+    for _ in 0..1000 {
+        progress(&mut l, &mut r)?;
+    }
+
+    let received = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::ChannelData(d) if d.id == cid => {
+                let seq = u32::from_be_bytes(d.data[..4].try_into().unwrap());
+                assert_eq!(d.data, payload(seq, size), "message {seq} is intact");
+                Some(seq)
+            }
+            _ => None,
+        })
+        .collect();
+
+    Ok(OutageRun {
+        sent,
+        outage,
+        received,
+    })
+}
+
+/// A message starting with the sequence number, and filled up with a pattern.
+fn payload(seq: u32, size: usize) -> Vec<u8> {
+    let mut data: Vec<u8> = (0..size).map(|i| (i as u32 ^ seq) as u8).collect();
+    data[..4].copy_from_slice(&seq.to_be_bytes());
+    data
+}
+
+fn setup(config: ChannelConfig) -> Result<(TestRtc, TestRtc, ChannelId), RtcError> {
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    let cid = change.add_channel_with_config(config);
+    let (offer, pending) = change.apply().unwrap();
+
+    let answer = r.rtc.sdp_api().accept_offer(offer)?;
+    l.rtc.sdp_api().accept_answer(pending, answer)?;
+
+    let open = |t: &TestRtc| {
+        t.events
+            .iter()
+            .any(|(_, e)| matches!(e, Event::ChannelOpen(_, _)))
+    };
+
+    while !open(&l) || !open(&r) {
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Channels never opened");
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    Ok((l, r, cid))
+}
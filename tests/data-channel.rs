@@ -1,7 +1,9 @@
 use std::net::Ipv4Addr;
 use std::time::Duration;
 
-use str0m::{Candidate, RtcError};
+use str0m::change::SdpAnswer;
+use str0m::channel::ChannelId;
+use str0m::{Candidate, Event, RtcError};
 use tracing::info_span;
 
 mod common;
@@ -55,3 +57,116 @@ pub fn data_channel() -> Result<(), RtcError> {
 
     Ok(())
 }
+
+#[test]
+pub fn data_channel_large_message() -> Result<(), RtcError> {
+    init_log();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    let cid = change.add_channel("Large".into());
+    let (offer, pending) = change.apply().unwrap();
+
+    let answer = r.rtc.sdp_api().accept_offer(offer)?;
+    l.rtc.sdp_api().accept_answer(pending, answer)?;
+
+    // R learns the channel from the DCEP open, and L opens on the ack.
+    let r_cid = loop {
+        if let (Some(id), Some(_)) = (opened(&r, "Large"), opened(&l, "Large")) {
+            break id;
+        }
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Channel never opened");
+        }
+        progress(&mut l, &mut r)?;
+    };
+    assert_eq!(opened(&l, "Large"), Some(cid));
+
+    // 256 kB, which is the max-message-size, with a pattern to catch reordered chunks.
+    let l_data: Vec<u8> = (0..262_144_u32).map(|i| (i % 251) as u8).collect();
+    let r_data: Vec<u8> = l_data.iter().rev().copied().collect();
+
+    l.channel(cid).unwrap().write(true, &l_data)?;
+    r.channel(r_cid).unwrap().write(true, &r_data)?;
+    l.channel(cid).unwrap().write(false, "after".as_bytes())?;
+
+    while received(&l).is_empty() || received(&r).len() < 2 {
+        if l.duration() > Duration::from_secs(30) {
+            panic!("Large messages not delivered");
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    // Each arrived whole, in order, and as binary or text as sent.
+    assert_eq!(
+        received(&r),
+        vec![(r_cid, true, l_data), (r_cid, false, b"after".to_vec())]
+    );
+    assert_eq!(received(&l), vec![(cid, true, r_data)]);
+
+    Ok(())
+}
+
+#[test]
+pub fn data_channel_remote_max_message_size() -> Result<(), RtcError> {
+    init_log();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    let cid = change.add_channel("Small".into());
+    let (offer, pending) = change.apply().unwrap();
+
+    // Without a=max-message-size, the remote takes 64 kB.
+    let answer = r.rtc.sdp_api().accept_offer(offer)?;
+    let munged: String = answer
+        .to_sdp_string()
+        .split_inclusive("\r\n")
+        .filter(|l| !l.starts_with("a=max-message-size"))
+        .collect();
+    let answer = SdpAnswer::from_sdp_string(&munged).unwrap();
+    l.rtc.sdp_api().accept_answer(pending, answer)?;
+
+    while opened(&l, "Small").is_none() {
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Channel never opened");
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let mut chan = l.channel(cid).unwrap();
+    assert!(chan.write(true, &[0; 65_537]).is_err());
+    assert_eq!(chan.write(true, &[0; 65_536])?, 65_536);
+
+    Ok(())
+}
+
+fn opened(t: &TestRtc, label: &str) -> Option<ChannelId> {
+    t.events.iter().find_map(|(_, e)| match e {
+        Event::ChannelOpen(id, l) if l == label => Some(*id),
+        _ => None,
+    })
+}
+
+fn received(t: &TestRtc) -> Vec<(ChannelId, bool, Vec<u8>)> {
+    t.events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::ChannelData(d) => Some((d.id, d.binary, d.data.clone())),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,69 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::DtlsCert;
+use str0m::error::DtlsError;
+use str0m::media::{Direction, MediaKind};
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, TestRtc};
+
+const CERT: &[u8] = include_bytes!("data/dtls-cert.der");
+const KEY: &[u8] = include_bytes!("data/dtls-key.der");
+const OTHER_KEY: &[u8] = include_bytes!("data/dtls-other-key.der");
+
+const FINGERPRINT: &str = "sha-256 E7:D9:8A:BC:67:09:DF:0E:91:0C:3C:FC:3F:45:3E:A7:\
+                           46:9C:0A:25:67:20:0B:42:64:F4:50:BE:FB:F6:08:00";
+
+#[test]
+pub fn dtls_cert_fixed_fingerprint() -> Result<(), RtcError> {
+    init_log();
+
+    let cert = DtlsCert::from_openssl_der(CERT, KEY)?;
+    assert_eq!(cert.fingerprint().to_string(), FINGERPRINT);
+
+    let config = RtcConfig::new().set_dtls_cert(cert);
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), config.build());
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    assert!(offer
+        .to_sdp_string()
+        .contains(&format!("a=fingerprint:{FINGERPRINT}\r\n")));
+
+    let answer = r.sdp_api().accept_offer(offer)?;
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    loop {
+        if r.events.iter().any(|(_, e)| matches!(e, Event::Connected)) {
+            break;
+        }
+        if r.duration() > Duration::from_secs(10) {
+            panic!("Failed to connect");
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    // R verified the certificate of L.
+    let fingerprint = r.direct_api().remote_dtls_fingerprint().unwrap();
+    assert_eq!(fingerprint.to_string(), FINGERPRINT);
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_cert_key_mismatch() {
+    let err = DtlsCert::from_openssl_der(CERT, OTHER_KEY).unwrap_err();
+    assert!(matches!(err, DtlsError::KeyMismatch));
+
+    assert!(DtlsCert::from_openssl_der(KEY, CERT).is_err());
+}
@@ -0,0 +1,196 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::SrtpProfile;
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::net::Receive;
+use str0m::{Candidate, DtlsState, Event, Input, Output, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, TestRtc};
+
+#[test]
+pub fn dtls_state_changes() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, _) = connect()?;
+
+    // A few more rounds for the last flight of the passive side.
+    for _ in 0..10 {
+        progress_recorded(&mut l, &mut r, &mut vec![])?;
+    }
+
+    // R answered a=setup:active, which makes R the DTLS client and L the server.
+    let (l_states, l_connected) = states(&l);
+    assert_eq!(
+        l_states,
+        [
+            "Started { active: false }",
+            "FlightReceived",
+            "FlightSent",
+            "FlightReceived",
+            "Connected",
+            "FlightSent",
+        ]
+    );
+
+    let (r_states, r_connected) = states(&r);
+    assert_eq!(
+        r_states,
+        [
+            "Started { active: true }",
+            "FlightSent",
+            "FlightReceived",
+            "FlightSent",
+            "FlightReceived",
+            "Connected",
+        ]
+    );
+
+    // Both agree on what was negotiated.
+    let (cipher, srtp_profile) = l_connected.unwrap();
+    assert!(!cipher.is_empty());
+    assert_eq!(r_connected, Some((cipher, srtp_profile)));
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_close_notify() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = connect()?;
+
+    let until = l.duration() + Duration::from_secs(1);
+    while l.duration() < until {
+        write_audio(&mut l, mid)?;
+        write_audio(&mut r, mid)?;
+        progress_recorded(&mut l, &mut r, &mut vec![])?;
+    }
+    assert!(r
+        .events
+        .iter()
+        .any(|(_, e)| matches!(e, Event::MediaData(_))));
+
+    l.rtc.close(None);
+
+    let mut sent = vec![];
+    for _ in 0..50 {
+        if r.is_alive() {
+            write_audio(&mut r, mid)?;
+        }
+        progress_recorded(&mut l, &mut r, &mut sent)?;
+    }
+
+    // L doesn't wait for the close_notify of R.
+    assert!(!l.is_alive());
+    assert!(!r.is_alive());
+
+    // Both sent a close_notify alert (21).
+    assert!(sent.contains(&(true, 21)));
+    assert!(sent.contains(&(false, 21)));
+
+    // R saw the close, and took no media after it.
+    let closed = r
+        .events
+        .iter()
+        .position(|(_, e)| matches!(e, Event::DtlsStateChange(DtlsState::Closed)))
+        .expect("DTLS closed at R");
+    assert!(!r.events[closed..]
+        .iter()
+        .any(|(_, e)| matches!(e, Event::MediaData(_))));
+
+    Ok(())
+}
+
+/// The DTLS state changes by name, and what the handshake negotiated.
+fn states(t: &TestRtc) -> (Vec<String>, Option<(String, SrtpProfile)>) {
+    let mut names = vec![];
+    let mut connected = None;
+
+    for (_, e) in &t.events {
+        match e {
+            Event::DtlsStateChange(DtlsState::Connected {
+                cipher,
+                srtp_profile,
+            }) => {
+                names.push("Connected".to_string());
+                connected = Some((cipher.clone(), *srtp_profile));
+            }
+            Event::DtlsStateChange(s) => names.push(format!("{s:?}")),
+            _ => {}
+        }
+    }
+
+    (names, connected)
+}
+
+fn write_audio(t: &mut TestRtc, mid: Mid) -> Result<(), RtcError> {
+    let pt = t.params_opus().pt();
+    let wallclock = t.start + t.duration();
+    let time = t.duration().into();
+    t.writer(mid)
+        .unwrap()
+        .write(pt, wallclock, time, vec![1_u8; 80])
+}
+
+fn connect() -> Result<(TestRtc, TestRtc, Mid), RtcError> {
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Audio, Direction::SendRecv, None, None)
+    });
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Failed to connect");
+        }
+        progress_recorded(&mut l, &mut r, &mut vec![])?;
+    }
+
+    Ok((l, r, mid))
+}
+
+/// Like `common::progress()`, recording the first byte of every datagram sent.
+fn progress_recorded(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    sent: &mut Vec<(bool, u8)>,
+) -> Result<(), RtcError> {
+    let from_l = l.last < r.last;
+    let (f, t) = if from_l { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                sent.push((from_l, v.contents[0]));
+                let receive = Receive::new(v.proto, v.source, v.destination, &v.contents)?;
+                let input = Input::Receive(f.last, receive);
+                t.span.in_scope(|| t.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::SdpAnswer;
+use str0m::error::DtlsError;
+use str0m::media::{Direction, MediaKind};
+use str0m::{Candidate, Event, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, TestRtc};
+
+#[test]
+pub fn dtls_fingerprint_mismatch() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = negotiate(|fingerprint| {
+        // Flip a bit in the digest.
+        let (head, tail) = fingerprint.split_at(fingerprint.len() - 1);
+        let last = if tail == "0" { "1" } else { "0" };
+        vec![format!("{head}{last}")]
+    })?;
+
+    let err = loop {
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Expected DTLS to fail");
+        }
+        if let Err(e) = progress(&mut l, &mut r) {
+            break e;
+        }
+    };
+
+    assert!(matches!(
+        err,
+        RtcError::Dtls(DtlsError::FingerprintMismatch)
+    ));
+    assert!(!l.is_alive());
+
+    // L never got as far as SRTP.
+    assert!(!l.events.iter().any(|(_, e)| matches!(e, Event::Connected)));
+    assert!(l.direct_api().remote_dtls_fingerprint().is_none());
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_fingerprint_any_matches() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = negotiate(|fingerprint| {
+        let wrong = format!("sha-256 {}", ["AB"; 32].join(":"));
+        vec![wrong, fingerprint.to_uppercase()]
+    })?;
+
+    loop {
+        if l.events.iter().any(|(_, e)| matches!(e, Event::Connected)) {
+            break;
+        }
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Failed to connect");
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    // The uppercase hash function name matched.
+    let fingerprint = l.direct_api().remote_dtls_fingerprint().unwrap();
+    assert_eq!(fingerprint.hash_func, "SHA-256");
+
+    Ok(())
+}
+
+/// Negotiate L and R, with the `a=fingerprint` of the answer rewritten by `munge`.
+fn negotiate(munge: impl Fn(&str) -> Vec<String>) -> Result<(TestRtc, TestRtc), RtcError> {
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let answer = r.sdp_api().accept_offer(offer)?;
+
+    let munged: String = answer
+        .to_sdp_string()
+        .split_inclusive("\r\n")
+        .flat_map(|line| match line.strip_prefix("a=fingerprint:") {
+            Some(v) => munge(v.trim_end())
+                .into_iter()
+                .map(|f| format!("a=fingerprint:{f}\r\n"))
+                .collect(),
+            None => vec![line.to_string()],
+        })
+        .collect();
+    let answer = SdpAnswer::from_sdp_string(&munged).unwrap();
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    Ok((l, r))
+}
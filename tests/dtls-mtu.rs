@@ -0,0 +1,169 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use openssl::asn1::Asn1Time;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509Name, X509};
+use str0m::change::DtlsCert;
+use str0m::media::{Direction, MediaKind};
+use str0m::net::Receive;
+use str0m::{Candidate, Event, Input, Output, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, TestRtc};
+
+const MTU: usize = 400;
+
+#[test]
+pub fn dtls_mtu_fragmented_certificate() -> Result<(), RtcError> {
+    init_log();
+
+    let (cert, cert_len) = big_cert();
+    assert!(cert_len > 4000, "cert is {cert_len} bytes");
+    let fingerprint = cert.fingerprint();
+
+    let config = RtcConfig::new().set_dtls_mtu(MTU);
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), config.clone().set_dtls_cert(cert).build());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), config.build());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let answer = r.sdp_api().accept_offer(offer)?;
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    let mut dtls_sizes = vec![];
+
+    loop {
+        let connected = |t: &TestRtc| t.events.iter().any(|(_, e)| matches!(e, Event::Connected));
+        if connected(&l) && connected(&r) {
+            break;
+        }
+        if l.duration() > Duration::from_secs(10) {
+            panic!("DTLS handshake failed");
+        }
+        progress_reordered(&mut l, &mut r, &mut dtls_sizes)?;
+    }
+
+    // The certificate alone needs many datagrams.
+    assert!(dtls_sizes.len() > cert_len / MTU);
+    assert!(dtls_sizes.iter().all(|n| *n <= MTU), "{dtls_sizes:?}");
+
+    // R reassembled exactly the certificate of L.
+    assert_eq!(r.direct_api().remote_dtls_fingerprint(), Some(fingerprint));
+
+    Ok(())
+}
+
+/// A certificate of more than 4 kB, from a long list of alternative names.
+fn big_cert() -> (DtlsCert, usize) {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let pkey = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, "WebRTC").unwrap();
+    let name = name.build();
+
+    let mut b = X509::builder().unwrap();
+    b.set_version(2).unwrap();
+    b.set_subject_name(&name).unwrap();
+    b.set_issuer_name(&name).unwrap();
+    b.set_pubkey(&pkey).unwrap();
+    b.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    b.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+
+    let mut san = SubjectAlternativeName::new();
+    for i in 0..100 {
+        san.dns(&format!("host-{i:03}.media.enterprise.example.com"));
+    }
+    let san = san.build(&b.x509v3_context(None, None)).unwrap();
+    b.append_extension(san).unwrap();
+
+    b.sign(&pkey, MessageDigest::sha256()).unwrap();
+    let x509 = b.build();
+    let len = x509.to_der().unwrap().len();
+
+    (DtlsCert::from_openssl(x509, pkey).unwrap(), len)
+}
+
+/// Whether the datagram only has handshake records (22) in epoch 0.
+fn is_plain_handshake(mut contents: &[u8]) -> bool {
+    while contents.len() >= 13 {
+        if contents[0] != 22 || contents[3..5] != [0, 0] {
+            return false;
+        }
+        let len = u16::from_be_bytes([contents[11], contents[12]]) as usize;
+        contents = &contents[(13 + len).min(contents.len())..];
+    }
+    true
+}
+
+/// Like `common::progress()`, but delivers unencrypted handshake records in reverse order.
+///
+/// OpenSSL drops a ChangeCipherSpec that arrives before the handshake messages leading up
+/// to it, so anything else is kept in place. The sizes of DTLS datagrams are recorded in
+/// `dtls_sizes`.
+fn progress_reordered(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    dtls_sizes: &mut Vec<usize>,
+) -> Result<(), RtcError> {
+    let (f, t) = if l.last < r.last { (l, r) } else { (r, l) };
+
+    let mut batch = vec![];
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                // DTLS content types are 20-63 (RFC 7983).
+                if (20..=63).contains(&v.contents[0]) {
+                    dtls_sizes.push(v.contents.len());
+                }
+                batch.push(v);
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    let mut ordered = vec![];
+    let mut run = vec![];
+    for v in batch {
+        if is_plain_handshake(&v.contents) {
+            run.push(v);
+        } else {
+            ordered.extend(run.drain(..).rev());
+            ordered.push(v);
+        }
+    }
+    ordered.extend(run.drain(..).rev());
+
+    for v in ordered {
+        let receive = Receive::new(v.proto, v.source, v.destination, &v.contents)?;
+        let input = Input::Receive(f.last, receive);
+        t.span.in_scope(|| t.rtc.handle_input(input))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::error::DtlsError;
+use str0m::media::{Direction, MediaKind};
+use str0m::net::Receive;
+use str0m::{Candidate, Event, Input, Output, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, TestRtc};
+
+#[test]
+pub fn dtls_retransmit_lost_server_hello() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = negotiate(RtcConfig::new())?;
+
+    let mut server_hellos = 0;
+    let mut drop = |contents: &[u8]| {
+        if is_server_hello(contents) {
+            server_hellos += 1;
+            server_hellos == 1
+        } else {
+            false
+        }
+    };
+
+    loop {
+        let connected = |t: &TestRtc| t.events.iter().any(|(_, e)| matches!(e, Event::Connected));
+        if connected(&l) && connected(&r) {
+            break;
+        }
+        if l.duration() > Duration::from_secs(10) {
+            panic!("DTLS handshake stalled");
+        }
+        progress_lossy(&mut l, &mut r, &mut drop)?;
+    }
+
+    // The first was lost, and the flight was retransmitted.
+    assert!(server_hellos >= 2);
+
+    // Retransmission is driven by the timeout after the loss.
+    assert!(l.duration() >= Duration::from_secs(1));
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_retransmit_max_retransmits() -> Result<(), RtcError> {
+    init_log();
+
+    let config = RtcConfig::new()
+        .set_dtls_initial_rto(Duration::from_millis(50))
+        .set_dtls_max_retransmits(2);
+    let (mut l, mut r) = negotiate(config)?;
+
+    let mut drop = is_server_hello;
+
+    let err = loop {
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Expected DTLS handshake to time out");
+        }
+        if let Err(e) = progress_lossy(&mut l, &mut r, &mut drop) {
+            break e;
+        }
+    };
+
+    assert!(matches!(err, RtcError::Dtls(DtlsError::HandshakeTimeout)));
+    assert!(!l.is_alive() || !r.is_alive());
+
+    // 50 + 100 + 200ms, with some slack for when the flight was first sent.
+    assert!(l.duration() < Duration::from_secs(1));
+
+    Ok(())
+}
+
+fn negotiate(config: RtcConfig) -> Result<(TestRtc, TestRtc), RtcError> {
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), config.clone().build());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), config.build());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let answer = r.sdp_api().accept_offer(offer)?;
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    Ok((l, r))
+}
+
+/// A DTLS handshake record (22), where the first message is a ServerHello (2).
+fn is_server_hello(contents: &[u8]) -> bool {
+    contents.len() > 13 && contents[0] == 22 && contents[13] == 2
+}
+
+/// Like `common::progress()`, dropping the datagrams selected by `drop`.
+fn progress_lossy(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    drop: &mut impl FnMut(&[u8]) -> bool,
+) -> Result<(), RtcError> {
+    let (f, t) = if l.last < r.last { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                if drop(&v.contents) {
+                    continue;
+                }
+                let receive = Receive::new(v.proto, v.source, v.destination, &v.contents)?;
+                let input = Input::Receive(f.last, receive);
+                t.span.in_scope(|| t.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::media::{Direction, MediaKind};
+use str0m::net::Receive;
+use str0m::{Candidate, DtlsState, Event, Input, Output, Rtc, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, TestRtc};
+
+#[test]
+pub fn dtls_setup_actpass_answered_active() -> Result<(), RtcError> {
+    init_log();
+
+    let (l, r, hello) = connect_with(Rtc::new(), Rtc::new(), None, "active")?;
+
+    // The answerer takes the active role by default.
+    assert_eq!(initiator(&l, &r, hello), "R");
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_setup_actpass_answered_passive() -> Result<(), RtcError> {
+    init_log();
+
+    // R picks the passive role before it answers.
+    let mut r_rtc = Rtc::new();
+    r_rtc.direct_api().start_dtls(false)?;
+    let (l, r, hello) = connect_with(Rtc::new(), r_rtc, None, "passive")?;
+
+    assert_eq!(initiator(&l, &r, hello), "L");
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_setup_active_offer() -> Result<(), RtcError> {
+    init_log();
+
+    let (l, r, hello) = connect_with(Rtc::new(), Rtc::new(), Some("active"), "passive")?;
+
+    assert_eq!(initiator(&l, &r, hello), "L");
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_setup_passive_offer() -> Result<(), RtcError> {
+    init_log();
+
+    let (l, r, hello) = connect_with(Rtc::new(), Rtc::new(), Some("passive"), "active")?;
+
+    assert_eq!(initiator(&l, &r, hello), "R");
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_setup_actpass_in_answer() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = with_candidates(Rtc::new(), Rtc::new())?;
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let answer = r.sdp_api().accept_offer(offer)?;
+
+    // With both sides waiting for the other, the handshake would never start.
+    let answer = SdpAnswer::from_sdp_string(&set_setup(&answer.to_sdp_string(), "actpass"))?;
+    let err = l.sdp_api().accept_answer(pending, answer).unwrap_err();
+    assert!(matches!(err, RtcError::RemoteSdp(_)));
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_setup_ice_lite() -> Result<(), RtcError> {
+    init_log();
+
+    // The ICE lite answerer is always ICE controlled, which doesn't stop it from
+    // being the DTLS client.
+    let r_rtc = RtcConfig::new().set_ice_lite(true).build();
+    let (l, r, hello) = connect_with(Rtc::new(), r_rtc, None, "active")?;
+
+    assert_eq!(initiator(&l, &r, hello), "R");
+
+    Ok(())
+}
+
+#[test]
+pub fn dtls_setup_renegotiation() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, hello) = connect_with(Rtc::new(), Rtc::new(), None, "active")?;
+    assert_eq!(initiator(&l, &r, hello), "R");
+
+    // A new offer from L states the role it already has.
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Video, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    assert_eq!(setup_of(&offer.to_sdp_string()), "passive");
+
+    let answer = r.sdp_api().accept_offer(offer)?;
+    assert_eq!(setup_of(&answer.to_sdp_string()), "active");
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    // An offer from R that would flip the roles is answered with the roles kept.
+    let mut change = r.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let offer = SdpOffer::from_sdp_string(&set_setup(&offer.to_sdp_string(), "passive"))?;
+
+    let answer = l.sdp_api().accept_offer(offer)?;
+    assert_eq!(setup_of(&answer.to_sdp_string()), "passive");
+    r.sdp_api().accept_answer(pending, answer)?;
+
+    for _ in 0..50 {
+        progress(&mut l, &mut r)?;
+    }
+
+    // One handshake only, and the transport is still up.
+    assert!(l.is_connected() && r.is_connected());
+    assert_eq!(started(&l), vec![false]);
+    assert_eq!(started(&r), vec![true]);
+
+    Ok(())
+}
+
+fn with_candidates(l_rtc: Rtc, r_rtc: Rtc) -> Result<(TestRtc, TestRtc), RtcError> {
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), l_rtc);
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), r_rtc);
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    Ok((l, r))
+}
+
+/// Connect L and R, with the `a=setup` of the offer rewritten when given, and that of the
+/// answer as expected.
+///
+/// Returns which side sent the ClientHello.
+fn connect_with(
+    l_rtc: Rtc,
+    r_rtc: Rtc,
+    offer_setup: Option<&str>,
+    answer_setup: &str,
+) -> Result<(TestRtc, TestRtc, &'static str), RtcError> {
+    let (mut l, mut r) = with_candidates(l_rtc, r_rtc)?;
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    let mut offer = offer.to_sdp_string();
+    assert_eq!(setup_of(&offer), "actpass");
+    if let Some(setup) = offer_setup {
+        offer = set_setup(&offer, setup);
+    }
+    let answer = r
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(&offer)?)?;
+
+    assert_eq!(setup_of(&answer.to_sdp_string()), answer_setup);
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    let mut hello = None;
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        if l.duration() > Duration::from_secs(10) {
+            panic!("DTLS handshake stalled");
+        }
+        progress_hello(&mut l, &mut r, &mut hello)?;
+    }
+
+    Ok((l, r, hello.expect("a ClientHello")))
+}
+
+/// The side that started the DTLS handshake, checked against who sent the ClientHello.
+fn initiator(l: &TestRtc, r: &TestRtc, hello: &str) -> &'static str {
+    let active = match (started(l).as_slice(), started(r).as_slice()) {
+        ([true], [false]) => "L",
+        ([false], [true]) => "R",
+        v => panic!("Expected exactly one active side: {v:?}"),
+    };
+    assert_eq!(active, hello);
+    active
+}
+
+fn started(t: &TestRtc) -> Vec<bool> {
+    t.events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::DtlsStateChange(DtlsState::Started { active }) => Some(*active),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A DTLS handshake record (22), where the first message is a ClientHello (1).
+fn is_client_hello(contents: &[u8]) -> bool {
+    contents.len() > 13 && contents[0] == 22 && contents[13] == 1
+}
+
+/// Like `common::progress()`, noting the side that sends the first ClientHello.
+fn progress_hello(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    hello: &mut Option<&'static str>,
+) -> Result<(), RtcError> {
+    let from_l = l.last < r.last;
+    let (f, t) = if from_l { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                if hello.is_none() && is_client_hello(&v.contents) {
+                    *hello = Some(if from_l { "L" } else { "R" });
+                }
+                let receive = Receive::new(v.proto, v.source, v.destination, &v.contents)?;
+                let input = Input::Receive(f.last, receive);
+                t.span.in_scope(|| t.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The value of the (only) `a=setup` in the SDP.
+fn setup_of(sdp: &str) -> String {
+    let mut values: Vec<_> = sdp
+        .split("\r\n")
+        .filter_map(|l| l.strip_prefix("a=setup:"))
+        .collect();
+    values.dedup();
+    assert_eq!(values.len(), 1, "{sdp}");
+    values[0].to_string()
+}
+
+fn set_setup(sdp: &str, setup: &str) -> String {
+    sdp.split_inclusive("\r\n")
+        .map(|line| {
+            if line.starts_with("a=setup:") {
+                format!("a=setup:{setup}\r\n")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,216 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::bwe::Bitrate;
+use str0m::format::PayloadParams;
+use str0m::media::{Direction, FeedbackCaps, KeyframeRequestKind, MediaKind};
+use str0m::rtp::rtcp::Rtcp;
+use str0m::rtp::RawPacket;
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, progress_with_loss, TestRtc};
+
+#[test]
+pub fn feedback_caps_all() -> Result<(), RtcError> {
+    init_log();
+
+    let (caps, sent) = run(|_| {})?;
+
+    assert_eq!(caps, FeedbackCaps::all());
+    assert_eq!(
+        sent,
+        Sent {
+            nack: true,
+            pli: false,
+            fir: true,
+            twcc: true,
+            remb: true,
+            twcc_seq: true,
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn feedback_caps_fir_downgrade_to_pli() -> Result<(), RtcError> {
+    init_log();
+
+    let (caps, sent) = run(|p| p.set_fb_fir(false))?;
+
+    assert!(caps.pli && !caps.fir);
+    assert!(sent.pli, "FIR request sent as PLI");
+    assert!(!sent.fir);
+
+    Ok(())
+}
+
+#[test]
+pub fn feedback_caps_no_keyframe_request() -> Result<(), RtcError> {
+    init_log();
+
+    let (caps, sent) = run(|p| {
+        p.set_fb_fir(false);
+        p.set_fb_pli(false);
+    })?;
+
+    assert!(!caps.pli && !caps.fir);
+    assert!(!sent.pli && !sent.fir);
+    assert!(sent.nack);
+
+    Ok(())
+}
+
+#[test]
+pub fn feedback_caps_no_nack() -> Result<(), RtcError> {
+    init_log();
+
+    let (caps, sent) = run(|p| p.set_fb_nack(false))?;
+
+    assert!(!caps.nack);
+    assert!(!sent.nack);
+    assert!(sent.fir && sent.twcc);
+
+    Ok(())
+}
+
+#[test]
+pub fn feedback_caps_no_twcc() -> Result<(), RtcError> {
+    init_log();
+
+    let (caps, sent) = run(|p| p.set_fb_transport_cc(false))?;
+
+    assert!(!caps.twcc);
+    assert!(!sent.twcc);
+    assert!(sent.remb);
+
+    // The answer had no transport-cc, and L doesn't write the sequence number.
+    assert!(!sent.twcc_seq);
+
+    Ok(())
+}
+
+#[test]
+pub fn feedback_caps_no_twcc_no_remb() -> Result<(), RtcError> {
+    init_log();
+
+    let (caps, sent) = run(|p| {
+        p.set_fb_transport_cc(false);
+        p.set_fb_remb(false);
+    })?;
+
+    assert!(!caps.twcc && !caps.remb);
+    assert!(!sent.twcc && !sent.remb);
+    assert!(sent.nack && sent.fir);
+
+    Ok(())
+}
+
+/// Feedback sent by the receiving side.
+#[derive(Debug, Default, PartialEq)]
+struct Sent {
+    nack: bool,
+    pli: bool,
+    fir: bool,
+    twcc: bool,
+    remb: bool,
+    /// The packets from L carried the transport-wide sequence number.
+    twcc_seq: bool,
+}
+
+/// L sends video with some loss to R, where R's payload params are changed by `change`.
+///
+/// R requests a FIR and a REMB, and the feedback R ends up sending is returned together
+/// with the negotiated caps.
+fn run(change: impl Fn(&mut PayloadParams)) -> Result<(FeedbackCaps, Sent), RtcError> {
+    let mut config = RtcConfig::new().enable_raw_packets(true);
+    for p in config.codec_config().iter_mut() {
+        change(p);
+    }
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), config.build());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+
+    // Both sides agree on the feedback.
+    let caps = l.media(mid).unwrap().feedback_caps();
+    assert_eq!(caps, r.media(mid).unwrap().feedback_caps());
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_vp8().pt();
+    let request_at = l.duration() + Duration::from_secs(1);
+    let end = l.duration() + Duration::from_secs(3);
+    let mut requested = false;
+
+    loop {
+        if l.last < r.last {
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid)
+                .unwrap()
+                .write(pt, wallclock, time, [1_u8; 1000])?;
+        }
+
+        if !requested && l.duration() > request_at {
+            let mut api = r.direct_api();
+            let rx = api.stream_rx_by_mid(mid, None).unwrap();
+            rx.request_keyframe(KeyframeRequestKind::Fir);
+            rx.request_remb(Bitrate::kbps(500));
+            requested = true;
+        }
+
+        progress_with_loss(&mut l, &mut r, 0.05)?;
+
+        if l.duration() > end {
+            break;
+        }
+    }
+
+    let mut sent = Sent::default();
+
+    for (_, e) in &r.events {
+        let Event::RawPacket(p) = e else {
+            continue;
+        };
+        let RawPacket::RtcpTx(rtcp) = &**p else {
+            continue;
+        };
+        match rtcp {
+            Rtcp::Nack(_) => sent.nack = true,
+            Rtcp::Pli(_) => sent.pli = true,
+            Rtcp::Fir(_) => sent.fir = true,
+            Rtcp::Twcc(_) => sent.twcc = true,
+            Rtcp::Remb(_) => sent.remb = true,
+            _ => {}
+        }
+    }
+
+    sent.twcc_seq = r.events.iter().any(|(_, e)| {
+        matches!(
+            e.as_raw_packet(),
+            Some(RawPacket::RtpRx(h, _)) if h.ext_vals.transport_cc.is_some()
+        )
+    });
+
+    Ok((caps, sent))
+}
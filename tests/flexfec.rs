@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use str0m::media::MediaKind;
+use str0m::rtp::{ExtensionValues, FlexfecConfig, Ssrc};
+use str0m::{Event, Rtc, RtcError};
+
+mod common;
+use common::{connect_l_r_with_rtc, init_log, progress, progress_with_loss};
+
+const FEC_PT: u8 = 118;
+
+#[test]
+pub fn flexfec_recover_lost_packets() -> Result<(), RtcError> {
+    init_log();
+    fastrand::seed(7);
+
+    let rtc1 = Rtc::builder().set_rtp_mode(true).build();
+    let rtc2 = Rtc::builder().set_rtp_mode(true).build();
+
+    let (mut l, mut r) = connect_l_r_with_rtc(rtc1, rtc2);
+
+    let mid = "vid".into();
+    let ssrc: Ssrc = 42.into();
+    let ssrc_fec: Ssrc = 43.into();
+
+    l.direct_api().declare_media(mid, MediaKind::Video);
+    l.direct_api()
+        .declare_stream_tx(ssrc, None, mid, None)
+        .set_flexfec(Some(
+            FlexfecConfig::new(ssrc_fec, FEC_PT.into()).set_rate(0.5, 0.5),
+        ));
+
+    r.direct_api().declare_media(mid, MediaKind::Video);
+    r.direct_api().expect_stream_rx(ssrc, None, mid, None);
+    r.direct_api()
+        .expect_stream_rx_fec(ssrc, ssrc_fec)
+        .expect("stream to protect");
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_vp8().pt();
+
+    let num_packets = 400;
+    let mut written = HashMap::new();
+
+    for index in 0..num_packets {
+        let wallclock = l.start + l.duration();
+
+        let mut direct = l.direct_api();
+        let stream = direct.stream_tx(&ssrc).unwrap();
+
+        // Frames of 4 packets with different sizes.
+        let time = 1_000_000 + (index / 4) as u32 * 3000;
+        let seq_no = 10_000 + index as u16;
+        let marker = index % 4 == 3;
+        let payload: Vec<u8> = (0..(10 + index % 97)).map(|i| (i + index) as u8).collect();
+
+        written.insert(seq_no, payload.clone());
+
+        stream
+            .write_rtp(
+                pt,
+                (seq_no as u64).into(),
+                time,
+                wallclock,
+                marker,
+                ExtensionValues::default(),
+                false,
+                payload,
+            )
+            .expect("clean write");
+
+        if (20..380).contains(&index) {
+            progress_with_loss(&mut l, &mut r, 0.1)?;
+        } else {
+            progress(&mut l, &mut r)?;
+        }
+    }
+
+    loop {
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(20) {
+            break;
+        }
+    }
+
+    let packets: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RtpPacket(v) => Some(v),
+            _ => None,
+        })
+        .collect();
+
+    let recovered = packets.iter().filter(|p| p.recovered).count();
+    assert!(recovered > 0, "no packets recovered");
+
+    // Recovered packets must be identical to what was sent.
+    for p in packets {
+        let payload = &written[&p.header.sequence_number];
+        assert_eq!(&p.payload, payload);
+        assert_eq!(p.header.payload_type, pt);
+        assert_eq!(p.header.ssrc, ssrc);
+    }
+
+    Ok(())
+}
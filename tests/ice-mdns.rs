@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::media::{Direction, MediaKind};
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, TestRtc};
+
+#[test]
+pub fn mdns_fake_resolver() -> Result<(), RtcError> {
+    init_log();
+
+    // A fake mDNS network.
+    let names: Arc<Mutex<HashMap<String, IpAddr>>> = Arc::default();
+    let resolved = Arc::new(AtomicUsize::new(0));
+
+    let register = {
+        let names = names.clone();
+        move |ip: IpAddr| {
+            let name = "4a6b1c2d-9e8f-4d3c-b2a1-0f9e8d7c6b5a.local".to_string();
+            names.lock().unwrap().insert(name.clone(), ip);
+            Some(name)
+        }
+    };
+
+    let resolve = {
+        let resolved = resolved.clone();
+        move |name: &str| {
+            resolved.fetch_add(1, Ordering::Relaxed);
+            names.lock().unwrap().get(name).copied()
+        }
+    };
+
+    let l_config = RtcConfig::new().set_mdns_register(register);
+    let r_config = RtcConfig::new().set_mdns_resolver(resolve);
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), l_config.build());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), r_config.build());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    // The IP of L is hidden.
+    let sdp = offer.to_sdp_string();
+    assert!(sdp.contains(" 4a6b1c2d-9e8f-4d3c-b2a1-0f9e8d7c6b5a.local 1000 typ host"));
+    assert!(!sdp.contains("1.1.1.1"));
+
+    // R only has what is signalled.
+    let offer = SdpOffer::from_sdp_string(&sdp).unwrap();
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    connect(&mut l, &mut r)?;
+
+    // R resolved the name itself.
+    assert_eq!(resolved.load(Ordering::Relaxed), 1);
+    assert!(!r
+        .events
+        .iter()
+        .any(|(_, e)| matches!(e, Event::ResolveMdns(_))));
+
+    Ok(())
+}
+
+#[test]
+pub fn mdns_resolve_event() -> Result<(), RtcError> {
+    init_log();
+
+    let register = |_ip| Some("9f8e7d6c-5b4a-4392-8170-6f5e4d3c2b1a.local".to_string());
+    let l_config = RtcConfig::new().set_mdns_register(register);
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), l_config.build());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), RtcConfig::new().build());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    // L doesn't get the candidates of R, so only R can start the checks.
+    let offer = SdpOffer::from_sdp_string(&offer.to_sdp_string()).unwrap();
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    let munged: String = answer
+        .to_sdp_string()
+        .split_inclusive("\r\n")
+        .filter(|l| !l.starts_with("a=candidate"))
+        .collect();
+    let answer = SdpAnswer::from_sdp_string(&munged).unwrap();
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    while r.duration() < Duration::from_secs(1) {
+        progress(&mut l, &mut r)?;
+    }
+    assert!(!l.is_connected() && !r.is_connected());
+
+    let name = r
+        .events
+        .iter()
+        .find_map(|(_, e)| match e {
+            Event::ResolveMdns(name) => Some(name.clone()),
+            _ => None,
+        })
+        .expect("ResolveMdns event");
+    assert_eq!(name, "9f8e7d6c-5b4a-4392-8170-6f5e4d3c2b1a.local");
+
+    r.resolve_mdns(&name, Ipv4Addr::new(1, 1, 1, 1).into());
+
+    connect(&mut l, &mut r)?;
+
+    Ok(())
+}
+
+fn connect(l: &mut TestRtc, r: &mut TestRtc) -> Result<(), RtcError> {
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(l, r)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use str0m::media::{Direction, MediaKind};
+use str0m::net::Receive;
+use str0m::{Candidate, CandidateKind, Input, Output, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, TestRtc};
+
+/// L's host candidate, on a private network.
+fn private() -> SocketAddr {
+    (Ipv4Addr::new(10, 0, 0, 1), 1000).into()
+}
+
+/// The mapping of the NAT in front of L.
+fn mapped() -> SocketAddr {
+    (Ipv4Addr::new(4, 4, 4, 4), 40_000).into()
+}
+
+#[test]
+pub fn ice_prflx_behind_nat() -> Result<(), RtcError> {
+    init_log();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    // L only knows its private address, which R can't reach.
+    l.add_local_candidate(Candidate::host(private(), "udp")?);
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let answer = r.sdp_api().accept_offer(offer)?;
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        if l.duration() > Duration::from_secs(10) {
+            panic!("Failed to connect through the NAT");
+        }
+        progress_nat(&mut l, &mut r)?;
+    }
+
+    // R learned the mapped address from the checks.
+    let pair = r.stats().candidate_pair.expect("nominated pair at R");
+    assert_eq!(pair.remote_address, mapped());
+    assert_eq!(pair.remote_candidate_type, CandidateKind::PeerReflexive);
+
+    // L sends from the host candidate, the base of the mapped address.
+    let pair = l.stats().candidate_pair.expect("nominated pair at L");
+    assert_eq!(pair.local_address, private());
+
+    // Peer reflexive candidates are never signalled.
+    let mut change = l.sdp_api();
+    change.add_media(MediaKind::Video, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let sdp = offer.to_sdp_string();
+    assert!(sdp.contains(" 10.0.0.1 1000 typ host"));
+    assert!(!sdp.contains("prflx") && !sdp.contains("4.4.4.4"));
+
+    let answer = r.sdp_api().accept_offer(offer)?;
+    assert!(!answer.to_sdp_string().contains("prflx"));
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    Ok(())
+}
+
+/// Like `common::progress()`, with a NAT in front of L.
+fn progress_nat(l: &mut TestRtc, r: &mut TestRtc) -> Result<(), RtcError> {
+    let from_l = l.last < r.last;
+    let (f, t) = if from_l { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                let (source, destination) = if from_l {
+                    assert_eq!(v.source, private());
+                    (mapped(), v.destination)
+                } else if v.destination == mapped() {
+                    (v.source, private())
+                } else {
+                    // The private address is not reachable.
+                    continue;
+                };
+                let receive = Receive::new(v.proto, source, destination, &v.contents)?;
+                let input = Input::Receive(f.last, receive);
+                t.span.in_scope(|| t.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,8 +1,10 @@
-use std::net::Ipv4Addr;
-use std::time::Duration;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
 
-use str0m::RtcConfig;
-use str0m::{Candidate, RtcError};
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::net::Receive;
+use str0m::{Candidate, Event, Input, Output, RtcError};
+use str0m::{IceConnectionState, RtcConfig};
 use tracing::info_span;
 
 mod common;
@@ -87,3 +89,158 @@ pub fn ice_restart() -> Result<(), RtcError> {
 
     Ok(())
 }
+
+#[test]
+pub fn ice_restart_old_path_dies() -> Result<(), RtcError> {
+    init_log();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let old: SocketAddr = (Ipv4Addr::new(1, 1, 1, 1), 1000).into();
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(Candidate::host(old, "udp")?);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let answer = r.sdp_api().accept_offer(offer)?;
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    let mut dead = None;
+
+    while !(l.is_connected() && r.is_connected()) {
+        progress_path(&mut l, &mut r, dead)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let until = l.duration() + Duration::from_secs(1);
+    while l.duration() < until {
+        write_and_progress(&mut l, &mut r, mid, dead)?;
+    }
+
+    // L moves to another network. The new candidates are gathered after the restart.
+    let (offer, pending) = {
+        let mut change = l.sdp_api();
+        change.ice_restart(false);
+        change.apply().unwrap()
+    };
+    let answer = r.sdp_api().accept_offer(offer)?;
+    l.sdp_api().accept_answer(pending, answer)?;
+
+    // Media still flows on the old pair.
+    let restart_at = r.last;
+    let until = l.duration() + Duration::from_millis(500);
+    while l.duration() < until {
+        write_and_progress(&mut l, &mut r, mid, dead)?;
+    }
+    assert!(media_since(&r, restart_at) > 10);
+
+    // The old path dies, and the new one turns up.
+    dead = Some(old);
+    let new = Candidate::host((Ipv4Addr::new(1, 1, 1, 2), 1000).into(), "udp")?;
+    l.add_local_candidate(new.clone());
+    r.add_remote_candidate(new);
+
+    while !(l.is_connected() && r.is_connected()) {
+        if l.duration() > until + Duration::from_secs(10) {
+            panic!("Failed to converge on the new path after ICE restart");
+        }
+        write_and_progress(&mut l, &mut r, mid, dead)?;
+    }
+
+    let switched_at = r.last;
+    let until = l.duration() + Duration::from_secs(1);
+    while l.duration() < until {
+        write_and_progress(&mut l, &mut r, mid, dead)?;
+    }
+    assert!(media_since(&r, switched_at) > 30);
+
+    let pair = l.stats().candidate_pair.expect("nominated pair");
+    assert_eq!(pair.local_address, (Ipv4Addr::new(1, 1, 1, 2), 1000).into());
+    assert!(l
+        .events
+        .iter()
+        .any(|(_, e)| matches!(e, Event::NetworkRouteChange(_))));
+
+    // DTLS was not redone, and the old pair was never given up on.
+    for t in [&l, &r] {
+        let connected = t
+            .events
+            .iter()
+            .filter(|(_, e)| matches!(e, Event::Connected));
+        assert_eq!(connected.count(), 1);
+        let expired = t
+            .events
+            .iter()
+            .any(|(_, e)| matches!(e, Event::IceConsentExpired));
+        assert!(!expired);
+        assert!(!t.events.iter().any(|(_, e)| {
+            *e == Event::IceConnectionStateChange(IceConnectionState::Disconnected)
+        }));
+    }
+
+    Ok(())
+}
+
+fn write_and_progress(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    mid: Mid,
+    dead: Option<SocketAddr>,
+) -> Result<(), RtcError> {
+    let pt = l.params_opus().pt();
+    let wallclock = l.start + l.duration();
+    let time = l.duration().into();
+    l.writer(mid)
+        .unwrap()
+        .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+    progress_path(l, r, dead)
+}
+
+fn media_since(t: &TestRtc, since: Instant) -> usize {
+    t.events
+        .iter()
+        .filter(|(at, e)| *at >= since && matches!(e, Event::MediaData(_)))
+        .count()
+}
+
+/// Like `common::progress()`, but drops everything to or from the dead address.
+fn progress_path(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    dead: Option<SocketAddr>,
+) -> Result<(), RtcError> {
+    let (f, t) = if l.last < r.last { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                if dead.map_or(false, |d| v.source == d || v.destination == d) {
+                    continue;
+                }
+                let receive = Receive::new(v.proto, v.source, v.destination, &v.contents)?;
+                let input = Input::Receive(f.last, receive);
+                t.span.in_scope(|| t.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
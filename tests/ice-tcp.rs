@@ -0,0 +1,170 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use str0m::media::{Direction, MediaKind};
+use str0m::net::{Protocol, Receive, Rfc4571, Transmit};
+use str0m::{Candidate, Event, Input, Output, RtcConfig, RtcError, TcpType};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, TestRtc};
+
+/// L listens on a passive candidate.
+fn passive_addr() -> SocketAddr {
+    (Ipv4Addr::new(1, 1, 1, 1), 1000).into()
+}
+
+/// R has an active candidate, which by convention has port 9.
+fn active_addr() -> SocketAddr {
+    (Ipv4Addr::new(2, 2, 2, 2), 9).into()
+}
+
+/// The port the OS picks for R's connection to L.
+fn ephemeral_addr() -> SocketAddr {
+    (Ipv4Addr::new(2, 2, 2, 2), 50_000).into()
+}
+
+#[test]
+pub fn ice_tcp_active_passive() -> Result<(), RtcError> {
+    init_log();
+
+    let rtc = || RtcConfig::new().build();
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), rtc());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), rtc());
+
+    let passive = Candidate::host(passive_addr(), "tcp")?.with_tcptype(TcpType::Passive)?;
+    let active = Candidate::host(active_addr(), "tcp")?.with_tcptype(TcpType::Active)?;
+    l.add_local_candidate(passive);
+    r.add_local_candidate(active);
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendOnly, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    assert!(offer
+        .to_sdp_string()
+        .contains(" 1.1.1.1 1000 typ host tcptype passive"));
+
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    assert!(answer
+        .to_sdp_string()
+        .contains(" 2.2.2.2 9 typ host tcptype active"));
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    let mut net = Network::default();
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r, &mut net)?;
+    }
+
+    // The connection was opened by R, and L never tried to open one.
+    assert!(net.open);
+    assert_eq!(net.dropped_l, 0);
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_opus().pt();
+    let until = l.duration() + Duration::from_secs(2);
+
+    loop {
+        let wallclock = l.start + l.duration();
+        let time = l.duration().into();
+        l.writer(mid)
+            .unwrap()
+            .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+        progress(&mut l, &mut r, &mut net)?;
+
+        if l.duration() > until {
+            break;
+        }
+    }
+
+    let received = r
+        .events
+        .iter()
+        .filter(|(_, e)| matches!(e, Event::MediaData(d) if d.mid == mid))
+        .count();
+    assert!(received > 50, "received {received} over TCP");
+
+    Ok(())
+}
+
+/// One TCP connection from R's active candidate to L's passive, as an in-memory stream.
+#[derive(Default)]
+struct Network {
+    open: bool,
+    to_l: Rfc4571,
+    to_r: Rfc4571,
+    dropped_l: usize,
+}
+
+impl Network {
+    /// Send the transmit over the stream, and return the packets read at the other end
+    /// as (source, destination, contents).
+    fn send(&mut self, from_l: bool, t: Transmit) -> Vec<(SocketAddr, SocketAddr, Vec<u8>)> {
+        assert_eq!(t.proto, Protocol::Tcp);
+
+        let framed = Rfc4571::frame(&t.contents).unwrap();
+
+        let (framing, source, destination) = if from_l {
+            // The passive candidate can only use a connection opened by R.
+            if t.source != passive_addr() || t.destination != ephemeral_addr() || !self.open {
+                self.dropped_l += 1;
+                return vec![];
+            }
+            (&mut self.to_r, passive_addr(), active_addr())
+        } else {
+            // Nothing but L's passive candidate accepts connections.
+            if t.source != active_addr() || t.destination != passive_addr() {
+                return vec![];
+            }
+            self.open = true;
+            (&mut self.to_l, ephemeral_addr(), passive_addr())
+        };
+
+        // The stream doesn't keep packet boundaries.
+        for chunk in framed.chunks(7) {
+            framing.push(chunk);
+        }
+
+        std::iter::from_fn(|| framing.pop())
+            .map(|p| (source, destination, p))
+            .collect()
+    }
+}
+
+/// Like `common::progress()`, but over the in-memory TCP connection.
+fn progress(l: &mut TestRtc, r: &mut TestRtc, net: &mut Network) -> Result<(), RtcError> {
+    let from_l = l.last < r.last;
+    let (f, t) = if from_l { (l, r) } else { (r, l) };
+
+    loop {
+        f.span
+            .in_scope(|| f.rtc.handle_input(Input::Timeout(f.last)))?;
+
+        match f.span.in_scope(|| f.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = f.last + Duration::from_millis(10);
+                f.last = if v == f.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                for (source, destination, packet) in net.send(from_l, v) {
+                    let receive = Receive::new(Protocol::Tcp, source, destination, &packet)?;
+                    let input = Input::Receive(f.last, receive);
+                    t.span.in_scope(|| t.rtc.handle_input(input))?;
+                }
+            }
+            Output::Event(v) => {
+                f.events.push((f.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::net::Ipv4Addr;
 use std::time::Duration;
 
-use str0m::format::{Codec, CodecExtra};
+use str0m::format::{Codec, CodecExtra, Vp8Meta};
 use str0m::media::{Direction, MediaKind};
 use str0m::{Candidate, Event, RtcError};
 use tracing::info_span;
@@ -101,6 +101,46 @@ pub fn test_vp8_keyframes_detection() -> Result<(), RtcError> {
     Ok(())
 }
 
+#[test]
+pub fn test_vp8_meta_chrome_keyframe() {
+    let data = vp8_data();
+
+    // Chrome with temporal layers: X, I (15 bit), L and T.
+    let (_, _, packet) = &data[0];
+    let (meta, payload) = Vp8Meta::parse(packet).unwrap();
+    assert!(meta.is_start_of_frame());
+    assert!(meta.is_keyframe);
+    assert!(meta.long_picture_id);
+    assert_eq!(meta.picture_id, Some(19489));
+    assert_eq!(meta.tl0_pic_idx, Some(220));
+    assert_eq!(meta.tid, Some(0));
+    assert_eq!(meta.key_idx, None);
+    // Start code and 240x180.
+    assert_eq!(&payload[3..10], &[0x9d, 0x01, 0x2a, 0xf0, 0x00, 0xb4, 0x00]);
+
+    // A delta frame on temporal layer 1, which is needed for the next layer sync.
+    let (_, _, packet) = &data[1];
+    let (meta, _) = Vp8Meta::parse(packet).unwrap();
+    assert!(!meta.is_keyframe);
+    assert!(!meta.non_reference);
+    assert_eq!(meta.tid, Some(1));
+    assert!(meta.layer_sync);
+
+    // A delta frame on temporal layer 1 that can be dropped.
+    let (_, _, packet) = &data[8];
+    let (meta, _) = Vp8Meta::parse(packet).unwrap();
+    assert!(!meta.is_keyframe);
+    assert!(meta.non_reference);
+    assert_eq!(meta.tid, Some(1));
+
+    // The keyframe packet as a continuation never counts as keyframe.
+    let mut packet = data[0].2.clone();
+    packet[0] &= !0x10;
+    let (meta, _) = Vp8Meta::parse(&packet).unwrap();
+    assert!(!meta.is_start_of_frame());
+    assert!(!meta.is_keyframe);
+}
+
 #[test]
 pub fn test_vp9_keyframes_detection() -> Result<(), RtcError> {
     init_log();
@@ -0,0 +1,253 @@
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use str0m::media::{Direction, MediaFeatures, MediaKind, Mid};
+use str0m::net::Receive;
+use str0m::rtp::rtcp::Rtcp;
+use str0m::rtp::RawPacket;
+use str0m::{Candidate, Event, Input, Output, RtcConfig, RtcError};
+use tracing::{info, info_span};
+
+mod common;
+use common::{init_log, negotiate, progress, progress_with_loss, TestRtc};
+
+#[test]
+pub fn media_features_none() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup(MediaFeatures::none())?;
+
+    assert_eq!(r.media(mid).unwrap().features(), MediaFeatures::none());
+    assert_eq!(l.media(mid).unwrap().features(), MediaFeatures::all());
+
+    // Nothing turned off is negotiated.
+    let caps = r.media(mid).unwrap().feedback_caps();
+    assert!(!caps.nack && !caps.twcc);
+    let caps = l.media(mid).unwrap().feedback_caps();
+    assert!(!caps.nack && !caps.twcc);
+
+    send_video(&mut l, &mut r, mid, 0.05, Duration::from_secs(3))?;
+
+    let (nack, twcc) = feedback_sent(&r);
+    assert!(!nack, "R sent NACK");
+    assert!(!twcc, "R sent TWCC");
+
+    let stats = r.rtc.stats();
+    assert_eq!(stats.inbound.len(), 1);
+    assert_eq!(stats.inbound[0].nack_count, None);
+
+    // The features can't change once negotiated.
+    let mut api = r.sdp_api();
+    assert!(matches!(
+        api.set_media_features(mid, MediaFeatures::all()),
+        Err(RtcError::MediaFeaturesLocked(m)) if m == mid
+    ));
+
+    Ok(())
+}
+
+#[test]
+pub fn media_features_all() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup(MediaFeatures::all())?;
+
+    send_video(&mut l, &mut r, mid, 0.05, Duration::from_secs(3))?;
+
+    let (nack, twcc) = feedback_sent(&r);
+    assert!(nack && twcc);
+
+    let stats = r.rtc.stats();
+    assert!(stats.inbound[0].nack_count.unwrap() > 0);
+
+    let stats = l.rtc.stats();
+    assert!(stats.outbound[0].retransmitted_packets_sent.unwrap() > 0);
+
+    Ok(())
+}
+
+#[test]
+pub fn media_features_direct_api() {
+    let mut rtc = RtcConfig::new().build();
+    let mid: Mid = "v".into();
+
+    let mut api = rtc.direct_api();
+    api.declare_media(mid, MediaKind::Video);
+    api.set_media_features(mid, MediaFeatures::none()).unwrap();
+
+    let ssrc = api.new_ssrc();
+    api.declare_stream_tx(ssrc, None, mid, None);
+
+    assert!(matches!(
+        api.set_media_features(mid, MediaFeatures::all()),
+        Err(RtcError::MediaFeaturesLocked(_))
+    ));
+    assert!(api
+        .set_media_features("x".into(), MediaFeatures::all())
+        .is_err());
+
+    assert_eq!(rtc.media(mid).unwrap().features(), MediaFeatures::none());
+}
+
+/// Per packet receive cost with all features off compared to on.
+///
+/// `RUST_LOG=media_features=info cargo test --release --test media-features -- --ignored`
+#[test]
+#[ignore]
+pub fn media_features_receive_cost() -> Result<(), RtcError> {
+    init_log();
+
+    const PACKETS: usize = 20_000;
+
+    for features in [MediaFeatures::all(), MediaFeatures::none()] {
+        let (mut l, mut r, mid) = setup(features)?;
+        let pt = l.params_vp8().pt();
+
+        let mut spent = Duration::ZERO;
+        let mut received = 0;
+
+        while received < PACKETS {
+            l.last += Duration::from_millis(1);
+            r.last = l.last;
+
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid)
+                .unwrap()
+                .write(pt, wallclock, time, [1_u8; 1000])?;
+
+            // L to R is the timed part.
+            l.rtc.handle_input(Input::Timeout(l.last))?;
+            loop {
+                match l.rtc.poll_output()? {
+                    Output::Timeout(_) => break,
+                    Output::Transmit(v) => {
+                        let input = Input::Receive(
+                            l.last,
+                            Receive {
+                                proto: v.proto,
+                                source: v.source,
+                                destination: v.destination,
+                                contents: (&*v.contents).try_into()?,
+                            },
+                        );
+                        let start = Instant::now();
+                        r.rtc.handle_input(input)?;
+                        spent += start.elapsed();
+                        received += 1;
+                    }
+                    Output::Event(_) => {}
+                }
+            }
+
+            // R to L, including R's feedback, is not.
+            r.rtc.handle_input(Input::Timeout(r.last))?;
+            loop {
+                match r.rtc.poll_output()? {
+                    Output::Timeout(_) => break,
+                    Output::Transmit(v) => {
+                        let input = Input::Receive(
+                            r.last,
+                            Receive {
+                                proto: v.proto,
+                                source: v.source,
+                                destination: v.destination,
+                                contents: (&*v.contents).try_into()?,
+                            },
+                        );
+                        l.rtc.handle_input(input)?;
+                    }
+                    Output::Event(_) => {}
+                }
+            }
+        }
+
+        info!(
+            "{:?}: {} ns/packet",
+            features,
+            spent.as_nanos() / received as u128
+        );
+    }
+
+    Ok(())
+}
+
+/// L sends video to R, where R has `features` for all media.
+fn setup(features: MediaFeatures) -> Result<(TestRtc, TestRtc, Mid), RtcError> {
+    let r_config = RtcConfig::new()
+        .set_media_features(features)
+        .enable_raw_packets(true);
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), r_config.build());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    Ok((l, r, mid))
+}
+
+fn send_video(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    mid: Mid,
+    loss: f32,
+    dur: Duration,
+) -> Result<(), RtcError> {
+    let pt = l.params_vp8().pt();
+    let end = l.duration() + dur;
+
+    loop {
+        if l.last < r.last {
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid)
+                .unwrap()
+                .write(pt, wallclock, time, [1_u8; 1000])?;
+        }
+
+        progress_with_loss(l, r, loss)?;
+
+        if l.duration() > end {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether NACK and TWCC feedback was sent.
+fn feedback_sent(t: &TestRtc) -> (bool, bool) {
+    let mut nack = false;
+    let mut twcc = false;
+
+    for (_, e) in &t.events {
+        let Event::RawPacket(p) = e else {
+            continue;
+        };
+        match &**p {
+            RawPacket::RtcpTx(Rtcp::Nack(_)) => nack = true,
+            RawPacket::RtcpTx(Rtcp::Twcc(_)) => twcc = true,
+            _ => {}
+        }
+    }
+
+    (nack, twcc)
+}
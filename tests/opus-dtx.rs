@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use str0m::media::{Frequency, MediaKind, MediaTime, Mid};
+use str0m::rtp::rtcp::{ReceptionReport, Rtcp};
+use str0m::rtp::{ExtensionValues, RawPacket, Ssrc};
+use str0m::{Event, RtcError};
+
+mod common;
+use common::{connect_l_r, init_log, progress, TestRtc};
+
+/// Hybrid fullband 20ms, as libWebRTC sends speech.
+const SPEECH: &[u8] = &[
+    0x78, 0x0b, 0xe4, 0xc1, 0x36, 0xec, 0xc5, 0x80, 0x2a, 0x73, 0x1f, 0x62, 0x91, 0x05,
+];
+
+/// Only the TOC byte, which is what the Opus encoder produces during DTX.
+const DTX: &[u8] = &[0x78];
+
+const TIME_BASE: u64 = 1_000_000;
+
+/// A packet of the trace.
+#[derive(Clone, Copy)]
+enum P {
+    Speech,
+    /// First speech after silence, with the marker bit.
+    Resume,
+    Dtx,
+    /// The sender, or an SFU in between, didn't send the packet.
+    Dropped,
+    /// The packet was sent, but lost on the way.
+    Lost,
+}
+
+/// Packets at 20ms intervals, talk spurts with silence in between.
+///
+/// The first silence is like the Opus encoder does DTX, a DTX packet every 400ms in
+/// sequence. The second is the same after an SFU dropped the DTX packets, which leaves
+/// gaps in the sequence numbers.
+fn trace() -> Vec<(u64, P)> {
+    let mut t = vec![];
+    let mut push = |ms: std::ops::Range<u64>, p: P| {
+        t.extend(ms.step_by(20).map(|ms| (ms, p)));
+    };
+
+    push(0..1000, P::Speech);
+    push(1000..2000, P::Dtx);
+    push(2000..2020, P::Resume);
+    push(2020..3000, P::Speech);
+    push(3000..4000, P::Dropped);
+    push(4000..4020, P::Resume);
+    push(4020..5000, P::Speech);
+
+    // Only every 20th DTX slot (400ms) has a packet, the others have no sequence number.
+    t.retain(|(ms, p)| !matches!(p, P::Dtx | P::Dropped) || ms % 400 == 200);
+
+    t
+}
+
+#[test]
+pub fn opus_dtx_no_loss() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid, ssrc) = setup();
+    send(&mut l, &mut r, mid, ssrc, &trace())?;
+
+    // The silences, both times from the end of the last speech to the next.
+    let silences: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::StreamSilence(s) => Some((s.ssrc, s.mid, s.start, s.end, s.skipped)),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        silences,
+        vec![
+            (ssrc, mid, time(1000), time(2000), 0),
+            (ssrc, mid, time(3000), time(4000), 3),
+        ]
+    );
+
+    // No NACK, and no loss reported.
+    assert_eq!(nacks(&r), 0);
+    let report = last_report(&r, ssrc);
+    assert_eq!(report.packets_lost, 0);
+    assert_eq!(report.fraction_lost, 0);
+
+    Ok(())
+}
+
+#[test]
+pub fn opus_loss_in_speech() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid, ssrc) = setup();
+
+    let mut trace = trace();
+    for (ms, p) in &mut trace {
+        if *ms == 500 {
+            *p = P::Lost;
+        }
+    }
+
+    send(&mut l, &mut r, mid, ssrc, &trace)?;
+
+    // Loss outside the silences is still loss.
+    assert!(nacks(&r) > 0);
+    assert_eq!(last_report(&r, ssrc).packets_lost, 1);
+
+    Ok(())
+}
+
+fn setup() -> (TestRtc, TestRtc, Mid, Ssrc) {
+    let (mut l, mut r) = connect_l_r();
+
+    let mid = "aud".into();
+    let ssrc: Ssrc = 42.into();
+
+    l.direct_api().declare_media(mid, MediaKind::Audio);
+    l.direct_api().declare_stream_tx(ssrc, None, mid, None);
+
+    r.direct_api().declare_media(mid, MediaKind::Audio);
+    r.direct_api().expect_stream_rx(ssrc, None, mid, None);
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    (l, r, mid, ssrc)
+}
+
+fn send(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    mid: Mid,
+    ssrc: Ssrc,
+    trace: &[(u64, P)],
+) -> Result<(), RtcError> {
+    let pt = l.params_opus().pt();
+    let start = l.duration();
+    let mut seq_no = 4711_u64;
+
+    for (ms, p) in trace {
+        let at = start + Duration::from_millis(*ms);
+        while l.duration() < at {
+            progress(l, r)?;
+        }
+
+        let (payload, marker) = match p {
+            P::Speech | P::Lost => (SPEECH, false),
+            P::Resume => (SPEECH, true),
+            P::Dtx => (DTX, false),
+            P::Dropped => {
+                seq_no += 1;
+                continue;
+            }
+        };
+
+        let wallclock = l.start + l.duration();
+        let mut api = l.direct_api();
+        let stream = api.stream_tx_by_mid(mid, None).unwrap();
+        assert_eq!(stream.ssrc(), ssrc);
+
+        if !matches!(p, P::Lost) {
+            stream.write_rtp(
+                pt,
+                seq_no.into(),
+                time(*ms).numer() as u32,
+                wallclock,
+                marker,
+                ExtensionValues::default(),
+                true,
+                payload.to_vec(),
+            )?;
+        }
+
+        seq_no += 1;
+    }
+
+    // Time for receiver reports and any NACK.
+    let settle = l.duration() + Duration::from_secs(3);
+    while l.duration() < settle {
+        progress(l, r)?;
+    }
+
+    Ok(())
+}
+
+fn time(ms: u64) -> MediaTime {
+    MediaTime::new(TIME_BASE + ms * 48, Frequency::FORTY_EIGHT_KHZ)
+}
+
+fn nacks(r: &TestRtc) -> usize {
+    r.events
+        .iter()
+        .filter(|(_, e)| matches!(e.as_raw_packet(), Some(RawPacket::RtcpTx(Rtcp::Nack(_)))))
+        .count()
+}
+
+fn last_report(r: &TestRtc, ssrc: Ssrc) -> ReceptionReport {
+    r.events
+        .iter()
+        .filter_map(|(_, e)| match e.as_raw_packet() {
+            Some(RawPacket::RtcpTx(Rtcp::ReceiverReport(rr))) => {
+                rr.reports.iter().find(|r| r.ssrc == ssrc).copied()
+            }
+            _ => None,
+        })
+        .next_back()
+        .expect("a receiver report")
+}
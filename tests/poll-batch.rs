@@ -0,0 +1,148 @@
+use std::net::Ipv4Addr;
+
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::net::{Receive, Transmit};
+use str0m::{Candidate, Event, Input, Output, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, TestRtc};
+
+#[test]
+pub fn poll_batch_keyframe() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup()?;
+
+    write_keyframe(&mut l, mid)?;
+    let batches = poll_batches(&mut l, 100)?;
+
+    // The whole keyframe is one batch, rather than one poll per packet.
+    assert_eq!(batches.len(), 1, "Batches: {:?}", sizes(&batches));
+    let batch = &batches[0];
+    assert!(batch.len() >= 35, "Batch size: {}", batch.len());
+
+    // Equal segment sizes where only the last is shorter, which GSO can send.
+    let size = batch[0].contents.len();
+    let (last, rest) = batch.split_last().unwrap();
+    assert!(rest.iter().all(|t| t.contents.len() == size));
+    assert!(last.contents.len() <= size);
+
+    // In order, since R gets the whole frame.
+    deliver(&mut r, batches)?;
+    assert_eq!(frame_sizes(&mut r), vec![40_000]);
+
+    Ok(())
+}
+
+#[test]
+pub fn poll_batch_max() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup()?;
+
+    write_keyframe(&mut l, mid)?;
+    let batches = poll_batches(&mut l, 10)?;
+
+    let sizes = sizes(&batches);
+    assert!(sizes.len() >= 4, "Batches: {:?}", sizes);
+    let (last, rest) = sizes.split_last().unwrap();
+    assert!(rest.iter().all(|s| *s == 10), "Batches: {:?}", sizes);
+    assert!(*last <= 10);
+
+    deliver(&mut r, batches)?;
+    assert_eq!(frame_sizes(&mut r), vec![40_000]);
+
+    Ok(())
+}
+
+fn setup() -> Result<(TestRtc, TestRtc, Mid), RtcError> {
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    // Let the setup traffic settle.
+    for _ in 0..50 {
+        progress(&mut l, &mut r)?;
+    }
+
+    Ok((l, r, mid))
+}
+
+fn write_keyframe(l: &mut TestRtc, mid: Mid) -> Result<(), RtcError> {
+    let pt = l.params_vp8().pt();
+    let wallclock = l.start + l.duration();
+    let time = l.duration().into();
+    l.writer(mid)
+        .unwrap()
+        .write(pt, wallclock, time, vec![1_u8; 40_000])?;
+
+    l.rtc.handle_input(Input::Timeout(l.last))
+}
+
+/// Poll until timeout, returning the batches.
+fn poll_batches(t: &mut TestRtc, max: usize) -> Result<Vec<Vec<Transmit>>, RtcError> {
+    let mut batches = vec![];
+    let mut out = vec![];
+
+    loop {
+        match t.rtc.poll_batch(max, &mut out)? {
+            None => batches.push(std::mem::take(&mut out)),
+            Some(Output::Timeout(_)) => break,
+            Some(_) => {}
+        }
+    }
+
+    Ok(batches)
+}
+
+fn sizes(batches: &[Vec<Transmit>]) -> Vec<usize> {
+    batches.iter().map(|b| b.len()).collect()
+}
+
+fn deliver(to: &mut TestRtc, batches: Vec<Vec<Transmit>>) -> Result<(), RtcError> {
+    for t in batches.into_iter().flatten() {
+        let input = Input::Receive(
+            to.last,
+            Receive {
+                proto: t.proto,
+                source: t.source,
+                destination: t.destination,
+                contents: (&*t.contents).try_into()?,
+            },
+        );
+        to.rtc.handle_input(input)?;
+    }
+
+    Ok(())
+}
+
+/// Sizes of the media frames `t` has received.
+fn frame_sizes(t: &mut TestRtc) -> Vec<usize> {
+    let mut sizes = vec![];
+
+    loop {
+        match t.rtc.poll_output().unwrap() {
+            Output::Timeout(_) => break,
+            Output::Event(Event::MediaData(d)) => sizes.push(d.data.len()),
+            _ => {}
+        }
+    }
+
+    sizes
+}
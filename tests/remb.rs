@@ -3,7 +3,7 @@ use std::time::Duration;
 
 use str0m::bwe::{Bitrate, BweKind};
 use str0m::media::{Direction, MediaKind};
-use str0m::{Candidate, Event, Rtc, RtcError};
+use str0m::{Candidate, Event, Rtc, RtcConfig, RtcError};
 use tracing::info_span;
 
 mod common;
@@ -74,3 +74,79 @@ pub fn remb() -> Result<(), RtcError> {
 
     Ok(())
 }
+
+#[test]
+pub fn remb_from_estimate() -> Result<(), RtcError> {
+    init_log();
+
+    // Without TWCC, the receiver estimates and sends REMB.
+    let no_twcc = || {
+        let mut config = RtcConfig::new();
+        for p in config.codec_config().iter_mut() {
+            p.set_fb_transport_cc(false);
+        }
+        config.build()
+    };
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), no_twcc());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), no_twcc());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_vp8().pt();
+    let end = l.duration() + Duration::from_secs(5);
+    let data = [1_u8; 1000];
+
+    loop {
+        // Only write when the next progress polls l.
+        if l.last < r.last {
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid).unwrap().write(pt, wallclock, time, data)?;
+        }
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > end {
+            break;
+        }
+    }
+
+    let l_remb: Vec<_> = l
+        .events
+        .iter()
+        .filter_map(|(t, e)| match e {
+            Event::EgressBitrateEstimate(BweKind::Remb(m, bitrate)) => Some((*t, *m, *bitrate)),
+            _ => None,
+        })
+        .collect();
+
+    assert!(l_remb.len() >= 3, "Should have REMB: {:?}", l_remb);
+    assert!(l_remb.iter().all(|(_, m, _)| *m == mid));
+    assert!(l_remb.iter().all(|(_, _, b)| *b > Bitrate::ZERO));
+
+    // Stable estimates are repeated at an interval.
+    for w in l_remb.windows(2) {
+        assert!(w[1].0 - w[0].0 <= Duration::from_millis(1100));
+    }
+
+    Ok(())
+}
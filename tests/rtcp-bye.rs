@@ -0,0 +1,270 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::rtp::rtcp::Rtcp;
+use str0m::rtp::{RawPacket, Ssrc};
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, TestRtc};
+
+#[test]
+pub fn rtcp_bye_on_close() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup()?;
+    let ssrc = send_audio(&mut l, &mut r, mid, Duration::from_secs(3))?;
+
+    l.rtc.close(Some("shutting down"));
+
+    for _ in 0..50 {
+        progress(&mut l, &mut r)?;
+    }
+
+    // The close_notify after the BYE disconnects R too.
+    assert!(!l.is_alive());
+    assert!(!r.is_alive());
+
+    assert_eq!(ended(&r), vec![(ssrc, mid, Some("shutting down".into()))]);
+
+    // The last compound L sent has the BYE last.
+    let last = last_compound(&l);
+    assert!(matches!(last.first(), Some(Rtcp::SenderReport(_))));
+    assert!(last.iter().any(|p| matches!(p, Rtcp::SourceDescription(_))));
+    match last.last() {
+        Some(Rtcp::Goodbye(g)) => {
+            assert!(g.reports.iter().any(|s| *s == ssrc));
+            assert_eq!(g.reason.as_deref(), Some("shutting down"));
+        }
+        p => panic!("Expected BYE last, got: {:?}", p),
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_bye_on_remove_media() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup()?;
+    send_audio(&mut l, &mut r, mid, Duration::from_secs(3))?;
+
+    // This time R sends, and it is R that ends the stream.
+    let ssrc = send_audio(&mut r, &mut l, mid, Duration::from_secs(3))?;
+
+    r.direct_api().remove_media(mid);
+
+    for _ in 0..50 {
+        progress(&mut l, &mut r)?;
+    }
+
+    // Both stay alive, only the media is gone.
+    assert!(l.is_alive());
+    assert!(r.is_alive());
+
+    assert_eq!(ended(&l), vec![(ssrc, mid, None)]);
+    assert_eq!(ended(&r), vec![]);
+
+    match last_compound(&r).last() {
+        Some(Rtcp::Goodbye(g)) => {
+            assert!(g.reports.iter().any(|s| *s == ssrc));
+            assert_eq!(g.reason, None);
+        }
+        p => panic!("Expected BYE last, got: {:?}", p),
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_no_feedback_after_close() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup()?;
+    let ssrc = send_audio(&mut l, &mut r, mid, Duration::from_secs(3))?;
+
+    r.rtc.close(None);
+
+    // Regular reports for the receive stream are due before the BYE goes out.
+    l.last += Duration::from_secs(3);
+    r.last += Duration::from_secs(3);
+
+    for _ in 0..50 {
+        progress(&mut l, &mut r)?;
+    }
+
+    assert!(!r.is_alive());
+
+    // Only the final reports, with the BYE last.
+    let last = last_compound(&r);
+    let reports: usize = last
+        .iter()
+        .map(|p| match p {
+            Rtcp::SenderReport(sr) => sr.reports.iter().filter(|b| b.ssrc == ssrc).count(),
+            Rtcp::ReceiverReport(rr) => rr.reports.iter().filter(|b| b.ssrc == ssrc).count(),
+            _ => 0,
+        })
+        .sum();
+    assert_eq!(reports, 1, "{last:?}");
+    assert!(
+        !last
+            .iter()
+            .any(|p| matches!(p, Rtcp::Twcc(_) | Rtcp::Nack(_))),
+        "{last:?}"
+    );
+    assert!(matches!(last.last(), Some(Rtcp::Goodbye(_))), "{last:?}");
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_bye_frees_state_when_idle() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, mid) = setup()?;
+    send_audio(&mut l, &mut r, mid, Duration::from_secs(3))?;
+    let ssrc = send_audio(&mut r, &mut l, mid, Duration::from_secs(3))?;
+
+    r.direct_api().remove_media(mid);
+
+    let bye_at = l.duration();
+    while l.duration() < bye_at + Duration::from_secs(15) {
+        progress(&mut l, &mut r)?;
+    }
+
+    assert_eq!(ended(&l), vec![(ssrc, mid, None)]);
+    let ended_at = l
+        .events
+        .iter()
+        .find(|(_, e)| matches!(e, Event::StreamEnded(_)))
+        .map(|(t, _)| *t)
+        .unwrap();
+
+    // The BYE only marks the stream ended. The sender info stays until no packets
+    // have arrived for a while.
+    let sender_info: Vec<_> = l
+        .events
+        .iter()
+        .filter_map(|(t, e)| match e {
+            Event::IngressStats(s) if s.ssrc == ssrc && *t > ended_at => {
+                Some((*t - ended_at, s.last_sender_info.is_some()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    assert!(sender_info
+        .iter()
+        .filter(|(t, _)| *t < Duration::from_secs(9))
+        .all(|(_, some)| *some));
+    assert!(sender_info
+        .iter()
+        .filter(|(t, _)| *t > Duration::from_secs(11))
+        .all(|(_, some)| !*some));
+    assert!(sender_info
+        .iter()
+        .any(|(t, _)| *t > Duration::from_secs(11)));
+
+    Ok(())
+}
+
+fn setup() -> Result<(TestRtc, TestRtc, Mid), RtcError> {
+    let rtc = || {
+        RtcConfig::new()
+            .enable_raw_packets(true)
+            .set_stats_interval(Some(Duration::from_secs(1)))
+            .build()
+    };
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), rtc());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), rtc());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Audio, Direction::SendRecv, None, None)
+    });
+
+    loop {
+        if l.is_connected() || r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    Ok((l, r, mid))
+}
+
+/// Send audio from `from` for `dur`, returning the SSRC it was sent on.
+fn send_audio(
+    from: &mut TestRtc,
+    to: &mut TestRtc,
+    mid: Mid,
+    dur: Duration,
+) -> Result<Ssrc, RtcError> {
+    let pt = from.params_opus().pt();
+    let until = from.duration() + dur;
+
+    loop {
+        let wallclock = from.start + from.duration();
+        let time = from.duration().into();
+        from.writer(mid)
+            .unwrap()
+            .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+        progress(from, to)?;
+
+        if from.duration() > until {
+            break;
+        }
+    }
+
+    let ssrc = from
+        .direct_api()
+        .stream_tx_by_mid(mid, None)
+        .unwrap()
+        .ssrc();
+
+    Ok(ssrc)
+}
+
+fn ended(t: &TestRtc) -> Vec<(Ssrc, Mid, Option<String>)> {
+    t.events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::StreamEnded(e) => Some((e.ssrc, e.mid, e.reason.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The RTCP of the last compound packet sent.
+fn last_compound(t: &TestRtc) -> Vec<Rtcp> {
+    let tx: Vec<_> = t
+        .events
+        .iter()
+        .filter_map(|(time, e)| match e {
+            Event::RawPacket(p) => match &**p {
+                RawPacket::RtcpTx(rtcp) => Some((*time, rtcp.clone())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    let last = tx.last().map(|(time, _)| *time).unwrap();
+
+    tx.into_iter()
+        .filter(|(time, _)| *time == last)
+        .map(|(_, rtcp)| rtcp)
+        .collect()
+}
@@ -0,0 +1,92 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::bwe::Bitrate;
+use str0m::media::{Direction, MediaKind};
+use str0m::rtp::rtcp::{Rtcp, RtcpConfig};
+use str0m::rtp::RawPacket;
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, TestRtc};
+
+#[test]
+pub fn rtcp_interval_configured() -> Result<(), RtcError> {
+    init_log();
+
+    // 24kbit/s session gives 1.2kbit/s for RTCP. Two members with 100 byte reports
+    // compute to 1.33s, which the minimum raises to 2s.
+    let rtcp = RtcpConfig::new(Bitrate::kbps(24))
+        .set_interval_bounds(Duration::from_secs(2), Duration::from_secs(30));
+
+    let r_rtc = RtcConfig::new()
+        .set_rtcp_config(Some(rtcp))
+        .enable_raw_packets(true)
+        .build();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), r_rtc);
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Audio, Direction::SendOnly, None, None)
+    });
+
+    loop {
+        if l.is_connected() || r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_opus().pt();
+
+    loop {
+        let wallclock = l.start + l.duration();
+        let time = l.duration().into();
+        l.writer(mid)
+            .unwrap()
+            .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(12) {
+            break;
+        }
+    }
+
+    let reports: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(t, e)| match e {
+            Event::RawPacket(p) => match &**p {
+                RawPacket::RtcpTx(Rtcp::ReceiverReport(_)) => Some(*t),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    // Without the config, audio is reported every 5 seconds.
+    assert!(reports.len() >= 4, "Not enough RR: {}", reports.len());
+
+    for w in reports.windows(2) {
+        let gap = w[1] - w[0];
+        assert!(
+            gap >= Duration::from_secs(2) && gap < Duration::from_millis(2100),
+            "RR gap: {:?}",
+            gap
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::{RtcpMuxPolicy, SdpAnswer, SdpOffer};
+use str0m::error::SdpError;
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::net::Transmit;
+use str0m::rtp::rtcp::Rtcp;
+use str0m::rtp::RawPacket;
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, progress_with, TestRtc};
+
+#[test]
+pub fn rtcp_mux_only_offered() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = setup(RtcpMuxPolicy::Require)?;
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    // We can only do RTCP on the RTP port.
+    let sdp = offer.to_sdp_string();
+    assert!(sdp.contains("a=rtcp-mux\r\na=rtcp-mux-only\r\na=rtcp-rsize\r\n"));
+
+    // The answer has a=rtcp-mux only.
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    let sdp = answer.to_sdp_string();
+    assert!(sdp.contains("a=rtcp-mux\r\n"));
+    assert!(!sdp.contains("a=rtcp-mux-only"));
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    connect(&mut l, &mut r)?;
+    send_audio(&mut l, &mut r, mid, Duration::from_secs(5), |_, _| true)?;
+
+    // SR from L and RR from R over the one transport.
+    assert!(received_rtcp(&r, |p| matches!(p, Rtcp::SenderReport(_))));
+    assert!(received_rtcp(&l, |p| matches!(p, Rtcp::ReceiverReport(_))));
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_mux_negotiated() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = setup(RtcpMuxPolicy::Negotiate)?;
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    // Mux is offered, but not required, with candidates for RTCP as fallback.
+    let sdp = offer.to_sdp_string();
+    assert!(sdp.contains("a=rtcp-mux\r\na=rtcp-rsize\r\n"));
+    assert!(sdp.contains("a=candidate:") && sdp.contains(" 2 udp "));
+
+    // The answer has a=rtcp-mux, and no candidates for RTCP.
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    let sdp = answer.to_sdp_string();
+    assert!(sdp.contains("a=rtcp-mux\r\n"));
+    assert!(!sdp.contains(" 2 udp "));
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    connect(&mut l, &mut r)?;
+
+    let mut rtcp_ports = vec![];
+    send_audio(&mut l, &mut r, mid, Duration::from_secs(5), |_, t| {
+        if is_rtcp(t) {
+            rtcp_ports.push(t.source.port());
+        }
+        true
+    })?;
+
+    // Muxed, the RTCP candidates are not used.
+    assert!(received_rtcp(&r, |p| matches!(p, Rtcp::SenderReport(_))));
+    assert!(received_rtcp(&l, |p| matches!(p, Rtcp::ReceiverReport(_))));
+    assert!(rtcp_ports.iter().all(|p| *p == 1000 || *p == 2000));
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_mux_disabled_one_side() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = setup(RtcpMuxPolicy::Negotiate)?;
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    // L offers a=rtcp-mux, but R is like a legacy gateway that doesn't do it.
+    let munged = offer.to_sdp_string().replace("a=rtcp-mux\r\n", "");
+    let offer = SdpOffer::from_sdp_string(&munged).unwrap();
+
+    // R answers without a=rtcp-mux, with its candidates for RTCP.
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    let sdp = answer.to_sdp_string();
+    assert!(!sdp.contains("a=rtcp-mux"));
+    assert!(sdp.contains(" 2 udp "));
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    connect(&mut l, &mut r)?;
+
+    let mut rtcp_ports = vec![];
+    send_audio(&mut l, &mut r, mid, Duration::from_secs(5), |_, t| {
+        if is_rtcp(t) {
+            rtcp_ports.push((t.source.port(), t.destination.port()));
+        }
+        true
+    })?;
+
+    // SR from L and reception reports from R, over the second component. R also sends,
+    // so its reports can come in its SR.
+    assert!(received_rtcp(&r, |p| matches!(p, Rtcp::SenderReport(_))));
+    assert!(received_rtcp(&l, |p| match p {
+        Rtcp::ReceiverReport(_) => true,
+        Rtcp::SenderReport(sr) => !sr.reports.is_empty(),
+        _ => false,
+    }));
+
+    assert!(rtcp_ports.contains(&(1001, 2001)));
+    assert!(rtcp_ports.contains(&(2001, 1001)));
+    assert!(rtcp_ports
+        .iter()
+        .all(|p| *p == (1001, 2001) || *p == (2001, 1001)));
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_mux_disabled_without_rtcp_candidates() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, _) = setup(RtcpMuxPolicy::Negotiate)?;
+
+    let mut r = TestRtc::new_with_rtc(
+        info_span!("R"),
+        RtcConfig::new()
+            .set_rtcp_mux_policy(RtcpMuxPolicy::Negotiate)
+            .build(),
+    );
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, _) = change.apply().unwrap();
+
+    let munged = offer.to_sdp_string().replace("a=rtcp-mux\r\n", "");
+    let offer = SdpOffer::from_sdp_string(&munged).unwrap();
+
+    // Nowhere to do RTCP without mux.
+    let err = r.sdp_api().accept_offer(offer).unwrap_err();
+    assert!(matches!(err, RtcError::Sdp(SdpError::Inconsistent(_))));
+    assert!(r.media(mid).is_none());
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_mux_missing_in_offer() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = setup(RtcpMuxPolicy::Require)?;
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, _) = change.apply().unwrap();
+
+    // Like a legacy gateway that wants RTCP on a separate port.
+    let sdp = offer.to_sdp_string();
+    let munged = sdp.replace("a=rtcp-mux\r\na=rtcp-mux-only\r\n", "");
+    let offer = SdpOffer::from_sdp_string(&munged).unwrap();
+
+    let err = r.sdp_api().accept_offer(offer).unwrap_err();
+    assert!(matches!(err, RtcError::Sdp(SdpError::Inconsistent(_))));
+
+    // Nothing is changed, and the offer with a=rtcp-mux is fine.
+    assert!(r.media(mid).is_none());
+
+    let offer = SdpOffer::from_sdp_string(&sdp).unwrap();
+    r.sdp_api().accept_offer(offer).unwrap();
+    assert!(r.media(mid).is_some());
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_mux_missing_in_answer() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = setup(RtcpMuxPolicy::Require)?;
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    let munged = answer.to_sdp_string().replace("a=rtcp-mux\r\n", "");
+    let answer = SdpAnswer::from_sdp_string(&munged).unwrap();
+
+    let err = l.sdp_api().accept_answer(pending, answer).unwrap_err();
+    assert!(matches!(err, RtcError::Sdp(SdpError::Inconsistent(_))));
+
+    assert!(l.media(mid).is_none());
+
+    Ok(())
+}
+
+fn setup(policy: RtcpMuxPolicy) -> Result<(TestRtc, TestRtc), RtcError> {
+    let rtc = || {
+        RtcConfig::new()
+            .enable_raw_packets(true)
+            .set_rtcp_mux_policy(policy)
+            .build()
+    };
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), rtc());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), rtc());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    if policy == RtcpMuxPolicy::Negotiate {
+        let rtcp1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1001).into(), "udp")?;
+        let rtcp2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2001).into(), "udp")?;
+        l.add_local_candidate(rtcp1.with_rtcp_component());
+        r.add_local_candidate(rtcp2.with_rtcp_component());
+    }
+
+    Ok((l, r))
+}
+
+fn connect(l: &mut TestRtc, r: &mut TestRtc) -> Result<(), RtcError> {
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(l, r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    Ok(())
+}
+
+fn send_audio(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    mid: Mid,
+    dur: Duration,
+    mut deliver: impl FnMut(bool, &mut Transmit) -> bool,
+) -> Result<(), RtcError> {
+    let pt = l.params_opus().pt();
+    let until = l.duration() + dur;
+
+    loop {
+        let wallclock = l.start + l.duration();
+        let time = l.duration().into();
+        l.writer(mid)
+            .unwrap()
+            .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+        progress_with(l, r, &mut deliver)?;
+
+        if l.duration() > until {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// SRTCP, going by the packet type in the unencrypted header (RFC 5761 4).
+fn is_rtcp(t: &Transmit) -> bool {
+    t.contents.len() > 8 && t.contents[0] >> 6 == 2 && (200..=206).contains(&t.contents[1])
+}
+
+fn received_rtcp(t: &TestRtc, f: impl Fn(&Rtcp) -> bool) -> bool {
+    t.events.iter().any(|(_, e)| match e {
+        Event::RawPacket(p) => matches!(&**p, RawPacket::RtcpRx(rtcp) if f(rtcp)),
+        _ => false,
+    })
+}
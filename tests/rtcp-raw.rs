@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use str0m::{Event, RtcError};
+
+mod common;
+use common::{connect_l_r, init_log, progress};
+
+#[test]
+pub fn rtcp_raw_app() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = connect_l_r();
+
+    // APP, subtype 5, sender SSRC 42, name "str0", 8 bytes of application data.
+    let app: Vec<u8> = vec![
+        0x85, 204, 0, 4, //
+        0, 0, 0, 42, //
+        b's', b't', b'r', b'0', //
+        0xde, 0xad, 0xbe, 0xef, //
+        1, 2, 3, 4,
+    ];
+
+    // Not a whole number of words.
+    assert!(l.direct_api().write_raw_rtcp(&app[..19]).is_err());
+
+    l.direct_api().write_raw_rtcp(app.clone())?;
+
+    loop {
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(2) {
+            break;
+        }
+    }
+
+    let received: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RtcpPacket(p) => Some(p),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(received.len(), 1);
+    let p = received[0];
+    assert_eq!(p.packet_type(), 204);
+    assert_eq!(p.count(), 5);
+    assert_eq!(p.sender_ssrc(), Some(42.into()));
+    assert_eq!(p.data(), app);
+
+    Ok(())
+}
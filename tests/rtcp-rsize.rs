@@ -0,0 +1,179 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::SdpAnswer;
+use str0m::media::{Direction, KeyframeRequestKind, MediaKind};
+use str0m::net::Receive;
+use str0m::rtp::rtcp::Rtcp;
+use str0m::rtp::RawPacket;
+use str0m::{Candidate, Event, Input, Output, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, TestRtc};
+
+/// RTCP packet types, the second byte of the (unencrypted) SRTCP header.
+const SR: u8 = 200;
+const RR: u8 = 201;
+const PSFB: u8 = 206;
+
+#[test]
+pub fn rtcp_rsize_answered_without() -> Result<(), RtcError> {
+    init_log();
+
+    let (r, datagrams) = run(false)?;
+
+    assert!(sent_pli(&r), "PLI sent");
+
+    // Every datagram is a compound RTCP, starting with SR/RR.
+    assert!(!datagrams.is_empty());
+    for (pt, len) in datagrams {
+        assert!(pt == SR || pt == RR, "compound RTCP ({pt}, {len} bytes)");
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn rtcp_rsize_negotiated() -> Result<(), RtcError> {
+    init_log();
+
+    let (r, datagrams) = run(true)?;
+
+    assert!(sent_pli(&r), "PLI sent");
+
+    // The PLI goes alone, 12 bytes plus the SRTCP index and auth tag. An RR and SDES
+    // would be more than that.
+    assert!(datagrams
+        .iter()
+        .any(|(pt, len)| *pt == PSFB && *len <= 12 + 4 + 16));
+
+    // The regular reports are still compound.
+    assert!(datagrams.iter().any(|(pt, _)| *pt == RR));
+
+    Ok(())
+}
+
+/// R offers to receive video from L, and the answer has `a=rtcp-rsize` if `rsize`.
+///
+/// R requests a keyframe with a PLI while receiving, and R's RTCP datagrams as
+/// (first packet type, length) are returned.
+fn run(rsize: bool) -> Result<(TestRtc, Vec<(u8, usize)>), RtcError> {
+    let mut l = TestRtc::new(info_span!("L"));
+    let config = RtcConfig::new().enable_raw_packets(true);
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), config.build());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = r.sdp_api();
+    let mid = change.add_media(MediaKind::Video, Direction::RecvOnly, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    // We always offer reduced-size RTCP.
+    assert!(offer.to_sdp_string().contains("a=rtcp-rsize\r\n"));
+
+    let answer = l.sdp_api().accept_offer(offer).unwrap();
+    let mut sdp = answer.to_sdp_string();
+    assert!(sdp.contains("a=rtcp-rsize\r\n"));
+    if !rsize {
+        sdp = sdp.replace("a=rtcp-rsize\r\n", "");
+    }
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    r.sdp_api().accept_answer(pending, answer).unwrap();
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_vp8().pt();
+    let request_at = l.duration() + Duration::from_millis(1500);
+    let end = l.duration() + Duration::from_secs(3);
+    let mut requested = false;
+    let mut datagrams = vec![];
+
+    loop {
+        if l.last < r.last {
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid)
+                .unwrap()
+                .write(pt, wallclock, time, [1_u8; 100])?;
+            progress(&mut l, &mut r)?;
+        } else {
+            if !requested && r.duration() > request_at {
+                let mut api = r.direct_api();
+                let rx = api.stream_rx_by_mid(mid, None).unwrap();
+                rx.request_keyframe(KeyframeRequestKind::Pli);
+                requested = true;
+            }
+            progress_r(&mut r, &mut l, &mut datagrams)?;
+        }
+
+        if l.duration() > end {
+            break;
+        }
+    }
+
+    Ok((r, datagrams))
+}
+
+/// Like `progress()` for R, but records R's RTCP datagrams.
+fn progress_r(
+    r: &mut TestRtc,
+    l: &mut TestRtc,
+    datagrams: &mut Vec<(u8, usize)>,
+) -> Result<(), RtcError> {
+    loop {
+        r.span
+            .in_scope(|| r.rtc.handle_input(Input::Timeout(r.last)))?;
+
+        match r.span.in_scope(|| r.rtc.poll_output())? {
+            Output::Timeout(v) => {
+                let tick = r.last + Duration::from_millis(10);
+                r.last = if v == r.last { tick } else { tick.min(v) };
+                break;
+            }
+            Output::Transmit(v) => {
+                let data = v.contents;
+
+                // RTP/RTCP has version 2, and RTCP packet types are 192-223.
+                if data.len() > 8 && data[0] >> 6 == 2 && (192..=223).contains(&data[1]) {
+                    datagrams.push((data[1], data.len()));
+                }
+
+                let input = Input::Receive(
+                    r.last,
+                    Receive {
+                        proto: v.proto,
+                        source: v.source,
+                        destination: v.destination,
+                        contents: (&*data).try_into()?,
+                    },
+                );
+                l.span.in_scope(|| l.rtc.handle_input(input))?;
+            }
+            Output::Event(v) => {
+                r.events.push((r.last, v));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn sent_pli(r: &TestRtc) -> bool {
+    r.events.iter().any(|(_, e)| {
+        matches!(e,
+            Event::RawPacket(p) if matches!(&**p, RawPacket::RtcpTx(Rtcp::Pli(_))))
+    })
+}
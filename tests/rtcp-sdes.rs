@@ -0,0 +1,122 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::media::{Direction, MediaKind};
+use str0m::rtp::rtcp::{Rtcp, SdesType};
+use str0m::rtp::RawPacket;
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, TestRtc};
+
+#[test]
+pub fn rtcp_sdes_cname() -> Result<(), RtcError> {
+    init_log();
+
+    let l_rtc = RtcConfig::new()
+        .set_cname("endpoint-l")
+        .set_sdes_items(None, Some("str0m".into()))
+        .enable_raw_packets(true)
+        .build();
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), l_rtc);
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    // Video is never written to, but its SSRCs are still described.
+    let (mid_audio, mid_video) = negotiate(&mut l, &mut r, |change| {
+        let a = change.add_media(MediaKind::Audio, Direction::SendOnly, None, None);
+        let v = change.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+        (a, v)
+    });
+
+    assert_eq!(l.media(mid_audio).unwrap().cname(), "endpoint-l");
+    assert_eq!(l.media(mid_video).unwrap().cname(), "endpoint-l");
+
+    loop {
+        if l.is_connected() || r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let params = l.params_opus();
+    let pt = params.pt();
+
+    loop {
+        let wallclock = l.start + l.duration();
+        let time = l.duration().into();
+        l.writer(mid_audio)
+            .unwrap()
+            .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(10) {
+            break;
+        }
+    }
+
+    let mut tx_ssrcs = vec![];
+    for mid in [mid_audio, mid_video] {
+        let mut api = l.direct_api();
+        let stream = api.stream_tx_by_mid(mid, None).unwrap();
+        tx_ssrcs.push(stream.ssrc());
+        tx_ssrcs.extend(stream.rtx());
+    }
+    tx_ssrcs.sort();
+    assert_eq!(tx_ssrcs.len(), 3, "audio, video and video RTX");
+
+    let sdes: Vec<_> = l
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RawPacket(p) => match &**p {
+                RawPacket::RtcpTx(Rtcp::SourceDescription(d)) => Some(d),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    assert!(sdes.len() > 5, "Not enough SDES");
+
+    for d in sdes {
+        let ssrcs: Vec<_> = d.reports.iter().map(|s| s.ssrc).collect();
+        assert_eq!(ssrcs, tx_ssrcs);
+
+        for (i, s) in d.reports.iter().enumerate() {
+            let mut values = s.values.iter();
+            assert_eq!(values.next(), Some(&(SdesType::CNAME, "endpoint-l".into())));
+            if i == 0 {
+                assert_eq!(values.next(), Some(&(SdesType::TOOL, "str0m".into())));
+            }
+            assert_eq!(values.next(), None);
+        }
+    }
+
+    // The remote got the CNAME.
+    let ssrc = l
+        .direct_api()
+        .stream_tx_by_mid(mid_audio, None)
+        .unwrap()
+        .ssrc();
+    let cname = r
+        .direct_api()
+        .stream_rx(&ssrc)
+        .unwrap()
+        .cname()
+        .map(String::from);
+    assert_eq!(cname.as_deref(), Some("endpoint-l"));
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use str0m::format::Codec;
+use str0m::media::MediaKind;
+use str0m::rtp::{ExtensionValues, Ssrc};
+use str0m::{Event, RtcError};
+
+mod common;
+use common::{connect_l_r, init_log, progress};
+
+#[test]
+pub fn rtp_direct_padding() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = connect_l_r();
+
+    let mid = "aud".into();
+    let ssrc: Ssrc = 1.into();
+
+    l.direct_api().declare_media(mid, MediaKind::Audio);
+    l.direct_api().declare_stream_tx(ssrc, None, mid, None);
+
+    r.direct_api().declare_media(mid, MediaKind::Audio);
+    r.direct_api().expect_stream_rx(ssrc, None, mid, None);
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let params = l.params_opus();
+    assert_eq!(params.spec().codec, Codec::Opus);
+    let pt = params.pt();
+
+    // Media payloads of odd sizes are padded for SRTP, and padding-only packets
+    // consume sequence numbers in between.
+    let to_write: Vec<(Option<&[u8]>, u8)> = vec![
+        (Some(&[0x1, 0x2, 0x3, 0x4, 0x5]), 0),
+        (None, 1),
+        (Some(&[0x6; 21]), 0),
+        (None, 200),
+        (Some(&[0x7, 0x1]), 0),
+    ];
+
+    let mut to_write: VecDeque<_> = to_write.into();
+
+    let mut write_at = l.last + Duration::from_millis(300);
+    let mut count: u64 = 0;
+
+    loop {
+        if l.start + l.duration() > write_at {
+            write_at = l.last + Duration::from_millis(300);
+            if let Some((payload, pad_len)) = to_write.pop_front() {
+                let wallclock = l.start + l.duration();
+
+                let mut direct = l.direct_api();
+                let stream = direct.stream_tx(&ssrc).unwrap();
+
+                let time = (count * 1000 + 47_000_000) as u32;
+                let seq_no = (47_000 + count).into();
+                count += 1;
+
+                if let Some(payload) = payload {
+                    stream
+                        .write_rtp(
+                            pt,
+                            seq_no,
+                            time,
+                            wallclock,
+                            false,
+                            ExtensionValues::default(),
+                            false,
+                            payload.to_vec(),
+                        )
+                        .expect("clean write");
+                } else {
+                    stream
+                        .write_padding(pt, seq_no, time, wallclock, pad_len)
+                        .expect("clean write");
+                }
+            }
+        }
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(5) {
+            break;
+        }
+    }
+
+    let packets: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| {
+            if let Event::RtpPacket(v) = e {
+                Some(v)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    assert_eq!(packets.len(), 5);
+
+    let seq: Vec<_> = packets.iter().map(|p| p.header.sequence_number).collect();
+    assert_eq!(seq, [47000, 47001, 47002, 47003, 47004]);
+
+    // Padding never reaches the payload.
+    assert_eq!(packets[0].payload, [0x1, 0x2, 0x3, 0x4, 0x5]);
+    assert_eq!(packets[1].payload, []);
+    assert_eq!(packets[2].payload, [0x6; 21]);
+    assert_eq!(packets[3].payload, []);
+    assert_eq!(packets[4].payload, [0x7, 0x1]);
+
+    assert!(packets[1].header.has_padding);
+    assert!(packets[3].header.has_padding);
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::format::Codec;
+use str0m::media::{Direction, MediaKind};
+use str0m::rtp::ExtensionValues;
+use str0m::{Candidate, Event, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, TestRtc};
+
+#[test]
+pub fn rtp_direct_per_mid() -> Result<(), RtcError> {
+    init_log();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    // Video in RTP mode, audio stays on the sample level API.
+    let (mid_rtp, mid_sample) = negotiate(&mut l, &mut r, |change| {
+        let mid_rtp = change.add_media(MediaKind::Video, Direction::SendRecv, None, None);
+        change.set_rtp_mode(mid_rtp, true);
+        let mid_sample = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+        (mid_rtp, mid_sample)
+    });
+
+    // The remote created media takes the mode when it's announced.
+    r.sdp_api().set_rtp_mode(mid_rtp, true);
+
+    assert!(l.media(mid_rtp).unwrap().rtp_mode());
+    assert!(!l.media(mid_sample).unwrap().rtp_mode());
+    assert!(r.media(mid_rtp).unwrap().rtp_mode());
+    assert!(!r.media(mid_sample).unwrap().rtp_mode());
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt_vp8 = l.params_vp8().pt();
+    let params_opus = l.params_opus();
+    assert_eq!(params_opus.spec().codec, Codec::Opus);
+    let pt_opus = params_opus.pt();
+
+    let ssrc = l
+        .direct_api()
+        .stream_tx_by_mid(mid_rtp, None)
+        .unwrap()
+        .ssrc();
+
+    // Arbitrary bytes that are not valid VP8.
+    let payloads: Vec<Vec<u8>> = (0..50_u8)
+        .map(|i| (0..(i as usize * 7 + 1)).map(|j| i ^ (j as u8)).collect())
+        .collect();
+
+    let mut to_write = payloads.iter();
+    let mut seq_no = 1000_u64;
+
+    loop {
+        let wallclock = l.start + l.duration();
+
+        if let Some(payload) = to_write.next() {
+            let mut direct = l.direct_api();
+            let stream = direct.stream_tx(&ssrc).unwrap();
+            stream.write_rtp(
+                pt_vp8,
+                seq_no.into(),
+                seq_no as u32 * 3000,
+                wallclock,
+                true,
+                ExtensionValues::default(),
+                true,
+                payload.clone(),
+            )?;
+            seq_no += 1;
+        }
+
+        let time = l.duration().into();
+        l.writer(mid_sample)
+            .unwrap()
+            .write(pt_opus, wallclock, time, vec![1_u8; 80])?;
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(3) {
+            break;
+        }
+    }
+
+    let received: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RtpPacket(p) => Some(p),
+            _ => None,
+        })
+        .collect();
+
+    // Every packet came through as an RtpPacket, with payload unchanged.
+    assert_eq!(received.len(), payloads.len());
+    for (p, payload) in received.iter().zip(payloads.iter()) {
+        assert_eq!(p.header.payload_type, pt_vp8);
+        assert_eq!(&p.payload, payload);
+    }
+
+    // Only the audio is depacketized.
+    let media_data: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::MediaData(d) => Some(d),
+            _ => None,
+        })
+        .collect();
+
+    assert!(media_data.len() > 100, "Not enough MediaData");
+    assert!(media_data.iter().all(|d| d.mid == mid_sample));
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use str0m::media::MediaKind;
+use str0m::rtp::{ExtensionValues, Ssrc};
+use str0m::{Event, RtcError};
+
+mod common;
+use common::{connect_l_r, init_log, progress};
+
+#[test]
+pub fn rtp_direct_restart() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = connect_l_r();
+
+    let mid = "aud".into();
+    let ssrc: Ssrc = 1.into();
+
+    l.direct_api().declare_media(mid, MediaKind::Audio);
+    l.direct_api().declare_stream_tx(ssrc, None, mid, None);
+
+    r.direct_api().declare_media(mid, MediaKind::Audio);
+    r.direct_api().expect_stream_rx(ssrc, None, mid, None);
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_opus().pt();
+
+    // A single wild packet in the middle must not restart the stream, but
+    // the jump to 60_000 that continues in sequence does.
+    let to_write: Vec<(u64, u32)> = vec![
+        (47_000, 1_000),
+        (47_001, 2_000),
+        (47_002, 3_000),
+        (55_000, 3_500_000_000),
+        (47_003, 4_000),
+        (47_004, 5_000),
+        (60_000, 2_000_000_000),
+        (60_001, 2_000_001_000),
+        (60_002, 2_000_002_000),
+    ];
+
+    let mut to_write: VecDeque<_> = to_write.into();
+
+    let mut write_at = l.last + Duration::from_millis(300);
+
+    loop {
+        if l.start + l.duration() > write_at {
+            write_at = l.last + Duration::from_millis(300);
+            if let Some((seq_no, time)) = to_write.pop_front() {
+                let wallclock = l.start + l.duration();
+
+                let mut direct = l.direct_api();
+                let stream = direct.stream_tx(&ssrc).unwrap();
+
+                stream
+                    .write_rtp(
+                        pt,
+                        seq_no.into(),
+                        time,
+                        wallclock,
+                        false,
+                        ExtensionValues::default(),
+                        false,
+                        vec![1, 2, 3],
+                    )
+                    .expect("clean write");
+            }
+        }
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(5) {
+            break;
+        }
+    }
+
+    let events: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RtpPacket(v) => Some(Some(v.header.sequence_number)),
+            Event::StreamRestarted(v) => {
+                assert_eq!(v.ssrc, ssrc);
+                assert_eq!(v.mid, mid);
+                Some(None)
+            }
+            _ => None,
+        })
+        .collect();
+
+    // The restart is signaled before the packet completing the probation.
+    assert_eq!(
+        events,
+        [
+            Some(47000),
+            Some(47001),
+            Some(47002),
+            Some(55000),
+            Some(47003),
+            Some(47004),
+            Some(60000),
+            None,
+            Some(60001),
+            Some(60002),
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::Rtc;
+
+mod common;
+use common::init_log;
+
+#[test]
+fn chrome_offer_h264() {
+    init_log();
+
+    let sdp = accept(&offer(&[
+        (
+            102,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42001f",
+        ),
+        (
+            104,
+            "level-asymmetry-allowed=1;packetization-mode=0;profile-level-id=42001f",
+        ),
+        (
+            106,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+        ),
+        (
+            108,
+            "level-asymmetry-allowed=1;packetization-mode=0;profile-level-id=42e01f",
+        ),
+        (
+            127,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=4d001f",
+        ),
+        (
+            39,
+            "level-asymmetry-allowed=1;packetization-mode=0;profile-level-id=4d001f",
+        ),
+        (
+            112,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=64001f",
+        ),
+        (
+            116,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=640c1f",
+        ),
+    ]));
+
+    // Constrained high is the only one we don't have.
+    assert_eq!(pts(&sdp), vec![102, 104, 106, 108, 127, 39, 112]);
+
+    // PTs that only differ in packetization-mode are kept apart.
+    assert_eq!(
+        fmtp(&sdp, 106),
+        "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f"
+    );
+    assert_eq!(
+        fmtp(&sdp, 108),
+        "level-asymmetry-allowed=1;packetization-mode=0;profile-level-id=42e01f"
+    );
+}
+
+#[test]
+fn safari_offer_h264() {
+    init_log();
+
+    let sdp = accept(&offer(&[
+        (
+            96,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=640c1f",
+        ),
+        (
+            98,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+        ),
+    ]));
+
+    assert_eq!(pts(&sdp), vec![98]);
+}
+
+#[test]
+fn hardware_offer_h264_lower_level() {
+    init_log();
+
+    // Baseline at level 3.0, without level-asymmetry-allowed.
+    let sdp = accept(&offer(&[(
+        97,
+        "profile-level-id=42801e; packetization-mode=1",
+    )]));
+
+    assert_eq!(pts(&sdp), vec![97]);
+
+    // The answer goes down to the lower level.
+    assert_eq!(
+        fmtp(&sdp, 97),
+        "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42001e"
+    );
+}
+
+#[test]
+fn h264_level_asymmetry() {
+    init_log();
+
+    // Both sides allow asymmetry, so we keep our level.
+    let sdp = accept(&offer(&[(
+        97,
+        "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e00d",
+    )]));
+
+    assert_eq!(
+        fmtp(&sdp, 97),
+        "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f"
+    );
+}
+
+#[test]
+fn h264_unsupported_packetization_mode() {
+    init_log();
+
+    // We have no config for packetization-mode 2.
+    let sdp = accept(&offer(&[
+        (97, "packetization-mode=2;profile-level-id=42e01f"),
+        (98, "packetization-mode=1;profile-level-id=42e01f"),
+    ]));
+
+    assert_eq!(pts(&sdp), vec![98]);
+}
+
+fn accept(offer: &str) -> String {
+    let mut rtc = Rtc::new();
+    let answer = rtc
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(offer).unwrap())
+        .unwrap();
+
+    let sdp = answer.to_sdp_string();
+
+    // The answer round-trips.
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    assert_eq!(answer.to_sdp_string(), sdp);
+
+    sdp
+}
+
+/// The PTs of the m-line.
+fn pts(sdp: &str) -> Vec<u8> {
+    let line = sdp.lines().find(|l| l.starts_with("m=video")).unwrap();
+    line.split(' ')
+        .skip(3)
+        .map(|pt| pt.parse().unwrap())
+        .collect()
+}
+
+fn fmtp(sdp: &str, pt: u8) -> &str {
+    let prefix = format!("a=fmtp:{pt} ");
+    let line = sdp.lines().find(|l| l.starts_with(&prefix)).unwrap();
+    &line[prefix.len()..]
+}
+
+/// An offer with one video m-line, with H264 for each (pt, fmtp).
+fn offer(h264: &[(u8, &str)]) -> String {
+    let pts: Vec<_> = h264.iter().map(|(pt, _)| pt.to_string()).collect();
+    let codecs: String = h264
+        .iter()
+        .map(|(pt, fmtp)| {
+            format!(
+                "a=rtpmap:{pt} H264/90000\r\n\
+                a=rtcp-fb:{pt} nack\r\n\
+                a=rtcp-fb:{pt} nack pli\r\n\
+                a=fmtp:{pt} {fmtp}\r\n"
+            )
+        })
+        .collect();
+
+    format!(
+        "v=0\r\n\
+        o=- 2947165282637412589 2 IN IP4 127.0.0.1\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        a=group:BUNDLE 0\r\n\
+        a=msid-semantic: WMS\r\n\
+        m=video 9 UDP/TLS/RTP/SAVPF {}\r\n\
+        c=IN IP4 0.0.0.0\r\n\
+        a=rtcp:9 IN IP4 0.0.0.0\r\n\
+        a=ice-ufrag:Fy0d\r\n\
+        a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+        a=ice-options:trickle\r\n\
+        a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+        a=setup:actpass\r\n\
+        a=mid:0\r\n\
+        a=sendrecv\r\n\
+        a=msid:- 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+        a=rtcp-mux\r\n\
+        {codecs}",
+        pts.join(" ")
+    )
+}
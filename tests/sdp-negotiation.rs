@@ -2,7 +2,7 @@ mod common;
 use common::init_log;
 use common::negotiate;
 use common::TestRtc;
-use str0m::change::SdpOffer;
+use str0m::change::{SdpAnswer, SdpOffer};
 use str0m::format::Codec;
 use str0m::format::CodecSpec;
 use str0m::format::FormatParams;
@@ -373,6 +373,211 @@ fn media_creator_can_change_inactive_to_recvonly() {
     assert_eq!(m_r.direction(), Direction::SendOnly);
 }
 
+#[test]
+fn chrome_offer_extmap() {
+    init_log();
+
+    use Extension::*;
+
+    let mut rtc = Rtc::new();
+    let answer = rtc
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(CHROME_OFFER).unwrap())
+        .unwrap();
+
+    let sdp = answer.to_sdp_string();
+
+    // The answer round-trips.
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    assert_eq!(answer.to_sdp_string(), sdp);
+
+    // Chrome offered mixing one and two-byte headers.
+    assert!(sdp.contains("a=extmap-allow-mixed\r\n"));
+
+    // The ids in the answer are those of the offer.
+    assert!(sdp.contains("a=extmap:13 urn:3gpp:video-orientation\r\n"));
+
+    let audio = rtc.media("0".into()).unwrap().remote_extmap();
+    assert!(audio.allow_mixed());
+    assert_eq!(
+        audio.iter().collect::<Vec<_>>(),
+        vec![
+            (1, &AudioLevel),
+            (2, &AbsoluteSendTime),
+            (3, &TransportSequenceNumber),
+            (4, &RtpMid),
+        ]
+    );
+
+    let video = rtc.media("1".into()).unwrap().remote_extmap();
+    assert!(video.allow_mixed());
+    assert_eq!(
+        video.iter().collect::<Vec<_>>(),
+        vec![
+            (2, &AbsoluteSendTime),
+            (3, &TransportSequenceNumber),
+            (4, &RtpMid),
+            (10, &RtpStreamId),
+            (11, &RepairedRtpStreamId),
+            (13, &VideoOrientation),
+        ]
+    );
+    assert_eq!(video.direction(13), Some(Direction::SendRecv));
+}
+
+#[test]
+fn chrome_offer_extmap_direction() {
+    init_log();
+
+    // Chrome only sends the video orientation, and doesn't allow mixed.
+    let offer = CHROME_OFFER
+        .replace(
+            "a=extmap:13 urn:3gpp:video-orientation",
+            "a=extmap:13/sendonly urn:3gpp:video-orientation",
+        )
+        .replace("a=extmap-allow-mixed\r\n", "");
+
+    let mut rtc = Rtc::new();
+    let answer = rtc
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(&offer).unwrap())
+        .unwrap();
+
+    let sdp = answer.to_sdp_string();
+    assert!(!sdp.contains("a=extmap-allow-mixed"));
+    assert!(sdp.contains("a=extmap:13/recvonly urn:3gpp:video-orientation\r\n"));
+
+    let video = rtc.media("1".into()).unwrap().remote_extmap();
+    assert!(!video.allow_mixed());
+    assert_eq!(video.direction(13), Some(Direction::RecvOnly));
+    assert_eq!(video.direction(3), Some(Direction::SendRecv));
+}
+
+#[test]
+fn answer_extmap_not_in_offer() {
+    init_log();
+
+    use Extension::*;
+
+    let mut l = Rtc::new();
+    let mut r = Rtc::new();
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    assert!(offer.to_sdp_string().contains("a=extmap-allow-mixed\r\n"));
+
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+
+    // The answer changes the id of the audio level, and introduces an extension
+    // that wasn't offered for audio. It also doesn't allow mixed.
+    let munged = answer
+        .to_sdp_string()
+        .replace(
+            "a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level",
+            "a=extmap:5 urn:ietf:params:rtp-hdrext:ssrc-audio-level\r\n\
+            a=extmap:13 urn:3gpp:video-orientation",
+        )
+        .replace("a=extmap-allow-mixed\r\n", "");
+
+    let answer = SdpAnswer::from_sdp_string(&munged).unwrap();
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    let exts = l.media(mid).unwrap().remote_extmap();
+    assert!(!exts.allow_mixed());
+    assert_eq!(
+        exts.iter().collect::<Vec<_>>(),
+        vec![
+            (2, &AbsoluteSendTime),
+            (3, &TransportSequenceNumber),
+            (4, &RtpMid),
+            (10, &RtpStreamId),
+            (11, &RepairedRtpStreamId),
+        ]
+    );
+
+    // The session keeps the offered id.
+    assert_eq!(l._exts().id_of(AudioLevel), Some(1));
+}
+
+/// Offer from Chrome with one audio and one video m-line.
+const CHROME_OFFER: &str = "v=0\r\n\
+    o=- 2947165282637412589 2 IN IP4 127.0.0.1\r\n\
+    s=-\r\n\
+    t=0 0\r\n\
+    a=group:BUNDLE 0 1\r\n\
+    a=extmap-allow-mixed\r\n\
+    a=msid-semantic: WMS 6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c\r\n\
+    m=audio 9 UDP/TLS/RTP/SAVPF 111 63 9 0 8 13 110 126\r\n\
+    c=IN IP4 0.0.0.0\r\n\
+    a=rtcp:9 IN IP4 0.0.0.0\r\n\
+    a=ice-ufrag:Fy0d\r\n\
+    a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+    a=ice-options:trickle\r\n\
+    a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+    a=setup:actpass\r\n\
+    a=mid:0\r\n\
+    a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level\r\n\
+    a=extmap:2 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r\n\
+    a=extmap:3 http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01\r\n\
+    a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid\r\n\
+    a=sendrecv\r\n\
+    a=msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0\r\n\
+    a=rtcp-mux\r\n\
+    a=rtcp-rsize\r\n\
+    a=rtpmap:111 opus/48000/2\r\n\
+    a=rtcp-fb:111 transport-cc\r\n\
+    a=fmtp:111 minptime=10;useinbandfec=1\r\n\
+    a=rtpmap:63 red/48000/2\r\n\
+    a=fmtp:63 111/111\r\n\
+    a=rtpmap:9 G722/8000\r\n\
+    a=rtpmap:0 PCMU/8000\r\n\
+    a=rtpmap:8 PCMA/8000\r\n\
+    a=rtpmap:13 CN/8000\r\n\
+    a=rtpmap:110 telephone-event/48000\r\n\
+    a=rtpmap:126 telephone-event/8000\r\n\
+    a=ssrc:2913346011 cname:Qw1Xk9mJgRr3aT7B\r\n\
+    a=ssrc:2913346011 msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0\r\n\
+    m=video 9 UDP/TLS/RTP/SAVPF 96 97\r\n\
+    c=IN IP4 0.0.0.0\r\n\
+    a=rtcp:9 IN IP4 0.0.0.0\r\n\
+    a=ice-ufrag:Fy0d\r\n\
+    a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+    a=ice-options:trickle\r\n\
+    a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+    a=setup:actpass\r\n\
+    a=mid:1\r\n\
+    a=extmap:14 urn:ietf:params:rtp-hdrext:toffset\r\n\
+    a=extmap:2 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r\n\
+    a=extmap:13 urn:3gpp:video-orientation\r\n\
+    a=extmap:3 http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01\r\n\
+    a=extmap:5 http://www.webrtc.org/experiments/rtp-hdrext/playout-delay\r\n\
+    a=extmap:6 http://www.webrtc.org/experiments/rtp-hdrext/video-content-type\r\n\
+    a=extmap:7 http://www.webrtc.org/experiments/rtp-hdrext/video-timing\r\n\
+    a=extmap:8 http://www.webrtc.org/experiments/rtp-hdrext/color-space\r\n\
+    a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid\r\n\
+    a=extmap:10 urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id\r\n\
+    a=extmap:11 urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id\r\n\
+    a=sendrecv\r\n\
+    a=msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+    a=rtcp-mux\r\n\
+    a=rtcp-rsize\r\n\
+    a=rtpmap:96 VP8/90000\r\n\
+    a=rtcp-fb:96 goog-remb\r\n\
+    a=rtcp-fb:96 transport-cc\r\n\
+    a=rtcp-fb:96 ccm fir\r\n\
+    a=rtcp-fb:96 nack\r\n\
+    a=rtcp-fb:96 nack pli\r\n\
+    a=rtpmap:97 rtx/90000\r\n\
+    a=fmtp:97 apt=96\r\n\
+    a=ssrc-group:FID 1742589311 3528840722\r\n\
+    a=ssrc:1742589311 cname:Qw1Xk9mJgRr3aT7B\r\n\
+    a=ssrc:1742589311 msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+    a=ssrc:3528840722 cname:Qw1Xk9mJgRr3aT7B\r\n\
+    a=ssrc:3528840722 msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+    ";
+
 fn with_params(
     span_l: Span,
     params_l: &[PayloadParams],
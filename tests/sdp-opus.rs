@@ -0,0 +1,159 @@
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::format::OpusParams;
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::Rtc;
+
+mod common;
+use common::init_log;
+
+#[test]
+fn firefox_offer_opus() {
+    init_log();
+
+    let mut rtc = Rtc::new();
+    let sdp = accept(&mut rtc, FIREFOX_OFFER);
+
+    // Our minptime, the rest from Firefox.
+    assert!(
+        sdp.contains("a=fmtp:109 minptime=10;useinbandfec=1;stereo=1;maxplaybackrate=48000\r\n")
+    );
+
+    let opus = opus_params(&rtc, "0".into());
+    assert_eq!(opus.max_playback_rate, Some(48000));
+    assert_eq!(opus.max_average_bitrate, None);
+    assert_eq!(opus.stereo, Some(true));
+    assert_eq!(opus.use_inband_fec, Some(true));
+    assert_eq!(opus.use_dtx, None);
+    assert_eq!(opus.min_p_time, Some(10));
+    assert_eq!(opus.max_p_time, None);
+}
+
+#[test]
+fn chrome_offer_opus() {
+    init_log();
+
+    let offer = FIREFOX_OFFER.replace(
+        "a=fmtp:109 maxplaybackrate=48000;stereo=1;useinbandfec=1",
+        "a=fmtp:109 minptime=10;useinbandfec=1",
+    );
+
+    let mut rtc = Rtc::new();
+    let sdp = accept(&mut rtc, &offer);
+
+    assert!(sdp.contains("a=fmtp:109 minptime=10;useinbandfec=1\r\n"));
+
+    let opus = opus_params(&rtc, "0".into());
+    assert_eq!(opus.max_playback_rate, None);
+    assert_eq!(opus.stereo, None);
+    assert_eq!(opus.use_inband_fec, Some(true));
+    assert_eq!(opus.min_p_time, Some(10));
+}
+
+#[test]
+fn opus_offer_constrains() {
+    init_log();
+
+    // Lower caps, longer packets, no FEC and a key we don't know.
+    let offer = FIREFOX_OFFER.replace(
+        "a=fmtp:109 maxplaybackrate=48000;stereo=1;useinbandfec=1",
+        "a=fmtp:109 maxplaybackrate=16000;maxaveragebitrate=20000;minptime=20;maxptime=40;\
+        useinbandfec=0;usedtx=1;cbr=1",
+    );
+
+    let mut rtc = Rtc::new();
+    let sdp = accept(&mut rtc, &offer);
+
+    // The unknown key goes back as is.
+    assert!(sdp.contains(
+        "a=fmtp:109 minptime=20;maxptime=40;useinbandfec=0;usedtx=1;\
+        maxplaybackrate=16000;maxaveragebitrate=20000;cbr=1\r\n"
+    ));
+
+    let opus = opus_params(&rtc, "0".into());
+    assert_eq!(opus.max_playback_rate, Some(16000));
+    assert_eq!(opus.max_average_bitrate, Some(20000));
+    assert_eq!(opus.use_inband_fec, Some(false));
+    assert_eq!(opus.use_dtx, Some(true));
+    assert_eq!(opus.min_p_time, Some(20));
+    assert_eq!(opus.max_p_time, Some(40));
+}
+
+#[test]
+fn opus_answer_negotiated() {
+    init_log();
+
+    let mut l = Rtc::new();
+    let mut r = Rtc::new();
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    let munged = answer.to_sdp_string().replace(
+        "minptime=10;useinbandfec=1",
+        "minptime=10;useinbandfec=1;stereo=1;maxaveragebitrate=64000",
+    );
+    let answer = SdpAnswer::from_sdp_string(&munged).unwrap();
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    let opus = opus_params(&l, mid);
+    assert_eq!(opus.stereo, Some(true));
+    assert_eq!(opus.max_average_bitrate, Some(64000));
+    assert_eq!(opus.use_inband_fec, Some(true));
+
+    // Both ways, without munging.
+    let opus = opus_params(&r, mid);
+    assert_eq!(opus.stereo, None);
+    assert_eq!(opus.max_average_bitrate, None);
+    assert_eq!(opus.min_p_time, Some(10));
+}
+
+fn accept(rtc: &mut Rtc, offer: &str) -> String {
+    let answer = rtc
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(offer).unwrap())
+        .unwrap();
+
+    let sdp = answer.to_sdp_string();
+
+    // The answer round-trips.
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    assert_eq!(answer.to_sdp_string(), sdp);
+
+    sdp
+}
+
+fn opus_params(rtc: &Rtc, mid: Mid) -> OpusParams {
+    rtc.media(mid).unwrap().opus_params().unwrap().clone()
+}
+
+/// Audio offer from Firefox, with Opus, G722, PCMU, PCMA and telephone-event.
+const FIREFOX_OFFER: &str = "v=0\r\n\
+    o=mozilla...THIS_IS_SDPARTA-99.0 5186390553519439322 0 IN IP4 0.0.0.0\r\n\
+    s=-\r\n\
+    t=0 0\r\n\
+    a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+    a=group:BUNDLE 0\r\n\
+    a=ice-options:trickle\r\n\
+    a=msid-semantic:WMS *\r\n\
+    m=audio 9 UDP/TLS/RTP/SAVPF 109 9 0 8 101\r\n\
+    c=IN IP4 0.0.0.0\r\n\
+    a=sendrecv\r\n\
+    a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level\r\n\
+    a=extmap:3 urn:ietf:params:rtp-hdrext:sdes:mid\r\n\
+    a=fmtp:109 maxplaybackrate=48000;stereo=1;useinbandfec=1\r\n\
+    a=fmtp:101 0-15\r\n\
+    a=ice-pwd:a2b5f6e1c7d4e3b9a8f0c1d2e3f4a5b6\r\n\
+    a=ice-ufrag:3c9a1f0e\r\n\
+    a=mid:0\r\n\
+    a=msid:{6c5b2e1d-3f4a-4b8c-9d0e-1f2a3b4c5d6e} {0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d}\r\n\
+    a=rtcp-mux\r\n\
+    a=rtpmap:109 opus/48000/2\r\n\
+    a=rtpmap:9 G722/8000/1\r\n\
+    a=rtpmap:0 PCMU/8000\r\n\
+    a=rtpmap:8 PCMA/8000\r\n\
+    a=rtpmap:101 telephone-event/8000\r\n\
+    a=setup:actpass\r\n\
+    a=ssrc:2437183614 cname:{5d2b3c4e-1a6f-4e7b-8c9d-0e1f2a3b4c5d}\r\n\
+    ";
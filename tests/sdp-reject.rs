@@ -0,0 +1,162 @@
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::media::{Direction, MediaKind};
+use str0m::{Rtc, RtcConfig};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, TestRtc};
+
+#[test]
+fn unknown_codec_m_line_rejected() {
+    init_log();
+
+    let mut rtc = Rtc::new();
+    let sdp = accept(&mut rtc, OFFER);
+
+    // All three m-lines in order, the middle one rejected and out of the BUNDLE.
+    let mlines: Vec<_> = sdp.lines().filter(|l| l.starts_with("m=")).collect();
+    assert_eq!(
+        mlines,
+        vec![
+            "m=audio 9 UDP/TLS/RTP/SAVPF 111",
+            "m=video 0 UDP/TLS/RTP/SAVPF 96",
+            "m=video 9 UDP/TLS/RTP/SAVPF 96 97",
+        ]
+    );
+    assert!(sdp.contains("a=group:BUNDLE 0 2\r\n"));
+
+    let rejected = rtc.media("1".into()).unwrap();
+    assert!(rejected.is_disabled());
+    assert_eq!(rejected.direction(), Direction::Inactive);
+    assert!(!rtc.media("0".into()).unwrap().is_disabled());
+    assert!(!rtc.media("2".into()).unwrap().is_disabled());
+
+    // No send stream for the rejected m-line.
+    assert!(rtc
+        .direct_api()
+        .stream_tx_by_mid("1".into(), None)
+        .is_none());
+    assert!(rtc
+        .direct_api()
+        .stream_tx_by_mid("2".into(), None)
+        .is_some());
+
+    // A renegotiation keeps the rejected m-line in its place.
+    let mut change = rtc.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendOnly, None, None);
+    let (offer, _) = change.apply().unwrap();
+
+    let sdp = offer.to_sdp_string();
+    let mids: Vec<_> = sdp
+        .lines()
+        .filter_map(|l| l.strip_prefix("a=mid:"))
+        .collect();
+    assert_eq!(mids, vec!["0", "1", "2", &mid.to_string()]);
+    assert!(sdp.contains("m=video 0 "));
+
+    // The same offer again is still rejected.
+    let sdp = accept(&mut rtc, OFFER);
+    assert!(sdp.contains("m=video 0 "));
+}
+
+#[test]
+fn m_line_rejected_in_answer() {
+    init_log();
+
+    // R has no video codec.
+    let mut l = TestRtc::new(info_span!("L"));
+    let rtc = RtcConfig::new().clear_codecs().enable_opus(true).build();
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), rtc);
+
+    let (audio, video) = negotiate(&mut l, &mut r, |change| {
+        let audio = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+        let video = change.add_media(MediaKind::Video, Direction::SendRecv, None, None);
+        (audio, video)
+    });
+
+    // Both sides have the video m-line as rejected, and no streams for it.
+    for rtc in [&mut l, &mut r] {
+        assert!(!rtc.media(audio).unwrap().is_disabled());
+        assert!(rtc.media(video).unwrap().is_disabled());
+        assert!(rtc.direct_api().stream_tx_by_mid(audio, None).is_some());
+        assert!(rtc.direct_api().stream_tx_by_mid(video, None).is_none());
+    }
+
+    // Adding video later is a new m-line after the rejected one.
+    let again = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendRecv, None, None)
+    });
+
+    assert!(r.media(again).unwrap().is_disabled());
+    assert_eq!(l.media(video).unwrap().direction(), Direction::Inactive);
+}
+
+fn accept(rtc: &mut Rtc, offer: &str) -> String {
+    let answer = rtc
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(offer).unwrap())
+        .unwrap();
+
+    let sdp = answer.to_sdp_string();
+
+    // The answer round-trips.
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    assert_eq!(answer.to_sdp_string(), sdp);
+
+    sdp
+}
+
+/// Offer with audio, video with only a codec we don't have, and video with VP8.
+const OFFER: &str = "v=0\r\n\
+    o=- 2947165282637412589 2 IN IP4 127.0.0.1\r\n\
+    s=-\r\n\
+    t=0 0\r\n\
+    a=group:BUNDLE 0 1 2\r\n\
+    a=msid-semantic: WMS\r\n\
+    m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+    c=IN IP4 0.0.0.0\r\n\
+    a=rtcp:9 IN IP4 0.0.0.0\r\n\
+    a=ice-ufrag:Fy0d\r\n\
+    a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+    a=ice-options:trickle\r\n\
+    a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+    a=setup:actpass\r\n\
+    a=mid:0\r\n\
+    a=sendrecv\r\n\
+    a=msid:- 1a2b\r\n\
+    a=rtcp-mux\r\n\
+    a=rtpmap:111 opus/48000/2\r\n\
+    a=fmtp:111 minptime=10;useinbandfec=1\r\n\
+    m=video 9 UDP/TLS/RTP/SAVPF 45 46\r\n\
+    c=IN IP4 0.0.0.0\r\n\
+    a=rtcp:9 IN IP4 0.0.0.0\r\n\
+    a=ice-ufrag:Fy0d\r\n\
+    a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+    a=ice-options:trickle\r\n\
+    a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+    a=setup:actpass\r\n\
+    a=mid:1\r\n\
+    a=sendrecv\r\n\
+    a=msid:- 3c4d\r\n\
+    a=rtcp-mux\r\n\
+    a=rtpmap:45 AV2/90000\r\n\
+    a=rtcp-fb:45 nack\r\n\
+    a=rtpmap:46 rtx/90000\r\n\
+    a=fmtp:46 apt=45\r\n\
+    m=video 9 UDP/TLS/RTP/SAVPF 96 97\r\n\
+    c=IN IP4 0.0.0.0\r\n\
+    a=rtcp:9 IN IP4 0.0.0.0\r\n\
+    a=ice-ufrag:Fy0d\r\n\
+    a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+    a=ice-options:trickle\r\n\
+    a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+    a=setup:actpass\r\n\
+    a=mid:2\r\n\
+    a=sendrecv\r\n\
+    a=msid:- 5e6f\r\n\
+    a=rtcp-mux\r\n\
+    a=rtpmap:96 VP8/90000\r\n\
+    a=rtcp-fb:96 nack\r\n\
+    a=rtpmap:97 rtx/90000\r\n\
+    a=fmtp:97 apt=96\r\n\
+    ";
@@ -0,0 +1,262 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::rtp::rtcp::Rtcp;
+use str0m::rtp::{RawPacket, Ssrc};
+use str0m::{Candidate, Event, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, negotiate, progress, TestRtc};
+
+#[test]
+pub fn remove_and_readd_video() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, audio) = setup()?;
+
+    let video = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+
+    send(&mut l, &mut r, &[audio, video], Duration::from_secs(2))?;
+    assert!(received(&r, video) > 0);
+
+    let audio_ssrc = ssrc_tx(&mut l, audio);
+    let video_ssrc = ssrc_tx(&mut l, video);
+
+    // Remove the video.
+    let mut change = l.sdp_api();
+    change.remove_media(video);
+    assert!(change.has_changes());
+    let (offer, pending) = change.apply().unwrap();
+
+    let sdp = offer.to_sdp_string();
+    assert!(sdp.contains("m=video 0 "));
+    assert!(sdp.contains(&format!("a=group:BUNDLE {audio}\r\n")));
+
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+    assert!(answer.to_sdp_string().contains("m=video 0 "));
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    assert!(l.media(video).unwrap().is_disabled());
+    assert!(r.media(video).unwrap().is_disabled());
+    assert!(!r.media(audio).unwrap().is_disabled());
+
+    // Audio carries on, on the same SSRC.
+    let audio_before = received(&r, audio);
+    send(&mut l, &mut r, &[audio], Duration::from_secs(1))?;
+    assert!(received(&r, audio) > audio_before);
+    assert_eq!(ssrc_tx(&mut l, audio), audio_ssrc);
+
+    // L says BYE for the video, and R has torn down the receive stream.
+    assert!(sent_bye(&l).contains(&video_ssrc));
+    assert!(!sent_bye(&l).contains(&audio_ssrc));
+    assert!(r.direct_api().stream_rx(&video_ssrc).is_none());
+    assert!(changed(&r, video).contains(&Direction::Inactive));
+
+    // Add video again, which is a new m-line with a new mid.
+    let video_before = received(&r, video);
+    let again = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+    assert_ne!(again, video);
+    assert_eq!(l.media(again).unwrap().direction(), Direction::SendOnly);
+    assert_eq!(r.media(again).unwrap().direction(), Direction::RecvOnly);
+
+    send(&mut l, &mut r, &[audio, again], Duration::from_secs(2))?;
+    assert!(received(&r, again) > 0);
+    assert_eq!(received(&r, video), video_before);
+    assert_eq!(ssrc_tx(&mut l, audio), audio_ssrc);
+
+    // The removed m-line is kept in the SDP.
+    let (offer, _) = negotiate_offer(&mut l, again, Direction::Inactive);
+    let sdp = offer.to_sdp_string();
+    assert!(sdp.contains("m=video 0 "));
+    assert_eq!(sdp.matches("m=").count(), 3);
+
+    Ok(())
+}
+
+#[test]
+pub fn direction_change_keeps_other_streams() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, audio) = setup()?;
+
+    let video = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendRecv, None, None)
+    });
+
+    send(&mut l, &mut r, &[audio, video], Duration::from_secs(1))?;
+
+    let audio_ssrc = ssrc_tx(&mut l, audio);
+
+    negotiate(&mut l, &mut r, |change| {
+        change.set_direction(video, Direction::Inactive);
+    });
+
+    assert!(!r.media(video).unwrap().is_disabled());
+    assert_eq!(r.media(video).unwrap().direction(), Direction::Inactive);
+    assert!(changed(&r, audio).is_empty());
+
+    let audio_before = received(&r, audio);
+    send(&mut l, &mut r, &[audio], Duration::from_secs(1))?;
+    assert!(received(&r, audio) > audio_before);
+    assert_eq!(ssrc_tx(&mut l, audio), audio_ssrc);
+
+    Ok(())
+}
+
+#[test]
+pub fn remote_reuses_removed_m_line() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r, audio) = setup()?;
+
+    let video = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Video, Direction::SendOnly, None, None)
+    });
+
+    negotiate(&mut l, &mut r, |change| {
+        change.remove_media(video);
+    });
+
+    // R offers new video, and (like some browsers) reuses the slot of the removed m-line.
+    let mut change = r.sdp_api();
+    let again = change.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    let sdp = offer.to_sdp_string();
+    let start = sdp.find("m=video 0 ").unwrap();
+    let end = start + sdp[start..].find("m=video 9 ").unwrap();
+    let munged = format!("{}{}", &sdp[..start], &sdp[end..]);
+
+    let offer = SdpOffer::from_sdp_string(&munged).unwrap();
+    let answer = l.sdp_api().accept_offer(offer).unwrap();
+
+    let sdp = answer.to_sdp_string();
+    assert_eq!(sdp.matches("m=").count(), 2);
+    assert!(!sdp.contains("m=video 0 "));
+
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    r.sdp_api().accept_answer(pending, answer).unwrap();
+
+    assert!(l.media(video).is_none());
+    assert!(r.media(video).is_none());
+    assert_eq!(l.media(again).unwrap().direction(), Direction::RecvOnly);
+
+    send(&mut r, &mut l, &[audio, again], Duration::from_secs(2))?;
+    assert!(received(&l, again) > 0);
+
+    Ok(())
+}
+
+fn setup() -> Result<(TestRtc, TestRtc, Mid), RtcError> {
+    let rtc = || RtcConfig::new().enable_raw_packets(true).build();
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), rtc());
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), rtc());
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        change.add_media(MediaKind::Audio, Direction::SendRecv, None, None)
+    });
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    Ok((l, r, mid))
+}
+
+/// Send on each of the mids from `from` for `dur`.
+fn send(from: &mut TestRtc, to: &mut TestRtc, mids: &[Mid], dur: Duration) -> Result<(), RtcError> {
+    let until = from.duration() + dur;
+
+    loop {
+        for mid in mids {
+            let kind = from.media(*mid).unwrap().kind();
+            let pt = if kind.is_audio() {
+                from.params_opus().pt()
+            } else {
+                from.params_vp8().pt()
+            };
+
+            let wallclock = from.start + from.duration();
+            let time = from.duration().into();
+            from.writer(*mid)
+                .unwrap()
+                .write(pt, wallclock, time, vec![1_u8; 80])?;
+        }
+
+        progress(from, to)?;
+
+        if from.duration() > until {
+            break;
+        }
+    }
+
+    // Let the last packets arrive.
+    for _ in 0..20 {
+        progress(from, to)?;
+    }
+
+    Ok(())
+}
+
+/// Start an offer changing the direction, without completing the negotiation.
+fn negotiate_offer(
+    t: &mut TestRtc,
+    mid: Mid,
+    dir: Direction,
+) -> (SdpOffer, str0m::change::SdpPendingOffer) {
+    let mut change = t.sdp_api();
+    change.set_direction(mid, dir);
+    change.apply().unwrap()
+}
+
+fn ssrc_tx(t: &mut TestRtc, mid: Mid) -> Ssrc {
+    t.direct_api().stream_tx_by_mid(mid, None).unwrap().ssrc()
+}
+
+fn received(t: &TestRtc, mid: Mid) -> usize {
+    t.events
+        .iter()
+        .filter(|(_, e)| matches!(e, Event::MediaData(d) if d.mid == mid))
+        .count()
+}
+
+fn changed(t: &TestRtc, mid: Mid) -> Vec<Direction> {
+    t.events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::MediaChanged(c) if c.mid == mid => Some(c.direction),
+            _ => None,
+        })
+        .collect()
+}
+
+fn sent_bye(t: &TestRtc) -> Vec<Ssrc> {
+    t.events
+        .iter()
+        .filter_map(|(_, e)| match e.as_raw_packet() {
+            Some(RawPacket::RtcpTx(Rtcp::Goodbye(g))) => Some(g.reports.iter().copied()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
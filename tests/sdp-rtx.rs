@@ -0,0 +1,156 @@
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::rtp::Ssrc;
+use str0m::Rtc;
+
+mod common;
+use common::init_log;
+
+const MAIN: u32 = 1742589311;
+const RTX: u32 = 3528840722;
+
+#[test]
+fn chrome_offer_rtx() {
+    init_log();
+
+    let mut rtc = Rtc::new();
+    let sdp = accept(&mut rtc, CHROME_OFFER);
+
+    // The answer keeps the RTX of the offer, for all three codecs.
+    assert!(sdp.contains("a=fmtp:97 apt=96\r\n"));
+    assert!(sdp.contains("a=fmtp:99 apt=98\r\n"));
+    assert!(sdp.contains("a=fmtp:103 apt=102\r\n"));
+
+    // And our own send stream has an RTX.
+    assert!(sdp.contains("a=ssrc-group:FID "));
+
+    assert_eq!(rtx_rx(&mut rtc, MAIN), Some(RTX.into()));
+}
+
+#[test]
+fn rtx_added_in_renegotiation() {
+    init_log();
+
+    // The first offer has no RTX SSRC.
+    let first = CHROME_OFFER
+        .replace(&format!("a=ssrc-group:FID {MAIN} {RTX}\r\n"), "")
+        .replace(&format!("a=ssrc:{RTX} cname:Qw1Xk9mJgRr3aT7B\r\n"), "");
+
+    let mut rtc = Rtc::new();
+    accept(&mut rtc, &first);
+    assert_eq!(rtx_rx(&mut rtc, MAIN), None);
+
+    accept(&mut rtc, CHROME_OFFER);
+    assert_eq!(rtx_rx(&mut rtc, MAIN), Some(RTX.into()));
+}
+
+#[test]
+fn rtx_missing_apt() {
+    init_log();
+
+    // None of the RTX PTs have an apt, which is not an error, but there is no RTX.
+    let offer = CHROME_OFFER
+        .replace("a=fmtp:97 apt=96\r\n", "")
+        .replace("a=fmtp:99 apt=98\r\n", "")
+        .replace("a=fmtp:103 apt=102\r\n", "");
+
+    let mut rtc = Rtc::new();
+    let sdp = accept(&mut rtc, &offer);
+
+    assert!(!sdp.contains("apt="));
+    assert_eq!(rtx_rx(&mut rtc, MAIN), None);
+}
+
+#[test]
+fn rtx_dangling_fid() {
+    init_log();
+
+    // The FID is for an SSRC that isn't declared.
+    let offer = CHROME_OFFER.replace(
+        &format!("a=ssrc-group:FID {MAIN} {RTX}"),
+        &format!("a=ssrc-group:FID 1234 {RTX}"),
+    );
+
+    let mut rtc = Rtc::new();
+    accept(&mut rtc, &offer);
+
+    assert_eq!(rtx_rx(&mut rtc, MAIN), None);
+    assert!(rtc.direct_api().stream_rx(&1234.into()).is_none());
+}
+
+fn accept(rtc: &mut Rtc, offer: &str) -> String {
+    let answer = rtc
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(offer).unwrap())
+        .unwrap();
+
+    let sdp = answer.to_sdp_string();
+
+    // The answer round-trips.
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    assert_eq!(answer.to_sdp_string(), sdp);
+
+    sdp
+}
+
+fn rtx_rx(rtc: &mut Rtc, ssrc: u32) -> Option<Ssrc> {
+    rtc.direct_api().stream_rx(&ssrc.into())?.rtx()
+}
+
+/// Offer from Chrome with VP8, VP9 and H264, each with RTX.
+const CHROME_OFFER: &str = "v=0\r\n\
+    o=- 2947165282637412589 2 IN IP4 127.0.0.1\r\n\
+    s=-\r\n\
+    t=0 0\r\n\
+    a=group:BUNDLE 0\r\n\
+    a=extmap-allow-mixed\r\n\
+    a=msid-semantic: WMS 6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c\r\n\
+    m=video 9 UDP/TLS/RTP/SAVPF 96 97 98 99 102 103\r\n\
+    c=IN IP4 0.0.0.0\r\n\
+    a=rtcp:9 IN IP4 0.0.0.0\r\n\
+    a=ice-ufrag:Fy0d\r\n\
+    a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+    a=ice-options:trickle\r\n\
+    a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+    a=setup:actpass\r\n\
+    a=mid:0\r\n\
+    a=extmap:2 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r\n\
+    a=extmap:3 http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01\r\n\
+    a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid\r\n\
+    a=extmap:10 urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id\r\n\
+    a=extmap:11 urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id\r\n\
+    a=sendrecv\r\n\
+    a=msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+    a=rtcp-mux\r\n\
+    a=rtcp-rsize\r\n\
+    a=rtpmap:96 VP8/90000\r\n\
+    a=rtcp-fb:96 goog-remb\r\n\
+    a=rtcp-fb:96 transport-cc\r\n\
+    a=rtcp-fb:96 ccm fir\r\n\
+    a=rtcp-fb:96 nack\r\n\
+    a=rtcp-fb:96 nack pli\r\n\
+    a=rtpmap:97 rtx/90000\r\n\
+    a=fmtp:97 apt=96\r\n\
+    a=rtpmap:98 VP9/90000\r\n\
+    a=rtcp-fb:98 goog-remb\r\n\
+    a=rtcp-fb:98 transport-cc\r\n\
+    a=rtcp-fb:98 ccm fir\r\n\
+    a=rtcp-fb:98 nack\r\n\
+    a=rtcp-fb:98 nack pli\r\n\
+    a=fmtp:98 profile-id=0\r\n\
+    a=rtpmap:99 rtx/90000\r\n\
+    a=fmtp:99 apt=98\r\n\
+    a=rtpmap:102 H264/90000\r\n\
+    a=rtcp-fb:102 goog-remb\r\n\
+    a=rtcp-fb:102 transport-cc\r\n\
+    a=rtcp-fb:102 ccm fir\r\n\
+    a=rtcp-fb:102 nack\r\n\
+    a=rtcp-fb:102 nack pli\r\n\
+    a=fmtp:102 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42001f\r\n\
+    a=rtpmap:103 rtx/90000\r\n\
+    a=fmtp:103 apt=102\r\n\
+    a=ssrc-group:FID 1742589311 3528840722\r\n\
+    a=ssrc:1742589311 cname:Qw1Xk9mJgRr3aT7B\r\n\
+    a=ssrc:1742589311 msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+    a=ssrc:3528840722 cname:Qw1Xk9mJgRr3aT7B\r\n\
+    a=ssrc:3528840722 msid:6e3d5b4b-6a1f-4b7e-9e57-2a7f0f4e1d3c 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+    ";
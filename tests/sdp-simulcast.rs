@@ -0,0 +1,213 @@
+use str0m::change::{SdpAnswer, SdpOffer};
+use str0m::media::{Direction, MediaKind, Mid, Rid, Rids, Simulcast};
+use str0m::Rtc;
+
+mod common;
+use common::{init_log, negotiate, TestRtc};
+
+#[test]
+fn chrome_simulcast_offer() {
+    init_log();
+
+    let offer = offer(
+        "a=rid:q send\r\n\
+        a=rid:h send\r\n\
+        a=rid:f send\r\n\
+        a=simulcast:send q;h;f\r\n",
+    );
+
+    let (rtc, sdp) = accept(&offer);
+
+    assert!(sdp.contains("a=rid:q recv\r\na=rid:h recv\r\na=rid:f recv\r\n"));
+    assert!(sdp.contains("a=simulcast:recv q;h;f\r\n"));
+
+    assert_eq!(rids_rx(&rtc, "1".into()), vec!["q", "h", "f"]);
+}
+
+#[test]
+fn firefox_simulcast_offer() {
+    init_log();
+
+    // Older Firefox writes "rid=" and a space before the direction. The "m" layer
+    // is paused, and there is a rid line without an a=simulcast entry.
+    let offer = offer(
+        "a=rid:h send\r\n\
+        a=rid:m send\r\n\
+        a=rid:l send\r\n\
+        a=rid:x send\r\n\
+        a=simulcast: send rid=h;~m;l\r\n",
+    );
+
+    let (rtc, sdp) = accept(&offer);
+
+    assert!(sdp.contains("a=simulcast:recv h;~m;l\r\n"));
+    assert!(!sdp.contains("a=rid:x"));
+    assert_eq!(rids_rx(&rtc, "1".into()), vec!["h", "m", "l"]);
+}
+
+#[test]
+fn safari_simulcast_offer() {
+    init_log();
+
+    // Restrictions per layer, one unknown key, one layer in the a=simulcast without
+    // a rid line and an invalid rid token.
+    let offer = offer(
+        "a=rid:l send max-width=320;max-height=180;x-foo=1\r\n\
+        a=rid:m send max-width=640;max-height=360;max-br=500000\r\n\
+        a=rid:h send max-fps=30\r\n\
+        a=simulcast:send l;m;h;u;b@d\r\n",
+    );
+
+    let (rtc, sdp) = accept(&offer);
+
+    // The answer agrees to the restrictions it understands.
+    assert!(sdp.contains("a=rid:l recv max-width=320;max-height=180\r\n"));
+    assert!(sdp.contains("a=rid:m recv max-width=640;max-height=360;max-br=500000\r\n"));
+    assert!(sdp.contains("a=rid:h recv max-fps=30\r\n"));
+    assert!(sdp.contains("a=simulcast:recv l;m;h\r\n"));
+    assert!(!sdp.contains("x-foo"));
+
+    assert_eq!(rids_rx(&rtc, "1".into()), vec!["l", "m", "h"]);
+}
+
+#[test]
+fn simulcast_answer_prunes_layer() {
+    init_log();
+
+    let mut l = TestRtc::new(info_span("L"));
+    let mut r = TestRtc::new(info_span("R"));
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+    change.set_simulcast(mid, layers(&["h", "m", "l"]));
+    let (offer, pending) = change.apply().unwrap();
+
+    let answer = r.sdp_api().accept_offer(offer).unwrap();
+
+    // The answerer doesn't want the "l" layer.
+    let munged = answer
+        .to_sdp_string()
+        .replace("a=rid:l recv\r\n", "")
+        .replace("a=simulcast:recv h;m;l", "a=simulcast:recv h;m");
+
+    let answer = SdpAnswer::from_sdp_string(&munged).unwrap();
+    l.sdp_api().accept_answer(pending, answer).unwrap();
+
+    assert_eq!(rids_tx(&mut l, mid), vec!["h", "m"]);
+}
+
+#[test]
+fn simulcast_renegotiate_layers() {
+    init_log();
+
+    let mut l = TestRtc::new(info_span("L"));
+    let mut r = TestRtc::new(info_span("R"));
+
+    let mid = negotiate(&mut l, &mut r, |change| {
+        let mid = change.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+        change.set_simulcast(mid, layers(&["h", "m", "l"]));
+        mid
+    });
+
+    assert_eq!(rids_tx(&mut l, mid), vec!["h", "m", "l"]);
+    assert_eq!(rids_rx(&r, mid), vec!["h", "m", "l"]);
+
+    // Remove a layer.
+    negotiate(&mut l, &mut r, |change| {
+        change.set_simulcast(mid, layers(&["h", "m"]));
+    });
+
+    assert_eq!(rids_tx(&mut l, mid), vec!["h", "m"]);
+    assert_eq!(rids_rx(&r, mid), vec!["h", "m"]);
+
+    // And add two.
+    negotiate(&mut l, &mut r, |change| {
+        change.set_simulcast(mid, layers(&["h", "m", "l", "xl"]));
+    });
+
+    assert_eq!(rids_tx(&mut l, mid), vec!["h", "m", "l", "xl"]);
+    assert_eq!(rids_rx(&r, mid), vec!["h", "m", "l", "xl"]);
+
+    // Setting the same again is not a change.
+    let mut change = l.sdp_api();
+    change.set_simulcast(mid, layers(&["h", "m", "l", "xl"]));
+    assert!(!change.has_changes());
+}
+
+fn layers(send: &[&str]) -> Simulcast {
+    Simulcast {
+        send: send.iter().map(|s| Rid::from(*s)).collect(),
+        recv: vec![],
+    }
+}
+
+fn accept(offer: &str) -> (Rtc, String) {
+    let mut rtc = Rtc::new();
+    let answer = rtc
+        .sdp_api()
+        .accept_offer(SdpOffer::from_sdp_string(offer).unwrap())
+        .unwrap();
+
+    let sdp = answer.to_sdp_string();
+
+    // The answer round-trips.
+    let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+    assert_eq!(answer.to_sdp_string(), sdp);
+
+    (rtc, sdp)
+}
+
+fn rids_rx(rtc: &Rtc, mid: Mid) -> Vec<String> {
+    match rtc.media(mid).unwrap().rids_rx() {
+        Rids::Specific(v) => v.iter().map(|r| r.to_string()).collect(),
+        Rids::Any => panic!("Expected specific rids"),
+    }
+}
+
+fn rids_tx(rtc: &mut Rtc, mid: Mid) -> Vec<String> {
+    let candidates = ["h", "m", "l", "xl"];
+    let mut api = rtc.direct_api();
+    candidates
+        .iter()
+        .filter(|rid| api.stream_tx_by_mid(mid, Some((**rid).into())).is_some())
+        .map(|rid| rid.to_string())
+        .collect()
+}
+
+fn info_span(name: &'static str) -> tracing::Span {
+    tracing::info_span!("", name)
+}
+
+/// An offer with one video m-line and the given simulcast attributes.
+fn offer(simulcast: &str) -> String {
+    format!(
+        "v=0\r\n\
+        o=- 2947165282637412589 2 IN IP4 127.0.0.1\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        a=group:BUNDLE 1\r\n\
+        a=msid-semantic: WMS\r\n\
+        m=video 9 UDP/TLS/RTP/SAVPF 96 97\r\n\
+        c=IN IP4 0.0.0.0\r\n\
+        a=rtcp:9 IN IP4 0.0.0.0\r\n\
+        a=ice-ufrag:Fy0d\r\n\
+        a=ice-pwd:Xo/xTwJlMf6u5x4KzCYj0+aS\r\n\
+        a=ice-options:trickle\r\n\
+        a=fingerprint:sha-256 8C:64:ED:03:76:D0:3D:B4:C1:5B:3E:A0:1A:7E:B0:05:9F:56:F6:97:9C:B5:B1:A6:4C:0A:8C:4C:DB:95:8E:20\r\n\
+        a=setup:actpass\r\n\
+        a=mid:1\r\n\
+        a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid\r\n\
+        a=extmap:10 urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id\r\n\
+        a=extmap:11 urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id\r\n\
+        a=sendonly\r\n\
+        a=msid:- 9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4\r\n\
+        a=rtcp-mux\r\n\
+        a=rtcp-rsize\r\n\
+        a=rtpmap:96 VP8/90000\r\n\
+        a=rtcp-fb:96 nack\r\n\
+        a=rtcp-fb:96 nack pli\r\n\
+        a=rtpmap:97 rtx/90000\r\n\
+        a=fmtp:97 apt=96\r\n\
+        {simulcast}"
+    )
+}
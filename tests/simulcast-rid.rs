@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use str0m::media::{MediaKind, Mid, Rid};
+use str0m::rtp::{ExtensionValues, LayerDiscovered, Ssrc};
+use str0m::{Event, RtcError};
+
+mod common;
+use common::{connect_l_r, init_log, progress, TestRtc};
+
+#[test]
+pub fn simulcast_rid_three_layers() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = connect_l_r();
+
+    let mid: Mid = "vid".into();
+    let rids: [Rid; 3] = ["f".into(), "h".into(), "q".into()];
+    let ssrcs: [Ssrc; 3] = [100.into(), 200.into(), 300.into()];
+
+    // No SSRC is signalled to R, only the rids to expect.
+    l.direct_api().declare_media(mid, MediaKind::Video);
+    let mut api = r.direct_api();
+    let media = api.declare_media(mid, MediaKind::Video);
+    for rid in rids {
+        media.expect_rid(rid);
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    // The first packets of "f" come without rid, and can't be attributed to a layer.
+    for n in 0..3 {
+        send(&mut l, &mut r, ssrcs[0], None, seq(0, n))?;
+    }
+
+    // The layers interleaved, like a browser sends them.
+    for n in 3..10 {
+        for (i, (ssrc, rid)) in ssrcs.iter().zip(rids).enumerate() {
+            send(&mut l, &mut r, *ssrc, Some(rid), seq(i, n))?;
+        }
+    }
+
+    // The sender restarts "q" on a new SSRC.
+    let restarted: Ssrc = 301.into();
+    for n in 0..5 {
+        send(&mut l, &mut r, restarted, Some(rids[2]), seq(3, n))?;
+    }
+
+    for _ in 0..100 {
+        progress(&mut l, &mut r)?;
+    }
+
+    assert_eq!(
+        discovered(&r),
+        vec![
+            LayerDiscovered {
+                mid,
+                rid: rids[0],
+                ssrc: ssrcs[0]
+            },
+            LayerDiscovered {
+                mid,
+                rid: rids[1],
+                ssrc: ssrcs[1]
+            },
+            LayerDiscovered {
+                mid,
+                rid: rids[2],
+                ssrc: ssrcs[2]
+            },
+            LayerDiscovered {
+                mid,
+                rid: rids[2],
+                ssrc: restarted
+            },
+        ]
+    );
+
+    // Each layer is a separate stream.
+    for (rid, ssrc) in rids.iter().zip([ssrcs[0], ssrcs[1], restarted]) {
+        let mut api = r.direct_api();
+        let stream = api.stream_rx_by_mid(mid, Some(*rid)).unwrap();
+        assert_eq!(stream.ssrc(), ssrc);
+    }
+
+    // Every packet is for the layer it was sent on, the early ones dropped.
+    let mut received: Vec<(Rid, u16)> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RtpPacket(p) => Some((p.header.ext_vals.rid?, p.header.sequence_number)),
+            _ => None,
+        })
+        .collect();
+    received.sort_by_key(|(rid, n)| (rid.to_string(), *n));
+
+    let mut expected = vec![];
+    for (i, rid) in rids.iter().enumerate() {
+        expected.extend((3..10).map(|n| (*rid, seq(i, n))));
+    }
+    expected.extend((0..5).map(|n| (rids[2], seq(3, n))));
+    expected.sort_by_key(|(rid, n)| (rid.to_string(), *n));
+
+    assert_eq!(received, expected);
+    assert_eq!(r.rtc.stats().rtp_packets_unmapped, 3);
+
+    Ok(())
+}
+
+/// Sequence number `n` for a layer, where the restarted "q" is layer 3.
+fn seq(layer: usize, n: u16) -> u16 {
+    1000 * (layer as u16 + 1) + n
+}
+
+/// Send one packet on `ssrc` with `rid`.
+///
+/// The sender only has one stream at a time per mid, which is why it's declared per packet.
+fn send(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    ssrc: Ssrc,
+    rid: Option<Rid>,
+    seq_no: u16,
+) -> Result<(), RtcError> {
+    let mid = "vid".into();
+    let pt = l.params_vp8().pt();
+    let wallclock = l.start + l.duration();
+    let time = seq_no as u32 * 3000;
+
+    let mut api = l.direct_api();
+    let stream = api.declare_stream_tx(ssrc, None, mid, rid);
+    stream.write_rtp(
+        pt,
+        (seq_no as u64).into(),
+        time,
+        wallclock,
+        true,
+        ExtensionValues::default(),
+        true,
+        vec![1, 2, 3, 4],
+    )?;
+
+    let until = l.duration() + Duration::from_millis(10);
+    while l.duration() < until {
+        progress(l, r)?;
+    }
+
+    assert!(l.direct_api().remove_stream_tx(ssrc));
+
+    Ok(())
+}
+
+fn discovered(t: &TestRtc) -> Vec<LayerDiscovered> {
+    t.events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::LayerDiscovered(d) => Some(d.clone()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,245 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::change::SdpOffer;
+use str0m::media::{MediaKind, Mid, Rid};
+use str0m::rtp::{Extension, ExtensionValues, LayerDiscovered, Ssrc};
+use str0m::{Candidate, Event, Rtc, RtcConfig, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, TestRtc};
+
+/// An offer like older libWebRTC makes for simulcast, without mid or rid header extensions.
+const OFFER: &str = "v=0\r\n\
+o=- 4611731400430051336 2 IN IP4 127.0.0.1\r\n\
+s=-\r\n\
+t=0 0\r\n\
+a=group:BUNDLE 0\r\n\
+a=msid-semantic: WMS stream\r\n\
+m=video 9 UDP/TLS/RTP/SAVPF 96 97\r\n\
+c=IN IP4 0.0.0.0\r\n\
+a=rtcp:9 IN IP4 0.0.0.0\r\n\
+a=ice-ufrag:{ufrag}\r\n\
+a=ice-pwd:{pass}\r\n\
+a=ice-options:trickle\r\n\
+a=fingerprint:{fingerprint}\r\n\
+a=setup:actpass\r\n\
+a=mid:0\r\n\
+a=extmap:2 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r\n\
+a=extmap:3 http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01\r\n\
+a=sendonly\r\n\
+a=rtcp-mux\r\n\
+a=rtcp-rsize\r\n\
+a=rtpmap:96 VP8/90000\r\n\
+a=rtcp-fb:96 goog-remb\r\n\
+a=rtcp-fb:96 transport-cc\r\n\
+a=rtcp-fb:96 ccm fir\r\n\
+a=rtcp-fb:96 nack\r\n\
+a=rtcp-fb:96 nack pli\r\n\
+a=rtpmap:97 rtx/90000\r\n\
+a=fmtp:97 apt=96\r\n\
+a=ssrc-group:SIM 1000 2000 3000\r\n\
+a=ssrc-group:FID 3000 3001\r\n\
+a=ssrc-group:FID 1000 1001\r\n\
+a=ssrc-group:FID 2000 2001\r\n\
+a=ssrc:1000 cname:Sp1kTuXM0jBXZt2P\r\n\
+a=ssrc:1000 msid:stream track\r\n\
+a=ssrc:1001 cname:Sp1kTuXM0jBXZt2P\r\n\
+a=ssrc:1001 msid:stream track\r\n\
+a=ssrc:2000 cname:Sp1kTuXM0jBXZt2P\r\n\
+a=ssrc:2000 msid:stream track\r\n\
+a=ssrc:2001 cname:Sp1kTuXM0jBXZt2P\r\n\
+a=ssrc:2001 msid:stream track\r\n\
+a=ssrc:3000 cname:Sp1kTuXM0jBXZt2P\r\n\
+a=ssrc:3000 msid:stream track\r\n\
+a=ssrc:3001 cname:Sp1kTuXM0jBXZt2P\r\n\
+a=ssrc:3001 msid:stream track\r\n\
+a=x-google-flag:conference\r\n\
+";
+
+#[test]
+pub fn ssrc_group_sim_layers() -> Result<(), RtcError> {
+    init_log();
+
+    let (mut l, mut r) = setup()?;
+
+    let mid: Mid = "0".into();
+    let rids: [Rid; 3] = ["0".into(), "1".into(), "2".into()];
+    let ssrcs: [(Ssrc, Ssrc); 3] = [
+        (1000.into(), 1001.into()),
+        (2000.into(), 2001.into()),
+        (3000.into(), 3001.into()),
+    ];
+    let unknown: Ssrc = 4000.into();
+
+    // The layers are known from the SDP, in the SIM group order.
+    assert_eq!(
+        discovered(&r),
+        rids.iter()
+            .zip(ssrcs)
+            .map(|(rid, (ssrc, _))| LayerDiscovered {
+                mid,
+                rid: *rid,
+                ssrc,
+            })
+            .collect::<Vec<_>>()
+    );
+
+    for (rid, (ssrc, rtx)) in rids.iter().zip(ssrcs) {
+        let mut api = r.direct_api();
+        let stream = api.stream_rx_by_mid(mid, Some(*rid)).unwrap();
+        assert_eq!(stream.ssrc(), ssrc);
+        assert_eq!(stream.rtx(), Some(rtx));
+    }
+
+    // The layers interleaved, and one SSRC the SDP doesn't mention.
+    for n in 0..5 {
+        for (i, (ssrc, _)) in ssrcs.iter().enumerate() {
+            send(&mut l, &mut r, mid, *ssrc, seq(i, n))?;
+        }
+        send(&mut l, &mut r, mid, unknown, seq(3, n))?;
+    }
+
+    for _ in 0..100 {
+        progress(&mut l, &mut r)?;
+    }
+
+    // Every packet is received on the SSRC it was sent on.
+    let mut received: Vec<(Ssrc, u16)> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RtpPacket(p) => Some((p.header.ssrc, p.header.sequence_number)),
+            _ => None,
+        })
+        .collect();
+    received.sort_by_key(|(ssrc, n)| (**ssrc, *n));
+
+    let mut expected = vec![];
+    for (i, ssrc) in ssrcs.iter().map(|(s, _)| *s).chain([unknown]).enumerate() {
+        expected.extend((0..5).map(|n| (ssrc, seq(i, n))));
+    }
+
+    assert_eq!(received, expected);
+
+    // The unknown SSRC is a single stream without rid.
+    let mut api = r.direct_api();
+    let stream = api.stream_rx(&unknown).unwrap();
+    assert_eq!(stream.rid(), None);
+
+    assert_eq!(r.rtc.stats().ssrc_group_fallbacks, 1);
+    assert_eq!(r.rtc.stats().rtp_packets_unmapped, 0);
+
+    Ok(())
+}
+
+/// L is an older libWebRTC sending simulcast to R, using only SSRCs to tell the layers apart.
+fn setup() -> Result<(TestRtc, TestRtc), RtcError> {
+    let l_rtc = Rtc::builder()
+        .set_rtp_mode(true)
+        .clear_extension_map()
+        .set_extension(2, Extension::AbsoluteSendTime)
+        .set_extension(3, Extension::TransportSequenceNumber)
+        .build();
+    let r_rtc = RtcConfig::new().set_rtp_mode(true).build();
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), l_rtc);
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), r_rtc);
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1.clone());
+    l.add_remote_candidate(host2.clone());
+    r.add_local_candidate(host2);
+    r.add_remote_candidate(host1);
+
+    let creds = l.direct_api().local_ice_credentials();
+    let fingerprint = l.direct_api().local_dtls_fingerprint();
+    let offer = OFFER
+        .replace("{ufrag}", &creds.ufrag)
+        .replace("{pass}", &creds.pass)
+        .replace("{fingerprint}", &fingerprint.to_string());
+
+    let offer = SdpOffer::from_sdp_string(&offer).unwrap();
+    let answer = r.sdp_api().accept_offer(offer)?;
+    let r_active = answer.to_sdp_string().contains("a=setup:active");
+
+    let creds = r.direct_api().local_ice_credentials();
+    let fingerprint = r.direct_api().local_dtls_fingerprint();
+
+    let mut api = l.direct_api();
+    api.set_remote_ice_credentials(creds);
+    api.set_remote_fingerprint(fingerprint);
+    api.set_ice_controlling(true);
+    api.start_dtls(!r_active)?;
+    api.declare_media("0".into(), MediaKind::Video);
+
+    // Until both polled the events of connecting.
+    let connected = |t: &TestRtc| t.events.iter().any(|(_, e)| matches!(e, Event::Connected));
+
+    loop {
+        if connected(&l) && connected(&r) {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    Ok((l, r))
+}
+
+/// Sequence number `n` for a layer, where the unknown SSRC is layer 3.
+fn seq(layer: usize, n: u16) -> u16 {
+    1000 * (layer as u16 + 1) + n
+}
+
+/// Send one packet on `ssrc`, without mid or rid.
+///
+/// The sender only has one stream at a time per mid, which is why it's declared per packet.
+fn send(
+    l: &mut TestRtc,
+    r: &mut TestRtc,
+    mid: Mid,
+    ssrc: Ssrc,
+    seq_no: u16,
+) -> Result<(), RtcError> {
+    let pt = l.params_vp8().pt();
+    let wallclock = l.start + l.duration();
+    let time = seq_no as u32 * 3000;
+
+    let mut api = l.direct_api();
+    let stream = api.declare_stream_tx(ssrc, None, mid, None);
+    stream.write_rtp(
+        pt,
+        (seq_no as u64).into(),
+        time,
+        wallclock,
+        true,
+        ExtensionValues::default(),
+        true,
+        vec![1, 2, 3, 4],
+    )?;
+
+    let until = l.duration() + Duration::from_millis(10);
+    while l.duration() < until {
+        progress(l, r)?;
+    }
+
+    assert!(l.direct_api().remove_stream_tx(ssrc));
+
+    Ok(())
+}
+
+fn discovered(t: &TestRtc) -> Vec<LayerDiscovered> {
+    t.events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::LayerDiscovered(d) => Some(d.clone()),
+            _ => None,
+        })
+        .collect()
+}
@@ -1,14 +1,17 @@
 use std::net::Ipv4Addr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use str0m::bwe::Bitrate;
 use str0m::format::Codec;
-use str0m::media::{Direction, MediaKind};
+use str0m::media::{Direction, MediaKind, Mid};
+use str0m::rtp::rtcp::Rtcp;
+use str0m::rtp::{ExtensionValues, RawPacket, Ssrc};
 use str0m::stats::MediaEgressStats;
-use str0m::{Candidate, Event, RtcConfig, RtcError};
+use str0m::{Candidate, Event, Input, Output, Rtc, RtcConfig, RtcError};
 use tracing::info_span;
 
 mod common;
-use common::{init_log, progress, TestRtc};
+use common::{connect_l_r_with_rtc, init_log, progress, TestRtc};
 
 #[test]
 pub fn stats() -> Result<(), RtcError> {
@@ -128,6 +131,20 @@ pub fn stats() -> Result<(), RtcError> {
         .filter_map(|egress_stat_l| egress_stat_l.rtt)
         .for_each(|rtt| assert!(rtt < 100_f32)); // rtt should be under 100ms in this scenario
 
+    let peer_stats_l = l
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::PeerStats(s) => Some(s),
+            _ => None,
+        })
+        .next_back()
+        .expect("PeerStats at L");
+
+    assert!(peer_stats_l.egress.bitrate > Bitrate::ZERO);
+    assert!(peer_stats_l.egress.rtcp_bitrate > Bitrate::ZERO);
+    assert!(peer_stats_l.egress_ssrc.values().any(|e| e.packets > 0));
+
     assert!(
         media_count_l > 1700,
         "Not enough MediaData at L: {}",
@@ -136,3 +153,319 @@ pub fn stats() -> Result<(), RtcError> {
 
     Ok(())
 }
+
+#[test]
+pub fn ingress_stats_match_rr() -> Result<(), RtcError> {
+    init_log();
+
+    let rtc1 = Rtc::builder()
+        .set_rtp_mode(true)
+        .enable_raw_packets(true)
+        .build();
+    let rtc2 = Rtc::builder()
+        .set_rtp_mode(true)
+        .enable_raw_packets(true)
+        .set_reordering_size_audio(0)
+        .set_stats_interval(Some(Duration::from_secs(1)))
+        .build();
+
+    let (mut l, mut r) = connect_l_r_with_rtc(rtc1, rtc2);
+
+    let mid = "aud".into();
+    let ssrc: Ssrc = 42.into();
+
+    l.direct_api().declare_media(mid, MediaKind::Audio);
+    l.direct_api().declare_stream_tx(ssrc, None, mid, None);
+    r.direct_api().declare_media(mid, MediaKind::Audio);
+    r.direct_api().expect_stream_rx(ssrc, None, mid, None);
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_opus().pt();
+
+    for index in 0..500_u64 {
+        // Every 10th packet is never sent.
+        if index % 10 != 5 {
+            let wallclock = l.start + l.duration();
+            let mut direct = l.direct_api();
+            let stream = direct.stream_tx(&ssrc).unwrap();
+            let time = (index * 960) as u32;
+            stream
+                .write_rtp(
+                    pt,
+                    (1000 + index).into(),
+                    time,
+                    wallclock,
+                    false,
+                    ExtensionValues::default(),
+                    false,
+                    vec![1, 2, 3, 4],
+                )
+                .expect("clean write");
+        }
+
+        let until = l.duration() + Duration::from_millis(20);
+        while l.duration() < until {
+            progress(&mut l, &mut r)?;
+        }
+    }
+
+    // Pair each stats event with the last RR sent before it.
+    let mut last_report = None;
+    let mut checked = 0;
+
+    for (_, e) in &r.events {
+        match e {
+            Event::RawPacket(p) => {
+                if let RawPacket::RtcpTx(Rtcp::ReceiverReport(rr)) = &**p {
+                    if let Some(report) = rr.reports.iter().find(|r| r.ssrc == ssrc) {
+                        last_report = Some(*report);
+                    }
+                }
+            }
+            Event::IngressStats(stats) => {
+                assert_eq!(stats.ssrc, ssrc);
+                assert_eq!(stats.mid, mid);
+
+                let Some(report) = last_report else {
+                    assert!(stats.fraction_lost.is_none());
+                    assert!(stats.packets_lost.is_none());
+                    continue;
+                };
+
+                assert_eq!(
+                    stats.fraction_lost,
+                    Some(report.fraction_lost as f32 / u8::MAX as f32)
+                );
+                assert_eq!(stats.packets_lost, Some(report.packets_lost as i64));
+                assert_eq!(stats.jitter, Some(report.jitter as f32 / 1000.0));
+
+                checked += 1;
+            }
+            _ => {}
+        }
+    }
+
+    assert!(checked > 3, "Too few IngressStats checked: {}", checked);
+
+    // 1 in 10 is lost.
+    let report = last_report.unwrap();
+    assert!(report.packets_lost > 20, "{:?}", report);
+    assert!(report.fraction_lost > 20, "{:?}", report);
+
+    Ok(())
+}
+
+#[test]
+pub fn stats_snapshot() -> Result<(), RtcError> {
+    init_log();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    let answer = r.rtc.sdp_api().accept_offer(offer)?;
+    l.rtc.sdp_api().accept_answer(pending, answer)?;
+
+    loop {
+        if l.is_connected() || r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_opus().pt();
+
+    loop {
+        let wallclock = l.start + l.duration();
+        let time = l.duration().into();
+        l.writer(mid)
+            .unwrap()
+            .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(10) {
+            break;
+        }
+    }
+
+    let stats_l = l.stats();
+    let stats_r = r.stats();
+
+    let pair = stats_l.candidate_pair.as_ref().expect("nominated pair");
+    assert_eq!(pair.local_address, "1.1.1.1:1000".parse().unwrap());
+    assert_eq!(pair.remote_address, "2.2.2.2:2000".parse().unwrap());
+    assert!(stats_l.bytes_sent > 0);
+
+    assert_eq!(stats_l.outbound.len(), 1);
+    let out = &stats_l.outbound[0];
+    assert_eq!(out.mid, mid);
+    assert_eq!(out.kind, MediaKind::Audio);
+    assert!(out.packets_sent > 400, "{}", out.packets_sent);
+    assert!(out.bitrate > Bitrate::ZERO);
+    assert_eq!(out.fraction_lost, Some(0.0));
+    assert_eq!(out.packets_lost, Some(0));
+    assert!(out.round_trip_time.unwrap() < 0.1);
+
+    let inb = stats_r
+        .inbound
+        .iter()
+        .find(|s| s.ssrc == out.ssrc)
+        .expect("inbound stream at R");
+    assert_eq!(inb.mid, mid);
+    assert!(inb.bytes_received <= out.bytes_sent);
+    assert!(inb.bytes_received > out.bytes_sent * 9 / 10);
+    assert!(inb.jitter.is_some());
+    assert!(inb.last_sender_report.is_some());
+
+    let json = serde_json::to_value(&stats_l).unwrap();
+    assert_eq!(json["outbound"][0]["kind"], "audio");
+    assert_eq!(json["outbound"][0]["mid"], mid.to_string());
+    assert_eq!(json["candidate_pair"]["local_candidate_type"], "host");
+
+    Ok(())
+}
+
+#[test]
+pub fn candidate_pair_stats() -> Result<(), RtcError> {
+    init_log();
+
+    let mut l = TestRtc::new(info_span!("L"));
+    let mut r = TestRtc::new(info_span!("R"));
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Audio, Direction::SendRecv, None, None);
+    let (offer, pending) = change.apply().unwrap();
+
+    let answer = r.rtc.sdp_api().accept_offer(offer)?;
+    l.rtc.sdp_api().accept_answer(pending, answer)?;
+
+    loop {
+        if l.is_connected() && r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let before = l.stats().candidate_pair.expect("nominated pair");
+    assert!(before.nominated);
+    assert!(before.nominated_at.is_some());
+    assert!(before.requests_sent > 0);
+    assert!(before.responses_received > 0);
+
+    let pt = l.params_opus().pt();
+
+    loop {
+        let wallclock = l.start + l.duration();
+        let time = l.duration().into();
+        l.writer(mid)
+            .unwrap()
+            .write(pt, wallclock, time, vec![1_u8; 80])?;
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(12) {
+            break;
+        }
+    }
+
+    let stats_l = l.stats();
+    let pair = stats_l.candidate_pair.as_ref().expect("nominated pair");
+    assert_eq!(stats_l.candidate_pairs.len(), 1);
+
+    // Media goes over the pair, and consent checks keep going.
+    assert!(pair.bytes_sent > before.bytes_sent + 10_000);
+    assert!(pair.responses_received >= before.responses_received + 2);
+    assert_eq!(pair.nominated_at, before.nominated_at);
+
+    let rtt = pair.current_round_trip_time.expect("rtt");
+    assert!(rtt < 0.1, "{rtt}");
+    assert!(pair.round_trip_time_ewma.unwrap() < 0.1);
+
+    let pair_r = r.stats().candidate_pair.expect("nominated pair at R");
+    assert!(pair_r.bytes_received > 10_000);
+    assert!(pair_r.requests_received > 0);
+
+    let json = serde_json::to_value(&stats_l).unwrap();
+    assert_eq!(json["candidate_pairs"][0]["check_state"], "succeeded");
+
+    Ok(())
+}
+
+#[test]
+pub fn rtc_stats_events() -> Result<(), RtcError> {
+    init_log();
+
+    let mut rtc = RtcConfig::new()
+        .set_rtc_stats_interval(Duration::from_secs(1))
+        .build();
+
+    let start = Instant::now();
+    let mid = Mid::from("a");
+    let ssrc = Ssrc::from(42);
+    let mut at = Vec::new();
+
+    // 100ms ticks for 5.5 seconds. A stream is added at 2.5s and removed at 3.7s.
+    for tick in 0..=55 {
+        let now = start + Duration::from_millis(tick * 100);
+
+        if tick == 25 {
+            let mut api = rtc.direct_api();
+            api.declare_media(mid, MediaKind::Video);
+            api.declare_stream_tx(ssrc, None, mid, None);
+        }
+        if tick == 37 {
+            assert!(rtc.direct_api().remove_stream_tx(ssrc));
+        }
+
+        rtc.handle_input(Input::Timeout(now))?;
+
+        loop {
+            match rtc.poll_output()? {
+                Output::Timeout(_) => break,
+                Output::Event(Event::RtcStats(s)) => at.push((tick, s.outbound.len())),
+                _ => {}
+            }
+        }
+    }
+
+    // Every second, plus straight away on stream changes.
+    assert_eq!(
+        at,
+        vec![
+            (10, 0),
+            (20, 0),
+            (25, 1),
+            (30, 1),
+            (37, 0),
+            (40, 0),
+            (50, 0)
+        ]
+    );
+
+    Ok(())
+}
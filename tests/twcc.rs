@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::net::Ipv4Addr;
 use std::time::Duration;
 
@@ -117,3 +118,82 @@ pub fn twcc() -> Result<(), RtcError> {
 
     Ok(())
 }
+
+#[test]
+pub fn twcc_seq_shared_across_ssrcs() -> Result<(), RtcError> {
+    init_log();
+    let l_rtc = Rtc::builder().enable_raw_packets(true).build();
+    let r_rtc = Rtc::builder().enable_raw_packets(true).build();
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), l_rtc);
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), r_rtc);
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let (mid_audio, mid_video) = negotiate(&mut l, &mut r, |change| {
+        let a = change.add_media(MediaKind::Audio, Direction::SendOnly, None, None);
+        let v = change.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+        (a, v)
+    });
+
+    loop {
+        if l.is_connected() || r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt_audio = l.params_opus().pt();
+    let pt_video = l.params_vp8().pt();
+
+    loop {
+        {
+            let wallclock = l.start + l.duration();
+            let time = l.duration().into();
+            l.writer(mid_audio)
+                .unwrap()
+                .write(pt_audio, wallclock, time, [1_u8; 80])?;
+            l.writer(mid_video)
+                .unwrap()
+                .write(pt_video, wallclock, time, [2_u8; 80])?;
+        }
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(3) {
+            break;
+        }
+    }
+
+    let sent: Vec<_> = {
+        use str0m::rtp::RawPacket;
+        l.events
+            .iter()
+            .filter_map(|(_, e)| {
+                if let Some(RawPacket::RtpTx(header, _)) = e.as_raw_packet() {
+                    Some((header.ssrc, header.ext_vals.transport_cc.unwrap()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    };
+
+    let ssrcs: HashSet<_> = sent.iter().map(|(ssrc, _)| *ssrc).collect();
+    assert!(ssrcs.len() >= 2, "Should send on at least two SSRCs");
+
+    let gapless = sent.windows(2).all(|w| w[0].1.wrapping_add(1) == w[1].1);
+    assert!(
+        gapless,
+        "Transport-wide sequence numbers should be gapless across SSRCs"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use str0m::format::Codec;
+use str0m::media::{Frequency, MediaKind, Pt};
+use str0m::rtp::{ExtensionValues, Ssrc};
+use str0m::{Event, Rtc, RtcError};
+
+mod common;
+use common::{connect_l_r_with_rtc, init_log, progress};
+
+const RED_PT: u8 = 122;
+const ULPFEC_PT: u8 = 123;
+
+#[test]
+pub fn ulpfec_recover_lost_packet() -> Result<(), RtcError> {
+    init_log();
+
+    // No header extensions, to know the bytes of the media packets for the FEC.
+    let mut rtc1 = Rtc::builder().set_rtp_mode(true).clear_extension_map();
+
+    // Let the sender write the RED payload type.
+    rtc1.codec_config().add_config(
+        RED_PT.into(),
+        None,
+        Codec::Generic,
+        Frequency::NINETY_KHZ,
+        None,
+        Default::default(),
+    );
+    let rtc1 = rtc1.build();
+    let rtc2 = Rtc::builder()
+        .set_rtp_mode(true)
+        .clear_extension_map()
+        .set_ulpfec_receive(Some((RED_PT.into(), ULPFEC_PT.into())))
+        .build();
+
+    let (mut l, mut r) = connect_l_r_with_rtc(rtc1, rtc2);
+
+    let mid = "vid".into();
+    let ssrc: Ssrc = 42.into();
+
+    l.direct_api().declare_media(mid, MediaKind::Video);
+    l.direct_api().declare_stream_tx(ssrc, None, mid, None);
+    r.direct_api().declare_media(mid, MediaKind::Video);
+    r.direct_api().expect_stream_rx(ssrc, None, mid, None);
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let pt = l.params_vp8().pt();
+
+    let payloads: [&[u8]; 3] = [
+        &[0x90, 0xe0, 0x80, 0x01, 0x00, 0x10, 0x9d, 0x01, 0x2a],
+        &[
+            0x80, 0xe0, 0x80, 0x01, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x11, 0x22,
+        ],
+        &[0x80, 0xe0, 0x80, 0x01, 0x42],
+    ];
+
+    let media: Vec<Vec<u8>> = payloads
+        .iter()
+        .enumerate()
+        .map(|(i, p)| rtp_packet(pt, 1000 + i as u16, i == 2, *ssrc, p))
+        .collect();
+
+    let fec = ulpfec(&media);
+
+    // Packet 1001 is lost, 1003 is the FEC.
+    let mut to_write: VecDeque<(u16, bool, Vec<u8>)> = VecDeque::new();
+    for i in [0, 2] {
+        let mut red = vec![*pt];
+        red.extend_from_slice(payloads[i]);
+        to_write.push_back((1000 + i as u16, i == 2, red));
+    }
+    let mut red = vec![ULPFEC_PT];
+    red.extend_from_slice(&fec);
+    to_write.push_back((1003, false, red));
+
+    let mut write_at = l.last + Duration::from_millis(100);
+
+    loop {
+        if l.start + l.duration() > write_at {
+            write_at = l.last + Duration::from_millis(100);
+
+            if let Some((seq_no, marker, payload)) = to_write.pop_front() {
+                let wallclock = l.start + l.duration();
+
+                let mut direct = l.direct_api();
+                let stream = direct.stream_tx(&ssrc).unwrap();
+
+                stream
+                    .write_rtp(
+                        RED_PT.into(),
+                        (seq_no as u64).into(),
+                        90_000,
+                        wallclock,
+                        marker,
+                        ExtensionValues::default(),
+                        false,
+                        payload,
+                    )
+                    .expect("clean write");
+            }
+        }
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(3) {
+            break;
+        }
+    }
+
+    let packets: Vec<_> = r
+        .events
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Event::RtpPacket(v) => Some(v),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(packets.len(), 3);
+
+    for (p, (seq_no, recovered)) in packets
+        .iter()
+        .zip([(1000, false), (1002, false), (1001, true)])
+    {
+        assert_eq!(p.header.sequence_number, seq_no);
+        assert_eq!(p.header.payload_type, pt);
+        assert_eq!(p.recovered, recovered);
+
+        let i = (seq_no - 1000) as usize;
+        assert_eq!(p.payload, payloads[i]);
+        assert_eq!(p.header.marker, i == 2);
+        assert_eq!(p.header.timestamp, 90_000);
+    }
+
+    Ok(())
+}
+
+/// The media packet as protected by the FEC, before wrapping in RED.
+fn rtp_packet(pt: Pt, seq_no: u16, marker: bool, ssrc: u32, payload: &[u8]) -> Vec<u8> {
+    let mut b = vec![0x90, *pt | if marker { 0x80 } else { 0 }];
+    b.extend_from_slice(&seq_no.to_be_bytes());
+    b.extend_from_slice(&90_000_u32.to_be_bytes());
+    b.extend_from_slice(&ssrc.to_be_bytes());
+    // The sender writes an empty header extension.
+    b.extend_from_slice(&[0xbe, 0xde, 0x00, 0x00]);
+    b.extend_from_slice(payload);
+    b
+}
+
+/// ULPFEC with level 0 protecting all packets, which must be in sequence.
+fn ulpfec(packets: &[Vec<u8>]) -> Vec<u8> {
+    let protection_len = packets.iter().map(|p| p.len() - 12).max().unwrap();
+
+    let mut header = [0_u8; 10];
+    let mut payload = vec![0; protection_len];
+
+    for p in packets {
+        header[0] ^= p[0];
+        header[1] ^= p[1];
+        for i in 4..8 {
+            header[i] ^= p[i];
+        }
+        let len = (p.len() - 12) as u16;
+        header[8] ^= (len >> 8) as u8;
+        header[9] ^= len as u8;
+
+        for (b, v) in payload.iter_mut().zip(&p[12..]) {
+            *b ^= v;
+        }
+    }
+
+    // E = 0, L = 0, and the SN base.
+    header[0] &= 0x3f;
+    header[2..4].copy_from_slice(&packets[0][2..4]);
+
+    let mask = (0xffff_u16 << (16 - packets.len())).to_be_bytes();
+
+    let mut fec = header.to_vec();
+    fec.extend_from_slice(&(protection_len as u16).to_be_bytes());
+    fec.extend_from_slice(&mask);
+    fec.extend_from_slice(&payload);
+    fec
+}
@@ -0,0 +1,103 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use str0m::format::Codec;
+use str0m::media::{Direction, MediaKind};
+use str0m::rtp::{Extension, VideoTiming};
+use str0m::Rtc;
+use str0m::{Candidate, Event, RtcError};
+use tracing::info_span;
+
+mod common;
+use common::{init_log, progress, TestRtc};
+
+#[test]
+pub fn video_timing() -> Result<(), RtcError> {
+    init_log();
+
+    let rtc_l = Rtc::builder()
+        .set_extension(7, Extension::VideoTiming)
+        .build();
+    let rtc_r = Rtc::builder()
+        .set_extension(7, Extension::VideoTiming)
+        .build();
+
+    let mut l = TestRtc::new_with_rtc(info_span!("L"), rtc_l);
+    let mut r = TestRtc::new_with_rtc(info_span!("R"), rtc_r);
+
+    let host1 = Candidate::host((Ipv4Addr::new(1, 1, 1, 1), 1000).into(), "udp")?;
+    let host2 = Candidate::host((Ipv4Addr::new(2, 2, 2, 2), 2000).into(), "udp")?;
+    l.add_local_candidate(host1);
+    r.add_local_candidate(host2);
+
+    let mut change = l.sdp_api();
+    let mid = change.add_media(MediaKind::Video, Direction::SendOnly, None, None);
+    let (offer, pending) = change.apply().unwrap();
+    let answer = r.rtc.sdp_api().accept_offer(offer)?;
+    l.rtc.sdp_api().accept_answer(pending, answer)?;
+
+    loop {
+        if l.is_connected() || r.is_connected() {
+            break;
+        }
+        progress(&mut l, &mut r)?;
+    }
+
+    let max = l.last.max(r.last);
+    l.last = max;
+    r.last = max;
+
+    let params = l.params_vp8();
+    assert_eq!(params.spec().codec, Codec::Vp8);
+    let pt = params.pt();
+
+    let data_a = vec![1_u8; 80];
+
+    loop {
+        // Pretend the frame was captured 30ms ago.
+        let wallclock = l.start + l.duration() - Duration::from_millis(30);
+        let time = l.duration().into();
+
+        let mut timing = VideoTiming::default();
+        timing.set_encode_start(wallclock, wallclock + Duration::from_millis(5));
+        timing.set_encode_finish(wallclock, wallclock + Duration::from_millis(25));
+
+        l.writer(mid)
+            .unwrap()
+            .video_timing(timing)
+            .write(pt, wallclock, time, data_a.clone())?;
+
+        progress(&mut l, &mut r)?;
+
+        if l.duration() > Duration::from_secs(3) {
+            break;
+        }
+    }
+
+    let datas = r.events.iter().filter_map(|(_, e)| {
+        if let Event::MediaData(d) = e {
+            Some(d)
+        } else {
+            None
+        }
+    });
+
+    let mut empty = true;
+    for data in datas {
+        empty = false;
+        let v = data.ext_vals.video_timing.expect("video timing");
+
+        // Fields set by the user are untouched by the send path.
+        assert_eq!(v.encode_start, 5);
+        assert_eq!(v.encode_finish, 25);
+
+        // Packetization and pacer exit are stamped by str0m. The test clock moves in
+        // 10ms ticks, which is the slack allowed here.
+        assert!(v.packetize_complete >= 20, "{v:?}");
+        assert!(v.last_left_pacer >= 30, "{v:?}");
+        assert!(v.last_left_pacer >= v.packetize_complete, "{v:?}");
+    }
+    assert!(!empty);
+
+    Ok(())
+}